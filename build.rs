@@ -11,8 +11,11 @@
 //!
 //! - C++ CMake tools for Windows
 //! - Developer Command Prompt for VS 2019 shell environment
+//! - CMake and Python 3
 //!
-//! _Unsure if the Windows host requirements also include CMake and Python 3._
+//! Outside a Developer Command Prompt, the build script finds Visual Studio
+//! and imports its `vcvarsall.bat` environment itself; see
+//! [`msvc_environment()`].
 //!
 //! There's a `./build` or `./build.bat` script in the `zig-bootstrap` directory
 //! that runs the whole build suite (LLVM, zlib, zstd, etc.) and then builds Zig
@@ -30,99 +33,1717 @@
 //! that directory and the `lib/` folder is right next to it.
 
 use std::{
-    env::{self, var},
+    collections::VecDeque,
+    env,
     error::Error,
     fs,
-    io::{self, Seek, SeekFrom},
-    path::Path,
+    io::{self, Write},
+    path::{Path, PathBuf},
     process::{Command, Stdio},
+    sync::{
+        Mutex,
+        mpsc::{self, RecvTimeoutError},
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
-use build::{cargo_pkg_version_major, cargo_pkg_version_minor, cargo_pkg_version_patch};
-use reqwest::blocking::get;
-use zip::{ZipArchive, read::root_dir_common_filter};
+#[path = "src/archive.rs"]
+#[allow(dead_code)]
+mod archive;
+#[path = "src/bootstrap_source.rs"]
+#[allow(dead_code)]
+mod bootstrap_source;
+#[path = "src/bootstrap_target.rs"]
+#[allow(dead_code)]
+mod bootstrap_target;
+#[path = "src/cache.rs"]
+#[allow(dead_code)]
+mod cache;
+#[path = "src/cancel.rs"]
+#[allow(dead_code)]
+mod cancel;
+#[path = "src/config.rs"]
+#[allow(dead_code)]
+mod config;
+#[path = "src/download.rs"]
+#[allow(dead_code)]
+mod download;
+#[path = "src/failure_report.rs"]
+#[allow(dead_code)]
+mod failure_report;
+#[path = "src/integrity.rs"]
+#[allow(dead_code)]
+mod integrity;
+#[path = "src/jobs.rs"]
+#[allow(dead_code)]
+mod jobs;
+#[path = "src/libc_headers.rs"]
+#[allow(dead_code)]
+mod libc_headers;
+#[path = "src/licenses.rs"]
+#[allow(dead_code)]
+mod licenses;
+#[path = "src/log.rs"]
+#[allow(dead_code)]
+mod log;
+#[path = "src/manifest.rs"]
+#[allow(dead_code)]
+mod manifest;
+#[path = "src/pack.rs"]
+#[allow(dead_code)]
+mod pack;
+#[path = "src/process.rs"]
+#[allow(dead_code)]
+mod process;
+#[path = "src/provenance.rs"]
+#[allow(dead_code)]
+mod provenance;
+#[path = "src/prune.rs"]
+#[allow(dead_code)]
+mod prune;
+#[path = "src/release.rs"]
+#[allow(dead_code)]
+mod release;
+#[path = "src/target_triple.rs"]
+#[allow(dead_code)]
+mod target_triple;
+#[path = "src/zig_env.rs"]
+#[allow(dead_code)]
+mod zig_env;
+
+/// Where the toolchain can come from, tried in [`DEFAULT_ORDER`] unless
+/// `ZIG_RS_TOOLCHAIN_ORDER` lists them differently (comma-separated, e.g.
+/// `prebuilt,source`). Sources that don't apply are skipped; sources that fail
+/// are reported and the next one is tried.
+const ORDER_ENV: &str = "ZIG_RS_TOOLCHAIN_ORDER";
+const DEFAULT_ORDER: &str = "archive,system,cache,prebuilt,source";
+/// The default order with the `prebuilt` feature, for targets ziglang.org
+/// publishes binaries for: a failed download is an error rather than the
+/// start of an hours-long LLVM build.
+const PREBUILT_ORDER: &str = "archive,system,cache,prebuilt";
+/// Set to `1` to use only the system `zig`, failing with the reason when it
+/// doesn't fit instead of falling back to a download or an LLVM build.
+const SYSTEM_ZIG_ENV: &str = "ZIG_RS_SYSTEM_ZIG";
+/// Path of the system `zig` to use instead of the one on `PATH`.
+const ZIG_ENV: &str = "ZIG";
+/// An extracted zig-bootstrap source tree to build from, for builds without
+/// network access.
+const BOOTSTRAP_DIR_ENV: &str = "ZIG_RS_BOOTSTRAP_DIR";
+/// A local `.zip`, `.tar.gz` or `.tar.xz` of the zig-bootstrap sources to
+/// extract instead of downloading them.
+const BOOTSTRAP_ARCHIVE_ENV: &str = "ZIG_RS_BOOTSTRAP_ARCHIVE";
+/// A Linux container image with zig-bootstrap's prerequisites (a C++
+/// compiler, CMake, Ninja or Make, Python 3) to run the bootstrap in, for
+/// hosts without them. zig-bootstrap cross-compiles, so the image's
+/// architecture doesn't have to match the target's.
+const BOOTSTRAP_IMAGE_ENV: &str = "ZIG_RS_BOOTSTRAP_IMAGE";
+/// `docker` or `podman`, or the path of either, to run
+/// [`BOOTSTRAP_IMAGE_ENV`] with. Defaults to whichever is installed, Podman
+/// first.
+const CONTAINER_RUNTIME_ENV: &str = "ZIG_RS_CONTAINER_RUNTIME";
+/// How often a running zig-bootstrap build says which part it is at, so CI
+/// doesn't take the hours of LLVM for a hang.
+const HEARTBEAT: Duration = Duration::from_secs(5 * 60);
+/// How many lines of its log a failed zig-bootstrap build prints again.
+const FAILURE_TAIL: usize = 100;
+/// How many jobs the zig-bootstrap build runs at once. Defaults to as many
+/// as Cargo's jobserver has free, up to `NUM_JOBS`.
+const JOBS_ENV: &str = "ZIG_RS_JOBS";
+
+/// The host C compiler zig-bootstrap's CMake builds with, as `CC`, instead
+/// of `cc` in the `[bootstrap]` table of the `zig_rs::config` file.
+const CC_ENV: &str = "ZIG_RS_CC";
+/// The host C++ compiler, as `CXX`, instead of `[bootstrap]` `cxx`.
+const CXX_ENV: &str = "ZIG_RS_CXX";
+/// Whitespace-separated `-DNAME[:TYPE]=VALUE` CMake cache entries for every
+/// project zig-bootstrap configures, instead of `[bootstrap]` `cmake-args`.
+const BOOTSTRAP_CMAKE_ARGS_ENV: &str = "ZIG_RS_BOOTSTRAP_CMAKE_ARGS";
+/// `1` to build LLVM with assertions, `0` not to, instead of `[bootstrap]`
+/// `assertions`.
+const BOOTSTRAP_ASSERTIONS_ENV: &str = "ZIG_RS_BOOTSTRAP_ASSERTIONS";
+/// Whitespace-separated arguments after the target and CPU, for forks of
+/// zig-bootstrap whose build script takes more, instead of `[bootstrap]`
+/// `args`.
+const BOOTSTRAP_ARGS_ENV: &str = "ZIG_RS_BOOTSTRAP_ARGS";
+/// A Zig version to install instead of the one this package pins, e.g.
+/// `0.13.0` or `0.15.0-dev.1+a1b2c3d`. Every toolchain source goes by it,
+/// and the installed `zig version` has to match it.
+const ZIG_VERSION_ENV: &str = "ZIG_RS_ZIG_VERSION";
+/// A zig-bootstrap tag, branch or commit to download and build instead of
+/// the tag of the Zig version. Only the `source` toolchain can provide it.
+const BOOTSTRAP_REF_ENV: &str = "ZIG_RS_BOOTSTRAP_REF";
+/// `gnu` or `musl`, the C library of a Linux host, instead of what
+/// `bootstrap_target::host_libc()` finds out.
+const HOST_LIBC_ENV: &str = "ZIG_RS_HOST_LIBC";
+/// The [`bootstrap_options()`] a source build used, in `OUT_DIR`, as a
+/// configuration file that builds the same again.
+const BOOTSTRAP_OPTIONS_FILE: &str = "zig-bootstrap-options.toml";
+
+/// The zig-bootstrap build's output, saved by [`run_bootstrap()`].
+const BOOTSTRAP_LOG: &str = "zig-bootstrap.log";
+
+/// What [`main()`] is doing, for a [`failure_report`] when it fails.
+static PHASE: Mutex<&str> = Mutex::new("config");
+/// What the installed toolchain came from, for [`write_provenance()`]; the
+/// toolchain sources add to it as they succeed.
+static DEPENDENCIES: Mutex<Vec<provenance::Dependency>> = Mutex::new(Vec::new());
+/// How the builder was set up beyond the host, for [`write_provenance()`].
+static ENVIRONMENT: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+/// What [`prune_lib()`] removed, for `BuildInfo` and the provenance.
+static PRUNED: Mutex<Vec<prune::Profile>> = Mutex::new(Vec::new());
+/// The toolchain being installed, set by [`installing()`].
+static INSTALLATION: Mutex<Option<Installation>> = Mutex::new(None);
+
+/// Where the target's toolchain goes in `OUT_DIR` when it isn't the host's.
+const TARGET_TOOLCHAIN_DIR: &str = "target-toolchain";
+
+/// A toolchain [`run()`] installs: always the host's into `OUT_DIR`, which
+/// build scripts and `include_zig!` run, and with the `target-toolchain`
+/// feature also the target's into [`TARGET_TOOLCHAIN_DIR`] when
+/// cross-compiling, for applications that ship Zig.
+#[derive(Debug, Clone)]
+struct Installation {
+    /// The platform the `zig` binary runs on.
+    cfg: bootstrap_target::TargetCfg,
+    dir: PathBuf,
+}
 
-/// If `./zig-bootstrap/` is not present we need to clone it. If we're building
-/// documentation for docs.rs or similar we don't want to do that. Instead of
-/// `git clone` we can skip depending on Git and just download & extract a
-/// `.zip` or `tar.gz` archive of the tag that we want.
 fn main() -> Result<(), Box<dyn Error>> {
+    let result = run();
+    if let Err(e) = &result
+        && failure_report::enabled()
+    {
+        let mut report = failure_report::Report::new(&PHASE.lock().unwrap(), e.as_ref());
+        if report.phase == "bootstrap" {
+            report.log = Some(build::out_dir().join(BOOTSTRAP_LOG));
+        }
+        if let Err(e) = failure_report::record(&report) {
+            build::warning(&format!("couldn't record the failure report: {e}"));
+        }
+    }
+    result
+}
+
+fn phase(name: &'static str) {
+    *PHASE.lock().unwrap() = name;
+}
+
+/// Makes `installation` the toolchain the toolchain sources install, and
+/// starts its provenance afresh.
+fn installing(installation: Installation) {
+    *INSTALLATION.lock().unwrap() = Some(installation);
+    DEPENDENCIES.lock().unwrap().clear();
+    ENVIRONMENT.lock().unwrap().clear();
+    PRUNED.lock().unwrap().clear();
+}
+
+fn installation() -> Installation {
+    INSTALLATION
+        .lock()
+        .unwrap()
+        .clone()
+        .expect("run() sets the installation first")
+}
+
+/// The rustc target the toolchain being installed runs on.
+fn toolchain_target() -> String {
+    installation().cfg.triple
+}
+
+/// Where the toolchain being installed goes.
+fn toolchain_dir() -> PathBuf {
+    installation().dir
+}
+
+/// The host's `cfg`s: Cargo only describes the target's to build scripts, so
+/// `rustc --print cfg` is asked when they differ. A glibc host that runs
+/// musl gets a musl toolchain; see `src/bootstrap_target.rs`.
+fn host_cfg() -> Result<bootstrap_target::TargetCfg, Box<dyn Error>> {
+    let mut cfg = rustc_host_cfg()?;
+    let libc = match env::var(HOST_LIBC_ENV) {
+        Ok(libc) => Some(
+            bootstrap_target::Libc::from_env(&libc)
+                .ok_or_else(|| format!("{HOST_LIBC_ENV} is {libc:?}, not gnu or musl"))?,
+        ),
+        Err(_) => bootstrap_target::host_libc(),
+    };
+    if cfg.correct_host_libc(libc) {
+        build::warning(&format!(
+            "the host is {}, but the system runs musl; installing a musl Zig",
+            cfg.triple
+        ));
+    }
+    Ok(cfg)
+}
+
+fn rustc_host_cfg() -> Result<bootstrap_target::TargetCfg, Box<dyn Error>> {
+    let host = build::host();
+    if host == build::target() {
+        return Ok(bootstrap_target::TargetCfg::from_env());
+    }
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let output = process::output(
+        Command::new(rustc)
+            .args(["--print", "cfg", "--target"])
+            .arg(&host),
+    )?;
+    if !output.status.success() {
+        return Err(format!(
+            "`rustc --print cfg --target {host}` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(bootstrap_target::TargetCfg::from_print_cfg(
+        &host,
+        &String::from_utf8(output.stdout)?,
+    ))
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
     build::rerun_if_env_changed("DO_IT");
+    build::rerun_if_env_changed(ORDER_ENV);
+    build::rerun_if_env_changed(SYSTEM_ZIG_ENV);
+    build::rerun_if_env_changed(ZIG_ENV);
+    build::rerun_if_env_changed(BOOTSTRAP_DIR_ENV);
+    build::rerun_if_env_changed(HOST_LIBC_ENV);
+    build::rerun_if_env_changed(BOOTSTRAP_ARCHIVE_ENV);
+    build::rerun_if_env_changed(BOOTSTRAP_IMAGE_ENV);
+    build::rerun_if_env_changed(CONTAINER_RUNTIME_ENV);
+    build::rerun_if_env_changed(JOBS_ENV);
+    build::rerun_if_env_changed(CC_ENV);
+    build::rerun_if_env_changed(CXX_ENV);
+    build::rerun_if_env_changed(BOOTSTRAP_CMAKE_ARGS_ENV);
+    build::rerun_if_env_changed(BOOTSTRAP_ASSERTIONS_ENV);
+    build::rerun_if_env_changed(BOOTSTRAP_ARGS_ENV);
+    build::rerun_if_env_changed(ZIG_VERSION_ENV);
+    build::rerun_if_env_changed(BOOTSTRAP_REF_ENV);
+    build::rerun_if_env_changed(prune::PROFILE_ENV);
+    build::rerun_if_env_changed(pack::ARCHIVE_ENV);
+    build::rerun_if_env_changed(download::OFFLINE_ENV);
+    build::rerun_if_env_changed(download::MIRRORS_ENV);
+    build::rerun_if_env_changed(download::FETCH_COMMAND_ENV);
+    build::rerun_if_env_changed(integrity::DIGESTS_ENV);
+    build::rerun_if_env_changed(integrity::SUMS_ENV);
+    build::rerun_if_env_changed(cache::DIR_ENV);
+    build::rerun_if_env_changed(cache::LINK_ENV);
+    build::rerun_if_env_changed(failure_report::FILE_ENV);
+    build::rerun_if_env_changed(failure_report::HOOK_ENV);
+    config::Config::load()?.rerun_if_changed();
+    build::rerun_if_env_changed(target_triple::TARGET_MAP_ENV);
+    if let Some(map) = target_triple::TargetMap::from_env()? {
+        build::rerun_if_changed(&map.path);
+    }
+    let host = Installation {
+        cfg: host_cfg()?,
+        dir: build::out_dir(),
+    };
+    let cross = host.cfg.triple != build::target();
+    let target =
+        (cross && env::var_os("CARGO_FEATURE_TARGET_TOOLCHAIN").is_some()).then(|| Installation {
+            cfg: bootstrap_target::TargetCfg::from_env(),
+            dir: build::out_dir().join(TARGET_TOOLCHAIN_DIR),
+        });
+    let target_toolchain = match &target {
+        Some(target) => Some(target.dir.clone()),
+        None => (!cross).then(build::out_dir),
+    };
+    installing(host.clone());
+    if env::var_os("CARGO_FEATURE_MACROS").is_some() {
+        write_macros()?;
+    }
 
     // Dev shortcircuit
-    if !env::var("DO_IT").is_ok() {
-        return Ok(());
+    if env::var("DO_IT").is_err() {
+        return write_build_info("Stub", None, target_toolchain.as_deref());
     }
 
-    if !docs_rs() && !fs::exists("zig-bootstrap")? {
-        let major = build::cargo_pkg_version_major();
-        let minor = build::cargo_pkg_version_minor();
-        let patch = build::cargo_pkg_version_patch();
+    if let Some(target) = target {
+        installing(target);
+        install()?;
+    }
+    // Last, so what `BUILD_INFO` describes is the host's.
+    installing(host);
+    let (bootstrap, zig_version) = install()?;
+    phase("metadata");
+    write_dep_metadata(zig_version.as_deref(), target_toolchain.as_deref())?;
+    write_build_info(bootstrap, zig_version, target_toolchain.as_deref())
+}
 
-        {
-            let response = reqwest::blocking::get(format!(
-                "https://github.com/ziglang/zig-bootstrap/archive/refs/tags/{major}.{minor}.{patch}.zip"
-            ))?;
-            let mut response = response.error_for_status()?;
-            let mut file = fs_err::File::create("zig-bootstrap.zip")?;
-            response.copy_to(&mut file)?;
+/// Installs the toolchain of [`installing()`] into its directory, with its
+/// licenses, hash manifest and provenance. Returns the `Bootstrap` variant
+/// name and the installed `zig version`, if it runs here.
+fn install() -> Result<(&'static str, Option<String>), Box<dyn Error>> {
+    phase("toolchain");
+    let dir = toolchain_dir();
+    fs_err::create_dir_all(&dir)?;
+    let bootstrap = if docs_rs() {
+        fs_err::write(dir.join(zig_exe()), [])?;
+        fs_err::create_dir_all(dir.join("lib"))?;
+        "Stub"
+    } else {
+        let bootstrap = install_toolchain()?;
+        phase("prune");
+        prune_lib()?;
+        bootstrap
+    };
+    phase("licenses");
+    licenses::collect(&dir)?;
+
+    phase("manifest");
+    manifest::Manifest::scan(&dir, &[zig_exe(), "lib", licenses::DIR_NAME])?
+        .write(&dir.join(manifest::FILE_NAME))?;
+
+    let zig_version = (bootstrap != "Stub").then(installed_zig_version).flatten();
+    if let Some(installed) = &zig_version {
+        check_installed_version(installed)?;
+    }
+    if bootstrap != "Stub" {
+        phase("provenance");
+        write_provenance(bootstrap, zig_version.as_deref())?;
+    }
+    Ok((bootstrap, zig_version))
+}
+
+/// `DEP_ZIG_*` variables for the build scripts of dependent crates, so they
+/// find the toolchain without guessing at our `OUT_DIR`: `ROOT` (the
+/// installation, `OUT_DIR`), `BIN` (the `zig` binary, also as `ZIG`),
+/// `LIB_DIR`, `VERSION` (the Zig version, like `BuildInfo::zig_version`),
+/// `WASI_SYSROOT`, `PROVENANCE` (see [`write_provenance()`]), for targets
+/// with a bundled libc, `LIBC_INCLUDE`, and `TARGET_ROOT` (the toolchain that
+/// runs on the target, see [`Installation`]) if there is one. All but
+/// `TARGET_ROOT` are the host's toolchain.
+fn write_dep_metadata(
+    zig_version: Option<&str>,
+    target_toolchain: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let lib_dir = build::out_dir().join("lib");
+    let zig = build::out_dir().join(zig_exe());
+    let version = match zig_version {
+        Some(version) => version.to_owned(),
+        None => self::zig_version(),
+    };
+    println!("cargo:root={}", build::out_dir().display());
+    println!("cargo:bin={}", zig.display());
+    println!("cargo:zig={}", zig.display());
+    println!("cargo:lib_dir={}", lib_dir.display());
+    println!("cargo:version={version}");
+    if let Some(root) = target_toolchain {
+        println!("cargo:target_root={}", root.display());
+    }
+    let provenance = build::out_dir().join(provenance::FILE_NAME);
+    if provenance.is_file() {
+        println!("cargo:provenance={}", provenance.display());
+    }
+    let wasi_sysroot = build::out_dir().join(libc_headers::WASI_SYSROOT_DIR);
+    libc_headers::wasi_sysroot(&lib_dir, &wasi_sysroot)?;
+    println!("cargo:wasi_sysroot={}", wasi_sysroot.display());
+    if let Ok((zig_target, _)) = zig_target_mcpu_for_build_target() {
+        let dirs = libc_headers::include_dirs(&lib_dir, &zig_target);
+        if !dirs.is_empty() {
+            let joined = env::join_paths(dirs)?;
+            println!("cargo:libc_include={}", joined.to_string_lossy());
+        }
+    }
+    Ok(())
+}
+
+/// Installs `zig` and `lib/` into [`toolchain_dir()`] from the first source
+/// in the configured order that works. Returns the `Bootstrap` variant name.
+fn install_toolchain() -> Result<&'static str, Box<dyn Error>> {
+    let prebuilt_only = env::var_os("CARGO_FEATURE_PREBUILT").is_some()
+        && release::target_platform(&toolchain_target()).is_some();
+    let custom = !bootstrap_options()?.is_default() || bootstrap_ref().is_some();
+    let order = env::var(ORDER_ENV).unwrap_or_else(|_| {
+        if system_zig_only() {
+            "system"
+        } else if custom {
+            // Any other toolchain is built without the options, or from
+            // another zig-bootstrap.
+            "source"
+        } else if prebuilt_only && !vendored_sources() {
+            PREBUILT_ORDER
+        } else {
+            DEFAULT_ORDER
+        }
+        .into()
+    });
+    let tagger = log::Tagger::new("toolchain");
+    let mut failures = Vec::new();
+    for source in order.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        phase("toolchain");
+        let result = match source {
+            "archive" => install_packed_archive(),
+            "system" => install_system_zig().map(|done| done.then_some("System")),
+            "cache" => install_cached(),
+            "prebuilt" => install_prebuilt_release().map(|done| done.then_some("Prebuilt")),
+            "source" => bootstrap().map(|()| Some("Source")),
+            _ => Err(format!("unknown toolchain source {source:?} in {ORDER_ENV}").into()),
+        };
+        match result {
+            Ok(Some(bootstrap)) => {
+                eprintln!("{}", tagger.tag(&format!("using the {source} toolchain")));
+                return Ok(bootstrap);
+            }
+            Ok(None) => eprintln!("{}", tagger.tag(&format!("{source}: not available"))),
+            Err(e) => {
+                eprintln!("{}", tagger.tag(&format!("{source}: {e}")));
+                failures.push(format!("{source}: {e}"));
+            }
+        }
+    }
+    Err(format!(
+        "no toolchain source worked (order: {order})\n{}",
+        failures.join("\n")
+    )
+    .into())
+}
+
+fn zig_exe() -> &'static str {
+    if installation().cfg.os == "windows" {
+        "zig.exe"
+    } else {
+        "zig"
+    }
+}
+
+fn system_zig_only() -> bool {
+    env::var_os(SYSTEM_ZIG_ENV).is_some_and(|value| value != "0")
+}
+
+/// The system `zig` (`ZIG` or the one on `PATH`) when it is exactly this
+/// package's version and the toolchain being installed is the host's (the
+/// system `zig` runs on nothing else). Its `zig env` has to name a `lib/` directory with Zig's
+/// standard library in it. With `ZIG_RS_SYSTEM_ZIG` set, a `zig` that doesn't
+/// fit is an error.
+fn install_system_zig() -> Result<bool, Box<dyn Error>> {
+    let zig = env::var_os(ZIG_ENV).map_or_else(|| "zig".into(), PathBuf::from);
+    let (zig_exe_path, lib_dir) = match probe_system_zig(&zig)? {
+        Ok(found) => found,
+        Err(reason) if system_zig_only() => {
+            return Err(format!("{SYSTEM_ZIG_ENV} is set but {reason}").into());
         }
+        Err(_) => return Ok(false),
+    };
+    build::rerun_if_changed(&zig_exe_path);
+    fs_err::copy(&zig_exe_path, toolchain_dir().join(zig_exe()))?;
+    copy_dir(&lib_dir, &toolchain_dir().join("lib"))?;
+    // Where an official release was extracted.
+    licenses::stage(&toolchain_dir(), "zig", &lib_dir.with_file_name("LICENSE"))?;
+    record_dependency(provenance::Dependency::file(
+        &zig_exe_path,
+        Some(manifest::hash_file(&zig_exe_path)?),
+    ));
+    record_dependency(provenance::Dependency::file(&lib_dir, None));
+    Ok(true)
+}
+
+/// Runs `zig version` and `zig env` of `zig` and returns the paths of the
+/// binary and its `lib/` directory, or why it can't be used.
+fn probe_system_zig(zig: &Path) -> Result<Result<(PathBuf, PathBuf), String>, Box<dyn Error>> {
+    if build::host() != toolchain_target() {
+        return Ok(Err(format!(
+            "a system zig doesn't run on {}",
+            toolchain_target()
+        )));
+    }
+    let version = match process::output(Command::new(zig).arg("version")) {
+        Ok(output) if output.status.success() => String::from_utf8(output.stdout)?,
+        _ => return Ok(Err(format!("`{} version` failed", zig.display()))),
+    };
+    let expected = zig_version();
+    if !version_fits(version.trim(), &expected) {
+        return Ok(Err(format!(
+            "{} is Zig {}, this package needs {expected}",
+            zig.display(),
+            version.trim()
+        )));
+    }
+    let output = process::output(Command::new(zig).arg("env"))?;
+    let zig_env = String::from_utf8(output.stdout)?;
+    let (Some(zig_exe_path), Some(lib_dir)) = (
+        zig_env::field(&zig_env, "zig_exe"),
+        zig_env::field(&zig_env, "lib_dir"),
+    ) else {
+        return Err("could not find zig_exe and lib_dir in `zig env`".into());
+    };
+    let lib_dir = PathBuf::from(lib_dir);
+    if !lib_dir.join("std").join("std.zig").is_file() {
+        return Ok(Err(format!(
+            "{} has no Zig standard library",
+            lib_dir.display()
+        )));
+    }
+    Ok(Ok((zig_exe_path.into(), lib_dir)))
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs_err::create_dir_all(to)?;
+    for entry in fs_err::read_dir(from)? {
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &dest)?;
+        } else {
+            fs_err::copy(entry.path(), dest)?;
+        }
+    }
+    Ok(())
+}
+
+/// An archive from `zig_rs::pack_toolchain()` named by
+/// `ZIG_RS_TOOLCHAIN_ARCHIVE`, typically restored by a CI cache action. It is
+/// only used when its fingerprint matches this build.
+fn install_packed_archive() -> Result<Option<&'static str>, Box<dyn Error>> {
+    let Some(archive) = env::var_os(pack::ARCHIVE_ENV) else {
+        return Ok(None);
+    };
+    let archive = Path::new(&archive);
+    build::rerun_if_changed(archive);
+    let staging = toolchain_dir().join("zig-packed");
+    if staging.exists() {
+        fs_err::remove_dir_all(&staging)?;
+    }
+    let _guard = process::remove_on_interrupt(&staging);
+    let fingerprint = pack::unpack(archive, &staging)?;
+    check_fingerprint(&fingerprint)?;
+    fs_err::rename(staging.join(zig_exe()), toolchain_dir().join(zig_exe()))?;
+    fs_err::rename(staging.join("lib"), toolchain_dir().join("lib"))?;
+    let staged_licenses = staging.join(licenses::DIR_NAME);
+    if staged_licenses.exists() {
+        fs_err::rename(staged_licenses, toolchain_dir().join(licenses::DIR_NAME))?;
+    }
+    fs_err::remove_dir_all(&staging)?;
+    record_dependency(provenance::Dependency::file(
+        archive,
+        Some(manifest::hash_file(archive)?),
+    ));
+    Ok(Some(bootstrap_variant(&fingerprint.bootstrap)))
+}
+
+fn check_fingerprint(fingerprint: &pack::Fingerprint) -> Result<(), Box<dyn Error>> {
+    let (zig_target, mcpu) = zig_target_mcpu_for_toolchain()?;
+    let version = zig_version();
+    // A toolchain pruned less is pruned the rest of the way after install.
+    let wanted = lib_profiles()?;
+    let pruned = prune::parse_profiles(&fingerprint.lib_profile)?;
+    if !pruned.iter().all(|profile| wanted.contains(profile)) {
+        return Err(format!(
+            "packed toolchain has no {} in lib/, this build keeps {}",
+            pruned
+                .iter()
+                .filter(|profile| !wanted.contains(profile))
+                .map(|profile| profile.name())
+                .collect::<Vec<_>>()
+                .join(", "),
+            prune::profiles_name(&wanted),
+        )
+        .into());
+    }
+    if !fingerprint.matches(&version, &zig_target, &mcpu) {
+        return Err(format!(
+            "packed toolchain is Zig {} for {}/{} (revision {}), this build needs Zig {version} for {zig_target}/{mcpu} (revision {})",
+            fingerprint.zig_version,
+            fingerprint.zig_target,
+            fingerprint.mcpu,
+            fingerprint.revision,
+            pack::REVISION
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Maps a printed `Bootstrap` back to its variant name.
+fn bootstrap_variant(printed: &str) -> &'static str {
+    match printed {
+        "source" => "Source",
+        "system" => "System",
+        _ => "Prebuilt",
+    }
+}
 
+/// A toolchain in the shared cache: one `zig_rs::unpack_toolchain()` put under
+/// `<cache>/toolchains/<version>/<zig_target>-<mcpu>/`, or else the official
+/// release for the toolchain's platform, as `zig_rs::Toolchain` caches it under
+/// `<cache>/toolchains/<version>/<arch>-<os>-release/`. The version is
+/// [`zig_version()`], so every version of this package pinning the same Zig
+/// release finds the same toolchain; one from another `pack::REVISION` is
+/// left to [`bootstrap()`] to rebuild.
+fn install_cached() -> Result<Option<&'static str>, Box<dyn Error>> {
+    let Some(toolchains) = toolchains_cache_dir() else {
+        return Ok(None);
+    };
+    let version_dir = toolchains.join(zig_version());
+
+    if let Ok((zig_target, mcpu)) = zig_target_mcpu_for_toolchain() {
+        let root = version_dir.join(format!("{zig_target}-{mcpu}"));
+        if let Ok(fingerprint) = pack::Fingerprint::read(&root.join(pack::FINGERPRINT_FILE_NAME))
+            && fingerprint.revision == pack::REVISION
         {
-            let file = fs_err::File::open("zig-bootstrap.zip")?;
-            let mut zip_archive = ZipArchive::new(file)?;
-            zip_archive.extract_unwrapped_root_dir("zig-bootstrap", root_dir_common_filter)?;
+            check_fingerprint(&fingerprint)?;
+            let tagger = log::Tagger::new("cache");
+            eprintln!(
+                "{}",
+                tagger.tag(&format!("using the toolchain in {}", root.display()))
+            );
+            let _ = cache::touch(&root);
+            install_from_cache(&root)?;
+            record_dependency(provenance::Dependency::file(
+                &root,
+                Some(fingerprint.manifest.clone()),
+            ));
+            return Ok(Some(bootstrap_variant(&fingerprint.bootstrap)));
+        }
+    }
+
+    let Some((arch, os)) = release::target_platform(&toolchain_target()) else {
+        return Ok(None);
+    };
+    let root = version_dir.join(format!("{arch}-{os}-release"));
+    if !root.join(manifest::FILE_NAME).is_file() {
+        return Ok(None);
+    }
+    let _ = cache::touch(&root);
+    install_from_cache(&root)?;
+    record_dependency(provenance::Dependency::file(
+        &root,
+        Some(manifest::hash_file(&root.join(manifest::FILE_NAME))?),
+    ));
+    Ok(Some("Prebuilt"))
+}
+
+/// The toolchain cache, unless the `no-cache` feature is on.
+fn toolchains_cache_dir() -> Option<PathBuf> {
+    if env::var_os("CARGO_FEATURE_NO_CACHE").is_some() {
+        return None;
+    }
+    cache::toolchains_dir()
+}
+
+/// Installs the cached toolchain at `root` into [`toolchain_dir()`]: as a copy checked
+/// against its manifest, or with `ZIG_RS_CACHE_LINK` on Unix hosts as
+/// symlinks to `zig` and `lib/`. The license texts are always copied, since
+/// `main()` adds to them.
+fn install_from_cache(root: &Path) -> Result<(), Box<dyn Error>> {
+    let manifest = manifest::Manifest::read(&root.join(manifest::FILE_NAME))?;
+    let link = cfg!(unix) && env::var_os(cache::LINK_ENV).is_some();
+    // Copying through the symlinks of an earlier build would write into the
+    // cache.
+    for name in [zig_exe(), "lib"] {
+        let dest = toolchain_dir().join(name);
+        match fs::symlink_metadata(&dest) {
+            Ok(metadata) if metadata.is_symlink() => fs_err::remove_file(&dest)?,
+            Ok(metadata) if link && metadata.is_dir() => fs_err::remove_dir_all(&dest)?,
+            Ok(_) if link => fs_err::remove_file(&dest)?,
+            _ => {}
+        }
+    }
+    if !link {
+        manifest.install(root, &toolchain_dir())?;
+        return Ok(());
+    }
+    #[cfg(unix)]
+    for name in [zig_exe(), "lib"] {
+        std::os::unix::fs::symlink(root.join(name), toolchain_dir().join(name))?;
+    }
+    let licenses = root.join(licenses::DIR_NAME);
+    if licenses.exists() {
+        copy_dir(&licenses, &toolchain_dir().join(licenses::DIR_NAME))?;
+    }
+    Ok(())
+}
+
+/// Downloads the official release for the toolchain's platform from
+/// ziglang.org.
+fn install_prebuilt_release() -> Result<bool, Box<dyn Error>> {
+    // Vendored sources are for builds without network access.
+    if vendored_sources() {
+        return Ok(false);
+    }
+    let Some((arch, os)) = release::target_platform(&toolchain_target()) else {
+        return Ok(false);
+    };
+    let version = zig_version();
+    download::require_network(
+        &release::url(&version, arch, os),
+        &format!(
+            "set {} to an archive from zig_rs::pack_toolchain(), or put the toolchain in the cache with zig_rs::unpack_toolchain()",
+            pack::ARCHIVE_ENV
+        ),
+    )?;
+    let staging = toolchain_dir().join("zig-release");
+    if staging.exists() {
+        fs_err::remove_dir_all(&staging)?;
+    }
+    let _guard = process::remove_on_interrupt(&staging);
+    let mut reporter = download::PercentReporter::new(log::Tagger::new("download"));
+    let sha256 = release::install(&version, arch, os, &staging, |progress| {
+        reporter.report(progress)
+    })?;
+    record_dependency(provenance::Dependency {
+        uri: release::url(&version, arch, os),
+        sha256: Some(sha256),
+    });
+    fs_err::rename(staging.join(zig_exe()), toolchain_dir().join(zig_exe()))?;
+    fs_err::rename(staging.join("lib"), toolchain_dir().join("lib"))?;
+    licenses::stage(&toolchain_dir(), "zig", &staging.join("LICENSE"))?;
+    fs_err::remove_dir_all(&staging)?;
+    Ok(true)
+}
+
+/// Builds the toolchain with zig-bootstrap into the shared cache, under
+/// `<cache>/toolchains/<version>/<zig_target>-<mcpu>/` where
+/// `install_cached()` finds it next time, and installs it from there. The
+/// entry is locked while it's built, so concurrent builds for the same
+/// target wait for one LLVM build instead of each running their own.
+/// Without a cache directory, or with the `no-cache` feature, it builds
+/// straight into [`toolchain_dir()`].
+fn bootstrap() -> Result<(), Box<dyn Error>> {
+    // A toolchain built with bootstrap options or from another zig-bootstrap
+    // isn't the one the cache key names.
+    let Some(toolchains) = toolchains_cache_dir()
+        .filter(|_| bootstrap_options().is_ok_and(|options| options.is_default()))
+        .filter(|_| bootstrap_ref().is_none())
+    else {
+        return bootstrap_from_source(&toolchain_dir());
+    };
+    let (zig_target, mcpu) = zig_target_mcpu_for_toolchain()?;
+    let version = zig_version();
+    let name = format!("{zig_target}-{mcpu}");
+    let version_dir = toolchains.join(&version);
+    let root = version_dir.join(&name);
+    fs_err::create_dir_all(&version_dir)?;
+    let lock = fs_err::File::create(version_dir.join(format!("{name}.lock")))?;
+    if lock.file().try_lock().is_err() {
+        let tagger = log::Tagger::new("cache");
+        eprintln!(
+            "{}",
+            tagger.tag("waiting for another build of this toolchain to finish")
+        );
+        lock.file().lock()?;
+    }
+    // Whoever held the lock may have just finished it.
+    let current = pack::Fingerprint::read(&root.join(pack::FINGERPRINT_FILE_NAME))
+        .is_ok_and(|fingerprint| fingerprint.revision == pack::REVISION);
+    if !current {
+        let partial = version_dir.join(format!("{name}.partial"));
+        if partial.exists() {
+            fs_err::remove_dir_all(&partial)?;
+        }
+        let _guard = process::remove_on_interrupt(&partial);
+        bootstrap_from_source(&partial)?;
+        licenses::collect(&partial)?;
+        let manifest_path = partial.join(manifest::FILE_NAME);
+        manifest::Manifest::scan(&partial, &[zig_exe(), "lib", licenses::DIR_NAME])?
+            .write(&manifest_path)?;
+        pack::Fingerprint {
+            zig_version: version,
+            zig_target,
+            mcpu,
+            revision: pack::REVISION,
+            bootstrap: "source".into(),
+            lib_profile: prune::FULL.into(),
+            manifest: manifest::hash_file(&manifest_path)?,
+        }
+        .write(&partial.join(pack::FINGERPRINT_FILE_NAME))?;
+        if root.exists() {
+            fs_err::remove_dir_all(&root)?;
+        }
+        fs_err::rename(&partial, &root)?;
+    } else {
+        record_dependency(provenance::Dependency::file(
+            &root,
+            Some(manifest::hash_file(&root.join(manifest::FILE_NAME))?),
+        ));
+    }
+    let _ = cache::touch(&root);
+    install_from_cache(&root)
+}
+
+/// The [`prune`] profiles of [`prune::PROFILE_ENV`] and the `lib-*`
+/// features together.
+fn lib_profiles() -> Result<Vec<prune::Profile>, Box<dyn Error>> {
+    let mut profiles = prune::parse_profiles(&env::var(prune::PROFILE_ENV).unwrap_or_default())
+        .map_err(|e| format!("{}: {e}", prune::PROFILE_ENV))?;
+    for profile in prune::Profile::ALL {
+        let feature = profile.feature().to_uppercase().replace('-', "_");
+        if env::var_os(format!("CARGO_FEATURE_{feature}")).is_some() {
+            profiles.push(profile);
         }
+    }
+    profiles.sort();
+    profiles.dedup();
+    Ok(profiles)
+}
 
-        fs_err::remove_file("zig-bootstrap.zip")?;
+/// Removes what [`lib_profiles()`] say from the installed `lib/`, unless it's
+/// a link into the shared cache, which other builds use whole. The native
+/// libc is the target's, whichever toolchain it is.
+fn prune_lib() -> Result<(), Box<dyn Error>> {
+    let profiles = lib_profiles()?;
+    if profiles.is_empty() {
+        return Ok(());
+    }
+    let lib_dir = toolchain_dir().join("lib");
+    if fs::symlink_metadata(&lib_dir)?.is_symlink() {
+        build::warning(&format!(
+            "not pruning lib/ ({}): it is linked into the cache with {}",
+            prune::profiles_name(&profiles),
+            cache::LINK_ENV
+        ));
+        return Ok(());
     }
+    let zig_target = match zig_target_mcpu_for_build_target() {
+        Ok((zig_target, _)) => zig_target,
+        Err(e) if profiles.contains(&prune::Profile::NativeLibc) => return Err(e.into()),
+        Err(_) => String::new(),
+    };
+    let freed = prune::prune(&lib_dir, &zig_target, &profiles)?;
+    let tagger = log::Tagger::new("prune");
+    eprintln!(
+        "{}",
+        tagger.tag(&format!(
+            "removed {:.1} MiB from lib/ ({})",
+            freed as f64 / (1024.0 * 1024.0),
+            prune::profiles_name(&profiles)
+        ))
+    );
+    *PRUNED.lock().unwrap() = profiles;
+    Ok(())
+}
+
+fn record_dependency(dependency: provenance::Dependency) {
+    DEPENDENCIES.lock().unwrap().push(dependency);
+}
 
-    if docs_rs() {
-        fs_err::write(
-            build::out_dir().join(if build::cargo_cfg_windows() {
-                "zig.exe"
+/// Writes the [`provenance`] statement of the toolchain being installed, with
+/// the `Bootstrap` variant `bootstrap` and the installed `zig_version` if
+/// it runs here.
+fn write_provenance(bootstrap: &str, zig_version: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let out_dir = toolchain_dir();
+    let (zig_target, mcpu) = zig_target_mcpu_for_toolchain().unwrap_or_default();
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| {
+            let feature = key.strip_prefix("CARGO_FEATURE_")?;
+            Some(feature.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+    let mut environment = vec![("host".into(), build::host())];
+    environment.extend(ENVIRONMENT.lock().unwrap().iter().cloned());
+    provenance::Provenance {
+        subjects: vec![
+            (
+                zig_exe().into(),
+                manifest::hash_file(&out_dir.join(zig_exe()))?,
+            ),
+            (
+                manifest::FILE_NAME.into(),
+                manifest::hash_file(&out_dir.join(manifest::FILE_NAME))?,
+            ),
+        ],
+        parameters: vec![
+            ("target".into(), toolchain_target()),
+            ("zig_target".into(), zig_target),
+            ("mcpu".into(), mcpu),
+            (
+                "zig_version".into(),
+                zig_version.map_or_else(self::zig_version, str::to_owned),
+            ),
+            ("source".into(), bootstrap.to_lowercase()),
+            ("features".into(), features.join(",")),
+            (
+                "lib_profile".into(),
+                prune::profiles_name(&PRUNED.lock().unwrap()),
+            ),
+        ]
+        .into_iter()
+        .chain(
+            bootstrap_ref()
+                .filter(|_| bootstrap == "Source")
+                .map(|bootstrap_ref| ("bootstrap_ref".into(), bootstrap_ref)),
+        )
+        .collect(),
+        environment,
+        dependencies: DEPENDENCIES.lock().unwrap().clone(),
+        builder_id: format!(
+            "https://crates.io/crates/{}/{}",
+            build::cargo_pkg_name(),
+            env::var("CARGO_PKG_VERSION")?
+        ),
+    }
+    .write(&out_dir.join(provenance::FILE_NAME))?;
+    Ok(())
+}
+
+/// The Zig version to install: [`ZIG_VERSION_ENV`], or else
+/// [`pinned_zig_version()`].
+fn zig_version() -> String {
+    env::var(ZIG_VERSION_ENV)
+        .ok()
+        .filter(|version| !version.is_empty())
+        .unwrap_or_else(pinned_zig_version)
+}
+
+/// The Zig release this package pins: its version without pre-release or
+/// build metadata, so `0.14.0` and `0.14.0-1` both build Zig 0.14.0 and
+/// share its cached toolchains.
+fn pinned_zig_version() -> String {
+    let major = build::cargo_pkg_version_major();
+    let minor = build::cargo_pkg_version_minor();
+    let patch = build::cargo_pkg_version_patch();
+    format!("{major}.{minor}.{patch}")
+}
+
+/// Whether the `zig version` output `version` is the wanted one; see
+/// `zig_env::Version::matches()`.
+fn version_fits(version: &str, expected: &str) -> bool {
+    match (
+        zig_env::Version::parse(version),
+        zig_env::Version::parse(expected),
+    ) {
+        (Some(version), Some(expected)) => version.matches(&expected),
+        _ => version == expected,
+    }
+}
+
+/// [`BOOTSTRAP_REF_ENV`], if set.
+fn bootstrap_ref() -> Option<String> {
+    env::var(BOOTSTRAP_REF_ENV)
+        .ok()
+        .filter(|bootstrap_ref| !bootstrap_ref.is_empty())
+}
+
+/// Fails when [`ZIG_VERSION_ENV`] asks for a version the `installed` one
+/// isn't, e.g. a zig-bootstrap ref that builds another, or a cached or
+/// packed toolchain mislabelled.
+fn check_installed_version(installed: &str) -> Result<(), Box<dyn Error>> {
+    let Ok(expected) = env::var(ZIG_VERSION_ENV) else {
+        return Ok(());
+    };
+    if expected.is_empty() {
+        return Ok(());
+    }
+    if !version_fits(installed, &expected) {
+        return Err(format!(
+            "the installed toolchain is Zig {installed}, but {ZIG_VERSION_ENV} asks for {expected}"
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Whether [`BOOTSTRAP_DIR_ENV`] or [`BOOTSTRAP_ARCHIVE_ENV`] is set, which
+/// also keeps the `prebuilt` source from downloading.
+fn vendored_sources() -> bool {
+    env::var_os(BOOTSTRAP_DIR_ENV).is_some() || env::var_os(BOOTSTRAP_ARCHIVE_ENV).is_some()
+}
+
+/// Builds the toolchain with zig-bootstrap from [`bootstrap_sources()`] and
+/// moves `zig` and `lib/` into `dest`.
+fn bootstrap_from_source(dest: &Path) -> Result<(), Box<dyn Error>> {
+    let image = env::var(BOOTSTRAP_IMAGE_ENV)
+        .ok()
+        .filter(|image| !image.is_empty());
+    // Before the sources are fetched, so missing tools fail the build early.
+    let msvc = if cfg!(windows) && image.is_none() {
+        msvc_environment()?
+    } else {
+        Vec::new()
+    };
+    let options = bootstrap_options()?;
+    let sources = bootstrap_sources()?;
+    let (zig_target, zig_mcpu) = zig_target_mcpu_for_toolchain()?;
+    // The tokens are held until the build is done. CMake hands the job count
+    // to Make or Ninja as `-j`, and anything run outside it still finds the
+    // jobserver in MAKEFLAGS.
+    let (jobs, _tokens) = match env::var(JOBS_ENV) {
+        Ok(jobs) => {
+            let jobs: usize = jobs
+                .parse()
+                .ok()
+                .filter(|&jobs| jobs > 0)
+                .ok_or_else(|| format!("{JOBS_ENV} is {jobs:?}, not a positive number"))?;
+            (jobs, None)
+        }
+        Err(_) => {
+            let tokens = jobs::acquire_available(jobs::max_jobs());
+            (tokens.jobs(), Some(tokens))
+        }
+    };
+    let mut envs = vec![("CMAKE_BUILD_PARALLEL_LEVEL".to_owned(), jobs.to_string())];
+    envs.extend(bootstrap_option_envs(&options, &sources)?);
+    fs_err::write(
+        build::out_dir().join(BOOTSTRAP_OPTIONS_FILE),
+        options.to_toml(),
+    )?;
+    let mut cmd = match &image {
+        Some(image) => container_command(image, &sources, dest, &envs)?,
+        None => {
+            let mut cmd = Command::new(if cfg!(windows) {
+                "./build.bat"
             } else {
-                "zig"
-            }),
-            [],
-        )?;
-        fs_err::create_dir_all(build::out_dir().join("lib"))?;
+                "./build"
+            });
+            cmd.current_dir(&sources).envs(msvc).envs(envs);
+            jobs::configure(&mut cmd);
+            cmd
+        }
+    };
+    cmd.arg(&zig_target).arg(&zig_mcpu).args(&options.args);
+    cmd.stdin(Stdio::null());
+    phase("bootstrap");
+    run_bootstrap(&mut cmd, &sources)?;
+    phase("toolchain");
+    if let Some(image) = image {
+        ENVIRONMENT
+            .lock()
+            .unwrap()
+            .push(("container_image".into(), image));
+    }
+    let zig_out_dir = sources
+        .join("out")
+        .join(format!("zig-{}-{}", &zig_target, &zig_mcpu));
+    fs_err::create_dir_all(dest)?;
+    fs_err::rename(zig_out_dir.join(zig_exe()), dest.join(zig_exe()))?;
+    fs_err::rename(zig_out_dir.join("lib"), dest.join("lib"))?;
+    // The sources of what is linked into `zig` carry the license texts.
+    for (name, file) in [
+        ("zig", "zig/LICENSE"),
+        ("llvm", "llvm/LICENSE.TXT"),
+        ("zlib", "zlib/LICENSE"),
+        ("zstd", "zstd/LICENSE"),
+    ] {
+        licenses::stage(dest, name, &sources.join(file))?;
+    }
+    Ok(())
+}
+
+/// Runs the zig-bootstrap build `cmd` in `sources`, forwarding its output
+/// and saving it to `OUT_DIR/`[`BOOTSTRAP_LOG`], with a warning every
+/// [`HEARTBEAT`]. On failure the last [`FAILURE_TAIL`] lines are printed
+/// again, and the error names the part that failed.
+fn run_bootstrap(cmd: &mut Command, sources: &Path) -> Result<(), Box<dyn Error>> {
+    let tagger = &log::Tagger::new("bootstrap");
+    let log_path = build::out_dir().join(BOOTSTRAP_LOG);
+    let log_file = Mutex::new(io::BufWriter::new(fs_err::File::create(&log_path)?));
+    let sources = fs_err::canonicalize(sources)?;
+    // The last lines and the part of the build they came from.
+    let state = &Mutex::new((VecDeque::with_capacity(FAILURE_TAIL), None));
+    let (done, finished) = mpsc::channel::<()>();
+    let started = Instant::now();
+    let status = thread::scope(|scope| {
+        scope.spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(HEARTBEAT) {
+                let component = state.lock().unwrap().1.unwrap_or("its first part");
+                let message = format!(
+                    "zig-bootstrap is still building {component} after {} minutes",
+                    started.elapsed().as_secs() / 60
+                );
+                build::warning(&message);
+                tagger.print(&message);
+            }
+        });
+        let status = log::run_tagged_with(cmd, tagger, |line| {
+            let line = line.trim_end_matches(['\r', '\n']);
+            let _ = writeln!(log_file.lock().unwrap(), "{line}");
+            let (tail, component) = &mut *state.lock().unwrap();
+            if let Some(found) = bootstrap_component(&sources, line) {
+                *component = Some(found);
+            }
+            if tail.len() == FAILURE_TAIL {
+                tail.pop_front();
+            }
+            tail.push_back(line.to_owned());
+        });
+        drop(done);
+        status
+    })?;
+    log_file.into_inner().unwrap().flush()?;
+    if status.success() {
+        return Ok(());
+    }
+    let (tail, component) = &*state.lock().unwrap();
+    tagger.print(&format!(
+        "the last {} lines of {}:",
+        tail.len(),
+        log_path.display()
+    ));
+    for line in tail {
+        tagger.print(line);
+    }
+    Err(format!(
+        "zig-bootstrap failed building {} ({status}); its output is in {}",
+        component.unwrap_or("an unknown part"),
+        log_path.display()
+    )
+    .into())
+}
+
+/// The part of zig-bootstrap (LLVM, zlib, zstd or Zig itself) a line of its
+/// output is about, going by the CMake build directories under `out/` or the
+/// source directories it names.
+fn bootstrap_component(sources: &Path, line: &str) -> Option<&'static str> {
+    const COMPONENTS: [(&str, &[&str]); 4] = [
+        ("LLVM", &["llvm", "clang", "lld"]),
+        ("zlib", &["zlib"]),
+        ("zstd", &["zstd"]),
+        ("Zig", &["zig"]),
+    ];
+    let build_dir = COMPONENTS.into_iter().find(|(_, dirs)| {
+        dirs.iter()
+            .any(|dir| line.contains(&format!("out/build-{dir}-")))
+    });
+    let source_dir = || {
+        COMPONENTS.into_iter().find(|(_, dirs)| {
+            dirs.iter()
+                .any(|dir| line.contains(&sources.join(dir).display().to_string()))
+        })
+    };
+    build_dir.or_else(source_dir).map(|(name, _)| name)
+}
+
+/// The environment of a Visual Studio developer prompt for the host, which
+/// `build.bat` needs: nothing when Cargo already runs in one, otherwise what
+/// `vcvarsall.bat` sets for the installation vswhere finds, or else what cc
+/// finds through the Visual Studio setup COM API. Fails naming every missing
+/// prerequisite of the C++ build tools, CMake and Python 3.
+fn msvc_environment() -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let host_arch = env::var("HOST")?
+        .split('-')
+        .next()
+        .unwrap_or_default()
+        .to_owned();
+    let mut missing = Vec::new();
+    let vars = if env::var_os("VSINSTALLDIR").is_some() {
+        Vec::new()
+    } else if let Some(installation) = vswhere(&host_arch) {
+        vcvarsall(&installation, &host_arch)?
+    } else if let Some(cl) = cc::windows_registry::find_tool(&host_arch, "cl.exe") {
+        cl.env()
+            .iter()
+            .map(|(key, value)| {
+                (
+                    key.to_string_lossy().into_owned(),
+                    value.to_string_lossy().into_owned(),
+                )
+            })
+            .collect()
     } else {
-        let (zig_target, zig_mcpu) = zig_target_mcpu_for_build_target()
-            .ok_or_else(|| format!("unmapped target: {}", build::target()))?;
-        let mut cmd = Command::new(if cfg!(windows) {
-            "./build.bat"
-        } else {
-            "./build"
+        missing.push(
+            "the Visual Studio C++ build tools (the \"Desktop development with C++\" workload)",
+        );
+        Vec::new()
+    };
+    let path = vars
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("PATH"))
+        .map(|(_, path)| path.into())
+        .or_else(|| env::var_os("PATH"))
+        .unwrap_or_default();
+    let on_path = |programs: &[&str]| {
+        env::split_paths(&path)
+            .any(|dir| programs.iter().any(|program| dir.join(program).is_file()))
+    };
+    if !on_path(&["cmake.exe"]) {
+        missing.push("CMake (cmake.exe on PATH)");
+    }
+    if !on_path(&["python.exe", "python3.exe", "py.exe"]) {
+        missing.push("Python 3 (python.exe or py.exe on PATH)");
+    }
+    if !missing.is_empty() {
+        return Err(format!(
+            "building Zig from source on Windows needs {}; install them, or set {BOOTSTRAP_IMAGE_ENV} to build in a container",
+            missing.join(", ")
+        )
+        .into());
+    }
+    Ok(vars)
+}
+
+/// The newest Visual Studio installation with the C++ tools for `host_arch`,
+/// from vswhere.
+fn vswhere(host_arch: &str) -> Option<PathBuf> {
+    let vswhere = env::var_os("ProgramFiles(x86)")
+        .map(|dir| PathBuf::from(dir).join(r"Microsoft Visual Studio\Installer\vswhere.exe"))
+        .filter(|vswhere| vswhere.is_file())
+        .unwrap_or_else(|| "vswhere.exe".into());
+    let component = if host_arch == "aarch64" {
+        "Microsoft.VisualStudio.Component.VC.Tools.ARM64"
+    } else {
+        "Microsoft.VisualStudio.Component.VC.Tools.x86.x64"
+    };
+    let output = process::output(Command::new(vswhere).args([
+        "-latest",
+        "-products",
+        "*",
+        "-requires",
+        component,
+        "-property",
+        "installationPath",
+        "-utf8",
+    ]))
+    .ok()
+    .filter(|output| output.status.success())?;
+    let installation = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    (!installation.is_empty()).then(|| installation.into())
+}
+
+/// The environment `vcvarsall.bat` of the Visual Studio `installation` sets
+/// up for building on `host_arch`.
+fn vcvarsall(
+    installation: &Path,
+    host_arch: &str,
+) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let vcvarsall = installation.join(r"VC\Auxiliary\Build\vcvarsall.bat");
+    let arch = match host_arch {
+        "aarch64" => "arm64",
+        "i686" | "i586" => "x86",
+        _ => "x64",
+    };
+    // A script rather than `cmd /c` arguments, which cmd would unquote.
+    let script = build::out_dir().join("vcvars.bat");
+    fs_err::write(
+        &script,
+        format!(
+            "@call \"{}\" {arch} >nul || exit /b 1\r\n@set\r\n",
+            vcvarsall.display()
+        ),
+    )?;
+    let output = process::output(Command::new("cmd").arg("/d").arg("/c").arg(&script))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} {arch} failed: {}",
+            vcvarsall.display(),
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .filter(|(key, _)| !key.is_empty())
+        .map(|(key, value)| (key.into(), value.into()))
+        .collect())
+}
+
+/// A command running zig-bootstrap's `./build` with the environment `envs`
+/// in a container of `image`, still missing the target and CPU. `sources` and
+/// `dest` are mounted at their own paths, so paths mean the same inside and
+/// out. The jobserver doesn't reach into the container.
+fn container_command(
+    image: &str,
+    sources: &Path,
+    dest: &Path,
+    envs: &[(String, String)],
+) -> Result<Command, Box<dyn Error>> {
+    let runtime = match env::var_os(CONTAINER_RUNTIME_ENV) {
+        Some(runtime) => PathBuf::from(runtime),
+        None => ["podman", "docker"]
+            .into_iter()
+            .map(PathBuf::from)
+            .find(|runtime| {
+                Command::new(runtime)
+                    .arg("--version")
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status()
+                    .is_ok_and(|status| status.success())
+            })
+            .ok_or_else(|| {
+                format!(
+                    "{BOOTSTRAP_IMAGE_ENV} is set but neither podman nor docker runs; install one or set {CONTAINER_RUNTIME_ENV}"
+                )
+            })?,
+    };
+    fs_err::create_dir_all(dest)?;
+    let sources = fs_err::canonicalize(sources)?;
+    let dest = fs_err::canonicalize(dest)?;
+    let mut cmd = Command::new(&runtime);
+    cmd.args(["run", "--rm", "--init"]);
+    for dir in [&sources, &dest] {
+        cmd.arg("--volume").arg(format!("{0}:{0}", dir.display()));
+    }
+    cmd.arg("--workdir").arg(&sources);
+    for (key, value) in envs {
+        cmd.arg("--env").arg(format!("{key}={value}"));
+    }
+    // Rootless Podman maps the container's root to us, but as root Docker
+    // would leave files behind that we can't move.
+    #[cfg(unix)]
+    if runtime.file_stem().is_none_or(|stem| stem != "podman") {
+        // SAFETY: getuid() and getgid() always succeed.
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        cmd.arg("--user").arg(format!("{uid}:{gid}"));
+    }
+    cmd.arg(image).arg("./build");
+    Ok(cmd)
+}
+
+/// The `BootstrapOptions` from the configuration, with the ones set in
+/// [`CC_ENV`], [`CXX_ENV`], [`BOOTSTRAP_CMAKE_ARGS_ENV`],
+/// [`BOOTSTRAP_ASSERTIONS_ENV`] and [`BOOTSTRAP_ARGS_ENV`] instead.
+fn bootstrap_options() -> Result<config::BootstrapOptions, Box<dyn Error>> {
+    let mut options = config::Config::load()?.bootstrap;
+    let var = |key: &str| env::var(key).ok().filter(|value| !value.trim().is_empty());
+    let words = |value: String| value.split_whitespace().map(str::to_owned).collect();
+    if let Some(cc) = var(CC_ENV) {
+        options.cc = Some(cc);
+    }
+    if let Some(cxx) = var(CXX_ENV) {
+        options.cxx = Some(cxx);
+    }
+    if let Some(cmake_args) = var(BOOTSTRAP_CMAKE_ARGS_ENV) {
+        options.cmake_args = words(cmake_args);
+    }
+    if let Some(assertions) = var(BOOTSTRAP_ASSERTIONS_ENV) {
+        options.assertions = Some(match assertions.trim() {
+            "1" => true,
+            "0" => false,
+            _ => {
+                return Err(
+                    format!("{BOOTSTRAP_ASSERTIONS_ENV} is {assertions:?}, not 1 or 0").into(),
+                );
+            }
         });
-        cmd.current_dir("zig-bootstrap")
-            .arg(&zig_target)
-            .arg(&zig_mcpu);
-        cmd.stdin(Stdio::null())
-            .stdout(io::stderr())
-            .stderr(io::stderr());
-        let status = cmd.status()?;
-        if !status.success() {
-            return Err(format!("zig-bootstrap {:?} failed: {}", &cmd, status).into());
-        }
-        let zig_out_dir = Path::new("zig-bootstrap")
-            .join("out")
-            .join(format!("zig-{}-{}", &zig_target, &zig_mcpu));
-        fs_err::rename(
-            zig_out_dir.join(if build::cargo_cfg_windows() {
-                "zig.exe"
-            } else {
-                "zig"
-            }),
-            build::out_dir().join(if build::cargo_cfg_windows() {
-                "zig.exe"
-            } else {
-                "zig"
-            }),
-        )?;
-        fs_err::rename(zig_out_dir.join("lib"), build::out_dir().join("lib"))?;
     }
+    if let Some(args) = var(BOOTSTRAP_ARGS_ENV) {
+        options.args = words(args);
+    }
+    if let Some(arg) = options
+        .cmake_args
+        .iter()
+        .find(|arg| !arg.starts_with("-D") || !arg.contains('='))
+    {
+        return Err(format!(
+            "bootstrap CMake argument {arg:?} isn't -DNAME=VALUE or -DNAME:TYPE=VALUE"
+        )
+        .into());
+    }
+    Ok(options)
+}
+
+/// The environment that applies `options` to zig-bootstrap's CMake: `CC`
+/// and `CXX`, and a `CMAKE_TOOLCHAIN_FILE` in `sources/out/` with the cache
+/// entries, which CMake reads before every project.
+fn bootstrap_option_envs(
+    options: &config::BootstrapOptions,
+    sources: &Path,
+) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let mut envs = Vec::new();
+    let mut environment = ENVIRONMENT.lock().unwrap();
+    if let Some(cc) = &options.cc {
+        envs.push(("CC".to_owned(), cc.clone()));
+        environment.push(("bootstrap_cc".into(), cc.clone()));
+    }
+    if let Some(cxx) = &options.cxx {
+        envs.push(("CXX".to_owned(), cxx.clone()));
+        environment.push(("bootstrap_cxx".into(), cxx.clone()));
+    }
+    let mut entries = options.cmake_args.clone();
+    if let Some(assertions) = options.assertions {
+        let value = if assertions { "ON" } else { "OFF" };
+        entries.push(format!("-DLLVM_ENABLE_ASSERTIONS:BOOL={value}"));
+    }
+    if !options.args.is_empty() {
+        environment.push(("bootstrap_args".into(), options.args.join(" ")));
+    }
+    if entries.is_empty() {
+        return Ok(envs);
+    }
+    environment.push(("bootstrap_cmake_args".into(), entries.join(" ")));
+    let mut file = String::from("# Written by zig-rs from its bootstrap options.\n");
+    for entry in &entries {
+        let (name, value) = entry[2..]
+            .split_once('=')
+            .expect("checked in bootstrap_options()");
+        let (name, kind) = name.split_once(':').unwrap_or((name, "STRING"));
+        let value = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('$', "\\$");
+        file += &format!("set({name} \"{value}\" CACHE {kind} \"\" FORCE)\n");
+    }
+    let out = sources.join("out");
+    fs_err::create_dir_all(&out)?;
+    // Canonical, like the paths mounted into a container.
+    let path = fs_err::canonicalize(&out)?.join("zig-rs-options.cmake");
+    fs_err::write(&path, file)?;
+    envs.push((
+        "CMAKE_TOOLCHAIN_FILE".to_owned(),
+        path.to_string_lossy().into_owned(),
+    ));
+    Ok(envs)
+}
 
+/// The zig-bootstrap source tree: [`BOOTSTRAP_DIR_ENV`] if set, otherwise
+/// `./zig-bootstrap/` (the submodule of a Git checkout). When that is missing
+/// it is extracted from [`BOOTSTRAP_ARCHIVE_ENV`], or else from a download
+/// of the `.zip` of the tag matching this crate's version. With either
+/// variable set nothing is downloaded.
+///
+/// [`ZIG_VERSION_ENV`] and [`BOOTSTRAP_REF_ENV`] download another tag,
+/// branch or commit instead, extracted into `OUT_DIR` since the submodule
+/// holds the pinned one.
+fn bootstrap_sources() -> Result<PathBuf, Box<dyn Error>> {
+    let bootstrap_ref = bootstrap_ref();
+    let version = zig_version();
+    let url = bootstrap_source::url(&version, bootstrap_ref.as_deref());
+    let tag = bootstrap_ref.unwrap_or(version);
+    let vendor = format!(
+        "download {url} and set {BOOTSTRAP_ARCHIVE_ENV} to it, or set {BOOTSTRAP_DIR_ENV} to the extracted zig-bootstrap {tag} sources"
+    );
+    if let Some(dir) = env::var_os(BOOTSTRAP_DIR_ENV) {
+        let dir = PathBuf::from(dir);
+        let script = if cfg!(windows) { "build.bat" } else { "build" };
+        if !dir.join(script).exists() {
+            return Err(format!(
+                "{BOOTSTRAP_DIR_ENV} is set to {}, which has no {script} script; point it at the zig-bootstrap {tag} sources",
+                dir.display()
+            )
+            .into());
+        }
+        record_dependency(provenance::Dependency::file(&dir, None));
+        return Ok(dir);
+    }
+    let dir = if tag == pinned_zig_version() {
+        PathBuf::from("zig-bootstrap")
+    } else {
+        build::out_dir().join(bootstrap_source::archive_stem(&tag))
+    };
+    let sibling = |suffix: &str| {
+        let mut path = dir.clone().into_os_string();
+        path.push(suffix);
+        PathBuf::from(path)
+    };
+    if fs::exists(&dir)? {
+        record_dependency(provenance::Dependency::file(&dir, None));
+        return Ok(dir);
+    }
+
+    // Where the archive came from decides whether it's removed after
+    // extraction and how the provenance names it.
+    let (archive, name, origin) = match env::var_os(BOOTSTRAP_ARCHIVE_ENV) {
+        Some(archive) => {
+            let archive = PathBuf::from(archive);
+            let name = file_name(&archive);
+            (archive, name, Origin::Given)
+        }
+        None => match bootstrap_source::cached(&tag) {
+            Some(archive) => {
+                let _ = cache::touch(&archive);
+                let name = file_name(&archive);
+                (archive, name, Origin::Prefetched)
+            }
+            None => {
+                download::require_network(&url, &vendor)?;
+                let archive = sibling(".zip");
+                let mut reporter = download::PercentReporter::new(log::Tagger::new("download"));
+                download::download(&url, &archive, |progress| reporter.report(progress))
+                    .map_err(|e| {
+                        format!(
+                            "downloading {url} failed: {e}; to build without network access, {vendor}"
+                        )
+                    })?;
+                // A mirror may serve the sources in another format.
+                let format = archive::Format::detect(&archive)?
+                    .ok_or_else(|| format!("{url} isn't an archive"))?;
+                let name = format!(
+                    "{}.{}",
+                    bootstrap_source::archive_stem(&tag),
+                    format.extension()
+                );
+                (archive, name, Origin::Downloaded)
+            }
+        },
+    };
+    let format = match archive::Format::from_name(&name) {
+        Some(format) => format,
+        None => archive::Format::detect(&archive)?.ok_or_else(|| {
+            format!(
+                "{BOOTSTRAP_ARCHIVE_ENV} is set to {}, which isn't a .zip, .tar.gz, .tar.xz or .tar.zst archive",
+                archive.display()
+            )
+        })?,
+    };
+    let sha256 = manifest::hash_file(&archive)?;
+    match integrity::check_sha256(&archive, &name, &url) {
+        Ok(true) => {}
+        Ok(false) => build::warning(&integrity::unverified_warning(&name, &url)),
+        Err(e) => {
+            if origin == Origin::Downloaded {
+                fs_err::remove_file(&archive)?;
+            }
+            return Err(e);
+        }
+    }
+
+    // Extract next to the final location so an interrupted extraction never
+    // looks like a complete source tree.
+    let partial = sibling(".partial");
+    if partial.exists() {
+        fs_err::remove_dir_all(&partial)?;
+    }
+    let _guard = process::remove_on_interrupt(&partial);
+    archive::extract(&archive, format, &partial)?;
+    fs_err::rename(&partial, &dir)?;
+    if origin == Origin::Given {
+        record_dependency(provenance::Dependency::file(&archive, Some(sha256)));
+    } else {
+        if origin == Origin::Downloaded {
+            fs_err::remove_file(&archive)?;
+        }
+        record_dependency(provenance::Dependency {
+            uri: url,
+            sha256: Some(sha256),
+        });
+    }
+    Ok(dir)
+}
+
+/// Where [`bootstrap_sources()`] got the zig-bootstrap archive from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Origin {
+    /// `ZIG_RS_BOOTSTRAP_ARCHIVE`.
+    Given,
+    /// The downloads cache, e.g. from `cargo zig prefetch`.
+    Prefetched,
+    /// Downloaded by this build into `OUT_DIR`.
+    Downloaded,
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Runs the installed `zig version`. Gives `None` when the toolchain can't run
+/// here, e.g. the target's when cross-compiling.
+fn installed_zig_version() -> Option<String> {
+    let output =
+        process::output(Command::new(toolchain_dir().join(zig_exe())).arg("version")).ok()?;
+    let version = String::from_utf8(output.stdout).ok()?;
+    (output.status.success() && !version.trim().is_empty()).then(|| version.trim().to_owned())
+}
+
+/// Writes the `__with_toolchain!` macro `src/macros.rs` includes, which
+/// hands `include_zig!` and `zig!` the toolchain and the `zig build-lib`
+/// flags for the target, and puts the directory they write their libraries
+/// to on the linker's search path.
+fn write_macros() -> Result<(), Box<dyn Error>> {
+    let out_dir = build::out_dir();
+    let dir = out_dir.join("macros");
+    fs_err::create_dir_all(&dir)?;
+    println!("cargo:rustc-link-search=native={}", dir.display());
+    let target = build::target();
+    let body = match target_triple::zig_target(&target) {
+        Some(zig_target) => {
+            let mcpu = config::Config::load()?
+                .mcpu(&target)
+                .unwrap_or("baseline")
+                .to_owned();
+            // As `Build` compiles `.zig` files.
+            let optimize = match env::var("OPT_LEVEL").as_deref() {
+                Ok("0") => "Debug",
+                Ok("s" | "z") => "ReleaseSmall",
+                _ if env::var_os("CARGO_CFG_DEBUG_ASSERTIONS").is_some() => "ReleaseSafe",
+                _ => "ReleaseFast",
+            };
+            let debug = env::var("DEBUG").is_ok_and(|d| d != "false");
+            let pic = !target.contains("-windows")
+                && !target.contains("-uefi")
+                && !zig_target.contains("-freestanding-");
+            let mut args = vec![
+                "-target".to_owned(),
+                zig_target,
+                format!("-mcpu={mcpu}"),
+                "-O".into(),
+                optimize.into(),
+                if debug { "-fno-strip" } else { "-fstrip" }.into(),
+                if pic { "-fPIC" } else { "-fno-PIC" }.into(),
+            ];
+            if !target.starts_with("wasm32-unknown")
+                && !target.contains("-none")
+                && !target.contains("-uefi")
+            {
+                args.push("-lc".into());
+            }
+            let archive = if target.contains("-msvc") {
+                "{}.lib"
+            } else {
+                "lib{}.a"
+            };
+            let args: Vec<String> = args.iter().map(|arg| format!("{arg:?}")).collect();
+            format!(
+                "$crate::__macros::$macro!(({:?}, {:?}, {:?}, {archive:?}, [{}]) $($input)*);",
+                out_dir.join(zig_exe()).display().to_string(),
+                out_dir.join("lib").display().to_string(),
+                dir.display().to_string(),
+                args.join(", "),
+            )
+        }
+        None => format!("::core::compile_error!(\"Zig can't compile for {target}\");"),
+    };
+    fs_err::write(
+        out_dir.join("macros.rs"),
+        format!(
+            "#[doc(hidden)]\n#[macro_export]\nmacro_rules! __with_toolchain {{\n    ($macro:ident $($input:tt)*) => {{ {body} }};\n}}\n"
+        ),
+    )?;
+    Ok(())
+}
+
+/// Writes the `BuildInfo` expression that `src/build_info.rs` includes as
+/// `BUILD_INFO`, for the host's toolchain. `bootstrap` is a `Bootstrap`
+/// variant name. Without a version from the toolchain itself the pinned
+/// [`zig_version()`] is used.
+fn write_build_info(
+    bootstrap: &str,
+    zig_version: Option<String>,
+    target_toolchain: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let zig_version = zig_version.unwrap_or_else(self::zig_version);
+    let zig_commit = match zig_version.split_once('+') {
+        Some((_, commit)) => format!("Some({commit:?})"),
+        None => "None".into(),
+    };
+    let bootstrap_ref = bootstrap_ref().filter(|_| bootstrap == "Source");
+    let (zig_target, mcpu) = zig_target_mcpu_for_toolchain().unwrap_or_default();
+    let out_dir = build::out_dir();
+    let zig = out_dir.join(zig_exe());
+    fs_err::write(
+        out_dir.join("build_info.rs"),
+        format!(
+            "BuildInfo {{
+    zig_version: {zig_version:?},
+    zig_commit: {zig_commit},
+    target: {target:?},
+    zig_target: {zig_target:?},
+    mcpu: {mcpu:?},
+    bootstrap: Bootstrap::{bootstrap},
+    bootstrap_ref: {bootstrap_ref:?},
+    zig: {zig:?},
+    lib_dir: {lib_dir:?},
+    lib_profiles: &[{lib_profiles}],
+    target_toolchain: {target_toolchain:?},
+}}
+",
+            target = toolchain_target(),
+            zig = zig.display().to_string(),
+            lib_dir = out_dir.join("lib").display().to_string(),
+            lib_profiles = PRUNED
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|profile| format!("Profile::{profile:?}"))
+                .collect::<Vec<_>>()
+                .join(", "),
+            target_toolchain = target_toolchain.map(|root| root.display().to_string()),
+        ),
+    )?;
     Ok(())
 }
 
@@ -130,14 +1751,27 @@ fn docs_rs() -> bool {
     env::var("DOCS_RS").is_ok()
 }
 
-/// Returns a `(zig_target, zig_mcpu)` tuple for the Rust target triple & CPU
-/// features specified by the environment variables provided to `build.rs`.
-fn zig_target_mcpu_for_build_target() -> Option<(String, String)> {
-    // Just basic target mapping for now.
-    Some(match build::target().as_str() {
-        "aarch64-apple-darwin" => ("aarch64-macos-none".into(), "baseline".into()),
-        "x86_64-unknown-linux-gnu" => ("x86_64-linux-gnu".into(), "baseline".into()),
-        "x86_64-pc-windows-gnu" => ("x86_64-windows-gnu".into(), "baseline".into()),
-        _ => return None,
-    })
+/// Returns a `(zig_target, zig_mcpu)` tuple for the Rust target Cargo
+/// describes to `build.rs`; see `src/bootstrap_target.rs`. An `mcpu`
+/// configured for the target (see `src/config.rs`) replaces the default;
+/// `main()` has already reported a configuration that doesn't load.
+fn zig_target_mcpu_for_build_target() -> Result<(String, String), bootstrap_target::UnmappedTarget>
+{
+    let (zig_target, mcpu) =
+        bootstrap_target::zig_target_mcpu(&bootstrap_target::TargetCfg::from_env())?;
+    let configured = config::Config::load()
+        .ok()
+        .and_then(|config| config.mcpu(&build::target()).map(str::to_owned));
+    Ok((zig_target, configured.unwrap_or(mcpu)))
+}
+
+/// [`zig_target_mcpu_for_build_target()`] for the platform the toolchain
+/// being installed runs on.
+fn zig_target_mcpu_for_toolchain() -> Result<(String, String), bootstrap_target::UnmappedTarget> {
+    let Installation { cfg, .. } = installation();
+    let (zig_target, mcpu) = bootstrap_target::zig_target_mcpu(&cfg)?;
+    let configured = config::Config::load()
+        .ok()
+        .and_then(|config| config.mcpu(&cfg.triple).map(str::to_owned));
+    Ok((zig_target, configured.unwrap_or(mcpu)))
 }
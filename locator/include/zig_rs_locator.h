@@ -0,0 +1,30 @@
+/* Finding the Zig toolchain the zig crate installed. See src/lib.rs. */
+
+#ifndef ZIG_RS_LOCATOR_H
+#define ZIG_RS_LOCATOR_H
+
+#include <stdint.h>
+
+#define ZIG_RS_LOCATOR_ABI_VERSION 1
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The version of this API, to compare with ZIG_RS_LOCATOR_ABI_VERSION. */
+uint32_t zig_rs_locator_abi_version(void);
+
+/* The path of the zig binary. */
+const char *zig_rs_locator_zig_path(void);
+
+/* The toolchain's lib/ directory, for ZIG_LIB_DIR. */
+const char *zig_rs_locator_lib_dir(void);
+
+/* The Zig version, e.g. "0.14.0". */
+const char *zig_rs_locator_version(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,62 @@
+//! A C API for finding the toolchain the `zig` crate's build script
+//! installed, for CMake superbuilds, Meson projects and other non-Rust
+//! builds in the same repository. Build it with
+//! `cargo build -p zig-rs-locator` and link `libzig_rs_locator.a` or load the
+//! shared library; `include/zig_rs_locator.h` declares:
+//!
+//! ```c
+//! #include <zig_rs_locator.h>
+//!
+//! const char *zig = zig_rs_locator_zig_path();
+//! ```
+//!
+//! Every function returns a NUL-terminated UTF-8 string owned by the
+//! library, valid for as long as it is loaded. The functions never fail, so
+//! they are safe to call from any thread. [`ABI_VERSION`] only goes up when
+//! a function is removed or changes meaning.
+
+use std::{
+    ffi::{CString, c_char},
+    sync::OnceLock,
+};
+
+/// What `zig_rs_locator_abi_version()` returns; `ZIG_RS_LOCATOR_ABI_VERSION`
+/// in the header.
+pub const ABI_VERSION: u32 = 1;
+
+/// The version of this API, to compare with `ZIG_RS_LOCATOR_ABI_VERSION`.
+#[unsafe(no_mangle)]
+pub extern "C" fn zig_rs_locator_abi_version() -> u32 {
+    ABI_VERSION
+}
+
+/// The path of the `zig` binary.
+#[unsafe(no_mangle)]
+pub extern "C" fn zig_rs_locator_zig_path() -> *const c_char {
+    static ZIG: OnceLock<CString> = OnceLock::new();
+    ZIG.get_or_init(|| c_string(&zig_rs::zig_path().to_string_lossy()))
+        .as_ptr()
+}
+
+/// The toolchain's `lib/` directory, for `ZIG_LIB_DIR`.
+#[unsafe(no_mangle)]
+pub extern "C" fn zig_rs_locator_lib_dir() -> *const c_char {
+    static LIB_DIR: OnceLock<CString> = OnceLock::new();
+    LIB_DIR
+        .get_or_init(|| c_string(&zig_rs::lib_dir().to_string_lossy()))
+        .as_ptr()
+}
+
+/// The Zig version, e.g. `0.14.0`.
+#[unsafe(no_mangle)]
+pub extern "C" fn zig_rs_locator_version() -> *const c_char {
+    static VERSION: OnceLock<CString> = OnceLock::new();
+    VERSION
+        .get_or_init(|| c_string(zig_rs::BUILD_INFO.zig_version))
+        .as_ptr()
+}
+
+/// `s` as a C string, empty if it holds a NUL.
+fn c_string(s: &str) -> CString {
+    CString::new(s).unwrap_or_default()
+}
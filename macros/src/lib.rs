@@ -0,0 +1,344 @@
+//! The proc-macros behind `zig_rs::include_zig!` and `zig_rs::zig!`; use
+//! them through the `macros` feature of the `zig` crate, whose build script
+//! tells them which toolchain and flags to compile with.
+//!
+//! Each invocation compiles its Zig code into a static library in the
+//! `zig` crate's `OUT_DIR`, which that crate's build script puts on the
+//! linker's search path, and expands to a `#[link]`ed `extern "C"` block
+//! declaring the `export fn`s of the code. Libraries are named by a hash of
+//! the code and flags, so unchanged code isn't compiled again.
+
+// Shared with the `zig` crate, which writes the same declarations for
+// `Build::emit_bindings()`, and tests the examples in its documentation.
+#[cfg(not(doctest))]
+#[path = "../../src/bindings.rs"]
+#[allow(dead_code)]
+mod bindings;
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+
+use proc_macro::{Delimiter, Literal, Span, TokenStream, TokenTree};
+
+/// `include_zig!((toolchain...) "src/math.zig")`: compiles the Zig file,
+/// relative to the crate's `Cargo.toml`, and declares its exports.
+#[proc_macro]
+pub fn include_zig(input: TokenStream) -> TokenStream {
+    expand(input, |toolchain, rest| {
+        let [TokenTree::Literal(literal)] = rest else {
+            return Err("expected the path of a .zig file".into());
+        };
+        let path = string_literal(literal).ok_or("expected the path of a .zig file")?;
+        let manifest_dir = env::var_os("CARGO_MANIFEST_DIR").unwrap_or_default();
+        let root = Path::new(&manifest_dir).join(path);
+        let source = std::fs::read_to_string(&root)
+            .map_err(|e| format!("couldn't read {}: {e}", root.display()))?;
+        let mut tokens = toolchain.compile(&root, &source)?;
+        // Makes rustc expand the macro again when the file changes.
+        tokens.insert_str(
+            0,
+            &format!("const _: &[u8] = ::core::include_bytes!({:?});\n", root),
+        );
+        Ok(tokens)
+    })
+}
+
+/// `zig!((toolchain...) ...Zig source...)`: compiles Zig written inline and
+/// declares its exports.
+#[proc_macro]
+pub fn zig(input: TokenStream) -> TokenStream {
+    expand(input, |toolchain, rest| {
+        let mut writer = SourceWriter::default();
+        writer.write_stream(rest.iter().cloned());
+        let source = writer.out + "\n";
+        std::fs::create_dir_all(&toolchain.dir)
+            .map_err(|e| format!("couldn't create {}: {e}", toolchain.dir.display()))?;
+        let root = toolchain
+            .dir
+            .join(format!("inline-{:016x}.zig", fnv1a(source.as_bytes())));
+        if !root.exists() {
+            std::fs::write(&root, &source)
+                .map_err(|e| format!("couldn't write {}: {e}", root.display()))?;
+        }
+        toolchain.compile(&root, &source)
+    })
+}
+
+/// Splits off the toolchain the `zig` crate's `macro_rules!` wrappers pass
+/// first, runs `f` with it and the rest of the input, and turns errors into
+/// a `compile_error!`.
+fn expand(
+    input: TokenStream,
+    f: impl FnOnce(&Toolchain, &[TokenTree]) -> Result<String, String>,
+) -> TokenStream {
+    let mut tokens: Vec<TokenTree> = input.into_iter().collect();
+    let result = match tokens.first() {
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => {
+            let toolchain = Toolchain::parse(group.stream());
+            tokens.remove(0);
+            toolchain.and_then(|toolchain| f(&toolchain, &tokens))
+        }
+        _ => Err("use zig_rs::include_zig! and zig_rs::zig!, not zig_macros".into()),
+    };
+    let expansion = result.unwrap_or_else(|e| format!("::core::compile_error!({e:?});"));
+    TokenStream::from_str(&expansion).unwrap_or_else(|e| {
+        TokenStream::from_str(&format!("::core::compile_error!({:?});", e.to_string())).unwrap()
+    })
+}
+
+/// What the `zig` crate's build script says to compile with.
+struct Toolchain {
+    zig: PathBuf,
+    lib_dir: PathBuf,
+    /// Where the libraries go, which is on the linker's search path.
+    dir: PathBuf,
+    /// The library's file name with `{}` for its name, e.g. `lib{}.a`.
+    archive: String,
+    /// `-target`, `-mcpu`, `-O` and the rest for `zig build-lib`.
+    args: Vec<String>,
+}
+
+impl Toolchain {
+    /// Parses `"zig", "lib_dir", "dir", "archive", ["arg", ...]`.
+    fn parse(stream: TokenStream) -> Result<Self, String> {
+        let mut strings = Vec::new();
+        let mut args = Vec::new();
+        for token in stream {
+            match token {
+                TokenTree::Literal(literal) => {
+                    strings.push(string_literal(&literal).ok_or("expected a string")?)
+                }
+                TokenTree::Group(group) if group.delimiter() == Delimiter::Bracket => {
+                    for token in group.stream() {
+                        if let TokenTree::Literal(literal) = token {
+                            args.push(string_literal(&literal).ok_or("expected a string")?);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        let [zig, lib_dir, dir, archive] = <[String; 4]>::try_from(strings)
+            .map_err(|_| "malformed toolchain from the zig crate")?;
+        Ok(Self {
+            zig: zig.into(),
+            lib_dir: lib_dir.into(),
+            dir: dir.into(),
+            archive,
+            args,
+        })
+    }
+
+    /// Compiles the Zig module rooted at `root`, whose code is `source`, into
+    /// a static library unless it's there already, and returns the
+    /// declarations of its exports.
+    fn compile(&self, root: &Path, source: &str) -> Result<String, String> {
+        let exports = bindings::parse(source)?;
+        let declarations = bindings::rust_bindings(&exports)?;
+        let mut key = Vec::new();
+        for part in [root.to_string_lossy().as_ref(), source]
+            .into_iter()
+            .chain(self.args.iter().map(String::as_str))
+        {
+            key.extend_from_slice(part.as_bytes());
+            key.push(0);
+        }
+        let name = format!("zig_macros_{:016x}", fnv1a(&key));
+        let archive = self.dir.join(self.archive.replace("{}", &name));
+        if !archive.exists() {
+            self.build_lib(root, &name, &archive)?;
+        }
+
+        Ok(format!(
+            "#[link(name = {name:?}, kind = \"static\")]\n{declarations}"
+        ))
+    }
+
+    fn build_lib(&self, root: &Path, name: &str, archive: &Path) -> Result<(), String> {
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("couldn't create {}: {e}", self.dir.display()))?;
+        let partial = archive.with_extension(format!("{}.partial", std::process::id()));
+        let cache = self.dir.join("zig-cache");
+        let mut cmd = Command::new(&self.zig);
+        cmd.arg("build-lib")
+            .arg(root)
+            .args(&self.args)
+            .arg("--name")
+            .arg(name)
+            .arg(format!("-femit-bin={}", partial.display()))
+            .env("ZIG_LIB_DIR", &self.lib_dir);
+        for (var, dir) in [
+            ("ZIG_LOCAL_CACHE_DIR", cache.join("local")),
+            ("ZIG_GLOBAL_CACHE_DIR", cache.join("global")),
+        ] {
+            if env::var_os(var).is_none() {
+                cmd.env(var, dir);
+            }
+        }
+        let output = cmd
+            .output()
+            .map_err(|e| format!("couldn't run {}: {e}", self.zig.display()))?;
+        if !output.status.success() {
+            let _ = std::fs::remove_file(&partial);
+            return Err(format!(
+                "zig build-lib {} failed: {}\n{}",
+                root.display(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            ));
+        }
+        std::fs::rename(&partial, archive)
+            .map_err(|e| format!("couldn't write {}: {e}", archive.display()))
+    }
+}
+
+/// Writes tokens back out as source, spaced and broken into lines as they
+/// were written, which Zig needs: `@import` is one token to Zig but two to
+/// Rust.
+#[derive(Default)]
+struct SourceWriter {
+    out: String,
+    line: usize,
+    column: usize,
+}
+
+impl SourceWriter {
+    fn write_stream(&mut self, tokens: impl IntoIterator<Item = TokenTree>) {
+        let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+        let mut i = 0;
+        while i < tokens.len() {
+            match &tokens[i] {
+                TokenTree::Group(group) => {
+                    let (open, close) = match group.delimiter() {
+                        Delimiter::Parenthesis => ("(", ")"),
+                        Delimiter::Brace => ("{", "}"),
+                        Delimiter::Bracket => ("[", "]"),
+                        Delimiter::None => ("", ""),
+                    };
+                    self.write(group.span_open(), open);
+                    self.write_stream(group.stream());
+                    self.write(group.span_close(), close);
+                }
+                // Rust turns `///` and `//!` comments into attributes.
+                TokenTree::Punct(punct) if punct.as_char() == '#' => {
+                    let inner = matches!(tokens.get(i + 1), Some(TokenTree::Punct(p)) if p.as_char() == '!');
+                    let attribute = i + 1 + usize::from(inner);
+                    match tokens.get(attribute).and_then(doc_comment) {
+                        Some(text) => {
+                            i = attribute;
+                            let prefix = if inner { "//!" } else { "///" };
+                            self.write(punct.span(), &format!("{prefix}{text}"));
+                            // Nothing may follow a comment on its line.
+                            self.out.push('\n');
+                            self.line += 1;
+                            self.column = 0;
+                        }
+                        None => self.write(punct.span(), "#"),
+                    }
+                }
+                TokenTree::Punct(punct) => self.write(punct.span(), &punct.as_char().to_string()),
+                TokenTree::Ident(ident) => self.write(ident.span(), &ident.to_string()),
+                TokenTree::Literal(literal) => self.write(literal.span(), &literal.to_string()),
+            }
+            i += 1;
+        }
+    }
+
+    fn write(&mut self, span: Span, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let (line, column) = (span.start().line(), span.start().column());
+        if !self.out.is_empty() {
+            if line > self.line {
+                for _ in self.line..line {
+                    self.out.push('\n');
+                }
+                self.out
+                    .extend(std::iter::repeat_n(' ', column.saturating_sub(1)));
+            } else if column > self.column {
+                self.out
+                    .extend(std::iter::repeat_n(' ', column - self.column));
+            } else if line < self.line || column < self.column {
+                // Spans from elsewhere, e.g. another macro.
+                self.out.push(' ');
+            }
+        }
+        self.out.push_str(text);
+        let end = span.end();
+        self.line = end.line();
+        self.column = end.column();
+    }
+}
+
+/// The text of a `[doc = "..."]` attribute.
+fn doc_comment(token: &TokenTree) -> Option<String> {
+    let TokenTree::Group(group) = token else {
+        return None;
+    };
+    let tokens: Vec<TokenTree> = group.stream().into_iter().collect();
+    match &tokens[..] {
+        [
+            TokenTree::Ident(ident),
+            TokenTree::Punct(eq),
+            TokenTree::Literal(text),
+        ] if ident.to_string() == "doc" && eq.as_char() == '=' => string_literal(text),
+        _ => None,
+    }
+}
+
+/// The value of a string literal, raw or with escapes.
+fn string_literal(literal: &Literal) -> Option<String> {
+    let text = literal.to_string();
+    if let Some(raw) = text.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let raw = &raw[hashes..];
+        return Some(
+            raw.strip_prefix('"')?
+                .strip_suffix(&format!("\"{}", "#".repeat(hashes)))?
+                .to_owned(),
+        );
+    }
+    let text = text.strip_prefix('"')?.strip_suffix('"')?;
+    let mut value = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => value.push('\n'),
+            'r' => value.push('\r'),
+            't' => value.push('\t'),
+            '0' => value.push('\0'),
+            'u' => {
+                let hex: String = chars.by_ref().skip(1).take_while(|&c| c != '}').collect();
+                value.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+            }
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                value.push(char::from(u8::from_str_radix(&hex, 16).ok()?));
+            }
+            // A line continuation skips the line break and indentation.
+            '\n' => {
+                let rest = chars.as_str().trim_start();
+                chars = rest.chars();
+            }
+            c => value.push(c),
+        }
+    }
+    Some(value)
+}
+
+/// FNV-1a, which unlike `DefaultHasher` is the same in every Rust release,
+/// so libraries keep their names.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
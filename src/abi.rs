@@ -0,0 +1,304 @@
+//! Checks that the Rust and Zig halves of a program agree on the C ABI of the
+//! types they share. Hand-maintained bindings drift: a reordered field or a
+//! `u32` that became a `usize` on one side compiles fine and corrupts memory
+//! at runtime. These checks are meant to run under `cargo test`:
+//!
+//! ```no_run
+//! #[repr(C)]
+//! pub struct Point {
+//!     pub x: f64,
+//!     pub y: f64,
+//! }
+//!
+//! #[test]
+//! fn layouts_match_zig() {
+//!     zig_rs::abi::LayoutCheck::new("src/bindings.zig")
+//!         .ty(zig_rs::layout!(Point { x, y }))
+//!         .assert();
+//! }
+//! ```
+//!
+//! The Rust side of the comparison comes from [`layout!`](crate::layout),
+//! the Zig side from a small generated program that imports the Zig source as
+//! a module and prints `@sizeOf`, `@alignOf` and `@offsetOf` for each type.
+
+use std::{
+    error::Error,
+    fmt::{self, Write as _},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::Toolchain;
+
+/// Size, alignment and field offsets of a type as seen by one compiler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeLayout {
+    /// Name of the type in the Zig source. Defaults to the Rust name.
+    pub name: String,
+    pub size: usize,
+    pub align: usize,
+    /// `(field name, offset)` in declaration order.
+    pub fields: Vec<(String, usize)>,
+}
+
+impl TypeLayout {
+    /// Uses a different name for the type on the Zig side.
+    pub fn zig_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+}
+
+/// Captures the Rust layout of a type, e.g. `layout!(Point { x, y })`. List
+/// every field whose offset should be compared; fields can be left out.
+#[macro_export]
+macro_rules! layout {
+    ($ty:ident { $($field:ident),* $(,)? }) => {
+        $crate::abi::TypeLayout {
+            name: ::std::string::String::from(::std::stringify!($ty)),
+            size: ::std::mem::size_of::<$ty>(),
+            align: ::std::mem::align_of::<$ty>(),
+            fields: ::std::vec![$(
+                (
+                    ::std::string::String::from(::std::stringify!($field)),
+                    ::std::mem::offset_of!($ty, $field),
+                )
+            ),*],
+        }
+    };
+    ($ty:ident) => {
+        $crate::layout!($ty {})
+    };
+}
+
+/// One compared quantity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    /// `Point size`, `Point align` or `Point.x offset`.
+    pub what: String,
+    pub rust: Option<usize>,
+    pub zig: Option<usize>,
+}
+
+impl Row {
+    pub fn matches(&self) -> bool {
+        self.rust == self.zig
+    }
+}
+
+/// The full comparison. Its `Display` impl renders a table with mismatches
+/// marked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LayoutReport {
+    pub rows: Vec<Row>,
+}
+
+impl LayoutReport {
+    pub fn is_ok(&self) -> bool {
+        self.rows.iter().all(Row::matches)
+    }
+}
+
+impl fmt::Display for LayoutReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .rows
+            .iter()
+            .map(|row| row.what.len())
+            .max()
+            .unwrap_or(0)
+            .max(4);
+        let cell = |value: Option<usize>| value.map_or("-".to_owned(), |v| v.to_string());
+        writeln!(f, "{:width$}  {:>6}  {:>6}", "", "rust", "zig")?;
+        for row in &self.rows {
+            write!(
+                f,
+                "{:width$}  {:>6}  {:>6}",
+                row.what,
+                cell(row.rust),
+                cell(row.zig)
+            )?;
+            if !row.matches() {
+                write!(f, "  <-- mismatch")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares Rust layouts against the declarations in a Zig source file.
+#[derive(Debug, Clone)]
+pub struct LayoutCheck {
+    zig_source: PathBuf,
+    types: Vec<TypeLayout>,
+    toolchain: Toolchain,
+}
+
+impl LayoutCheck {
+    /// `zig_source` is the Zig file declaring the shared types as `pub`.
+    pub fn new(zig_source: impl Into<PathBuf>) -> Self {
+        Self {
+            zig_source: zig_source.into(),
+            types: Vec::new(),
+            toolchain: Toolchain::bundled(),
+        }
+    }
+
+    pub fn ty(mut self, layout: TypeLayout) -> Self {
+        self.types.push(layout);
+        self
+    }
+
+    pub fn toolchain(mut self, toolchain: Toolchain) -> Self {
+        self.toolchain = toolchain;
+        self
+    }
+
+    /// Builds and runs the Zig side and compares it with the Rust layouts.
+    pub fn run(&self) -> Result<LayoutReport, Box<dyn Error>> {
+        let zig = self.zig_layouts()?;
+        let mut report = LayoutReport::default();
+        for rust in &self.types {
+            let zig = zig.iter().find(|t| t.name == rust.name);
+            report.rows.push(Row {
+                what: format!("{} size", rust.name),
+                rust: Some(rust.size),
+                zig: zig.map(|t| t.size),
+            });
+            report.rows.push(Row {
+                what: format!("{} align", rust.name),
+                rust: Some(rust.align),
+                zig: zig.map(|t| t.align),
+            });
+            for (field, offset) in &rust.fields {
+                let zig_offset =
+                    zig.and_then(|t| t.fields.iter().find(|(f, _)| f == field).map(|(_, o)| *o));
+                report.rows.push(Row {
+                    what: format!("{}.{field} offset", rust.name),
+                    rust: Some(*offset),
+                    zig: zig_offset,
+                });
+            }
+        }
+        Ok(report)
+    }
+
+    /// Like [`run()`](Self::run) but panics with the comparison table when
+    /// anything differs, for use directly in a `#[test]`.
+    pub fn assert(&self) {
+        match self.run() {
+            Ok(report) if report.is_ok() => {}
+            Ok(report) => panic!("Rust and Zig disagree on type layouts:\n\n{report}"),
+            Err(e) => panic!("could not check layouts against Zig: {e}"),
+        }
+    }
+
+    fn zig_layouts(&self) -> Result<Vec<TypeLayout>, Box<dyn Error>> {
+        let mut names = String::new();
+        for ty in &self.types {
+            write!(names, "\"{}\", ", ty.name)?;
+        }
+        let program = format!(
+            r#"const std = @import("std");
+const shared = @import("shared");
+
+pub fn main() !void {{
+    const out = std.io.getStdOut().writer();
+    inline for (.{{ {names}}}) |name| {{
+        const T = @field(shared, name);
+        try out.print("size {{s}} {{d}}\n", .{{ name, @sizeOf(T) }});
+        try out.print("align {{s}} {{d}}\n", .{{ name, @alignOf(T) }});
+        switch (@typeInfo(T)) {{
+            .@"struct" => |s| inline for (s.fields) |field| {{
+                try out.print("offset {{s}} {{s}} {{d}}\n", .{{ name, field.name, @offsetOf(T, field.name) }});
+            }},
+            else => {{}},
+        }}
+    }}
+}}
+"#
+        );
+        let output = run_zig_program(&self.toolchain, &program, &[("shared", &self.zig_source)])?;
+        Ok(parse_layouts(&output))
+    }
+}
+
+fn parse_layouts(output: &str) -> Vec<TypeLayout> {
+    let mut layouts: Vec<TypeLayout> = Vec::new();
+    for line in output.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let (name, rest) = match parts.as_slice() {
+            [_, name, rest @ ..] => (*name, rest),
+            _ => continue,
+        };
+        let index = match layouts.iter().position(|t| t.name == name) {
+            Some(index) => index,
+            None => {
+                layouts.push(TypeLayout {
+                    name: name.into(),
+                    size: 0,
+                    align: 0,
+                    fields: Vec::new(),
+                });
+                layouts.len() - 1
+            }
+        };
+        let layout = &mut layouts[index];
+        match (parts[0], rest) {
+            ("size", [n]) => layout.size = n.parse().unwrap_or(0),
+            ("align", [n]) => layout.align = n.parse().unwrap_or(0),
+            ("offset", [field, n]) => layout
+                .fields
+                .push(((*field).into(), n.parse().unwrap_or(0))),
+            _ => {}
+        }
+    }
+    layouts
+}
+
+/// Writes `program` to a scratch directory and runs it with `zig run`,
+/// exposing each `(name, path)` in `modules` to it as `@import("name")`.
+/// Returns its stdout.
+pub(crate) fn run_zig_program(
+    toolchain: &Toolchain,
+    program: &str,
+    modules: &[(&str, &Path)],
+) -> Result<String, Box<dyn Error>> {
+    let dir = scratch_dir("abi")?;
+    let main = dir.join("main.zig");
+    fs_err::write(&main, program)?;
+    let mut cmd = toolchain.command();
+    cmd.arg("run");
+    for (name, _) in modules {
+        cmd.arg("--dep").arg(name);
+    }
+    cmd.arg(format!("-Mmain={}", main.display()));
+    for (name, path) in modules {
+        cmd.arg(format!("-M{name}={}", path.canonicalize()?.display()));
+    }
+    let output = crate::process::output(&mut cmd)?;
+    let _ = fs_err::remove_dir_all(&dir);
+    if !output.status.success() {
+        return Err(format!(
+            "{cmd:?} failed: {}\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// A fresh directory under the system temp dir, unique per process and call.
+pub(crate) fn scratch_dir(purpose: &str) -> std::io::Result<PathBuf> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "zig-rs-{purpose}-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs_err::create_dir_all(&dir)?;
+    Ok(dir)
+}
@@ -0,0 +1,124 @@
+//! Extraction of the archive formats toolchains and sources are distributed
+//! in. Every archive we deal with has a single top-level directory
+//! (`zig-bootstrap-0.14.0/`, `zig-linux-x86_64-0.14.0/`, ...) which is
+//! stripped, so the contents land directly in the destination.
+
+use std::{
+    error::Error,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+};
+
+use zip::{ZipArchive, read::root_dir_common_filter};
+
+/// Archive formats [`extract()`] understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Zip,
+    TarGz,
+    TarXz,
+    TarZst,
+}
+
+impl Format {
+    /// Guesses the format from a file name or URL.
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name.ends_with(".zip") {
+            Some(Format::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Format::TarGz)
+        } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+            Some(Format::TarXz)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(Format::TarZst)
+        } else {
+            None
+        }
+    }
+
+    /// Recognizes the format of the archive at `path` by its first bytes,
+    /// for archives whose name doesn't say, e.g. from a mirror.
+    pub fn detect(path: &Path) -> io::Result<Option<Self>> {
+        let mut magic = [0; 6];
+        let mut file = fs_err::File::open(path)?;
+        let mut len = 0;
+        while len < magic.len() {
+            match file.read(&mut magic[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        Ok(match &magic[..len] {
+            [b'P', b'K', 3, 4, ..] => Some(Format::Zip),
+            [0x1f, 0x8b, ..] => Some(Format::TarGz),
+            [0xfd, b'7', b'z', b'X', b'Z', 0] => Some(Format::TarXz),
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(Format::TarZst),
+            _ => None,
+        })
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Zip => "zip",
+            Format::TarGz => "tar.gz",
+            Format::TarXz => "tar.xz",
+            Format::TarZst => "tar.zst",
+        }
+    }
+}
+
+/// Extracts `archive` into `dest`, stripping the single top-level directory.
+pub fn extract(archive: &Path, format: Format, dest: &Path) -> Result<(), Box<dyn Error>> {
+    match format {
+        Format::Zip => {
+            let file = fs_err::File::open(archive)?;
+            ZipArchive::new(file)?.extract_unwrapped_root_dir(dest, root_dir_common_filter)?;
+        }
+        Format::TarGz => {
+            let file = fs_err::File::open(archive)?;
+            extract_tar(flate2::read::GzDecoder::new(file), dest)?;
+        }
+        Format::TarXz => {
+            let file = fs_err::File::open(archive)?;
+            extract_tar(xz2::read::XzDecoder::new(file), dest)?;
+        }
+        Format::TarZst => {
+            let file = fs_err::File::open(archive)?;
+            extract_tar(zstd::Decoder::new(file)?, dest)?;
+        }
+    }
+    Ok(())
+}
+
+fn extract_tar(reader: impl Read, dest: &Path) -> io::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let Some(relative) = strip_root(&entry.path()?) else {
+            continue;
+        };
+        let path = dest.join(relative);
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        entry.unpack(&path)?;
+    }
+    Ok(())
+}
+
+/// Drops the first component of an archive path. Returns `None` for the root
+/// directory itself and for paths that would escape the destination.
+fn strip_root(path: &Path) -> Option<PathBuf> {
+    let mut components = path.components();
+    components.next()?;
+    let rest: PathBuf = components.collect();
+    if rest.as_os_str().is_empty()
+        || rest
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    Some(rest)
+}
@@ -0,0 +1,376 @@
+//! `cargo zig` subcommands for managing what this crate leaves on disk.
+//!
+//! ```sh
+//! cargo zig clean [--toolchains] [--builds] [--zig-cache] [--incremental]
+//!                 [--objects] [--downloads] [--version <v>]...
+//!                 [--older-than <age>] [--max-size <size>] [--gc] [--dry-run]
+//! cargo zig prefetch [--target <triple>]... [--source] [--zig-version <v>]
+//!                    [--bootstrap-ref <ref>] [--fetch <dir>]... [--build]
+//! cargo zig matrix --out <dir> --host <triple>... [--source]
+//!                  [--zig-version <v>]
+//! cargo zig report [--out <dir>] [--zig-version <v>] [--no-minimize]
+//!                  -- <zig args>...
+//! ```
+//!
+//! `prefetch` is meant for a CI job that warms the shared cache
+//! (`ZIG_RS_CACHE_DIR`) the build jobs restore, so they download nothing.
+//! Dependencies of `build.zig.zon` files land in Zig's global cache, which
+//! build scripts only use with `ZIG_RS_SHARED_ZIG_CACHE` set.
+//!
+//! `matrix` packs toolchains for a list of hosts into a directory for
+//! internal distribution; see [`zig_rs::matrix`].
+//!
+//! `report` reruns a `zig` command that crashes and writes a bug report
+//! bundle for Zig's issue tracker; see [`zig_rs::crash`].
+
+use std::{
+    env,
+    error::Error,
+    ffi::OsString,
+    path::PathBuf,
+    process::{Command, ExitCode},
+};
+
+use zig_rs::{
+    PINNED_ZIG_VERSION, Toolchain, bootstrap_source,
+    cache::{self, Clean, Kind},
+    crash,
+    matrix::{self, Matrix},
+    release,
+};
+
+const USAGE: &str = "\
+usage: cargo zig clean [options]
+       cargo zig prefetch [options]
+       cargo zig matrix --out <dir> [options]
+       cargo zig report [options] -- <zig args>...
+
+clean options:
+    --toolchains          clean the shared toolchain cache
+    --builds              clean the build output cache
+    --zig-cache           clean Zig's global cache
+    --incremental         clean incremental compilation state
+    --objects             clean the shared object cache
+    --downloads           clean prefetched source archives
+    --version <v>         only remove toolchains, incremental state and
+                          objects for Zig version <v>
+    --older-than <age>    only remove entries unused for <age> (e.g. 30d, 12h)
+    --max-size <size>     trim the selected caches to <size> (e.g. 10G)
+    --gc                  trim the selected caches to $ZIG_RS_CACHE_MAX_SIZE
+                          (default 20G), least recently used first
+    --dry-run             print what would be removed without removing it
+
+With no cache selected, all caches are cleaned.
+
+prefetch options:
+    --target <triple>     fetch the toolchain for rustc target <triple>: the
+                          official release, or the zig-bootstrap sources for
+                          targets without one (default: the host)
+    --source              fetch the zig-bootstrap sources even for targets
+                          with a release
+    --zig-version <v>     fetch Zig <v> (default: the pinned version, or
+                          $ZIG_RS_ZIG_VERSION)
+    --bootstrap-ref <ref> fetch the sources of this zig-bootstrap tag, branch
+                          or commit (default: $ZIG_RS_BOOTSTRAP_REF)
+    --fetch <dir>         fetch the dependencies of <dir>/build.zig.zon into
+                          Zig's global cache
+    --build               also build the toolchain for every target into the
+                          shared cache, as the build script would; needs the
+                          Rust targets installed
+
+matrix options:
+    --out <dir>           write the toolchain archives and their index here
+    --host <triple>       pack a toolchain that runs on rustc target <triple>
+    --source              build every toolchain with zig-bootstrap, even for
+                          hosts with an official release
+    --zig-version <v>     pack Zig <v> (default: the pinned version)
+
+report options:
+    --out <dir>           write the bug report here (default: zig-crash)
+    --zig-version <v>     run Zig <v> (default: the pinned version, or
+                          $ZIG_RS_ZIG_VERSION)
+    --no-minimize         keep every flag instead of dropping the ones the
+                          crash doesn't need";
+
+fn main() -> ExitCode {
+    // Cargo runs `cargo-zig zig <args>` for `cargo zig <args>`.
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "zig") {
+        args.remove(0);
+    }
+    let result = match args.first().map(String::as_str) {
+        Some("clean") => clean(&args[1..]),
+        Some("prefetch") => prefetch(&args[1..]),
+        Some("matrix") => matrix(&args[1..]),
+        Some("report") => report(&args[1..]),
+        Some("-h" | "--help") | None => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        Some(other) => Err(format!("unknown subcommand: {other}\n\n{USAGE}").into()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn clean(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut clean = Clean::new();
+    let mut dry_run = false;
+    let mut gc = false;
+    let mut max_size = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} expects a value"));
+        clean = match arg.as_str() {
+            "--toolchains" => clean.kind(Kind::Toolchains),
+            "--builds" => clean.kind(Kind::Builds),
+            "--zig-cache" => clean.kind(Kind::ZigGlobal),
+            "--incremental" => clean.kind(Kind::Incremental),
+            "--objects" => clean.kind(Kind::Objects),
+            "--downloads" => clean.kind(Kind::Downloads),
+            "--version" => clean.version(value()?),
+            "--older-than" => {
+                let value = value()?;
+                clean.older_than(
+                    cache::parse_age(value).ok_or_else(|| format!("invalid age: {value}"))?,
+                )
+            }
+            "--max-size" => {
+                max_size = true;
+                let value = value()?;
+                clean.max_size(
+                    cache::parse_size(value).ok_or_else(|| format!("invalid size: {value}"))?,
+                )
+            }
+            "--gc" => {
+                gc = true;
+                clean
+            }
+            "--dry-run" => {
+                dry_run = true;
+                clean.dry_run(true)
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            other => return Err(format!("unknown option: {other}\n\n{USAGE}").into()),
+        };
+    }
+
+    if gc && !max_size {
+        clean = clean.max_size(cache::max_size()?);
+    }
+    let report = clean.run()?;
+    let verb = if dry_run { "would remove" } else { "removed" };
+    for entry in &report.removed {
+        println!(
+            "{verb} {} ({})",
+            entry.path.display(),
+            human_size(entry.size)
+        );
+    }
+    println!(
+        "{verb} {} entries, {} freed, {} remaining",
+        report.removed.len(),
+        human_size(report.freed),
+        human_size(report.remaining)
+    );
+    Ok(())
+}
+
+fn prefetch(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut targets = Vec::new();
+    let mut source = false;
+    let mut version = env::var("ZIG_RS_ZIG_VERSION").unwrap_or_else(|_| PINNED_ZIG_VERSION.into());
+    let mut bootstrap_ref = env::var("ZIG_RS_BOOTSTRAP_REF").ok();
+    let mut fetch = Vec::new();
+    let mut build = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} expects a value"));
+        match arg.as_str() {
+            "--target" => targets.push(value()?.clone()),
+            "--source" => source = true,
+            "--zig-version" => version = value()?.clone(),
+            "--bootstrap-ref" => bootstrap_ref = Some(value()?.clone()),
+            "--fetch" => fetch.push(PathBuf::from(value()?)),
+            "--build" => build = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            other => return Err(format!("unknown option: {other}\n\n{USAGE}").into()),
+        }
+    }
+
+    // A zig-bootstrap ref only makes sense as sources.
+    let source = source || bootstrap_ref.is_some();
+    for target in targets_or_host(&targets) {
+        match release::target_platform(&target).filter(|_| !source) {
+            Some((arch, os)) => {
+                let toolchain = Toolchain::release(&version, arch, os)?;
+                println!("{target}: zig {version} in {}", toolchain.root().display());
+            }
+            None => {
+                let archive = bootstrap_source::prefetch(&version, bootstrap_ref.as_deref())?;
+                println!("{target}: zig-bootstrap sources in {}", archive.display());
+            }
+        }
+    }
+    if !fetch.is_empty() {
+        let toolchain = Toolchain::version(&version)?;
+        for dir in &fetch {
+            let mut cmd = toolchain.command();
+            cmd.arg("build").arg("--fetch").current_dir(dir);
+            run(&mut cmd)?;
+            println!("{}: dependencies fetched", dir.display());
+        }
+    }
+    if build {
+        for target in targets_or_host(&targets) {
+            build_toolchain(&target, &version, bootstrap_ref.as_deref(), source)?;
+        }
+    }
+    Ok(())
+}
+
+/// `targets`, or the host's target when there are none.
+fn targets_or_host(targets: &[String]) -> Vec<String> {
+    if targets.is_empty() {
+        // cargo-zig is built for the host.
+        vec![zig_rs::BUILD_INFO.target.into()]
+    } else {
+        targets.to_vec()
+    }
+}
+
+/// Runs this crate's build script for `target`, installing from the cache
+/// what the steps before fetched or else building from source; with
+/// `source`, always the source build. The build script keeps source builds
+/// in the cache by Zig version and target, where the builds of consuming
+/// crates find them. A `bootstrap_ref` build isn't cached and only checks
+/// that the ref builds.
+fn build_toolchain(
+    target: &str,
+    version: &str,
+    bootstrap_ref: Option<&str>,
+    source: bool,
+) -> Result<(), Box<dyn Error>> {
+    let order = if source { "source" } else { "cache,source" };
+    let mut vars = vec![
+        ("ZIG_RS_ZIG_VERSION", version),
+        ("ZIG_RS_TOOLCHAIN_ORDER", order),
+    ];
+    if let Some(bootstrap_ref) = bootstrap_ref {
+        vars.push(("ZIG_RS_BOOTSTRAP_REF", bootstrap_ref));
+    }
+    matrix::run_build_script(target, &vars)?;
+    println!("{target}: toolchain built");
+    Ok(())
+}
+
+fn matrix(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut matrix = Matrix::new();
+    let mut out = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} expects a value"));
+        matrix = match arg.as_str() {
+            "--host" => matrix.host(value()?),
+            "--source" => matrix.source(true),
+            "--zig-version" => matrix.zig_version(value()?),
+            "--out" => {
+                out = Some(PathBuf::from(value()?));
+                matrix
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            other => return Err(format!("unknown option: {other}\n\n{USAGE}").into()),
+        };
+    }
+    let out = out.ok_or_else(|| format!("--out is required\n\n{USAGE}"))?;
+    let index = matrix.build(&out)?;
+    for host in &index.hosts {
+        println!(
+            "{}: {} ({})",
+            host.target,
+            out.join(&host.archive).display(),
+            host.bootstrap
+        );
+    }
+    Ok(())
+}
+
+fn report(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut out = PathBuf::from("zig-crash");
+    let mut version = env::var("ZIG_RS_ZIG_VERSION").unwrap_or_else(|_| PINNED_ZIG_VERSION.into());
+    let mut minimize = true;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} expects a value"));
+        match arg.as_str() {
+            "--out" => out = PathBuf::from(value()?),
+            "--zig-version" => version = value()?.clone(),
+            "--no-minimize" => minimize = false,
+            "--" => break,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            other => return Err(format!("unknown option: {other}\n\n{USAGE}").into()),
+        }
+    }
+    let zig_args: Vec<OsString> = args.map(OsString::from).collect();
+    if zig_args.is_empty() {
+        return Err(format!("no zig command to report\n\n{USAGE}").into());
+    }
+    let toolchain = Toolchain::version(&version)?;
+    let Some(report) = crash::report(&toolchain, &zig_args, &env::current_dir()?, &out, minimize)?
+    else {
+        return Err("zig didn't crash".into());
+    };
+    println!("{}", report.signature);
+    if report.minimized != report.args {
+        println!(
+            "reduced {} arguments to {}",
+            report.args.len(),
+            report.minimized.len()
+        );
+    }
+    println!(
+        "report in {}; check it reproduces with reproduce.sh before filing it",
+        report.dir.join("ISSUE.md").display()
+    );
+    Ok(())
+}
+
+fn run(cmd: &mut Command) -> Result<(), Box<dyn Error>> {
+    let status = cmd.status()?;
+    if !status.success() {
+        return Err(format!("{:?} failed: {status}", cmd).into());
+    }
+    Ok(())
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
@@ -0,0 +1,35 @@
+//! A caching `zig cc`, for the shims to run when `ZIG_RS_CC_CACHE` names
+//! this program; see [`zig_rs::cc_cache`].
+//!
+//! ```sh
+//! zig-cc-cache <toolchain root> <zig version> <cc|c++> <args>...
+//! ```
+
+use std::{env, ffi::OsString, process::ExitCode};
+
+use zig_rs::{Toolchain, cc_cache};
+
+const USAGE: &str = "usage: zig-cc-cache <toolchain root> <zig version> <cc|c++> <args>...";
+
+fn main() -> ExitCode {
+    let mut args = env::args_os().skip(1);
+    let (Some(root), Some(version), Some(compiler)) = (args.next(), args.next(), args.next())
+    else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    let (Some(version), Some(compiler)) = (version.to_str(), compiler.to_str()) else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    let toolchain = Toolchain::at(version, root);
+    let args: Vec<OsString> = args.collect();
+    match cc_cache::run(&toolchain, compiler, &args) {
+        // Killed by a signal: fail all the same.
+        Ok(status) => ExitCode::from(status.code().map_or(1, |code| code as u8)),
+        Err(e) => {
+            eprintln!("zig-cc-cache: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
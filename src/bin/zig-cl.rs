@@ -0,0 +1,39 @@
+//! A `zig cc` that also takes `cl.exe` command lines, for the shims of
+//! `*-msvc` targets to run when `ZIG_RS_CL` names this program; see
+//! [`zig_rs::cl`].
+//!
+//! ```sh
+//! zig-cl <toolchain root> <zig version> <cc|c++> <zig target> <args>...
+//! ```
+
+use std::{env, ffi::OsString, process::ExitCode};
+
+use zig_rs::{Toolchain, cl};
+
+const USAGE: &str = "usage: zig-cl <toolchain root> <zig version> <cc|c++> <zig target> <args>...";
+
+fn main() -> ExitCode {
+    let mut args = env::args_os().skip(1);
+    let (Some(root), Some(version), Some(compiler), Some(zig_target)) =
+        (args.next(), args.next(), args.next(), args.next())
+    else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    let (Some(version), Some(compiler), Some(zig_target)) =
+        (version.to_str(), compiler.to_str(), zig_target.to_str())
+    else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    let toolchain = Toolchain::at(version, root);
+    let args: Vec<OsString> = args.collect();
+    match cl::run(&toolchain, compiler, zig_target, &args) {
+        // Killed by a signal: fail all the same.
+        Ok(status) => ExitCode::from(status.code().map_or(1, |code| code as u8)),
+        Err(e) => {
+            eprintln!("zig-cl: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,21 @@
+//! `zig objcopy` of the bundled toolchain, as a program of its own for
+//! Makefiles and tools that run `objcopy`; see [`zig_rs::tools`].
+//!
+//! ```sh
+//! zig-objcopy -O binary firmware.elf firmware.bin
+//! ```
+
+use std::{env, process::ExitCode};
+
+use zig_rs::tools;
+
+fn main() -> ExitCode {
+    match tools::objcopy().args(env::args_os().skip(1)).status() {
+        // Killed by a signal: fail all the same.
+        Ok(status) => ExitCode::from(status.code().map_or(1, |code| code as u8)),
+        Err(e) => {
+            eprintln!("zig-objcopy: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
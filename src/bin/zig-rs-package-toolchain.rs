@@ -0,0 +1,96 @@
+//! Packs a toolchain as an image layer, for baking it into builder images so
+//! every `cargo build` inside them finds it in the cache; see
+//! [`zig_rs::pack::pack_layer()`].
+//!
+//! ```sh
+//! zig-rs-package-toolchain [--host <triple>] [--zig-version <v>] [--source]
+//!                          [--prefix <dir>] [--out <file>]
+//! ```
+//!
+//! It prints the lines of a `Dockerfile` adding the layer:
+//!
+//! ```dockerfile
+//! ADD zig-rs-toolchain-x86_64-unknown-linux-gnu.tar /
+//! ENV ZIG_RS_CACHE_DIR=/opt/zig-rs
+//! ```
+
+use std::{
+    env,
+    error::Error,
+    path::{Component, Path, PathBuf},
+    process::ExitCode,
+};
+
+use zig_rs::{PINNED_ZIG_VERSION, matrix, pack};
+
+const USAGE: &str = "\
+usage: zig-rs-package-toolchain [options]
+
+options:
+    --host <triple>       pack a toolchain that runs on rustc target <triple>
+                          (default: the host)
+    --zig-version <v>     pack Zig <v> (default: the pinned version, or
+                          $ZIG_RS_ZIG_VERSION)
+    --source              build the toolchain with zig-bootstrap, even for
+                          hosts with an official release
+    --prefix <dir>        the ZIG_RS_CACHE_DIR of the image (default:
+                          /opt/zig-rs)
+    --out <file>          write the layer here (default:
+                          zig-rs-toolchain-<triple>.tar)";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    // The binary is built for the host.
+    let mut host = zig_rs::BUILD_INFO.target.to_owned();
+    let mut version = env::var("ZIG_RS_ZIG_VERSION").unwrap_or_else(|_| PINNED_ZIG_VERSION.into());
+    let mut source = false;
+    let mut prefix = PathBuf::from("/opt/zig-rs");
+    let mut out = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} expects a value"));
+        match arg.as_str() {
+            "--host" => host = value()?.clone(),
+            "--zig-version" => version = value()?.clone(),
+            "--source" => source = true,
+            "--prefix" => prefix = PathBuf::from(value()?),
+            "--out" => out = Some(PathBuf::from(value()?)),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            other => return Err(format!("unknown option: {other}\n\n{USAGE}").into()),
+        }
+    }
+    if !prefix.is_absolute() {
+        return Err(format!("--prefix must be absolute, not {}", prefix.display()).into());
+    }
+    let out = out.unwrap_or_else(|| PathBuf::from(format!("zig-rs-toolchain-{host}.tar")));
+
+    let (root, fingerprint) = matrix::provision(&host, &version, source)?;
+    // Layer paths are relative to the image's root.
+    let relative: PathBuf = prefix
+        .components()
+        .filter(|part| matches!(part, Component::Normal(_)))
+        .collect();
+    let dir = pack::pack_layer(&root, fingerprint, &relative, &out)?;
+    eprintln!(
+        "{}: toolchain in /{}",
+        out.display(),
+        dir.display().to_string().replace('\\', "/")
+    );
+    let name = out.file_name().map_or(out.as_path(), Path::new);
+    println!("ADD {} /", name.display());
+    println!("ENV ZIG_RS_CACHE_DIR={}", prefix.display());
+    Ok(())
+}
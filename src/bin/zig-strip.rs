@@ -0,0 +1,22 @@
+//! A `strip` for every target Zig supports, running `zig objcopy` of the
+//! bundled toolchain; see [`zig_rs::tools::strip()`].
+//!
+//! ```sh
+//! zig-strip [-s | -g | --only-keep-debug] [-o <output>] <file>...
+//! ```
+
+use std::{env, ffi::OsString, process::ExitCode};
+
+use zig_rs::{Toolchain, tools};
+
+fn main() -> ExitCode {
+    let args: Vec<OsString> = env::args_os().skip(1).collect();
+    match tools::strip(&Toolchain::bundled(), &args) {
+        // Killed by a signal: fail all the same.
+        Ok(status) => ExitCode::from(status.code().map_or(1, |code| code as u8)),
+        Err(e) => {
+            eprintln!("zig-strip: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
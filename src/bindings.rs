@@ -0,0 +1,430 @@
+//! Rust and C declarations for the `export fn`s of Zig source, so the code
+//! calling into Zig doesn't drift out of sync with it. The
+//! [`Build::emit_bindings()`](crate::Build::emit_bindings) of a library with
+//! `.zig` files writes both:
+//!
+//! ```no_run
+//! // build.rs
+//! zig_rs::Build::new()
+//!     .file("src/math.zig")
+//!     .emit_bindings(true)
+//!     .compile("math");
+//!
+//! // src/lib.rs
+//! // include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+//! ```
+//!
+//! The self-hosted Zig compiler doesn't implement `-femit-h`, so the
+//! declarations are read from the source instead. Integers, floats, `bool`,
+//! the `c_*` types and pointers to them cross as their equivalents; pointers
+//! to anything else become `void` pointers, and other types are an error.
+//! Only the exports of the files themselves are declared, not of the files
+//! they import.
+//!
+//! The `include_zig!` and `zig!` macros share this module, so it only needs
+//! `std`.
+
+use std::fmt::Write as _;
+
+/// An `export fn` of the C calling convention, with its Zig types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    pub name: String,
+    /// Names and types. Unnamed (`_`) parameters get their position as the
+    /// name, `_0`, `_1`, ...
+    pub params: Vec<(String, String)>,
+    /// `void` when it returns nothing.
+    pub ret: String,
+}
+
+impl Export {
+    /// The declaration for an `extern "C"` block, e.g.
+    /// `pub fn r#add(r#a: i32, r#b: i32) -> i32;`.
+    pub fn rust(&self) -> Result<String, String> {
+        let mut params = Vec::new();
+        for (name, ty) in &self.params {
+            let ty = rust_type(ty).ok_or_else(|| self.unsupported("Rust", ty))?;
+            params.push(format!("{}: {ty}", rust_ident(name)));
+        }
+        let name = rust_ident(&self.name);
+        let mut declaration = String::new();
+        if !name.starts_with("r#") {
+            let _ = write!(declaration, "#[link_name = {:?}] ", self.name);
+        }
+        let _ = write!(declaration, "pub fn {name}({})", params.join(", "));
+        if self.ret != "void" {
+            let ret = rust_type(&self.ret).ok_or_else(|| self.unsupported("Rust", &self.ret))?;
+            let _ = write!(declaration, " -> {ret}");
+        }
+        declaration.push(';');
+        Ok(declaration)
+    }
+
+    /// The C prototype, e.g. `int32_t add(int32_t a, int32_t b);`.
+    pub fn c(&self) -> Result<String, String> {
+        let mut params = Vec::new();
+        for (name, ty) in &self.params {
+            let ty = c_type(ty).ok_or_else(|| self.unsupported("C", ty))?;
+            params.push(c_declarator(&ty, &c_ident(name)));
+        }
+        if params.is_empty() {
+            params.push("void".into());
+        }
+        let ret = match self.ret.as_str() {
+            "noreturn" => "__attribute__((noreturn)) void".to_owned(),
+            ret => c_type(ret).ok_or_else(|| self.unsupported("C", ret))?,
+        };
+        Ok(format!(
+            "{}({});",
+            c_declarator(&ret, &self.name),
+            params.join(", ")
+        ))
+    }
+
+    fn unsupported(&self, language: &str, ty: &str) -> String {
+        format!(
+            "export fn {}: no {language} type for `{ty}`; pass it by pointer or use a C-compatible type",
+            self.name
+        )
+    }
+}
+
+/// The `export fn`s in `source`.
+pub fn parse(source: &str) -> Result<Vec<Export>, String> {
+    let source = strip_comments(source);
+    let mut exports = Vec::new();
+    let mut rest = source.as_str();
+    while let Some(start) = find_word(rest, "export") {
+        rest = rest[start + "export".len()..].trim_start();
+        let Some(after_fn) = rest
+            .strip_prefix("fn")
+            .filter(|r| r.starts_with(char::is_whitespace))
+        else {
+            // `export var` or `export const`.
+            continue;
+        };
+        let after_fn = after_fn.trim_start();
+        let name_len = after_fn
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(after_fn.len());
+        let name = &after_fn[..name_len];
+        let signature = &after_fn[name_len..];
+        let open = signature
+            .find('(')
+            .ok_or_else(|| format!("export fn {name}: expected parameters"))?;
+        let close = open
+            + matching_paren(&signature[open..])
+                .ok_or_else(|| format!("export fn {name}: unclosed parameters"))?;
+        let mut params = Vec::new();
+        for param in split_top_level(&signature[open + 1..close]) {
+            let param = param.trim();
+            if param.is_empty() {
+                continue;
+            }
+            let param = param.strip_prefix("noalias ").unwrap_or(param).trim();
+            let (param_name, ty) = param
+                .split_once(':')
+                .ok_or_else(|| format!("export fn {name}: can't read parameter {param:?}"))?;
+            let param_name = match param_name.trim() {
+                "_" => format!("_{}", params.len()),
+                param_name => param_name.to_owned(),
+            };
+            params.push((param_name, ty.trim().to_owned()));
+        }
+        let mut ret = signature[close + 1..]
+            .split('{')
+            .next()
+            .unwrap_or_default()
+            .trim();
+        // The calling convention is C either way.
+        if let Some(callconv) = ret.strip_prefix("callconv") {
+            let end = matching_paren(callconv.trim_start()).unwrap_or(0);
+            ret = callconv.trim_start()[end + 1..].trim();
+        }
+        exports.push(Export {
+            name: name.to_owned(),
+            params,
+            ret: ret.to_owned(),
+        });
+        rest = &signature[close..];
+    }
+    Ok(exports)
+}
+
+/// An `unsafe extern "C"` block declaring `exports`, for `include!`.
+pub fn rust_bindings(exports: &[Export]) -> Result<String, String> {
+    let mut out = String::from("unsafe extern \"C\" {\n");
+    for export in exports {
+        let _ = writeln!(out, "    {}", export.rust()?);
+    }
+    out.push_str("}\n");
+    Ok(out)
+}
+
+/// A C header declaring `exports`, usable from C++ too.
+pub fn c_header(exports: &[Export]) -> Result<String, String> {
+    let mut out = String::from(
+        "#pragma once\n\n\
+         #include <stdbool.h>\n\
+         #include <stddef.h>\n\
+         #include <stdint.h>\n\n\
+         #ifdef __cplusplus\n\
+         extern \"C\" {\n\
+         #endif\n\n",
+    );
+    for export in exports {
+        let _ = writeln!(out, "{}", export.c()?);
+    }
+    out.push_str("\n#ifdef __cplusplus\n}\n#endif\n");
+    Ok(out)
+}
+
+/// `name` as a Rust identifier: raw, since Zig's keywords aren't Rust's, or
+/// renamed where Rust has no raw form.
+fn rust_ident(name: &str) -> String {
+    match name {
+        "self" | "Self" | "super" | "crate" => format!("{name}_"),
+        name if name.starts_with('_') => name.to_owned(),
+        name => format!("r#{name}"),
+    }
+}
+
+/// `name` as a C parameter name, renamed where it's a keyword.
+fn c_ident(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "auto", "bool", "case", "char", "class", "default", "delete", "do", "double", "float",
+        "goto", "int", "long", "new", "register", "restrict", "short", "signed", "sizeof",
+        "static", "template", "this", "typedef", "unsigned", "void",
+    ];
+    if KEYWORDS.contains(&name) {
+        format!("{name}_")
+    } else {
+        name.to_owned()
+    }
+}
+
+/// `ty name`, or `ty *name` for pointers.
+fn c_declarator(ty: &str, name: &str) -> String {
+    if ty.ends_with('*') {
+        format!("{ty}{name}")
+    } else {
+        format!("{ty} {name}")
+    }
+}
+
+/// The pointee of a Zig pointer type, and whether it's `const`. Optional
+/// pointers count, as null is just another value of a C pointer.
+fn pointer(zig: &str) -> Option<(bool, &str)> {
+    let zig = zig.strip_prefix('?').unwrap_or(zig);
+    let pointee = ["[*c]", "[*:0]", "[*]", "*"]
+        .iter()
+        .find_map(|prefix| zig.strip_prefix(prefix))?
+        .trim_start();
+    let (constant, pointee) = match pointee.strip_prefix("const ") {
+        Some(pointee) => (true, pointee),
+        None => (false, pointee),
+    };
+    let pointee = pointee.trim();
+    Some((
+        constant,
+        pointee.strip_prefix("volatile ").unwrap_or(pointee).trim(),
+    ))
+}
+
+/// The Rust type for a Zig type that can cross the C ABI. Pointers to types
+/// Rust doesn't know become `c_void` pointers.
+fn rust_type(zig: &str) -> Option<String> {
+    let zig = zig.trim();
+    if let Some((constant, pointee)) = pointer(zig) {
+        let pointee = match pointee {
+            "anyopaque" => "::core::ffi::c_void".to_owned(),
+            pointee => rust_type(pointee)
+                .filter(|ty| ty != "!")
+                .unwrap_or_else(|| "::core::ffi::c_void".to_owned()),
+        };
+        let mutability = if constant { "const" } else { "mut" };
+        return Some(format!("*{mutability} {pointee}"));
+    }
+    if zig.starts_with('?') {
+        return None;
+    }
+    Some(match zig {
+        "bool" | "f32" | "f64" | "isize" | "usize" | "i8" | "i16" | "i32" | "i64" | "i128"
+        | "u8" | "u16" | "u32" | "u64" | "u128" => zig.to_owned(),
+        "noreturn" => "!".to_owned(),
+        "c_char" | "c_short" | "c_ushort" | "c_int" | "c_uint" | "c_long" | "c_ulong"
+        | "c_longlong" | "c_ulonglong" => format!("::core::ffi::{zig}"),
+        _ => return None,
+    })
+}
+
+/// The C type for a Zig type that can cross the C ABI, spelled with the
+/// `<stdint.h>` names. Pointers to types C doesn't know become `void`
+/// pointers.
+fn c_type(zig: &str) -> Option<String> {
+    let zig = zig.trim();
+    if let Some((constant, pointee)) = pointer(zig) {
+        let pointee = match pointee {
+            "anyopaque" => "void".to_owned(),
+            pointee => c_type(pointee).unwrap_or_else(|| "void".to_owned()),
+        };
+        // `const` goes after the pointee when that's a pointer itself.
+        return Some(match (constant, pointee.ends_with('*')) {
+            (true, true) => format!("{pointee}const *"),
+            (true, false) => format!("const {pointee} *"),
+            (false, true) => format!("{pointee}*"),
+            (false, false) => format!("{pointee} *"),
+        });
+    }
+    Some(
+        match zig {
+            "void" => "void",
+            "bool" => "bool",
+            "f32" => "float",
+            "f64" => "double",
+            "isize" => "intptr_t",
+            "usize" => "size_t",
+            "i8" => "int8_t",
+            "i16" => "int16_t",
+            "i32" => "int32_t",
+            "i64" => "int64_t",
+            "i128" => "__int128",
+            "u8" => "uint8_t",
+            "u16" => "uint16_t",
+            "u32" => "uint32_t",
+            "u64" => "uint64_t",
+            "u128" => "unsigned __int128",
+            "c_char" => "char",
+            "c_short" => "short",
+            "c_ushort" => "unsigned short",
+            "c_int" => "int",
+            "c_uint" => "unsigned int",
+            "c_long" => "long",
+            "c_ulong" => "unsigned long",
+            "c_longlong" => "long long",
+            "c_ulonglong" => "unsigned long long",
+            "c_longdouble" => "long double",
+            _ => return None,
+        }
+        .to_owned(),
+    )
+}
+
+/// `source` with `//` comments removed, leaving string and character
+/// literals alone.
+fn strip_comments(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    for line in source.lines() {
+        // Multiline string literals, `\\...`, run to the end of the line.
+        if line.trim_start().starts_with("\\\\") {
+            out.push('\n');
+            continue;
+        }
+        let mut quote = None;
+        let mut escaped = false;
+        let mut end = line.len();
+        for (i, c) in line.char_indices() {
+            match quote {
+                Some(q) => {
+                    if escaped {
+                        escaped = false;
+                    } else if c == '\\' {
+                        escaped = true;
+                    } else if c == q {
+                        quote = None;
+                    }
+                }
+                None if c == '"' || c == '\'' => quote = Some(c),
+                None if line[i..].starts_with("//") => {
+                    end = i;
+                    break;
+                }
+                None => {}
+            }
+        }
+        // Strings can't hold `export fn` for the search either.
+        out.push_str(&blank_strings(&line[..end]));
+        out.push('\n');
+    }
+    out
+}
+
+/// `code` with the contents of string literals replaced by spaces.
+fn blank_strings(code: &str) -> String {
+    let mut out = String::with_capacity(code.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in code.chars() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+                out.push(c);
+                continue;
+            }
+            out.push(' ');
+        } else {
+            in_string = c == '"';
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Where `word` first appears in `text` as a whole word.
+fn find_word(text: &str, word: &str) -> Option<usize> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_' || c == '@';
+    let mut from = 0;
+    while let Some(i) = text[from..].find(word) {
+        let start = from + i;
+        let end = start + word.len();
+        let before = text[..start].chars().next_back();
+        let after = text[end..].chars().next();
+        if !before.is_some_and(is_ident) && !after.is_some_and(is_ident) {
+            return Some(start);
+        }
+        from = end;
+    }
+    None
+}
+
+/// The index of the parenthesis closing the one `text` starts with.
+fn matching_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `text` split at the commas outside parentheses and brackets.
+fn split_top_level(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
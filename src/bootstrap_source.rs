@@ -0,0 +1,80 @@
+//! Where the zig-bootstrap sources the build script builds from are
+//! downloaded from, and fetching them into
+//! [`cache::downloads_dir()`](crate::cache::downloads_dir) ahead of the
+//! build, where the build script finds them instead of downloading. That is
+//! what `cargo zig prefetch --source` does in CI cache-warming jobs.
+
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use crate::{archive::Format, cache, download, integrity, log::Tagger};
+
+/// The GitHub archive of the zig-bootstrap sources of Zig `version`, or of
+/// the tag, branch or commit `git_ref` instead.
+pub fn url(version: &str, git_ref: Option<&str>) -> String {
+    match git_ref {
+        // GitHub resolves branches and commits here too.
+        Some(git_ref) => format!("https://github.com/ziglang/zig-bootstrap/archive/{git_ref}.zip"),
+        None => format!("https://github.com/ziglang/zig-bootstrap/archive/refs/tags/{version}.zip"),
+    }
+}
+
+/// What the archive of `tag`, a version or ref, is called without its
+/// extension, e.g. `zig-bootstrap-0.14.0`: the name pinned digests and the
+/// downloads cache know it by.
+pub fn archive_stem(tag: &str) -> String {
+    // Branch names may have slashes.
+    format!("zig-bootstrap-{}", tag.replace(['/', '\\'], "-"))
+}
+
+/// The archive of `tag` in the downloads cache, in whichever format it was
+/// served in.
+pub fn cached(tag: &str) -> Option<PathBuf> {
+    let dir = cache::downloads_dir()?;
+    let stem = archive_stem(tag);
+    [Format::Zip, Format::TarGz, Format::TarXz, Format::TarZst]
+        .into_iter()
+        .map(|format| dir.join(format!("{stem}.{}", format.extension())))
+        .find(|path| path.is_file())
+}
+
+/// Downloads the sources of Zig `version` (or `git_ref`) into the downloads
+/// cache, checked against their pinned digest, unless they're there
+/// already. Returns the archive.
+pub fn prefetch(version: &str, git_ref: Option<&str>) -> Result<PathBuf, Box<dyn Error>> {
+    let tag = git_ref.unwrap_or(version);
+    if let Some(archive) = cached(tag) {
+        let _ = cache::touch(&archive);
+        return Ok(archive);
+    }
+    let dir = cache::downloads_dir().ok_or("no cache directory; set ZIG_RS_CACHE_DIR")?;
+    fs_err::create_dir_all(&dir)?;
+    let url = url(version, git_ref);
+    let partial = dir.join(format!(
+        "{}.{}.partial",
+        archive_stem(tag),
+        std::process::id()
+    ));
+    let mut reporter = download::PercentReporter::new(Tagger::new("download"));
+    download::download(&url, &partial, |progress| reporter.report(progress))?;
+    let result = finish(&url, tag, &partial, &dir);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Names the downloaded `partial` by its format and moves it into `dir`.
+fn finish(url: &str, tag: &str, partial: &Path, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    // A mirror may serve the sources in another format.
+    let format = Format::detect(partial)?.ok_or_else(|| format!("{url} isn't an archive"))?;
+    let name = format!("{}.{}", archive_stem(tag), format.extension());
+    if !integrity::check_sha256(partial, &name, url)? {
+        Tagger::new("download").print(&integrity::unverified_warning(&name, url));
+    }
+    let archive = dir.join(name);
+    fs_err::rename(partial, &archive)?;
+    Ok(archive)
+}
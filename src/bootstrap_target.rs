@@ -0,0 +1,555 @@
+//! The Zig target and `-mcpu` a toolchain is bootstrapped for, i.e. the
+//! platform the `zig` binary itself runs on, for a rustc target. The build
+//! script reads the target from Cargo's `CARGO_CFG_TARGET_*` variables rather
+//! than matching whole triples, so new vendors (`-openwrt-`, `-win7-`) and
+//! spellings (`x86_64h`, `riscv64a23`) of a known platform need no entry.
+//!
+//! Only platforms zig-bootstrap can build a toolchain for are mapped: Linux
+//! (glibc, musl, Android, OpenHarmony), macOS, Windows and the BSDs, illumos
+//! and Solaris. Everything else is an [`UnmappedTarget`].
+//!
+//! A Linux host's triple can name the wrong C library, e.g. a glibc rustc
+//! on Alpine or Void musl, and a toolchain built for it then fails to run
+//! with baffling "not found" errors. [`host_libc()`] looks at what the
+//! system runs instead, and [`TargetCfg::correct_host_libc()`] fixes the
+//! host's `cfg`s with it.
+
+use std::{env, error::Error, fmt, path::Path};
+
+/// The parts of a rustc target that select a Zig target, as Cargo passes
+/// them to build scripts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TargetCfg {
+    /// The full triple, `TARGET`.
+    pub triple: String,
+    /// `target_arch`, e.g. `arm` for both `armv5te-` and `armv7-` triples.
+    pub arch: String,
+    /// `target_endian`: `little` or `big`.
+    pub endian: String,
+    /// `target_os`, e.g. `linux`, `android`, `macos`.
+    pub os: String,
+    /// `target_env`, e.g. `gnu`, `musl`, `msvc`, or empty.
+    pub env: String,
+    /// `target_abi`, e.g. `eabihf`, `abi64`, `x32`, or empty.
+    pub abi: String,
+}
+
+impl TargetCfg {
+    /// Reads `TARGET` and `CARGO_CFG_TARGET_{ARCH,ENDIAN,OS,ENV,ABI}`.
+    pub fn from_env() -> Self {
+        let var = |name| env::var(name).unwrap_or_default();
+        Self {
+            triple: var("TARGET"),
+            arch: var("CARGO_CFG_TARGET_ARCH"),
+            endian: var("CARGO_CFG_TARGET_ENDIAN"),
+            os: var("CARGO_CFG_TARGET_OS"),
+            env: var("CARGO_CFG_TARGET_ENV"),
+            abi: var("CARGO_CFG_TARGET_ABI"),
+        }
+    }
+
+    /// Reads the output of `rustc --print cfg --target <triple>`, for tools
+    /// that map targets outside a build script.
+    pub fn from_print_cfg(triple: &str, cfg: &str) -> Self {
+        let var = |name: &str| {
+            cfg.lines()
+                .find_map(|line| {
+                    line.strip_prefix(name)?
+                        .strip_prefix("=\"")?
+                        .strip_suffix('"')
+                })
+                .unwrap_or_default()
+                .to_owned()
+        };
+        Self {
+            triple: triple.to_owned(),
+            arch: var("target_arch"),
+            endian: var("target_endian"),
+            os: var("target_os"),
+            env: var("target_env"),
+            abi: var("target_abi"),
+        }
+    }
+
+    /// Makes a Linux host's `target_env` `musl` when it says `gnu` but the
+    /// system runs musl, which has no glibc to run a glibc `zig` with.
+    /// Returns whether it changed. A `musl` host is left alone on a glibc
+    /// system: toolchains built for musl are static and run there all the
+    /// same.
+    pub fn correct_host_libc(&mut self, libc: Option<Libc>) -> bool {
+        if self.os != "linux" || self.env != "gnu" || libc != Some(Libc::Musl) {
+            return false;
+        }
+        self.env = "musl".into();
+        true
+    }
+
+    /// The architecture as the triple spells it, e.g. `armv7` or `i586`,
+    /// which `target_arch` doesn't distinguish.
+    fn sub_arch(&self) -> &str {
+        self.triple.split('-').next().unwrap_or_default()
+    }
+
+    fn big_endian(&self) -> bool {
+        self.endian == "big"
+    }
+}
+
+/// The C library of a Linux system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Libc {
+    Gnu,
+    Musl,
+}
+
+impl Libc {
+    /// As `target_env` spells it.
+    pub fn env(self) -> &'static str {
+        match self {
+            Self::Gnu => "gnu",
+            Self::Musl => "musl",
+        }
+    }
+
+    /// `gnu` or `musl`.
+    pub fn from_env(env: &str) -> Option<Self> {
+        match env {
+            "gnu" => Some(Self::Gnu),
+            "musl" => Some(Self::Musl),
+            _ => None,
+        }
+    }
+
+    /// The C library whose dynamic loader is `interpreter`, the program
+    /// interpreter an executable names, e.g. `/lib/ld-musl-x86_64.so.1`.
+    pub fn of_interpreter(interpreter: &str) -> Option<Self> {
+        let name = interpreter.rsplit('/').next()?;
+        if name.starts_with("ld-musl-") {
+            Some(Self::Musl)
+        } else if name.starts_with("ld-linux") || name.starts_with("ld64.so") || name == "ld.so.1" {
+            Some(Self::Gnu)
+        } else {
+            None
+        }
+    }
+}
+
+/// The C library of the Linux system this runs on, going by the program
+/// interpreter of `/bin/sh`, or by which dynamic loaders are installed when
+/// `sh` is static (busybox on some images). `None` elsewhere, and when
+/// neither tells.
+pub fn host_libc() -> Option<Libc> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    if let Some(libc) = elf_interpreter(Path::new("/bin/sh"))
+        .as_deref()
+        .and_then(Libc::of_interpreter)
+    {
+        return Some(libc);
+    }
+    let loaders = |dir: &str| {
+        std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| Libc::of_interpreter(&entry.ok()?.file_name().to_string_lossy()))
+            .collect::<Vec<_>>()
+    };
+    let found: Vec<Libc> = ["/lib", "/lib64"].into_iter().flat_map(loaders).collect();
+    match (found.contains(&Libc::Gnu), found.contains(&Libc::Musl)) {
+        (true, false) => Some(Libc::Gnu),
+        (false, true) => Some(Libc::Musl),
+        _ => None,
+    }
+}
+
+/// The `PT_INTERP` of the ELF executable at `path`.
+fn elf_interpreter(path: &Path) -> Option<String> {
+    const PT_INTERP: u32 = 3;
+    let data = std::fs::read(path).ok()?;
+    if !data.starts_with(b"\x7fELF") {
+        return None;
+    }
+    let wide = *data.get(4)? == 2;
+    let little = *data.get(5)? == 1;
+    let uint = |offset: usize, width: usize| -> Option<u64> {
+        let bytes = data.get(offset..offset + width)?;
+        let fold = |value: u64, &byte: &u8| (value << 8) | u64::from(byte);
+        Some(if little {
+            bytes.iter().rev().fold(0, fold)
+        } else {
+            bytes.iter().fold(0, fold)
+        })
+    };
+    let word = if wide { 8 } else { 4 };
+    let (phoff, phentsize, phnum) = if wide {
+        (uint(0x20, 8)?, uint(0x36, 2)?, uint(0x38, 2)?)
+    } else {
+        (uint(0x1c, 4)?, uint(0x2a, 2)?, uint(0x2c, 2)?)
+    };
+    for i in 0..phnum {
+        let header = usize::try_from(phoff + i * phentsize).ok()?;
+        if uint(header, 4)? != u64::from(PT_INTERP) {
+            continue;
+        }
+        // p_offset follows p_type (and p_flags in 64-bit headers); p_filesz
+        // is two words after it.
+        let offset_at = header + if wide { 8 } else { 4 };
+        let offset = usize::try_from(uint(offset_at, word)?).ok()?;
+        let size = usize::try_from(uint(offset_at + 3 * word, word)?).ok()?;
+        let interpreter = data.get(offset..offset + size)?;
+        let interpreter = interpreter.strip_suffix(b"\0").unwrap_or(interpreter);
+        return String::from_utf8(interpreter.to_vec()).ok();
+    }
+    None
+}
+
+/// A rustc target zig-bootstrap can't build a toolchain for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnmappedTarget {
+    pub target: String,
+    pub reason: Unmapped,
+}
+
+/// Which part of an [`UnmappedTarget`] has no Zig equivalent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unmapped {
+    /// `target_arch`, or the architecture on this OS.
+    Arch(String),
+    Os(String),
+    /// `target_env` (`none` if empty), with `target_abi` after a `/` if there
+    /// is one.
+    Env(String),
+}
+
+impl fmt::Display for UnmappedTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "no Zig toolchain can be bootstrapped for {}: ",
+            self.target
+        )?;
+        match &self.reason {
+            Unmapped::Arch(arch) => write!(f, "unsupported architecture {arch}"),
+            Unmapped::Os(os) => write!(f, "unsupported OS {os}"),
+            Unmapped::Env(env) => write!(f, "unsupported environment {env}"),
+        }
+    }
+}
+
+impl Error for UnmappedTarget {}
+
+/// The `(zig_target, mcpu)` to bootstrap a toolchain for, e.g.
+/// `("arm-linux-gnueabihf", "arm1176jzf_s")` for
+/// `arm-unknown-linux-gnueabihf`. The CPU is `baseline` unless Zig's
+/// baseline for the architecture is newer than the oldest CPU the rustc
+/// target supports.
+pub fn zig_target_mcpu(cfg: &TargetCfg) -> Result<(String, String), UnmappedTarget> {
+    let unmapped = |reason| UnmappedTarget {
+        target: cfg.triple.clone(),
+        reason,
+    };
+    let arch = zig_arch(cfg).ok_or_else(|| unmapped(Unmapped::Arch(cfg.arch.clone())))?;
+    let os = match cfg.os.as_str() {
+        "linux" | "android" => "linux",
+        "macos" if matches!(arch, "x86_64" | "aarch64") => "macos",
+        "windows" if matches!(arch, "x86_64" | "x86" | "aarch64") => "windows",
+        "macos" | "windows" => return Err(unmapped(Unmapped::Arch(cfg.arch.clone()))),
+        os @ ("freebsd" | "netbsd" | "openbsd" | "dragonfly" | "illumos" | "solaris") => os,
+        os => return Err(unmapped(Unmapped::Os(os.into()))),
+    };
+    let abi = zig_abi(cfg).ok_or_else(|| {
+        let env = if cfg.env.is_empty() { "none" } else { &cfg.env };
+        let env = if cfg.abi.is_empty() {
+            env.to_owned()
+        } else {
+            format!("{env}/{}", cfg.abi)
+        };
+        unmapped(Unmapped::Env(env))
+    })?;
+    Ok((format!("{arch}-{os}-{abi}"), zig_mcpu(cfg).into()))
+}
+
+fn zig_arch(cfg: &TargetCfg) -> Option<&'static str> {
+    let big = cfg.big_endian();
+    Some(match cfg.arch.as_str() {
+        "x86_64" => "x86_64",
+        "x86" => "x86",
+        "aarch64" if big => "aarch64_be",
+        // Zig has no ARM64EC architecture. ARM64EC processes run on Windows
+        // on ARM machines which also run native ARM64 code, so the toolchain
+        // itself is a plain aarch64 build.
+        "aarch64" | "arm64ec" => "aarch64",
+        "arm" if big => "armeb",
+        "arm" => "arm",
+        "riscv64" => "riscv64",
+        "riscv32" => "riscv32",
+        "powerpc" => "powerpc",
+        "powerpc64" if big => "powerpc64",
+        "powerpc64" => "powerpc64le",
+        "mips" | "mips32r6" if big => "mips",
+        "mips" | "mips32r6" => "mipsel",
+        "mips64" | "mips64r6" if big => "mips64",
+        "mips64" | "mips64r6" => "mips64el",
+        "loongarch64" => "loongarch64",
+        "s390x" => "s390x",
+        "sparc" => "sparc",
+        "sparc64" => "sparc64",
+        "m68k" => "m68k",
+        "hexagon" => "hexagon",
+        _ => return None,
+    })
+}
+
+fn zig_abi(cfg: &TargetCfg) -> Option<String> {
+    let abi = cfg.abi.as_str();
+    // The part of the ABI Zig spells after the libc, e.g. `eabihf` in
+    // `musleabihf`.
+    let suffix = match abi {
+        // PowerPC ELF ABI versions follow from the libc, and Windows
+        // `gnullvm` targets only differ in their C++ and unwind libraries.
+        "" | "elfv1" | "elfv2" | "llvm" => "",
+        "eabi" | "eabihf" | "x32" => abi,
+        "abi64" => "abi64",
+        "ilp32" => "ilp32",
+        _ => return None,
+    };
+    Some(match (cfg.os.as_str(), cfg.env.as_str()) {
+        ("android", _) if suffix.is_empty() => "android".into(),
+        ("android", _) if suffix == "eabi" => "androideabi".into(),
+        ("linux", "gnu" | "musl") => format!("{}{suffix}", cfg.env),
+        ("linux", "ohos") if matches!(suffix, "" | "eabi") => format!("ohos{suffix}"),
+        ("macos", "") if suffix.is_empty() => "none".into(),
+        // The bootstrap only knows the GNU ABI on Windows, which links
+        // against the bundled mingw-w64 import libraries rather than those
+        // of the Windows SDK.
+        ("windows", "gnu" | "msvc") if suffix.is_empty() => "gnu".into(),
+        ("freebsd" | "netbsd" | "openbsd" | "dragonfly" | "illumos" | "solaris", "") => {
+            match suffix {
+                "" => "none".into(),
+                "eabi" | "eabihf" => suffix.into(),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    })
+}
+
+/// The oldest CPU the rustc target supports, where Zig's `baseline` for the
+/// architecture is newer.
+fn zig_mcpu(cfg: &TargetCfg) -> &'static str {
+    match (cfg.arch.as_str(), cfg.sub_arch()) {
+        ("x86", "i586") => "pentium",
+        ("arm", "armv4t") => "arm7tdmi",
+        ("arm", "armv5te") => "arm926ej_s",
+        // `arm-linux-androideabi` is ARMv5TE, `arm-unknown-linux-*` ARMv6.
+        ("arm", "arm") if cfg.os == "android" => "arm926ej_s",
+        ("arm", "arm" | "armv6") => "arm1176jzf_s",
+        ("mips32r6", _) => "mips32r6",
+        ("mips64r6", _) => "mips64r6",
+        _ => "baseline",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `triple`, `"arch endian os env abi"` as `rustc --print cfg` reports
+    /// them (`-` for empty), and the expected Zig target and CPU.
+    #[rustfmt::skip]
+    const SUPPORTED: &[(&str, &str, &str, &str)] = &[
+        ("aarch64-apple-darwin", "aarch64 little macos - -", "aarch64-macos-none", "baseline"),
+        ("aarch64-linux-android", "aarch64 little android - -", "aarch64-linux-android", "baseline"),
+        ("aarch64-pc-windows-gnullvm", "aarch64 little windows gnu llvm", "aarch64-windows-gnu", "baseline"),
+        ("aarch64-pc-windows-msvc", "aarch64 little windows msvc -", "aarch64-windows-gnu", "baseline"),
+        ("aarch64-unknown-freebsd", "aarch64 little freebsd - -", "aarch64-freebsd-none", "baseline"),
+        ("aarch64-unknown-illumos", "aarch64 little illumos - -", "aarch64-illumos-none", "baseline"),
+        ("aarch64-unknown-linux-gnu", "aarch64 little linux gnu -", "aarch64-linux-gnu", "baseline"),
+        ("aarch64-unknown-linux-gnu_ilp32", "aarch64 little linux gnu ilp32", "aarch64-linux-gnuilp32", "baseline"),
+        ("aarch64-unknown-linux-musl", "aarch64 little linux musl -", "aarch64-linux-musl", "baseline"),
+        ("aarch64-unknown-linux-ohos", "aarch64 little linux ohos -", "aarch64-linux-ohos", "baseline"),
+        ("aarch64-unknown-netbsd", "aarch64 little netbsd - -", "aarch64-netbsd-none", "baseline"),
+        ("aarch64-unknown-openbsd", "aarch64 little openbsd - -", "aarch64-openbsd-none", "baseline"),
+        ("aarch64_be-unknown-linux-gnu", "aarch64 big linux gnu -", "aarch64_be-linux-gnu", "baseline"),
+        ("aarch64_be-unknown-linux-gnu_ilp32", "aarch64 big linux gnu ilp32", "aarch64_be-linux-gnuilp32", "baseline"),
+        ("aarch64_be-unknown-linux-musl", "aarch64 big linux musl -", "aarch64_be-linux-musl", "baseline"),
+        ("aarch64_be-unknown-netbsd", "aarch64 big netbsd - -", "aarch64_be-netbsd-none", "baseline"),
+        ("arm-linux-androideabi", "arm little android - eabi", "arm-linux-androideabi", "arm926ej_s"),
+        ("arm-unknown-linux-gnueabi", "arm little linux gnu eabi", "arm-linux-gnueabi", "arm1176jzf_s"),
+        ("arm-unknown-linux-gnueabihf", "arm little linux gnu eabihf", "arm-linux-gnueabihf", "arm1176jzf_s"),
+        ("arm-unknown-linux-musleabi", "arm little linux musl eabi", "arm-linux-musleabi", "arm1176jzf_s"),
+        ("arm-unknown-linux-musleabihf", "arm little linux musl eabihf", "arm-linux-musleabihf", "arm1176jzf_s"),
+        ("arm64e-apple-darwin", "aarch64 little macos - -", "aarch64-macos-none", "baseline"),
+        ("arm64ec-pc-windows-msvc", "arm64ec little windows msvc -", "aarch64-windows-gnu", "baseline"),
+        ("armeb-unknown-linux-gnueabi", "arm big linux gnu eabi", "armeb-linux-gnueabi", "baseline"),
+        ("armv4t-unknown-linux-gnueabi", "arm little linux gnu eabi", "arm-linux-gnueabi", "arm7tdmi"),
+        ("armv5te-unknown-linux-gnueabi", "arm little linux gnu eabi", "arm-linux-gnueabi", "arm926ej_s"),
+        ("armv5te-unknown-linux-musleabi", "arm little linux musl eabi", "arm-linux-musleabi", "arm926ej_s"),
+        ("armv6-unknown-freebsd", "arm little freebsd - eabihf", "arm-freebsd-eabihf", "arm1176jzf_s"),
+        ("armv6-unknown-netbsd-eabihf", "arm little netbsd - eabihf", "arm-netbsd-eabihf", "arm1176jzf_s"),
+        ("armv7-linux-androideabi", "arm little android - eabi", "arm-linux-androideabi", "baseline"),
+        ("armv7-unknown-freebsd", "arm little freebsd - eabihf", "arm-freebsd-eabihf", "baseline"),
+        ("armv7-unknown-linux-gnueabi", "arm little linux gnu eabi", "arm-linux-gnueabi", "baseline"),
+        ("armv7-unknown-linux-gnueabihf", "arm little linux gnu eabihf", "arm-linux-gnueabihf", "baseline"),
+        ("armv7-unknown-linux-musleabi", "arm little linux musl eabi", "arm-linux-musleabi", "baseline"),
+        ("armv7-unknown-linux-musleabihf", "arm little linux musl eabihf", "arm-linux-musleabihf", "baseline"),
+        ("armv7-unknown-linux-ohos", "arm little linux ohos eabi", "arm-linux-ohoseabi", "baseline"),
+        ("armv7-unknown-netbsd-eabihf", "arm little netbsd - eabihf", "arm-netbsd-eabihf", "baseline"),
+        ("hexagon-unknown-linux-musl", "hexagon little linux musl -", "hexagon-linux-musl", "baseline"),
+        ("i586-unknown-linux-gnu", "x86 little linux gnu -", "x86-linux-gnu", "pentium"),
+        ("i586-unknown-linux-musl", "x86 little linux musl -", "x86-linux-musl", "pentium"),
+        ("i586-unknown-netbsd", "x86 little netbsd - -", "x86-netbsd-none", "pentium"),
+        ("i686-linux-android", "x86 little android - -", "x86-linux-android", "baseline"),
+        ("i686-pc-windows-gnu", "x86 little windows gnu -", "x86-windows-gnu", "baseline"),
+        ("i686-pc-windows-gnullvm", "x86 little windows gnu llvm", "x86-windows-gnu", "baseline"),
+        ("i686-pc-windows-msvc", "x86 little windows msvc -", "x86-windows-gnu", "baseline"),
+        ("i686-unknown-freebsd", "x86 little freebsd - -", "x86-freebsd-none", "baseline"),
+        ("i686-unknown-linux-gnu", "x86 little linux gnu -", "x86-linux-gnu", "baseline"),
+        ("i686-unknown-linux-musl", "x86 little linux musl -", "x86-linux-musl", "baseline"),
+        ("i686-unknown-netbsd", "x86 little netbsd - -", "x86-netbsd-none", "baseline"),
+        ("i686-unknown-openbsd", "x86 little openbsd - -", "x86-openbsd-none", "baseline"),
+        ("i686-win7-windows-gnu", "x86 little windows gnu -", "x86-windows-gnu", "baseline"),
+        ("i686-win7-windows-msvc", "x86 little windows msvc -", "x86-windows-gnu", "baseline"),
+        ("loongarch64-unknown-linux-gnu", "loongarch64 little linux gnu -", "loongarch64-linux-gnu", "baseline"),
+        ("loongarch64-unknown-linux-musl", "loongarch64 little linux musl -", "loongarch64-linux-musl", "baseline"),
+        ("loongarch64-unknown-linux-ohos", "loongarch64 little linux ohos -", "loongarch64-linux-ohos", "baseline"),
+        ("m68k-unknown-linux-gnu", "m68k big linux gnu -", "m68k-linux-gnu", "baseline"),
+        ("mips-unknown-linux-gnu", "mips big linux gnu -", "mips-linux-gnu", "baseline"),
+        ("mips-unknown-linux-musl", "mips big linux musl -", "mips-linux-musl", "baseline"),
+        ("mips64-openwrt-linux-musl", "mips64 big linux musl abi64", "mips64-linux-muslabi64", "baseline"),
+        ("mips64-unknown-linux-gnuabi64", "mips64 big linux gnu abi64", "mips64-linux-gnuabi64", "baseline"),
+        ("mips64-unknown-linux-muslabi64", "mips64 big linux musl abi64", "mips64-linux-muslabi64", "baseline"),
+        ("mips64el-unknown-linux-gnuabi64", "mips64 little linux gnu abi64", "mips64el-linux-gnuabi64", "baseline"),
+        ("mips64el-unknown-linux-muslabi64", "mips64 little linux musl abi64", "mips64el-linux-muslabi64", "baseline"),
+        ("mipsel-unknown-linux-gnu", "mips little linux gnu -", "mipsel-linux-gnu", "baseline"),
+        ("mipsel-unknown-linux-musl", "mips little linux musl -", "mipsel-linux-musl", "baseline"),
+        ("mipsel-unknown-netbsd", "mips little netbsd - -", "mipsel-netbsd-none", "baseline"),
+        ("mipsisa32r6-unknown-linux-gnu", "mips32r6 big linux gnu -", "mips-linux-gnu", "mips32r6"),
+        ("mipsisa32r6el-unknown-linux-gnu", "mips32r6 little linux gnu -", "mipsel-linux-gnu", "mips32r6"),
+        ("mipsisa64r6-unknown-linux-gnuabi64", "mips64r6 big linux gnu abi64", "mips64-linux-gnuabi64", "mips64r6"),
+        ("mipsisa64r6el-unknown-linux-gnuabi64", "mips64r6 little linux gnu abi64", "mips64el-linux-gnuabi64", "mips64r6"),
+        ("powerpc-unknown-freebsd", "powerpc big freebsd - -", "powerpc-freebsd-none", "baseline"),
+        ("powerpc-unknown-linux-gnu", "powerpc big linux gnu -", "powerpc-linux-gnu", "baseline"),
+        ("powerpc-unknown-linux-musl", "powerpc big linux musl -", "powerpc-linux-musl", "baseline"),
+        ("powerpc-unknown-netbsd", "powerpc big netbsd - -", "powerpc-netbsd-none", "baseline"),
+        ("powerpc-unknown-openbsd", "powerpc big openbsd - -", "powerpc-openbsd-none", "baseline"),
+        ("powerpc64-unknown-freebsd", "powerpc64 big freebsd - elfv2", "powerpc64-freebsd-none", "baseline"),
+        ("powerpc64-unknown-linux-gnu", "powerpc64 big linux gnu elfv1", "powerpc64-linux-gnu", "baseline"),
+        ("powerpc64-unknown-linux-musl", "powerpc64 big linux musl elfv2", "powerpc64-linux-musl", "baseline"),
+        ("powerpc64-unknown-openbsd", "powerpc64 big openbsd - elfv2", "powerpc64-openbsd-none", "baseline"),
+        ("powerpc64le-unknown-freebsd", "powerpc64 little freebsd - elfv2", "powerpc64le-freebsd-none", "baseline"),
+        ("powerpc64le-unknown-linux-gnu", "powerpc64 little linux gnu elfv2", "powerpc64le-linux-gnu", "baseline"),
+        ("powerpc64le-unknown-linux-musl", "powerpc64 little linux musl elfv2", "powerpc64le-linux-musl", "baseline"),
+        ("riscv32gc-unknown-linux-gnu", "riscv32 little linux gnu -", "riscv32-linux-gnu", "baseline"),
+        ("riscv32gc-unknown-linux-musl", "riscv32 little linux musl -", "riscv32-linux-musl", "baseline"),
+        ("riscv64-linux-android", "riscv64 little android - -", "riscv64-linux-android", "baseline"),
+        ("riscv64a23-unknown-linux-gnu", "riscv64 little linux gnu -", "riscv64-linux-gnu", "baseline"),
+        ("riscv64gc-unknown-freebsd", "riscv64 little freebsd - -", "riscv64-freebsd-none", "baseline"),
+        ("riscv64gc-unknown-linux-gnu", "riscv64 little linux gnu -", "riscv64-linux-gnu", "baseline"),
+        ("riscv64gc-unknown-linux-musl", "riscv64 little linux musl -", "riscv64-linux-musl", "baseline"),
+        ("riscv64gc-unknown-netbsd", "riscv64 little netbsd - -", "riscv64-netbsd-none", "baseline"),
+        ("riscv64gc-unknown-openbsd", "riscv64 little openbsd - -", "riscv64-openbsd-none", "baseline"),
+        ("s390x-unknown-linux-gnu", "s390x big linux gnu -", "s390x-linux-gnu", "baseline"),
+        ("s390x-unknown-linux-musl", "s390x big linux musl -", "s390x-linux-musl", "baseline"),
+        ("sparc-unknown-linux-gnu", "sparc big linux gnu -", "sparc-linux-gnu", "baseline"),
+        ("sparc64-unknown-linux-gnu", "sparc64 big linux gnu -", "sparc64-linux-gnu", "baseline"),
+        ("sparc64-unknown-netbsd", "sparc64 big netbsd - -", "sparc64-netbsd-none", "baseline"),
+        ("sparc64-unknown-openbsd", "sparc64 big openbsd - -", "sparc64-openbsd-none", "baseline"),
+        ("sparcv9-sun-solaris", "sparc64 big solaris - -", "sparc64-solaris-none", "baseline"),
+        ("thumbv7neon-linux-androideabi", "arm little android - eabi", "arm-linux-androideabi", "baseline"),
+        ("thumbv7neon-unknown-linux-gnueabihf", "arm little linux gnu eabihf", "arm-linux-gnueabihf", "baseline"),
+        ("thumbv7neon-unknown-linux-musleabihf", "arm little linux musl eabihf", "arm-linux-musleabihf", "baseline"),
+        ("x86_64-apple-darwin", "x86_64 little macos - -", "x86_64-macos-none", "baseline"),
+        ("x86_64-linux-android", "x86_64 little android - -", "x86_64-linux-android", "baseline"),
+        ("x86_64-pc-solaris", "x86_64 little solaris - -", "x86_64-solaris-none", "baseline"),
+        ("x86_64-pc-windows-gnu", "x86_64 little windows gnu -", "x86_64-windows-gnu", "baseline"),
+        ("x86_64-pc-windows-gnullvm", "x86_64 little windows gnu llvm", "x86_64-windows-gnu", "baseline"),
+        ("x86_64-pc-windows-msvc", "x86_64 little windows msvc -", "x86_64-windows-gnu", "baseline"),
+        ("x86_64-unikraft-linux-musl", "x86_64 little linux musl -", "x86_64-linux-musl", "baseline"),
+        ("x86_64-unknown-dragonfly", "x86_64 little dragonfly - -", "x86_64-dragonfly-none", "baseline"),
+        ("x86_64-unknown-freebsd", "x86_64 little freebsd - -", "x86_64-freebsd-none", "baseline"),
+        ("x86_64-unknown-illumos", "x86_64 little illumos - -", "x86_64-illumos-none", "baseline"),
+        ("x86_64-unknown-linux-gnu", "x86_64 little linux gnu -", "x86_64-linux-gnu", "baseline"),
+        ("x86_64-unknown-linux-gnux32", "x86_64 little linux gnu x32", "x86_64-linux-gnux32", "baseline"),
+        ("x86_64-unknown-linux-musl", "x86_64 little linux musl -", "x86_64-linux-musl", "baseline"),
+        ("x86_64-unknown-linux-ohos", "x86_64 little linux ohos -", "x86_64-linux-ohos", "baseline"),
+        ("x86_64-unknown-netbsd", "x86_64 little netbsd - -", "x86_64-netbsd-none", "baseline"),
+        ("x86_64-unknown-openbsd", "x86_64 little openbsd - -", "x86_64-openbsd-none", "baseline"),
+        ("x86_64-win7-windows-gnu", "x86_64 little windows gnu -", "x86_64-windows-gnu", "baseline"),
+        ("x86_64-win7-windows-msvc", "x86_64 little windows msvc -", "x86_64-windows-gnu", "baseline"),
+        ("x86_64h-apple-darwin", "x86_64 little macos - -", "x86_64-macos-none", "baseline"),
+    ];
+
+    fn cfg(triple: &str, fields: &str) -> TargetCfg {
+        let fields: Vec<String> = fields
+            .split(' ')
+            .map(|field| if field == "-" { "" } else { field }.into())
+            .collect();
+        TargetCfg {
+            triple: triple.into(),
+            arch: fields[0].clone(),
+            endian: fields[1].clone(),
+            os: fields[2].clone(),
+            env: fields[3].clone(),
+            abi: fields[4].clone(),
+        }
+    }
+
+    #[test]
+    fn supported_triples() {
+        for &(triple, fields, zig_target, mcpu) in SUPPORTED {
+            assert_eq!(
+                zig_target_mcpu(&cfg(triple, fields)),
+                Ok((zig_target.into(), mcpu.into())),
+                "{triple}"
+            );
+        }
+    }
+
+    /// Like [`SUPPORTED`], with the expected reason.
+    type UnmappedCase = (&'static str, &'static str, fn() -> Unmapped);
+
+    #[rustfmt::skip]
+    const UNMAPPED: &[UnmappedCase] = &[
+        ("wasm32-wasip1", "wasm32 little wasi p1 -", || Unmapped::Arch("wasm32".into())),
+        ("i686-apple-darwin", "x86 little macos - -", || Unmapped::Arch("x86".into())),
+        ("thumbv7a-pc-windows-msvc", "arm little windows msvc -", || Unmapped::Arch("arm".into())),
+        ("aarch64-apple-ios", "aarch64 little ios - -", || Unmapped::Os("ios".into())),
+        ("x86_64-unknown-none", "x86_64 little none - -", || Unmapped::Os("none".into())),
+        ("x86_64-unknown-linux-none", "x86_64 little linux - -", || Unmapped::Env("none".into())),
+        ("mips-unknown-linux-uclibc", "mips big linux uclibc -", || Unmapped::Env("uclibc".into())),
+        ("powerpc-unknown-linux-gnuspe", "powerpc big linux gnu spe", || Unmapped::Env("gnu/spe".into())),
+        ("x86_64-uwp-windows-msvc", "x86_64 little windows msvc uwp", || Unmapped::Env("msvc/uwp".into())),
+        ("csky-unknown-linux-gnuabiv2", "csky little linux gnu abiv2", || Unmapped::Arch("csky".into())),
+    ];
+
+    #[test]
+    fn unmapped_triples() {
+        for &(triple, fields, reason) in UNMAPPED {
+            assert_eq!(
+                zig_target_mcpu(&cfg(triple, fields)),
+                Err(UnmappedTarget {
+                    target: triple.into(),
+                    reason: reason(),
+                }),
+                "{triple}"
+            );
+        }
+    }
+
+    #[test]
+    fn libc_of_interpreters() {
+        for (interpreter, libc) in [
+            ("/lib/ld-musl-x86_64.so.1", Some(Libc::Musl)),
+            ("/lib/ld-musl-armhf.so.1", Some(Libc::Musl)),
+            ("/lib64/ld-linux-x86-64.so.2", Some(Libc::Gnu)),
+            ("/lib/ld-linux-aarch64.so.1", Some(Libc::Gnu)),
+            ("/lib64/ld64.so.2", Some(Libc::Gnu)),
+            ("/lib/ld.so.1", Some(Libc::Gnu)),
+            ("/system/bin/linker64", None),
+        ] {
+            assert_eq!(Libc::of_interpreter(interpreter), libc, "{interpreter}");
+        }
+    }
+}
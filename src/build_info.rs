@@ -0,0 +1,91 @@
+//! What the build script produced: which Zig, for which target, and how it was
+//! obtained. Applications can log [`BUILD_INFO`] to record exactly which
+//! compiler built their native parts.
+
+use std::fmt;
+
+use crate::prune::Profile;
+
+/// How the build script obtained the bundled toolchain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bootstrap {
+    /// Built from source with zig-bootstrap.
+    Source,
+    /// An official prebuilt release.
+    Prebuilt,
+    /// A `zig` already installed on the system.
+    System,
+    /// No usable toolchain, only empty placeholder files. This is what docs.rs
+    /// builds and the `DO_IT` development shortcut produce.
+    Stub,
+}
+
+impl fmt::Display for Bootstrap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Source => "source",
+            Self::Prebuilt => "prebuilt",
+            Self::System => "system",
+            Self::Stub => "stub",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildInfo {
+    /// As reported by `zig version`, e.g. `0.14.0` or `0.15.0-dev.1+a1b2c3d`.
+    pub zig_version: &'static str,
+    /// The upstream Zig commit, when the version names one.
+    pub zig_commit: Option<&'static str>,
+    /// The rustc target triple the toolchain was built for: the host's, even
+    /// when the crate was cross-compiled, so build scripts can run it.
+    pub target: &'static str,
+    /// The Zig target and CPU passed to zig-bootstrap. Empty for targets the
+    /// build script has no mapping for.
+    pub zig_target: &'static str,
+    pub mcpu: &'static str,
+    pub bootstrap: Bootstrap,
+    /// The zig-bootstrap tag, branch or commit a source build was built from
+    /// when `ZIG_RS_BOOTSTRAP_REF` named one.
+    pub bootstrap_ref: Option<&'static str>,
+    pub zig: &'static str,
+    pub lib_dir: &'static str,
+    /// What was [pruned](crate::prune) from `lib/`; empty for all of it.
+    pub lib_profiles: &'static [Profile],
+    /// The directory of a toolchain that runs on the target the crate was
+    /// built for: the bundled one when that's the host, or else the one the
+    /// `target-toolchain` feature installs next to it, if it's on.
+    pub target_toolchain: Option<&'static str>,
+}
+
+/// The Zig release this version of the package pins. The build script
+/// installs it unless `ZIG_RS_ZIG_VERSION` asks for another, so compare it
+/// with [`BuildInfo::zig_version`] to tell whether it did.
+pub const PINNED_ZIG_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION_MAJOR"),
+    ".",
+    env!("CARGO_PKG_VERSION_MINOR"),
+    ".",
+    env!("CARGO_PKG_VERSION_PATCH")
+);
+
+/// Generated by the build script.
+pub const BUILD_INFO: BuildInfo = include!(concat!(env!("OUT_DIR"), "/build_info.rs"));
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "zig {}", self.zig_version)?;
+        if let Some(commit) = self.zig_commit {
+            write!(f, " ({commit})")?;
+        }
+        write!(f, " for {}", self.target)?;
+        if !self.zig_target.is_empty() {
+            write!(f, " ({} {})", self.zig_target, self.mcpu)?;
+        }
+        write!(f, ", {} build", self.bootstrap)?;
+        if let Some(bootstrap_ref) = self.bootstrap_ref {
+            write!(f, " of zig-bootstrap {bootstrap_ref}")?;
+        }
+        write!(f, " at {}", self.zig)
+    }
+}
@@ -0,0 +1,507 @@
+//! Locations of the caches this crate and Zig write to, and selective cleaning
+//! of them. These grow silently: every toolchain version/target pair, every
+//! build output and every `zig` compile leaves something behind, and it's not
+//! unusual to find tens of gigabytes after a few months.
+//!
+//! The zig-rs cache lives in `ZIG_RS_CACHE_DIR` if set, otherwise in the
+//! platform cache directory (`$XDG_CACHE_HOME/zig-rs`, `~/.cache/zig-rs`,
+//! `~/Library/Caches/zig-rs` or `%LOCALAPPDATA%\zig-rs`). It's laid out as:
+//!
+//! ```text
+//! <cache>/toolchains/<zig-version>/<platform>/
+//! <cache>/builds/<key>/
+//! <cache>/incremental/<zig-version>/<project>-<key>/<zig-target>/
+//! <cache>/objects/<zig-version>/<key>.o
+//! <cache>/objects/<zig-version>/<key>.stderr
+//! <cache>/downloads/<file>
+//! ```
+//!
+//! The build script bootstraps toolchains into the toolchain cache rather
+//! than `OUT_DIR`, so `cargo clean` and other crates don't start another
+//! LLVM build, and copies them into `OUT_DIR` from there ([`LINK_ENV`]
+//! symlinks them instead). The `no-cache` feature turns this off.
+//!
+//! Zig's own caches are kept out of both when `zig` runs inside a build
+//! script; see [`zig_build_script_dirs()`].
+//!
+//! Entries are [`touch()`]ed when they're used, so [`gc()`] can trim the
+//! caches to a size budget by evicting whatever was used least recently
+//! rather than whatever was installed first.
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use sha2::{Digest, Sha256};
+
+/// Environment variable that overrides [`dir()`].
+pub const DIR_ENV: &str = "ZIG_RS_CACHE_DIR";
+
+/// Root of the zig-rs cache.
+pub fn dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os(DIR_ENV) {
+        return Some(dir.into());
+    }
+    platform_cache_dir().map(|dir| dir.join("zig-rs"))
+}
+
+/// Shared toolchain cache (`<cache>/toolchains`).
+pub fn toolchains_dir() -> Option<PathBuf> {
+    dir().map(|dir| dir.join("toolchains"))
+}
+
+/// Build output cache (`<cache>/builds`).
+pub fn builds_dir() -> Option<PathBuf> {
+    dir().map(|dir| dir.join("builds"))
+}
+
+/// Incremental compilation state (`<cache>/incremental`).
+pub fn incremental_dir() -> Option<PathBuf> {
+    dir().map(|dir| dir.join("incremental"))
+}
+
+/// Object files shared between [`Build`](crate::Build)s (`<cache>/objects`).
+pub fn objects_dir() -> Option<PathBuf> {
+    dir().map(|dir| dir.join("objects"))
+}
+
+/// Source archives fetched ahead of the build, e.g. by `cargo zig prefetch`
+/// (`<cache>/downloads`). The build script looks here before downloading.
+pub fn downloads_dir() -> Option<PathBuf> {
+    dir().map(|dir| dir.join("downloads"))
+}
+
+/// Environment variable that makes the build script symlink a cached
+/// toolchain into `OUT_DIR` instead of copying it, on Unix hosts. Cleaning
+/// the toolchain cache then breaks the builds that linked to it.
+pub const LINK_ENV: &str = "ZIG_RS_CACHE_LINK";
+
+/// Name of the file in a directory entry whose modification time records
+/// when the entry was last used.
+const LAST_USED_FILE: &str = ".last-used";
+
+/// How stale a last-used time gets before [`touch()`] rewrites it, so hot
+/// paths don't write to the cache on every use.
+const TOUCH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Records that the cache entry at `path`, a directory or a single file, was
+/// just used, by bumping the modification time [`Entry::modified`] and
+/// [`gc()`] go by. Does nothing when it was recorded less than an hour ago.
+/// Callers ignore failures, e.g. from a read-only cache.
+pub fn touch(path: &Path) -> io::Result<()> {
+    let now = SystemTime::now();
+    let recent = |mtime: SystemTime| {
+        now.duration_since(mtime)
+            .is_ok_and(|elapsed| elapsed < TOUCH_INTERVAL)
+    };
+    if fs::metadata(path)?.is_dir() {
+        let marker = path.join(LAST_USED_FILE);
+        if fs::metadata(&marker).is_ok_and(|m| m.modified().is_ok_and(recent)) {
+            return Ok(());
+        }
+        fs::File::create(marker)?.set_modified(now)
+    } else {
+        let file = fs::File::options().append(true).open(path)?;
+        if file.metadata()?.modified().is_ok_and(recent) {
+            return Ok(());
+        }
+        file.set_modified(now)
+    }
+}
+
+/// Environment variable holding the size budget of [`gc()`], e.g. `10G`.
+pub const MAX_SIZE_ENV: &str = "ZIG_RS_CACHE_MAX_SIZE";
+
+/// The size budget of [`gc()`] when [`MAX_SIZE_ENV`] isn't set: 20 GiB.
+pub const DEFAULT_MAX_SIZE: u64 = 20 << 30;
+
+/// The size budget of [`gc()`]: [`MAX_SIZE_ENV`] parsed with
+/// [`parse_size()`], or [`DEFAULT_MAX_SIZE`].
+pub fn max_size() -> io::Result<u64> {
+    match env::var(MAX_SIZE_ENV) {
+        Ok(value) => parse_size(&value).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{MAX_SIZE_ENV}: invalid size: {value}"),
+            )
+        }),
+        Err(_) => Ok(DEFAULT_MAX_SIZE),
+    }
+}
+
+/// Trims every cache to [`max_size()`] in total, evicting the least recently
+/// used entries first. `cargo zig clean --gc` runs this.
+pub fn gc() -> io::Result<Report> {
+    Clean::new().max_size(max_size()?).run()
+}
+
+/// The persistent `-fincremental` state directory for the Zig project in
+/// `project` built with Zig `zig_version` for `zig_target`. Unlike the
+/// hermetic build script caches this survives `cargo clean`, which is the
+/// point: the edit-rebuild loop of a large Zig module only stays fast while
+/// the compiler's state is around.
+pub fn incremental_state_dir(
+    project: &Path,
+    zig_version: &str,
+    zig_target: &str,
+) -> io::Result<Option<PathBuf>> {
+    let Some(root) = incremental_dir() else {
+        return Ok(None);
+    };
+    let project = project.canonicalize()?;
+    let name = project
+        .file_name()
+        .map_or("project".into(), |n| n.to_string_lossy());
+    let key = &crate::manifest::hex(&Sha256::digest(project.as_os_str().as_encoded_bytes()))[..16];
+    let entry = root.join(zig_version).join(format!("{name}-{key}"));
+    fs::create_dir_all(&entry)?;
+    let _ = touch(&entry);
+    Ok(Some(entry.join(zig_target)))
+}
+
+/// Zig's own global cache, resolved the same way `zig` resolves it:
+/// `ZIG_GLOBAL_CACHE_DIR`, then `%LOCALAPPDATA%\zig` on Windows, then
+/// `$XDG_CACHE_HOME/zig` or `~/.cache/zig` everywhere else (including macOS).
+pub fn zig_global_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("ZIG_GLOBAL_CACHE_DIR") {
+        return Some(dir.into());
+    }
+    if cfg!(windows) {
+        return env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("zig"));
+    }
+    xdg_cache_dir().map(|dir| dir.join("zig"))
+}
+
+/// Environment variable that, when set, makes build scripts use Zig's usual
+/// shared caches instead of [`zig_build_script_dirs()`].
+pub const SHARED_ZIG_CACHE_ENV: &str = "ZIG_RS_SHARED_ZIG_CACHE";
+
+/// The `(local, global)` Zig cache directories for `zig` commands run by a
+/// Cargo build script: `OUT_DIR/zig-cache/local` and `OUT_DIR/zig-cache/global`.
+/// Keeping them inside the target directory means `cargo clean` removes them
+/// and sandboxed builds never write to `$HOME`. Returns `None` outside build
+/// scripts (no `OUT_DIR`) and when [`SHARED_ZIG_CACHE_ENV`] is set.
+pub fn zig_build_script_dirs() -> Option<(PathBuf, PathBuf)> {
+    if env::var_os(SHARED_ZIG_CACHE_ENV).is_some() {
+        return None;
+    }
+    let dir = PathBuf::from(env::var_os("OUT_DIR")?).join("zig-cache");
+    Some((dir.join("local"), dir.join("global")))
+}
+
+fn xdg_cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+}
+
+fn platform_cache_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") && env::var_os("XDG_CACHE_HOME").is_none() {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library").join("Caches"))
+    } else {
+        xdg_cache_dir()
+    }
+}
+
+/// One of the caches [`Clean`] knows how to prune.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    /// Bootstrapped toolchains, one entry per version and target.
+    Toolchains,
+    /// Outputs of the build helpers.
+    Builds,
+    /// Zig's global cache. Version filters don't apply to it.
+    ZigGlobal,
+    /// Incremental compilation state, one entry per version and project.
+    Incremental,
+    /// Deduplicated objects, one entry per object file.
+    Objects,
+    /// Prefetched source archives, one entry per file.
+    Downloads,
+}
+
+impl Kind {
+    pub const ALL: [Kind; 6] = [
+        Kind::Toolchains,
+        Kind::Builds,
+        Kind::ZigGlobal,
+        Kind::Incremental,
+        Kind::Objects,
+        Kind::Downloads,
+    ];
+
+    pub fn dir(self) -> Option<PathBuf> {
+        match self {
+            Kind::Toolchains => toolchains_dir(),
+            Kind::Builds => builds_dir(),
+            Kind::Incremental => incremental_dir(),
+            Kind::Objects => objects_dir(),
+            Kind::Downloads => downloads_dir(),
+            Kind::ZigGlobal => zig_global_dir(),
+        }
+    }
+
+    /// Lists the removable units of this cache. An entry is the smallest
+    /// directory that can be deleted without corrupting the rest of the cache.
+    pub fn entries(self) -> io::Result<Vec<Entry>> {
+        let Some(root) = self.dir() else {
+            return Ok(Vec::new());
+        };
+        let mut entries = Vec::new();
+        match self {
+            Kind::Toolchains | Kind::Incremental | Kind::Objects => {
+                for version in read_dir_if_exists(&root)? {
+                    let version_name = version
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .map(String::from);
+                    for target in read_dir_if_exists(&version)? {
+                        entries.push(Entry::new(self, target, version_name.clone())?);
+                    }
+                }
+            }
+            Kind::Builds | Kind::Downloads => {
+                for path in read_dir_if_exists(&root)? {
+                    entries.push(Entry::new(self, path, None)?);
+                }
+            }
+            Kind::ZigGlobal => {
+                // `h/` holds manifests pointing into `o/`, so deleting single
+                // objects is safe: Zig treats a missing object as a cache miss.
+                for sub in ["o", "z", "tmp"] {
+                    for path in read_dir_if_exists(&root.join(sub))? {
+                        entries.push(Entry::new(self, path, None)?);
+                    }
+                }
+            }
+        }
+        Ok(entries)
+    }
+}
+
+fn read_dir_if_exists(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir.map(|entry| Ok(entry?.path())).collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// A removable cache entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub kind: Kind,
+    pub path: PathBuf,
+    /// Zig version the entry belongs to, where that's known.
+    pub version: Option<String>,
+    /// Total size of all files in the entry.
+    pub size: u64,
+    /// Most recent modification time of anything in the entry. [`touch()`]
+    /// bumps it whenever the entry is used, so it's also the last use.
+    pub modified: SystemTime,
+}
+
+impl Entry {
+    fn new(kind: Kind, path: PathBuf, version: Option<String>) -> io::Result<Self> {
+        let (size, modified) = size_and_mtime(&path)?;
+        Ok(Self {
+            kind,
+            path,
+            version,
+            size,
+            modified,
+        })
+    }
+}
+
+/// Sums file sizes and finds the newest mtime under `path`. Symlinks are not
+/// followed.
+pub(crate) fn size_and_mtime(path: &Path) -> io::Result<(u64, SystemTime)> {
+    let metadata = fs::symlink_metadata(path)?;
+    let mut size = 0;
+    let mut modified = metadata.modified()?;
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            let (s, m) = size_and_mtime(&entry?.path())?;
+            size += s;
+            modified = modified.max(m);
+        }
+    } else {
+        size = metadata.len();
+    }
+    Ok((size, modified))
+}
+
+/// Selective cache cleaning. With no filters set, everything in the selected
+/// caches is removed.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use zig_rs::cache::{Clean, Kind};
+///
+/// // Drop anything not touched in 30 days, then trim Zig's cache to 5 GiB.
+/// Clean::new()
+///     .kind(Kind::ZigGlobal)
+///     .older_than(Duration::from_secs(30 * 24 * 60 * 60))
+///     .max_size(5 << 30)
+///     .run()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Clean {
+    kinds: Vec<Kind>,
+    versions: Vec<String>,
+    older_than: Option<Duration>,
+    max_size: Option<u64>,
+    dry_run: bool,
+}
+
+/// What [`Clean::run()`] removed (or would have removed, for a dry run).
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub removed: Vec<Entry>,
+    /// Bytes freed.
+    pub freed: u64,
+    /// Bytes left in the selected caches.
+    pub remaining: u64,
+}
+
+impl Clean {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restricts cleaning to `kind`. Can be called several times; if never
+    /// called, all caches are cleaned.
+    pub fn kind(mut self, kind: Kind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    /// Only removes toolchain entries for Zig `version`. Can be called several
+    /// times. Caches without version information are left alone when a
+    /// version filter is set.
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.versions.push(version.into());
+        self
+    }
+
+    /// Only removes entries that haven't been modified for `age`.
+    pub fn older_than(mut self, age: Duration) -> Self {
+        self.older_than = Some(age);
+        self
+    }
+
+    /// Removes the least recently modified entries until the selected caches
+    /// fit in `bytes`, in addition to whatever the other filters remove.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Reports what would be removed without removing anything.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn run(&self) -> io::Result<Report> {
+        let kinds: &[Kind] = if self.kinds.is_empty() {
+            &Kind::ALL
+        } else {
+            &self.kinds
+        };
+        let mut entries = Vec::new();
+        for kind in kinds {
+            entries.extend(kind.entries()?);
+        }
+        // Oldest first, so the size budget evicts in LRU order.
+        entries.sort_by_key(|entry| entry.modified);
+
+        let now = SystemTime::now();
+        let unfiltered = self.versions.is_empty() && self.older_than.is_none();
+        let mut remaining: u64 = entries.iter().map(|entry| entry.size).sum();
+        let mut report = Report::default();
+        for entry in entries {
+            let version_ok = self.versions.is_empty()
+                || entry
+                    .version
+                    .as_ref()
+                    .is_some_and(|version| self.versions.contains(version));
+            let age_ok = self.older_than.is_none_or(|age| {
+                now.duration_since(entry.modified)
+                    .is_ok_and(|elapsed| elapsed >= age)
+            });
+            let filtered = !unfiltered && version_ok && age_ok;
+            let over_budget = self.max_size.is_some_and(|max| remaining > max);
+            let remove = if self.max_size.is_some() {
+                filtered || over_budget
+            } else {
+                unfiltered || filtered
+            };
+            if !remove {
+                continue;
+            }
+            if !self.dry_run {
+                remove_path(&entry.path)?;
+            }
+            remaining -= entry.size;
+            report.freed += entry.size;
+            report.removed.push(entry);
+        }
+        report.remaining = remaining;
+        Ok(report)
+    }
+}
+
+fn remove_path(path: &Path) -> io::Result<()> {
+    let result = if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Parses a size like `500M`, `10G`, `1.5GiB` or `4096` (bytes).
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        _ => return None,
+    };
+    Some((number * multiplier as f64) as u64)
+}
+
+/// Parses an age like `30d`, `12h`, `90m` or `45s`.
+pub fn parse_age(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let (split, unit) = s.char_indices().last()?;
+    let number: u64 = s[..split].parse().ok()?;
+    let secs = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(Duration::from_secs(number.checked_mul(secs)?))
+}
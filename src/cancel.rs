@@ -0,0 +1,220 @@
+//! Stopping long operations from another thread, for applications that
+//! embed this crate, such as IDEs and build daemons, and need to abort a
+//! download, a toolchain build or a compile without killing the thread
+//! doing it:
+//!
+//! ```no_run
+//! use zig_rs::cancel::{self, CancellationToken};
+//!
+//! let token = CancellationToken::new();
+//! let worker = std::thread::spawn({
+//!     let token = token.clone();
+//!     move || {
+//!         cancel::with_token(&token, || {
+//!             zig_rs::Build::new().file("src/foo.c").try_compile("foo")
+//!         })
+//!     }
+//! });
+//! // The user closed the project.
+//! token.cancel();
+//! if let Err(zig_rs::Error::Cancelled(cancelled)) = worker.join().unwrap() {
+//!     eprintln!("{cancelled}");
+//! }
+//! ```
+//!
+//! Under [`with_token()`], cancelling kills every child process
+//! [`process::spawn()`](crate::process::spawn) started for the operation,
+//! and the operation checks the token between units of work, e.g. every
+//! chunk of a download and before every source it compiles. It then fails
+//! with [`Cancelled`], which says how far it got: [`Error::Cancelled`] from
+//! the builders, an [`io::Error`] of kind [`Interrupted`] wrapping it
+//! elsewhere; [`is_cancelled()`] recognizes all of them. What's done is
+//! kept for the next attempt, e.g. the `.part` file of a download, which is
+//! resumed.
+//!
+//! Nothing here is tied to an async runtime. An async caller runs the
+//! operation with its runtime's `spawn_blocking` and holds a
+//! [`drop_guard()`](CancellationToken::drop_guard), so dropping the future
+//! cancels it.
+//!
+//! [`Error::Cancelled`]: crate::Error::Cancelled
+//! [`Interrupted`]: io::ErrorKind::Interrupted
+
+use std::{
+    cell::RefCell,
+    error::Error,
+    fmt, io,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crate::process;
+
+/// A handle that cancels the operations running under it; see the [module
+/// documentation](self). Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<State>);
+
+#[derive(Debug, Default)]
+struct State {
+    cancelled: AtomicBool,
+    /// Live children, by pid, and whether each has its own process group.
+    children: Mutex<Vec<(u32, bool)>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels every operation running under the token, now and later, and
+    /// kills their child processes.
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        for &(pid, grouped) in self.0.children.lock().unwrap().iter() {
+            process::kill(pid, grouped);
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// A guard that cancels the token when dropped, unless
+    /// [`disarm()`](DropGuard::disarm)ed first.
+    pub fn drop_guard(&self) -> DropGuard {
+        DropGuard(Some(self.clone()))
+    }
+
+    /// Records a child of an operation under the token, killing it right
+    /// away if the token is already cancelled.
+    pub(crate) fn add_child(&self, pid: u32, grouped: bool) {
+        let mut children = self.0.children.lock().unwrap();
+        children.push((pid, grouped));
+        if self.is_cancelled() {
+            process::kill(pid, grouped);
+        }
+    }
+
+    pub(crate) fn remove_child(&self, pid: u32) {
+        self.0
+            .children
+            .lock()
+            .unwrap()
+            .retain(|&(child, _)| child != pid);
+    }
+}
+
+/// Returned by [`CancellationToken::drop_guard()`].
+#[derive(Debug)]
+#[must_use]
+pub struct DropGuard(Option<CancellationToken>);
+
+impl DropGuard {
+    /// Keeps the token from being cancelled and returns it.
+    pub fn disarm(mut self) -> CancellationToken {
+        self.0.take().expect("armed until disarmed")
+    }
+}
+
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        if let Some(token) = &self.0 {
+            token.cancel();
+        }
+    }
+}
+
+/// The error of a cancelled operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cancelled {
+    /// How far the operation got, e.g. `3/10 objects compiled`, if it says.
+    pub progress: Option<String>,
+}
+
+impl Cancelled {
+    pub fn new() -> Self {
+        Self { progress: None }
+    }
+
+    pub fn with_progress(progress: impl Into<String>) -> Self {
+        Self {
+            progress: Some(progress.into()),
+        }
+    }
+}
+
+impl Default for Cancelled {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.progress {
+            Some(progress) => write!(f, "cancelled after {progress}"),
+            None => f.write_str("cancelled"),
+        }
+    }
+}
+
+impl Error for Cancelled {}
+
+impl From<Cancelled> for io::Error {
+    fn from(cancelled: Cancelled) -> Self {
+        io::Error::new(io::ErrorKind::Interrupted, cancelled)
+    }
+}
+
+thread_local! {
+    static TOKEN: RefCell<Option<CancellationToken>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` under `token` on this thread, and on the threads this crate
+/// starts for it.
+pub fn with_token<R>(token: &CancellationToken, f: impl FnOnce() -> R) -> R {
+    scoped(Some(token.clone()), f)
+}
+
+/// The token of the operation running on this thread, if it has one.
+pub fn current() -> Option<CancellationToken> {
+    TOKEN.with_borrow(Clone::clone)
+}
+
+/// Runs `f` under `token`, or under none; for passing the [`current()`]
+/// token on to another thread.
+pub fn scoped<R>(token: Option<CancellationToken>, f: impl FnOnce() -> R) -> R {
+    let previous = TOKEN.replace(token);
+    let result = f();
+    TOKEN.set(previous);
+    result
+}
+
+/// Fails if the operation running on this thread was cancelled.
+pub fn check() -> Result<(), Cancelled> {
+    match current() {
+        Some(token) if token.is_cancelled() => Err(Cancelled::new()),
+        _ => Ok(()),
+    }
+}
+
+/// Whether `e`, or an error it wraps, is a [`Cancelled`].
+pub fn is_cancelled(e: &(dyn Error + 'static)) -> bool {
+    let mut e = Some(e);
+    while let Some(error) = e {
+        if error.is::<Cancelled>() {
+            return true;
+        }
+        // `io::Error::source()` skips the error it wraps.
+        if let Some(io) = error.downcast_ref::<io::Error>()
+            && io.get_ref().is_some_and(|inner| inner.is::<Cancelled>())
+        {
+            return true;
+        }
+        e = error.source();
+    }
+    false
+}
@@ -0,0 +1,281 @@
+//! A ccache-like cache in front of `zig cc` and `zig c++`, for third-party
+//! build systems (`./configure` probes, Make, CMake) compiling vendored C
+//! code over and over. The `zig-cc-cache` binary runs a compile command
+//! through [`run()`]:
+//!
+//! ```sh
+//! zig-cc-cache <toolchain root> <zig version> cc -target x86_64-linux-gnu -c foo.c -o foo.o
+//! ```
+//!
+//! and the [`shims`](crate::shims) route their `cc` and `c++` wrappers
+//! through it when [`PROGRAM_ENV`] names it. Commands that compile one
+//! source to an object are looked up by a digest of the compiler, the
+//! flags other than include directories and macros, and the preprocessed
+//! source, in the same
+//! [object cache](crate::Build::object_cache) as [`Build`](crate::Build), so
+//! either finds what the other compiled. A hit copies the object into place
+//! and prints the warnings the compile printed. Everything else (links,
+//! preprocessing, several sources, response files) runs `zig` as is.
+
+use std::{
+    env,
+    ffi::OsString,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitStatus,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{Toolchain, cache, manifest, process};
+
+/// Environment variable naming the `zig-cc-cache` program, e.g. the one
+/// `cargo install zig` puts on `PATH`, for [`shims::write()`](crate::shims::write)
+/// to run `zig cc` and `zig c++` through.
+pub const PROGRAM_ENV: &str = "ZIG_RS_CC_CACHE";
+
+/// Options whose value is the next argument.
+const WITH_VALUE: &[&str] = &[
+    "-o",
+    "-MF",
+    "-MT",
+    "-MQ",
+    "-I",
+    "-D",
+    "-U",
+    "-include",
+    "-imacros",
+    "-isystem",
+    "-idirafter",
+    "-iquote",
+    "-isysroot",
+    "-iprefix",
+    "-iwithprefix",
+    "-target",
+    "-x",
+    "-Xclang",
+    "-Xpreprocessor",
+    "-Xassembler",
+    "-Xlinker",
+    "-arch",
+    "-mllvm",
+    "-L",
+    "-l",
+    "-T",
+    "-z",
+    "-u",
+    "-e",
+    "-F",
+    "-framework",
+    "--sysroot",
+];
+
+/// Options that make a command something other than a compile to an object.
+const UNCACHEABLE: &[&str] = &[
+    "-E",
+    "-S",
+    "-M",
+    "-MM",
+    "-fsyntax-only",
+    "-v",
+    "-###",
+    "--help",
+    "--version",
+    "-",
+];
+
+/// Runs `zig <compiler> <args>` with `toolchain`, `compiler` being `cc` or
+/// `c++`, from the object cache when it's a compile the cache has seen.
+pub fn run(toolchain: &Toolchain, compiler: &str, args: &[OsString]) -> io::Result<ExitStatus> {
+    let compile = parse(args).filter(|_| matches!(compiler, "cc" | "c++"));
+    let dir = cache::objects_dir().map(|dir| dir.join(toolchain.zig_version()));
+    let (Some(compile), Some(dir)) = (compile, dir) else {
+        return process::status(toolchain.command().arg(compiler).args(args));
+    };
+    let Some(key) = compile.key(toolchain, compiler)? else {
+        return process::status(toolchain.command().arg(compiler).args(args));
+    };
+    let object = dir.join(format!("{key}.o"));
+    let stderr = dir.join(format!("{key}.stderr"));
+    if object.exists() {
+        fs_err::copy(&object, &compile.output)?;
+        let _ = cache::touch(&object);
+        if let Ok(warnings) = fs_err::read(&stderr) {
+            io::stderr().write_all(&warnings)?;
+        }
+        return Ok(ExitStatus::default());
+    }
+    let output = process::output(toolchain.command().arg(compiler).args(args))?;
+    io::stdout().write_all(&output.stdout)?;
+    io::stderr().write_all(&output.stderr)?;
+    if output.status.success() {
+        // Rename into place so concurrent compiles never see half an object.
+        fs_err::create_dir_all(&dir)?;
+        if !output.stderr.is_empty() {
+            let partial = stderr.with_extension(format!("{}.partial", std::process::id()));
+            fs_err::write(&partial, &output.stderr)?;
+            fs_err::rename(&partial, &stderr)?;
+        }
+        let partial = object.with_extension(format!("{}.partial", std::process::id()));
+        fs_err::copy(&compile.output, &partial)?;
+        fs_err::rename(&partial, &object)?;
+    }
+    Ok(output.status)
+}
+
+/// Options left out of the key, followed by their value or with it
+/// attached: those that only feed the preprocessor, whose output the key
+/// already has, and path maps, which like the source path only change the
+/// paths debug info names.
+const UNKEYED: &[&str] = &[
+    "-I",
+    "-isystem",
+    "-iquote",
+    "-idirafter",
+    "-D",
+    "-U",
+    "-include",
+    "-ffile-prefix-map=",
+];
+
+/// The object cache key of compiling a source with `args`, whose
+/// preprocessed text is `preprocessed`, with `zig cc` (`cpp` false) or
+/// `zig c++`. `args` are the flags without the source, the output and the
+/// dependency file options. [Some options](UNKEYED) are left out, so the
+/// same vendored source compiled by two crates, with include directories of
+/// their own, gets the same key; the cache is per Zig version, and
+/// `-target` is kept.
+pub(crate) fn object_key(cpp: bool, args: &[String], preprocessed: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(if cpp { "c++\0" } else { "cc\0" });
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if UNKEYED.contains(&arg.as_str()) {
+            args.next();
+            continue;
+        }
+        if UNKEYED.iter().any(|option| arg.starts_with(option)) {
+            continue;
+        }
+        hasher.update(arg);
+        hasher.update("\0");
+    }
+    hasher.update(preprocessed);
+    manifest::hex(&hasher.finalize())
+}
+
+/// A command compiling one source to an object.
+#[derive(Debug)]
+struct Compile {
+    source: String,
+    output: PathBuf,
+    /// The flags that go into the key.
+    flags: Vec<String>,
+    /// `-MD`, `-MF <file>` and the like, for writing the dependency file
+    /// while preprocessing.
+    dep_args: Vec<String>,
+}
+
+/// The compile `args` describe, or `None` when they describe anything else.
+fn parse(args: &[OsString]) -> Option<Compile> {
+    let args: Vec<&str> = args.iter().map(|arg| arg.to_str()).collect::<Option<_>>()?;
+    let mut compile_only = false;
+    let mut source = None;
+    let mut output = None;
+    let mut flags = Vec::new();
+    let mut dep_args = Vec::new();
+    let mut dep_target = false;
+    let mut dep_file = false;
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i];
+        i += 1;
+        if UNCACHEABLE.contains(&arg) || arg.starts_with('@') {
+            return None;
+        }
+        if WITH_VALUE.contains(&arg) {
+            let value = *args.get(i)?;
+            i += 1;
+            match arg {
+                "-o" => output = Some(value),
+                "-MF" => {
+                    dep_file = true;
+                    dep_args.extend([arg.to_owned(), value.to_owned()]);
+                }
+                "-MT" | "-MQ" => {
+                    dep_target = true;
+                    dep_args.extend([arg.to_owned(), value.to_owned()]);
+                }
+                _ => flags.extend([arg.to_owned(), value.to_owned()]),
+            }
+            continue;
+        }
+        match arg {
+            "-c" => compile_only = true,
+            "-MD" | "-MMD" | "-MP" => dep_args.push(arg.to_owned()),
+            arg if arg.starts_with("-o") => output = Some(&arg[2..]),
+            arg if arg.starts_with('-') => flags.push(arg.to_owned()),
+            arg if source.is_none() => source = Some(arg),
+            // Several sources.
+            _ => return None,
+        }
+    }
+    let source = source?;
+    if !compile_only {
+        return None;
+    }
+    let output = match output {
+        Some(output) => PathBuf::from(output),
+        None => Path::new(source).with_extension("o").file_name()?.into(),
+    };
+    if dep_args.iter().any(|arg| arg == "-MD" || arg == "-MMD") {
+        if !dep_file {
+            dep_args.extend([
+                "-MF".into(),
+                output.with_extension("d").display().to_string(),
+            ]);
+        }
+        if !dep_target {
+            dep_args.extend(["-MT".into(), output.display().to_string()]);
+        }
+    }
+    Some(Compile {
+        source: source.to_owned(),
+        output,
+        flags,
+        dep_args,
+    })
+}
+
+impl Compile {
+    /// Preprocesses the source, writing the dependency file if one was asked
+    /// for, and returns the key. `None` when it doesn't preprocess, leaving
+    /// the error to the real compile.
+    fn key(&self, toolchain: &Toolchain, compiler: &str) -> io::Result<Option<String>> {
+        let output = process::output(
+            toolchain
+                .command()
+                .arg(compiler)
+                .args(&self.flags)
+                .args(&self.dep_args)
+                .arg("-E")
+                .arg("-P")
+                .arg(&self.source),
+        )?;
+        if !output.status.success() {
+            return Ok(None);
+        }
+        let mut flags = self.flags.clone();
+        // Debug info records the directory it was compiled in.
+        if flags
+            .iter()
+            .any(|flag| flag.starts_with("-g") && flag != "-g0")
+        {
+            flags.push(format!(
+                "-fdebug-compilation-dir={}",
+                env::current_dir()?.display()
+            ));
+        }
+        Ok(Some(object_key(compiler == "c++", &flags, &output.stdout)))
+    }
+}
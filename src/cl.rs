@@ -0,0 +1,206 @@
+//! Translation of `cl.exe` command lines into `zig cc` ones, so tools that
+//! drive a C compiler in MSVC mode (cc-rs does for `*-pc-windows-msvc`) can
+//! run Zig in its place when cross compiling:
+//!
+//! ```
+//! let args = zig_rs::cl::translate(["-nologo", "-MD", "-O2", "-Iinclude", "-DFOO#1", "-c", "foo.c", "-Fofoo.o"]);
+//! assert_eq!(args, ["-D_MT", "-D_DLL", "-O2", "-Iinclude", "-DFOO=1", "-c", "foo.c", "-ofoo.o"]);
+//! ```
+//!
+//! Options may start with `-` or `/`. Since `/` also starts absolute paths on
+//! Unix hosts, a `/` argument naming an existing file is taken as an input.
+//! Options Zig has no equivalent for (`/nologo`, `/FS`, `/bigobj`, MSVC warning
+//! numbers, ...) are dropped, and arguments that aren't `cl.exe` options pass
+//! through unchanged.
+//!
+//! The `zig-cl` program `cargo install zig` installs runs [`run()`]. With
+//! [`PROGRAM_ENV`] naming it, the `cc` and `c++` [`shims`](crate::shims) of
+//! `*-msvc` targets go through it, so they take both kinds of command line.
+
+use std::{env, ffi::OsString, io, path::Path, process::ExitStatus};
+
+use crate::{Toolchain, cc_cache, process};
+
+/// Environment variable naming the `zig-cl` program, e.g. the one
+/// `cargo install zig` puts on `PATH`, for
+/// [`shims::write()`](crate::shims::write) to run the `cc` and `c++`
+/// wrappers of `*-msvc` targets through.
+pub const PROGRAM_ENV: &str = "ZIG_RS_CL";
+
+/// Runs `zig cc` or `zig c++` (`compiler`) of `toolchain` for `zig_target`
+/// with `args`, [translated](translate) when they are a `cl.exe` command
+/// line, and through [`cc_cache::run()`] when [`cc_cache::PROGRAM_ENV`] is
+/// set.
+pub fn run(
+    toolchain: &Toolchain,
+    compiler: &str,
+    zig_target: &str,
+    args: &[OsString],
+) -> io::Result<ExitStatus> {
+    let mut zig_args: Vec<OsString> = vec!["-target".into(), zig_target.into()];
+    let strings: Option<Vec<&str>> = args.iter().map(|arg| arg.to_str()).collect();
+    match strings {
+        Some(strings) if is_cl_command_line(&strings) => {
+            zig_args.extend(translate(strings).into_iter().map(OsString::from));
+        }
+        _ => zig_args.extend(args.iter().cloned()),
+    }
+    if env::var_os(cc_cache::PROGRAM_ENV).is_some() {
+        return cc_cache::run(toolchain, compiler, &zig_args);
+    }
+    process::status(toolchain.command().arg(compiler).args(&zig_args))
+}
+
+/// Whether `args` are a `cl.exe` command line rather than a `zig cc` one:
+/// whether there is `/nologo` or an `/Fo` or `/Fe` output (spelled with `-`
+/// or `/`), which cc-rs, CMake and Meson pass `cl.exe` and `zig cc` never
+/// takes. A `zig cc` command line can't be translated, since options like
+/// `-MD` and `-Wall` mean something else to `cl.exe`.
+///
+/// ```
+/// use zig_rs::cl::is_cl_command_line;
+///
+/// assert!(is_cl_command_line(&["-nologo", "-MD", "-c", "foo.c", "-Fofoo.o"]));
+/// assert!(is_cl_command_line(&["/c", "foo.c", "/Fo:foo.obj"]));
+/// assert!(!is_cl_command_line(&["-MD", "-Wall", "-c", "foo.c", "-o", "foo.o"]));
+/// ```
+pub fn is_cl_command_line<S: AsRef<str>>(args: &[S]) -> bool {
+    args.iter().map(AsRef::as_ref).any(|arg| {
+        let Some(option) = arg.strip_prefix(['-', '/']) else {
+            return false;
+        };
+        // An absolute path on a Unix host.
+        (arg.starts_with('-') || !Path::new(arg).exists())
+            && (option == "nologo" || option.starts_with("Fo") || option.starts_with("Fe"))
+    })
+}
+
+/// Translates `cl.exe` arguments into `zig cc` arguments. The target is not
+/// part of a `cl.exe` command line; callers add `-target`.
+pub fn translate<S: AsRef<str>>(args: impl IntoIterator<Item = S>) -> Vec<String> {
+    let args: Vec<String> = args.into_iter().map(|a| a.as_ref().to_owned()).collect();
+    let mut out = Vec::new();
+    let mut inputs = Vec::new();
+    let mut object_dir = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let option = match arg.strip_prefix('-') {
+            Some(option) => option,
+            None => match arg.strip_prefix('/') {
+                Some(option) if !Path::new(arg).exists() => option,
+                _ => {
+                    inputs.push(arg.clone());
+                    out.push(arg.clone());
+                    continue;
+                }
+            },
+        };
+        let mut value = |prefix: &str| value(option, prefix, &mut iter);
+        match option {
+            "nologo" | "FS" | "bigobj" | "Brepro" | "GR" | "GS" | "Gm-" | "Oi" | "utf-8"
+            | "permissive-" | "showIncludes" | "errorReport:none" => {}
+            "c" => out.push("-c".into()),
+            "E" => out.push("-E".into()),
+            "EP" => out.extend(["-E".into(), "-P".into()]),
+            "MD" => out.extend(["-D_MT".into(), "-D_DLL".into()]),
+            "MDd" => out.extend(["-D_MT".into(), "-D_DLL".into(), "-D_DEBUG".into()]),
+            "MT" => out.push("-D_MT".into()),
+            "MTd" => out.extend(["-D_MT".into(), "-D_DEBUG".into()]),
+            "Od" => out.push("-O0".into()),
+            "O1" | "Os" => out.push("-Os".into()),
+            "O2" | "Ox" | "Ot" => out.push("-O2".into()),
+            "Oy-" => out.push("-fno-omit-frame-pointer".into()),
+            "Ob0" => out.push("-fno-inline".into()),
+            "Z7" | "Zi" | "ZI" => out.push("-g".into()),
+            "W0" | "w" => out.push("-w".into()),
+            "W1" | "W2" | "W3" => out.push("-Wall".into()),
+            "W4" => out.extend(["-Wall".into(), "-Wextra".into()]),
+            "Wall" => out.push("-Weverything".into()),
+            "WX" => out.push("-Werror".into()),
+            "EHsc" | "EHs" | "EHa" => out.push("-fexceptions".into()),
+            "EHs-" | "EHs-c-" => out.push("-fno-exceptions".into()),
+            "GR-" => out.push("-fno-rtti".into()),
+            "GS-" => out.push("-fno-stack-protector".into()),
+            "Gy" => out.push("-ffunction-sections".into()),
+            "Gw" => out.push("-fdata-sections".into()),
+            "TC" => out.extend(["-x".into(), "c".into()]),
+            "TP" => out.extend(["-x".into(), "c++".into()]),
+            "arch:AVX" => out.push("-mavx".into()),
+            "arch:AVX2" => out.push("-mavx2".into()),
+            "arch:AVX512" => out.extend(["-mavx512f".into(), "-mavx512bw".into()]),
+            "arch:SSE" | "arch:SSE2" | "arch:IA32" => {}
+            "link" => {
+                out.extend(iter.by_ref().map(|arg| format!("-Wl,{arg}")));
+            }
+            _ if option.starts_with("Zc:")
+                || option.starts_with("diagnostics:")
+                || option.starts_with("favor:")
+                || option.starts_with("wd")
+                || option.starts_with("we")
+                || option.starts_with("wo") => {}
+            _ if option.starts_with("std:") => {
+                let std = &option["std:".len()..];
+                out.push(format!(
+                    "-std={}",
+                    match std {
+                        "c++latest" => "c++2c",
+                        "clatest" => "c2x",
+                        std => std,
+                    }
+                ));
+            }
+            _ => {
+                if let Some(dir) = value("I") {
+                    out.push(format!("-I{dir}"));
+                } else if let Some(define) = value("D") {
+                    // `/DNAME#VALUE` is `cl.exe` for `NAME=VALUE`.
+                    if define.contains('=') {
+                        out.push(format!("-D{define}"));
+                    } else {
+                        out.push(format!("-D{}", define.replacen('#', "=", 1)));
+                    }
+                } else if let Some(name) = value("U") {
+                    out.push(format!("-U{name}"));
+                } else if let Some(file) = value("FI") {
+                    out.extend(["-include".into(), file]);
+                } else if let Some(file) = value("Tc") {
+                    out.extend(["-x".into(), "c".into(), file.clone()]);
+                    inputs.push(file);
+                } else if let Some(file) = value("Tp") {
+                    out.extend(["-x".into(), "c++".into(), file.clone()]);
+                    inputs.push(file);
+                } else if let Some(path) = value("Fo").or_else(|| value("Fe")) {
+                    // A trailing separator names a directory for the objects.
+                    if path.ends_with(['/', '\\']) {
+                        object_dir = Some(path);
+                    } else {
+                        out.push(format!("-o{path}"));
+                    }
+                } else {
+                    out.push(arg.clone());
+                }
+            }
+        }
+    }
+    if let (Some(dir), [input]) = (object_dir, &inputs[..]) {
+        let stem = Path::new(input).file_stem().unwrap_or_default();
+        out.push(format!("-o{dir}{}.obj", stem.to_string_lossy()));
+    }
+    out
+}
+
+/// The value of `option` if it is `prefix` with a value attached (`/Idir`,
+/// `/Fo:path`) or following as the next argument (`/I dir`).
+fn value<'a>(
+    option: &str,
+    prefix: &str,
+    rest: &mut impl Iterator<Item = &'a String>,
+) -> Option<String> {
+    let attached = option.strip_prefix(prefix)?;
+    let attached = attached.strip_prefix(':').unwrap_or(attached);
+    Some(if attached.is_empty() {
+        rest.next().cloned().unwrap_or_default()
+    } else {
+        attached.to_owned()
+    })
+}
@@ -0,0 +1,2323 @@
+//! Compiling C, C++, assembly and Zig sources into a static library from a
+//! Cargo build script with `zig cc`, in the style of the `cc` crate:
+//!
+//! ```no_run
+//! // build.rs
+//! zig_rs::Build::new()
+//!     .file("src/foo.c")
+//!     .include("include")
+//!     .define("FOO_FAST", None)
+//!     .flag_if_supported("-fno-plt")
+//!     .compile("foo");
+//! ```
+//!
+//! Zig provides the cross compiler, the libc headers and the archiver, so the
+//! same build script works for every target Zig supports without any system C
+//! toolchain.
+//!
+//! A `.zig` file is the root of a Zig module and compiles with
+//! `zig build-obj` into an object of the same library, for the same target:
+//!
+//! ```no_run
+//! // build.rs
+//! zig_rs::Build::new()
+//!     .file("src/foo.zig")
+//!     .optimize("ReleaseSafe")
+//!     .compile("foo");
+//! ```
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::{
+    Error, Toolchain, bindings, cache,
+    cancel::{self, Cancelled},
+    cc_cache,
+    config::Config,
+    diagnostic::{self, Diagnostic, Severity},
+    error::CommandError,
+    hardening::Hardening,
+    jobs, link_map,
+    log::{self, Event, Tagger},
+    naming::SharedLibrary,
+    process::{self, Limits},
+    reproducible,
+    runtime::{self, CxxStdlib, Runtime},
+    sign::{self, Signature, Signer},
+    symbols, target,
+    target_features::{self, FeaturePolicy},
+    zig_build, zig_imports,
+};
+
+mod fingerprint;
+
+use fingerprint::Fingerprint;
+
+/// What a successful [`Build::try_compile()`] produced.
+#[derive(Debug, Clone)]
+pub struct Artifacts {
+    pub lib: PathBuf,
+    pub objects: Vec<PathBuf>,
+    /// Flags for the final link, already printed as `cargo:rustc-link-arg`
+    /// unless cargo metadata is off.
+    pub link_args: Vec<String>,
+    /// Flags for linking `cdylib`s only, printed as
+    /// `cargo:rustc-cdylib-link-arg`.
+    pub cdylib_link_args: Vec<String>,
+    /// Set by [`Build::optimize_size()`].
+    pub size_report: Option<SizeReport>,
+    /// Warnings and notes printed along the way, the repeats across
+    /// sources [grouped](diagnostic::group).
+    pub diagnostics: Vec<Diagnostic>,
+    /// Every file the compiler read: the sources and the headers they
+    /// include, except system headers. Printed as `cargo:rerun-if-changed`.
+    pub inputs: Vec<PathBuf>,
+    /// Whether the library's fingerprint still matched and nothing was
+    /// compiled.
+    pub up_to_date: bool,
+    /// Whether [`lib`](Self::lib) is a thin archive; see
+    /// [`Build::thin_archive()`].
+    pub thin: bool,
+    /// Copies of the Zig runtime archives [`Build::runtime()`] selected,
+    /// linked after [`lib`](Self::lib).
+    pub runtime_libs: Vec<PathBuf>,
+    /// What compiling each source took, in the order of the sources. Empty
+    /// when the library was up to date.
+    pub units: Vec<UnitStats>,
+    /// `bindings.rs` and `<name>.h` in the output directory, set by
+    /// [`Build::emit_bindings()`].
+    pub bindings: Option<(PathBuf, PathBuf)>,
+    /// The signature of [`lib`](Self::lib), with [`Build::signer()`].
+    pub signature: Option<Signature>,
+    /// The library [`Build::shared_library()`] linked, next to
+    /// [`lib`](Self::lib).
+    pub shared_lib: Option<PathBuf>,
+}
+
+/// The cost of compiling one source file; see [`Artifacts::units`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnitStats {
+    pub file: PathBuf,
+    /// Wall time and peak memory of the compiler. `None` when the object
+    /// came from the object cache or was still up to date from the last
+    /// build.
+    pub usage: Option<process::Usage>,
+}
+
+/// A table of the `count` slowest `units`, slowest first, with their wall
+/// time and peak memory, and a line with the totals.
+pub fn unit_stats_summary(units: &[UnitStats], count: usize) -> String {
+    let mut compiled: Vec<(&UnitStats, process::Usage)> = units
+        .iter()
+        .filter_map(|unit| Some((unit, unit.usage?)))
+        .collect();
+    compiled.sort_by_key(|&(_, usage)| std::cmp::Reverse(usage.wall_time));
+    let mut summary = String::new();
+    for (unit, usage) in compiled.iter().take(count) {
+        let memory = match usage.peak_memory {
+            Some(bytes) => format!("{:.0} MiB", bytes as f64 / (1024.0 * 1024.0)),
+            None => "?".into(),
+        };
+        summary += &format!(
+            "{:>8.2}s {memory:>9}  {}\n",
+            usage.wall_time.as_secs_f64(),
+            unit.file.display()
+        );
+    }
+    let total: Duration = compiled.iter().map(|(_, usage)| usage.wall_time).sum();
+    summary += &format!(
+        "{} compiled in {:.2}s in total, {} reused\n",
+        compiled.len(),
+        total.as_secs_f64(),
+        units.len() - compiled.len()
+    );
+    summary
+}
+
+/// The result of compiling one source file.
+struct Compiled {
+    diagnostics: Vec<Diagnostic>,
+    inputs: Vec<PathBuf>,
+    usage: Option<process::Usage>,
+}
+
+/// The PE subsystem a Windows or UEFI binary runs under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    /// Gets a console window, the default for Windows.
+    Console,
+    /// A GUI application without a console. Rust binaries also need
+    /// `#![windows_subsystem = "windows"]` or an explicit entry point.
+    Windows,
+    /// A UEFI application such as a bootloader, unloaded when it returns. The
+    /// default for UEFI targets.
+    EfiApplication,
+    /// A UEFI driver that stays loaded until `ExitBootServices()`.
+    EfiBootServiceDriver,
+    /// A UEFI driver that stays loaded while the OS runs.
+    EfiRuntimeDriver,
+}
+
+impl Subsystem {
+    /// The name linkers and `zig build-exe --subsystem` take.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Console => "console",
+            Self::Windows => "windows",
+            Self::EfiApplication => "efi_application",
+            Self::EfiBootServiceDriver => "efi_boot_service_driver",
+            Self::EfiRuntimeDriver => "efi_runtime_driver",
+        }
+    }
+}
+
+/// A hardening or loader keyword ELF linkers take as `-z <keyword>`, with
+/// its closest equivalent on other targets; see [`Build::z_option()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZOption {
+    /// `-z relro`: makes relocated data read-only after startup. Apple
+    /// linkers always do this for `__DATA_CONST`.
+    Relro,
+    /// `-z now`: resolves every symbol at load time. `-bind_at_load` on
+    /// Apple targets.
+    Now,
+    /// `-z noexecstack`: a non-executable stack. The default on Apple
+    /// targets, `/NXCOMPAT` or `--nxcompat` on Windows.
+    NoExecStack,
+    /// `-z origin`: lets `$ORIGIN` in rpaths name the binary's directory.
+    /// Apple's `@loader_path` needs no flag.
+    Origin,
+}
+
+impl ZOption {
+    fn keyword(self) -> &'static str {
+        match self {
+            Self::Relro => "relro",
+            Self::Now => "now",
+            Self::NoExecStack => "noexecstack",
+            Self::Origin => "origin",
+        }
+    }
+}
+
+/// Library sizes in bytes before and after [`Build::optimize_size()`]. Both
+/// are static archives, so they include code the final link may still drop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeReport {
+    pub before: u64,
+    pub after: u64,
+}
+
+/// Environment variable that turns on [`Build::object_cache()`] for builds that
+/// don't set it.
+pub const OBJECT_CACHE_ENV: &str = "ZIG_RS_OBJECT_CACHE";
+
+/// Variables besides Cargo's that change where the compiler's results come
+/// from, printed as `cargo:rerun-if-env-changed` along with the ones the
+/// builder reads itself ([`OBJECT_CACHE_ENV`],
+/// [`GLIBC_VERSION_ENV`](target::GLIBC_VERSION_ENV) and
+/// [`CONFIG_ENV`](crate::config::CONFIG_ENV)).
+const TRACKED_ENV: &[&str] = &[
+    cache::DIR_ENV,
+    sign::ENV,
+    target::TARGET_MAP_ENV,
+    cache::SHARED_ZIG_CACHE_ENV,
+    "ZIG_LOCAL_CACHE_DIR",
+    "ZIG_GLOBAL_CACHE_DIR",
+];
+
+/// How many units [`Build::print_unit_stats()`] lists.
+const UNIT_STATS_SHOWN: usize = 10;
+
+/// `(zig binary, Zig target, flag)` of a cached flag probe.
+type ProbeKey = (PathBuf, String, String);
+
+/// A source transform added with [`Build::transform()`].
+#[derive(Clone)]
+struct Transform(Arc<TransformFn>);
+
+type TransformFn = dyn Fn(&Path, &str) -> String + Send + Sync;
+
+impl fmt::Debug for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Transform(..)")
+    }
+}
+
+/// Builds a static library from C, C++ and assembly sources.
+#[derive(Debug, Clone)]
+pub struct Build {
+    files: Vec<PathBuf>,
+    transforms: Vec<Transform>,
+    includes: Vec<PathBuf>,
+    defines: Vec<(String, Option<String>)>,
+    flags: Vec<String>,
+    file_flags: Vec<(PathBuf, String)>,
+    third_party: Vec<(PathBuf, bool)>,
+    flags_if_supported: Vec<String>,
+    warnings_into_errors: bool,
+    suppressed_warnings: Vec<String>,
+    notes: bool,
+    cargo_warnings: bool,
+    print_unit_stats: bool,
+    target: Option<String>,
+    glibc_version: Option<String>,
+    mcpu: Option<String>,
+    target_features: FeaturePolicy,
+    opt_level: Option<String>,
+    optimize: Option<String>,
+    debug: Option<bool>,
+    pic: Option<bool>,
+    single_threaded: bool,
+    wasm_threads: Option<bool>,
+    hardening: Option<Hardening>,
+    runtime: Option<Runtime>,
+    optimize_size: bool,
+    link_map: Option<PathBuf>,
+    thin_archive: bool,
+    object_cache: Option<bool>,
+    emit_bindings: bool,
+    shared_library: Option<SharedLibrary>,
+    frameworks: Vec<(String, bool)>,
+    framework_dirs: Vec<PathBuf>,
+    sysroot: Option<PathBuf>,
+    subsystem: Option<Subsystem>,
+    windows_manifest: Option<PathBuf>,
+    stack_size: Option<u64>,
+    version_script: Option<PathBuf>,
+    exported_symbols: Vec<String>,
+    allowed_symbols: Vec<String>,
+    def_file: Option<PathBuf>,
+    dynamic_linker: Option<PathBuf>,
+    allow_shlib_undefined: Option<bool>,
+    no_undefined: bool,
+    link_args: Vec<String>,
+    z_options: Vec<ZOption>,
+    linker_args: Vec<String>,
+    out_dir: Option<PathBuf>,
+    cargo_metadata: bool,
+    signer: Option<Signer>,
+    limits: Limits,
+    toolchain: Toolchain,
+}
+
+impl Default for Build {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Build {
+    pub fn new() -> Self {
+        Self {
+            files: Vec::new(),
+            transforms: Vec::new(),
+            includes: Vec::new(),
+            defines: Vec::new(),
+            flags: Vec::new(),
+            file_flags: Vec::new(),
+            third_party: Vec::new(),
+            flags_if_supported: Vec::new(),
+            warnings_into_errors: false,
+            suppressed_warnings: Vec::new(),
+            notes: true,
+            cargo_warnings: false,
+            print_unit_stats: false,
+            target: None,
+            glibc_version: None,
+            mcpu: None,
+            target_features: FeaturePolicy::Ignore,
+            opt_level: None,
+            optimize: None,
+            debug: None,
+            pic: None,
+            single_threaded: false,
+            wasm_threads: None,
+            hardening: None,
+            runtime: None,
+            optimize_size: false,
+            link_map: None,
+            thin_archive: false,
+            object_cache: None,
+            emit_bindings: false,
+            shared_library: None,
+            frameworks: Vec::new(),
+            framework_dirs: Vec::new(),
+            sysroot: None,
+            subsystem: None,
+            windows_manifest: None,
+            stack_size: None,
+            version_script: None,
+            exported_symbols: Vec::new(),
+            allowed_symbols: Vec::new(),
+            def_file: None,
+            dynamic_linker: None,
+            allow_shlib_undefined: None,
+            no_undefined: false,
+            link_args: Vec::new(),
+            z_options: Vec::new(),
+            linker_args: Vec::new(),
+            out_dir: None,
+            cargo_metadata: true,
+            signer: None,
+            limits: Limits::default(),
+            toolchain: Toolchain::bundled(),
+        }
+    }
+
+    /// Adds a source file. `.cc`, `.cpp`, `.cxx` and `.c++` files are compiled
+    /// as C++, `.zig` files with `zig build-obj` as the root of a Zig module,
+    /// everything else with `zig cc` as C or assembly.
+    pub fn file(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.files.push(path.as_ref().into());
+        self
+    }
+
+    pub fn files<P: AsRef<Path>>(&mut self, paths: impl IntoIterator<Item = P>) -> &mut Self {
+        for path in paths {
+            self.file(path);
+        }
+        self
+    }
+
+    /// Runs `transform` on the contents of every source before it's
+    /// compiled, e.g. to inject a version string or rewrite the include
+    /// paths of vendored code; it gets the source's path and contents and
+    /// returns the new contents. Transforms run in the order they're added.
+    ///
+    /// Sources a transform changes are written to `<out dir>/<name>-src/`
+    /// and compiled from there, so the fingerprint and the
+    /// [object cache](Self::object_cache) see the new contents, and
+    /// `#include "..."` still finds headers next to the original. Relative
+    /// `@import`s of a Zig source resolve next to the copy, so only change
+    /// Zig roots that import nothing but modules on the module path. Sources
+    /// that aren't UTF-8 are left alone.
+    ///
+    /// ```no_run
+    /// zig_rs::Build::new()
+    ///     .file("vendor/foo/version.c")
+    ///     .transform(|_, source| {
+    ///         source.replace("@VERSION@", env!("CARGO_PKG_VERSION"))
+    ///     })
+    ///     .compile("foo");
+    /// ```
+    pub fn transform(
+        &mut self,
+        transform: impl Fn(&Path, &str) -> String + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.transforms.push(Transform(Arc::new(transform)));
+        self
+    }
+
+    pub fn include(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.includes.push(dir.as_ref().into());
+        self
+    }
+
+    /// Defines a preprocessor macro, `-D<name>` or `-D<name>=<value>`.
+    pub fn define<'a>(&mut self, name: &str, value: impl Into<Option<&'a str>>) -> &mut Self {
+        self.defines
+            .push((name.into(), value.into().map(String::from)));
+        self
+    }
+
+    /// Passes `flag` to every compile.
+    pub fn flag(&mut self, flag: &str) -> &mut Self {
+        self.flags.push(flag.into());
+        self
+    }
+
+    /// Passes `flag` only when compiling `path`, as given to
+    /// [`file()`](Self::file). It comes after the flags every file gets, so
+    /// it wins over them: `-O3` for one hot file, or
+    /// `-fno-sanitize=undefined` for a third-party one.
+    pub fn file_flag(&mut self, path: impl AsRef<Path>, flag: &str) -> &mut Self {
+        self.file_flags.push((path.as_ref().into(), flag.into()));
+        self
+    }
+
+    /// Marks the sources and include directories at or under `path`, a file
+    /// or directory as given to [`file()`](Self::file) and
+    /// [`include()`](Self::include), as third-party code, or with `false`
+    /// as the project's own again. Third-party C and C++ sources are
+    /// compiled with `-w`, their include directories are searched with
+    /// `-isystem` so their headers don't warn in the project's sources
+    /// either, and warnings and notes in their files are dropped from the
+    /// parsed results and [`cargo_warnings()`](Self::cargo_warnings). Errors
+    /// still fail the build. The closest path marked wins, so a vendored
+    /// tree can keep a directory of local patches strict:
+    ///
+    /// ```no_run
+    /// zig_rs::Build::new()
+    ///     .files(["vendor/sqlite/sqlite3.c", "vendor/patches/vfs.c", "src/glue.c"])
+    ///     .include("vendor/sqlite")
+    ///     .third_party("vendor", true)
+    ///     .third_party("vendor/patches", false)
+    ///     .warnings_into_errors(true)
+    ///     .cargo_warnings(true)
+    ///     .compile("glue");
+    /// ```
+    pub fn third_party(&mut self, path: impl AsRef<Path>, third_party: bool) -> &mut Self {
+        self.third_party.push((path.as_ref().into(), third_party));
+        self
+    }
+
+    /// Passes `flag` to every compile if the toolchain accepts it for the
+    /// target, so build scripts keep working across the Zig versions users
+    /// pin. See [`is_flag_supported()`](Self::is_flag_supported).
+    pub fn flag_if_supported(&mut self, flag: &str) -> &mut Self {
+        self.flags_if_supported.push(flag.into());
+        self
+    }
+
+    /// Turns warnings into errors (`-Werror`). Warnings silenced with
+    /// [`suppress_warning()`](Self::suppress_warning) stay silent.
+    pub fn warnings_into_errors(&mut self, warnings_into_errors: bool) -> &mut Self {
+        self.warnings_into_errors = warnings_into_errors;
+        self
+    }
+
+    /// Silences a clang warning category, e.g. `"unused-parameter"` for
+    /// `-Wno-unused-parameter`, and drops any such diagnostics from the
+    /// parsed results. Useful for noisy vendored code.
+    pub fn suppress_warning(&mut self, name: &str) -> &mut Self {
+        self.suppressed_warnings
+            .push(name.trim_start_matches("-W").to_owned());
+        self
+    }
+
+    /// Whether `note:` diagnostics are kept in the parsed results. On by
+    /// default; Zig in particular attaches several notes to most errors.
+    pub fn notes(&mut self, notes: bool) -> &mut Self {
+        self.notes = notes;
+        self
+    }
+
+    /// Prints the warnings of the compiles as `cargo:warning`s, so Cargo
+    /// shows them without `-vv`. A warning from a header many sources
+    /// include is printed once, with how often it came up. Off by default.
+    pub fn cargo_warnings(&mut self, cargo_warnings: bool) -> &mut Self {
+        self.cargo_warnings = cargo_warnings;
+        self
+    }
+
+    /// Prints the slowest sources with their compile time and peak memory
+    /// (see [`unit_stats_summary()`]) after compiling, to find translation
+    /// units worth splitting. Off by default; [`Artifacts::units`] has the
+    /// numbers either way.
+    pub fn print_unit_stats(&mut self, print: bool) -> &mut Self {
+        self.print_unit_stats = print;
+        self
+    }
+
+    /// The rustc target to compile for. Defaults to `TARGET`.
+    pub fn target(&mut self, target: &str) -> &mut Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Links against glibc `version` (`2.17`) instead of Zig's default for
+    /// `-gnu` Linux targets, like a `.2.17` suffix on the target. Defaults to
+    /// [`GLIBC_VERSION_ENV`](target::GLIBC_VERSION_ENV). Checked against the
+    /// versions `zig targets` lists before anything compiles.
+    pub fn glibc_version(&mut self, version: &str) -> &mut Self {
+        self.glibc_version = Some(version.into());
+        self
+    }
+
+    /// The Zig CPU model and features to compile for, e.g. `x86_64_v3` or
+    /// `cortex_a72+crypto`. Defaults to the target's entry in the project's
+    /// [`config`](crate::config), or Zig's baseline for the target.
+    pub fn mcpu(&mut self, mcpu: &str) -> &mut Self {
+        self.mcpu = Some(mcpu.into());
+        self
+    }
+
+    /// What to do when the CPU features of the `-mcpu` differ from the ones
+    /// Cargo enabled for the Rust target; see
+    /// [`target_features`](crate::target_features). Ignored by default, and
+    /// for targets other than Cargo's `TARGET`.
+    pub fn target_features(&mut self, policy: FeaturePolicy) -> &mut Self {
+        self.target_features = policy;
+        self
+    }
+
+    /// `0`–`3`, `s` or `z`. Defaults to `OPT_LEVEL`.
+    pub fn opt_level(&mut self, opt_level: &str) -> &mut Self {
+        self.opt_level = Some(opt_level.into());
+        self
+    }
+
+    /// Zig's optimize mode for `.zig` sources, e.g. `ReleaseSafe`. Defaults
+    /// to `ReleaseSmall` with [`optimize_size()`](Self::optimize_size) and
+    /// otherwise to the mode matching [`opt_level()`](Self::opt_level), as
+    /// [`zig_build::optimize_for_profile()`] picks it.
+    pub fn optimize(&mut self, mode: &str) -> &mut Self {
+        self.optimize = Some(mode.into());
+        self
+    }
+
+    /// Whether to emit debug info. Defaults to `DEBUG`.
+    pub fn debug(&mut self, debug: bool) -> &mut Self {
+        self.debug = Some(debug);
+        self
+    }
+
+    /// Whether to compile position-independent code. Defaults to on for every
+    /// target except Windows and freestanding ones.
+    pub fn pic(&mut self, pic: bool) -> &mut Self {
+        self.pic = Some(pic);
+        self
+    }
+
+    /// Compiles for a single-threaded environment. Clang only has such a
+    /// mode for wasm (`-mthread-model single`), where it also turns off
+    /// [`wasm_threads()`](Self::wasm_threads); elsewhere this does nothing.
+    pub fn single_threaded(&mut self, single_threaded: bool) -> &mut Self {
+        self.single_threaded = single_threaded;
+        self
+    }
+
+    /// Enables atomics, bulk memory and `-pthread` on wasm targets. Defaults to
+    /// [`target::wasm_threads_default()`], so the C code agrees with how the
+    /// Rust code is built. Ignored for other targets.
+    pub fn wasm_threads(&mut self, wasm_threads: bool) -> &mut Self {
+        self.wasm_threads = Some(wasm_threads);
+        self
+    }
+
+    /// Applies a hardening preset: its compiler flags to every compile and its
+    /// linker flags to the final link.
+    pub fn hardening(&mut self, hardening: Hardening) -> &mut Self {
+        self.hardening = Some(hardening);
+        self
+    }
+
+    /// Chooses which of Zig's compiler-rt, libunwind and libc++ are linked
+    /// with the library. Defaults to [`Runtime::for_target()`], which leaves
+    /// the runtime to Rust's where it has one and otherwise links Zig's
+    /// libc++ for C++ sources.
+    pub fn runtime(&mut self, runtime: Runtime) -> &mut Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    /// Optimizes for size: `-Oz`, no debug info, and every function and
+    /// object in its own section (`-ffunction-sections -fdata-sections`) so
+    /// the final link's section GC (`--gc-sections`, `-dead_strip` or
+    /// `/OPT:REF`, also requested) can drop whatever is unused. The final
+    /// link also strips the binary (`--strip-all`, or `-S -x` on Apple),
+    /// except with MSVC's linker, where symbols go to the PDB. As this is
+    /// usually tuned by measuring, [`Artifacts::size_report`] compares the
+    /// library against one built with the profile's usual settings, which
+    /// costs a second compile.
+    pub fn optimize_size(&mut self) -> &mut Self {
+        self.optimize_size = true;
+        self
+    }
+
+    /// Links an Apple framework, e.g. `"CoreFoundation"`. Ignored for other
+    /// targets.
+    pub fn framework(&mut self, name: &str) -> &mut Self {
+        self.frameworks.push((name.into(), false));
+        self
+    }
+
+    /// Links an Apple framework weakly (`-weak_framework`), so the binary
+    /// still loads on OS versions that lack it. Its symbols must then be
+    /// checked for null before use. Ignored for other targets.
+    pub fn weak_framework(&mut self, name: &str) -> &mut Self {
+        self.frameworks.push((name.into(), true));
+        self
+    }
+
+    /// Adds a framework search directory (`-F`) for both headers and linking.
+    /// Ignored for non-Apple targets.
+    pub fn framework_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.framework_dirs.push(dir.as_ref().into());
+        self
+    }
+
+    /// Selects the PE subsystem of the binaries the library is linked into,
+    /// for Windows and UEFI targets. Rust's UEFI targets already link EFI
+    /// applications, so drivers are what those need this for. A subsystem of
+    /// the other kind fails the build; other targets ignore it.
+    pub fn windows_subsystem(&mut self, subsystem: Subsystem) -> &mut Self {
+        self.subsystem = Some(subsystem);
+        self
+    }
+
+    /// Embeds an application manifest (for DPI awareness, UAC levels, common
+    /// controls v6, ...) into the binaries the library is linked into. MSVC
+    /// targets use the linker's `/MANIFESTINPUT`; GNU targets link a resource
+    /// compiled with `zig rc`. Ignored for other targets.
+    pub fn windows_manifest(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.windows_manifest = Some(path.as_ref().into());
+        self
+    }
+
+    /// Sets the main thread's stack size in bytes. Only Windows and UEFI take
+    /// this at link time; ignored for other targets.
+    pub fn stack_size(&mut self, bytes: u64) -> &mut Self {
+        self.stack_size = Some(bytes);
+        self
+    }
+
+    /// Links `cdylib`s with a linker version script on ELF targets, to give
+    /// the shared library versioned symbols and a fixed export list. rustc
+    /// passes a version script of its own that exports the Rust
+    /// `#[no_mangle]` items; LLD merges the two, GNU ld only accepts scripts
+    /// without anonymous version tags alongside it. The Solaris link editor
+    /// takes mapfiles instead (see [`target::uses_solaris_ld()`]), so there
+    /// this fails the build. Ignored for other targets.
+    pub fn version_script(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.version_script = Some(path.as_ref().into());
+        self
+    }
+
+    /// Exports `symbol` from `cdylib`s even though rustc wouldn't, e.g. a C
+    /// function from this library that is part of the shared library's ABI:
+    /// `--export-dynamic-symbol` on ELF, `-exported_symbol` on Apple, `/EXPORT`
+    /// with MSVC, a generated `.def` file with MinGW, a generated mapfile with
+    /// the Solaris link editor and `--export` on wasm.
+    pub fn export_symbol(&mut self, symbol: &str) -> &mut Self {
+        self.exported_symbols.push(symbol.into());
+        self
+    }
+
+    /// Adds a pattern, where `*` matches any run of characters, to the
+    /// symbols the library may define. Once there is one, the build fails
+    /// when the library defines a symbol no pattern or
+    /// [`export_symbol()`](Self::export_symbol) matches, e.g. a helper of
+    /// vendored C code that isn't `static`; see [`symbols`](crate::symbols).
+    pub fn allow_symbol(&mut self, pattern: &str) -> &mut Self {
+        self.allowed_symbols.push(pattern.into());
+        self
+    }
+
+    /// Links `cdylib`s with a module-definition file on Windows. Ignored for
+    /// other targets.
+    pub fn def_file(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.def_file = Some(path.as_ref().into());
+        self
+    }
+
+    /// Overrides the ELF interpreter of linked executables, e.g. for NixOS or
+    /// a glibc in a non-standard prefix. Ignored for non-ELF targets.
+    pub fn dynamic_linker(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.dynamic_linker = Some(path.as_ref().into());
+        self
+    }
+
+    /// Whether undefined symbols in shared libraries linked against are
+    /// tolerated (`--allow-shlib-undefined`) or reported
+    /// (`--no-allow-shlib-undefined`). Unset leaves the linker default.
+    /// Ignored for non-ELF targets.
+    pub fn allow_shlib_undefined(&mut self, allow: bool) -> &mut Self {
+        self.allow_shlib_undefined = Some(allow);
+        self
+    }
+
+    /// Makes undefined symbols an error when linking `cdylib`s (`-z defs`,
+    /// `-undefined error` on Apple) instead of leaving them for the loader.
+    pub fn no_undefined(&mut self, no_undefined: bool) -> &mut Self {
+        self.no_undefined = no_undefined;
+        self
+    }
+
+    /// Has the final link write a map file to `path`, which
+    /// [`LinkMap`](crate::link_map::LinkMap) can break down by object, section
+    /// and symbol. Every binary, test and example the package links writes to
+    /// the same path, so the last link wins.
+    pub fn link_map(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.link_map = Some(path.as_ref().into());
+        self
+    }
+
+    /// Passes `arg` to the linker driver of the artifact the library ends up
+    /// in, through `cargo:rustc-link-arg`.
+    pub fn link_arg(&mut self, arg: &str) -> &mut Self {
+        self.link_args.push(arg.into());
+        self
+    }
+
+    /// Passes a `-z` option to the final link, spelled for the target's linker:
+    /// `-Wl,-z,<keyword>` on ELF and the equivalent described on each
+    /// [`ZOption`] elsewhere. Targets with no equivalent fail the build
+    /// rather than silently linking without it.
+    pub fn z_option(&mut self, option: ZOption) -> &mut Self {
+        self.z_options.push(option);
+        self
+    }
+
+    /// Passes `arg` through the linker driver to the linker of the artifact
+    /// the library ends up in: as `-Wl,<arg>`, with `-Xlinker` for arguments
+    /// containing commas, or unchanged for MSVC's `link.exe`. The build fails
+    /// when `arg` is spelled for another linker flavor, e.g. a GNU-style
+    /// `--option` for Apple's `ld64`, `-z` outside ELF or a `-Wl,` prefix
+    /// that would be doubled.
+    pub fn linker_arg(&mut self, arg: &str) -> &mut Self {
+        self.linker_args.push(arg.into());
+        self
+    }
+
+    /// Creates a thin archive, which records the paths of the objects instead
+    /// of copying them, and links it without bundling it into the rlib. That
+    /// saves time and disk for large object sets, but the archive is only
+    /// usable while the objects stay in `OUT_DIR`, so a regular archive is
+    /// made anyway with a custom [`out_dir()`](Self::out_dir), without cargo
+    /// metadata (the library is going somewhere else) and for MSVC targets.
+    pub fn thin_archive(&mut self, thin_archive: bool) -> &mut Self {
+        self.thin_archive = thin_archive;
+        self
+    }
+
+    /// Shares objects with every other `Build` on the machine through
+    /// [`cache::objects_dir()`], keyed by the Zig version, the flags other
+    /// than include directories and macros, and the preprocessed source, so
+    /// identical vendored C files compiled by several crates are only
+    /// compiled once. Each file is preprocessed to compute
+    /// the key. Objects taken from the cache print no warnings, and their
+    /// debug info names the source path of whichever build compiled them.
+    /// Defaults to on when [`OBJECT_CACHE_ENV`] is set.
+    pub fn object_cache(&mut self, object_cache: bool) -> &mut Self {
+        self.object_cache = Some(object_cache);
+        self
+    }
+
+    /// Writes Rust declarations of the `export fn`s of the `.zig` files to
+    /// `bindings.rs` in the output directory, for the crate to `include!`,
+    /// and C declarations of them to `<name>.h` next to it. See
+    /// [`bindings`](crate::bindings) for which types can cross.
+    pub fn emit_bindings(&mut self, emit_bindings: bool) -> &mut Self {
+        self.emit_bindings = emit_bindings;
+        self
+    }
+
+    /// The system root to take headers from, `--sysroot`. Needed for libc
+    /// headers on targets whose libc Zig doesn't bundle, Haiku and Fuchsia
+    /// (see [`target::needs_sysroot()`]).
+    /// Also links the objects into a shared library named and versioned as
+    /// `library` says, for packaging: `libfoo.so.1.2.3` with its soname set
+    /// and the `libfoo.so.1` and `libfoo.so` symlinks next to it, or
+    /// `foo-1.2.3.dll` with the import library `foo.lib` (`libfoo.dll.a` for
+    /// MinGW), which can't share the archive's name; see
+    /// [`naming`](crate::naming). It's linked with the same runtime libraries
+    /// and link arguments, and goes next to the archive the crate itself
+    /// links. Symlinks are only created on Unix hosts. Not available for
+    /// wasm, UEFI, freestanding and ARM64EC targets.
+    ///
+    /// ```no_run
+    /// use zig_rs::naming::SharedLibrary;
+    ///
+    /// zig_rs::Build::new()
+    ///     .files(["src/foo.c", "src/bar.c"])
+    ///     .shared_library(SharedLibrary::versioned("foo", env!("CARGO_PKG_VERSION")))
+    ///     .compile("foo");
+    /// ```
+    pub fn shared_library(&mut self, library: SharedLibrary) -> &mut Self {
+        self.shared_library = Some(library);
+        self
+    }
+
+    pub fn sysroot(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.sysroot = Some(dir.as_ref().into());
+        self
+    }
+
+    /// Where objects and the library go. Defaults to `OUT_DIR`.
+    pub fn out_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.out_dir = Some(dir.as_ref().into());
+        self
+    }
+
+    /// Whether to print `cargo:` link and rerun directives. On by default.
+    pub fn cargo_metadata(&mut self, cargo_metadata: bool) -> &mut Self {
+        self.cargo_metadata = cargo_metadata;
+        self
+    }
+
+    /// Signs the library once it's written, and records the signature in
+    /// the output directory; see [`sign`]. Defaults to the command in
+    /// [`sign::ENV`], if set.
+    pub fn signer(&mut self, signer: Signer) -> &mut Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Memory and CPU time limits for each compiler and archiver process.
+    /// Unset limits come from the environment; see [`process`].
+    pub fn limits(&mut self, limits: Limits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn toolchain(&mut self, toolchain: Toolchain) -> &mut Self {
+        self.toolchain = toolchain;
+        self
+    }
+
+    /// Compiles everything into `lib<name>.a` (`<name>.lib` for MSVC targets)
+    /// and links it into the crate. Panics on failure, like `cc`; see
+    /// [`try_compile()`](Self::try_compile) for a build script that wants to
+    /// fall back to something else. Source files compile in parallel as far
+    /// as Cargo's jobserver allows (see [`jobs::map()`]).
+    pub fn compile(&self, name: &str) {
+        if let Err(e) = self.try_compile(name) {
+            panic!("failed to compile {name} with zig cc: {e}");
+        }
+    }
+
+    /// Like [`compile()`](Self::compile) but returns failures, including the
+    /// failing command line and its parsed diagnostics, instead of panicking.
+    /// When several sources fail, the error is the first one's with the
+    /// diagnostics of all of them, [grouped](diagnostic::group):
+    ///
+    /// ```no_run
+    /// // build.rs
+    /// let simd = zig_rs::Build::new()
+    ///     .file("src/simd.c")
+    ///     .flag("-mavx2")
+    ///     .try_compile("simd");
+    /// if let Err(e) = simd {
+    ///     println!("cargo:warning=building without AVX2: {e}");
+    ///     zig_rs::Build::new().file("src/scalar.c").compile("simd");
+    /// }
+    /// ```
+    pub fn try_compile(&self, name: &str) -> Result<Artifacts, Error> {
+        if let Cow::Owned(build) = self.transformed(name)? {
+            return build.try_compile(name);
+        }
+        let target = self.get_target()?;
+        self.rerun_if_env_changed();
+        self.check_glibc_version()?;
+        let out_dir = self.get_out_dir()?;
+        let obj_dir = out_dir.join(format!("{name}-objs"));
+        fs_err::create_dir_all(&obj_dir)?;
+        let args = self.common_args()?;
+        let zig_args = if self.files.iter().any(|file| is_zig(file)) {
+            self.zig_args()?
+        } else {
+            Vec::new()
+        };
+        let tagger = Tagger::new(&format!("cc/{name}"));
+
+        let objects: Vec<PathBuf> = self
+            .files
+            .iter()
+            .enumerate()
+            .map(|(i, file)| {
+                let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+                obj_dir.join(format!("{i}-{stem}.o"))
+            })
+            .collect();
+        let lib = out_dir.join(if is_msvc_like(&target) {
+            format!("{name}.lib")
+        } else {
+            format!("lib{name}.a")
+        });
+
+        let thin = self.thin_archive
+            && self.out_dir.is_none()
+            && self.cargo_metadata
+            && !is_msvc_like(&target);
+        // `D` zeroes timestamps, owners and groups; see [`reproducible`].
+        let ar_flags = if thin { "crsTD" } else { "crsD" };
+
+        let fingerprint_path = fingerprint::path_for(&lib);
+        let mut fingerprint = Fingerprint {
+            zig_version: self.toolchain.zig_version().into(),
+            flags: args
+                .iter()
+                .chain(&zig_args)
+                .cloned()
+                .chain(
+                    self.file_flags
+                        .iter()
+                        .map(|(path, flag)| format!("{}: {flag}", path.display())),
+                )
+                .chain(
+                    self.third_party
+                        .iter()
+                        .map(|(path, on)| format!("{}: third-party={on}", path.display())),
+                )
+                .collect(),
+            ar_flags: ar_flags.into(),
+            files: self.files.clone(),
+            inputs: Vec::new(),
+        };
+        let previous = Fingerprint::read(&fingerprint_path)
+            .ok()
+            .filter(|previous| previous.is_up_to_date(&fingerprint))
+            .filter(|_| lib.exists() && objects.iter().all(|o| o.exists()));
+        let up_to_date = previous.is_some();
+        let mut diagnostics = Vec::new();
+        let mut units = Vec::new();
+        if let Some(previous) = previous {
+            fingerprint = previous;
+            tagger.print("up to date");
+        } else {
+            if fingerprint_path.exists() {
+                fs_err::remove_file(&fingerprint_path)?;
+            }
+            let shared_dir = self.object_cache_dir();
+            let jobs: Vec<_> = self.files.iter().zip(&objects).collect();
+            let done = AtomicUsize::new(0);
+            tagger.started();
+            let compiled = jobs::map(&jobs, |&(file, object)| {
+                cancel::check()?;
+                let args = self.file_args(file, if is_zig(file) { &zig_args } else { &args });
+                // Each object has a fingerprint of its own, so only the
+                // sources whose inputs changed are compiled again.
+                let mut unit = Fingerprint {
+                    zig_version: self.toolchain.zig_version().into(),
+                    flags: args.clone(),
+                    ar_flags: String::new(),
+                    files: vec![file.clone()],
+                    inputs: Vec::new(),
+                };
+                let unit_path = fingerprint::path_for(object);
+                let previous = Fingerprint::read(&unit_path)
+                    .ok()
+                    .filter(|previous| object.exists() && previous.is_up_to_date(&unit));
+                let result = if let Some(previous) = previous {
+                    Ok(Compiled {
+                        diagnostics: Vec::new(),
+                        inputs: previous.inputs.into_iter().map(|(_, p)| p).collect(),
+                        usage: None,
+                    })
+                } else {
+                    if unit_path.exists() {
+                        fs_err::remove_file(&unit_path)?;
+                    }
+                    let result = if is_zig(file) {
+                        self.compile_zig_object(file, object, &args, &tagger)
+                    } else {
+                        self.compile_object(file, object, &args, shared_dir.as_deref(), &tagger)
+                    };
+                    if let Ok(compiled) = &result {
+                        unit.add_inputs(compiled.inputs.iter().cloned())?;
+                        unit.write(&unit_path)?;
+                    }
+                    result
+                };
+                let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                log::emit(Event::Progress {
+                    step: tagger.step().into(),
+                    fraction: Some(done as f64 / jobs.len() as f64),
+                    message: format!("{done}/{} objects", jobs.len()),
+                });
+                result
+            });
+            tagger.finished(compiled.iter().all(Result::is_ok));
+            if cancel::check().is_err() {
+                let done = compiled.iter().filter(|result| result.is_ok()).count();
+                return Err(Cancelled::with_progress(format!(
+                    "{done}/{} objects compiled",
+                    jobs.len()
+                ))
+                .into());
+            }
+            let mut failed = None;
+            for (result, file) in compiled.into_iter().zip(&self.files) {
+                let compiled = match result {
+                    Ok(compiled) => compiled,
+                    Err(e) => {
+                        if let Error::Command(e) = &e {
+                            diagnostics.extend(e.diagnostics.iter().cloned());
+                        }
+                        failed.get_or_insert(e);
+                        continue;
+                    }
+                };
+                diagnostics.extend(compiled.diagnostics);
+                fingerprint.add_inputs(compiled.inputs)?;
+                units.push(UnitStats {
+                    file: file.clone(),
+                    usage: compiled.usage,
+                });
+            }
+            diagnostics = diagnostic::group(diagnostics);
+            if self.cargo_warnings {
+                for warning in diagnostics
+                    .iter()
+                    .filter(|d| d.severity == Severity::Warning)
+                {
+                    build::warning(&warning.to_string());
+                }
+            }
+            if let Some(mut e) = failed {
+                if let Error::Command(e) = &mut e {
+                    e.diagnostics = diagnostics;
+                }
+                return Err(e);
+            }
+            if self.print_unit_stats {
+                for line in unit_stats_summary(&units, UNIT_STATS_SHOWN).lines() {
+                    tagger.print(line);
+                }
+            }
+
+            if lib.exists() {
+                fs_err::remove_file(&lib)?;
+            }
+            let mut members = objects.clone();
+            members.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+            let mut cmd = self.toolchain.command();
+            cmd.arg("ar").arg(ar_flags).arg(&lib).args(&members);
+            self.run(&mut cmd, &tagger.child("ar"))?;
+            fingerprint.write(&fingerprint_path)?;
+        }
+        let inputs: Vec<PathBuf> = fingerprint.inputs.into_iter().map(|(_, p)| p).collect();
+        if !self.allowed_symbols.is_empty() {
+            let allowed: Vec<&String> = self
+                .allowed_symbols
+                .iter()
+                .chain(&self.exported_symbols)
+                .collect();
+            let unlisted = symbols::unlisted(&lib, &target, &allowed)?;
+            if !unlisted.is_empty() {
+                return Err(Error::Config(format!(
+                    "{} defines symbols no allow_symbol() pattern matches: {}",
+                    lib.display(),
+                    unlisted.join(", ")
+                )));
+            }
+        }
+        let bindings = if self.emit_bindings {
+            Some(self.write_bindings(name, &out_dir)?)
+        } else {
+            None
+        };
+
+        let mut link_args = self.link_args.clone();
+        if let Some(hardening) = &self.hardening {
+            link_args.extend(hardening.link_args(&self.zig_target()?));
+        }
+        if let Some(path) = &self.link_map {
+            if target::uses_solaris_ld(&target) {
+                return Err(Error::Config(
+                    "the Solaris link editor can't write a map file".into(),
+                ));
+            }
+            link_args.push(link_map::link_arg(&target, path));
+        }
+        if target.contains("-windows") || target.contains("-uefi") {
+            link_args.extend(self.windows_link_args(&target, &obj_dir, &tagger)?);
+        }
+        link_args.extend(self.linker_flags(&target)?);
+        link_args.extend(loader_link_args(
+            &target,
+            self.dynamic_linker.as_deref(),
+            self.allow_shlib_undefined,
+        )?);
+        let cdylib_link_args = self.cdylib_link_args(&target, &obj_dir)?;
+        let runtime = self.runtime.unwrap_or_else(|| Runtime::for_target(&target));
+        let cpp = self.files.iter().any(|file| is_cpp(file));
+        let runtime_libs = self.runtime_libs(&runtime, cpp, &target, &out_dir, &obj_dir)?;
+        let shared_lib = match &self.shared_library {
+            Some(library) => {
+                let inputs: Vec<PathBuf> = objects.iter().chain(&runtime_libs).cloned().collect();
+                Some(self.link_shared(library, &lib, &inputs, &link_args, up_to_date, &tagger)?)
+            }
+            None => None,
+        };
+        let mut size_report = None;
+        if self.optimize_size {
+            link_args.push(gc_sections_link_arg(&target).into());
+            link_args.extend(strip_link_arg(&target).map(String::from));
+            let mut baseline = self.clone();
+            baseline.optimize_size = false;
+            baseline
+                .cargo_metadata(false)
+                .out_dir(obj_dir.join("size-baseline"));
+            let before = fs_err::metadata(baseline.try_compile(name)?.lib)?.len();
+            let after = fs_err::metadata(&lib)?.len();
+            tagger.print(&format!(
+                "{before} bytes with the profile's settings, {after} bytes optimized for size"
+            ));
+            size_report = Some(SizeReport { before, after });
+        }
+        if self.cargo_metadata {
+            build::rustc_link_search_kind("native", &out_dir);
+            // Bundling would copy a thin archive's member paths into the
+            // rlib, where they mean nothing.
+            build::rustc_link_lib_kind(if thin { "static:-bundle" } else { "static" }, name);
+            for lib in &runtime_libs {
+                let file_name = lib.file_name().unwrap_or_default().to_string_lossy();
+                if let Some((kind, name)) = zig_build::library_kind_and_name(&file_name) {
+                    build::rustc_link_lib_kind(kind, name);
+                }
+            }
+            if cpp && runtime.cxx_stdlib == CxxStdlib::System {
+                build::rustc_link_lib("c++");
+            }
+            for arg in &link_args {
+                build::rustc_link_arg(arg);
+            }
+            for arg in &cdylib_link_args {
+                build::rustc_cdylib_link_arg(arg);
+            }
+            for path in [&self.version_script, &self.def_file].into_iter().flatten() {
+                build::rerun_if_changed(path);
+            }
+            if target.contains("-apple-") {
+                for dir in &self.framework_dirs {
+                    build::rustc_link_search_kind("framework", dir);
+                }
+                for (framework, weak) in &self.frameworks {
+                    if *weak {
+                        build::rustc_link_arg(&format!("-Wl,-weak_framework,{framework}"));
+                    } else {
+                        build::rustc_link_lib_kind("framework", framework);
+                    }
+                }
+            }
+            for input in &inputs {
+                build::rerun_if_changed(input);
+            }
+        }
+        let signature = match self.signer.clone().or_else(Signer::from_env) {
+            Some(signer) => Some(signer.sign_and_record(&lib)?),
+            None => None,
+        };
+        Ok(Artifacts {
+            lib,
+            objects,
+            link_args,
+            cdylib_link_args,
+            size_report,
+            diagnostics,
+            inputs,
+            up_to_date,
+            thin,
+            runtime_libs,
+            units,
+            bindings,
+            signature,
+            shared_lib,
+        })
+    }
+
+    /// Links `inputs` into `library` next to `archive`, unless the archive
+    /// was up to date and the library is there, and creates its symlinks.
+    fn link_shared(
+        &self,
+        library: &SharedLibrary,
+        archive: &Path,
+        inputs: &[PathBuf],
+        link_args: &[String],
+        up_to_date: bool,
+        tagger: &Tagger,
+    ) -> Result<PathBuf, Error> {
+        let target = self.get_target()?;
+        let zig_target = self.zig_target()?;
+        if target.starts_with("wasm")
+            || target.contains("-uefi")
+            || zig_target.contains("-freestanding-")
+        {
+            return Err(Error::Config(format!("no shared libraries for {target}")));
+        }
+        if target.starts_with("arm64ec-") {
+            return Err(Error::Config(format!(
+                "Zig can't link ARM64EC images; link the library into a cdylib for {target} instead"
+            )));
+        }
+        let out_dir = self.get_out_dir()?;
+        let path = out_dir.join(library.file_name(&target));
+        if !(up_to_date && path.exists()) {
+            let cpp = self.files.iter().any(|file| is_cpp(file));
+            let mut cmd = self.toolchain.command();
+            cmd.arg(if cpp { "c++" } else { "cc" })
+                .arg("-shared")
+                .arg("-target")
+                .arg(&zig_target)
+                .args(inputs)
+                .args(link_args.iter().filter(|arg| !is_link_exe_option(arg)))
+                .args(library.link_args(&target));
+            if let Some(import_library) = library.import_library(&target) {
+                let import_library = out_dir.join(import_library);
+                if import_library == archive {
+                    return Err(Error::Config(format!(
+                        "the import library of {} would replace {}; compile the archive under another name",
+                        path.display(),
+                        archive.display()
+                    )));
+                }
+                cmd.arg(format!("-Wl,--out-implib,{}", import_library.display()));
+            }
+            cmd.arg("-o").arg(&path);
+            self.run(&mut cmd, &tagger.child("shared"))?;
+        }
+        #[cfg(unix)]
+        for (link, to) in library.links(&target) {
+            let link = out_dir.join(link);
+            if fs::symlink_metadata(&link).is_ok() {
+                fs_err::remove_file(&link)?;
+            }
+            std::os::unix::fs::symlink(to, &link)?;
+        }
+        Ok(path)
+    }
+
+    /// Builds the library twice, into `<name>-reproducibility/a` and `b` under
+    /// the output directory and without the object cache or cargo metadata,
+    /// and reports what keeps it from being reproducible: the problems
+    /// [`reproducible::check_archive()`] finds in the first build and the
+    /// differences [`reproducible::compare()`] finds between the two. Empty
+    /// when the library is reproducible.
+    pub fn verify_reproducible(&self, name: &str) -> Result<Vec<String>, Error> {
+        let dir = self.get_out_dir()?.join(format!("{name}-reproducibility"));
+        let mut libs = Vec::new();
+        for build in ["a", "b"] {
+            let mut copy = self.clone();
+            copy.cargo_metadata(false)
+                .object_cache(false)
+                .out_dir(dir.join(build));
+            libs.push(copy.try_compile(name)?.lib);
+        }
+        let mut problems = reproducible::check_archive(&libs[0])?;
+        problems.extend(reproducible::compare(&libs[0], &libs[1])?);
+        Ok(problems)
+    }
+
+    /// Runs only the preprocessor (`-E`) over every C, C++ and assembly
+    /// source, with the flags [`compile()`](Self::compile) would use, and
+    /// returns the output of all of them in order. For code generators that
+    /// need the macros of a vendored header expanded for the target:
+    ///
+    /// ```no_run
+    /// // build.rs
+    /// let expanded = zig_rs::Build::new()
+    ///     .file("src/constants.c")
+    ///     .include("vendor/foo/include")
+    ///     .expand()?;
+    /// # Ok::<(), zig_rs::Error>(())
+    /// ```
+    pub fn expand(&self) -> Result<String, Error> {
+        self.compile_to_text("expand", "-E", "i")
+    }
+
+    /// Like [`expand()`](Self::expand) but compiles to assembly (`-S`), to
+    /// inspect the code generated for the target.
+    pub fn assembly(&self) -> Result<String, Error> {
+        self.compile_to_text("assembly", "-S", "s")
+    }
+
+    /// Runs every source through `zig cc` in `mode`, writing files with
+    /// `extension` under `<out dir>/<name>`, and joins them.
+    fn compile_to_text(&self, name: &str, mode: &str, extension: &str) -> Result<String, Error> {
+        if let Cow::Owned(build) = self.transformed(name)? {
+            return build.compile_to_text(name, mode, extension);
+        }
+        self.rerun_if_env_changed();
+        self.check_glibc_version()?;
+        let args = self.common_args()?;
+        let dir = self.get_out_dir()?.join(name);
+        fs_err::create_dir_all(&dir)?;
+        let tagger = Tagger::new(&format!("cc/{name}"));
+        let mut text = String::new();
+        for (i, file) in self.files.iter().enumerate() {
+            if is_zig(file) {
+                return Err(Error::Config(format!(
+                    "{}: {mode} only applies to C, C++ and assembly sources",
+                    file.display()
+                )));
+            }
+            let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+            let output = dir.join(format!("{i}-{stem}.{extension}"));
+            let mut cmd = self.toolchain.command();
+            cmd.arg(if is_cpp(file) { "c++" } else { "cc" })
+                .args(self.file_args(file, &args))
+                .arg(mode)
+                .arg(file)
+                .arg("-o")
+                .arg(&output);
+            self.run(&mut cmd, &tagger)?;
+            text += &fs_err::read_to_string(&output)?;
+            if self.cargo_metadata {
+                build::rerun_if_changed(file);
+            }
+        }
+        Ok(text)
+    }
+
+    /// This build with the sources its [transforms](Self::transform) change
+    /// swapped for the changed copies, which are written now, and without the
+    /// transforms.
+    fn transformed(&self, name: &str) -> Result<Cow<'_, Self>, Error> {
+        if self.transforms.is_empty() {
+            return Ok(Cow::Borrowed(self));
+        }
+        let dir = self.get_out_dir()?.join(format!("{name}-src"));
+        let mut transformed = self.clone();
+        transformed.transforms.clear();
+        for (i, file) in self.files.iter().enumerate() {
+            // Missing sources fail in the compile, with the usual error.
+            let Ok(original) = fs::read_to_string(file) else {
+                continue;
+            };
+            let contents = self
+                .transforms
+                .iter()
+                .fold(original.clone(), |contents, transform| {
+                    (transform.0)(file, &contents)
+                });
+            if contents == original {
+                continue;
+            }
+            // A directory per source keeps the file name, and with it the
+            // object's name and the language.
+            let copy = dir
+                .join(i.to_string())
+                .join(file.file_name().unwrap_or_default());
+            fs_err::create_dir_all(copy.parent().unwrap())?;
+            if fs::read_to_string(&copy).ok().as_ref() != Some(&contents) {
+                fs_err::write(&copy, &contents)?;
+            }
+            if self.cargo_metadata {
+                build::rerun_if_changed(file);
+            }
+            if !is_zig(file) {
+                let parent = file.parent().filter(|p| !p.as_os_str().is_empty());
+                let parent = parent.unwrap_or(Path::new("."));
+                transformed
+                    .file_flags
+                    .push((copy.clone(), format!("-iquote{}", parent.display())));
+            }
+            for (path, flag) in &self.file_flags {
+                if path == file {
+                    transformed.file_flags.push((copy.clone(), flag.clone()));
+                }
+            }
+            if self.is_third_party(file) {
+                transformed.third_party.push((copy.clone(), true));
+            }
+            transformed.files[i] = copy;
+        }
+        Ok(Cow::Owned(transformed))
+    }
+
+    /// Compiles `file` into `object`, or copies it from the shared object
+    /// cache.
+    fn compile_object(
+        &self,
+        file: &Path,
+        object: &Path,
+        args: &[String],
+        shared_dir: Option<&Path>,
+        tagger: &Tagger,
+    ) -> Result<Compiled, Error> {
+        let depfile = object.with_extension("d");
+        let shared = shared_dir.and_then(|dir| self.shared_object(dir, file, args, &depfile));
+        if let Some(shared) = &shared
+            && shared.exists()
+        {
+            fs_err::copy(shared, object)?;
+            let _ = cache::touch(shared);
+            let mut inputs = fingerprint::read_depfile(&depfile).unwrap_or_default();
+            inputs.push(file.to_owned());
+            return Ok(Compiled {
+                diagnostics: Vec::new(),
+                inputs,
+                usage: None,
+            });
+        }
+        let mut cmd = self.toolchain.command();
+        cmd.arg(if is_cpp(file) { "c++" } else { "cc" })
+            .args(args)
+            .arg("-MMD")
+            .arg("-MF")
+            .arg(&depfile)
+            .arg("-c")
+            .arg(file)
+            .arg("-o")
+            .arg(object);
+        let (diagnostics, usage) = self.run_measured(&mut cmd, tagger)?;
+        if let Some(shared) = &shared {
+            // Rename into place so concurrent builds never see half an
+            // object.
+            let partial = shared.with_extension(format!("{}.partial", std::process::id()));
+            fs_err::create_dir_all(shared.parent().unwrap())?;
+            fs_err::copy(object, &partial)?;
+            fs_err::rename(&partial, shared)?;
+        }
+        // Assembly without the preprocessor writes no dependency file.
+        let inputs = match fingerprint::read_depfile(&depfile) {
+            Ok(inputs) if !inputs.is_empty() => inputs,
+            _ => vec![file.to_owned()],
+        };
+        Ok(Compiled {
+            diagnostics,
+            inputs,
+            usage: Some(usage),
+        })
+    }
+
+    /// Compiles the Zig module rooted at `file` into `object`. Zig writes no
+    /// dependency file, so the inputs are what [`zig_imports::files()`]
+    /// finds from the root.
+    fn compile_zig_object(
+        &self,
+        file: &Path,
+        object: &Path,
+        args: &[String],
+        tagger: &Tagger,
+    ) -> Result<Compiled, Error> {
+        let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+        let mut cmd = self.toolchain.command();
+        cmd.arg("build-obj")
+            .arg(file)
+            .args(args)
+            .arg("--name")
+            .arg(&*stem)
+            .arg(format!("-femit-bin={}", object.display()));
+        let (diagnostics, usage) = self.run_measured(&mut cmd, tagger)?;
+        Ok(Compiled {
+            diagnostics,
+            inputs: zig_imports::files(file)?,
+            usage: Some(usage),
+        })
+    }
+
+    /// Writes `bindings.rs` and `<name>.h` into `out_dir` for the `.zig`
+    /// files, leaving them untouched when the contents haven't changed.
+    fn write_bindings(&self, name: &str, out_dir: &Path) -> Result<(PathBuf, PathBuf), Error> {
+        let mut exports = Vec::new();
+        for file in self.files.iter().filter(|file| is_zig(file)) {
+            let source = fs_err::read_to_string(file)?;
+            exports.extend(
+                bindings::parse(&source)
+                    .map_err(|e| Error::Config(format!("{}: {e}", file.display())))?,
+            );
+        }
+        if exports.is_empty() {
+            return Err(Error::Config(format!(
+                "{name}: no `export fn`s in .zig files to emit bindings for"
+            )));
+        }
+        let header = "// Generated by zig_rs::bindings from the Zig sources. Do not edit.\n\n";
+        let rust = bindings::rust_bindings(&exports).map_err(Error::Config)?;
+        let c = bindings::c_header(&exports).map_err(Error::Config)?;
+        let paths = (
+            out_dir.join("bindings.rs"),
+            out_dir.join(format!("{name}.h")),
+        );
+        for (path, contents) in [(&paths.0, rust), (&paths.1, c)] {
+            let contents = format!("{header}{contents}");
+            if std::fs::read_to_string(path).ok() != Some(contents.clone()) {
+                fs_err::write(path, contents)?;
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Checks whether the toolchain accepts `flag` for the target by compiling
+    /// an empty C file with it. Results are cached per toolchain, target and
+    /// flag for the lifetime of the process.
+    pub fn is_flag_supported(&self, flag: &str) -> Result<bool, Error> {
+        static PROBES: OnceLock<Mutex<HashMap<ProbeKey, bool>>> = OnceLock::new();
+        let zig_target = self.zig_target()?;
+        let key = (self.toolchain.zig(), zig_target.clone(), flag.to_owned());
+        let probes = PROBES.get_or_init(Default::default);
+        if let Some(&supported) = probes.lock().unwrap().get(&key) {
+            return Ok(supported);
+        }
+
+        // A directory per probe, so concurrent builds don't overwrite each
+        // other's source or object.
+        static NEXT_PROBE: AtomicUsize = AtomicUsize::new(0);
+        let probe = NEXT_PROBE.fetch_add(1, Ordering::Relaxed);
+        let dir = self
+            .get_out_dir()?
+            .join("flag-probes")
+            .join(probe.to_string());
+        fs_err::create_dir_all(&dir)?;
+        let source = dir.join("probe.c");
+        fs_err::write(&source, "int zig_rs_probe(void) { return 0; }\n")?;
+        // Unknown warning options are only warnings; -Werror makes them count.
+        let supported = process::status(
+            self.toolchain
+                .command()
+                .arg("cc")
+                .arg("-target")
+                .arg(&zig_target)
+                .arg("-Werror")
+                .arg(flag)
+                .arg("-c")
+                .arg(&source)
+                .arg("-o")
+                .arg(dir.join("probe.o"))
+                .stdout(Stdio::null())
+                .stderr(Stdio::null()),
+        )?
+        .success();
+        let _ = fs::remove_dir_all(&dir);
+        probes.lock().unwrap().insert(key, supported);
+        Ok(supported)
+    }
+
+    /// `<cache>/objects/<zig-version>` when the object cache is on.
+    fn object_cache_dir(&self) -> Option<PathBuf> {
+        if self.cargo_metadata {
+            build::rerun_if_env_changed(OBJECT_CACHE_ENV);
+        }
+        let enabled = self
+            .object_cache
+            .unwrap_or_else(|| env::var_os(OBJECT_CACHE_ENV).is_some());
+        enabled
+            .then(cache::objects_dir)
+            .flatten()
+            .map(|dir| dir.join(self.toolchain.zig_version()))
+    }
+
+    /// The path `file` compiled with `args` has in the object cache `dir`.
+    /// Preprocessing also writes `depfile`. `None` when the file doesn't
+    /// preprocess, leaving the error to the real compile.
+    fn shared_object(
+        &self,
+        dir: &Path,
+        file: &Path,
+        args: &[String],
+        depfile: &Path,
+    ) -> Option<PathBuf> {
+        let cpp = is_cpp(file);
+        let output = process::output(
+            self.toolchain
+                .command()
+                .arg(if cpp { "c++" } else { "cc" })
+                .args(args)
+                .arg("-MMD")
+                .arg("-MF")
+                .arg(depfile)
+                .arg("-E")
+                .arg("-P")
+                .arg(file),
+        )
+        .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let key = cc_cache::object_key(cpp, args, &output.stdout);
+        Some(dir.join(format!("{key}.o")))
+    }
+
+    /// Copies the Zig archives `runtime` needs next to the library, as
+    /// `libzig_<name>.a` (`zig_<name>.lib` for MSVC targets) so they can't
+    /// be confused with a system library of the same name.
+    fn runtime_libs(
+        &self,
+        runtime: &Runtime,
+        cpp: bool,
+        target: &str,
+        out_dir: &Path,
+        obj_dir: &Path,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let names = runtime.zig_libs(cpp);
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+        let zig_target = self.zig_target()?;
+        let archives =
+            runtime::zig_archives(&self.toolchain, &zig_target, &obj_dir.join("runtime-probe"))
+                .map_err(|e| Error::Config(e.to_string()))?;
+        let mut libs = Vec::new();
+        for name in names {
+            let archive = archives.get(name).ok_or_else(|| {
+                Error::Config(format!("Zig doesn't build lib{name} for {zig_target}"))
+            })?;
+            let lib = out_dir.join(if is_msvc_like(target) {
+                format!("zig_{name}.lib")
+            } else {
+                format!("libzig_{name}.a")
+            });
+            fs_err::copy(archive, &lib)?;
+            libs.push(lib);
+        }
+        Ok(libs)
+    }
+
+    /// Linker flags for [`version_script()`](Self::version_script),
+    /// [`export_symbol()`](Self::export_symbol) and
+    /// [`def_file()`](Self::def_file).
+    fn cdylib_link_args(&self, target: &str, obj_dir: &Path) -> Result<Vec<String>, Error> {
+        let mut args = Vec::new();
+        if self.no_undefined {
+            args.extend(no_undefined_link_arg(target).map(String::from));
+        }
+        if target.contains("-apple-") {
+            for symbol in &self.exported_symbols {
+                args.push(format!("-Wl,-exported_symbol,_{symbol}"));
+            }
+        } else if is_msvc_like(target) {
+            for symbol in &self.exported_symbols {
+                args.push(format!("/EXPORT:{symbol}"));
+            }
+            if let Some(def) = &self.def_file {
+                args.push(format!("/DEF:{}", std::path::absolute(def)?.display()));
+            }
+        } else if target.contains("-windows") {
+            // MinGW linkers take any number of .def files as inputs.
+            if !self.exported_symbols.is_empty() {
+                let def = obj_dir.join("exports.def");
+                fs_err::write(
+                    &def,
+                    format!("EXPORTS\n    {}\n", self.exported_symbols.join("\n    ")),
+                )?;
+                args.push(def.display().to_string());
+            }
+            if let Some(def) = &self.def_file {
+                args.push(std::path::absolute(def)?.display().to_string());
+            }
+        } else if target::uses_solaris_ld(target) {
+            // rustc passes a mapfile of its own; the link editor merges them.
+            if !self.exported_symbols.is_empty() {
+                let mapfile = obj_dir.join("exports.mapfile");
+                fs_err::write(
+                    &mapfile,
+                    format!(
+                        "$mapfile_version 2\nSYMBOL_SCOPE {{\n    global:\n        {};\n}};\n",
+                        self.exported_symbols.join(";\n        ")
+                    ),
+                )?;
+                args.push(format!("-Wl,-M,{}", mapfile.display()));
+            }
+            if self.version_script.is_some() {
+                return Err(Error::Config(format!(
+                    "the Solaris link editor takes mapfiles, not version scripts, for {target}; pass one with `linker_arg(\"-M<mapfile>\")`"
+                )));
+            }
+        } else if is_elf(target) {
+            for symbol in &self.exported_symbols {
+                args.push(format!("-Wl,--export-dynamic-symbol={symbol}"));
+            }
+            if let Some(script) = &self.version_script {
+                args.push(format!(
+                    "-Wl,--version-script={}",
+                    std::path::absolute(script)?.display()
+                ));
+            }
+        } else if target.starts_with("wasm") {
+            for symbol in &self.exported_symbols {
+                args.push(format!("-Wl,--export={symbol}"));
+            }
+        }
+        Ok(args)
+    }
+
+    /// Linker flags for [`windows_subsystem()`](Self::windows_subsystem),
+    /// [`windows_manifest()`](Self::windows_manifest) and
+    /// [`stack_size()`](Self::stack_size), and the machine of ARM64EC images.
+    fn windows_link_args(
+        &self,
+        target: &str,
+        obj_dir: &Path,
+        tagger: &Tagger,
+    ) -> Result<Vec<String>, Error> {
+        let msvc = is_msvc_like(target);
+        let mut args = Vec::new();
+        if target.starts_with("arm64ec-") {
+            // The library is x64 code, and lld-link takes the machine from
+            // the first object it reads unless told otherwise.
+            args.push("/MACHINE:ARM64EC".into());
+        }
+        args.extend(subsystem_link_args(
+            target,
+            self.subsystem,
+            self.stack_size,
+            msvc,
+        )?);
+        if let Some(manifest) = &self.windows_manifest
+            && target.contains("-windows")
+        {
+            let manifest = std::path::absolute(manifest)?;
+            if msvc {
+                args.push("/MANIFEST:EMBED".into());
+                args.push(format!("/MANIFESTINPUT:{}", manifest.display()));
+            } else {
+                let (mut cmd, res) = manifest_resource(&self.toolchain, &manifest, obj_dir)?;
+                self.run(&mut cmd, &tagger.child("rc"))?;
+                args.push(res.display().to_string());
+            }
+            if self.cargo_metadata {
+                build::rerun_if_changed(&manifest);
+            }
+        }
+        Ok(args)
+    }
+
+    /// Linker flags for [`z_option()`](Self::z_option) and
+    /// [`linker_arg()`](Self::linker_arg), checked against the linker flavor
+    /// of `target`.
+    fn linker_flags(&self, target: &str) -> Result<Vec<String>, Error> {
+        let apple = target.contains("-apple-");
+        let msvc = is_msvc_like(target);
+        let solaris_ld = target::uses_solaris_ld(target);
+        let mut args = Vec::new();
+        for &option in &self.z_options {
+            let arg = match option {
+                ZOption::Relro | ZOption::NoExecStack if solaris_ld => {
+                    return Err(Error::Config(format!(
+                        "-z {} has no equivalent in the Solaris link editor for {target}",
+                        option.keyword()
+                    )));
+                }
+                _ if is_elf(target) => Some(format!("-Wl,-z,{}", option.keyword())),
+                ZOption::Now if apple => Some("-Wl,-bind_at_load".into()),
+                ZOption::Relro | ZOption::NoExecStack | ZOption::Origin if apple => None,
+                ZOption::NoExecStack if msvc => Some("/NXCOMPAT".into()),
+                ZOption::NoExecStack if target.contains("-windows") => {
+                    Some("-Wl,--nxcompat".into())
+                }
+                _ => {
+                    return Err(Error::Config(format!(
+                        "-z {} has no equivalent for {target}",
+                        option.keyword()
+                    )));
+                }
+            };
+            args.extend(arg);
+        }
+        for arg in &self.linker_args {
+            let flavor = if apple {
+                "ld64"
+            } else if solaris_ld {
+                "Solaris"
+            } else if msvc {
+                "link.exe"
+            } else {
+                "GNU-style"
+            };
+            let mismatch = arg.starts_with("-Wl,")
+                || arg.starts_with("-Xlinker")
+                || (arg.starts_with("-z") && !is_elf(target))
+                || ((apple || solaris_ld) && arg.starts_with("--"))
+                || (msvc && arg.starts_with("--"))
+                || (!msvc && is_link_exe_option(arg));
+            if mismatch {
+                return Err(Error::Config(format!(
+                    "linker argument `{arg}` isn't spelled for the {flavor} linker of {target}"
+                )));
+            }
+            if msvc {
+                args.push(arg.clone());
+            } else if arg.contains(',') {
+                args.extend(["-Xlinker".into(), arg.clone()]);
+            } else {
+                args.push(format!("-Wl,{arg}"));
+            }
+        }
+        Ok(args)
+    }
+
+    /// Runs `cmd` with tagged output and returns the diagnostics it printed
+    /// that pass the configured filters.
+    fn run(&self, cmd: &mut Command, tagger: &Tagger) -> Result<Vec<Diagnostic>, Error> {
+        self.run_measured(cmd, tagger)
+            .map(|(diagnostics, _)| diagnostics)
+    }
+
+    /// Like [`run()`](Self::run) but also returns what the compiler used.
+    fn run_measured(
+        &self,
+        cmd: &mut Command,
+        tagger: &Tagger,
+    ) -> Result<(Vec<Diagnostic>, process::Usage), Error> {
+        let (status, output, usage) =
+            process::with_limits(self.limits, || log::run_tagged_measured(cmd, tagger))?;
+        let diagnostics: Vec<Diagnostic> = diagnostic::parse(&output)
+            .into_iter()
+            .filter(|d| self.notes || d.severity != Severity::Note)
+            .filter(|d| {
+                !d.flag.as_ref().is_some_and(|flag| {
+                    self.suppressed_warnings
+                        .iter()
+                        .any(|name| flag.strip_prefix("-W") == Some(name.as_str()))
+                })
+            })
+            .filter(|d| d.severity == Severity::Error || !self.is_third_party(Path::new(&d.file)))
+            .collect();
+        if !status.success() {
+            return Err(Error::Command(Box::new(CommandError::new(
+                cmd,
+                status,
+                diagnostics,
+                output,
+            ))));
+        }
+        Ok((diagnostics, usage))
+    }
+
+    fn get_target(&self) -> Result<String, Error> {
+        match &self.target {
+            Some(target) => Ok(target.clone()),
+            None => env::var("TARGET").map_err(|_| Error::Config("TARGET is not set".into())),
+        }
+    }
+
+    fn zig_target(&self) -> Result<String, Error> {
+        let target = target::pin_glibc_version(&self.get_target()?, self.glibc_version.as_deref());
+        target::zig_target(&target)
+            .ok_or_else(|| Error::Config(format!("no Zig target for {target}")))
+    }
+
+    fn rerun_if_env_changed(&self) {
+        if self.cargo_metadata {
+            for var in TRACKED_ENV {
+                build::rerun_if_env_changed(var);
+            }
+        }
+    }
+
+    /// Fails when the target pins a glibc version the toolchain doesn't ship.
+    fn check_glibc_version(&self) -> Result<(), Error> {
+        if self.cargo_metadata {
+            build::rerun_if_env_changed(target::GLIBC_VERSION_ENV);
+        }
+        target::check_glibc_version(&self.toolchain, &self.zig_target()?)
+            .map_err(|e| Error::Config(e.to_string()))
+    }
+
+    fn get_out_dir(&self) -> Result<PathBuf, Error> {
+        match &self.out_dir {
+            Some(dir) => Ok(dir.clone()),
+            None => env::var_os("OUT_DIR")
+                .map(PathBuf::from)
+                .ok_or_else(|| Error::Config("OUT_DIR is not set".into())),
+        }
+    }
+
+    /// The flags shared by every compile, in order: target, optimization,
+    /// debug info, PIC, includes, defines, then user flags.
+    fn common_args(&self) -> Result<Vec<String>, Error> {
+        let target = self.get_target()?;
+        let zig_target = self.zig_target()?;
+        let mut args = vec!["-target".to_owned(), zig_target.clone()];
+        if let Some(mcpu) = self.get_mcpu()? {
+            args.push(format!("-mcpu={mcpu}"));
+        }
+        let opt_level = match &self.opt_level {
+            _ if self.optimize_size => "z".into(),
+            Some(level) => level.clone(),
+            None => env::var("OPT_LEVEL").unwrap_or_else(|_| "0".into()),
+        };
+        args.push(format!("-O{opt_level}"));
+        if self.optimize_size {
+            args.extend(["-ffunction-sections".into(), "-fdata-sections".into()]);
+        }
+        if let Some(hardening) = &self.hardening {
+            args.extend(hardening.compile_args(&zig_target, opt_level != "0"));
+        }
+        if self.is_debug() {
+            args.push("-g".into());
+        }
+        // Debug info and `__FILE__` name sources, headers and generated files
+        // by these directories, so a checkout built elsewhere differs. The
+        // later map wins where both apply, and OUT_DIR is usually inside the
+        // package.
+        for (var, to) in [("CARGO_MANIFEST_DIR", "."), ("OUT_DIR", "out")] {
+            if let Some(dir) = env::var_os(var) {
+                args.push(format!(
+                    "-ffile-prefix-map={}={to}",
+                    Path::new(&dir).display()
+                ));
+            }
+        }
+        if self.is_pic(&target, &zig_target) {
+            args.push("-fPIC".into());
+        }
+        if target.starts_with("wasm") {
+            if self.single_threaded {
+                args.extend(["-mthread-model".into(), "single".into()]);
+            } else if self
+                .wasm_threads
+                .unwrap_or_else(|| target::wasm_threads_default(&target))
+            {
+                args.extend([
+                    "-pthread".into(),
+                    "-matomics".into(),
+                    "-mbulk-memory".into(),
+                ]);
+            }
+        }
+        if target.contains("-apple-") {
+            for dir in &self.framework_dirs {
+                args.push(format!("-F{}", dir.display()));
+            }
+        }
+        if let Some(sysroot) = &self.sysroot {
+            args.push(format!("--sysroot={}", sysroot.display()));
+        }
+        args.extend(self.include_args());
+        for (name, value) in &self.defines {
+            args.push(match value {
+                Some(value) => format!("-D{name}={value}"),
+                None => format!("-D{name}"),
+            });
+        }
+        if self.warnings_into_errors {
+            args.push("-Werror".into());
+        }
+        for name in &self.suppressed_warnings {
+            args.push(format!("-Wno-{name}"));
+        }
+        args.extend(self.flags.iter().cloned());
+        for flag in &self.flags_if_supported {
+            if self.is_flag_supported(flag)? {
+                args.push(flag.clone());
+            }
+        }
+        Ok(args)
+    }
+
+    /// `-I` for the include directories, `-isystem` for the
+    /// [third-party](Self::third_party) ones.
+    fn include_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for dir in &self.includes {
+            if self.is_third_party(dir) {
+                args.extend(["-isystem".into(), dir.display().to_string()]);
+            } else {
+                args.push(format!("-I{}", dir.display()));
+            }
+        }
+        args
+    }
+
+    /// Whether `path` is at or under the closest path marked with
+    /// [`third_party(.., true)`](Self::third_party).
+    fn is_third_party(&self, path: &Path) -> bool {
+        self.third_party
+            .iter()
+            .filter(|(marked, _)| path.starts_with(marked))
+            .max_by_key(|(marked, _)| marked.components().count())
+            .is_some_and(|&(_, third_party)| third_party)
+    }
+
+    /// `args` followed by `-w` for [third-party](Self::third_party) C and
+    /// C++ and the [`file_flag()`](Self::file_flag)s of `file`.
+    fn file_args(&self, file: &Path, args: &[String]) -> Vec<String> {
+        let mut args = args.to_vec();
+        if !is_zig(file) && self.is_third_party(file) {
+            args.push("-w".into());
+        }
+        args.extend(
+            self.file_flags
+                .iter()
+                .filter(|(path, _)| path == file)
+                .map(|(_, flag)| flag.clone()),
+        );
+        args
+    }
+
+    /// The flags of `zig build-obj` for `.zig` sources: target, optimize
+    /// mode, debug info, PIC, then the includes and defines `@cImport` sees.
+    fn zig_args(&self) -> Result<Vec<String>, Error> {
+        let target = self.get_target()?;
+        let zig_target = self.zig_target()?;
+        let mut args = vec!["-target".to_owned(), zig_target.clone()];
+        if let Some(mcpu) = self.get_mcpu()? {
+            args.push(format!("-mcpu={mcpu}"));
+        }
+        let optimize = match &self.optimize {
+            Some(mode) => mode.clone(),
+            None if self.optimize_size => "ReleaseSmall".into(),
+            None => match self
+                .opt_level
+                .clone()
+                .or_else(|| env::var("OPT_LEVEL").ok())
+                .as_deref()
+            {
+                Some("0") => "Debug".into(),
+                Some("s" | "z") => "ReleaseSmall".into(),
+                _ if env::var_os("CARGO_CFG_DEBUG_ASSERTIONS").is_some() => "ReleaseSafe".into(),
+                _ => "ReleaseFast".into(),
+            },
+        };
+        args.extend(["-O".into(), optimize]);
+        if self.optimize_size {
+            args.extend(["-ffunction-sections".into(), "-fdata-sections".into()]);
+        }
+        args.push(
+            if self.is_debug() {
+                "-fno-strip"
+            } else {
+                "-fstrip"
+            }
+            .into(),
+        );
+        args.push(
+            if self.is_pic(&target, &zig_target) {
+                "-fPIC"
+            } else {
+                "-fno-PIC"
+            }
+            .into(),
+        );
+        if self.single_threaded {
+            args.push("-fsingle-threaded".into());
+        }
+        if let Some(sysroot) = &self.sysroot {
+            args.extend(["--sysroot".into(), sysroot.display().to_string()]);
+        }
+        args.extend(self.include_args());
+        for (name, value) in &self.defines {
+            args.push(match value {
+                Some(value) => format!("-D{name}={value}"),
+                None => format!("-D{name}"),
+            });
+        }
+        let libc = !target.starts_with("wasm32-unknown")
+            && !target.contains("-none")
+            && !target.contains("-uefi");
+        if libc {
+            args.push("-lc".into());
+        }
+        Ok(args)
+    }
+
+    /// [`mcpu()`](Self::mcpu), or the CPU the project's configuration gives
+    /// for the target, checked or adjusted against the Rust target's
+    /// features as [`target_features()`](Self::target_features) says.
+    fn get_mcpu(&self) -> Result<Option<String>, Error> {
+        let mcpu = match &self.mcpu {
+            Some(mcpu) => Some(mcpu.clone()),
+            None => {
+                let config = Config::load().map_err(|e| Error::Config(e.to_string()))?;
+                if self.cargo_metadata {
+                    config.rerun_if_changed();
+                }
+                let target = self.get_target()?;
+                let (target, _) = target::split_glibc_version(&target);
+                config.mcpu(target).map(str::to_owned)
+            }
+        };
+        let target = self.get_target()?;
+        if self.target_features == FeaturePolicy::Ignore
+            || env::var("TARGET").ok().as_deref() != Some(target::split_glibc_version(&target).0)
+        {
+            return Ok(mcpu);
+        }
+        let base = mcpu.as_deref().unwrap_or("baseline");
+        let comparison = target_features::compare(
+            &self.toolchain,
+            &target,
+            &self.zig_target()?,
+            base,
+            &target_features::rust_features(),
+        )?;
+        if comparison.is_match() {
+            return Ok(mcpu);
+        }
+        if self.target_features == FeaturePolicy::Adjust {
+            return Ok(Some(comparison.adjusted_mcpu(base)));
+        }
+        let mut differences = Vec::new();
+        if !comparison.zig_only.is_empty() {
+            differences.push(format!(
+                "only the Zig code has {}",
+                comparison.zig_only.join(", ")
+            ));
+        }
+        if !comparison.rust_only.is_empty() {
+            differences.push(format!(
+                "only the Rust code has {}",
+                comparison.rust_only.join(", ")
+            ));
+        }
+        Err(Error::Config(format!(
+            "-mcpu={base} disagrees with the Rust target's features: {}; use -mcpu={}",
+            differences.join("; "),
+            comparison.adjusted_mcpu(base)
+        )))
+    }
+
+    fn is_debug(&self) -> bool {
+        !self.optimize_size
+            && self
+                .debug
+                .unwrap_or_else(|| env::var("DEBUG").is_ok_and(|d| d != "false"))
+    }
+
+    fn is_pic(&self, target: &str, zig_target: &str) -> bool {
+        self.pic.unwrap_or_else(|| {
+            !target.contains("-windows")
+                && !target.contains("-uefi")
+                && !zig_target.contains("-freestanding-")
+        })
+    }
+}
+
+/// Whether binaries for the rustc target `target` are ELF files.
+fn is_elf(target: &str) -> bool {
+    !target.contains("-apple-")
+        && !target.contains("-windows")
+        && !target.contains("-uefi")
+        && !target.starts_with("wasm")
+}
+
+/// Linker flags for [`Build::dynamic_linker()`] and
+/// [`Build::allow_shlib_undefined()`] on `target`, which the
+/// [`shims`](crate::shims) pass as well.
+pub(crate) fn loader_link_args(
+    target: &str,
+    dynamic_linker: Option<&Path>,
+    allow_shlib_undefined: Option<bool>,
+) -> Result<Vec<String>, Error> {
+    let mut args = Vec::new();
+    if target::uses_solaris_ld(target) {
+        if let Some(path) = dynamic_linker {
+            args.push(format!("-Wl,-I,{}", path.display()));
+        }
+        // Undefined symbols in dependencies are always allowed.
+        if allow_shlib_undefined == Some(false) {
+            return Err(Error::Config(format!(
+                "the Solaris link editor can't reject undefined symbols of shared libraries for {target}"
+            )));
+        }
+    } else if is_elf(target) {
+        if let Some(path) = dynamic_linker {
+            args.push(format!("-Wl,--dynamic-linker={}", path.display()));
+        }
+        match allow_shlib_undefined {
+            Some(true) => args.push("-Wl,--allow-shlib-undefined".into()),
+            Some(false) => args.push("-Wl,--no-allow-shlib-undefined".into()),
+            None => {}
+        }
+    }
+    Ok(args)
+}
+
+/// Linker flags for [`Build::windows_subsystem()`] and
+/// [`Build::stack_size()`] on `target`, spelled for `link.exe` when `msvc`
+/// and for the `zig cc` driver otherwise, as the [`shims`](crate::shims)
+/// pass them.
+pub(crate) fn subsystem_link_args(
+    target: &str,
+    subsystem: Option<Subsystem>,
+    stack_size: Option<u64>,
+    msvc: bool,
+) -> Result<Vec<String>, Error> {
+    let mut args = Vec::new();
+    if let Some(subsystem) = subsystem {
+        let efi = matches!(
+            subsystem,
+            Subsystem::EfiApplication
+                | Subsystem::EfiBootServiceDriver
+                | Subsystem::EfiRuntimeDriver
+        );
+        if efi != target.contains("-uefi") {
+            return Err(Error::Config(format!(
+                "the {} subsystem doesn't exist for {target}",
+                subsystem.name()
+            )));
+        }
+        let name = subsystem.name();
+        args.push(if msvc {
+            format!("/SUBSYSTEM:{}", name.to_uppercase())
+        } else {
+            format!("-Wl,--subsystem,{name}")
+        });
+    }
+    if let Some(bytes) = stack_size {
+        args.push(if msvc {
+            format!("/STACK:{bytes}")
+        } else {
+            format!("-Wl,--stack,{bytes}")
+        });
+    }
+    Ok(args)
+}
+
+/// Writes `dir/manifest.rc`, a resource script embedding the application
+/// manifest at `manifest` (an absolute path), and returns the `zig rc`
+/// command compiling it to the returned `dir/manifest.res`, which the linker
+/// takes as an input.
+pub(crate) fn manifest_resource(
+    toolchain: &Toolchain,
+    manifest: &Path,
+    dir: &Path,
+) -> io::Result<(Command, PathBuf)> {
+    // 24 is RT_MANIFEST, 1 the ID Windows looks for in executables.
+    let rc = dir.join("manifest.rc");
+    let res = dir.join("manifest.res");
+    let path = manifest.display().to_string().replace('\\', "\\\\");
+    fs_err::write(&rc, format!("1 24 \"{path}\"\n"))?;
+    let mut cmd = toolchain.command();
+    cmd.arg("rc").arg(&rc).arg(&res);
+    Ok((cmd, res))
+}
+
+/// The linker flag of [`Build::no_undefined()`] for shared libraries of
+/// `target`, if it has one.
+pub(crate) fn no_undefined_link_arg(target: &str) -> Option<&'static str> {
+    if target.contains("-apple-") {
+        Some("-Wl,-undefined,error")
+    } else if is_elf(target) {
+        Some("-Wl,-z,defs")
+    } else {
+        None
+    }
+}
+
+/// Whether `arg` looks like a `link.exe` option such as `/NXCOMPAT` or
+/// `/DEBUG:FULL` rather than an absolute path.
+fn is_link_exe_option(arg: &str) -> bool {
+    arg.strip_prefix('/')
+        .map(|rest| rest.split(':').next().unwrap_or(rest))
+        .is_some_and(|name| !name.is_empty() && name.bytes().all(|b| b.is_ascii_uppercase()))
+}
+
+/// Whether rustc links for `target` with a `link.exe`-style linker and
+/// `<name>.lib` archives: MSVC and UEFI targets.
+fn is_msvc_like(target: &str) -> bool {
+    target.ends_with("-msvc") || target.contains("-uefi")
+}
+
+/// The linker driver flag that garbage-collects unreferenced sections.
+fn gc_sections_link_arg(target: &str) -> &'static str {
+    if target.contains("-apple-") {
+        "-Wl,-dead_strip"
+    } else if target::uses_solaris_ld(target) {
+        "-Wl,-z,ignore"
+    } else if is_msvc_like(target) {
+        "/OPT:REF"
+    } else {
+        "-Wl,--gc-sections"
+    }
+}
+
+/// The linker driver flag that strips symbols and debug info from the
+/// output, or `None` for `link.exe`-style linkers, which keep them in a PDB.
+fn strip_link_arg(target: &str) -> Option<&'static str> {
+    if target.contains("-apple-") {
+        Some("-Wl,-S,-x")
+    } else if target::uses_solaris_ld(target) {
+        Some("-Wl,-s")
+    } else if is_msvc_like(target) {
+        None
+    } else {
+        Some("-Wl,--strip-all")
+    }
+}
+
+fn is_zig(file: &Path) -> bool {
+    file.extension().is_some_and(|e| e == "zig")
+}
+
+fn is_cpp(file: &Path) -> bool {
+    matches!(
+        file.extension().and_then(|e| e.to_str()),
+        Some("cc" | "cpp" | "cxx" | "c++" | "C")
+    )
+}
@@ -0,0 +1,145 @@
+//! The `<lib>.fingerprint` file [`Build`](super::Build) writes next to each
+//! library: the Zig version, the compiler and archiver flags, the source list
+//! and a digest of every file the compiler read (sources and the project's
+//! headers, from the `-MMD` dependency files, and the Zig files
+//! [`zig_imports`](crate::zig_imports) finds). A build whose fingerprint still
+//! matches is skipped, and the recorded inputs become the
+//! `cargo:rerun-if-changed` list. Each object gets an `<object>.fingerprint`
+//! of its own, without archiver flags, so when a build isn't skipped only the
+//! sources whose inputs changed are compiled again.
+//!
+//! ```text
+//! zig_version 0.14.0
+//! flag -target
+//! flag x86_64-linux-gnu
+//! ar_flags crsD
+//! file src/foo.c
+//! input 4c1a…e0  src/foo.c
+//! input 93bd…7f  include/foo.h
+//! ```
+
+use std::{
+    fmt::Write as _,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::manifest;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Fingerprint {
+    pub zig_version: String,
+    pub flags: Vec<String>,
+    pub ar_flags: String,
+    pub files: Vec<PathBuf>,
+    /// `(digest, path)` of every file the compiler read.
+    pub inputs: Vec<(String, PathBuf)>,
+}
+
+/// Where the fingerprint of `lib` lives.
+pub(crate) fn path_for(lib: &Path) -> PathBuf {
+    let mut name = lib.file_name().unwrap_or_default().to_owned();
+    name.push(".fingerprint");
+    lib.with_file_name(name)
+}
+
+impl Fingerprint {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let mut fingerprint = Self::default();
+        for line in fs_err::read_to_string(path)?.lines() {
+            let malformed = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: malformed line {line:?}", path.display()),
+                )
+            };
+            let (key, value) = line.split_once(' ').ok_or_else(malformed)?;
+            match key {
+                "zig_version" => fingerprint.zig_version = value.into(),
+                "flag" => fingerprint.flags.push(value.into()),
+                "ar_flags" => fingerprint.ar_flags = value.into(),
+                "file" => fingerprint.files.push(value.into()),
+                "input" => {
+                    let (digest, path) = value.split_once("  ").ok_or_else(malformed)?;
+                    fingerprint.inputs.push((digest.into(), path.into()));
+                }
+                _ => return Err(malformed()),
+            }
+        }
+        Ok(fingerprint)
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut contents = format!("zig_version {}\n", self.zig_version);
+        for flag in &self.flags {
+            writeln!(contents, "flag {flag}").expect("writing to a String");
+        }
+        writeln!(contents, "ar_flags {}", self.ar_flags).expect("writing to a String");
+        for file in &self.files {
+            writeln!(contents, "file {}", file.display()).expect("writing to a String");
+        }
+        for (digest, input) in &self.inputs {
+            writeln!(contents, "input {digest}  {}", input.display()).expect("writing to a String");
+        }
+        fs_err::write(path, contents)
+    }
+
+    /// Whether a build recorded as `self` still matches `current`, which has
+    /// no inputs yet: same version, flags and sources, and no input changed
+    /// on disk since.
+    pub fn is_up_to_date(&self, current: &Self) -> bool {
+        self.zig_version == current.zig_version
+            && self.flags == current.flags
+            && self.ar_flags == current.ar_flags
+            && self.files == current.files
+            && self
+                .inputs
+                .iter()
+                .all(|(digest, path)| manifest::hash_file(path).is_ok_and(|d| d == *digest))
+    }
+
+    /// Digests `paths` into the input list, skipping ones already there.
+    pub fn add_inputs(&mut self, paths: impl IntoIterator<Item = PathBuf>) -> io::Result<()> {
+        for path in paths {
+            if !self.inputs.iter().any(|(_, p)| *p == path) {
+                self.inputs.push((manifest::hash_file(&path)?, path));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The prerequisites listed in a Makefile-style dependency file as written by
+/// `-MMD -MF`.
+pub(crate) fn read_depfile(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let contents = fs_err::read_to_string(path)?;
+    // The target may be a Windows path with a drive letter, so look for the
+    // separator with whitespace after it.
+    let Some(start) = contents
+        .find(": ")
+        .or_else(|| contents.find(":\n"))
+        .or_else(|| contents.find(":\r\n"))
+    else {
+        return Ok(Vec::new());
+    };
+    let mut paths = Vec::new();
+    let mut current = String::new();
+    let mut chars = contents[start + 1..].chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&' ') => current.push(chars.next().unwrap()),
+            '\\' if matches!(chars.peek(), Some('\n' | '\r')) => {}
+            '$' if chars.peek() == Some(&'$') => current.push(chars.next().unwrap()),
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    paths.push(PathBuf::from(std::mem::take(&mut current)));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        paths.push(PathBuf::from(current));
+    }
+    Ok(paths)
+}
@@ -0,0 +1,236 @@
+//! Project configuration read from `zig-rs.toml` files and Cargo manifest
+//! metadata, for settings a whole workspace should agree on:
+//!
+//! ```toml
+//! # zig-rs.toml, or [package.metadata.zig-rs] / [workspace.metadata.zig-rs]
+//! # in Cargo.toml
+//! [mcpu]
+//! x86_64-unknown-linux-gnu = "x86_64_v3"
+//! aarch64-apple-darwin = "apple_m1"
+//!
+//! [bootstrap]
+//! cc = "clang"
+//! cxx = "clang++"
+//! cmake-args = ["-DLLVM_ENABLE_LIBXML2=OFF"]
+//! assertions = true
+//! ```
+//!
+//! The `[bootstrap]` table is for the zig-bootstrap build of the toolchain;
+//! see [`BootstrapOptions`]. Only the build script of a path dependency or
+//! one pointed at a file with [`CONFIG_ENV`] sees it.
+//!
+//! [`Config::load()`] reads [`CONFIG_ENV`] when it is set and otherwise every
+//! `zig-rs.toml` and `Cargo.toml` from `CARGO_MANIFEST_DIR` (or the current
+//! directory) up to the filesystem root, with closer files winning and a
+//! `zig-rs.toml` winning over the `Cargo.toml` next to it. Build
+//! scripts of dependent crates thus see their workspace's configuration; this
+//! crate's own build script, which lives in the Cargo registry, only sees it
+//! through [`CONFIG_ENV`] or a path dependency.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use toml::Table;
+
+/// Environment variable naming the one configuration file to read.
+pub const CONFIG_ENV: &str = "ZIG_RS_CONFIG";
+
+pub const FILE_NAME: &str = "zig-rs.toml";
+
+/// The merged configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// Zig `-mcpu` values by rustc target.
+    pub mcpu: BTreeMap<String, String>,
+    pub bootstrap: BootstrapOptions,
+    /// Files the configuration came from, for `cargo:rerun-if-changed`.
+    pub sources: Vec<PathBuf>,
+}
+
+/// How zig-bootstrap builds the toolchain, beyond the target and CPU.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BootstrapOptions {
+    /// The host C compiler CMake builds with, as `CC`.
+    pub cc: Option<String>,
+    /// The host C++ compiler CMake builds with, as `CXX`.
+    pub cxx: Option<String>,
+    /// `-DNAME[:TYPE]=VALUE` cache entries for every CMake project
+    /// zig-bootstrap configures.
+    pub cmake_args: Vec<String>,
+    /// Whether LLVM is built with assertions, `LLVM_ENABLE_ASSERTIONS`.
+    pub assertions: Option<bool>,
+    /// Arguments after the target and CPU, for forks of zig-bootstrap whose
+    /// build script takes more.
+    pub args: Vec<String>,
+}
+
+impl BootstrapOptions {
+    /// Whether nothing is set, so zig-bootstrap builds what it always does.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The options as a `[bootstrap]` table, to read back with
+    /// [`Config::load()`].
+    pub fn to_toml(&self) -> String {
+        let mut table = Table::new();
+        if let Some(cc) = &self.cc {
+            table.insert("cc".into(), cc.clone().into());
+        }
+        if let Some(cxx) = &self.cxx {
+            table.insert("cxx".into(), cxx.clone().into());
+        }
+        table.insert("cmake-args".into(), self.cmake_args.clone().into());
+        if let Some(assertions) = self.assertions {
+            table.insert("assertions".into(), assertions.into());
+        }
+        table.insert("args".into(), self.args.clone().into());
+        let mut root = Table::new();
+        root.insert("bootstrap".into(), table.into());
+        root.to_string()
+    }
+
+    fn merge(&mut self, path: &Path, table: &Table) -> Result<(), Box<dyn Error>> {
+        let string = |key: &str| -> Result<Option<String>, Box<dyn Error>> {
+            match table.get(key) {
+                Some(value) => Ok(Some(value.as_str().map(str::to_owned).ok_or_else(
+                    || format!("{}: bootstrap.{key} is not a string", path.display()),
+                )?)),
+                None => Ok(None),
+            }
+        };
+        let strings = |key: &str| -> Result<Option<Vec<String>>, Box<dyn Error>> {
+            let Some(value) = table.get(key) else {
+                return Ok(None);
+            };
+            value
+                .as_array()
+                .and_then(|array| {
+                    array
+                        .iter()
+                        .map(|value| value.as_str().map(str::to_owned))
+                        .collect()
+                })
+                .map(Some)
+                .ok_or_else(|| {
+                    format!(
+                        "{}: bootstrap.{key} is not an array of strings",
+                        path.display()
+                    )
+                    .into()
+                })
+        };
+        if let Some(cc) = string("cc")? {
+            self.cc = Some(cc);
+        }
+        if let Some(cxx) = string("cxx")? {
+            self.cxx = Some(cxx);
+        }
+        if let Some(cmake_args) = strings("cmake-args")? {
+            self.cmake_args = cmake_args;
+        }
+        if let Some(assertions) = table.get("assertions") {
+            self.assertions = Some(assertions.as_bool().ok_or_else(|| {
+                format!("{}: bootstrap.assertions is not a boolean", path.display())
+            })?);
+        }
+        if let Some(args) = strings("args")? {
+            self.args = args;
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Finds and merges the configuration as described in the
+    /// [module docs](self).
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let mut config = Self::default();
+        if let Some(path) = env::var_os(CONFIG_ENV) {
+            config.merge_file(Path::new(&path), None)?;
+            return Ok(config);
+        }
+        let start = match env::var_os("CARGO_MANIFEST_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => env::current_dir()?,
+        };
+        // Farthest first, so closer files override.
+        let dirs: Vec<&Path> = start.ancestors().collect();
+        for dir in dirs.into_iter().rev() {
+            let manifest = dir.join("Cargo.toml");
+            if manifest.is_file() {
+                for section in ["workspace", "package"] {
+                    config.merge_file(&manifest, Some(section))?;
+                }
+            }
+            let file = dir.join(FILE_NAME);
+            if file.is_file() {
+                config.merge_file(&file, None)?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// The `-mcpu` configured for `rust_target`.
+    pub fn mcpu(&self, rust_target: &str) -> Option<&str> {
+        self.mcpu.get(rust_target).map(String::as_str)
+    }
+
+    /// Prints `cargo:rerun-if-changed` for every source and
+    /// `cargo:rerun-if-env-changed` for [`CONFIG_ENV`].
+    pub fn rerun_if_changed(&self) {
+        build::rerun_if_env_changed(CONFIG_ENV);
+        for path in &self.sources {
+            build::rerun_if_changed(path);
+        }
+    }
+
+    /// Merges `path`, or its `[<section>.metadata.zig-rs]` table for a Cargo
+    /// manifest.
+    fn merge_file(&mut self, path: &Path, section: Option<&str>) -> Result<(), Box<dyn Error>> {
+        if !self.sources.iter().any(|p| p == path) {
+            self.sources.push(path.to_owned());
+        }
+        let table: Table = fs_err::read_to_string(path)?
+            .parse()
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        let table = match section {
+            Some(section) => match table
+                .get(section)
+                .and_then(|s| s.get("metadata"))
+                .and_then(|m| m.get("zig-rs"))
+            {
+                Some(table) => table.as_table().ok_or_else(|| {
+                    format!(
+                        "{}: {section}.metadata.zig-rs is not a table",
+                        path.display()
+                    )
+                })?,
+                None => return Ok(()),
+            },
+            None => &table,
+        };
+        if let Some(mcpu) = table.get("mcpu") {
+            let mcpu = mcpu
+                .as_table()
+                .ok_or_else(|| format!("{}: mcpu is not a table", path.display()))?;
+            for (target, cpu) in mcpu {
+                let cpu = cpu
+                    .as_str()
+                    .ok_or_else(|| format!("{}: mcpu.{target} is not a string", path.display()))?;
+                self.mcpu.insert(target.clone(), cpu.into());
+            }
+        }
+        if let Some(bootstrap) = table.get("bootstrap") {
+            let bootstrap = bootstrap
+                .as_table()
+                .ok_or_else(|| format!("{}: bootstrap is not a table", path.display()))?;
+            self.bootstrap.merge(path, bootstrap)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+//! Bug reports of `zig` crashing, for turning a panic, a segfault or an LLVM
+//! assertion deep inside a build into something Zig's issue tracker can act
+//! on. [`report()`] reruns the command, drops the flags the crash doesn't
+//! need and gathers what a report needs into one directory; `cargo zig
+//! report` does the same from the command line:
+//!
+//! ```sh
+//! cargo zig report --out zig-crash -- build-obj -OReleaseFast -target aarch64-linux-gnu src/main.zig
+//! ```
+//!
+//! The directory holds
+//!
+//! ```text
+//! <out>/ISSUE.md       the report, ready to paste into an issue
+//! <out>/reproduce.sh   the smallest command that still crashes
+//! <out>/output.txt     what the crashing command printed
+//! <out>/version.txt    `zig version`
+//! <out>/env.json       `zig env`
+//! <out>/builtin.zig    `--show-builtin` for the command's target
+//! <out>/sources/       the files named on the command line
+//! ```
+//!
+//! Only files named on the command line are copied: headers and imports
+//! they pull in from elsewhere aren't, so check `sources/` reproduces the
+//! crash before filing it. [`CommandError`](crate::error::CommandError)s of
+//! crashes point at `cargo zig report`.
+
+use std::{
+    ffi::OsString,
+    fmt::Write as _,
+    io,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+};
+
+use crate::{Toolchain, error::quote, process};
+
+/// How many times [`report()`] reruns the command while minimizing it.
+pub const MAX_RUNS: usize = 64;
+
+/// A crash gathered by [`report()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// The directory it was written to.
+    pub dir: PathBuf,
+    pub zig_version: String,
+    /// The command's arguments, after `zig`.
+    pub args: Vec<String>,
+    /// The arguments with the flags the crash doesn't need dropped.
+    pub minimized: Vec<String>,
+    /// What identifies the crash; see [`signature()`].
+    pub signature: String,
+    /// What the crashing command printed.
+    pub output: String,
+}
+
+/// What identifies a crash of `zig` in the `output` of a command that exited
+/// with `status`, e.g. `panic: reached unreachable code` or `LLVM ERROR: ...`,
+/// or `None` when the command failed, or succeeded, without crashing.
+/// Addresses and thread IDs are left out, so reruns of the same crash have
+/// the same signature.
+pub fn signature(status: ExitStatus, output: &str) -> Option<String> {
+    for line in output.lines().map(str::trim) {
+        if let Some((_, message)) = line.split_once(" panic: ")
+            && line.starts_with("thread ")
+        {
+            return Some(format!("panic: {message}"));
+        }
+        if line.starts_with("LLVM ERROR:") || line.starts_with("error: unreachable code") {
+            return Some(line.into());
+        }
+        for fault in ["Segmentation fault", "Illegal instruction", "Bus error"] {
+            if line.starts_with(fault) {
+                return Some(fault.into());
+            }
+        }
+        if line.starts_with("PLEASE submit a bug report") {
+            return Some("clang crashed".into());
+        }
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return Some(format!("killed by signal {signal}"));
+        }
+    }
+    // NTSTATUS errors: access violation, stack overflow and the like.
+    match status.code() {
+        Some(code) if cfg!(windows) && code as u32 & 0xC000_0000 == 0xC000_0000 => {
+            Some(format!("exception {:#010x}", code as u32))
+        }
+        _ => None,
+    }
+}
+
+/// Runs `zig <args>` with `toolchain` in `dir` and, if it crashes, writes
+/// its [bug report bundle](self) to `out` and returns it. `None` when the
+/// command doesn't crash. With `minimize`, flags are dropped one at a time,
+/// with their values, while the command keeps crashing the same way, for at
+/// most [`MAX_RUNS`] reruns.
+pub fn report(
+    toolchain: &Toolchain,
+    args: &[OsString],
+    dir: &Path,
+    out: &Path,
+    minimize: bool,
+) -> io::Result<Option<Report>> {
+    let args: Vec<String> = args
+        .iter()
+        .map(|arg| {
+            arg.to_str().map(ToOwned::to_owned).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} isn't UTF-8", arg.display()),
+                )
+            })
+        })
+        .collect::<io::Result<_>>()?;
+    let (output, Some(signature)) = run(toolchain, &args, dir)? else {
+        return Ok(None);
+    };
+    let minimized = if minimize {
+        self::minimize(toolchain, &args, dir, &signature)?
+    } else {
+        args.clone()
+    };
+    let zig_version = toolchain
+        .query_version()
+        .map_or_else(|_| toolchain.zig_version().to_owned(), |v| v.to_string());
+
+    fs_err::create_dir_all(out)?;
+    let sources = out.join("sources");
+    let mut reproduce = vec!["zig".to_owned()];
+    for arg in &minimized {
+        reproduce.push(quote(&copy_source(arg, dir, &sources)?));
+    }
+    fs_err::write(
+        out.join("reproduce.sh"),
+        format!(
+            "#!/bin/sh\ncd \"$(dirname \"$0\")/sources\" || exit\n{}\n",
+            reproduce.join(" ")
+        ),
+    )?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs_err::set_permissions(
+            out.join("reproduce.sh"),
+            std::fs::Permissions::from_mode(0o755),
+        )?;
+    }
+    fs_err::create_dir_all(&sources)?;
+    fs_err::write(out.join("output.txt"), &output)?;
+    fs_err::write(out.join("version.txt"), format!("{zig_version}\n"))?;
+    if let Ok(env) = process::output(toolchain.command().arg("env"))
+        && env.status.success()
+    {
+        fs_err::write(out.join("env.json"), env.stdout)?;
+    }
+    let mut builtin = toolchain.command();
+    builtin.args(["build-obj", "--show-builtin"]);
+    builtin.args(target_args(&minimized));
+    if let Ok(builtin) = process::output(&mut builtin)
+        && builtin.status.success()
+    {
+        fs_err::write(out.join("builtin.zig"), builtin.stdout)?;
+    }
+
+    let report = Report {
+        dir: out.to_owned(),
+        zig_version,
+        args,
+        minimized,
+        signature,
+        output,
+    };
+    fs_err::write(out.join("ISSUE.md"), report.issue())?;
+    Ok(Some(report))
+}
+
+impl Report {
+    /// The text of an issue for Zig's tracker, in Markdown.
+    pub fn issue(&self) -> String {
+        let command = |args: &[String]| {
+            let mut words = vec!["zig".to_owned()];
+            words.extend(args.iter().map(|arg| quote(arg)));
+            words.join(" ")
+        };
+        let mut issue = String::new();
+        let _ = writeln!(issue, "# Zig {}: {}\n", self.zig_version, self.signature);
+        let _ = writeln!(issue, "## Zig Version\n\n{}\n", self.zig_version);
+        let _ = writeln!(issue, "## Steps to Reproduce\n");
+        let _ = writeln!(
+            issue,
+            "With the attached `sources/`:\n\n```sh\n{}\n```\n",
+            command(&self.minimized)
+        );
+        if self.minimized != self.args {
+            let _ = writeln!(
+                issue,
+                "reduced from\n\n```sh\n{}\n```\n",
+                command(&self.args)
+            );
+        }
+        let _ = writeln!(issue, "## Output\n\n```\n{}\n```", self.output.trim_end());
+        issue
+    }
+}
+
+/// Runs `zig <args>` and returns its output and crash signature.
+fn run(toolchain: &Toolchain, args: &[String], dir: &Path) -> io::Result<(String, Option<String>)> {
+    let output = process::output(toolchain.command().args(args).current_dir(dir))?;
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text += &String::from_utf8_lossy(&output.stderr);
+    let signature = signature(output.status, &text);
+    Ok((text, signature))
+}
+
+/// `args` without the flags that `signature` doesn't need. The subcommand
+/// and the inputs always stay.
+fn minimize(
+    toolchain: &Toolchain,
+    args: &[String],
+    dir: &Path,
+    signature: &str,
+) -> io::Result<Vec<String>> {
+    let mut args = args.to_vec();
+    let mut runs = 0;
+    let mut i = args.len();
+    while i > 1 && runs < MAX_RUNS {
+        i -= 1;
+        if !args[i].starts_with('-') {
+            continue;
+        }
+        // A flag with its value first, then the flag alone.
+        let with_value = args.get(i + 1).is_some_and(|next| !next.starts_with('-'));
+        for len in [2, 1].into_iter().filter(|&len| len == 1 || with_value) {
+            if runs == MAX_RUNS {
+                break;
+            }
+            let mut candidate = args.clone();
+            candidate.drain(i..i + len);
+            runs += 1;
+            if run(toolchain, &candidate, dir)?.1.as_deref() == Some(signature) {
+                args = candidate;
+                break;
+            }
+        }
+    }
+    Ok(args)
+}
+
+/// Copies the file `arg` names, if it names one, into `sources` and returns
+/// the argument for running from there. `-Mname=path` and `--flag=path`
+/// arguments name the file after the `=`.
+fn copy_source(arg: &str, dir: &Path, sources: &Path) -> io::Result<String> {
+    let (prefix, path) = match arg.rsplit_once('=') {
+        Some((prefix, path)) if arg.starts_with('-') => (format!("{prefix}="), path),
+        _ if arg.starts_with('-') => return Ok(arg.into()),
+        _ => (String::new(), arg),
+    };
+    let from = dir.join(path);
+    if !from.is_file() {
+        return Ok(arg.into());
+    }
+    // Relative paths below `dir` keep their place, so imports between the
+    // sources still resolve; the rest go at the top.
+    let relative = Path::new(path);
+    let relative = if relative.is_relative() && !path.split(['/', '\\']).any(|part| part == "..") {
+        relative.to_owned()
+    } else {
+        PathBuf::from(relative.file_name().unwrap_or(relative.as_os_str()))
+    };
+    let to = sources.join(&relative);
+    if let Some(parent) = to.parent() {
+        fs_err::create_dir_all(parent)?;
+    }
+    fs_err::copy(&from, &to)?;
+    Ok(format!(
+        "{prefix}{}",
+        relative.to_string_lossy().replace('\\', "/")
+    ))
+}
+
+/// The `-target` and `-mcpu` arguments among `args`.
+fn target_args(args: &[String]) -> Vec<String> {
+    let mut target = Vec::new();
+    for (i, arg) in args.iter().enumerate() {
+        match arg.as_str() {
+            "-target" | "-mcpu" => target.extend(args.get(i..i + 2).into_iter().flatten().cloned()),
+            arg if arg.starts_with("-mcpu=") => target.push(arg.into()),
+            _ => {}
+        }
+    }
+    target
+}
@@ -0,0 +1,120 @@
+//! Compiler diagnostics parsed from `zig` and `zig cc` output. Both print the
+//! familiar `path:line:column: severity: message` form, Zig followed by source
+//! excerpts and `note:` lines, clang by caret lines; only the headline lines
+//! are kept.
+//!
+//! A header included by many sources repeats its warnings once per
+//! compile; [`group()`] folds the repeats into one diagnostic with a
+//! [`count`](Diagnostic::count).
+
+use std::{collections::HashMap, fmt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Note => "note",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Source path as printed by the compiler.
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+    pub message: String,
+    /// The clang warning flag in brackets at the end of the message, e.g.
+    /// `-Wunused-variable`.
+    pub flag: Option<String>,
+    /// How many times it was printed: 1 as parsed, more after [`group()`].
+    pub count: usize,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}: {}",
+            self.file, self.line, self.column, self.severity, self.message
+        )?;
+        if let Some(flag) = &self.flag {
+            write!(f, " [{flag}]")?;
+        }
+        if self.count > 1 {
+            write!(f, " ({} times)", self.count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses every diagnostic headline in `output`.
+pub fn parse(output: &str) -> Vec<Diagnostic> {
+    output.lines().filter_map(parse_line).collect()
+}
+
+/// `diagnostics` with the ones that only differ in their
+/// [`count`](Diagnostic::count) merged, adding up the counts, in the order
+/// each first appears.
+pub fn group(diagnostics: impl IntoIterator<Item = Diagnostic>) -> Vec<Diagnostic> {
+    let mut grouped: Vec<Diagnostic> = Vec::new();
+    let mut index: HashMap<Diagnostic, usize> = HashMap::new();
+    for diagnostic in diagnostics {
+        let key = Diagnostic {
+            count: 0,
+            ..diagnostic.clone()
+        };
+        match index.get(&key) {
+            Some(&i) => grouped[i].count += diagnostic.count,
+            None => {
+                index.insert(key, grouped.len());
+                grouped.push(diagnostic);
+            }
+        }
+    }
+    grouped
+}
+
+fn parse_line(line: &str) -> Option<Diagnostic> {
+    let (severity, location, message) = [
+        (Severity::Error, ": error: "),
+        (Severity::Error, ": fatal error: "),
+        (Severity::Warning, ": warning: "),
+        (Severity::Note, ": note: "),
+    ]
+    .iter()
+    .find_map(|&(severity, marker)| {
+        let (location, message) = line.split_once(marker)?;
+        Some((severity, location, message))
+    })?;
+    // Split from the right so Windows drive letters stay in the path.
+    let mut parts = location.rsplitn(3, ':');
+    let column = parts.next()?.parse().ok()?;
+    let line = parts.next()?.parse().ok()?;
+    let file = parts.next()?.trim().to_owned();
+    let (message, flag) = match message.rsplit_once(" [") {
+        Some((text, flag)) if flag.starts_with("-W") && flag.ends_with(']') => {
+            (text.to_owned(), Some(flag.trim_end_matches(']').to_owned()))
+        }
+        _ => (message.to_owned(), None),
+    };
+    Some(Diagnostic {
+        severity,
+        file,
+        line,
+        column,
+        message,
+        flag,
+        count: 1,
+    })
+}
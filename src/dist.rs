@@ -0,0 +1,278 @@
+//! A `dist/` tree of the native outputs of many targets, for release
+//! packaging of hybrid projects. Each target gets a directory of its own,
+//! split as an install prefix would be:
+//!
+//! ```text
+//! <dist>/<rust-target>/bin/
+//! <dist>/<rust-target>/lib/
+//! <dist>/<rust-target>/include/
+//! <dist>/SHA256SUMS
+//! <dist>/zig-rs.dist
+//! ```
+//!
+//! `SHA256SUMS` checks with `sha256sum -c` from `<dist>`, and `zig-rs.dist`
+//! is the [`Index`] of every file with its target and kind:
+//!
+//! ```no_run
+//! use zig_rs::{Build, dist::Dist, zig_exe::ZigExe};
+//!
+//! let mut dist = Dist::new("dist");
+//! for target in ["x86_64-unknown-linux-gnu", "aarch64-apple-darwin"] {
+//!     let lib = Build::new()
+//!         .file("src/foo.c")
+//!         .target(target)
+//!         .emit_bindings(true)
+//!         .cargo_metadata(false)
+//!         .out_dir(format!("target/zig-out/{target}"))
+//!         .try_compile("foo")?;
+//!     dist.add_build(target, &lib)?;
+//!     let exe = ZigExe::new("foo-cli", "zig/main.zig")
+//!         .target(target)
+//!         .staticlib(&lib.lib)
+//!         .out_dir(format!("target/zig-out/{target}"))
+//!         .build_artifacts()?;
+//!     dist.add_exe(target, &exe)?;
+//! }
+//! let index = dist.finish()?;
+//! println!("{} files", index.files.len());
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{compile, manifest, zig_exe};
+
+/// File name of the [`Index`] in a dist directory.
+pub const INDEX_FILE_NAME: &str = "zig-rs.dist";
+
+/// File name of the checksums in a dist directory.
+pub const CHECKSUMS_FILE_NAME: &str = "SHA256SUMS";
+
+/// The directory of a target a file goes in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    /// Executables and their debug info.
+    Bin,
+    /// Libraries.
+    Lib,
+    /// C headers.
+    Include,
+}
+
+impl Kind {
+    /// The directory's name, which is also how the [`Index`] spells it.
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            Self::Bin => "bin",
+            Self::Lib => "lib",
+            Self::Include => "include",
+        }
+    }
+
+    fn from_dir_name(name: &str) -> Option<Self> {
+        [Self::Bin, Self::Lib, Self::Include]
+            .into_iter()
+            .find(|kind| kind.dir_name() == name)
+    }
+}
+
+/// A dist directory being filled. See the [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct Dist {
+    root: PathBuf,
+    files: Vec<File>,
+}
+
+/// The files of a dist directory, written by [`Dist::finish()`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Index {
+    pub files: Vec<File>,
+}
+
+/// A file in a dist directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct File {
+    /// The rustc target it was built for.
+    pub target: String,
+    pub kind: Kind,
+    /// Its path relative to the dist directory, with forward slashes.
+    pub path: String,
+    /// SHA-256 of the file.
+    pub sha256: String,
+}
+
+impl Dist {
+    /// A dist tree at `root`, which is created as files are added.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            files: Vec::new(),
+        }
+    }
+
+    /// Copies the file or directory at `path` into the `kind` directory of
+    /// `target`, e.g. a `.dSYM` bundle into `<dist>/<target>/bin/`.
+    pub fn add(&mut self, target: &str, kind: Kind, path: &Path) -> io::Result<()> {
+        let name = path.file_name().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} has no file name", path.display()),
+            )
+        })?;
+        let relative = Path::new(target).join(kind.dir_name()).join(name);
+        self.copy(target, kind, path, &relative)
+    }
+
+    /// Adds what a [`Build`](crate::Build) wrote for `target`: the library,
+    /// its detached signature and the
+    /// [runtime libraries](compile::Artifacts::runtime_libs) to `lib/`, and
+    /// the header of [`emit_bindings()`](crate::Build::emit_bindings) to
+    /// `include/`.
+    pub fn add_build(&mut self, target: &str, artifacts: &compile::Artifacts) -> io::Result<()> {
+        self.add(target, Kind::Lib, &artifacts.lib)?;
+        if let Some(detached) = artifacts
+            .signature
+            .as_ref()
+            .and_then(|s| s.detached.as_ref())
+        {
+            self.add(target, Kind::Lib, detached)?;
+        }
+        for lib in &artifacts.runtime_libs {
+            self.add(target, Kind::Lib, lib)?;
+        }
+        if let Some((_, header)) = &artifacts.bindings {
+            self.add(target, Kind::Include, header)?;
+        }
+        Ok(())
+    }
+
+    /// Adds what a [`ZigExe`](zig_exe::ZigExe) wrote for `target`, the
+    /// executable, its separate debug info and its detached signature, to
+    /// `bin/`.
+    pub fn add_exe(&mut self, target: &str, artifacts: &zig_exe::Artifacts) -> io::Result<()> {
+        self.add(target, Kind::Bin, &artifacts.exe)?;
+        if let Some(debug_info) = &artifacts.debug_info {
+            self.add(target, Kind::Bin, debug_info)?;
+        }
+        if let Some(detached) = artifacts
+            .signature
+            .as_ref()
+            .and_then(|s| s.detached.as_ref())
+        {
+            self.add(target, Kind::Bin, detached)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the checksums and the [`Index`] of the files added so far and
+    /// returns the index.
+    pub fn finish(&self) -> io::Result<Index> {
+        fs_err::create_dir_all(&self.root)?;
+        let mut files = self.files.clone();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut checksums = String::new();
+        for file in &files {
+            checksums += &format!("{}  {}\n", file.sha256, file.path);
+        }
+        fs_err::write(self.root.join(CHECKSUMS_FILE_NAME), checksums)?;
+        let index = Index { files };
+        index.write(&self.root.join(INDEX_FILE_NAME))?;
+        Ok(index)
+    }
+
+    fn copy(&mut self, target: &str, kind: Kind, from: &Path, relative: &Path) -> io::Result<()> {
+        let to = self.root.join(relative);
+        if fs::metadata(from)?.is_dir() {
+            for entry in fs_err::read_dir(from)? {
+                let name = entry?.file_name();
+                self.copy(target, kind, &from.join(&name), &relative.join(&name))?;
+            }
+            return Ok(());
+        }
+        if let Some(parent) = to.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        fs_err::copy(from, &to)?;
+        // Forward slashes keep the index identical across hosts.
+        let path = relative.to_string_lossy().replace('\\', "/");
+        self.files.retain(|file| file.path != path);
+        self.files.push(File {
+            target: target.into(),
+            kind,
+            path,
+            sha256: manifest::hash_file(&to)?,
+        });
+        Ok(())
+    }
+}
+
+impl Index {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let invalid = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {message}", path.display()),
+            )
+        };
+        let mut files = Vec::new();
+        for line in fs_err::read_to_string(path)?.lines() {
+            let fields: Vec<&str> = line.splitn(5, ' ').collect();
+            let ["file", target, kind, sha256, path] = fields[..] else {
+                return Err(invalid(format!("bad line {line:?}")));
+            };
+            files.push(File {
+                target: target.into(),
+                kind: Kind::from_dir_name(kind)
+                    .ok_or_else(|| invalid(format!("bad kind {kind:?}")))?,
+                path: path.into(),
+                sha256: sha256.into(),
+            });
+        }
+        Ok(Self { files })
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs_err::write(path, self.to_string())
+    }
+
+    /// The targets with files, in order.
+    pub fn targets(&self) -> Vec<&str> {
+        let mut targets: Vec<&str> = self.files.iter().map(|file| &*file.target).collect();
+        targets.sort();
+        targets.dedup();
+        targets
+    }
+
+    /// The files in the dist directory `dir` that are missing or don't have
+    /// their digest.
+    pub fn damaged(&self, dir: &Path) -> io::Result<Vec<&File>> {
+        let mut damaged = Vec::new();
+        for file in &self.files {
+            let path = dir.join(&file.path);
+            if !path.is_file() || manifest::hash_file(&path)? != file.sha256 {
+                damaged.push(file);
+            }
+        }
+        Ok(damaged)
+    }
+}
+
+impl fmt::Display for Index {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for file in &self.files {
+            // The path goes last, since it may have spaces.
+            writeln!(
+                f,
+                "file {} {} {} {}",
+                file.target,
+                file.kind.dir_name(),
+                file.sha256,
+                file.path
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,815 @@
+//! Archive downloads with progress reporting. The zig-bootstrap source archive
+//! is well over 100 MB and a silent download of that size is indistinguishable
+//! from a hang, so every download reports its progress to a callback.
+//!
+//! On flaky networks one giant GET of such an archive fails over and over.
+//! [`download()`] writes to a `.part` file next to the destination and
+//! resumes it with a range request after a failure, retrying each URL with
+//! exponential backoff. Each URL has its own `.part` file, named after a
+//! digest of the URL, so a mirror never resumes what another one served.
+//! With [`MIRRORS_ENV`] set it tries the mirrors before the original URL. It
+//! first looks for a [chunk list](ChunkList) of the file on them, and if one
+//! has it, fetches the file in chunks in parallel, spread over every mirror
+//! (and the original URL), checking each chunk's SHA-256 and retrying a bad
+//! or failed chunk elsewhere. A chunk list is served as
+//! `<file URL>.chunks`, written by [`ChunkList::for_file()`].
+//!
+//! Under a [cancellation token](crate::cancel) a download stops after the
+//! chunk it's receiving. The `.part` file stays, so the next download of the
+//! URL resumes it.
+
+use std::{
+    env,
+    error::Error,
+    fmt::Write as _,
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use reqwest::{StatusCode, blocking::Client, header};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    cancel::{self, Cancelled},
+    log::{self, Event, Tagger},
+    manifest,
+};
+
+/// Environment variable that, set to `strict`, makes everything that would
+/// download fail up front instead, with an error naming what was needed and
+/// how to provide it locally. For sealed build environments where a network
+/// access is a reproducibility bug rather than a convenience.
+pub const OFFLINE_ENV: &str = "ZIG_RS_OFFLINE";
+
+/// Whether [`OFFLINE_ENV`] is `strict`.
+pub fn strict_offline() -> bool {
+    std::env::var(OFFLINE_ENV).is_ok_and(|value| value == "strict")
+}
+
+/// Fails in strict offline mode with an error naming `resource` and, in
+/// `provide`, how to make it available without the network.
+pub fn require_network(resource: &str, provide: &str) -> Result<(), Box<dyn Error>> {
+    if strict_offline() {
+        return Err(
+            format!("{OFFLINE_ENV}=strict forbids downloading {resource}; {provide}").into(),
+        );
+    }
+    Ok(())
+}
+
+/// A snapshot of an in-flight download.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Bytes received so far.
+    pub downloaded: u64,
+    /// Total size from `Content-Length`, if the server sent one.
+    pub total: Option<u64>,
+    /// Time since the request was sent.
+    pub elapsed: Duration,
+    /// Set on the final update, after the whole body has been received.
+    pub finished: bool,
+}
+
+impl Progress {
+    /// Completed fraction in `0.0..=1.0`, if the total size is known.
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total {
+            Some(0) => Some(1.0),
+            Some(total) => Some((self.downloaded as f64 / total as f64).min(1.0)),
+            None => None,
+        }
+    }
+
+    /// Average throughput since the start of the download.
+    pub fn bytes_per_second(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.downloaded as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Estimated time remaining at the average throughput so far.
+    pub fn eta(&self) -> Option<Duration> {
+        let remaining = self.total?.saturating_sub(self.downloaded);
+        let rate = self.bytes_per_second();
+        if rate > 0.0 {
+            Some(Duration::from_secs_f64(remaining as f64 / rate))
+        } else {
+            None
+        }
+    }
+}
+
+/// Environment variable listing mirrors, separated by whitespace or commas,
+/// that [`download()`] tries before the original URL. A mirror is either a
+/// base URL that serves files under their URL's last path segment, or a
+/// template with placeholders (see [`mirror_url()`]), e.g.
+/// `https://artifactory.example.com/github/{path}`.
+pub const MIRRORS_ENV: &str = "ZIG_RS_MIRRORS";
+
+/// How often [`download()`] tries each URL.
+const ATTEMPTS: u32 = 4;
+
+/// The wait after the first failed attempt, doubled after every further one.
+const BACKOFF: Duration = Duration::from_secs(1);
+
+/// The size of the chunks [`ChunkList::for_file()`] splits files into.
+pub const DEFAULT_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// How many chunks are fetched at once.
+const PARALLEL_CHUNKS: usize = 4;
+
+/// The mirrors from [`MIRRORS_ENV`], without trailing slashes.
+pub fn mirrors() -> Vec<String> {
+    env::var(MIRRORS_ENV)
+        .unwrap_or_default()
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|mirror| !mirror.is_empty())
+        .map(|mirror| mirror.trim_end_matches('/').to_owned())
+        .collect()
+}
+
+/// Where `mirror` serves `url`. A template's placeholders are replaced:
+///
+/// - `{url}`: all of `url`
+/// - `{path}`: `url` without its scheme and host, e.g.
+///   `ziglang/zig-bootstrap/archive/refs/tags/0.14.0.zip`
+/// - `{file}`: the last path segment, e.g. `0.14.0.zip`
+/// - `{name}`: the last path segment without an archive extension, e.g.
+///   `0.14.0`, for mirrors that repackage archives in another format
+///
+/// Any other mirror is a base URL, to which `/{file}` is appended.
+pub fn mirror_url(mirror: &str, url: &str) -> String {
+    let file = url.rsplit('/').next().unwrap_or(url);
+    if !mirror.contains('{') {
+        return format!("{mirror}/{file}");
+    }
+    let path = url
+        .split_once("://")
+        .and_then(|(_, rest)| rest.split_once('/'))
+        .map_or(url, |(_, path)| path);
+    let name = [".tar.gz", ".tar.xz", ".tar.zst", ".tgz", ".txz", ".zip"]
+        .iter()
+        .find_map(|extension| file.strip_suffix(extension))
+        .unwrap_or(file);
+    mirror
+        .replace("{url}", url)
+        .replace("{path}", path)
+        .replace("{file}", file)
+        .replace("{name}", name)
+}
+
+/// Downloads `url` to the file at `dest`, calling `on_progress` after every
+/// chunk and once more, with [`Progress::finished`] set, when the body has been
+/// fully received. The download is done by the [fetcher](Fetcher) in use,
+/// [`HttpFetcher`] by default. Fails without a request in strict offline
+/// mode; callers check [`require_network()`] first to say how to provide
+/// `url` instead.
+pub fn download(
+    url: &str,
+    dest: &Path,
+    mut on_progress: impl FnMut(&Progress),
+) -> Result<(), Box<dyn Error>> {
+    require_network(url, "provide it locally")?;
+    fetcher().fetch(url, dest, &mut on_progress)
+}
+
+/// Environment variable holding a command that [`download()`] runs instead
+/// of fetching over HTTP, with the URL and the destination file as its last
+/// two arguments, e.g. `gsutil cp` or a script that asks an internal
+/// artifact store. The command is split on whitespace. This also reaches
+/// the downloads of this crate's build script, which [`set_fetcher()`]
+/// can't.
+pub const FETCH_COMMAND_ENV: &str = "ZIG_RS_FETCH_COMMAND";
+
+/// What [`download()`] hands every download to, so environments with their
+/// own artifact store (an internal proxy, bazel-remote, a cloud bucket) can
+/// serve them:
+///
+/// ```no_run
+/// use std::{error::Error, path::Path};
+///
+/// use zig_rs::download::{self, Fetcher, Progress};
+///
+/// struct Bucket;
+///
+/// impl Fetcher for Bucket {
+///     fn fetch(
+///         &self,
+///         url: &str,
+///         dest: &Path,
+///         _on_progress: &mut dyn FnMut(&Progress),
+///     ) -> Result<(), Box<dyn Error>> {
+///         let key = url.rsplit('/').next().unwrap_or(url);
+///         std::fs::copy(Path::new("/mnt/bucket").join(key), dest)?;
+///         Ok(())
+///     }
+/// }
+///
+/// download::set_fetcher(Bucket);
+/// ```
+pub trait Fetcher: Send + Sync {
+    /// Downloads `url` to the file at `dest`, reporting progress to
+    /// `on_progress` like [`download()`] if it can.
+    fn fetch(
+        &self,
+        url: &str,
+        dest: &Path,
+        on_progress: &mut dyn FnMut(&Progress),
+    ) -> Result<(), Box<dyn Error>>;
+
+    /// Fetches `url` into memory. By default through a temporary file.
+    fn fetch_bytes(&self, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = env::temp_dir().join(format!(
+            "zig-rs-fetch-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = self
+            .fetch(url, &path, &mut |_| {})
+            .and_then(|()| Ok(fs_err::read(&path)?));
+        let _ = std::fs::remove_file(&path);
+        result
+    }
+}
+
+/// The fetcher [`set_fetcher()`] installed.
+static FETCHER: RwLock<Option<Arc<dyn Fetcher>>> = RwLock::new(None);
+
+/// Makes [`download()`] use `fetcher` for the rest of the process, in this
+/// crate's library and in tools built on it.
+pub fn set_fetcher(fetcher: impl Fetcher + 'static) {
+    *FETCHER.write().unwrap() = Some(Arc::new(fetcher));
+}
+
+/// The fetcher in use: the one [`set_fetcher()`] installed, else a
+/// [`CommandFetcher`] for [`FETCH_COMMAND_ENV`], else [`HttpFetcher`].
+pub fn fetcher() -> Arc<dyn Fetcher> {
+    if let Some(fetcher) = &*FETCHER.read().unwrap() {
+        return fetcher.clone();
+    }
+    match env::var(FETCH_COMMAND_ENV) {
+        Ok(command) if !command.trim().is_empty() => Arc::new(CommandFetcher::new(&command)),
+        _ => Arc::new(HttpFetcher),
+    }
+}
+
+/// Runs a command for each download; see [`FETCH_COMMAND_ENV`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandFetcher {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandFetcher {
+    /// `command` split on whitespace, e.g. `gsutil cp`.
+    pub fn new(command: &str) -> Self {
+        let mut words = command.split_whitespace().map(str::to_owned);
+        Self {
+            program: words.next().unwrap_or_default(),
+            args: words.collect(),
+        }
+    }
+}
+
+impl Fetcher for CommandFetcher {
+    fn fetch(
+        &self,
+        url: &str,
+        dest: &Path,
+        _on_progress: &mut dyn FnMut(&Progress),
+    ) -> Result<(), Box<dyn Error>> {
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&self.args).arg(url).arg(dest);
+        let status = log::run_tagged(&mut cmd, &Tagger::new("download"))?;
+        if !status.success() {
+            return Err(format!("{cmd:?} failed: {status}").into());
+        }
+        if !dest.exists() {
+            return Err(format!("{cmd:?} succeeded but wrote no {}", dest.display()).into());
+        }
+        Ok(())
+    }
+}
+
+/// The built-in HTTP client. With [mirrors](MIRRORS_ENV) that have a chunk
+/// list for the file it fetches with [`download_chunked()`], otherwise with
+/// resumed retries as described in the [module docs](self).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HttpFetcher;
+
+impl Fetcher for HttpFetcher {
+    fn fetch(
+        &self,
+        url: &str,
+        dest: &Path,
+        on_progress: &mut dyn FnMut(&Progress),
+    ) -> Result<(), Box<dyn Error>> {
+        fetch_http(url, dest, on_progress)
+    }
+}
+
+fn fetch_http(
+    url: &str,
+    dest: &Path,
+    mut on_progress: impl FnMut(&Progress),
+) -> Result<(), Box<dyn Error>> {
+    let mut urls: Vec<String> = mirrors()
+        .iter()
+        .map(|mirror| mirror_url(mirror, url))
+        .collect();
+    let client = Client::new();
+    let chunks = urls.iter().find_map(|url| {
+        let response = client
+            .get(format!("{url}.chunks"))
+            .send()
+            .ok()?
+            .error_for_status()
+            .ok()?;
+        ChunkList::parse(&response.text().ok()?).ok()
+    });
+    urls.push(url.into());
+    if let Some(chunks) = chunks {
+        return download_chunked(&urls, &chunks, dest, on_progress);
+    }
+
+    let tagger = Tagger::new("download");
+    let start = Instant::now();
+    let mut progress = Progress {
+        downloaded: 0,
+        total: None,
+        elapsed: Duration::ZERO,
+        finished: false,
+    };
+    let mut errors = Vec::new();
+    for url in &urls {
+        let part = part_path(dest, url);
+        for attempt in 0..ATTEMPTS {
+            match fetch_to_part(&client, url, &part, start, &mut progress, &mut on_progress) {
+                Ok(()) => {
+                    fs_err::rename(&part, dest)?;
+                    // What the URLs that failed left is of no more use.
+                    for other in &urls {
+                        let _ = std::fs::remove_file(part_path(dest, other));
+                    }
+                    progress.elapsed = start.elapsed();
+                    progress.finished = true;
+                    on_progress(&progress);
+                    return Ok(());
+                }
+                Err(Fetch::Retry(e)) if attempt + 1 < ATTEMPTS => {
+                    let delay = BACKOFF * 2u32.pow(attempt);
+                    tagger.print(&format!("{url}: {e}; retrying in {}s", delay.as_secs()));
+                    thread::sleep(delay);
+                    if cancel::check().is_err() {
+                        return Err(cancelled_download(&progress, &part).into());
+                    }
+                }
+                Err(Fetch::Cancelled) => return Err(cancelled_download(&progress, &part).into()),
+                Err(Fetch::Retry(e) | Fetch::Fatal(e)) => {
+                    errors.push(format!("{url}: {e}"));
+                    break;
+                }
+            }
+        }
+    }
+    Err(format!("downloading failed from every URL: {}", errors.join("; ")).into())
+}
+
+/// The `.part` file [`fetch_to_part()`] downloads `url` into on its way to
+/// `dest`: `<dest>.<digest of url>.part`. Two URLs may serve different bytes
+/// under the same name, and appending one's to the other's corrupts the file.
+fn part_path(dest: &Path, url: &str) -> PathBuf {
+    let digest = manifest::hex(&Sha256::digest(url.as_bytes()));
+    dest.with_file_name(format!(
+        "{}.{}.part",
+        dest.file_name().unwrap_or_default().to_string_lossy(),
+        &digest[..16]
+    ))
+}
+
+/// Why [`fetch_to_part()`] failed.
+enum Fetch {
+    /// The connection or the server failed; another attempt may work.
+    Retry(String),
+    /// The server doesn't have the file.
+    Fatal(String),
+    /// The download's cancellation token was cancelled.
+    Cancelled,
+}
+
+fn cancelled_download(progress: &Progress, part: &Path) -> Cancelled {
+    Cancelled::with_progress(format!(
+        "{}, kept in {}",
+        format_progress(progress),
+        part.display()
+    ))
+}
+
+/// Fetches `url` into `part`, resuming after the bytes it already holds if
+/// the server supports range requests and starting over otherwise.
+fn fetch_to_part(
+    client: &Client,
+    url: &str,
+    part: &Path,
+    start: Instant,
+    progress: &mut Progress,
+    on_progress: &mut impl FnMut(&Progress),
+) -> Result<(), Fetch> {
+    let retry = |e: &dyn std::fmt::Display| Fetch::Retry(e.to_string());
+    let offset = std::fs::metadata(part).map_or(0, |metadata| metadata.len());
+    let mut request = client.get(url);
+    if offset > 0 {
+        request = request.header(header::RANGE, format!("bytes={offset}-"));
+    }
+    let mut response = request.send().map_err(|e| retry(&e))?;
+    let status = response.status();
+    if status == StatusCode::RANGE_NOT_SATISFIABLE {
+        // The partial file is from something else, or already complete;
+        // start over.
+        fs_err::remove_file(part).map_err(|e| retry(&e))?;
+        return Err(Fetch::Retry(format!("can't resume at byte {offset}")));
+    }
+    if status.is_client_error()
+        && status != StatusCode::REQUEST_TIMEOUT
+        && status != StatusCode::TOO_MANY_REQUESTS
+    {
+        return Err(Fetch::Fatal(status.to_string()));
+    }
+    if !status.is_success() {
+        return Err(Fetch::Retry(status.to_string()));
+    }
+    let resumed = status == StatusCode::PARTIAL_CONTENT;
+    let mut file = if resumed {
+        fs_err::OpenOptions::new().append(true).open(part)
+    } else {
+        fs_err::File::create(part)
+    }
+    .map_err(|e| retry(&e))?;
+    progress.downloaded = if resumed { offset } else { 0 };
+    progress.total = response
+        .content_length()
+        .map(|len| len + progress.downloaded);
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = response.read(&mut buf).map_err(|e| retry(&e))?;
+        if n == 0 {
+            break;
+        }
+        file.write_all(&buf[..n]).map_err(|e| retry(&e))?;
+        progress.downloaded += n as u64;
+        progress.elapsed = start.elapsed();
+        on_progress(progress);
+        if cancel::check().is_err() {
+            file.flush().map_err(|e| retry(&e))?;
+            return Err(Fetch::Cancelled);
+        }
+    }
+    file.flush().map_err(|e| retry(&e))?;
+    if progress
+        .total
+        .is_some_and(|total| progress.downloaded < total)
+    {
+        return Err(Fetch::Retry("the connection closed early".into()));
+    }
+    Ok(())
+}
+
+/// A file split into fixed-size chunks, with the SHA-256 of each and of the
+/// whole file, in a text format for mirrors to serve:
+///
+/// ```text
+/// size 152004321
+/// chunk_size 8388608
+/// sha256 5d1a7e6f...
+/// chunk 93bd4c1a...
+/// chunk 0e7fa2c9...
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkList {
+    /// The size of the whole file in bytes.
+    pub size: u64,
+    /// The size of every chunk but the last.
+    pub chunk_size: u64,
+    pub sha256: String,
+    /// The SHA-256 of each chunk, in order.
+    pub chunks: Vec<String>,
+}
+
+impl ChunkList {
+    /// Splits the file at `path` into chunks of `chunk_size` bytes.
+    pub fn for_file(path: &Path, chunk_size: u64) -> Result<Self, Box<dyn Error>> {
+        if chunk_size == 0 {
+            return Err("the chunk size must not be zero".into());
+        }
+        let mut file = fs_err::File::open(path)?;
+        let size = file.metadata()?.len();
+        let mut chunks = Vec::new();
+        let mut whole = Sha256::new();
+        let mut buf = vec![0; chunk_size as usize];
+        loop {
+            let mut filled = 0;
+            while filled < buf.len() {
+                let n = file.read(&mut buf[filled..])?;
+                if n == 0 {
+                    break;
+                }
+                filled += n;
+            }
+            if filled == 0 {
+                break;
+            }
+            whole.update(&buf[..filled]);
+            chunks.push(manifest::hex(&Sha256::digest(&buf[..filled])));
+        }
+        Ok(Self {
+            size,
+            chunk_size,
+            sha256: manifest::hex(&whole.finalize()),
+            chunks,
+        })
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let mut list = Self {
+            size: 0,
+            chunk_size: 0,
+            sha256: String::new(),
+            chunks: Vec::new(),
+        };
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let malformed = || format!("malformed chunk list line {line:?}");
+            let (key, value) = line.split_once(' ').ok_or_else(malformed)?;
+            let digest = || {
+                let digest = value.len() == 64 && value.bytes().all(|b| b.is_ascii_hexdigit());
+                digest
+                    .then(|| value.to_ascii_lowercase())
+                    .ok_or_else(malformed)
+            };
+            match key {
+                "size" => list.size = value.parse().map_err(|_| malformed())?,
+                "chunk_size" => list.chunk_size = value.parse().map_err(|_| malformed())?,
+                "sha256" => list.sha256 = digest()?,
+                "chunk" => list.chunks.push(digest()?),
+                _ => return Err(malformed().into()),
+            }
+        }
+        let expected = if list.chunk_size == 0 {
+            None
+        } else {
+            Some(list.size.div_ceil(list.chunk_size))
+        };
+        if expected != Some(list.chunks.len() as u64) || list.sha256.is_empty() {
+            return Err("inconsistent chunk list".into());
+        }
+        Ok(list)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut text = format!(
+            "size {}\nchunk_size {}\nsha256 {}\n",
+            self.size, self.chunk_size, self.sha256
+        );
+        for chunk in &self.chunks {
+            writeln!(text, "chunk {chunk}").expect("writing to a String");
+        }
+        fs_err::write(path, text)?;
+        Ok(())
+    }
+
+    /// The byte range of chunk `index`, end exclusive.
+    fn range(&self, index: usize) -> (u64, u64) {
+        let start = index as u64 * self.chunk_size;
+        (start, (start + self.chunk_size).min(self.size))
+    }
+}
+
+/// Downloads the file `chunks` describes to `dest`, fetching its chunks
+/// with HTTP range requests, several at a time, from all of `urls` in turn.
+/// A chunk that fails to download or doesn't match its digest is retried
+/// from the next URL, for up to two rounds over all of them. Reports
+/// progress like [`download()`]; the whole file is checked at the end.
+pub fn download_chunked(
+    urls: &[String],
+    chunks: &ChunkList,
+    dest: &Path,
+    mut on_progress: impl FnMut(&Progress),
+) -> Result<(), Box<dyn Error>> {
+    if urls.is_empty() {
+        return Err("no URLs to download from".into());
+    }
+    let start = Instant::now();
+    let file = fs_err::File::create(dest)?;
+    file.set_len(chunks.size)?;
+    let file = Mutex::new(file);
+    let client = Client::new();
+    let next = &AtomicUsize::new(0);
+    let mut progress = Progress {
+        downloaded: 0,
+        total: Some(chunks.size),
+        elapsed: Duration::ZERO,
+        finished: false,
+    };
+    // Workers send the sizes of finished chunks, so `on_progress` only ever
+    // runs on this thread.
+    let (done, received) = mpsc::channel();
+    let token = cancel::current();
+    let cancelled = || token.as_ref().is_some_and(|token| token.is_cancelled());
+    let fetch = &|index: usize, done: &mpsc::Sender<u64>| -> Result<(), String> {
+        let (begin, end) = chunks.range(index);
+        let mut errors = Vec::new();
+        for attempt in 0..urls.len() * 2 {
+            let url = &urls[(index + attempt) % urls.len()];
+            let data = client
+                .get(url)
+                .header(header::RANGE, format!("bytes={begin}-{}", end - 1))
+                .send()
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| e.to_string())
+                .and_then(|response| {
+                    // A server that ignores the range sends a 200 with the
+                    // whole file, which is only right for a single chunk.
+                    if response.status() != StatusCode::PARTIAL_CONTENT && begin != 0 {
+                        return Err("the server doesn't support range requests".into());
+                    }
+                    let mut data = Vec::with_capacity((end - begin) as usize);
+                    response
+                        .take(end - begin)
+                        .read_to_end(&mut data)
+                        .map_err(|e| e.to_string())?;
+                    Ok(data)
+                });
+            let data = match data {
+                Ok(data) if manifest::hex(&Sha256::digest(&data)) == chunks.chunks[index] => data,
+                Ok(_) => {
+                    errors.push(format!("{url}: chunk {index} doesn't match its digest"));
+                    continue;
+                }
+                Err(e) => {
+                    errors.push(format!("{url}: {e}"));
+                    continue;
+                }
+            };
+            {
+                let mut file = file.lock().unwrap();
+                file.seek(SeekFrom::Start(begin))
+                    .and_then(|_| file.write_all(&data))
+                    .map_err(|e| e.to_string())?;
+            }
+            let _ = done.send(data.len() as u64);
+            return Ok(());
+        }
+        Err(format!(
+            "chunk {index} failed from every mirror: {}",
+            errors.join("; ")
+        ))
+    };
+    let result = thread::scope(|scope| {
+        let workers: Vec<_> = (0..PARALLEL_CHUNKS.min(chunks.chunks.len()))
+            .map(|_| {
+                let done = done.clone();
+                scope.spawn(move || {
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        if index >= chunks.chunks.len() || cancelled() {
+                            return Ok(());
+                        }
+                        if let Err(e) = fetch(index, &done) {
+                            // Let the other workers stop early.
+                            next.store(chunks.chunks.len(), Ordering::Relaxed);
+                            return Err(e);
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(done);
+        for len in received {
+            progress.downloaded += len;
+            progress.elapsed = start.elapsed();
+            on_progress(&progress);
+        }
+        workers
+            .into_iter()
+            .try_for_each(|worker| worker.join().expect("chunk download panicked"))
+    });
+    let mut file = file.into_inner().unwrap();
+    file.flush()?;
+    drop(file);
+    if let Err(e) = result {
+        let _ = fs_err::remove_file(dest);
+        return Err(e.into());
+    }
+    if cancelled() {
+        // Chunks land out of order, so there's nothing to resume.
+        let _ = fs_err::remove_file(dest);
+        return Err(Cancelled::with_progress(format_progress(&progress)).into());
+    }
+    let actual = manifest::hash_file(dest)?;
+    if actual != chunks.sha256 {
+        fs_err::remove_file(dest)?;
+        return Err(format!(
+            "{} has SHA-256 {actual} after a chunked download, expected {}",
+            dest.display(),
+            chunks.sha256
+        )
+        .into());
+    }
+    progress.elapsed = start.elapsed();
+    progress.finished = true;
+    on_progress(&progress);
+    Ok(())
+}
+
+/// A progress callback that prints a single percentage line at most every
+/// `interval`, plus one final line. This keeps CI logs readable while still
+/// showing that something is happening.
+#[derive(Debug)]
+pub struct PercentReporter {
+    tagger: Tagger,
+    interval: Duration,
+    last: Option<Instant>,
+    done: bool,
+}
+
+impl PercentReporter {
+    pub fn new(tagger: Tagger) -> Self {
+        Self {
+            tagger,
+            interval: Duration::from_secs(5),
+            last: None,
+            done: false,
+        }
+    }
+
+    /// Sets the minimum time between two printed lines.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Handles one progress update. Pass `|p| reporter.report(p)` to
+    /// [`download()`].
+    /// With a [subscriber](log::subscribe()), every update is sent to it as
+    /// an [`Event::Progress`].
+    pub fn report(&mut self, progress: &Progress) {
+        if self.done {
+            return;
+        }
+        let event = Event::Progress {
+            step: self.tagger.step().into(),
+            fraction: progress.fraction(),
+            message: format_progress(progress),
+        };
+        if self.last.is_none() {
+            self.tagger.started();
+        }
+        if log::emit(event) {
+            self.last = Some(Instant::now());
+            self.done = progress.finished;
+            if self.done {
+                self.tagger.finished(true);
+            }
+            return;
+        }
+        let due = self.last.is_none_or(|last| last.elapsed() >= self.interval);
+        if !due && !progress.finished {
+            return;
+        }
+        self.last = Some(Instant::now());
+        self.done = progress.finished;
+        self.tagger.print(&format_progress(progress));
+    }
+}
+
+/// Formats a progress snapshot as e.g. `42% (61.3/145.9 MiB, ETA 0:37)`.
+pub fn format_progress(progress: &Progress) -> String {
+    let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    match (progress.fraction(), progress.total) {
+        (Some(fraction), Some(total)) => {
+            let mut line = format!(
+                "{:.0}% ({:.1}/{:.1} MiB",
+                fraction * 100.0,
+                mib(progress.downloaded),
+                mib(total)
+            );
+            if let Some(eta) = progress.eta().filter(|_| fraction < 1.0) {
+                let secs = eta.as_secs();
+                line += &format!(", ETA {}:{:02}", secs / 60, secs % 60);
+            }
+            line + ")"
+        }
+        _ => format!("{:.1} MiB", mib(progress.downloaded)),
+    }
+}
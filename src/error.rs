@@ -0,0 +1,163 @@
+//! The error type of the builders.
+
+use std::{fmt, io, path::PathBuf, process::Command, process::ExitStatus};
+
+use crate::{
+    cancel::Cancelled,
+    crash,
+    diagnostic::{Diagnostic, Severity},
+    glibc_audit,
+    link_report::{self, LinkReport},
+    replay,
+};
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Missing or invalid configuration, e.g. `OUT_DIR` not set or a target
+    /// with no Zig equivalent.
+    Config(String),
+    Io(io::Error),
+    /// A compiler or archiver invocation exited unsuccessfully.
+    Command(Box<CommandError>),
+    /// A binary linked for a glibc target needs a newer glibc than the
+    /// target is pinned to; see [`glibc_audit`].
+    Glibc(Box<glibc_audit::Report>),
+    /// The [cancellation token](crate::cancel) of the operation was
+    /// cancelled.
+    Cancelled(Cancelled),
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandError {
+    /// The full command line, quoted so it can be pasted into a shell.
+    pub command: String,
+    pub status: ExitStatus,
+    /// Everything the command printed.
+    pub output: String,
+    pub diagnostics: Vec<Diagnostic>,
+    /// A script rerunning the command, with [`replay::ENV`] set.
+    pub replay: Option<PathBuf>,
+}
+
+impl CommandError {
+    /// The failure of `cmd`, with its [replay script](replay) written if
+    /// they're turned on.
+    pub fn new(
+        cmd: &Command,
+        status: ExitStatus,
+        diagnostics: Vec<Diagnostic>,
+        output: String,
+    ) -> Self {
+        let replay = replay::write_if_enabled(cmd).unwrap_or_else(|e| {
+            eprintln!("zig-rs: couldn't write a replay script: {e}");
+            None
+        });
+        Self {
+            command: command_line(cmd),
+            status,
+            output,
+            diagnostics,
+            replay,
+        }
+    }
+
+    /// The diagnostics with [`Severity::Error`].
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+    }
+
+    /// The undefined symbols and missing libraries of a failed link, if the
+    /// command was one; see [`link_report`].
+    pub fn link_report(&self) -> Option<LinkReport> {
+        link_report::parse(&self.output)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Config(message) => f.write_str(message),
+            Self::Io(e) => e.fmt(f),
+            Self::Command(e) => {
+                write!(f, "`{}` failed: {}", e.command, e.status)?;
+                if let Some(first) = e.errors().next() {
+                    write!(f, "\n{first}")?;
+                } else if let Some(report) = e.link_report() {
+                    write!(f, "\n{}", report.to_string().trim_end())?;
+                }
+                if let Some(replay) = &e.replay {
+                    write!(f, "\nreplay with {}", replay.display())?;
+                }
+                if crash::signature(e.status, &e.output).is_some() {
+                    write!(
+                        f,
+                        "\nzig crashed; `cargo zig report -- <args>` writes a bug report of it"
+                    )?;
+                }
+                Ok(())
+            }
+            Self::Glibc(report) => report.fmt(f),
+            Self::Cancelled(cancelled) => cancelled.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Cancelled(cancelled) => Some(cancelled),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        match e
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<Cancelled>())
+        {
+            Some(cancelled) => Self::Cancelled(cancelled.clone()),
+            None => Self::Io(e),
+        }
+    }
+}
+
+impl From<Cancelled> for Error {
+    fn from(cancelled: Cancelled) -> Self {
+        Self::Cancelled(cancelled)
+    }
+}
+
+/// Renders `cmd` as a shell command line, with environment overrides first.
+pub(crate) fn command_line(cmd: &Command) -> String {
+    let mut words = Vec::new();
+    for (key, value) in cmd.get_envs() {
+        if let Some(value) = value {
+            words.push(format!(
+                "{}={}",
+                key.to_string_lossy(),
+                quote(&value.to_string_lossy())
+            ));
+        }
+    }
+    words.push(quote(&cmd.get_program().to_string_lossy()));
+    words.extend(cmd.get_args().map(|arg| quote(&arg.to_string_lossy())));
+    words.join(" ")
+}
+
+pub(crate) fn quote(word: &str) -> String {
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if plain {
+        word.to_owned()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
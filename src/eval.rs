@@ -0,0 +1,133 @@
+//! Evaluating Zig expressions, for code generators and property tests that
+//! take Zig's semantics (wrapping arithmetic, packed struct layout, ...) as
+//! the ground truth:
+//!
+//! ```no_run
+//! assert_eq!(zig_rs::eval("@as(u8, 255) +% 1")?, "0");
+//! assert_eq!(zig_rs::eval("@bitSizeOf(packed struct { a: u3, b: u6 })")?, "9");
+//! # Ok::<(), zig_rs::Error>(())
+//! ```
+//!
+//! The expression becomes the value of a constant in a small `main`, which
+//! is built for the host and run; strings print as they are and everything
+//! else with `{any}`. Binaries are cached in [`cache::builds_dir()`] keyed by
+//! the toolchain and the expression, so evaluating an expression again only
+//! runs it.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{Error, Toolchain, cache, diagnostic, error::CommandError, manifest, process};
+
+/// The program an expression is evaluated in.
+const TEMPLATE: &str = r#"const std = @import("std");
+
+pub fn main() !void {
+    const value = (
+        @EXPR@
+    );
+    const stdout = std.io.getStdOut().writer();
+    if (comptime isString(@TypeOf(value))) {
+        try stdout.print("{s}", .{value});
+    } else {
+        try stdout.print("{any}", .{value});
+    }
+}
+
+fn isString(comptime T: type) bool {
+    return switch (@typeInfo(T)) {
+        .pointer => |pointer| switch (pointer.size) {
+            .slice => pointer.child == u8,
+            .one => switch (@typeInfo(pointer.child)) {
+                .array => |array| array.child == u8,
+                else => false,
+            },
+            else => false,
+        },
+        else => false,
+    };
+}
+"#;
+
+/// Evaluates a Zig expression with the bundled toolchain; see
+/// [`eval_with()`].
+pub fn eval(expr: &str) -> Result<String, Error> {
+    eval_with(&Toolchain::bundled(), expr)
+}
+
+/// Evaluates the Zig expression `expr` with `toolchain` and returns what it
+/// prints. An expression that doesn't compile comes back as
+/// [`Error::Command`] with the compiler's diagnostics, and so does one
+/// that panics when run, e.g. on overflow, with the panic message.
+pub fn eval_with(toolchain: &Toolchain, expr: &str) -> Result<String, Error> {
+    let source = TEMPLATE.replace("@EXPR@", expr);
+    let dir = entry_dir(toolchain, &source);
+    let binary = dir.join(format!("eval{}", env::consts::EXE_SUFFIX));
+    if binary.is_file() {
+        let _ = cache::touch(&dir);
+    } else {
+        build(toolchain, &source, &dir, &binary)?;
+    }
+
+    let mut cmd = std::process::Command::new(&binary);
+    let output = process::output(&mut cmd)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        return Err(Error::Command(Box::new(CommandError::new(
+            &cmd,
+            output.status,
+            Vec::new(),
+            stderr,
+        ))));
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| Error::Config(format!("`{expr}` printed invalid UTF-8")))
+}
+
+/// `<cache>/builds/eval-<key>`, or a directory under the system's temporary
+/// one when there's no cache.
+fn entry_dir(toolchain: &Toolchain, source: &str) -> PathBuf {
+    let mut hasher = Sha256::new();
+    hasher.update(toolchain.zig().to_string_lossy().as_bytes());
+    hasher.update("\0");
+    hasher.update(toolchain.zig_version());
+    hasher.update("\0");
+    hasher.update(source);
+    let name = format!("eval-{}", manifest::hex(&hasher.finalize()));
+    match cache::builds_dir() {
+        Some(dir) => dir.join(name),
+        None => env::temp_dir().join("zig-rs").join(name),
+    }
+}
+
+/// Compiles `source` into `binary`, in place only once it's complete so
+/// concurrent evaluations of the same expression never run half a binary.
+fn build(toolchain: &Toolchain, source: &str, dir: &Path, binary: &Path) -> Result<(), Error> {
+    fs_err::create_dir_all(dir)?;
+    let root = dir.join("eval.zig");
+    fs_err::write(&root, source)?;
+    let partial = binary.with_extension(format!("{}.partial", std::process::id()));
+    let mut cmd = toolchain.command();
+    cmd.current_dir(dir)
+        .arg("build-exe")
+        .arg(&root)
+        .arg("-O")
+        .arg("Debug")
+        .arg(format!("-femit-bin={}", partial.display()));
+    let output = process::output(&mut cmd)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        return Err(Error::Command(Box::new(CommandError::new(
+            &cmd,
+            output.status,
+            diagnostic::parse(&stderr),
+            stderr,
+        ))));
+    }
+    fs_err::rename(&partial, binary)?;
+    Ok(())
+}
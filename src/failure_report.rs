@@ -0,0 +1,136 @@
+//! Local reports of failed builds, for platform teams supporting many
+//! developers who want to know why zig-rs builds fail across their fleet.
+//! Nothing is recorded unless asked for, and nothing is sent anywhere:
+//!
+//! - with [`FILE_ENV`] set to a path, each failure of the toolchain build
+//!   appends a line of JSON to that file;
+//! - with [`HOOK_ENV`] set to a program, it runs with the same JSON on its
+//!   stdin, to hand the report to whatever the team collects them with.
+//!
+//! A report, which is written on a single line:
+//!
+//! ```text
+//! {
+//!   "phase": "toolchain",
+//!   "kind": "not-found",
+//!   "message": "...",
+//!   "host": "x86_64-unknown-linux-gnu",
+//!   "target": "aarch64-apple-darwin",
+//!   "zig_rs_version": "0.14.0",
+//!   "log": null
+//! }
+//! ```
+//!
+//! `phase` is what the build was doing (`config`, `toolchain`,
+//! `bootstrap` for the zig-bootstrap build itself, `licenses`, `manifest`,
+//! `provenance` or `metadata`), `kind` the [`io::ErrorKind`] behind the
+//! failure in kebab case or `error`, and `log` the zig-bootstrap log of a
+//! `bootstrap` failure. Build scripts of dependent crates can [`record()`]
+//! their own failures in the same place.
+
+use std::{env, error::Error, io, io::Write, path::PathBuf, process::Command};
+
+use crate::{process, provenance::quote};
+
+/// A path to append a line of JSON to for each failure.
+pub const FILE_ENV: &str = "ZIG_RS_FAILURE_REPORTS";
+/// A program to run with each failure report on its stdin.
+pub const HOOK_ENV: &str = "ZIG_RS_FAILURE_HOOK";
+
+/// One failed build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub phase: String,
+    pub kind: String,
+    pub message: String,
+    pub host: String,
+    pub target: String,
+    pub zig_rs_version: String,
+    pub log: Option<PathBuf>,
+}
+
+impl Report {
+    /// A report of `error` in `phase`, with the host and target of the
+    /// running build script.
+    pub fn new(phase: &str, error: &(dyn Error + 'static)) -> Self {
+        Self {
+            phase: phase.into(),
+            kind: kind(error),
+            message: error.to_string(),
+            host: env::var("HOST").unwrap_or_default(),
+            target: env::var("TARGET").unwrap_or_default(),
+            zig_rs_version: env!("CARGO_PKG_VERSION").into(),
+            log: None,
+        }
+    }
+
+    /// The report as one line of JSON.
+    pub fn to_json(&self) -> String {
+        let log = match &self.log {
+            Some(log) => quote(&log.to_string_lossy()),
+            None => "null".into(),
+        };
+        format!(
+            "{{\"phase\": {}, \"kind\": {}, \"message\": {}, \"host\": {}, \"target\": {}, \"zig_rs_version\": {}, \"log\": {log}}}",
+            quote(&self.phase),
+            quote(&self.kind),
+            quote(&self.message),
+            quote(&self.host),
+            quote(&self.target),
+            quote(&self.zig_rs_version),
+        )
+    }
+}
+
+/// Whether [`FILE_ENV`] or [`HOOK_ENV`] is set.
+pub fn enabled() -> bool {
+    env::var_os(FILE_ENV).is_some() || env::var_os(HOOK_ENV).is_some()
+}
+
+/// Appends `report` to [`FILE_ENV`] and runs [`HOOK_ENV`] with it, for
+/// whichever is set.
+pub fn record(report: &Report) -> io::Result<()> {
+    let json = report.to_json() + "\n";
+    if let Some(path) = env::var_os(FILE_ENV) {
+        let path = PathBuf::from(path);
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs_err::create_dir_all(dir)?;
+        }
+        fs_err::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?
+            .write_all(json.as_bytes())?;
+    }
+    if let Some(hook) = env::var_os(HOOK_ENV) {
+        let output = process::output_with_input(&mut Command::new(&hook), json.as_bytes())?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "{} failed: {}",
+                PathBuf::from(hook).display(),
+                output.status
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The [`io::ErrorKind`] behind `error`, e.g. `permission-denied`, or
+/// `error`.
+fn kind(error: &(dyn Error + 'static)) -> String {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        if let Some(error) = error.downcast_ref::<io::Error>() {
+            let mut kind = String::new();
+            for c in format!("{:?}", error.kind()).chars() {
+                if c.is_ascii_uppercase() && !kind.is_empty() {
+                    kind.push('-');
+                }
+                kind.push(c.to_ascii_lowercase());
+            }
+            return kind;
+        }
+        current = error.source();
+    }
+    "error".into()
+}
@@ -0,0 +1,561 @@
+//! Generates Zig `extern` declarations from a Rust crate's C ABI surface, so
+//! Zig code that calls into Rust doesn't drift out of sync with it.
+//!
+//! Every `pub extern "C" fn` with `#[no_mangle]` (or `#[export_name]`) becomes
+//! an `extern fn` declaration, and every `#[repr(C)]` struct or fieldless enum
+//! becomes the matching `extern struct`/`enum(c_int)`:
+//!
+//! ```no_run
+//! // build.rs
+//! zig_rs::ffi::Generator::new()
+//!     .crate_root("src/lib.rs")
+//!     .write(std::path::Path::new(&std::env::var("OUT_DIR")?).join("rust.zig"))?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Requires the `ffi` feature.
+
+use std::{
+    error::Error,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+mod roundtrip;
+
+pub use roundtrip::RoundTrip;
+
+use syn::{
+    Abi, Attribute, Expr, Fields, FnArg, GenericArgument, Item, ItemEnum, ItemFn, ItemStruct, Lit,
+    PathArguments, ReturnType, Type, TypeBareFn, Visibility,
+};
+
+/// Collects Rust sources and renders their C ABI items as Zig.
+#[derive(Debug, Clone, Default)]
+pub struct Generator {
+    files: Vec<PathBuf>,
+    roots: Vec<PathBuf>,
+}
+
+impl Generator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a single source file. `mod` declarations in it are not followed.
+    pub fn file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.files.push(path.into());
+        self
+    }
+
+    /// Adds a crate root (`src/lib.rs`) and every module file reachable from
+    /// it through `mod foo;` declarations.
+    pub fn crate_root(mut self, path: impl Into<PathBuf>) -> Self {
+        self.roots.push(path.into());
+        self
+    }
+
+    /// Parses every source and returns the items in them, with inline and
+    /// file modules flattened.
+    fn items(&self) -> Result<Vec<Item>, Box<dyn Error>> {
+        let mut items = Vec::new();
+        for file in &self.files {
+            items.extend(parse_file(file)?.items);
+        }
+        for root in &self.roots {
+            collect_module(root, true, &mut items)?;
+        }
+        Ok(items)
+    }
+
+    /// Every file [`write()`](Self::write) registers for reruns.
+    fn rerun_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.files.iter().chain(&self.roots).cloned().collect();
+        paths.extend(
+            self.roots
+                .iter()
+                .filter_map(|root| root.parent().map(Path::to_owned)),
+        );
+        paths
+    }
+
+    /// Returns the generated Zig source.
+    pub fn generate(&self) -> Result<String, Box<dyn Error>> {
+        let items = self.items()?;
+        let mut out = String::from(
+            "// Generated by zig_rs::ffi from the Rust sources. Do not edit.\n\n\
+             const std = @import(\"std\");\n",
+        );
+        for item in &items {
+            render_item(item, &mut out)?;
+        }
+        Ok(out)
+    }
+
+    /// Writes the generated source to `path`, leaving the file untouched when
+    /// the contents haven't changed so Zig's cache stays warm, and registers
+    /// every scanned Rust file with `cargo:rerun-if-changed`.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        write_if_changed(path.as_ref(), &self.generate()?)?;
+        for path in self.rerun_paths() {
+            build::rerun_if_changed(path);
+        }
+        Ok(())
+    }
+}
+
+fn write_if_changed(path: &Path, contents: &str) -> Result<(), Box<dyn Error>> {
+    if std::fs::read_to_string(path).ok().as_deref() != Some(contents) {
+        fs_err::write(path, contents)?;
+    }
+    Ok(())
+}
+
+fn parse_file(path: &Path) -> Result<syn::File, Box<dyn Error>> {
+    let source = fs_err::read_to_string(path)?;
+    syn::parse_file(&source).map_err(|e| format!("{}: {e}", path.display()).into())
+}
+
+/// Parses `path` and appends its items, descending into `mod foo;` files.
+/// `is_root` is set for `lib.rs`/`main.rs`/`mod.rs`, whose child modules live
+/// next to them rather than in a directory named after them.
+fn collect_module(path: &Path, is_root: bool, items: &mut Vec<Item>) -> Result<(), Box<dyn Error>> {
+    let file = parse_file(path)?;
+    let dir = if is_root {
+        path.parent().map(Path::to_owned).unwrap_or_default()
+    } else {
+        path.with_extension("")
+    };
+    collect_items(file.items, &dir, items)
+}
+
+fn collect_items(
+    file_items: Vec<Item>,
+    dir: &Path,
+    items: &mut Vec<Item>,
+) -> Result<(), Box<dyn Error>> {
+    for item in file_items {
+        match item {
+            Item::Mod(module) => {
+                let name = module.ident.to_string();
+                if let Some((_, content)) = module.content {
+                    collect_items(content, &dir.join(&name), items)?;
+                } else if let Some(explicit) = path_attr(&module.attrs) {
+                    collect_module(&dir.join(explicit), false, items)?;
+                } else if dir.join(format!("{name}.rs")).exists() {
+                    collect_module(&dir.join(format!("{name}.rs")), false, items)?;
+                } else {
+                    collect_module(&dir.join(&name).join("mod.rs"), true, items)?;
+                }
+            }
+            item => items.push(item),
+        }
+    }
+    Ok(())
+}
+
+fn path_attr(attrs: &[Attribute]) -> Option<String> {
+    attrs.iter().find_map(|attr| {
+        let nv = attr.meta.require_name_value().ok()?;
+        if !nv.path.is_ident("path") {
+            return None;
+        }
+        match &nv.value {
+            Expr::Lit(lit) => match &lit.lit {
+                Lit::Str(s) => Some(s.value()),
+                _ => None,
+            },
+            _ => None,
+        }
+    })
+}
+
+fn render_item(item: &Item, out: &mut String) -> Result<(), Box<dyn Error>> {
+    match item {
+        Item::Fn(f) => render_fn(f, out),
+        Item::Struct(s) => render_struct(s, out),
+        Item::Enum(e) => render_enum(e, out),
+        _ => Ok(()),
+    }
+}
+
+fn is_c_abi(abi: &Option<Abi>) -> bool {
+    abi.as_ref().is_some_and(|abi| {
+        abi.name
+            .as_ref()
+            .is_none_or(|name| matches!(name.value().as_str(), "C" | "C-unwind"))
+    })
+}
+
+/// The symbol name a function is exported under, if it's exported unmangled.
+fn export_name(attrs: &[Attribute], ident: &syn::Ident) -> Option<String> {
+    for attr in attrs {
+        // `#[unsafe(no_mangle)]` is the edition 2024 spelling.
+        let meta = if attr.path().is_ident("unsafe") {
+            match attr.parse_args::<syn::Meta>() {
+                Ok(meta) => meta,
+                Err(_) => continue,
+            }
+        } else {
+            attr.meta.clone()
+        };
+        if meta.path().is_ident("no_mangle") {
+            return Some(ident.to_string());
+        }
+        if meta.path().is_ident("export_name")
+            && let syn::Meta::NameValue(nv) = &meta
+            && let Expr::Lit(lit) = &nv.value
+            && let Lit::Str(s) = &lit.lit
+        {
+            return Some(s.value());
+        }
+    }
+    None
+}
+
+fn render_fn(f: &ItemFn, out: &mut String) -> Result<(), Box<dyn Error>> {
+    if !matches!(f.vis, Visibility::Public(_)) || !is_c_abi(&f.sig.abi) {
+        return Ok(());
+    }
+    let Some(name) = export_name(&f.attrs, &f.sig.ident) else {
+        return Ok(());
+    };
+    let mut params = Vec::new();
+    for arg in &f.sig.inputs {
+        let FnArg::Typed(arg) = arg else {
+            return Err(format!("{name}: `self` parameters aren't C ABI").into());
+        };
+        let param_name = match &*arg.pat {
+            syn::Pat::Ident(ident) => ident.ident.to_string(),
+            _ => format!("arg{}", params.len()),
+        };
+        params.push(format!(
+            "{}: {}",
+            zig_ident(&param_name),
+            zig_type(&arg.ty)?
+        ));
+    }
+    if f.sig.variadic.is_some() {
+        params.push("...".into());
+    }
+    writeln!(
+        out,
+        "\npub extern fn {}({}) {};",
+        zig_ident(&name),
+        params.join(", "),
+        zig_return_type(&f.sig.output)?
+    )?;
+    Ok(())
+}
+
+/// The integer type from `#[repr(C)]`/`#[repr(u8)]`/..., and whether the item
+/// is `#[repr(C)]` or `#[repr(transparent)]` at all.
+fn repr(attrs: &[Attribute]) -> (bool, bool, Option<String>) {
+    let (mut c, mut transparent, mut int) = (false, false, None);
+    for attr in attrs.iter().filter(|a| a.path().is_ident("repr")) {
+        let _ = attr.parse_nested_meta(|meta| {
+            let ident = meta
+                .path
+                .get_ident()
+                .map(|i| i.to_string())
+                .unwrap_or_default();
+            match ident.as_str() {
+                "C" => c = true,
+                "transparent" => transparent = true,
+                "u8" | "u16" | "u32" | "u64" | "i8" | "i16" | "i32" | "i64" | "usize" | "isize" => {
+                    int = Some(ident)
+                }
+                _ => {}
+            }
+            Ok(())
+        });
+    }
+    (c, transparent, int)
+}
+
+fn render_struct(s: &ItemStruct, out: &mut String) -> Result<(), Box<dyn Error>> {
+    if !matches!(s.vis, Visibility::Public(_)) {
+        return Ok(());
+    }
+    let (c, transparent, _) = repr(&s.attrs);
+    let name = s.ident.to_string();
+    if transparent {
+        let field = s
+            .fields
+            .iter()
+            .next()
+            .ok_or_else(|| format!("{name}: empty #[repr(transparent)] struct"))?;
+        writeln!(out, "\npub const {name} = {};", zig_type(&field.ty)?)?;
+        return Ok(());
+    }
+    if !c {
+        return Ok(());
+    }
+    writeln!(out, "\npub const {name} = extern struct {{")?;
+    match &s.fields {
+        Fields::Named(fields) => {
+            for field in &fields.named {
+                let ident = field.ident.as_ref().expect("named field").to_string();
+                writeln!(out, "    {}: {},", zig_ident(&ident), zig_type(&field.ty)?)?;
+            }
+        }
+        Fields::Unnamed(fields) => {
+            for (i, field) in fields.unnamed.iter().enumerate() {
+                writeln!(out, "    @\"{i}\": {},", zig_type(&field.ty)?)?;
+            }
+        }
+        Fields::Unit => {}
+    }
+    writeln!(out, "}};")?;
+    Ok(())
+}
+
+fn render_enum(e: &ItemEnum, out: &mut String) -> Result<(), Box<dyn Error>> {
+    if !matches!(e.vis, Visibility::Public(_)) {
+        return Ok(());
+    }
+    let (c, _, int) = repr(&e.attrs);
+    if !c && int.is_none() {
+        return Ok(());
+    }
+    let name = e.ident.to_string();
+    if e.variants.iter().any(|v| !matches!(v.fields, Fields::Unit)) {
+        return Err(format!("{name}: enums with fields have no Zig equivalent").into());
+    }
+    let tag = match int.as_deref() {
+        None => "c_int".to_owned(),
+        Some(int) => zig_primitive(int)
+            .expect("repr ints are primitives")
+            .to_owned(),
+    };
+    writeln!(out, "\npub const {name} = enum({tag}) {{")?;
+    for variant in &e.variants {
+        let ident = zig_ident(&variant.ident.to_string());
+        match &variant.discriminant {
+            Some((_, expr)) => writeln!(out, "    {ident} = {},", zig_expr(expr)?)?,
+            None => writeln!(out, "    {ident},")?,
+        }
+    }
+    writeln!(out, "    _,\n}};")?;
+    Ok(())
+}
+
+fn zig_expr(expr: &Expr) -> Result<String, Box<dyn Error>> {
+    match expr {
+        Expr::Lit(lit) => match &lit.lit {
+            Lit::Int(int) => Ok(int.base10_digits().to_owned()),
+            _ => Err("unsupported enum discriminant".into()),
+        },
+        Expr::Unary(unary) if matches!(unary.op, syn::UnOp::Neg(_)) => {
+            Ok(format!("-{}", zig_expr(&unary.expr)?))
+        }
+        _ => Err("unsupported enum discriminant".into()),
+    }
+}
+
+fn zig_primitive(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "i8" => "i8",
+        "i16" => "i16",
+        "i32" => "i32",
+        "i64" => "i64",
+        "i128" => "i128",
+        "u8" => "u8",
+        "u16" => "u16",
+        "u32" => "u32",
+        "u64" => "u64",
+        "u128" => "u128",
+        "isize" => "isize",
+        "usize" => "usize",
+        "f32" | "c_float" => "f32",
+        "f64" | "c_double" => "f64",
+        "bool" => "bool",
+        // Signed or not as the target's C `char` is, like Rust's.
+        "c_char" => "c_char",
+        "c_schar" => "i8",
+        "c_uchar" => "u8",
+        "c_short" => "c_short",
+        "c_ushort" => "c_ushort",
+        "c_int" => "c_int",
+        "c_uint" => "c_uint",
+        "c_long" => "c_long",
+        "c_ulong" => "c_ulong",
+        "c_longlong" => "c_longlong",
+        "c_ulonglong" => "c_ulonglong",
+        "c_void" => "anyopaque",
+        _ => return None,
+    })
+}
+
+fn zig_return_type(output: &ReturnType) -> Result<String, Box<dyn Error>> {
+    match output {
+        ReturnType::Default => Ok("void".into()),
+        ReturnType::Type(_, ty) => match &**ty {
+            Type::Never(_) => Ok("noreturn".into()),
+            ty => zig_type(ty),
+        },
+    }
+}
+
+fn zig_fn_pointer(f: &TypeBareFn) -> Result<String, Box<dyn Error>> {
+    if !is_c_abi(&f.abi) {
+        return Err("function pointers must be extern \"C\"".into());
+    }
+    let params = f
+        .inputs
+        .iter()
+        .map(|arg| zig_type(&arg.ty))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(format!(
+        "*const fn ({}) callconv(.c) {}",
+        params.join(", "),
+        zig_return_type(&f.output)?
+    ))
+}
+
+/// Maps a Rust type to its Zig spelling. Pointers become C pointers (`[*c]T`)
+/// since Rust raw pointers carry no nullability or length information.
+fn zig_type(ty: &Type) -> Result<String, Box<dyn Error>> {
+    Ok(match ty {
+        Type::Paren(p) => zig_type(&p.elem)?,
+        Type::Group(g) => zig_type(&g.elem)?,
+        Type::Tuple(t) if t.elems.is_empty() => "void".into(),
+        Type::Never(_) => "noreturn".into(),
+        Type::Array(a) => {
+            let len = match &a.len {
+                Expr::Lit(lit) => match &lit.lit {
+                    Lit::Int(int) => int.base10_digits().to_owned(),
+                    _ => return Err("unsupported array length".into()),
+                },
+                Expr::Path(path) => path
+                    .path
+                    .segments
+                    .last()
+                    .map(|s| s.ident.to_string())
+                    .unwrap_or_default(),
+                _ => return Err("unsupported array length".into()),
+            };
+            format!("[{len}]{}", zig_type(&a.elem)?)
+        }
+        Type::Ptr(p) => pointer(&p.elem, p.mutability.is_some(), true)?,
+        Type::Reference(r) => pointer(&r.elem, r.mutability.is_some(), false)?,
+        Type::BareFn(f) => zig_fn_pointer(f)?,
+        Type::Path(path) => {
+            let segment = path.path.segments.last().ok_or("empty type path")?;
+            let name = segment.ident.to_string();
+            if name == "Option" {
+                // Option<extern "C" fn(..)>, Option<&T> and Option<NonNull<T>>
+                // are nullable pointers with the same ABI.
+                let inner = generic_arg(&segment.arguments).ok_or("Option without a type")?;
+                return Ok(format!("?{}", zig_type(inner)?));
+            }
+            if name == "NonNull" {
+                let inner = generic_arg(&segment.arguments).ok_or("NonNull without a type")?;
+                return pointer(inner, true, false);
+            }
+            match zig_primitive(&name) {
+                Some(primitive) => primitive.into(),
+                None => name,
+            }
+        }
+        _ => return Err("unsupported type in C ABI item".into()),
+    })
+}
+
+fn pointer(elem: &Type, mutable: bool, raw: bool) -> Result<String, Box<dyn Error>> {
+    let elem_zig = zig_type(elem)?;
+    let constness = if mutable { "" } else { "const " };
+    // `*mut c_void` is the idiomatic opaque handle; Zig spells it
+    // `?*anyopaque` since C pointers to opaque types aren't allowed.
+    if elem_zig == "anyopaque" {
+        let pointer = format!("*{constness}anyopaque");
+        return Ok(if raw { format!("?{pointer}") } else { pointer });
+    }
+    Ok(if raw {
+        format!("[*c]{constness}{elem_zig}")
+    } else {
+        format!("*{constness}{elem_zig}")
+    })
+}
+
+fn generic_arg(arguments: &PathArguments) -> Option<&Type> {
+    match arguments {
+        PathArguments::AngleBracketed(args) => args.args.iter().find_map(|arg| match arg {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        }),
+        _ => None,
+    }
+}
+
+/// Quotes identifiers that are Zig keywords or primitive type names.
+fn zig_ident(name: &str) -> String {
+    const RESERVED: &[&str] = &[
+        "addrspace",
+        "align",
+        "allowzero",
+        "and",
+        "anyframe",
+        "anytype",
+        "asm",
+        "async",
+        "await",
+        "break",
+        "callconv",
+        "catch",
+        "comptime",
+        "const",
+        "continue",
+        "defer",
+        "else",
+        "enum",
+        "errdefer",
+        "error",
+        "export",
+        "extern",
+        "fn",
+        "for",
+        "if",
+        "inline",
+        "linksection",
+        "noalias",
+        "noinline",
+        "nosuspend",
+        "opaque",
+        "or",
+        "orelse",
+        "packed",
+        "pub",
+        "resume",
+        "return",
+        "struct",
+        "suspend",
+        "switch",
+        "test",
+        "threadlocal",
+        "try",
+        "type",
+        "union",
+        "unreachable",
+        "usingnamespace",
+        "var",
+        "volatile",
+        "while",
+        "void",
+        "bool",
+        "true",
+        "false",
+        "null",
+        "undefined",
+    ];
+    let primitive_int = |s: &str| {
+        (s.starts_with('i') || s.starts_with('u'))
+            && s.len() > 1
+            && s[1..].bytes().all(|b| b.is_ascii_digit())
+    };
+    if RESERVED.contains(&name) || primitive_int(name) {
+        format!("@\"{name}\"")
+    } else {
+        name.to_owned()
+    }
+}
@@ -0,0 +1,391 @@
+//! Call-through tests over a crate's C ABI surface. Layout checks catch types
+//! that disagree; these catch calling convention bugs, which mostly show up on
+//! the less common targets (struct returns on Windows x64, HFAs on aarch64
+//! Apple, wasm's multivalue rules, ...).
+//!
+//! For every exported Rust function this generates a Zig `export fn` that
+//! forwards its arguments to the Rust function and returns its result, plus a
+//! Rust `#[test]` that calls the function both directly and through Zig with
+//! the same sample arguments and compares the results field by field:
+//!
+//! ```no_run
+//! // build.rs
+//! let generator = zig_rs::ffi::Generator::new().crate_root("src/lib.rs");
+//! zig_rs::ffi::RoundTrip::new(generator).compile()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! ```ignore
+//! // src/lib.rs, in the module that declares or imports the FFI items
+//! #[cfg(test)]
+//! mod abi_roundtrip {
+//!     use super::*;
+//!     include!(concat!(env!("OUT_DIR"), "/zig_rs_roundtrip.rs"));
+//! }
+//! ```
+//!
+//! Functions are called twice, so they should be free of side effects.
+//! Functions taking or returning pointers are skipped since there is no
+//! meaningful sample value for them; callback parameters get a generated
+//! callback that returns a sample value.
+
+use std::{collections::HashMap, env, error::Error, fmt::Write as _, path::Path};
+
+use quote::ToTokens;
+use syn::{Fields, FnArg, Item, ItemFn, ReturnType, Type, TypeBareFn, Visibility};
+
+use super::{
+    Generator, export_name, generic_arg, is_c_abi, repr, write_if_changed, zig_ident,
+    zig_return_type, zig_type,
+};
+use crate::{
+    Toolchain,
+    log::{self, Tagger},
+    target,
+};
+
+/// Base name of the generated files and library.
+const NAME: &str = "zig_rs_roundtrip";
+
+/// Generates round-trip tests for the items a [`Generator`] finds.
+#[derive(Debug, Clone)]
+pub struct RoundTrip {
+    generator: Generator,
+}
+
+/// Struct and enum definitions sample values are built from, plus the
+/// callbacks generated along the way.
+#[derive(Default)]
+struct Context {
+    structs: HashMap<String, Vec<(String, Type)>>,
+    enums: HashMap<String, String>,
+    callbacks: String,
+    callback_count: usize,
+}
+
+impl RoundTrip {
+    pub fn new(generator: Generator) -> Self {
+        Self { generator }
+    }
+
+    /// Returns the Zig and Rust halves of the harness.
+    pub fn generate(&self) -> Result<(String, String), Box<dyn Error>> {
+        let items = self.generator.items()?;
+        let mut cx = Context::default();
+        for item in &items {
+            match item {
+                Item::Struct(s) if matches!(s.vis, Visibility::Public(_)) && repr(&s.attrs).0 => {
+                    let fields = match &s.fields {
+                        Fields::Named(fields) => fields
+                            .named
+                            .iter()
+                            .map(|f| (f.ident.as_ref().expect("named").to_string(), f.ty.clone()))
+                            .collect(),
+                        _ => continue,
+                    };
+                    cx.structs.insert(s.ident.to_string(), fields);
+                }
+                Item::Enum(e) if matches!(e.vis, Visibility::Public(_)) => {
+                    let (c, _, int) = repr(&e.attrs);
+                    if let (true, Some(first)) = (c || int.is_some(), e.variants.first()) {
+                        cx.enums
+                            .insert(e.ident.to_string(), first.ident.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut zig = self.generator.generate()?;
+        let mut externs = String::new();
+        let mut tests = String::new();
+        for item in &items {
+            let Item::Fn(f) = item else { continue };
+            if !matches!(f.vis, Visibility::Public(_)) || !is_c_abi(&f.sig.abi) {
+                continue;
+            }
+            let Some(symbol) = export_name(&f.attrs, &f.sig.ident) else {
+                continue;
+            };
+            match function(f, &symbol, &mut cx)? {
+                Some(harness) => {
+                    zig += &harness.zig;
+                    externs += &harness.extern_decl;
+                    tests += &harness.test;
+                }
+                None => {
+                    writeln!(
+                        tests,
+                        "\n// {symbol}: skipped, pointer arguments or results"
+                    )?;
+                }
+            }
+        }
+
+        let mut rust = String::from("// Generated by zig_rs::ffi::RoundTrip. Do not edit.\n");
+        if !externs.is_empty() {
+            writeln!(rust, "\nunsafe extern \"C\" {{{externs}}}")?;
+        }
+        rust += &cx.callbacks;
+        rust += &tests;
+        Ok((zig, rust))
+    }
+
+    /// Writes `zig_rs_roundtrip.zig` and `zig_rs_roundtrip.rs` into `dir`.
+    pub fn write(&self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        let (zig, rust) = self.generate()?;
+        write_if_changed(&dir.join(format!("{NAME}.zig")), &zig)?;
+        write_if_changed(&dir.join(format!("{NAME}.rs")), &rust)?;
+        for path in self.generator.rerun_paths() {
+            build::rerun_if_changed(path);
+        }
+        Ok(())
+    }
+
+    /// For build scripts: writes the harness into `OUT_DIR`, compiles its Zig
+    /// half into a static library for `TARGET` with the bundled toolchain, and
+    /// links it into the crate.
+    pub fn compile(&self) -> Result<(), Box<dyn Error>> {
+        let out_dir = env::var_os("OUT_DIR").ok_or("OUT_DIR is not set")?;
+        let out_dir = Path::new(&out_dir);
+        let rust_target = env::var("TARGET")?;
+        let zig_target = target::zig_target(&rust_target)
+            .ok_or_else(|| format!("no Zig target for {rust_target}"))?;
+        self.write(out_dir)?;
+
+        let lib = if rust_target.ends_with("-msvc") {
+            format!("{NAME}.lib")
+        } else {
+            format!("lib{NAME}.a")
+        };
+        let mut cmd = Toolchain::bundled().command();
+        cmd.current_dir(out_dir)
+            .arg("build-lib")
+            .arg(format!("{NAME}.zig"))
+            .arg("-target")
+            .arg(&zig_target)
+            .arg("-OReleaseSafe")
+            .arg("-fPIC")
+            .arg(format!("-femit-bin={lib}"));
+        let status = log::run_tagged(&mut cmd, &Tagger::new(NAME))?;
+        if !status.success() {
+            return Err(format!("{cmd:?} failed: {status}").into());
+        }
+        build::rustc_link_search_kind("native", out_dir);
+        build::rustc_link_lib_kind("static", NAME);
+        Ok(())
+    }
+}
+
+/// The generated pieces for one exported function.
+struct Harness {
+    /// Zig `export fn` forwarding to the Rust function.
+    zig: String,
+    /// Rust declaration of that Zig function.
+    extern_decl: String,
+    test: String,
+}
+
+/// Returns the harness for `f`, or `None` if it can't be sampled.
+fn function(f: &ItemFn, symbol: &str, cx: &mut Context) -> Result<Option<Harness>, Box<dyn Error>> {
+    let ident = f.sig.ident.to_string();
+    let wrapper = format!("{NAME}_{symbol}");
+    let mut zig_params = Vec::new();
+    let mut zig_args = Vec::new();
+    let mut rust_params = Vec::new();
+    let mut samples = Vec::new();
+    for (i, arg) in f.sig.inputs.iter().enumerate() {
+        let FnArg::Typed(arg) = arg else {
+            return Ok(None);
+        };
+        let Some(sample) = sample(&arg.ty, i + 1, cx) else {
+            return Ok(None);
+        };
+        let name = format!("a{i}");
+        zig_params.push(format!("{name}: {}", zig_type(&arg.ty)?));
+        zig_args.push(name.clone());
+        rust_params.push(format!("{name}: {}", arg.ty.to_token_stream()));
+        samples.push(sample);
+    }
+    let (rust_ret, comparison) = match &f.sig.output {
+        ReturnType::Default => (String::new(), String::new()),
+        ReturnType::Type(_, ty) => {
+            let Some(comparison) = compare(ty, "direct", "through_zig", cx) else {
+                return Ok(None);
+            };
+            (format!(" -> {}", ty.to_token_stream()), comparison)
+        }
+    };
+
+    let zig_fn = format!(
+        "\nexport fn {wrapper}({}) {} {{\n    return {}({});\n}}\n",
+        zig_params.join(", "),
+        zig_return_type(&f.sig.output)?,
+        zig_ident(symbol),
+        zig_args.join(", ")
+    );
+    let extern_decl = format!(
+        "\n    fn {wrapper}({}){rust_ret};\n",
+        rust_params.join(", ")
+    );
+    let args = samples.join(", ");
+    let test = format!(
+        "\n#[test]\n#[allow(unused_unsafe, unused_variables, clippy::float_cmp)]\n\
+         fn roundtrip_{ident}() {{\n    \
+         let direct = unsafe {{ {ident}({args}) }};\n    \
+         let through_zig = unsafe {{ {wrapper}({args}) }};\n\
+         {comparison}}}\n"
+    );
+    Ok(Some(Harness {
+        zig: zig_fn,
+        extern_decl,
+        test,
+    }))
+}
+
+fn is_signed(name: &str) -> Option<bool> {
+    Some(match name {
+        "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "c_schar" | "c_short" | "c_int"
+        | "c_long" | "c_longlong" => true,
+        "u8" | "u16" | "u32" | "u64" | "u128" | "usize" | "c_char" | "c_uchar" | "c_ushort"
+        | "c_uint" | "c_ulong" | "c_ulonglong" => false,
+        _ => return None,
+    })
+}
+
+fn type_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(path) => path.path.segments.last().map(|s| s.ident.to_string()),
+        Type::Paren(p) => type_name(&p.elem),
+        Type::Group(g) => type_name(&g.elem),
+        _ => None,
+    }
+}
+
+/// A Rust expression producing a distinctive sample of `ty`. `seed` varies
+/// the value per argument and field so swapped arguments are caught.
+fn sample(ty: &Type, seed: usize, cx: &mut Context) -> Option<String> {
+    match ty {
+        Type::Paren(p) => return sample(&p.elem, seed, cx),
+        Type::Group(g) => return sample(&g.elem, seed, cx),
+        Type::Array(a) => {
+            let len: usize = match &a.len {
+                syn::Expr::Lit(lit) => match &lit.lit {
+                    syn::Lit::Int(int) => int.base10_parse().ok()?,
+                    _ => return None,
+                },
+                _ => return None,
+            };
+            if len > 32 {
+                return None;
+            }
+            let elems = (0..len)
+                .map(|i| sample(&a.elem, seed + i, cx))
+                .collect::<Option<Vec<_>>>()?;
+            return Some(format!("[{}]", elems.join(", ")));
+        }
+        Type::BareFn(f) => return callback(f, cx),
+        _ => {}
+    }
+    let name = type_name(ty)?;
+    let n = 11 + (seed % 38) * 3;
+    if let Some(signed) = is_signed(&name) {
+        let ty = ty.to_token_stream();
+        return Some(if signed && seed % 2 == 1 {
+            format!("(-{n}) as {ty}")
+        } else {
+            format!("{n} as {ty}")
+        });
+    }
+    match name.as_str() {
+        "f32" | "f64" | "c_float" | "c_double" => {
+            let sign = if seed % 2 == 1 { "-" } else { "" };
+            Some(format!("({sign}{n}.5) as {}", ty.to_token_stream()))
+        }
+        "bool" => Some(seed.is_multiple_of(2).to_string()),
+        "Option" => {
+            let Type::Path(path) = ty else { return None };
+            let inner = generic_arg(&path.path.segments.last()?.arguments)?;
+            match inner {
+                Type::BareFn(f) => Some(format!("Some({})", callback(f, cx)?)),
+                _ => None,
+            }
+        }
+        _ => {
+            if let Some(variant) = cx.enums.get(&name) {
+                return Some(format!("{name}::{variant}"));
+            }
+            let fields = cx.structs.get(&name)?.clone();
+            let fields = fields
+                .iter()
+                .enumerate()
+                .map(|(i, (field, ty))| Some(format!("{field}: {}", sample(ty, seed + i + 1, cx)?)))
+                .collect::<Option<Vec<_>>>()?;
+            Some(format!("{name} {{ {} }}", fields.join(", ")))
+        }
+    }
+}
+
+/// Generates a callback matching `f` that returns a sample value, and returns
+/// its name.
+fn callback(f: &TypeBareFn, cx: &mut Context) -> Option<String> {
+    if !is_c_abi(&f.abi) {
+        return None;
+    }
+    cx.callback_count += 1;
+    let name = format!("{NAME}_callback_{}", cx.callback_count);
+    let params: Vec<String> = f
+        .inputs
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| format!("_a{i}: {}", arg.ty.to_token_stream()))
+        .collect();
+    let (ret, body) = match &f.output {
+        ReturnType::Default => (String::new(), String::new()),
+        ReturnType::Type(_, ret) => (
+            format!(" -> {}", ret.to_token_stream()),
+            sample(ret, cx.callback_count, cx)?,
+        ),
+    };
+    let _ = writeln!(
+        cx.callbacks,
+        "\nextern \"C\" fn {name}({}){ret} {{\n    {body}\n}}",
+        params.join(", ")
+    );
+    Some(format!("{name} as {}", f.to_token_stream()))
+}
+
+/// Statements asserting that the values at places `a` and `b` of type `ty`
+/// are identical, or `None` if `ty` can't be compared.
+fn compare(ty: &Type, a: &str, b: &str, cx: &Context) -> Option<String> {
+    match ty {
+        Type::Paren(p) => return compare(&p.elem, a, b, cx),
+        Type::Group(g) => return compare(&g.elem, a, b, cx),
+        Type::Tuple(t) if t.elems.is_empty() => return Some(String::new()),
+        Type::Array(arr) => {
+            let inner = compare(&arr.elem, &format!("{a}[i]"), &format!("{b}[i]"), cx)?;
+            return Some(format!("    for i in 0..{a}.len() {{\n{inner}    }}\n"));
+        }
+        _ => {}
+    }
+    let name = type_name(ty)?;
+    if is_signed(&name).is_some() || name == "bool" {
+        return Some(format!("    assert_eq!({a}, {b}, \"{a} vs {b}\");\n"));
+    }
+    match name.as_str() {
+        "f32" | "f64" | "c_float" | "c_double" => Some(format!(
+            "    assert_eq!({a}.to_bits(), {b}.to_bits(), \"{a} vs {b}\");\n"
+        )),
+        _ if cx.enums.contains_key(&name) => Some(format!(
+            "    assert!(::std::mem::discriminant(&{a}) == ::std::mem::discriminant(&{b}), \"{a} vs {b}\");\n"
+        )),
+        _ => {
+            let fields = cx.structs.get(&name)?;
+            let mut out = String::new();
+            for (field, ty) in fields {
+                out += &compare(ty, &format!("{a}.{field}"), &format!("{b}.{field}"), cx)?;
+            }
+            Some(out)
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! `zig fmt` from Rust, for tests and tools that keep embedded Zig code
+//! formatted without running the CLI and parsing what it prints:
+//!
+//! ```no_run
+//! let diagnostics = zig_rs::fmt::check_paths(&["src/zig"])?;
+//! assert!(diagnostics.is_empty(), "run zig fmt:\n{diagnostics:#?}");
+//! # Ok::<(), zig_rs::Error>(())
+//! ```
+//!
+//! `zig fmt --check` only names the files it would change, so
+//! [`check_paths()`] formats each of them again to point at the first line
+//! that differs.
+//!
+//! Whole-tree checks of large repos are slow for pre-commit hooks, so
+//! [`check_files()`] and [`format_files()`] take the files that changed,
+//! e.g. from `git diff --name-only`, and skip the ones that aren't Zig or no
+//! longer exist. Without such a list, [`check_changed()`] keeps a [`State`]
+//! file of what was last checked as formatted and only checks files whose
+//! contents changed since:
+//!
+//! ```no_run
+//! let diagnostics = zig_rs::fmt::check_changed(&["src/zig"], "target/zig-rs.fmt")?;
+//! # Ok::<(), zig_rs::Error>(())
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    Error, Toolchain,
+    diagnostic::{self, Diagnostic, Severity},
+    error::CommandError,
+    manifest, process,
+};
+
+/// Directories `zig fmt` doesn't descend into, and neither does [`State`].
+const SKIPPED_DIRS: &[&str] = &[".zig-cache", "zig-cache", "zig-out"];
+
+/// The files last checked as formatted, with the SHA-256 of their contents
+/// then; see [`check_changed()`]. Written as a line per file:
+///
+/// ```text
+/// formatted <sha256> <file>
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct State {
+    pub files: BTreeMap<PathBuf, String>,
+}
+
+/// Formats Zig source with the bundled toolchain; see [`format_str_with()`].
+pub fn format_str(source: &str) -> Result<String, Error> {
+    format_str_with(&Toolchain::bundled(), source)
+}
+
+/// Formats Zig source with `toolchain`'s `zig fmt --stdin`. Source that
+/// doesn't parse comes back as [`Error::Command`] with the diagnostics, whose
+/// file is `<stdin>`.
+pub fn format_str_with(toolchain: &Toolchain, source: &str) -> Result<String, Error> {
+    let mut cmd = toolchain.command();
+    cmd.arg("fmt").arg("--stdin");
+    let output = process::output_with_input(&mut cmd, source.as_bytes())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        return Err(Error::Command(Box::new(CommandError::new(
+            &cmd,
+            output.status,
+            diagnostic::parse(&stderr),
+            stderr,
+        ))));
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| Error::Config("zig fmt printed invalid UTF-8".into()))
+}
+
+/// Checks Zig files, and the `.zig` and `.zon` files under directories, with
+/// the bundled toolchain; see [`check_paths_with()`].
+pub fn check_paths(paths: &[impl AsRef<Path>]) -> Result<Vec<Diagnostic>, Error> {
+    check_paths_with(&Toolchain::bundled(), paths)
+}
+
+/// Checks that `paths` are formatted with `toolchain`'s `zig fmt --check`.
+/// Each file that isn't gets an error at the first line formatting changes,
+/// and files that don't parse get the parser's diagnostics. Empty when
+/// everything is formatted.
+pub fn check_paths_with(
+    toolchain: &Toolchain,
+    paths: &[impl AsRef<Path>],
+) -> Result<Vec<Diagnostic>, Error> {
+    let mut cmd = toolchain.command();
+    cmd.arg("fmt")
+        .arg("--check")
+        .args(paths.iter().map(AsRef::as_ref));
+    let output = process::output(&mut cmd)?;
+    if output.status.success() {
+        return Ok(Vec::new());
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    let mut diagnostics = diagnostic::parse(&stderr);
+    for file in stdout.lines().filter(|line| !line.is_empty()) {
+        let source = fs_err::read_to_string(file)?;
+        let (line, column) = first_change(&source, &format_str_with(toolchain, &source)?);
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            file: file.into(),
+            line,
+            column,
+            message: "not formatted with zig fmt".into(),
+            flag: None,
+            count: 1,
+        });
+    }
+    if diagnostics.is_empty() {
+        return Err(Error::Command(Box::new(CommandError::new(
+            &cmd,
+            output.status,
+            diagnostics,
+            stderr,
+        ))));
+    }
+    Ok(diagnostics)
+}
+
+/// Checks the Zig files among `changed` with the bundled toolchain; see
+/// [`check_files_with()`].
+pub fn check_files(changed: &[impl AsRef<Path>]) -> Result<Vec<Diagnostic>, Error> {
+    check_files_with(&Toolchain::bundled(), changed)
+}
+
+/// Checks the `.zig` and `.zon` files among `changed` like
+/// [`check_paths_with()`]. Other files, directories and files that were
+/// deleted are skipped, so `changed` can be a list of changes straight from
+/// the VCS.
+pub fn check_files_with(
+    toolchain: &Toolchain,
+    changed: &[impl AsRef<Path>],
+) -> Result<Vec<Diagnostic>, Error> {
+    let files = zig_files(changed);
+    if files.is_empty() {
+        return Ok(Vec::new());
+    }
+    check_paths_with(toolchain, &files)
+}
+
+/// Formats the Zig files among `changed` with the bundled toolchain; see
+/// [`format_files_with()`].
+pub fn format_files(changed: &[impl AsRef<Path>]) -> Result<Vec<PathBuf>, Error> {
+    format_files_with(&Toolchain::bundled(), changed)
+}
+
+/// Formats the `.zig` and `.zon` files among `changed` in place with
+/// `toolchain`'s `zig fmt`, skipping the rest like [`check_files_with()`],
+/// and returns the files it rewrote. Files that don't parse are left as they
+/// are and come back as [`Error::Command`].
+pub fn format_files_with(
+    toolchain: &Toolchain,
+    changed: &[impl AsRef<Path>],
+) -> Result<Vec<PathBuf>, Error> {
+    let files = zig_files(changed);
+    if files.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut cmd = toolchain.command();
+    cmd.arg("fmt").args(&files);
+    let output = process::output(&mut cmd)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        return Err(Error::Command(Box::new(CommandError::new(
+            &cmd,
+            output.status,
+            diagnostic::parse(&stderr),
+            stderr,
+        ))));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Checks the Zig files in `paths` that changed since the last check with
+/// the bundled toolchain; see [`check_changed_with()`].
+pub fn check_changed(
+    paths: &[impl AsRef<Path>],
+    state: impl AsRef<Path>,
+) -> Result<Vec<Diagnostic>, Error> {
+    check_changed_with(&Toolchain::bundled(), paths, state)
+}
+
+/// Checks the Zig files in `paths`, and under directories among them, whose
+/// contents aren't what the [`State`] in the file `state` recorded as
+/// formatted, like [`check_paths_with()`]. Files that come back formatted
+/// are recorded, and files that don't are checked again next time. A
+/// missing `state` checks everything.
+pub fn check_changed_with(
+    toolchain: &Toolchain,
+    paths: &[impl AsRef<Path>],
+    state: impl AsRef<Path>,
+) -> Result<Vec<Diagnostic>, Error> {
+    let state_path = state.as_ref();
+    let mut state = match State::read(state_path) {
+        Ok(state) => state,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => State::default(),
+        Err(e) => return Err(e.into()),
+    };
+    let changed = state.changed(paths)?;
+    let files: Vec<&Path> = changed.iter().map(|(file, _)| file.as_path()).collect();
+    let diagnostics = check_files_with(toolchain, &files)?;
+    for (file, sha256) in changed {
+        // `zig fmt` prints files as it was given them.
+        if diagnostics
+            .iter()
+            .any(|diagnostic| Path::new(&diagnostic.file) == file)
+        {
+            state.files.remove(&file);
+        } else {
+            state.files.insert(file, sha256);
+        }
+    }
+    if let Some(parent) = state_path.parent() {
+        fs_err::create_dir_all(parent)?;
+    }
+    state.write(state_path)?;
+    Ok(diagnostics)
+}
+
+impl State {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let mut files = BTreeMap::new();
+        for line in fs_err::read_to_string(path)?.lines() {
+            let fields: Vec<&str> = line.splitn(3, ' ').collect();
+            match fields[..] {
+                ["formatted", sha256, file] => files.insert(file.into(), sha256.into()),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}: bad line {line:?}", path.display()),
+                    ));
+                }
+            };
+        }
+        Ok(Self { files })
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs_err::write(path, self.to_string())
+    }
+
+    /// The `.zig` and `.zon` files in `paths`, and under directories among
+    /// them, whose contents differ from what's recorded, with their
+    /// SHA-256 now.
+    pub fn changed(&self, paths: &[impl AsRef<Path>]) -> io::Result<Vec<(PathBuf, String)>> {
+        let mut files = Vec::new();
+        for path in paths {
+            let path = path.as_ref();
+            if path.is_dir() {
+                walk(path, &mut files)?;
+            } else {
+                files.extend(zig_files(&[path]));
+            }
+        }
+        let mut changed = Vec::new();
+        for file in files {
+            let sha256 = manifest::hash_file(&file)?;
+            if self.files.get(&file) != Some(&sha256) {
+                changed.push((file, sha256));
+            }
+        }
+        Ok(changed)
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (file, sha256) in &self.files {
+            // Paths go last, since they may have spaces.
+            writeln!(f, "formatted {sha256} {}", file.to_string_lossy())?;
+        }
+        Ok(())
+    }
+}
+
+/// The `.zig` and `.zon` files among `paths` that exist.
+fn zig_files(paths: &[impl AsRef<Path>]) -> Vec<PathBuf> {
+    paths
+        .iter()
+        .map(AsRef::as_ref)
+        .filter(|path| is_zig(path) && path.is_file())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+fn is_zig(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "zig" || extension == "zon")
+}
+
+/// The `.zig` and `.zon` files under `dir`, in the order of their paths.
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs_err::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if !SKIPPED_DIRS.contains(&&*name) {
+                walk(&path, files)?;
+            }
+        } else if is_zig(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The 1-based line and column where `formatted` first differs from
+/// `source`.
+fn first_change(source: &str, formatted: &str) -> (u32, u32) {
+    let mut line = 1;
+    let mut column = 1;
+    for (a, b) in source.chars().zip(formatted.chars()) {
+        if a != b {
+            break;
+        }
+        if a == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+    (line, column)
+}
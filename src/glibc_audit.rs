@@ -0,0 +1,325 @@
+//! Checks that a binary linked for a glibc target runs on the glibc it was
+//! pinned to. Every glibc symbol an ELF binary imports carries the version
+//! it was introduced at (`memcpy@GLIBC_2.14`), and the dynamic loader
+//! refuses to start a binary that needs a version the system's glibc doesn't
+//! have. Zig links against stubs of the pinned version, but objects and
+//! archives built elsewhere can still pull in newer symbols, which only shows
+//! once the binary hits an old distro:
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! let exe = Path::new("target/x86_64-unknown-linux-gnu/release/app");
+//! if let Some(report) = zig_rs::glibc_audit::audit(exe, "2.17")? {
+//!     eprintln!("{report}");
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! [`ZigExe::glibc_check()`](crate::zig_exe::ZigExe::glibc_check) runs the
+//! audit on the executables it links.
+
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use crate::target::parse_version;
+
+const SHT_DYNSYM: u32 = 11;
+const SHT_GNU_VERNEED: u32 = 0x6fff_fffe;
+const SHT_GNU_VERSYM: u32 = 0x6fff_ffff;
+const VERSYM_HIDDEN: u16 = 0x8000;
+
+/// What to do when a binary needs a newer glibc than its target is pinned
+/// to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlibcCheck {
+    /// Print the [`Report`] as a cargo warning.
+    Warn,
+    /// Fail the build with [`Error::Glibc`](crate::Error::Glibc).
+    Deny,
+}
+
+/// A symbol a binary imports at a glibc symbol version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionedSymbol {
+    pub symbol: String,
+    /// The library the version is looked up in, e.g. `libc.so.6`.
+    pub library: String,
+    /// The glibc version without the `GLIBC_` prefix, e.g. `2.14`.
+    pub version: String,
+}
+
+/// The symbols of a binary that need a newer glibc than it targets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub path: PathBuf,
+    /// The glibc version the binary targets.
+    pub max: String,
+    /// The offending symbols, newest version first.
+    pub too_new: Vec<VersionedSymbol>,
+}
+
+impl Report {
+    /// The newest glibc version the binary needs.
+    pub fn needed(&self) -> &str {
+        self.too_new
+            .first()
+            .map_or(self.max.as_str(), |symbol| &symbol.version)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} needs glibc {} but targets glibc {}:",
+            self.path.display(),
+            self.needed(),
+            self.max
+        )?;
+        for symbol in &self.too_new {
+            write!(
+                f,
+                "\n  {}@GLIBC_{} ({})",
+                symbol.symbol, symbol.version, symbol.library
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the binary at `path` against glibc `max` (`2.17`). Gives `None`
+/// when every glibc symbol it imports is at most that old, which includes
+/// files that aren't ELF and statically linked ones.
+pub fn audit(path: &Path, max: &str) -> io::Result<Option<Report>> {
+    let Some(max_parts) = parse_version(max) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid glibc version {max:?}"),
+        ));
+    };
+    let mut too_new: Vec<VersionedSymbol> = glibc_symbols(path)?
+        .into_iter()
+        .filter(|symbol| parse_version(&symbol.version).is_some_and(|version| version > max_parts))
+        .collect();
+    if too_new.is_empty() {
+        return Ok(None);
+    }
+    too_new.sort_by(|a, b| {
+        let version = |symbol: &VersionedSymbol| parse_version(&symbol.version);
+        version(b)
+            .cmp(&version(a))
+            .then_with(|| a.symbol.cmp(&b.symbol))
+    });
+    Ok(Some(Report {
+        path: path.into(),
+        max: max.into(),
+        too_new,
+    }))
+}
+
+/// The undefined dynamic symbols of the ELF file at `path` that import a
+/// `GLIBC_` symbol version, such as `GLIBC_2.14` or `GLIBC_PRIVATE`. Empty
+/// for files that aren't ELF or import nothing from glibc.
+pub fn glibc_symbols(path: &Path) -> io::Result<Vec<VersionedSymbol>> {
+    let data = fs_err::read(path)?;
+    versioned_symbols(&data).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {e}", path.display()),
+        )
+    })
+}
+
+struct Section {
+    kind: u32,
+    offset: usize,
+    size: usize,
+    link: u32,
+    info: u32,
+    entsize: usize,
+}
+
+fn versioned_symbols(data: &[u8]) -> Result<Vec<VersionedSymbol>, String> {
+    let Some(elf) = Elf::new(data) else {
+        return Ok(Vec::new());
+    };
+    let sections = elf.sections()?;
+    let find = |kind| sections.iter().find(|section| section.kind == kind);
+    let (Some(dynsym), Some(versym), Some(verneed)) = (
+        find(SHT_DYNSYM),
+        find(SHT_GNU_VERSYM),
+        find(SHT_GNU_VERNEED),
+    ) else {
+        return Ok(Vec::new());
+    };
+
+    // Version index -> (library, version name), from `.gnu.version_r`.
+    let verneed_strings = sections
+        .get(verneed.link as usize)
+        .ok_or("bad .gnu.version_r string table")?;
+    let mut versions = Vec::new();
+    let mut need = verneed.offset;
+    for _ in 0..verneed.info {
+        let count = elf.u16(need + 2)?;
+        let library = elf.string(verneed_strings, elf.u32(need + 4)?)?;
+        let mut aux = need + elf.u32(need + 8)? as usize;
+        for _ in 0..count {
+            let index = elf.u16(aux + 6)?;
+            let name = elf.string(verneed_strings, elf.u32(aux + 8)?)?;
+            if let Some(version) = name.strip_prefix("GLIBC_") {
+                versions.push((index, library.clone(), version.to_owned()));
+            }
+            aux += elf.u32(aux + 12)? as usize;
+        }
+        let next = elf.u32(need + 12)? as usize;
+        if next == 0 {
+            break;
+        }
+        need += next;
+    }
+    if versions.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let symbol_strings = sections
+        .get(dynsym.link as usize)
+        .ok_or("bad .dynsym string table")?;
+    let entsize = if dynsym.entsize != 0 {
+        dynsym.entsize
+    } else if elf.is_64 {
+        24
+    } else {
+        16
+    };
+    let shndx_offset = if elf.is_64 { 6 } else { 14 };
+    let mut symbols = Vec::new();
+    for i in 0..dynsym.size / entsize {
+        let entry = dynsym.offset + i * entsize;
+        // Only undefined symbols are imports.
+        if elf.u16(entry + shndx_offset)? != 0 {
+            continue;
+        }
+        let index = elf.u16(versym.offset + i * 2)? & !VERSYM_HIDDEN;
+        let Some((_, library, version)) = versions.iter().find(|(i, ..)| *i == index) else {
+            continue;
+        };
+        let symbol = elf.string(symbol_strings, elf.u32(entry)?)?;
+        if !symbol.is_empty() {
+            symbols.push(VersionedSymbol {
+                symbol,
+                library: library.clone(),
+                version: version.clone(),
+            });
+        }
+    }
+    Ok(symbols)
+}
+
+struct Elf<'a> {
+    data: &'a [u8],
+    is_64: bool,
+    big_endian: bool,
+}
+
+impl<'a> Elf<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        if data.len() < 16 || &data[..4] != b"\x7fELF" {
+            return None;
+        }
+        let is_64 = match data[4] {
+            1 => false,
+            2 => true,
+            _ => return None,
+        };
+        let big_endian = match data[5] {
+            1 => false,
+            2 => true,
+            _ => return None,
+        };
+        Some(Self {
+            data,
+            is_64,
+            big_endian,
+        })
+    }
+
+    fn sections(&self) -> Result<Vec<Section>, String> {
+        let (shoff, shentsize, shnum) = if self.is_64 {
+            (self.u64(0x28)?, self.u16(0x3a)?, self.u16(0x3c)?)
+        } else {
+            (self.u32(0x20)?.into(), self.u16(0x2e)?, self.u16(0x30)?)
+        };
+        let mut sections = Vec::new();
+        for i in 0..shnum as usize {
+            let header = shoff as usize + i * shentsize as usize;
+            sections.push(if self.is_64 {
+                Section {
+                    kind: self.u32(header + 4)?,
+                    offset: self.u64(header + 24)? as usize,
+                    size: self.u64(header + 32)? as usize,
+                    link: self.u32(header + 40)?,
+                    info: self.u32(header + 44)?,
+                    entsize: self.u64(header + 56)? as usize,
+                }
+            } else {
+                Section {
+                    kind: self.u32(header + 4)?,
+                    offset: self.u32(header + 16)? as usize,
+                    size: self.u32(header + 20)? as usize,
+                    link: self.u32(header + 24)?,
+                    info: self.u32(header + 28)?,
+                    entsize: self.u32(header + 36)? as usize,
+                }
+            });
+        }
+        Ok(sections)
+    }
+
+    /// The NUL-terminated string at `offset` in the string table `section`.
+    fn string(&self, section: &Section, offset: u32) -> Result<String, String> {
+        let start = section.offset + offset as usize;
+        let rest = self
+            .data
+            .get(start..section.offset + section.size)
+            .ok_or("string out of bounds")?;
+        let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+        Ok(String::from_utf8_lossy(&rest[..end]).into_owned())
+    }
+
+    fn bytes<const N: usize>(&self, offset: usize) -> Result<[u8; N], String> {
+        self.data
+            .get(offset..offset + N)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| "truncated ELF file".into())
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, String> {
+        let bytes = self.bytes(offset)?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, String> {
+        let bytes = self.bytes(offset)?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn u64(&self, offset: usize) -> Result<u64, String> {
+        let bytes = self.bytes(offset)?;
+        Ok(if self.big_endian {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        })
+    }
+}
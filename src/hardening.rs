@@ -0,0 +1,117 @@
+//! Security hardening flags, spelled correctly for each target so nobody has to
+//! research which of `-z relro`, `_FORTIFY_SOURCE` or `-fcf-protection` a given
+//! Zig target accepts.
+
+/// A set of hardening measures. [`Hardening::recommended()`] turns on
+/// everything the target supports; individual measures can be switched off.
+/// Measures a target has no equivalent for are left out silently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hardening {
+    /// `-fstack-protector-strong`. Not on wasm, freestanding or Windows
+    /// targets, which have no `__stack_chk_fail` in Zig's libcs.
+    pub stack_protector: bool,
+    /// `-D_FORTIFY_SOURCE=<level>` for glibc and Apple targets, in optimized
+    /// builds only since fortification relies on the optimizer.
+    pub fortify: Option<u8>,
+    /// `-z relro` on ELF targets.
+    pub relro: bool,
+    /// `-z now` on ELF targets, so the relocated GOT can be read-only.
+    pub now: bool,
+    /// `-z noexecstack` on ELF targets.
+    pub noexecstack: bool,
+    /// Control-flow integrity where the hardware has it: CET
+    /// (`-fcf-protection=full`) on x86 and x86_64, PAC/BTI
+    /// (`-mbranch-protection=standard`) on aarch64.
+    pub cfi: bool,
+}
+
+impl Default for Hardening {
+    fn default() -> Self {
+        Self::recommended()
+    }
+}
+
+impl Hardening {
+    pub fn recommended() -> Self {
+        Self {
+            stack_protector: true,
+            fortify: Some(2),
+            relro: true,
+            now: true,
+            noexecstack: true,
+            cfi: true,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            stack_protector: false,
+            fortify: None,
+            relro: false,
+            now: false,
+            noexecstack: false,
+            cfi: false,
+        }
+    }
+
+    /// Compiler flags for sources built for `zig_target`. `optimized` is
+    /// whether the build uses an opt level above 0.
+    pub fn compile_args(&self, zig_target: &str, optimized: bool) -> Vec<String> {
+        let (arch, os, abi) = split(zig_target);
+        let mut args = Vec::new();
+        let hosted = !matches!(
+            os,
+            "freestanding" | "uefi" | "wasi" | "emscripten" | "windows"
+        );
+        if self.stack_protector && hosted && !arch.starts_with("wasm") {
+            args.push("-fstack-protector-strong".into());
+        }
+        if let Some(level) = self.fortify {
+            let libc = (os == "linux" && abi.starts_with("gnu")) || matches!(os, "macos" | "ios");
+            if optimized && libc {
+                args.push(format!("-D_FORTIFY_SOURCE={level}"));
+            }
+        }
+        if self.cfi {
+            match arch {
+                "x86" | "x86_64" => args.push("-fcf-protection=full".into()),
+                "aarch64" => args.push("-mbranch-protection=standard".into()),
+                _ => {}
+            }
+        }
+        args
+    }
+
+    /// Linker driver flags for binaries linked for `zig_target`.
+    pub fn link_args(&self, zig_target: &str) -> Vec<String> {
+        let (_, os, _) = split(zig_target);
+        let elf = !matches!(
+            os,
+            "macos" | "ios" | "windows" | "uefi" | "wasi" | "emscripten" | "freestanding"
+        );
+        // The Solaris link editor only knows `-z now` of these.
+        let solaris_ld = crate::target::uses_solaris_ld(zig_target);
+        let mut args = Vec::new();
+        if elf {
+            for (enabled, flag) in [
+                (self.relro && !solaris_ld, "relro"),
+                (self.now, "now"),
+                (self.noexecstack && !solaris_ld, "noexecstack"),
+            ] {
+                if enabled {
+                    args.push(format!("-Wl,-z,{flag}"));
+                }
+            }
+        }
+        args
+    }
+}
+
+fn split(zig_target: &str) -> (&str, &str, &str) {
+    let mut parts = zig_target.splitn(3, '-');
+    (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or_default(),
+    )
+}
@@ -0,0 +1,237 @@
+//! A rebuild-and-reload loop for Zig plugins loaded as shared libraries, the
+//! usual game and audio development workflow:
+//!
+//! ```no_run
+//! use zig_rs::hot_reload::{Plugin, Symbol};
+//!
+//! let mut plugin = Plugin::new("synth", "plugin/synth.zig");
+//! plugin.load()?;
+//! loop {
+//!     if let Err(e) = plugin.reload_if_changed() {
+//!         eprintln!("{e}"); // keeps running the last good build
+//!     }
+//!     let render: Symbol<extern "C" fn(f32) -> f32> =
+//!         unsafe { plugin.get(b"render")? };
+//!     render(0.5);
+//! #   break;
+//! }
+//! # Ok::<(), zig_rs::Error>(())
+//! ```
+//!
+//! Every build goes to a new file (`libsynth-3.so`), so the library in use is
+//! never overwritten, which Windows doesn't allow while it is loaded and which
+//! crashes processes elsewhere. The new build is loaded before the old one is
+//! unloaded; a failed build leaves the old one in place. Symbols must not be
+//! kept across a reload.
+
+use std::{
+    env::consts::{DLL_PREFIX, DLL_SUFFIX},
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+pub use libloading::{Library, Symbol};
+
+use crate::{
+    Error, Toolchain, diagnostic,
+    error::CommandError,
+    link_report,
+    log::{self, Tagger},
+};
+
+/// A Zig shared library built for the host and reloaded when its sources
+/// change.
+#[derive(Debug)]
+pub struct Plugin {
+    name: String,
+    root: PathBuf,
+    watched: Vec<PathBuf>,
+    optimize: String,
+    out_dir: PathBuf,
+    toolchain: Toolchain,
+    generation: u64,
+    built_at: Option<SystemTime>,
+    loaded: Option<(Library, PathBuf)>,
+}
+
+impl Plugin {
+    /// A plugin called `name` built from the root source file `root`. The
+    /// directory containing `root` is watched.
+    pub fn new(name: &str, root: impl AsRef<Path>) -> Self {
+        let root = root.as_ref().to_owned();
+        let dir = root.parent().unwrap_or(Path::new(".")).to_owned();
+        Self {
+            name: name.into(),
+            watched: vec![dir],
+            root,
+            optimize: "Debug".into(),
+            out_dir: std::env::temp_dir().join("zig-rs-hot-reload").join(name),
+            toolchain: Toolchain::bundled(),
+            generation: 0,
+            built_at: None,
+            loaded: None,
+        }
+    }
+
+    /// Also rebuilds when files under `path` change.
+    pub fn watch(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.watched.push(path.as_ref().into());
+        self
+    }
+
+    /// Zig's optimize mode. Defaults to `Debug`.
+    pub fn optimize(&mut self, mode: &str) -> &mut Self {
+        self.optimize = mode.into();
+        self
+    }
+
+    /// Where the builds go. Defaults to a directory under the system's
+    /// temporary directory; builds from earlier runs in it are removed on
+    /// the first [`load()`](Self::load).
+    pub fn out_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.out_dir = dir.as_ref().into();
+        self
+    }
+
+    pub fn toolchain(&mut self, toolchain: Toolchain) -> &mut Self {
+        self.toolchain = toolchain;
+        self
+    }
+
+    /// Builds and loads the plugin if it isn't loaded yet.
+    pub fn load(&mut self) -> Result<&Library, Error> {
+        if self.loaded.is_none() {
+            self.remove_stale_builds()?;
+            self.rebuild()?;
+        }
+        Ok(&self.loaded.as_ref().expect("loaded above").0)
+    }
+
+    /// Rebuilds and reloads the plugin when a watched file changed since the
+    /// last build, returning whether it did. On a failed build the previous
+    /// library stays loaded.
+    pub fn reload_if_changed(&mut self) -> Result<bool, Error> {
+        let changed = match self.built_at {
+            Some(built_at) => self.latest_change()? > Some(built_at),
+            None => true,
+        };
+        if changed {
+            self.rebuild()?;
+        }
+        Ok(changed)
+    }
+
+    /// The loaded library, if any.
+    pub fn library(&self) -> Option<&Library> {
+        self.loaded.as_ref().map(|(library, _)| library)
+    }
+
+    /// Looks up `symbol` in the loaded library.
+    ///
+    /// # Safety
+    ///
+    /// `T` must match the symbol's type, as for [`Library::get()`], and the
+    /// symbol must not be used after the next reload.
+    pub unsafe fn get<T>(&self, symbol: &[u8]) -> Result<Symbol<'_, T>, Error> {
+        let library = self
+            .library()
+            .ok_or_else(|| Error::Config(format!("plugin {} isn't loaded", self.name)))?;
+        unsafe { library.get(symbol) }.map_err(|e| Error::Io(io::Error::other(e)))
+    }
+
+    fn rebuild(&mut self) -> Result<(), Error> {
+        let started = SystemTime::now();
+        self.generation += 1;
+        fs_err::create_dir_all(&self.out_dir)?;
+        let path = self.out_dir.join(format!(
+            "{DLL_PREFIX}{}-{}{DLL_SUFFIX}",
+            self.name, self.generation
+        ));
+        let mut cmd = self.toolchain.command();
+        cmd.arg("build-lib")
+            .arg("-dynamic")
+            .arg(&self.root)
+            .arg("-O")
+            .arg(&self.optimize)
+            .arg("--name")
+            .arg(&self.name)
+            .arg(format!("-femit-bin={}", path.display()));
+        let tagger = Tagger::new(&format!("hot-reload/{}", self.name));
+        let (status, output) = log::run_tagged_captured(&mut cmd, &tagger)?;
+        // A failed build isn't retried until something changes again.
+        self.built_at = Some(started);
+        if !status.success() {
+            let (status, output) = link_report::rerun_verbose(&mut cmd, &tagger, status, output)?;
+            return Err(Error::Command(Box::new(CommandError::new(
+                &cmd,
+                status,
+                diagnostic::parse(&output),
+                output,
+            ))));
+        }
+
+        // SAFETY: loading runs the library's initializers, which the plugin
+        // author controls like the rest of its code.
+        let library = unsafe { Library::new(&path) }.map_err(|e| Error::Io(io::Error::other(e)))?;
+        if let Some((old, old_path)) = self.loaded.replace((library, path)) {
+            drop(old);
+            // Windows may keep the file locked a moment longer; the next
+            // run's `load()` removes it then.
+            let _ = fs_err::remove_file(old_path);
+        }
+        Ok(())
+    }
+
+    /// The newest modification time of a watched file.
+    fn latest_change(&self) -> io::Result<Option<SystemTime>> {
+        let mut latest = None;
+        for path in &self.watched {
+            latest = latest.max(newest_mtime(path)?);
+        }
+        Ok(latest)
+    }
+
+    /// Removes builds of this plugin left in the output directory by earlier
+    /// runs.
+    fn remove_stale_builds(&self) -> io::Result<()> {
+        let Ok(entries) = fs_err::read_dir(&self.out_dir) else {
+            return Ok(());
+        };
+        let prefix = format!("{DLL_PREFIX}{}-", self.name);
+        for entry in entries {
+            let path = entry?.path();
+            let stale = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(&prefix));
+            if stale {
+                let _ = fs_err::remove_file(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The newest modification time under `path`, skipping Zig's cache and
+/// output directories.
+fn newest_mtime(path: &Path) -> io::Result<Option<SystemTime>> {
+    let metadata = match fs_err::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if !metadata.is_dir() {
+        return Ok(Some(metadata.modified()?));
+    }
+    let mut latest = None;
+    for entry in fs_err::read_dir(path)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if matches!(name.to_str(), Some(".zig-cache" | "zig-cache" | "zig-out")) {
+            continue;
+        }
+        latest = latest.max(newest_mtime(&entry.path())?);
+    }
+    Ok(latest)
+}
@@ -0,0 +1,262 @@
+//! The compiler's intermediate forms of a Zig module, for tools that look at
+//! generated code: ZIR, AIR, LLVM IR and bitcode, and assembly.
+//!
+//! ```no_run
+//! use zig_rs::inspect::{Inspect, Output};
+//!
+//! let inspect = Inspect::new("src/hash.zig").target("x86_64-unknown-linux-gnu").clone();
+//! if let Some(ir) = inspect.emit(Output::LlvmIr)? {
+//!     println!("LLVM IR in {}", ir.display());
+//! }
+//! # Ok::<(), zig_rs::Error>(())
+//! ```
+//!
+//! Which forms a toolchain produces depends on how it was built: ZIR and AIR
+//! dumps need a Zig built with debug extensions, which release builds
+//! aren't, and LLVM IR and bitcode need the LLVM backend. A form the
+//! toolchain can't produce comes back as `None` rather than an error.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    Error, Toolchain, diagnostic, error::CommandError, process, target, zig_build, zig_imports,
+};
+
+/// What the compiler says when it doesn't produce a form at all, rather
+/// than failing on the source.
+const UNSUPPORTED: &[&str] = &[
+    "unrecognized parameter",
+    "debug extensions",
+    "requires LLVM",
+    "LLVM extensions",
+    "not built with LLVM",
+];
+
+/// An intermediate form of a module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Output {
+    /// The untyped IR of each file, from `zig ast-check -t`.
+    Zir,
+    /// The typed, analyzed IR of each function, from `--verbose-air`.
+    Air,
+    /// Textual LLVM IR, from `-femit-llvm-ir`.
+    LlvmIr,
+    /// LLVM bitcode, from `-femit-llvm-bc`.
+    LlvmBitcode,
+    /// Assembly, from `-femit-asm`.
+    Assembly,
+}
+
+impl Output {
+    pub const ALL: [Self; 5] = [
+        Self::Zir,
+        Self::Air,
+        Self::LlvmIr,
+        Self::LlvmBitcode,
+        Self::Assembly,
+    ];
+
+    /// The extension of the files [`Inspect::emit()`] writes.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Zir => "zir",
+            Self::Air => "air",
+            Self::LlvmIr => "ll",
+            Self::LlvmBitcode => "bc",
+            Self::Assembly => "s",
+        }
+    }
+}
+
+/// Compiles a Zig root source file for its intermediate forms with
+/// `zig build-obj`, without keeping an object.
+#[derive(Debug, Clone)]
+pub struct Inspect {
+    root: PathBuf,
+    target: Option<String>,
+    optimize: Option<String>,
+    flags: Vec<String>,
+    out_dir: Option<PathBuf>,
+    cargo_metadata: bool,
+    toolchain: Toolchain,
+}
+
+impl Inspect {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().into(),
+            target: None,
+            optimize: None,
+            flags: Vec::new(),
+            out_dir: None,
+            cargo_metadata: true,
+            toolchain: Toolchain::bundled(),
+        }
+    }
+
+    /// The rustc target to compile for. Defaults to `TARGET`.
+    pub fn target(&mut self, target: &str) -> &mut Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Zig's optimize mode, e.g. `ReleaseFast`. Defaults to
+    /// [`zig_build::optimize_for_profile()`].
+    pub fn optimize(&mut self, mode: &str) -> &mut Self {
+        self.optimize = Some(mode.into());
+        self
+    }
+
+    /// Passes a flag through to `zig build-obj`, e.g. `-mcpu=native`.
+    pub fn flag(&mut self, flag: &str) -> &mut Self {
+        self.flags.push(flag.into());
+        self
+    }
+
+    /// Where [`emit()`](Self::emit) puts the files. Defaults to `OUT_DIR`.
+    pub fn out_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.out_dir = Some(dir.as_ref().into());
+        self
+    }
+
+    /// Whether to print `cargo:rerun-if-changed` for the root source file and
+    /// the files it imports. On by default.
+    pub fn cargo_metadata(&mut self, cargo_metadata: bool) -> &mut Self {
+        self.cargo_metadata = cargo_metadata;
+        self
+    }
+
+    pub fn toolchain(&mut self, toolchain: Toolchain) -> &mut Self {
+        self.toolchain = toolchain;
+        self
+    }
+
+    /// Writes `output` to `<stem>.<extension>` in the
+    /// [output directory](Self::out_dir) and returns its path, or `None` when
+    /// the toolchain can't produce it. Compile errors come back as
+    /// [`Error::Command`] with the compiler's diagnostics.
+    pub fn emit(&self, output: Output) -> Result<Option<PathBuf>, Error> {
+        let out_dir = match &self.out_dir {
+            Some(dir) => dir.clone(),
+            None => env::var_os("OUT_DIR")
+                .map(PathBuf::from)
+                .ok_or_else(|| Error::Config("OUT_DIR is not set".into()))?,
+        };
+        fs_err::create_dir_all(&out_dir)?;
+        let stem = self
+            .root
+            .file_stem()
+            .ok_or_else(|| Error::Config(format!("{} has no file name", self.root.display())))?
+            .to_string_lossy()
+            .into_owned();
+        let path = out_dir.join(format!("{stem}.{}", output.extension()));
+        if self.cargo_metadata {
+            for file in zig_imports::files(&self.root)? {
+                build::rerun_if_changed(file);
+            }
+        }
+        let mut cmd = self.toolchain.command();
+        if output == Output::Zir {
+            cmd.arg("ast-check").arg("-t").arg(&self.root);
+            let Some(result) = self.run(&mut cmd)? else {
+                return Ok(None);
+            };
+            fs_err::write(&path, result.stdout)?;
+            return Ok(Some(path));
+        }
+        cmd.arg("build-obj")
+            .arg(&self.root)
+            .args(self.target_args()?);
+        let scratch = out_dir.join(format!("{stem}.inspect.o"));
+        // So a file left from an earlier run isn't taken for this one's.
+        let _ = fs_err::remove_file(&path);
+        match output {
+            // AIR is printed as functions reach code generation, which
+            // `-fno-emit-bin` skips.
+            Output::Air => cmd
+                .arg(format!("-femit-bin={}", scratch.display()))
+                .arg("--verbose-air"),
+            Output::LlvmIr => cmd
+                .arg("-fno-emit-bin")
+                .arg(format!("-femit-llvm-ir={}", path.display())),
+            Output::LlvmBitcode => cmd
+                .arg("-fno-emit-bin")
+                .arg(format!("-femit-llvm-bc={}", path.display())),
+            Output::Assembly => cmd
+                .arg("-fno-emit-bin")
+                .arg(format!("-femit-asm={}", path.display())),
+            Output::Zir => unreachable!("handled above"),
+        };
+        cmd.args(&self.flags);
+        let result = self.run(&mut cmd);
+        let _ = fs_err::remove_file(&scratch);
+        let Some(result) = result? else {
+            return Ok(None);
+        };
+        if output == Output::Air {
+            // Builds without debug extensions take the flag and print nothing.
+            let air = String::from_utf8_lossy(&result.stderr);
+            if !air.contains("# Begin Function AIR") {
+                return Ok(None);
+            }
+            fs_err::write(&path, air.as_bytes())?;
+        }
+        Ok(path.exists().then_some(path))
+    }
+
+    /// [`emit()`](Self::emit)s each of `outputs`, leaving out the ones the
+    /// toolchain can't produce.
+    pub fn emit_all(&self, outputs: &[Output]) -> Result<Vec<(Output, PathBuf)>, Error> {
+        let mut emitted = Vec::new();
+        for &output in outputs {
+            if let Some(path) = self.emit(output)? {
+                emitted.push((output, path));
+            }
+        }
+        Ok(emitted)
+    }
+
+    fn target_args(&self) -> Result<Vec<String>, Error> {
+        let target = match &self.target {
+            Some(target) => target.clone(),
+            None => env::var("TARGET").map_err(|_| Error::Config("TARGET is not set".into()))?,
+        };
+        let target = target::pin_glibc_version(&target, None);
+        let zig_target = target::zig_target(&target)
+            .ok_or_else(|| Error::Config(format!("no Zig target for {target}")))?;
+        let optimize = self
+            .optimize
+            .as_deref()
+            .unwrap_or_else(|| zig_build::optimize_for_profile());
+        Ok(vec![
+            "-target".into(),
+            zig_target,
+            "-O".into(),
+            optimize.into(),
+        ])
+    }
+
+    /// Runs `cmd`, or returns `None` when the compiler says it can't produce
+    /// the output.
+    fn run(&self, cmd: &mut Command) -> Result<Option<std::process::Output>, Error> {
+        let output = process::output(cmd)?;
+        if output.status.success() {
+            return Ok(Some(output));
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        let diagnostics = diagnostic::parse(&stderr);
+        if diagnostics.is_empty() && UNSUPPORTED.iter().any(|marker| stderr.contains(marker)) {
+            return Ok(None);
+        }
+        Err(Error::Command(Box::new(CommandError::new(
+            cmd,
+            output.status,
+            diagnostics,
+            stderr,
+        ))))
+    }
+}
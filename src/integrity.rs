@@ -0,0 +1,248 @@
+//! Integrity checks of downloaded archives. Official release archives are
+//! checked against the minisign signature ziglang.org publishes next to each
+//! one, zig-bootstrap source archives against the SHA-256 digests pinned
+//! here. A zig-bootstrap archive with no known digest is used with a
+//! warning until its digest is pinned.
+//!
+//! Auditors who want to vouch for every download themselves set
+//! `ZIG_RS_DIGESTS` to a file in `sha256sum` format naming archives by their
+//! file name:
+//!
+//! ```text
+//! 5d1a7e6f...  zig-bootstrap-0.14.0.zip
+//! 473ec26f...  zig-linux-x86_64-0.14.0.tar.xz
+//! ```
+//!
+//! Its digests take precedence over the pinned ones, and an archive it
+//! doesn't list is an error.
+//!
+//! Security teams who'd rather review one file pin every artifact by URL in
+//! a [`SUMS_FILE_NAME`] file at the root of the workspace (or the file
+//! [`SUMS_ENV`] names):
+//!
+//! ```text
+//! # zig-rs.sums
+//! 473ec26f...  https://ziglang.org/download/0.14.0/zig-linux-x86_64-0.14.0.tar.xz
+//! 5d1a7e6f...  https://github.com/ziglang/zig-bootstrap/archive/refs/tags/0.14.0.zip
+//! 1220c3b2...  https://example.com/zlib-1.3.1.tar.gz
+//! ```
+//!
+//! When there is one it's enforced strictly: every archive a toolchain is
+//! installed from needs an entry its SHA-256 matches, whichever mirror or
+//! [fetcher](crate::download::Fetcher) served it, and so does every `.url`
+//! dependency of a `build.zig.zon` that [`ZigBuild`](crate::ZigBuild)
+//! builds. Zig fetches and checks those itself, so their entry is the Zig
+//! package hash the manifest's `.hash` has to be. It's looked for from
+//! `CARGO_MANIFEST_DIR` (or the current directory) and from `OUT_DIR` up to
+//! the filesystem root, the closest file winning, so this crate's build
+//! script in the Cargo registry finds the one next to the workspace's
+//! `target/` directory.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use minisign_verify::{PublicKey, Signature};
+
+use crate::manifest::{self, Manifest};
+
+/// Environment variable naming a digest file that replaces [`PINNED`].
+pub const DIGESTS_ENV: &str = "ZIG_RS_DIGESTS";
+
+/// File name of the checksum database; see the [module
+/// documentation](self).
+pub const SUMS_FILE_NAME: &str = "zig-rs.sums";
+
+/// Environment variable naming the checksum database to use instead of
+/// looking for a [`SUMS_FILE_NAME`].
+pub const SUMS_ENV: &str = "ZIG_RS_SUMS";
+
+/// The key ziglang.org signs releases and development snapshots with.
+pub const ZIG_PUBLIC_KEY: &str = "RWSGOq2NVecA2UPNdBUZykf1CCb147pkmdtYxgb3Ti+JO/wCYvhbAb/U";
+
+/// `(file name, SHA-256)` of the zig-bootstrap tag archives GitHub serves
+/// from `archive/refs/tags/<tag>.zip`, named `zig-bootstrap-<tag>.zip`. Add
+/// the digest of a tag's archive (`sha256sum`) when bumping the Zig version;
+/// until then the archive is used unverified, with the warning from
+/// [`unverified_warning()`], unless [`DIGESTS_ENV`] or the [`Sums`] database
+/// vouches for it.
+pub const PINNED: &[(&str, &str)] = &[];
+
+/// Checks the archive at `path`, downloaded from `url` as `name`, against
+/// the [`Sums`] database if there is one, and against its digest from
+/// [`DIGESTS_ENV`] or [`PINNED`]. Returns whether a digest was known;
+/// without a database or `ZIG_RS_DIGESTS`, archives with no pinned digest
+/// pass unchecked, so callers decide whether that's enough.
+pub fn check_sha256(path: &Path, name: &str, url: &str) -> Result<bool, Box<dyn Error>> {
+    let pinned = match Sums::find()? {
+        Some(sums) => {
+            sums.check_file(url, path)?;
+            true
+        }
+        None => false,
+    };
+    Ok(check_digests(path, name)? || pinned)
+}
+
+/// What to warn about an archive [`check_sha256()`] knew no digest for.
+pub fn unverified_warning(name: &str, url: &str) -> String {
+    format!(
+        "no pinned SHA-256 digest for {name}; it is used unverified (pin {url} in {SUMS_FILE_NAME} or set {DIGESTS_ENV} to check it)"
+    )
+}
+
+fn check_digests(path: &Path, name: &str) -> Result<bool, Box<dyn Error>> {
+    let expected = match env::var_os(DIGESTS_ENV) {
+        Some(digests) => {
+            let manifest = Manifest::read(Path::new(&digests))?;
+            let digest = manifest
+                .entries()
+                .find(|&(file, _)| file == Path::new(name))
+                .map(|(_, digest)| digest.to_owned());
+            match digest {
+                Some(digest) => digest,
+                None => {
+                    return Err(format!(
+                        "{} (from {DIGESTS_ENV}) has no digest for {name}",
+                        Path::new(&digests).display()
+                    )
+                    .into());
+                }
+            }
+        }
+        None => match PINNED.iter().find(|(file, _)| *file == name) {
+            Some((_, digest)) => (*digest).to_owned(),
+            None => return Ok(false),
+        },
+    };
+    let actual = manifest::hash_file(path)?;
+    if !actual.eq_ignore_ascii_case(&expected) {
+        return Err(format!(
+            "{name} has SHA-256 {actual}, expected {expected}; delete it and download it again, or check {DIGESTS_ENV}"
+        )
+        .into());
+    }
+    Ok(true)
+}
+
+/// Checks the archive at `path`, downloaded as `name`, against the minisign
+/// signature `signature` (the contents of its `.minisig` file) made with
+/// [`ZIG_PUBLIC_KEY`]. The signed comment has to name the same file, so a
+/// validly signed archive can't stand in for another.
+pub fn check_minisign(path: &Path, name: &str, signature: &str) -> Result<(), Box<dyn Error>> {
+    let key = PublicKey::from_base64(ZIG_PUBLIC_KEY).expect("ZIG_PUBLIC_KEY is valid");
+    let signature =
+        Signature::decode(signature).map_err(|e| format!("bad signature for {name}: {e}"))?;
+    key.verify(&fs_err::read(path)?, &signature, true)
+        .map_err(|e| format!("{name} doesn't match its ziglang.org signature: {e}"))?;
+    let signed_file = signature
+        .trusted_comment()
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("file:"));
+    if let Some(signed_file) = signed_file
+        && signed_file != name
+    {
+        return Err(format!("the signature for {name} is for {signed_file}").into());
+    }
+    Ok(())
+}
+
+/// A checksum database: SHA-256 digests, or Zig package hashes, by URL.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sums {
+    /// Where it was read from, for errors.
+    pub path: PathBuf,
+    entries: BTreeMap<String, String>,
+}
+
+impl Sums {
+    /// The database [`SUMS_ENV`] names, or else the closest
+    /// [`SUMS_FILE_NAME`]; see the [module documentation](self).
+    pub fn find() -> Result<Option<Self>, Box<dyn Error>> {
+        if let Some(path) = env::var_os(SUMS_ENV) {
+            return Self::read(Path::new(&path)).map(Some);
+        }
+        let start = match env::var_os("CARGO_MANIFEST_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => env::current_dir()?,
+        };
+        let out_dir = env::var_os("OUT_DIR").map(PathBuf::from);
+        for start in [Some(start), out_dir].into_iter().flatten() {
+            let found = start
+                .ancestors()
+                .map(|dir| dir.join(SUMS_FILE_NAME))
+                .find(|path| path.is_file());
+            if let Some(path) = found {
+                return Self::read(&path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads `<digest>  <url>` lines, skipping blank ones and `#` comments.
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut entries = BTreeMap::new();
+        for line in fs_err::read_to_string(path)?.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((digest, url)) = line.split_once(char::is_whitespace) else {
+                return Err(format!("{}: malformed line {line:?}", path.display()).into());
+            };
+            entries.insert(url.trim_start().to_owned(), digest.to_owned());
+        }
+        Ok(Self {
+            path: path.to_owned(),
+            entries,
+        })
+    }
+
+    /// The digest listed for `url`.
+    pub fn get(&self, url: &str) -> Option<&str> {
+        self.entries.get(url).map(String::as_str)
+    }
+
+    /// Checks the file at `path`, downloaded from `url`, against the
+    /// database, which has to list it.
+    pub fn check_file(&self, url: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+        let expected = self.listed(url)?;
+        let actual = manifest::hash_file(path)?;
+        if !actual.eq_ignore_ascii_case(expected) {
+            return Err(format!(
+                "{url} has SHA-256 {actual}, but {} pins {expected}",
+                self.path.display()
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Checks the Zig package hash a `build.zig.zon` gives the dependency at
+    /// `url` against the database, which has to list it.
+    pub fn check_package(&self, url: &str, hash: Option<&str>) -> Result<(), Box<dyn Error>> {
+        let expected = self.listed(url)?;
+        if hash != Some(expected) {
+            return Err(format!(
+                "the dependency at {url} has hash {}, but {} pins {expected}",
+                hash.unwrap_or("(none)"),
+                self.path.display()
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    fn listed(&self, url: &str) -> Result<&str, Box<dyn Error>> {
+        self.get(url).ok_or_else(|| {
+            format!(
+                "{url} isn't in {}, which pins every download",
+                self.path.display()
+            )
+            .into()
+        })
+    }
+}
@@ -0,0 +1,215 @@
+//! Parallelism bounded by the jobserver Cargo hands build scripts. Cargo runs
+//! up to `NUM_JOBS` jobs at a time and every build script holds one of them;
+//! running more processes than that on top, as `zig cc` per source file or a
+//! `zig build` with its own thread pool would, oversubscribes the machine.
+//! Work here only goes parallel with extra tokens from the jobserver, which
+//! are given back as soon as the work is done.
+//!
+//! Outside Cargo, or when the jobserver can't be opened, the limit is
+//! `NUM_JOBS` or the number of CPUs.
+
+use std::{
+    any::Any,
+    env, io,
+    panic::{self, AssertUnwindSafe},
+    process::Command,
+    sync::{Mutex, OnceLock, mpsc},
+    thread,
+    time::Duration,
+};
+
+use jobserver::{Acquired, Client};
+
+use crate::cancel;
+
+/// How long [`acquire_available()`] waits for each token before deciding the
+/// jobserver has none to spare.
+const TOKEN_WAIT: Duration = Duration::from_millis(20);
+
+/// Cargo's jobserver, if the running process was started with one.
+fn client() -> Option<&'static Client> {
+    static CLIENT: OnceLock<Option<Client>> = OnceLock::new();
+    CLIENT
+        // SAFETY: the descriptors named in the environment are the ones
+        // Cargo passed down, and nothing in this crate closes them.
+        .get_or_init(|| unsafe { Client::from_env() })
+        .as_ref()
+}
+
+/// The most jobs to run at once, counting the one the process already holds.
+pub fn max_jobs() -> usize {
+    env::var("NUM_JOBS")
+        .ok()
+        .and_then(|jobs| jobs.parse().ok())
+        .or_else(|| thread::available_parallelism().ok().map(usize::from))
+        .unwrap_or(1)
+        .max(1)
+}
+
+/// Passes the jobserver on to `cmd`, so build tools it runs (`make`, another
+/// Cargo) take their jobs from the same pool.
+pub fn configure(cmd: &mut Command) {
+    if let Some(client) = client() {
+        client.configure(cmd);
+    }
+}
+
+/// Jobserver tokens held on top of the process's own, released on drop.
+#[derive(Debug)]
+pub struct Tokens {
+    jobs: usize,
+    _acquired: Vec<Acquired>,
+}
+
+impl Tokens {
+    /// How many jobs these tokens allow, counting the process's own.
+    pub fn jobs(&self) -> usize {
+        self.jobs
+    }
+}
+
+/// Takes every token the jobserver has free right now, up to enough for
+/// `max` jobs, for handing to a single command that runs jobs of its own
+/// (`zig build -j<N>`). Without a jobserver that's `max` jobs outright.
+pub fn acquire_available(max: usize) -> Tokens {
+    let max = max.max(1);
+    let Some(client) = client() else {
+        return Tokens {
+            jobs: max,
+            _acquired: Vec::new(),
+        };
+    };
+    let wanted = max - 1;
+    let mut acquired = Vec::new();
+    let mut blocking = false;
+    while acquired.len() < wanted {
+        match client.try_acquire() {
+            Ok(Some(token)) => acquired.push(token),
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                blocking = true;
+                break;
+            }
+            Ok(None) | Err(_) => break,
+        }
+    }
+    // Anonymous pipes can't be read without blocking; ask a helper thread
+    // instead and take what arrives quickly.
+    if blocking {
+        let (tx, rx) = mpsc::channel();
+        if let Ok(helper) = client.clone().into_helper_thread(move |token| {
+            let _ = tx.send(token);
+        }) {
+            for _ in 0..wanted {
+                helper.request_token();
+            }
+            while acquired.len() < wanted {
+                match rx.recv_timeout(TOKEN_WAIT) {
+                    Ok(Ok(token)) => acquired.push(token),
+                    _ => break,
+                }
+            }
+        }
+    }
+    Tokens {
+        jobs: acquired.len() + 1,
+        _acquired: acquired,
+    }
+}
+
+/// Calls `f` on every item on up to [`max_jobs()`] threads and returns the
+/// results in order. The first job runs on the process's own token; every
+/// other one waits for a token from the jobserver, if there is one. A panic
+/// in `f` is resumed on the calling thread. Every job runs under the
+/// calling thread's [cancellation token](crate::cancel), if it has one.
+pub fn map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    enum Event {
+        /// A job finished, with its panic if it had one.
+        Done(Option<Box<dyn Any + Send>>),
+        Token(io::Result<Acquired>),
+    }
+
+    let max = max_jobs();
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+    let (tx, rx) = mpsc::channel();
+    let token_tx = tx.clone();
+    let helper = client()
+        .filter(|_| max > 1 && items.len() > 1)
+        .and_then(|client| {
+            client
+                .clone()
+                .into_helper_thread(move |token| {
+                    let _ = token_tx.send(Event::Token(token));
+                })
+                .ok()
+        });
+    let cancel_token = cancel::current();
+    let (f, results_ref, cancel_token) = (&f, &results, &cancel_token);
+    thread::scope(|scope| {
+        let mut next = 0;
+        let mut running = 0;
+        let mut requested = 0;
+        let start = |token: Option<Acquired>, next: &mut usize, running: &mut usize| {
+            let i = *next;
+            *next += 1;
+            *running += 1;
+            let tx = tx.clone();
+            scope.spawn(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    cancel::scoped(cancel_token.clone(), || f(&items[i]))
+                }));
+                drop(token);
+                let panic = match result {
+                    Ok(result) => {
+                        results_ref.lock().unwrap()[i] = Some(result);
+                        None
+                    }
+                    Err(payload) => Some(payload),
+                };
+                let _ = tx.send(Event::Done(panic));
+            });
+        };
+        while next < items.len() || running > 0 {
+            if running == 0 && next < items.len() {
+                start(None, &mut next, &mut running);
+            }
+            match &helper {
+                Some(helper) => {
+                    while running + requested < max && next + requested < items.len() {
+                        helper.request_token();
+                        requested += 1;
+                    }
+                }
+                None if client().is_none() => {
+                    while running < max && next < items.len() {
+                        start(None, &mut next, &mut running);
+                    }
+                }
+                None => {}
+            }
+            match rx.recv().expect("a sender is held") {
+                Event::Done(None) => running -= 1,
+                Event::Done(Some(payload)) => panic::resume_unwind(payload),
+                Event::Token(token) => {
+                    requested -= 1;
+                    if let Ok(token) = token
+                        && next < items.len()
+                    {
+                        start(Some(token), &mut next, &mut running);
+                    }
+                }
+            }
+        }
+    });
+    drop(helper);
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every job reports back"))
+        .collect()
+}
@@ -0,0 +1,254 @@
+//! ⚡ Zig packaged for Rust
+//!
+//! The build script bootstraps a Zig toolchain into `OUT_DIR`. This library
+//! holds the pieces of that process that are also useful to the build scripts
+//! and tools of crates that depend on this one, starting with running the
+//! bundled Zig itself:
+//!
+//! ```no_run
+//! let status = zig_rs::command().arg("version").status()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+pub mod abi;
+pub mod archive;
+pub mod bindings;
+pub mod bootstrap_source;
+pub mod bootstrap_target;
+pub mod build_info;
+pub mod cache;
+pub mod cancel;
+pub mod cc_cache;
+pub mod cl;
+pub mod compile;
+pub mod config;
+pub mod crash;
+pub mod diagnostic;
+pub mod dist;
+pub mod download;
+pub mod error;
+pub mod eval;
+pub mod failure_report;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fmt;
+pub mod glibc_audit;
+pub mod hardening;
+#[cfg(feature = "hot-reload")]
+pub mod hot_reload;
+pub mod inspect;
+pub mod integrity;
+pub mod jobs;
+pub mod libc_headers;
+pub mod licenses;
+pub mod link_map;
+pub mod link_metadata;
+pub mod link_report;
+pub mod log;
+#[cfg(feature = "macros")]
+mod macros;
+pub mod manifest;
+pub mod matrix;
+pub mod naming;
+pub mod notarize;
+pub mod pack;
+pub mod postprocess;
+pub mod process;
+pub mod provenance;
+pub mod prune;
+pub mod release;
+pub mod replay;
+pub mod reproducible;
+pub mod runtime;
+pub mod self_test;
+pub mod shims;
+pub mod sign;
+pub mod support;
+#[cfg(feature = "symbolicate")]
+pub mod symbolicate;
+pub mod symbols;
+pub mod target;
+pub mod target_features;
+mod target_triple;
+pub mod testing;
+pub mod toolchain;
+pub mod tools;
+pub mod translate_c;
+pub mod universal;
+pub mod zig_build;
+pub mod zig_env;
+pub mod zig_exe;
+pub mod zig_imports;
+pub mod zig_test;
+
+pub use build_info::{BUILD_INFO, PINNED_ZIG_VERSION};
+pub use compile::Build;
+pub use error::Error;
+pub use hardening::Hardening;
+pub use runtime::Runtime;
+pub use self_test::self_test;
+pub use target::TargetSupport;
+pub use toolchain::Toolchain;
+pub use translate_c::TranslateC;
+pub use zig_build::{ZigBuild, build_zig_package};
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub use zig_macros as __macros;
+pub use zig_test::TestBuilder;
+
+/// Path of the bundled `zig` binary; see [`host_zig_path()`].
+pub fn zig_path() -> PathBuf {
+    host_zig_path()
+}
+
+/// Path of the bundled `zig` binary that runs on the host the crate was
+/// built on, also when it was cross-compiled, for build scripts and tools.
+pub fn host_zig_path() -> PathBuf {
+    Toolchain::bundled().zig()
+}
+
+/// Path of the bundled `zig` binary that runs on the target the crate was
+/// built for, to ship with an application. When cross-compiling, only the
+/// `target-toolchain` feature installs one; see
+/// [`Toolchain::bundled_for_target()`].
+pub fn target_zig_path() -> Option<PathBuf> {
+    Toolchain::bundled_for_target().map(|toolchain| toolchain.zig())
+}
+
+/// The bundled toolchain's `lib/` directory, Zig's standard library and libc
+/// sources.
+pub fn lib_dir() -> PathBuf {
+    Toolchain::bundled().lib_dir()
+}
+
+/// A [`Command`] running the bundled `zig`, with `ZIG_LIB_DIR` set; see
+/// [`Toolchain::command()`].
+pub fn command() -> Command {
+    Toolchain::bundled().command()
+}
+
+/// Checks the installed `zig` binary and `lib/` tree against the hash manifest
+/// the build script wrote when it installed them.
+pub fn verify() -> io::Result<manifest::Verification> {
+    Toolchain::bundled().verify()
+}
+
+/// Verifies the installation and copies any missing or modified files back
+/// from `source`, a pristine copy of the same toolchain (e.g. the
+/// `zig-bootstrap/out/zig-<target>-<mcpu>/` directory or an extracted
+/// toolchain archive). Returns the verification result from before the
+/// repair.
+pub fn repair(source: &Path) -> io::Result<manifest::Verification> {
+    Toolchain::bundled().repair(source)
+}
+
+/// Copies the bundled `zig` binary and `lib/` tree into `dest`, together with
+/// a hash manifest, for applications that ship the toolchain next to their own
+/// binary instead of depending on this crate's `OUT_DIR`. The layout of `dest`
+/// matches an official Zig release, so Zig finds its `lib/` directory without
+/// any extra configuration. Returns the path of the copied `zig` binary.
+///
+/// The toolchain copied is the one for the target, [`target_zig_path()`];
+/// cross-compiled applications need the `target-toolchain` feature.
+pub fn install_toolchain(dest: &Path) -> io::Result<PathBuf> {
+    let toolchain = Toolchain::bundled_for_target().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "no toolchain for the target; enable the target-toolchain feature to install one when cross-compiling",
+        )
+    })?;
+    Ok(toolchain.install(dest)?.zig())
+}
+
+/// The bundled wasm32-wasi sysroot; see [`Toolchain::wasi_sysroot()`].
+pub fn wasi_sysroot() -> io::Result<PathBuf> {
+    Toolchain::bundled().wasi_sysroot()
+}
+
+/// The licenses of the bundled toolchain's third-party components, to ship
+/// along with it; see [`licenses`].
+pub fn licenses() -> io::Result<Vec<licenses::License>> {
+    Toolchain::bundled().licenses()
+}
+
+/// The [provenance] statement of the bundled toolchain, as JSON, for
+/// release pipelines to sign and attach.
+pub fn provenance() -> io::Result<String> {
+    Toolchain::bundled().provenance()
+}
+
+/// `zig env` of the bundled toolchain; see [`zig_env`].
+pub fn env() -> Result<zig_env::ZigEnv, Box<dyn std::error::Error>> {
+    Toolchain::bundled().env()
+}
+
+/// `zig version` of the bundled toolchain, parsed; see [`zig_env`].
+pub fn version() -> Result<zig_env::Version, Box<dyn std::error::Error>> {
+    Toolchain::bundled().query_version()
+}
+
+/// Evaluates a Zig expression with the bundled toolchain and returns what it
+/// prints; see [`eval`](mod@eval).
+pub fn eval(expr: &str) -> Result<String, Error> {
+    eval::eval(expr)
+}
+
+/// Checks a buffer of Zig source with the bundled toolchain; see
+/// [`Toolchain::check_syntax()`].
+pub fn check_syntax(source: &str) -> io::Result<Vec<diagnostic::Diagnostic>> {
+    Toolchain::bundled().check_syntax(source)
+}
+
+/// Reports whether the bundled toolchain can cross-compile for `rust_target`
+/// and which libcs it can provide, so unsupported targets are caught before
+/// anything is compiled rather than at link time.
+pub fn can_target(rust_target: &str) -> Result<TargetSupport, Box<dyn std::error::Error>> {
+    Toolchain::bundled().can_target(rust_target)
+}
+
+/// Packs the bundled toolchain into a relocatable `.tar.zst` at `dest`, with a
+/// [fingerprint](pack::Fingerprint) of what it is, for CI caches. See
+/// [`pack`].
+pub fn pack_toolchain(dest: &Path) -> io::Result<()> {
+    pack::pack(
+        Toolchain::bundled().root(),
+        pack::Fingerprint {
+            zig_version: BUILD_INFO.zig_version.into(),
+            zig_target: BUILD_INFO.zig_target.into(),
+            mcpu: BUILD_INFO.mcpu.into(),
+            revision: pack::REVISION,
+            bootstrap: BUILD_INFO.bootstrap.to_string(),
+            lib_profile: prune::profiles_name(BUILD_INFO.lib_profiles),
+            manifest: String::new(),
+        },
+        dest,
+    )
+}
+
+/// Unpacks an archive written by [`pack_toolchain()`] into the shared cache
+/// under `<cache>/toolchains/<version>/<zig_target>-<mcpu>/`, where the build
+/// script looks for it too, and returns the toolchain.
+pub fn unpack_toolchain(src: &Path) -> Result<Toolchain, Box<dyn std::error::Error>> {
+    let toolchains = cache::toolchains_dir().ok_or("no cache directory; set ZIG_RS_CACHE_DIR")?;
+    fs_err::create_dir_all(&toolchains)?;
+    let partial = toolchains.join(format!(".unpack-{}", std::process::id()));
+    if partial.exists() {
+        fs_err::remove_dir_all(&partial)?;
+    }
+    let _guard = process::remove_on_interrupt(&partial);
+    let fingerprint = pack::unpack(src, &partial)?;
+    let version_dir = toolchains.join(&fingerprint.zig_version);
+    let root = version_dir.join(format!("{}-{}", fingerprint.zig_target, fingerprint.mcpu));
+    fs_err::create_dir_all(&version_dir)?;
+    if root.exists() {
+        fs_err::remove_dir_all(&root)?;
+    }
+    fs_err::rename(&partial, &root)?;
+    Ok(Toolchain::at(fingerprint.zig_version, root))
+}
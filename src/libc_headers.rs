@@ -0,0 +1,117 @@
+//! The libc headers Zig ships in `lib/libc/include/` (glibc, musl, wasi-libc,
+//! mingw-w64, macOS), for tools that parse C outside `zig cc` — bindgen most
+//! of all — and would otherwise need the target's system headers installed:
+//!
+//! ```no_run
+//! // build.rs
+//! let target = std::env::var("TARGET")?;
+//! let dirs = zig_rs::Toolchain::bundled().libc_include_dirs(&target);
+//! let clang_args = dirs.iter().map(|dir| format!("-isystem{}", dir.display()));
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! The build script also publishes the directories for the target it builds
+//! for as `DEP_ZIG_LIBC_INCLUDE`, joined like `PATH`, to the build scripts of
+//! crates that depend on this one directly, next to `DEP_ZIG_BIN`,
+//! `DEP_ZIG_LIB_DIR` and `DEP_ZIG_VERSION`.
+//!
+//! For wasm32-wasi, whose toolchains expect a wasi-sdk style sysroot rather
+//! than include directories, it also assembles one from wasi-libc's headers
+//! and publishes it as `DEP_ZIG_WASI_SYSROOT` (see [`wasi_sysroot()`] and
+//! [`Toolchain::wasi_sysroot()`](crate::Toolchain::wasi_sysroot)):
+//!
+//! ```sh
+//! clang --target=wasm32-wasip1 --sysroot="$DEP_ZIG_WASI_SYSROOT" -c foo.c
+//! ```
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Directory of the wasi sysroot inside the install directory.
+pub const WASI_SYSROOT_DIR: &str = "wasi-sysroot";
+
+/// The libc include directories under `lib_dir` that `zig cc` searches for
+/// the Zig target `zig_target` (`x86_64-linux-gnu.2.17`, `wasm32-wasi-musl`,
+/// ...), most specific first. Empty for targets without a bundled libc.
+pub fn include_dirs(lib_dir: &Path, zig_target: &str) -> Vec<PathBuf> {
+    let mut parts = zig_target.split('-');
+    let (Some(arch), Some(os)) = (parts.next(), parts.next()) else {
+        return Vec::new();
+    };
+    // Version suffixes (`macos.11`, `gnu.2.17`) don't select headers.
+    let os = os.split('.').next().unwrap_or(os);
+    let abi = parts
+        .next()
+        .map_or("none", |abi| abi.split('.').next().unwrap_or(abi));
+    let generic_arch = generic_arch(arch);
+    let libc = match (os, abi) {
+        ("linux", abi) if abi.starts_with("gnu") => Some("glibc"),
+        (_, abi) if abi.starts_with("musl") => Some("musl"),
+        ("windows", _) => Some("mingw"),
+        _ => None,
+    };
+
+    let include = lib_dir.join("libc").join("include");
+    let mut names = vec![
+        format!("{arch}-{os}-{abi}"),
+        format!("{generic_arch}-{os}-{abi}"),
+    ];
+    names.extend(libc.map(|libc| format!("generic-{libc}")));
+    names.push(format!("{generic_arch}-{os}-any"));
+    names.push(format!("any-{os}-any"));
+    names.dedup();
+    names
+        .into_iter()
+        .map(|name| include.join(name))
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+/// Assembles a sysroot for wasm32-wasi at `dest` from the wasi-libc headers
+/// under `lib_dir`, merged into `include/` the way wasi-sdk lays them out.
+/// It has headers only: enough to compile C for a Rust `wasm32-wasip1`
+/// staticlib linked by rustc, which brings its own wasi-libc, but not to
+/// link C executables.
+pub fn wasi_sysroot(lib_dir: &Path, dest: &Path) -> io::Result<()> {
+    let include = dest.join("include");
+    if include.exists() {
+        fs_err::remove_dir_all(&include)?;
+    }
+    fs_err::create_dir_all(&include)?;
+    // Most specific last, so its headers win.
+    for dir in include_dirs(lib_dir, "wasm32-wasi-musl").iter().rev() {
+        copy_into(dir, &include)?;
+    }
+    Ok(())
+}
+
+fn copy_into(from: &Path, to: &Path) -> io::Result<()> {
+    fs_err::create_dir_all(to)?;
+    for entry in fs_err::read_dir(from)? {
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_into(&entry.path(), &dest)?;
+        } else {
+            fs_err::copy(entry.path(), dest)?;
+        }
+    }
+    Ok(())
+}
+
+/// The architecture family Zig files headers shared by several architectures
+/// under, e.g. `x86` for `x86_64` or `arm` for `thumb`.
+fn generic_arch(arch: &str) -> &str {
+    match arch {
+        "x86" | "x86_64" => "x86",
+        "arm" | "armeb" | "thumb" | "thumbeb" => "arm",
+        "aarch64" | "aarch64_be" => "aarch64",
+        "wasm32" | "wasm64" => "wasm",
+        _ => ["mips", "powerpc", "riscv", "sparc", "loongarch"]
+            .into_iter()
+            .find(|family| arch.starts_with(family))
+            .unwrap_or(arch),
+    }
+}
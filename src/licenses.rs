@@ -0,0 +1,263 @@
+//! The third-party licenses of an installed toolchain, for applications that
+//! redistribute it (see [`crate::install_toolchain()`]) and have to ship the
+//! notices of everything in it: LLVM, Clang and LLD, zlib and zstd are linked
+//! into the `zig` binary, and `lib/` carries the sources of musl, glibc,
+//! mingw-w64, wasi-libc and LLVM's C++ runtime libraries.
+//!
+//! The build script copies every license text it finds into `licenses/`
+//! next to the `zig` binary, one directory per component, and lists them in
+//! `licenses/licenses.toml`:
+//!
+//! ```toml
+//! [[component]]
+//! files = ["musl/COPYRIGHT"]
+//! name = "musl"
+//! spdx = "MIT"
+//! url = "https://git.musl-libc.org/cgit/musl/tree/COPYRIGHT"
+//! ```
+//!
+//! `files` is relative to `licenses/`. It is empty for components whose text
+//! isn't part of the installed toolchain, such as zlib's in an official
+//! release; `url` points at the upstream text then.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use toml::{Table, Value};
+
+/// Directory of the license texts inside the install directory.
+pub const DIR_NAME: &str = "licenses";
+/// File name of the index inside [`DIR_NAME`].
+pub const FILE_NAME: &str = "licenses.toml";
+
+/// One component of the toolchain and its license.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct License {
+    pub name: String,
+    /// SPDX license expression.
+    pub spdx: String,
+    /// Upstream location of the license text.
+    pub url: String,
+    /// Absolute paths of the license texts, if the toolchain has them.
+    pub files: Vec<PathBuf>,
+}
+
+struct Component {
+    name: &'static str,
+    spdx: &'static str,
+    url: &'static str,
+    /// Directory under `lib/` whose license files belong to the component.
+    lib_dir: Option<&'static str>,
+}
+
+const LLVM_SPDX: &str = "Apache-2.0 WITH LLVM-exception";
+
+const COMPONENTS: &[Component] = &[
+    Component {
+        name: "zig",
+        spdx: "MIT",
+        url: "https://github.com/ziglang/zig/blob/master/LICENSE",
+        lib_dir: None,
+    },
+    Component {
+        name: "llvm",
+        spdx: LLVM_SPDX,
+        url: "https://github.com/llvm/llvm-project/blob/main/LICENSE.TXT",
+        lib_dir: None,
+    },
+    Component {
+        name: "zlib",
+        spdx: "Zlib",
+        url: "https://github.com/madler/zlib/blob/develop/LICENSE",
+        lib_dir: None,
+    },
+    Component {
+        name: "zstd",
+        spdx: "BSD-3-Clause OR GPL-2.0-only",
+        url: "https://github.com/facebook/zstd/blob/dev/LICENSE",
+        lib_dir: None,
+    },
+    Component {
+        name: "libcxx",
+        spdx: LLVM_SPDX,
+        url: "https://github.com/llvm/llvm-project/blob/main/libcxx/LICENSE.TXT",
+        lib_dir: Some("libcxx"),
+    },
+    Component {
+        name: "libcxxabi",
+        spdx: LLVM_SPDX,
+        url: "https://github.com/llvm/llvm-project/blob/main/libcxxabi/LICENSE.TXT",
+        lib_dir: Some("libcxxabi"),
+    },
+    Component {
+        name: "libunwind",
+        spdx: LLVM_SPDX,
+        url: "https://github.com/llvm/llvm-project/blob/main/libunwind/LICENSE.TXT",
+        lib_dir: Some("libunwind"),
+    },
+    Component {
+        name: "libtsan",
+        spdx: LLVM_SPDX,
+        url: "https://github.com/llvm/llvm-project/blob/main/compiler-rt/LICENSE.TXT",
+        lib_dir: Some("libtsan"),
+    },
+    Component {
+        name: "musl",
+        spdx: "MIT",
+        url: "https://git.musl-libc.org/cgit/musl/tree/COPYRIGHT",
+        lib_dir: Some("libc/musl"),
+    },
+    Component {
+        name: "glibc",
+        spdx: "LGPL-2.1-or-later",
+        url: "https://sourceware.org/git/?p=glibc.git;a=blob;f=COPYING.LIB",
+        lib_dir: Some("libc/glibc"),
+    },
+    Component {
+        name: "mingw-w64",
+        spdx: "LicenseRef-mingw-w64",
+        url: "https://github.com/mingw-w64/mingw-w64/blob/master/COPYING",
+        lib_dir: Some("libc/mingw"),
+    },
+    Component {
+        name: "wasi-libc",
+        spdx: "Apache-2.0 WITH LLVM-exception OR Apache-2.0 OR MIT",
+        url: "https://github.com/WebAssembly/wasi-libc/blob/main/LICENSE",
+        lib_dir: Some("libc/wasi"),
+    },
+];
+
+/// Copies the license text `file` of the component `name` (`zig`, `llvm`,
+/// `zlib` or `zstd`, which have nothing under `lib/`) into the licenses
+/// directory of the toolchain being installed at `root`. Missing files are
+/// skipped.
+pub fn stage(root: &Path, name: &str, file: &Path) -> io::Result<()> {
+    let Some(file_name) = file.file_name() else {
+        return Ok(());
+    };
+    if !file.is_file() {
+        return Ok(());
+    }
+    let dir = root.join(DIR_NAME).join(name);
+    fs_err::create_dir_all(&dir)?;
+    fs_err::copy(file, dir.join(file_name))?;
+    Ok(())
+}
+
+/// Copies the license files under `root/lib/` into the licenses directory
+/// and writes its index, keeping texts already [staged](stage()). LLVM's
+/// text is the one its C++ runtime libraries ship when no other was staged.
+pub fn collect(root: &Path) -> io::Result<()> {
+    let dir = root.join(DIR_NAME);
+    fs_err::create_dir_all(&dir)?;
+    for component in COMPONENTS {
+        let Some(lib_dir) = component.lib_dir else {
+            continue;
+        };
+        let Ok(entries) = fs_err::read_dir(root.join("lib").join(lib_dir)) else {
+            continue;
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if is_license_file(&path) {
+                stage(root, component.name, &path)?;
+            }
+        }
+    }
+    if staged_files(&dir, "llvm")?.is_empty() {
+        for file in staged_files(&dir, "libcxx")? {
+            stage(root, "llvm", &dir.join(file))?;
+        }
+    }
+
+    let mut components = Vec::new();
+    for component in COMPONENTS {
+        let mut table = Table::new();
+        table.insert("name".into(), component.name.into());
+        table.insert("spdx".into(), component.spdx.into());
+        table.insert("url".into(), component.url.into());
+        let files = staged_files(&dir, component.name)?
+            .into_iter()
+            .map(|file| Value::from(file.to_string_lossy().replace('\\', "/")))
+            .collect::<Vec<_>>();
+        table.insert("files".into(), files.into());
+        components.push(Value::Table(table));
+    }
+    let mut index = Table::new();
+    index.insert("component".into(), components.into());
+    fs_err::write(dir.join(FILE_NAME), index.to_string())
+}
+
+/// Reads the index of the toolchain installed at `root`.
+pub fn read(root: &Path) -> io::Result<Vec<License>> {
+    let dir = root.join(DIR_NAME);
+    let path = dir.join(FILE_NAME);
+    let invalid = |message: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {message}", path.display()),
+        )
+    };
+    let index: Table = fs_err::read_to_string(&path)?
+        .parse()
+        .map_err(|e: toml::de::Error| invalid(e.message()))?;
+    let Some(Value::Array(components)) = index.get("component") else {
+        return Err(invalid("no [[component]] tables"));
+    };
+    let string = |table: &Table, key: &str| {
+        table
+            .get(key)
+            .and_then(Value::as_str)
+            .map(str::to_owned)
+            .ok_or_else(|| invalid(&format!("component without a string `{key}`")))
+    };
+    let mut licenses = Vec::new();
+    for component in components {
+        let table = component
+            .as_table()
+            .ok_or_else(|| invalid("`component` is not an array of tables"))?;
+        let files = match table.get("files") {
+            Some(Value::Array(files)) => files
+                .iter()
+                .filter_map(Value::as_str)
+                .map(|file| dir.join(file))
+                .collect(),
+            _ => Vec::new(),
+        };
+        licenses.push(License {
+            name: string(table, "name")?,
+            spdx: string(table, "spdx")?,
+            url: string(table, "url")?,
+            files,
+        });
+    }
+    Ok(licenses)
+}
+
+/// Paths relative to `dir` of the texts staged for the component `name`.
+fn staged_files(dir: &Path, name: &str) -> io::Result<Vec<PathBuf>> {
+    let Ok(entries) = fs_err::read_dir(dir.join(name)) else {
+        return Ok(Vec::new());
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        files.push(Path::new(name).join(entry?.file_name()));
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// `LICENSE`, `LICENSE.TXT`, `COPYING.LIB`, `COPYRIGHT`, `NOTICE` and the
+/// like.
+fn is_license_file(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    let name = name.to_ascii_uppercase();
+    path.is_file()
+        && ["LICENSE", "LICENCE", "COPYING", "COPYRIGHT", "NOTICE"]
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
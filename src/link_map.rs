@@ -0,0 +1,391 @@
+//! Linker map files, for finding out what made a binary big. The maps written
+//! by LLD (which `zig cc` links with), GNU ld and Apple's ld64 are understood;
+//! they all boil down to output sections, the input sections from each object
+//! placed in them, and the symbols inside those.
+//!
+//! ```no_run
+//! // build.rs
+//! # let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR")?);
+//! zig_rs::Build::new()
+//!     .file("src/foo.c")
+//!     .link_map(out_dir.join("app.map"))
+//!     .compile("foo");
+//!
+//! // later, e.g. in a size-tracking tool
+//! let map = zig_rs::link_map::LinkMap::read(&out_dir.join("app.map"))?;
+//! for (object, size) in map.sizes_by_object().iter().take(10) {
+//!     println!("{size:>10} {object}");
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{collections::HashMap, io, path::Path};
+
+/// A section of the output file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub address: u64,
+    pub size: u64,
+}
+
+/// A piece of an object file placed into an output section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputSection {
+    /// The output section it was placed in.
+    pub section: String,
+    /// The object or archive member it came from, as printed by the linker.
+    pub object: String,
+    pub address: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub section: String,
+    pub object: String,
+    pub address: u64,
+    /// As printed by the linker where it prints one (ld64), otherwise the
+    /// distance to the next symbol or the end of the input section.
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkMap {
+    pub sections: Vec<Section>,
+    pub inputs: Vec<InputSection>,
+    pub symbols: Vec<Symbol>,
+}
+
+/// The linker driver flag that writes a map of the link to `path`.
+pub fn link_arg(rust_target: &str, path: &Path) -> String {
+    if rust_target.contains("-apple-") {
+        format!("-Wl,-map,{}", path.display())
+    } else if rust_target.ends_with("-msvc") {
+        format!("/MAP:{}", path.display())
+    } else {
+        format!("-Wl,-Map={}", path.display())
+    }
+}
+
+impl LinkMap {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Ok(Self::parse(&fs_err::read_to_string(path)?))
+    }
+
+    /// Parses a map file, detecting which linker wrote it. Lines that don't
+    /// fit the detected format are skipped.
+    pub fn parse(text: &str) -> Self {
+        let mut map = if text.contains("# Sections:") {
+            parse_ld64(text)
+        } else if text
+            .lines()
+            .next()
+            .is_some_and(|line| line.split_whitespace().eq(LLD_HEADER))
+        {
+            parse_lld(text)
+        } else {
+            parse_gnu(text)
+        };
+        map.fill_symbol_sizes();
+        map
+    }
+
+    /// Total input section size per object, largest first.
+    pub fn sizes_by_object(&self) -> Vec<(String, u64)> {
+        let mut sizes: HashMap<&str, u64> = HashMap::new();
+        for input in &self.inputs {
+            *sizes.entry(&input.object).or_default() += input.size;
+        }
+        sorted(sizes)
+    }
+
+    /// Total input section size per output section, largest first. Unlike
+    /// [`sections`](Self::sections) this leaves out padding and alignment.
+    pub fn sizes_by_section(&self) -> Vec<(String, u64)> {
+        let mut sizes: HashMap<&str, u64> = HashMap::new();
+        for input in &self.inputs {
+            *sizes.entry(&input.section).or_default() += input.size;
+        }
+        sorted(sizes)
+    }
+
+    /// The `n` largest symbols.
+    pub fn largest_symbols(&self, n: usize) -> Vec<&Symbol> {
+        let mut symbols: Vec<&Symbol> = self.symbols.iter().collect();
+        symbols.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+        symbols.truncate(n);
+        symbols
+    }
+
+    /// Gives symbols the linker printed without a size the distance to the
+    /// next symbol in the same input section. The symbols are sorted once
+    /// and each input section looks its own up, as maps of code built with
+    /// `-ffunction-sections` have as many input sections as symbols.
+    fn fill_symbol_sizes(&mut self) {
+        let symbols = &self.symbols;
+        let key = |i: usize| {
+            let s = &symbols[i];
+            (s.object.as_str(), s.section.as_str(), s.address)
+        };
+        let mut sizeless: Vec<usize> = (0..symbols.len())
+            .filter(|&i| symbols[i].size == 0)
+            .collect();
+        sizeless.sort_by_key(|&i| key(i));
+        let mut sizes = Vec::new();
+        for input in &self.inputs {
+            let end = input.address + input.size;
+            let at = |address| (input.object.as_str(), input.section.as_str(), address);
+            let start = sizeless.partition_point(|&i| key(i) < at(input.address));
+            let len = sizeless[start..].partition_point(|&i| key(i) < at(end));
+            let mut next = end;
+            for &i in sizeless[start..start + len].iter().rev() {
+                sizes.push((i, next - symbols[i].address));
+                next = symbols[i].address;
+            }
+        }
+        for (i, size) in sizes {
+            let symbol = &mut self.symbols[i];
+            if symbol.size == 0 {
+                symbol.size = size;
+            }
+        }
+    }
+}
+
+fn sorted(sizes: HashMap<&str, u64>) -> Vec<(String, u64)> {
+    let mut sizes: Vec<(String, u64)> = sizes
+        .into_iter()
+        .map(|(name, size)| (name.to_owned(), size))
+        .collect();
+    sizes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    sizes
+}
+
+const LLD_HEADER: [&str; 7] = ["VMA", "LMA", "Size", "Align", "Out", "In", "Symbol"];
+
+fn hex(s: &str) -> Option<u64> {
+    u64::from_str_radix(s.trim_start_matches("0x"), 16).ok()
+}
+
+/// ```text
+///      VMA      LMA     Size Align Out     In      Symbol
+///   2012b8   2012b8       2c     4 .text
+///   2012b8   2012b8       2c     4         /tmp/a.o:(.text)
+///   2012b8   2012b8        0     1                 _start
+/// ```
+fn parse_lld(text: &str) -> LinkMap {
+    let mut map = LinkMap::default();
+    let mut lines = text.lines();
+    let Some(header) = lines.next() else {
+        return map;
+    };
+    let Some(out_column) = header.find("Out") else {
+        return map;
+    };
+    let in_column = header.find("In ").unwrap_or(out_column + 8);
+    let mut section = String::new();
+    let mut object = String::new();
+    for line in lines {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [vma, _lma, size, _align, ..] = fields[..] else {
+            continue;
+        };
+        let (Some(address), Some(size)) = (hex(vma), hex(size)) else {
+            continue;
+        };
+        let name_column = line.len() - line[out_column.min(line.len())..].trim_start().len();
+        let name = line[name_column..].trim();
+        if name_column <= out_column {
+            section = name.to_owned();
+            map.sections.push(Section {
+                name: section.clone(),
+                address,
+                size,
+            });
+        } else if name_column <= in_column {
+            // `/path/to/lib.a(member.o):(.text.foo)`
+            object = match name.rsplit_once(":(") {
+                Some((object, _)) => object.to_owned(),
+                None => name.to_owned(),
+            };
+            map.inputs.push(InputSection {
+                section: section.clone(),
+                object: object.clone(),
+                address,
+                size,
+            });
+        } else {
+            map.symbols.push(Symbol {
+                name: name.to_owned(),
+                section: section.clone(),
+                object: object.clone(),
+                address,
+                size,
+            });
+        }
+    }
+    map
+}
+
+/// ```text
+/// .text           0x0000000000401000      0x1a5
+///  *(.text.unlikely .text.*_unlikely .text.unlikely.*)
+///  .text          0x0000000000401000       0x2e /usr/lib/crt1.o
+///                 0x0000000000401000                _start
+/// ```
+///
+/// Section names too long for their column are put on a line of their own.
+fn parse_gnu(text: &str) -> LinkMap {
+    let mut map = LinkMap::default();
+    let mut section = String::new();
+    let mut object = String::new();
+    let mut pending_name: Option<(bool, String)> = None;
+    let Some(start) = text.find("Linker script and memory map") else {
+        return map;
+    };
+    for line in text[start..].lines().skip(1) {
+        let top_level = !line.starts_with(' ');
+        let mut fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+        let mut top_level_name = top_level;
+        let owned_name;
+        if let Some((pending_top_level, name)) = pending_name.take() {
+            owned_name = name;
+            fields.insert(0, &owned_name);
+            top_level_name = pending_top_level;
+        }
+        match fields[..] {
+            [name] if name.starts_with('.') => {
+                pending_name = Some((top_level, name.to_owned()));
+            }
+            [name, address, size, ..] if top_level_name && name.starts_with('.') => {
+                let (Some(address), Some(size)) = (hex(address), hex(size)) else {
+                    continue;
+                };
+                section = name.to_owned();
+                map.sections.push(Section {
+                    name: section.clone(),
+                    address,
+                    size,
+                });
+            }
+            [name, address, size, ref file @ ..] if name.starts_with('.') && !file.is_empty() => {
+                let (Some(address), Some(size)) = (hex(address), hex(size)) else {
+                    continue;
+                };
+                object = file.join(" ");
+                map.inputs.push(InputSection {
+                    section: section.clone(),
+                    object: object.clone(),
+                    address,
+                    size,
+                });
+            }
+            [address, name] if address.starts_with("0x") && !name.contains('=') => {
+                let Some(address) = hex(address) else {
+                    continue;
+                };
+                map.symbols.push(Symbol {
+                    name: name.to_owned(),
+                    section: section.clone(),
+                    object: object.clone(),
+                    address,
+                    size: 0,
+                });
+            }
+            _ => {}
+        }
+    }
+    map
+}
+
+/// ```text
+/// # Object files:
+/// [  1] /tmp/main.o
+/// # Sections:
+/// # Address    Size        Segment    Section
+/// 0x100003F80    0x00000030    __TEXT    __text
+/// # Symbols:
+/// # Address    Size        File  Name
+/// 0x100003F80    0x00000020    [  1] _main
+/// ```
+fn parse_ld64(text: &str) -> LinkMap {
+    let mut map = LinkMap::default();
+    let mut objects: HashMap<String, String> = HashMap::new();
+    let mut part = "";
+    for line in text.lines() {
+        if let Some(heading) = line.strip_prefix("# ") {
+            if heading.ends_with(':') {
+                part = heading;
+            }
+            continue;
+        }
+        match part {
+            "Object files:" => {
+                if let Some((index, path)) = line.split_once(']') {
+                    objects.insert(
+                        index.trim_start_matches('[').trim().into(),
+                        path.trim().into(),
+                    );
+                }
+            }
+            "Sections:" => {
+                if let [address, size, segment, section] =
+                    line.split_whitespace().collect::<Vec<_>>()[..]
+                    && let (Some(address), Some(size)) = (hex(address), hex(size))
+                {
+                    map.sections.push(Section {
+                        name: format!("{segment},{section}"),
+                        address,
+                        size,
+                    });
+                }
+            }
+            "Symbols:" => {
+                let mut fields = line.splitn(3, '\t');
+                let (Some(address), Some(size), Some(rest)) =
+                    (fields.next(), fields.next(), fields.next())
+                else {
+                    continue;
+                };
+                let (Some(address), Some(size)) = (hex(address.trim()), hex(size.trim())) else {
+                    continue;
+                };
+                let Some((index, name)) = rest.split_once(']') else {
+                    continue;
+                };
+                let object = objects
+                    .get(index.trim_start_matches('[').trim())
+                    .cloned()
+                    .unwrap_or_default();
+                let section = map
+                    .sections
+                    .iter()
+                    .find(|s| (s.address..s.address + s.size).contains(&address))
+                    .map(|s| s.name.clone())
+                    .unwrap_or_default();
+                map.symbols.push(Symbol {
+                    name: name.trim().to_owned(),
+                    section: section.clone(),
+                    object: object.clone(),
+                    address,
+                    size,
+                });
+                // ld64 has no input section records; one per symbol keeps the
+                // per-object totals meaningful.
+                map.inputs.push(InputSection {
+                    section,
+                    object,
+                    address,
+                    size,
+                });
+            }
+            _ => {}
+        }
+    }
+    map
+}
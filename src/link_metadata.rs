@@ -0,0 +1,194 @@
+//! Link metadata passed up from the crates of a workspace that each build
+//! Zig or C code, so the build script of the binary crate links all of their
+//! libraries once and in an order that works. Each library crate, with a
+//! `links` key in its manifest, builds without cargo metadata and exports
+//! what it built along with what its own dependencies exported:
+//!
+//! ```no_run
+//! // crates/codec/build.rs, with `links = "codec"`
+//! use zig_rs::link_metadata::LinkMetadata;
+//!
+//! let artifacts = zig_rs::Build::new()
+//!     .file("zig/codec.zig")
+//!     .emit_bindings(true)
+//!     .cargo_metadata(false)
+//!     .try_compile("codec")?;
+//! let mut metadata = LinkMetadata::from_artifacts(&artifacts);
+//! metadata.merge(LinkMetadata::from_dependencies()?);
+//! metadata.export()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! ```no_run
+//! // app/build.rs
+//! zig_rs::link_metadata::LinkMetadata::from_dependencies()?.emit();
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Cargo only hands a build script the `DEP_<LINKS>_*` variables of its
+//! direct dependencies, hence the re-export. A library that several crates
+//! export is linked once, after the libraries of every crate exporting it,
+//! since a crate's own libraries come before its dependencies'.
+
+use std::{
+    env,
+    error::Error,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use crate::{compile::Artifacts, zig_build};
+
+/// Key of the search paths, `DEP_<LINKS>_ZIG_RS_LINK_SEARCH`, joined like
+/// `PATH`.
+pub const SEARCH_KEY: &str = "zig_rs_link_search";
+
+/// Key of the libraries, `DEP_<LINKS>_ZIG_RS_LINK_LIBS`, `kind=name`s
+/// separated by commas.
+pub const LIBS_KEY: &str = "zig_rs_link_libs";
+
+/// Key of the include directories, `DEP_<LINKS>_ZIG_RS_INCLUDE`, joined like
+/// `PATH`.
+pub const INCLUDE_KEY: &str = "zig_rs_include";
+
+/// Native search paths, libraries and include directories, each listed once.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkMetadata {
+    pub search: Vec<PathBuf>,
+    /// `rustc-link-lib` kinds and names, e.g. `("static", "codec")`, in link
+    /// order.
+    pub libs: Vec<(String, String)>,
+    /// Where the libraries' headers are, for C code of dependent crates.
+    pub include: Vec<PathBuf>,
+}
+
+impl LinkMetadata {
+    /// The library [`Build::try_compile()`](crate::Build::try_compile)
+    /// built, then its runtime libraries, and the directory of its emitted
+    /// header, if any.
+    pub fn from_artifacts(artifacts: &Artifacts) -> Self {
+        let mut metadata = Self::default();
+        for lib in [&artifacts.lib].into_iter().chain(&artifacts.runtime_libs) {
+            let file_name = lib.file_name().unwrap_or_default().to_string_lossy();
+            if let Some((kind, name)) = zig_build::library_kind_and_name(&file_name) {
+                metadata.add_search(lib.parent().unwrap_or(Path::new(".")));
+                metadata.add_lib(kind, name);
+            }
+        }
+        if let Some((_, header)) = &artifacts.bindings {
+            metadata.add_include(header.parent().unwrap_or(Path::new(".")));
+        }
+        metadata
+    }
+
+    /// What the direct dependencies [exported](Self::export), in the order
+    /// of their `links` names and merged with [`merge()`](Self::merge).
+    pub fn from_dependencies() -> Result<Self, Box<dyn Error>> {
+        let suffix = |key: &str| format!("_{}", key.to_ascii_uppercase());
+        let mut links: Vec<String> = env::vars_os()
+            .filter_map(|(var, _)| {
+                let var = var.into_string().ok()?;
+                let rest = var.strip_prefix("DEP_")?;
+                [SEARCH_KEY, LIBS_KEY, INCLUDE_KEY]
+                    .into_iter()
+                    .find_map(|key| rest.strip_suffix(&suffix(key)))
+                    .map(str::to_owned)
+            })
+            .collect();
+        links.sort();
+        links.dedup();
+        let mut metadata = Self::default();
+        for name in links {
+            let var = |key: &str| env::var_os(format!("DEP_{name}{}", suffix(key)));
+            let paths =
+                |key: &str| var(key).map_or_else(Vec::new, |v| env::split_paths(&v).collect());
+            let mut dependency = Self {
+                search: paths(SEARCH_KEY),
+                libs: Vec::new(),
+                include: paths(INCLUDE_KEY),
+            };
+            let libs = var(LIBS_KEY).unwrap_or_default();
+            let libs = libs
+                .to_str()
+                .ok_or_else(|| format!("DEP_{name}{} isn't UTF-8", suffix(LIBS_KEY)))?;
+            for lib in libs.split(',').filter(|lib| !lib.is_empty()) {
+                let (kind, lib) = lib.split_once('=').unwrap_or(("static", lib));
+                dependency.libs.push((kind.into(), lib.into()));
+            }
+            metadata.merge(dependency);
+        }
+        Ok(metadata)
+    }
+
+    /// Adds `other`'s entries after these. A library in both moves to its
+    /// place in `other`, so it stays after the libraries that need it.
+    pub fn merge(&mut self, other: Self) {
+        for dir in &other.search {
+            self.add_search(dir);
+        }
+        for (kind, name) in &other.libs {
+            self.add_lib(kind, name);
+        }
+        for dir in &other.include {
+            self.add_include(dir);
+        }
+    }
+
+    pub fn add_search(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        let dir = dir.as_ref();
+        if !self.search.iter().any(|known| known == dir) {
+            self.search.push(dir.into());
+        }
+        self
+    }
+
+    /// Adds a library at the end, moving it there if it's already listed.
+    pub fn add_lib(&mut self, kind: &str, name: &str) -> &mut Self {
+        self.libs.retain(|(_, known)| known != name);
+        self.libs.push((kind.into(), name.into()));
+        self
+    }
+
+    pub fn add_include(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        let dir = dir.as_ref();
+        if !self.include.iter().any(|known| known == dir) {
+            self.include.push(dir.into());
+        }
+        self
+    }
+
+    /// Prints the metadata as `cargo:` keys, which the build scripts of the
+    /// crates that depend on this one read back with
+    /// [`from_dependencies()`](Self::from_dependencies). The crate needs a
+    /// `links` key for Cargo to pass them on.
+    pub fn export(&self) -> Result<(), Box<dyn Error>> {
+        let join =
+            |dirs: &[PathBuf]| -> Result<OsString, Box<dyn Error>> { Ok(env::join_paths(dirs)?) };
+        println!(
+            "cargo:{SEARCH_KEY}={}",
+            join(&self.search)?.to_string_lossy()
+        );
+        let libs: Vec<String> = self
+            .libs
+            .iter()
+            .map(|(kind, name)| format!("{kind}={name}"))
+            .collect();
+        println!("cargo:{LIBS_KEY}={}", libs.join(","));
+        println!(
+            "cargo:{INCLUDE_KEY}={}",
+            join(&self.include)?.to_string_lossy()
+        );
+        Ok(())
+    }
+
+    /// Prints the search paths and libraries as `cargo:rustc-link-search`
+    /// and `cargo:rustc-link-lib`, for the crate that links them.
+    pub fn emit(&self) {
+        for dir in &self.search {
+            build::rustc_link_search_kind("native", dir);
+        }
+        for (kind, name) in &self.libs {
+            build::rustc_link_lib_kind(kind, name);
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! What went wrong in a failed link. Linker errors don't have the
+//! `path:line:column` form [`diagnostic`](crate::diagnostic) parses, and the
+//! message rarely says which library was missing, so failed links are
+//! summarized into a [`LinkReport`]: the undefined symbols and who referenced
+//! them, the libraries that weren't found and where the linker looked, and a
+//! guess at the `-l` flag or search path that would fix it.
+//!
+//! It understands the messages of LLD (`ld.lld`, `ld64.lld`, `lld-link`,
+//! `wasm-ld`), of Zig's own linkers and of GNU ld. Builders that link with
+//! `zig build-exe` or `build-lib` run a failed link again with
+//! `--verbose-link` so the report also has the final linker command line; see
+//! [`CommandError::link_report()`](crate::error::CommandError::link_report).
+
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+};
+
+use crate::log::{self, Tagger};
+
+/// A summary of a failed link.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkReport {
+    /// The linker command line `--verbose-link` printed, if any.
+    pub linker_command: Option<String>,
+    pub undefined_symbols: Vec<UndefinedSymbol>,
+    pub missing_libraries: Vec<MissingLibrary>,
+    /// Likely fixes, e.g. ``link `-lm` for `sqrt` ``.
+    pub suggestions: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndefinedSymbol {
+    pub symbol: String,
+    /// The object files or source locations the linker named as referencing
+    /// it, as printed.
+    pub referenced_by: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingLibrary {
+    /// The library as it was asked for, e.g. `ssl` for `-lssl` or
+    /// `ssl.lib`.
+    pub name: String,
+    /// The directories the linker says it searched.
+    pub searched: Vec<PathBuf>,
+}
+
+impl fmt::Display for LinkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for undefined in &self.undefined_symbols {
+            write!(f, "undefined symbol: {}", undefined.symbol)?;
+            if let Some(first) = undefined.referenced_by.first() {
+                write!(f, " (referenced by {first})")?;
+            }
+            writeln!(f)?;
+        }
+        for missing in &self.missing_libraries {
+            writeln!(f, "library not found: {}", missing.name)?;
+        }
+        for suggestion in &self.suggestions {
+            writeln!(f, "help: {suggestion}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the output of a failed link. Gives `None` if it has no linker
+/// errors this module recognizes.
+pub fn parse(output: &str) -> Option<LinkReport> {
+    let mut report = LinkReport {
+        linker_command: output
+            .lines()
+            .find(|line| is_linker_command(line))
+            .map(|line| line.trim().to_owned()),
+        ..Default::default()
+    };
+    let mut lines = output.lines().peekable();
+    while let Some(line) = lines.next() {
+        if let Some(symbol) = undefined_symbol(line) {
+            let mut referenced_by = Vec::new();
+            while let Some(next) = lines.peek().and_then(|next| reference(next)) {
+                if !next.is_empty() {
+                    referenced_by.push(next.to_owned());
+                }
+                lines.next();
+            }
+            match report
+                .undefined_symbols
+                .iter_mut()
+                .find(|undefined| undefined.symbol == symbol)
+            {
+                Some(undefined) => undefined.referenced_by.extend(referenced_by),
+                None => report.undefined_symbols.push(UndefinedSymbol {
+                    symbol: symbol.to_owned(),
+                    referenced_by,
+                }),
+            }
+        } else if let Some(name) = missing_library(line) {
+            let mut searched = Vec::new();
+            while let Some(next) = lines.peek() {
+                let path = next.trim();
+                if !next.starts_with(char::is_whitespace) || path.is_empty() {
+                    break;
+                }
+                // Zig lists the files it tried rather than the directories.
+                let path = Path::new(path);
+                let dir = match path.file_name() {
+                    Some(file) if file.to_string_lossy().contains(&name) => path.parent(),
+                    _ => Some(path),
+                };
+                if let Some(dir) = dir
+                    && !searched.iter().any(|searched| searched == dir)
+                {
+                    searched.push(dir.to_owned());
+                }
+                lines.next();
+            }
+            if !report.missing_libraries.iter().any(|m| m.name == name) {
+                report
+                    .missing_libraries
+                    .push(MissingLibrary { name, searched });
+            }
+        }
+    }
+    if report.undefined_symbols.is_empty() && report.missing_libraries.is_empty() {
+        return None;
+    }
+    report.suggestions = suggestions(&report);
+    Some(report)
+}
+
+/// Runs a failed `zig build-exe`/`build-lib` command again with
+/// `--verbose-link` when its output looks like a link failure without the
+/// linker command line, and returns the second run's status and output.
+/// Zig's cache makes this a relink rather than a rebuild.
+pub(crate) fn rerun_verbose(
+    cmd: &mut Command,
+    tagger: &Tagger,
+    status: ExitStatus,
+    output: String,
+) -> io::Result<(ExitStatus, String)> {
+    match parse(&output) {
+        Some(report) if report.linker_command.is_none() => {
+            cmd.arg("--verbose-link");
+            log::run_tagged_captured(cmd, tagger)
+        }
+        _ => Ok((status, output)),
+    }
+}
+
+fn is_linker_command(line: &str) -> bool {
+    let program = line.split_whitespace().next().unwrap_or_default();
+    let program = program.rsplit(['/', '\\']).next().unwrap_or(program);
+    let linker = matches!(
+        program.trim_end_matches(".exe"),
+        "ld.lld" | "ld64.lld" | "lld-link" | "wasm-ld" | "ld" | "ld.bfd" | "ld.gold"
+    );
+    (linker && line.split_whitespace().count() > 2) || line.starts_with("zig ld ")
+}
+
+/// The symbol in an undefined symbol headline.
+fn undefined_symbol(line: &str) -> Option<&str> {
+    if let Some((_, rest)) = line.split_once("undefined symbol: ") {
+        return Some(rest.trim());
+    }
+    // GNU ld: "main.c:(.text+0x5): undefined reference to `foo'"
+    let (_, rest) = line.split_once("undefined reference to ")?;
+    Some(
+        rest.trim()
+            .trim_start_matches(['`', '\''])
+            .trim_end_matches(['\'', '`']),
+    )
+}
+
+/// The referencing location on a line following an undefined symbol
+/// headline: LLD's `>>> referenced by x.o:(main)` and `>>>        x.o`, or
+/// Zig's `note: referenced by ...` and continuation `note:` lines.
+fn reference(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let rest = line
+        .strip_prefix(">>>")
+        .or_else(|| line.strip_prefix("note:"))?
+        .trim();
+    Some(rest.strip_prefix("referenced by ").unwrap_or(rest).trim())
+}
+
+/// The library in a "library not found" headline.
+fn missing_library(line: &str) -> Option<String> {
+    // LLD: "unable to find library -lfoo"; Apple: "library not found for -lfoo"
+    for marker in ["unable to find library -l", "library not found for -l"] {
+        if let Some((_, rest)) = line.split_once(marker) {
+            return Some(rest.trim().into());
+        }
+    }
+    // Zig: "unable to find dynamic system library 'foo' using strategy ..."
+    if let Some((_, rest)) = line.split_once("system library '") {
+        return Some(rest.split('\'').next()?.into());
+    }
+    // lld-link: "could not open 'foo.lib': no such file or directory"
+    let (_, rest) = line.split_once("could not open '")?;
+    let file = rest.split('\'').next()?;
+    file.ends_with(".lib").then(|| file.into())
+}
+
+/// Libraries that define common symbols C code forgets to link. Names ending
+/// in `_` are prefixes.
+const KNOWN_SYMBOLS: &[(&[&str], &str)] = &[
+    (
+        &[
+            "sqrt", "sin", "cos", "tan", "pow", "exp", "log", "log2", "log10", "floor", "ceil",
+            "fmod", "round", "atan2", "hypot",
+        ],
+        "m",
+    ),
+    (&["pthread_"], "pthread"),
+    (&["dlopen", "dlsym", "dlclose", "dlerror"], "dl"),
+    (
+        &["clock_gettime", "shm_open", "shm_unlink", "timer_create"],
+        "rt",
+    ),
+    (&["_Unwind_"], "unwind"),
+];
+
+fn suggestions(report: &LinkReport) -> Vec<String> {
+    let mut suggestions = Vec::new();
+    for undefined in &report.undefined_symbols {
+        let symbol = undefined.symbol.as_str();
+        // Mach-O symbols have an extra leading underscore.
+        let suggestion =
+            symbol_suggestion(symbol).or_else(|| symbol_suggestion(symbol.strip_prefix('_')?));
+        if let Some(suggestion) = suggestion
+            && !suggestions.contains(&suggestion)
+        {
+            suggestions.push(suggestion);
+        }
+    }
+    for missing in &report.missing_libraries {
+        suggestions.push(format!(
+            "add the directory containing {} with `-L` (`cargo:rustc-link-search`), or install it",
+            library_files(&missing.name)
+        ));
+    }
+    suggestions
+}
+
+fn symbol_suggestion(symbol: &str) -> Option<String> {
+    if let Some(import) = symbol.strip_prefix("__imp_") {
+        return Some(format!(
+            "`{symbol}` is a DLL import; link the import library that exports `{import}`"
+        ));
+    }
+    if ["_Z", "__cxa_", "__gxx_"]
+        .iter()
+        .any(|prefix| symbol.starts_with(prefix))
+    {
+        return Some(format!(
+            "`{symbol}` is from the C++ runtime; link a C++ standard library (see `Runtime::cxx_stdlib`)"
+        ));
+    }
+    if symbol.starts_with("__") && (symbol.ends_with("ti3") || symbol.ends_with("tf2")) {
+        return Some(format!(
+            "`{symbol}` is a compiler-rt routine; enable `Runtime::compiler_rt`"
+        ));
+    }
+    let (_, lib) = KNOWN_SYMBOLS.iter().find(|(names, _)| {
+        names.iter().any(|name| {
+            if name.ends_with('_') {
+                symbol.starts_with(name)
+            } else {
+                symbol == *name
+            }
+        })
+    })?;
+    Some(format!("link `-l{lib}` for `{symbol}`"))
+}
+
+/// The file names a library is looked up as.
+fn library_files(name: &str) -> String {
+    if name.ends_with(".lib") {
+        format!("`{name}`")
+    } else {
+        format!("`lib{name}.a` or `lib{name}.so`")
+    }
+}
@@ -0,0 +1,288 @@
+//! Cargo runs build scripts for many crates in parallel and interleaves all of
+//! their output. A bare `-- Configuring done` or `error: undefined symbol`
+//! line gives no hint about which crate or which step printed it, so every
+//! line we forward from a child process is tagged like this:
+//!
+//! ```text
+//! [zig][zig/bootstrap] -- Configuring done
+//! ```
+//!
+//! The leading `[zig]` can be changed with `ZIG_RS_LOG_PREFIX`. The bracketed
+//! step is `<package>/<step>` where `<package>` is the crate whose build script
+//! is running (from `CARGO_PKG_NAME`) when that is known.
+//!
+//! IDEs and GUIs that draw their own progress [`subscribe()`] instead: the
+//! steps then report [`Event`]s over a channel rather than printing to
+//! stderr.
+//!
+//! ```no_run
+//! use zig_rs::log::{self, Event};
+//!
+//! let events = log::subscribe();
+//! std::thread::spawn(|| {
+//!     let _ = zig_rs::Toolchain::version("0.14.0");
+//!     log::unsubscribe();
+//! });
+//! for event in events {
+//!     match event {
+//!         Event::Progress { step, message, .. } => println!("{step}: {message}"),
+//!         Event::Line { step, line } => println!("{step}: {line}"),
+//!         _ => {}
+//!     }
+//! }
+//! ```
+
+use std::{
+    env,
+    io::{self, BufRead, BufReader, Read, Write},
+    process::{Command, ExitStatus, Stdio},
+    sync::{
+        Mutex,
+        mpsc::{self, Receiver, Sender},
+    },
+    thread,
+};
+
+use crate::process;
+
+/// Environment variable that overrides [`DEFAULT_PREFIX`].
+pub const PREFIX_ENV: &str = "ZIG_RS_LOG_PREFIX";
+
+/// Prefix used when `ZIG_RS_LOG_PREFIX` is not set.
+pub const DEFAULT_PREFIX: &str = "[zig]";
+
+/// Returns the configured line prefix.
+pub fn prefix() -> String {
+    env::var(PREFIX_ENV).unwrap_or_else(|_| DEFAULT_PREFIX.into())
+}
+
+/// What a step of a long-running operation (a download, a bootstrap, a
+/// compile) reports to a [subscriber](subscribe()). `step` is the one in
+/// [`Tagger::step()`], e.g. `zig/download`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Started {
+        step: String,
+    },
+    /// How far along the step is, from 0 to 1, when that is known, and a
+    /// description like the line that would have been printed.
+    Progress {
+        step: String,
+        fraction: Option<f64>,
+        message: String,
+    },
+    /// A line of output, untagged.
+    Line {
+        step: String,
+        line: String,
+    },
+    Finished {
+        step: String,
+        success: bool,
+    },
+}
+
+static SUBSCRIBER: Mutex<Option<Sender<Event>>> = Mutex::new(None);
+
+/// Sends the [`Event`]s of every step in this process to the returned
+/// receiver, instead of printing tagged lines to stderr, until the receiver
+/// is dropped. A second subscription replaces the first.
+pub fn subscribe() -> Receiver<Event> {
+    let (sender, receiver) = mpsc::channel();
+    *SUBSCRIBER.lock().unwrap() = Some(sender);
+    receiver
+}
+
+/// Ends the subscription, so iterating over the receiver stops once it has
+/// returned the events sent so far, and steps print to stderr again.
+pub fn unsubscribe() {
+    *SUBSCRIBER.lock().unwrap() = None;
+}
+
+/// Sends `event` to the subscriber, if any. Returns whether it did, in which
+/// case the caller prints nothing.
+pub(crate) fn emit(event: Event) -> bool {
+    let mut subscriber = SUBSCRIBER.lock().unwrap();
+    let Some(sender) = &*subscriber else {
+        return false;
+    };
+    if sender.send(event).is_ok() {
+        return true;
+    }
+    *subscriber = None;
+    false
+}
+
+/// Tags lines of output with a prefix and the step that produced them.
+#[derive(Debug, Clone)]
+pub struct Tagger {
+    prefix: String,
+    step: String,
+}
+
+impl Tagger {
+    /// Creates a tagger for `step` using the configured [`prefix()`]. The step
+    /// is qualified with `CARGO_PKG_NAME` if that is set.
+    pub fn new(step: &str) -> Self {
+        let step = match env::var("CARGO_PKG_NAME") {
+            Ok(package) => format!("{package}/{step}"),
+            Err(_) => step.into(),
+        };
+        Self::with_prefix(prefix(), step)
+    }
+
+    /// Creates a tagger with an explicit prefix and step, ignoring the
+    /// environment.
+    pub fn with_prefix(prefix: impl Into<String>, step: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            step: step.into(),
+        }
+    }
+
+    /// Returns a tagger for a sub-step, e.g. `bootstrap` → `bootstrap/llvm`.
+    pub fn child(&self, step: &str) -> Self {
+        Self::with_prefix(self.prefix.clone(), format!("{}/{}", self.step, step))
+    }
+
+    pub fn step(&self) -> &str {
+        &self.step
+    }
+
+    /// Formats a single line. Trailing `\r`/`\n` are stripped.
+    pub fn tag(&self, line: &str) -> String {
+        format!(
+            "{}[{}] {}",
+            self.prefix,
+            self.step,
+            line.trim_end_matches(['\r', '\n'])
+        )
+    }
+
+    /// Prints `line` tagged to stderr, or sends it to the subscriber as an
+    /// [`Event::Line`].
+    pub fn print(&self, line: &str) {
+        let event = Event::Line {
+            step: self.step.clone(),
+            line: line.trim_end_matches(['\r', '\n']).into(),
+        };
+        if !emit(event) {
+            eprintln!("{}", self.tag(line));
+        }
+    }
+
+    pub(crate) fn started(&self) {
+        emit(Event::Started {
+            step: self.step.clone(),
+        });
+    }
+
+    pub(crate) fn finished(&self, success: bool) {
+        emit(Event::Finished {
+            step: self.step.clone(),
+            success,
+        });
+    }
+
+    /// Copies `reader` to `writer` line by line, tagging every line. Output
+    /// that isn't valid UTF-8 is converted lossily rather than dropped. Each
+    /// line is written with a single `write_all` so lines from concurrent
+    /// forwarders sharing a locked writer don't tear. With a
+    /// [subscriber](subscribe()), lines go to it instead of `writer`.
+    pub fn forward(&self, reader: impl Read, writer: impl Write) -> io::Result<()> {
+        self.forward_with(reader, writer, |_| {})
+    }
+
+    /// Like [`forward()`](Self::forward) but also hands every untagged line
+    /// to `on_line`.
+    fn forward_with(
+        &self,
+        reader: impl Read,
+        mut writer: impl Write,
+        mut on_line: impl FnMut(&str),
+    ) -> io::Result<()> {
+        let mut reader = BufReader::new(reader);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            if reader.read_until(b'\n', &mut buf)? == 0 {
+                return Ok(());
+            }
+            let text = String::from_utf8_lossy(&buf);
+            on_line(&text);
+            let event = Event::Line {
+                step: self.step.clone(),
+                line: text.trim_end_matches(['\r', '\n']).into(),
+            };
+            if emit(event) {
+                continue;
+            }
+            let line = self.tag(&text) + "\n";
+            writer.write_all(line.as_bytes())?;
+            writer.flush()?;
+        }
+    }
+}
+
+/// Runs `cmd` to completion, forwarding both its stdout and stderr to our
+/// stderr through `tagger`. Build scripts must keep stdout free for `cargo:`
+/// directives, so nothing is ever forwarded to stdout. The child is started with
+/// [`process::spawn()`].
+pub fn run_tagged(cmd: &mut Command, tagger: &Tagger) -> io::Result<ExitStatus> {
+    run_tagged_with(cmd, tagger, |_| {})
+}
+
+/// Like [`run_tagged()`] but also hands every untagged line of stdout and
+/// stderr to `on_line` as it arrives.
+pub(crate) fn run_tagged_with(
+    cmd: &mut Command,
+    tagger: &Tagger,
+    on_line: impl Fn(&str) + Sync,
+) -> io::Result<ExitStatus> {
+    tagger.started();
+    let mut child = process::spawn(cmd.stdout(Stdio::piped()).stderr(Stdio::piped()))?;
+    let stdout = child.inner().stdout.take().expect("stdout is piped");
+    let stderr = child.inner().stderr.take().expect("stderr is piped");
+    let on_line = &on_line;
+    thread::scope(|scope| {
+        let out = scope.spawn(|| tagger.forward_with(stdout, io::stderr(), on_line));
+        let err = scope.spawn(|| tagger.forward_with(stderr, io::stderr(), on_line));
+        out.join().expect("stdout forwarder panicked")?;
+        err.join().expect("stderr forwarder panicked")
+    })?;
+    let status = child.wait()?;
+    tagger.finished(status.success());
+    Ok(status)
+}
+
+/// Like [`run_tagged()`] but also returns everything the command printed,
+/// untagged, with stdout and stderr lines in the order they arrived.
+pub fn run_tagged_captured(cmd: &mut Command, tagger: &Tagger) -> io::Result<(ExitStatus, String)> {
+    run_tagged_measured(cmd, tagger).map(|(status, output, _)| (status, output))
+}
+
+/// Like [`run_tagged_captured()`] but also returns what the child used.
+pub(crate) fn run_tagged_measured(
+    cmd: &mut Command,
+    tagger: &Tagger,
+) -> io::Result<(ExitStatus, String, process::Usage)> {
+    tagger.started();
+    let mut child = process::spawn(cmd.stdout(Stdio::piped()).stderr(Stdio::piped()))?;
+    let stdout = child.inner().stdout.take().expect("stdout is piped");
+    let stderr = child.inner().stderr.take().expect("stderr is piped");
+    let captured = Mutex::new(String::new());
+    let capture = |line: &str| {
+        let mut captured = captured.lock().unwrap();
+        captured.push_str(line.trim_end_matches(['\r', '\n']));
+        captured.push('\n');
+    };
+    thread::scope(|scope| {
+        let out = scope.spawn(|| tagger.forward_with(stdout, io::stderr(), capture));
+        let err = scope.spawn(|| tagger.forward_with(stderr, io::stderr(), capture));
+        out.join().expect("stdout forwarder panicked")?;
+        err.join().expect("stderr forwarder panicked")
+    })?;
+    let (status, usage) = child.wait_with_usage()?;
+    tagger.finished(status.success());
+    Ok((status, captured.into_inner().unwrap(), usage))
+}
@@ -0,0 +1,52 @@
+//! [`include_zig!`](crate::include_zig) and [`zig!`](crate::zig), behind the
+//! `macros` feature.
+
+/// Compiles a Zig file, relative to the crate's `Cargo.toml`, with the
+/// bundled toolchain while the crate compiles, links it in and declares its
+/// `export fn`s in an `extern "C"` block:
+///
+/// ```ignore
+/// // src/math.zig: export fn add(a: i32, b: i32) i32 { return a + b; }
+/// zig_rs::include_zig!("src/math.zig");
+///
+/// fn main() {
+///     assert_eq!(unsafe { add(2, 3) }, 5);
+/// }
+/// ```
+///
+/// Integers, floats, `bool`, the `c_*` types and pointers to them cross as
+/// their Rust equivalents; pointers to anything else become `c_void`
+/// pointers. Only the exports of the file itself are declared, not of the
+/// files it imports. The code is compiled for the crate's target like
+/// [`Build`](crate::Build) compiles `.zig` files, and compiled again when the
+/// file changes. The macro runs the bundled `zig` on the build machine, which
+/// is built for it also when cross-compiling.
+#[macro_export]
+macro_rules! include_zig {
+    ($path:literal) => {
+        $crate::__with_toolchain!(include_zig $path);
+    };
+}
+
+/// [`include_zig!`](crate::include_zig) for Zig written inline:
+///
+/// ```ignore
+/// zig_rs::zig! {
+///     const std = @import("std");
+///
+///     export fn checksum(data: [*]const u8, len: usize) u32 {
+///         return std.hash.Crc32.hash(data[0..len]);
+///     }
+/// }
+/// ```
+///
+/// The code has to be made of Rust tokens, which rules out Zig's `\\`
+/// multiline strings. `//` comments are dropped and doc comments kept.
+#[macro_export]
+macro_rules! zig {
+    ($($source:tt)*) => {
+        $crate::__with_toolchain!(zig $($source)*);
+    };
+}
+
+include!(concat!(env!("OUT_DIR"), "/macros.rs"));
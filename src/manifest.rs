@@ -0,0 +1,199 @@
+//! A hash manifest of an installed toolchain. The build script writes one next
+//! to the `zig` binary and `lib/` tree it installs, and [`crate::verify()`]
+//! checks the installation against it later. Partially synced caches and
+//! antivirus quarantines otherwise show up as baffling compile errors deep
+//! inside Zig's standard library.
+//!
+//! The format is the one `sha256sum` uses, so it can also be checked by hand
+//! with `sha256sum -c zig-rs.manifest` from the install directory.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+/// File name of the manifest inside the install directory.
+pub const FILE_NAME: &str = "zig-rs.manifest";
+
+/// Relative path ↦ lowercase hex SHA-256 digest.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    entries: BTreeMap<PathBuf, String>,
+}
+
+/// The result of checking an installation against its [`Manifest`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Verification {
+    /// Files listed in the manifest that no longer exist.
+    pub missing: Vec<PathBuf>,
+    /// Files whose contents no longer match the recorded digest.
+    pub modified: Vec<PathBuf>,
+}
+
+impl Verification {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.modified.is_empty()
+    }
+
+    /// All damaged files, missing ones first.
+    pub fn damaged(&self) -> impl Iterator<Item = &Path> {
+        self.missing
+            .iter()
+            .chain(&self.modified)
+            .map(PathBuf::as_path)
+    }
+}
+
+impl Manifest {
+    /// Hashes every file under each of `paths` (files or directories, relative
+    /// to `root`).
+    pub fn scan(root: &Path, paths: &[&str]) -> io::Result<Self> {
+        let mut manifest = Self::default();
+        for path in paths {
+            manifest.scan_path(root, Path::new(path))?;
+        }
+        Ok(manifest)
+    }
+
+    fn scan_path(&mut self, root: &Path, relative: &Path) -> io::Result<()> {
+        let path = root.join(relative);
+        if fs::metadata(&path)?.is_dir() {
+            for entry in fs_err::read_dir(&path)? {
+                self.scan_path(root, &relative.join(entry?.file_name()))?;
+            }
+        } else {
+            self.entries.insert(relative.to_owned(), hash_file(&path)?);
+        }
+        Ok(())
+    }
+
+    /// Hashes the file at `relative` under `root` again, e.g. after signing
+    /// it in place.
+    pub fn rehash(&mut self, root: &Path, relative: &Path) -> io::Result<()> {
+        self.entries
+            .insert(relative.to_owned(), hash_file(&root.join(relative))?);
+        Ok(())
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&Path, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_path(), v.as_str()))
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut contents = String::new();
+        for (relative, digest) in &self.entries {
+            // Forward slashes keep the manifest identical across hosts.
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            writeln!(contents, "{digest}  {relative}").expect("writing to a String");
+        }
+        fs_err::write(path, contents)
+    }
+
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let mut manifest = Self::default();
+        for line in fs_err::read_to_string(path)?.lines() {
+            let (digest, relative) = line.split_once("  ").ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: malformed line {line:?}", path.display()),
+                )
+            })?;
+            manifest
+                .entries
+                .insert(PathBuf::from(relative), digest.to_owned());
+        }
+        Ok(manifest)
+    }
+
+    /// Re-hashes every listed file under `root`.
+    pub fn verify(&self, root: &Path) -> io::Result<Verification> {
+        let mut verification = Verification::default();
+        for (relative, digest) in &self.entries {
+            match hash_file(&root.join(relative)) {
+                Ok(actual) if actual == *digest => {}
+                Ok(_) => verification.modified.push(relative.clone()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    verification.missing.push(relative.clone())
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(verification)
+    }
+
+    /// Copies every damaged file from `source`, a pristine copy of the same
+    /// installation, back into `root`. Files in `source` are checked against
+    /// the manifest before they are copied.
+    pub fn repair(
+        &self,
+        root: &Path,
+        verification: &Verification,
+        source: &Path,
+    ) -> io::Result<()> {
+        for relative in verification.damaged() {
+            let from = source.join(relative);
+            if hash_file(&from)? != self.entries[relative] {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} does not match the manifest either", from.display()),
+                ));
+            }
+            let to = root.join(relative);
+            if let Some(parent) = to.parent() {
+                fs_err::create_dir_all(parent)?;
+            }
+            fs_err::copy(&from, &to)?;
+        }
+        Ok(())
+    }
+
+    /// Copies every listed file from `root` into `dest`, checking each one
+    /// against its digest on the way, and writes the manifest into `dest` so
+    /// the copy can be verified later.
+    pub fn install(&self, root: &Path, dest: &Path) -> io::Result<()> {
+        for (relative, digest) in &self.entries {
+            let to = dest.join(relative);
+            if let Some(parent) = to.parent() {
+                fs_err::create_dir_all(parent)?;
+            }
+            fs_err::copy(root.join(relative), &to)?;
+            if hash_file(&to)? != *digest {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{} does not match the manifest",
+                        root.join(relative).display()
+                    ),
+                ));
+            }
+        }
+        self.write(&dest.join(FILE_NAME))
+    }
+}
+
+/// Returns the lowercase hex SHA-256 digest of a file.
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = fs_err::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        write!(s, "{b:02x}").expect("writing to a String");
+        s
+    })
+}
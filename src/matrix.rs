@@ -0,0 +1,371 @@
+//! Toolchains for many hosts at once, for platform teams that provision Zig
+//! for their organization instead of having every build bootstrap it. A
+//! [`Matrix`] fetches or builds the toolchain for each host it lists and
+//! packs it as [`pack_toolchain()`](crate::pack_toolchain) would, so builds
+//! on that host install it with `ZIG_RS_TOOLCHAIN_ARCHIVE` or after
+//! [`unpack_toolchain()`](crate::unpack_toolchain):
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! let index = zig_rs::matrix::Matrix::new()
+//!     .host("x86_64-unknown-linux-gnu")
+//!     .host("aarch64-apple-darwin")
+//!     .host("x86_64-unknown-illumos")
+//!     .build(Path::new("dist"))?;
+//! for host in &index.hosts {
+//!     println!("{} {}", host.target, host.archive);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Every toolchain is cross-built for its host: hosts ziglang.org publishes
+//! releases for get the official release, the rest (and all of them with
+//! [`Matrix::source()`]) are built with zig-bootstrap by this crate's build
+//! script, which needs the Rust target installed. The output is
+//!
+//! ```text
+//! <dest>/<rust-target>.tar.zst
+//! <dest>/zig-rs.matrix
+//! ```
+//!
+//! where `zig-rs.matrix` is the [`Index`] of what was built.
+
+use std::{
+    env,
+    error::Error,
+    fmt, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    PINNED_ZIG_VERSION, Toolchain,
+    bootstrap_target::{self, TargetCfg},
+    cache,
+    cancel::{self, Cancelled},
+    log::Tagger,
+    manifest,
+    pack::{self, Fingerprint},
+    process, prune, release,
+};
+
+/// File name of the [`Index`] in a matrix directory.
+pub const INDEX_FILE_NAME: &str = "zig-rs.matrix";
+
+/// The toolchains to build. See the [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct Matrix {
+    zig_version: String,
+    hosts: Vec<String>,
+    source: bool,
+}
+
+impl Matrix {
+    /// A matrix of [`PINNED_ZIG_VERSION`] toolchains for no hosts yet.
+    pub fn new() -> Self {
+        Self {
+            zig_version: PINNED_ZIG_VERSION.into(),
+            hosts: Vec::new(),
+            source: false,
+        }
+    }
+
+    pub fn zig_version(mut self, version: impl Into<String>) -> Self {
+        self.zig_version = version.into();
+        self
+    }
+
+    /// Adds the host, by rustc target, that a toolchain should run on.
+    pub fn host(mut self, rust_target: impl Into<String>) -> Self {
+        self.hosts.push(rust_target.into());
+        self
+    }
+
+    pub fn hosts<I, S>(mut self, rust_targets: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.hosts.extend(rust_targets.into_iter().map(Into::into));
+        self
+    }
+
+    /// Builds every toolchain from source, even for hosts with an official
+    /// release.
+    pub fn source(mut self, source: bool) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Packs a toolchain for every host into `dest` and writes the index,
+    /// which is returned. Toolchains are fetched and built through the shared
+    /// cache, so running it again only packs.
+    pub fn build(&self, dest: &Path) -> Result<Index, Box<dyn Error>> {
+        if self.hosts.is_empty() {
+            return Err("no hosts to build toolchains for".into());
+        }
+        fs_err::create_dir_all(dest)?;
+        let mut index = Index {
+            zig_version: self.zig_version.clone(),
+            hosts: Vec::new(),
+        };
+        for target in &self.hosts {
+            cancel::check().map_err(|_| {
+                Cancelled::with_progress(format!(
+                    "{}/{} toolchains packed",
+                    index.hosts.len(),
+                    self.hosts.len()
+                ))
+            })?;
+            let (root, fingerprint) = provision(target, &self.zig_version, self.source)?;
+            let archive = format!("{target}.tar.zst");
+            pack::pack(&root, fingerprint.clone(), &dest.join(&archive))?;
+            index.hosts.push(Host {
+                target: target.clone(),
+                sha256: manifest::hash_file(&dest.join(&archive))?,
+                archive,
+                zig_target: fingerprint.zig_target,
+                mcpu: fingerprint.mcpu,
+                bootstrap: fingerprint.bootstrap,
+            });
+        }
+        index.write(&dest.join(INDEX_FILE_NAME))?;
+        Ok(index)
+    }
+}
+
+impl Default for Matrix {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What a matrix directory holds: one line for the Zig version and one per
+/// host, `host <rust-target> <archive> <zig-target> <mcpu> <bootstrap>
+/// <sha256>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    pub zig_version: String,
+    pub hosts: Vec<Host>,
+}
+
+/// A packed toolchain in a matrix directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Host {
+    /// The rustc target of the host the toolchain runs on.
+    pub target: String,
+    /// The archive's file name, relative to the index.
+    pub archive: String,
+    pub zig_target: String,
+    pub mcpu: String,
+    /// How the toolchain was obtained, as in its
+    /// [`Fingerprint::bootstrap`].
+    pub bootstrap: String,
+    /// SHA-256 of the archive.
+    pub sha256: String,
+}
+
+impl Index {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let contents = fs_err::read_to_string(path)?;
+        let invalid = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {message}", path.display()),
+            )
+        };
+        let mut zig_version = None;
+        let mut hosts = Vec::new();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[..] {
+                ["zig_version", version] => zig_version = Some(version.to_owned()),
+                ["host", target, archive, zig_target, mcpu, bootstrap, sha256] => {
+                    hosts.push(Host {
+                        target: target.into(),
+                        archive: archive.into(),
+                        zig_target: zig_target.into(),
+                        mcpu: mcpu.into(),
+                        bootstrap: bootstrap.into(),
+                        sha256: sha256.into(),
+                    })
+                }
+                _ => return Err(invalid(format!("bad line {line:?}"))),
+            }
+        }
+        Ok(Self {
+            zig_version: zig_version.ok_or_else(|| invalid("no zig_version line".into()))?,
+            hosts,
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs_err::write(path, self.to_string())
+    }
+
+    /// The toolchain for the host `rust_target`.
+    pub fn host(&self, rust_target: &str) -> Option<&Host> {
+        self.hosts.iter().find(|host| host.target == rust_target)
+    }
+
+    /// The hosts in the matrix directory `dir` whose archive is missing or
+    /// doesn't have its digest.
+    pub fn damaged(&self, dir: &Path) -> io::Result<Vec<&Host>> {
+        let mut damaged = Vec::new();
+        for host in &self.hosts {
+            let archive = dir.join(&host.archive);
+            if !archive.is_file() || manifest::hash_file(&archive)? != host.sha256 {
+                damaged.push(host);
+            }
+        }
+        Ok(damaged)
+    }
+}
+
+impl fmt::Display for Index {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "zig_version {}", self.zig_version)?;
+        for host in &self.hosts {
+            writeln!(
+                f,
+                "host {} {} {} {} {} {}",
+                host.target, host.archive, host.zig_target, host.mcpu, host.bootstrap, host.sha256
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// What `rustc --print cfg` says about `rust_target`.
+fn rustc_cfg(rust_target: &str) -> Result<String, Box<dyn Error>> {
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let output = process::output(
+        Command::new(rustc)
+            .arg("--print")
+            .arg("cfg")
+            .arg("--target")
+            .arg(rust_target),
+    )?;
+    if !output.status.success() {
+        return Err(format!(
+            "rustc doesn't know the target {rust_target}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Fetches or builds the toolchain that runs on the host `rust_target`, as
+/// [`Matrix::build()`] does for each host, and returns where it's installed
+/// with its fingerprint. The fingerprint's manifest digest is filled in when
+/// it's packed.
+pub fn provision(
+    rust_target: &str,
+    zig_version: &str,
+    source: bool,
+) -> Result<(PathBuf, Fingerprint), Box<dyn Error>> {
+    let tagger = Tagger::new("matrix");
+    let cfg = TargetCfg::from_print_cfg(rust_target, &rustc_cfg(rust_target)?);
+    let (zig_target, mcpu) = bootstrap_target::zig_target_mcpu(&cfg)?;
+    match release::target_platform(rust_target) {
+        Some((arch, os)) if !source => {
+            eprintln!(
+                "{}",
+                tagger.tag(&format!("{rust_target}: fetching the {arch}-{os} release"))
+            );
+            let toolchain = Toolchain::release(zig_version, arch, os)?;
+            let fingerprint = Fingerprint {
+                zig_version: zig_version.into(),
+                zig_target,
+                mcpu,
+                revision: pack::REVISION,
+                bootstrap: "prebuilt".into(),
+                lib_profile: prune::FULL.into(),
+                manifest: String::new(),
+            };
+            Ok((toolchain.root().to_owned(), fingerprint))
+        }
+        _ => {
+            eprintln!(
+                "{}",
+                tagger.tag(&format!("{rust_target}: building {zig_target} {mcpu}"))
+            );
+            let root = cache::toolchains_dir()
+                .ok_or("no cache directory; set ZIG_RS_CACHE_DIR")?
+                .join(zig_version)
+                .join(format!("{zig_target}-{mcpu}"));
+            run_build_script(
+                rust_target,
+                &[
+                    ("ZIG_RS_ZIG_VERSION", zig_version),
+                    ("ZIG_RS_TOOLCHAIN_ORDER", "source"),
+                ],
+            )?;
+            let fingerprint = Fingerprint::read(&root.join(pack::FINGERPRINT_FILE_NAME))?;
+            Ok((root, fingerprint))
+        }
+    }
+}
+
+/// Runs this crate's build script for `rust_target`, with the environment
+/// variables `vars` on top of the process's, by checking a throwaway crate
+/// that depends on this one with the `target-toolchain` feature. That's how
+/// toolchains get into the shared cache without a crate to build; the Rust
+/// target has to be installed.
+pub fn run_build_script(rust_target: &str, vars: &[(&str, &str)]) -> Result<(), Box<dyn Error>> {
+    let dir = env::temp_dir().join(format!("zig-rs-build-script-{}", std::process::id()));
+    fs_err::create_dir_all(dir.join("src"))?;
+    check_throwaway_crate(&dir, rust_target, vars).map_err(|e| {
+        if cancel::is_cancelled(&*e) {
+            return e;
+        }
+        // Kept for the bootstrap log the build script points at.
+        format!("{e}; the throwaway crate is kept in {}", dir.display()).into()
+    })?;
+    let _ = fs_err::remove_dir_all(&dir);
+    Ok(())
+}
+
+fn check_throwaway_crate(
+    dir: &Path,
+    rust_target: &str,
+    vars: &[(&str, &str)],
+) -> Result<(), Box<dyn Error>> {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // The sources may be gone since this was built, e.g. by `cargo install`.
+    // The target's toolchain is the one asked for; the host's is installed
+    // too, as it is for every build.
+    let dependency = if manifest_dir.join("Cargo.toml").is_file() {
+        format!("path = {:?}", manifest_dir)
+    } else {
+        format!("version = \"={}\"", env!("CARGO_PKG_VERSION"))
+    };
+    fs_err::write(
+        dir.join("Cargo.toml"),
+        format!(
+            "[package]\nname = \"zig-rs-build-script\"\nversion = \"0.0.0\"\nedition = \"2024\"\n\n[dependencies]\nzig = {{ {dependency}, features = [\"target-toolchain\"] }}\n\n[workspace]\n"
+        ),
+    )?;
+    fs_err::write(dir.join("src").join("lib.rs"), "")?;
+    // Resolve to the versions this was built with where possible.
+    let lockfile = manifest_dir.join("Cargo.lock");
+    if lockfile.is_file() {
+        fs_err::copy(&lockfile, dir.join("Cargo.lock"))?;
+    }
+    let cargo = env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let mut cmd = Command::new(cargo);
+    cmd.arg("check")
+        .arg("--target")
+        .arg(rust_target)
+        .current_dir(dir)
+        // Without it the build script only writes placeholders.
+        .env("DO_IT", "1")
+        .envs(vars.iter().copied());
+    let status = process::status(&mut cmd)?;
+    if !status.success() {
+        return Err(format!("building the toolchain for {rust_target} failed: {status}").into());
+    }
+    Ok(())
+}
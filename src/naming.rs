@@ -0,0 +1,174 @@
+//! File names of versioned shared libraries as each platform spells them, so
+//! packaging doesn't rename libraries and patch their sonames afterwards.
+//! [`Build::shared_library()`](crate::Build::shared_library) links one with
+//! the name and soname from here and creates its symlinks:
+//!
+//! ```
+//! use zig_rs::naming::{SharedLibrary, Versioning};
+//!
+//! let lib = SharedLibrary::versioned("foo", "1.2.3");
+//! let linux = "x86_64-unknown-linux-gnu";
+//! assert_eq!(lib.file_name(linux), "libfoo.so.1.2.3");
+//! assert_eq!(lib.soname(linux).as_deref(), Some("libfoo.so.1"));
+//! assert_eq!(
+//!     lib.links(linux),
+//!     [("libfoo.so.1".to_owned(), "libfoo.so.1.2.3".to_owned()),
+//!      ("libfoo.so".to_owned(), "libfoo.so.1".to_owned())]
+//! );
+//! assert_eq!(lib.file_name("aarch64-apple-darwin"), "libfoo.1.2.3.dylib");
+//! assert_eq!(lib.file_name("x86_64-pc-windows-msvc"), "foo-1.2.3.dll");
+//! assert_eq!(lib.import_library("x86_64-pc-windows-msvc").as_deref(), Some("foo.lib"));
+//!
+//! let release = lib.versioning(Versioning::Release);
+//! assert_eq!(release.file_name(linux), "libfoo-1.2.3.so");
+//! assert_eq!(release.links(linux), [("libfoo.so".to_owned(), "libfoo-1.2.3.so".to_owned())]);
+//! ```
+//!
+//! Windows has no sonames or symlinks; the version is only in the DLL's
+//! name, and the import library keeps the plain name so `-lfoo` finds it.
+
+/// Where the version goes in a shared library's name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Versioning {
+    /// After the suffix, `libfoo.so.1.2.3` with the soname `libfoo.so.1`
+    /// (`libfoo.1.2.3.dylib` on Apple): binaries linked against it keep
+    /// working across minor versions.
+    #[default]
+    Soversion,
+    /// Before the suffix, `libfoo-1.2.3.so`, which is also the soname, as
+    /// libtool's `-release` spells it: every version is incompatible with
+    /// the others.
+    Release,
+}
+
+/// The name and version of a shared library.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedLibrary {
+    pub name: String,
+    /// `1.2.3`, or `None` for an unversioned library.
+    pub version: Option<String>,
+    pub versioning: Versioning,
+}
+
+impl SharedLibrary {
+    /// An unversioned library, `libfoo.so`, `libfoo.dylib` or `foo.dll`.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            version: None,
+            versioning: Versioning::default(),
+        }
+    }
+
+    pub fn versioned(name: &str, version: &str) -> Self {
+        Self {
+            version: Some(version.into()),
+            ..Self::new(name)
+        }
+    }
+
+    pub fn versioning(mut self, versioning: Versioning) -> Self {
+        self.versioning = versioning;
+        self
+    }
+
+    /// The name of the file the linker writes for `target`.
+    pub fn file_name(&self, target: &str) -> String {
+        self.name_with(target, self.version.as_deref())
+    }
+
+    /// What binaries linked against the library record to load it:
+    /// `DT_SONAME` on ELF, where it names the versioned link, and the
+    /// install name on Apple, `@rpath/`-relative. `None` on Windows.
+    pub fn soname(&self, target: &str) -> Option<String> {
+        if target.contains("-windows") {
+            return None;
+        }
+        let name = match self.versioning {
+            Versioning::Soversion => self.name_with(target, self.major()),
+            Versioning::Release => self.file_name(target),
+        };
+        Some(if target.contains("-apple-") {
+            format!("@rpath/{name}")
+        } else {
+            name
+        })
+    }
+
+    /// The symlinks next to the library, `(link, what it points to)`, most
+    /// specific first: `libfoo.so.1 -> libfoo.so.1.2.3` for the loader and
+    /// `libfoo.so -> libfoo.so.1` for `-lfoo`. Empty on Windows and for
+    /// unversioned libraries.
+    pub fn links(&self, target: &str) -> Vec<(String, String)> {
+        if target.contains("-windows") || self.version.is_none() {
+            return Vec::new();
+        }
+        let file_name = self.file_name(target);
+        let plain = self.name_with(target, None);
+        match (self.versioning, self.major()) {
+            (Versioning::Soversion, Some(major)) if Some(major) != self.version.as_deref() => {
+                let soname = self.name_with(target, Some(major));
+                vec![(soname.clone(), file_name), (plain, soname)]
+            }
+            _ => vec![(plain, file_name)],
+        }
+    }
+
+    /// The import library the linker writes next to the DLL, named without
+    /// the version so `-lfoo` finds it: `foo.lib`, or `libfoo.dll.a` for
+    /// MinGW. `None` off Windows.
+    pub fn import_library(&self, target: &str) -> Option<String> {
+        if !target.contains("-windows") {
+            return None;
+        }
+        Some(if target.ends_with("-msvc") {
+            format!("{}.lib", self.name)
+        } else {
+            format!("lib{}.dll.a", self.name)
+        })
+    }
+
+    /// Flags for `zig cc -shared` that give the library its
+    /// [soname](Self::soname), and on Apple its current and compatibility
+    /// versions.
+    pub fn link_args(&self, target: &str) -> Vec<String> {
+        let Some(soname) = self.soname(target) else {
+            return Vec::new();
+        };
+        if !target.contains("-apple-") {
+            return vec![format!("-Wl,-soname,{soname}")];
+        }
+        let mut args = vec![format!("-Wl,-install_name,{soname}")];
+        if let (Some(version), Some(major)) = (&self.version, self.major()) {
+            args.push(format!("-Wl,-current_version,{version}"));
+            args.push(format!("-Wl,-compatibility_version,{major}"));
+        }
+        args
+    }
+
+    /// The first component of the version.
+    fn major(&self) -> Option<&str> {
+        let version = self.version.as_deref()?;
+        version.split('.').next()
+    }
+
+    fn name_with(&self, target: &str, version: Option<&str>) -> String {
+        let name = &self.name;
+        match (version, self.versioning) {
+            _ if target.contains("-windows") => match version {
+                Some(version) => format!("{name}-{version}.dll"),
+                None => format!("{name}.dll"),
+            },
+            (None, _) if target.contains("-apple-") => format!("lib{name}.dylib"),
+            (None, _) => format!("lib{name}.so"),
+            (Some(version), Versioning::Soversion) if target.contains("-apple-") => {
+                format!("lib{name}.{version}.dylib")
+            }
+            (Some(version), Versioning::Soversion) => format!("lib{name}.so.{version}"),
+            (Some(version), Versioning::Release) if target.contains("-apple-") => {
+                format!("lib{name}-{version}.dylib")
+            }
+            (Some(version), Versioning::Release) => format!("lib{name}-{version}.so"),
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Checks that Mach-O executables and dylibs built here can go through
+//! Apple's notary service once the app embedding them signs them with its
+//! Developer ID and the hardened runtime (`codesign --options runtime`).
+//! Notarization rejects binaries without an `LC_UUID` or built against an
+//! SDK older than macOS 10.9, and `codesign` can only sign a file with room
+//! for its load command and `__LINKEDIT` at the end.
+//!
+//! [`ZigExe::notarizable()`](crate::zig_exe::ZigExe::notarizable) links
+//! with that room and checks the result; entitlements from
+//! [`ZigExe::entitlements()`](crate::zig_exe::ZigExe::entitlements) go into
+//! the signature and survive the ad-hoc re-signing after
+//! [`postprocess`](crate::postprocess):
+//!
+//! ```no_run
+//! let exe = zig_rs::zig_exe::ZigExe::new("helper", "zig/main.zig")
+//!     .target("aarch64-apple-darwin")
+//!     .entitlements("macos/helper.entitlements")
+//!     .notarizable(true)
+//!     .build()?;
+//! for problem in zig_rs::notarize::check(&exe)? {
+//!     println!("cargo:warning={problem}");
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{io, path::Path};
+
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+const MH_MAGIC: u32 = 0xfeed_face;
+const FAT_MAGIC: u32 = 0xcafe_babe;
+const MH_EXECUTE: u32 = 2;
+const MH_DYLIB: u32 = 6;
+const MH_BUNDLE: u32 = 8;
+const LC_SEGMENT_64: u32 = 0x19;
+const LC_UUID: u32 = 0x1b;
+const LC_CODE_SIGNATURE: u32 = 0x1d;
+const LC_VERSION_MIN_MACOSX: u32 = 0x24;
+const LC_BUILD_VERSION: u32 = 0x32;
+const PLATFORM_MACOS: u32 = 1;
+/// macOS 10.9, encoded like the versions in the load commands.
+const MIN_SDK: u32 = 0x000a_0900;
+
+/// What would keep the Mach-O executable, dylib or bundle at `path`, thin or
+/// fat, from being signed and notarized. Empty when there is nothing.
+pub fn check(path: &Path) -> io::Result<Vec<String>> {
+    let data = fs_err::read(path)?;
+    let be = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+    };
+    if be(0) != Some(FAT_MAGIC) {
+        return Ok(check_slice(&data));
+    }
+    let mut problems = Vec::new();
+    for i in 0..be(4).unwrap_or(0) as usize {
+        let arch = 8 + i * 20;
+        let (Some(cputype), Some(offset), Some(size)) = (be(arch), be(arch + 8), be(arch + 12))
+        else {
+            problems.push("truncated fat header".into());
+            break;
+        };
+        let Some(slice) = data.get(offset as usize..offset as usize + size as usize) else {
+            problems.push(format!("{} slice is truncated", arch_name(cputype)));
+            continue;
+        };
+        for problem in check_slice(slice) {
+            problems.push(format!("{} slice: {problem}", arch_name(cputype)));
+        }
+    }
+    Ok(problems)
+}
+
+fn check_slice(data: &[u8]) -> Vec<String> {
+    match read_u32(data, 0) {
+        Some(MH_MAGIC_64) => {}
+        Some(MH_MAGIC) => return vec!["32-bit Mach-O files can't be notarized".into()],
+        _ => return vec!["not a Mach-O file".into()],
+    }
+    let (Some(filetype), Some(ncmds), Some(sizeofcmds)) =
+        (read_u32(data, 12), read_u32(data, 16), read_u32(data, 20))
+    else {
+        return vec!["truncated Mach-O header".into()];
+    };
+    if !matches!(filetype, MH_EXECUTE | MH_DYLIB | MH_BUNDLE) {
+        return vec!["not an executable, dylib or bundle".into()];
+    }
+    let mut problems = Vec::new();
+    let mut uuid = None;
+    let mut sdk = None;
+    let mut signed = false;
+    let mut linkedit_end = None;
+    let mut first_section = usize::MAX;
+    let mut offset = 32;
+    for _ in 0..ncmds {
+        let (Some(cmd), Some(cmdsize)) = (read_u32(data, offset), read_u32(data, offset + 4))
+        else {
+            return vec!["truncated load commands".into()];
+        };
+        match cmd {
+            LC_UUID => uuid = data.get(offset + 8..offset + 24),
+            LC_BUILD_VERSION if read_u32(data, offset + 8) == Some(PLATFORM_MACOS) => {
+                sdk = read_u32(data, offset + 16);
+            }
+            LC_VERSION_MIN_MACOSX => sdk = read_u32(data, offset + 12),
+            LC_CODE_SIGNATURE => signed = true,
+            LC_SEGMENT_64 => {
+                let nsects = read_u32(data, offset + 64).unwrap_or(0) as usize;
+                for i in 0..nsects {
+                    let section_offset = read_u32(data, offset + 72 + i * 80 + 48).unwrap_or(0);
+                    if section_offset != 0 {
+                        first_section = first_section.min(section_offset as usize);
+                    }
+                }
+                if data
+                    .get(offset + 8..offset + 24)
+                    .is_some_and(|name| name.starts_with(b"__LINKEDIT\0"))
+                {
+                    linkedit_end = read_u64(data, offset + 40)
+                        .zip(read_u64(data, offset + 48))
+                        .map(|(fileoff, filesize)| fileoff + filesize);
+                }
+            }
+            _ => {}
+        }
+        offset += cmdsize as usize;
+    }
+    match uuid {
+        None => problems.push("no LC_UUID load command".into()),
+        Some(uuid) if uuid.iter().all(|&b| b == 0) => problems.push("the LC_UUID is zero".into()),
+        Some(_) => {}
+    }
+    if let Some(sdk) = sdk
+        && sdk < MIN_SDK
+    {
+        problems.push(format!(
+            "built against the macOS {} SDK, older than 10.9",
+            version(sdk)
+        ));
+    }
+    if !signed && 32 + sizeofcmds as usize + 16 > first_section {
+        problems.push(
+            "no room after the load commands for LC_CODE_SIGNATURE; link with -headerpad_max_install_names".into(),
+        );
+    }
+    if linkedit_end != Some(data.len() as u64) {
+        problems.push("__LINKEDIT isn't at the end of the file".into());
+    }
+    problems
+}
+
+fn arch_name(cputype: u32) -> String {
+    match cputype {
+        0x0100_0007 => "x86_64".into(),
+        0x0100_000c => "arm64".into(),
+        _ => format!("CPU type {cputype:#x}"),
+    }
+}
+
+/// A version encoded as `xxxx.yy.zz` in nibbles.
+fn version(encoded: u32) -> String {
+    format!(
+        "{}.{}.{}",
+        encoded >> 16,
+        (encoded >> 8) & 0xff,
+        encoded & 0xff
+    )
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
@@ -0,0 +1,254 @@
+//! Relocatable toolchain archives for CI caches. Bootstrapping Zig from source
+//! takes a long time, so pipelines pack the toolchain a job built with
+//! [`crate::pack_toolchain()`], store the file with their native cache action
+//! and restore it in later jobs. Pointing `ZIG_RS_TOOLCHAIN_ARCHIVE` at the
+//! restored file makes the build script unpack it instead of building again;
+//! [`crate::unpack_toolchain()`] unpacks it into the shared cache.
+//!
+//! An archive is a `.tar.zst` with a single `zig-rs-toolchain/` directory
+//! holding `zig`, `lib/`, the hash [manifest](crate::manifest) and a
+//! [`Fingerprint`] saying what the toolchain is.
+//!
+//! For builder images, [`pack_layer()`] writes the toolchain as a plain tar
+//! laid out as the shared cache is, to be unpacked at the root of an image
+//! (an OCI layer, or Docker's `ADD`). Builds with `ZIG_RS_CACHE_DIR`
+//! pointing at the prefix then install it from the cache and provision
+//! nothing; the `zig-rs-package-toolchain` binary does both steps.
+
+use std::{
+    error::Error,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    archive::{self, Format},
+    manifest::{self, Manifest},
+    prune,
+};
+
+/// File name of the fingerprint inside the install directory and archive.
+pub const FINGERPRINT_FILE_NAME: &str = "zig-rs.fingerprint";
+
+const ROOT_DIR: &str = "zig-rs-toolchain";
+
+/// Environment variable the build script checks for an archive to reuse.
+pub const ARCHIVE_ENV: &str = "ZIG_RS_TOOLCHAIN_ARCHIVE";
+
+/// The revision of how this crate builds toolchains, which goes into every
+/// [`Fingerprint`].
+///
+/// Toolchains in archives and the shared cache are keyed by the Zig version
+/// they are, not the version of the crate that built them, so every zig-rs
+/// release pinning the same Zig release (the same `major.minor.patch`, told
+/// apart by pre-release or build metadata) shares one toolchain per target
+/// and CPU instead of bootstrapping its own. That holds as long as they
+/// build the same thing: a release that changes what goes into a toolchain
+/// (zig-bootstrap flags or patches, the installed layout) bumps this, and
+/// toolchains of another revision are then built again rather than reused.
+pub const REVISION: u32 = 1;
+
+/// What a packed toolchain is. Archives are only reused for the same Zig
+/// version, target, CPU and [`REVISION`]; the manifest digest ties the
+/// fingerprint to the exact files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub zig_version: String,
+    pub zig_target: String,
+    pub mcpu: String,
+    /// The [`REVISION`] of the crate that built the toolchain. Fingerprints
+    /// from before revisions were recorded read as revision 1.
+    pub revision: u32,
+    /// How the packed toolchain was originally obtained, as printed by
+    /// [`Bootstrap`](crate::build_info::Bootstrap).
+    pub bootstrap: String,
+    /// The [profiles](crate::prune) `lib/` was pruned with, as
+    /// [`prune::profiles_name()`] writes them. Fingerprints from before
+    /// pruning read as [`prune::FULL`].
+    pub lib_profile: String,
+    /// SHA-256 of the manifest file.
+    pub manifest: String,
+}
+
+impl Fingerprint {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let contents = fs_err::read_to_string(path)?;
+        let field = |key: &str| {
+            contents
+                .lines()
+                .find_map(|line| line.strip_prefix(key)?.strip_prefix(' '))
+                .map(str::to_owned)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}: no {key} line", path.display()),
+                    )
+                })
+        };
+        Ok(Self {
+            zig_version: field("zig_version")?,
+            zig_target: field("zig_target")?,
+            mcpu: field("mcpu")?,
+            revision: field("revision").map_or(Ok(1), |revision| {
+                revision.parse().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}: bad revision {revision:?}", path.display()),
+                    )
+                })
+            })?,
+            bootstrap: field("bootstrap")?,
+            lib_profile: field("lib_profile").unwrap_or_else(|_| prune::FULL.into()),
+            manifest: field("manifest")?,
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs_err::write(path, self.to_string())
+    }
+
+    /// Whether a toolchain with this fingerprint can stand in for one of
+    /// `zig_version` built for `zig_target` and `mcpu` by this crate's
+    /// [`REVISION`]. A `+commit` suffix on the packed version is ignored.
+    pub fn matches(&self, zig_version: &str, zig_target: &str, mcpu: &str) -> bool {
+        let packed = self
+            .zig_version
+            .split_once('+')
+            .map_or(self.zig_version.as_str(), |(version, _)| version);
+        packed == zig_version
+            && self.zig_target == zig_target
+            && self.mcpu == mcpu
+            && self.revision == REVISION
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "zig_version {}", self.zig_version)?;
+        writeln!(f, "zig_target {}", self.zig_target)?;
+        writeln!(f, "mcpu {}", self.mcpu)?;
+        writeln!(f, "revision {}", self.revision)?;
+        writeln!(f, "bootstrap {}", self.bootstrap)?;
+        writeln!(f, "lib_profile {}", self.lib_profile)?;
+        writeln!(f, "manifest {}", self.manifest)
+    }
+}
+
+/// Packs the toolchain installed at `root` (which must have a manifest) into
+/// `dest`. `fingerprint.manifest` is filled in from the manifest on disk.
+pub fn pack(root: &Path, mut fingerprint: Fingerprint, dest: &Path) -> io::Result<()> {
+    let manifest_path = root.join(manifest::FILE_NAME);
+    let manifest = Manifest::read(&manifest_path)?;
+    fingerprint.manifest = manifest::hash_file(&manifest_path)?;
+
+    if let Some(parent) = dest.parent() {
+        fs_err::create_dir_all(parent)?;
+    }
+    let partial = dest.with_extension("partial");
+    let file = fs_err::File::create(&partial)?;
+    let mut builder = tar::Builder::new(zstd::Encoder::new(file, 0)?);
+    for (relative, _) in manifest.entries() {
+        builder.append_path_with_name(root.join(relative), Path::new(ROOT_DIR).join(relative))?;
+    }
+    builder.append_path_with_name(
+        &manifest_path,
+        Path::new(ROOT_DIR).join(manifest::FILE_NAME),
+    )?;
+    let fingerprint = fingerprint.to_string();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(fingerprint.len() as u64);
+    header.set_mode(0o644);
+    builder.append_data(
+        &mut header,
+        Path::new(ROOT_DIR).join(FINGERPRINT_FILE_NAME),
+        fingerprint.as_bytes(),
+    )?;
+    builder.into_inner()?.finish()?;
+    fs_err::rename(&partial, dest)
+}
+
+/// Writes the toolchain installed at `root` (which must have a manifest) into
+/// the tar `dest`, under the directory of the shared cache at `prefix` it's
+/// looked up in:
+///
+/// ```text
+/// <prefix>/toolchains/<zig version>/<zig target>-<mcpu>/
+/// ```
+///
+/// `prefix` is relative to the root of the image, e.g. `opt/zig-rs` for
+/// `ZIG_RS_CACHE_DIR=/opt/zig-rs`. Entries are sorted, with fixed times and
+/// no owners, so the same toolchain always makes the same layer digest.
+/// Returns the toolchain's directory inside the layer.
+pub fn pack_layer(
+    root: &Path,
+    mut fingerprint: Fingerprint,
+    prefix: &Path,
+    dest: &Path,
+) -> io::Result<PathBuf> {
+    let manifest_path = root.join(manifest::FILE_NAME);
+    let manifest = Manifest::read(&manifest_path)?;
+    fingerprint.manifest = manifest::hash_file(&manifest_path)?;
+    let dir = prefix
+        .join("toolchains")
+        .join(&fingerprint.zig_version)
+        .join(format!("{}-{}", fingerprint.zig_target, fingerprint.mcpu));
+
+    let mut files: Vec<PathBuf> = manifest.entries().map(|(path, _)| path.into()).collect();
+    files.push(manifest::FILE_NAME.into());
+    let mut dirs: Vec<PathBuf> = dir.ancestors().map(Path::to_owned).collect();
+    for file in &files {
+        dirs.extend(file.ancestors().skip(1).map(|parent| dir.join(parent)));
+    }
+    dirs.retain(|dir| !dir.as_os_str().is_empty());
+    dirs.sort();
+    dirs.dedup();
+
+    if let Some(parent) = dest.parent() {
+        fs_err::create_dir_all(parent)?;
+    }
+    let partial = dest.with_extension("partial");
+    let mut builder = tar::Builder::new(fs_err::File::create(&partial)?);
+    builder.mode(tar::HeaderMode::Deterministic);
+    for path in &dirs {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_mode(0o755);
+        header.set_size(0);
+        builder.append_data(&mut header, path, io::empty())?;
+    }
+    for file in &files {
+        builder.append_path_with_name(root.join(file), dir.join(file))?;
+    }
+    let fingerprint = fingerprint.to_string();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(fingerprint.len() as u64);
+    header.set_mode(0o644);
+    builder.append_data(
+        &mut header,
+        dir.join(FINGERPRINT_FILE_NAME),
+        fingerprint.as_bytes(),
+    )?;
+    builder.into_inner()?;
+    fs_err::rename(&partial, dest)?;
+    Ok(dir)
+}
+
+/// Extracts an archive written by [`pack()`] into `dest` and checks the files
+/// against the packed manifest. Returns the archive's fingerprint.
+pub fn unpack(src: &Path, dest: &Path) -> Result<Fingerprint, Box<dyn Error>> {
+    archive::extract(src, Format::TarZst, dest)?;
+    let fingerprint = Fingerprint::read(&dest.join(FINGERPRINT_FILE_NAME))?;
+    let manifest_path = dest.join(manifest::FILE_NAME);
+    if manifest::hash_file(&manifest_path)? != fingerprint.manifest {
+        return Err(format!("{}: manifest does not match the fingerprint", src.display()).into());
+    }
+    let verification = Manifest::read(&manifest_path)?.verify(dest)?;
+    if !verification.is_ok() {
+        let damaged: Vec<_> = verification
+            .damaged()
+            .map(|path| path.display().to_string())
+            .collect();
+        return Err(format!("{}: damaged files: {}", src.display(), damaged.join(", ")).into());
+    }
+    Ok(fingerprint)
+}
@@ -0,0 +1,170 @@
+//! `objcopy` and `strip` for build outputs, and splitting their debug info
+//! into separate files. Rewriting a Mach-O executable or dylib invalidates
+//! its code signature, and arm64 macOS kills processes whose signature
+//! doesn't match, so for Apple targets these re-sign their output ad hoc:
+//! with `codesign` on macOS hosts and with an in-crate signer everywhere
+//! else.
+
+use std::{
+    env,
+    error::Error,
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{Toolchain, error::command_line, process};
+
+mod macho;
+
+/// Environment variable naming the `strip` to use for Mach-O files, which
+/// `zig objcopy` can't rewrite. Defaults to `llvm-strip`, then `strip`.
+pub const STRIP_ENV: &str = "STRIP";
+
+/// Environment variable naming the `dsymutil` to use for Apple targets.
+/// Defaults to `dsymutil`, then `llvm-dsymutil`.
+pub const DSYMUTIL_ENV: &str = "DSYMUTIL";
+
+/// Runs `zig objcopy <args> <input> <output>` and re-signs `output` when the
+/// rustc target `target` is an Apple one.
+pub fn objcopy<S: AsRef<OsStr>>(
+    toolchain: &Toolchain,
+    target: &str,
+    args: impl IntoIterator<Item = S>,
+    input: &Path,
+    output: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut cmd = toolchain.command();
+    cmd.arg("objcopy").args(args).arg(input).arg(output);
+    run(&mut cmd)?;
+    resign(target, output)
+}
+
+/// Strips symbols and debug info from `path` in place: with `zig objcopy
+/// --strip-all` for ELF and the like, and with [`STRIP_ENV`] for Mach-O
+/// files, which are then re-signed.
+pub fn strip(toolchain: &Toolchain, target: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+    let data = fs_err::read(path)?;
+    if !macho::is_macho(&data) {
+        return objcopy(toolchain, target, ["--strip-all"], path, path);
+    }
+    let strip = env::var_os(STRIP_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            let llvm_strip = process::status(Command::new("llvm-strip").arg("--version"));
+            if llvm_strip.is_ok_and(|status| status.success()) {
+                "llvm-strip".into()
+            } else {
+                "strip".into()
+            }
+        });
+    // `-x` keeps the dynamic symbols a dylib exports.
+    run(Command::new(strip).arg("-x").arg(path))?;
+    resign(target, path)
+}
+
+/// Moves the debug info of the executable or shared library at `path`,
+/// built for the rustc target `target`, into a separate file the way the
+/// target's debuggers look for it, and returns that file:
+///
+/// - ELF: `<path>.debug`, with `zig objcopy --only-keep-debug`; `path` gets
+///   a `.gnu_debuglink` to it and loses its debug sections.
+/// - Apple: the `<path>.dSYM` bundle, with [`DSYMUTIL_ENV`]; `path` is then
+///   [stripped](strip()).
+/// - Windows and UEFI: the `.pdb` Zig writes next to `path`, which is left
+///   alone.
+///
+/// Gives `None` when there's nothing to split, e.g. for wasm or a binary
+/// built without debug info.
+pub fn split_debug_info(
+    toolchain: &Toolchain,
+    target: &str,
+    path: &Path,
+) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    if target.contains("-windows") || target.contains("-uefi") {
+        let pdb = path.with_extension("pdb");
+        return Ok(pdb.is_file().then_some(pdb));
+    }
+    let mut file_name = path.file_name().unwrap_or_default().to_owned();
+    if target.contains("-apple-") {
+        file_name.push(".dSYM");
+        let dsym = path.with_file_name(file_name);
+        let dsymutil = env::var_os(DSYMUTIL_ENV)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                let dsymutil = process::status(Command::new("dsymutil").arg("--version"));
+                if dsymutil.is_ok_and(|status| status.success()) {
+                    "dsymutil".into()
+                } else {
+                    "llvm-dsymutil".into()
+                }
+            });
+        run(Command::new(dsymutil).arg(path).arg("-o").arg(&dsym))?;
+        strip(toolchain, target, path)?;
+        return Ok(dsym.is_dir().then_some(dsym));
+    }
+    if !fs_err::read(path)?.starts_with(b"\x7fELF") {
+        return Ok(None);
+    }
+    file_name.push(".debug");
+    let debug = path.with_file_name(file_name);
+    objcopy(toolchain, target, ["--only-keep-debug"], path, &debug)?;
+    let mut debuglink = OsString::from("--add-gnu-debuglink=");
+    debuglink.push(&debug);
+    objcopy(
+        toolchain,
+        target,
+        [OsString::from("--strip-debug"), debuglink],
+        path,
+        path,
+    )?;
+    Ok(Some(debug))
+}
+
+/// Signs the Mach-O executable, dylib or bundle at `path` ad hoc, replacing
+/// any signature it has but keeping its entitlements. Other files are left
+/// alone; returns whether `path` was signed.
+pub fn adhoc_sign(path: &Path) -> Result<bool, Box<dyn Error>> {
+    if !macho::is_signable(&fs_err::read(path)?) {
+        return Ok(false);
+    }
+    if cfg!(target_os = "macos") {
+        let status = process::status(
+            Command::new("codesign")
+                .args([
+                    "--sign",
+                    "-",
+                    "--force",
+                    "--preserve-metadata=entitlements,flags,runtime",
+                ])
+                .arg(path),
+        );
+        if status.is_ok_and(|status| status.success()) {
+            return Ok(true);
+        }
+    }
+    macho::sign(path)?;
+    Ok(true)
+}
+
+/// Re-signs `path` after it was rewritten, for Apple targets.
+fn resign(target: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+    if target.contains("-apple-") {
+        adhoc_sign(path)?;
+    }
+    Ok(())
+}
+
+fn run(cmd: &mut Command) -> Result<(), Box<dyn Error>> {
+    let output = process::output(cmd)?;
+    if !output.status.success() {
+        return Err(format!(
+            "`{}` failed: {}\n{}",
+            command_line(cmd),
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,299 @@
+//! An ad-hoc code signer for thin 64-bit Mach-O files, for hosts without
+//! `codesign`. It writes what `codesign -s -` does: a code directory of
+//! SHA-256 page hashes, an empty requirements blob and an empty CMS blob,
+//! appended at the end of `__LINKEDIT` and named by `LC_CODE_SIGNATURE`. An
+//! existing signature is replaced, keeping its entitlements.
+
+use std::{io, path::Path};
+
+use sha2::{Digest, Sha256};
+
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+const MH_EXECUTE: u32 = 2;
+const LC_SEGMENT_64: u32 = 0x19;
+const LC_CODE_SIGNATURE: u32 = 0x1d;
+const CPU_TYPE_ARM64: u32 = 0x0100_000c;
+
+const CSMAGIC_EMBEDDED_SIGNATURE: u32 = 0xfade_0cc0;
+const CSMAGIC_CODEDIRECTORY: u32 = 0xfade_0c02;
+const CSMAGIC_REQUIREMENTS: u32 = 0xfade_0c01;
+const CSMAGIC_BLOBWRAPPER: u32 = 0xfade_0b01;
+const CSSLOT_CODEDIRECTORY: u32 = 0;
+const CSSLOT_REQUIREMENTS: u32 = 2;
+const CSSLOT_ENTITLEMENTS: u32 = 5;
+const CSSLOT_DER_ENTITLEMENTS: u32 = 7;
+const CSSLOT_SIGNATURESLOT: u32 = 0x10000;
+const CS_ADHOC: u32 = 0x2;
+const CS_EXECSEG_MAIN_BINARY: u64 = 0x1;
+const CS_HASHTYPE_SHA256: u8 = 2;
+/// Code directory version with the executable segment fields.
+const CD_VERSION: u32 = 0x20400;
+const CD_HEADER_SIZE: usize = 88;
+const PAGE_SHIFT: u8 = 12;
+const PAGE_SIZE: usize = 1 << PAGE_SHIFT;
+const HASH_SIZE: usize = 32;
+
+/// Whether `data` is a thin 64-bit Mach-O file.
+pub(crate) fn is_macho(data: &[u8]) -> bool {
+    read_u32(data, 0) == Some(MH_MAGIC_64)
+}
+
+/// Whether `data` is a Mach-O file the loader checks signatures of: an
+/// executable, dylib or bundle rather than an object file.
+pub(crate) fn is_signable(data: &[u8]) -> bool {
+    is_macho(data) && matches!(read_u32(data, 12), Some(2 | 6 | 8))
+}
+
+struct Segment {
+    /// Offset of the load command.
+    cmd: usize,
+    fileoff: u64,
+    filesize: u64,
+}
+
+/// Signs the Mach-O file at `path` ad hoc, in place.
+pub(crate) fn sign(path: &Path) -> io::Result<()> {
+    let mut data = fs_err::read(path)?;
+    let identifier = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    sign_bytes(&mut data, &identifier).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {e}", path.display()),
+        )
+    })?;
+    fs_err::write(path, data)
+}
+
+fn sign_bytes(data: &mut Vec<u8>, identifier: &str) -> Result<(), String> {
+    if !is_macho(data) {
+        return Err("not a thin 64-bit Mach-O file".into());
+    }
+    let field = |data: &[u8], offset| read_u32(data, offset).ok_or("truncated Mach-O header");
+    let cputype = field(data, 4)?;
+    let filetype = field(data, 12)?;
+    let ncmds = field(data, 16)?;
+    let sizeofcmds = field(data, 20)? as usize;
+
+    let mut text = None;
+    let mut linkedit = None;
+    let mut signature = None;
+    let mut first_section = usize::MAX;
+    let mut offset = 32;
+    for _ in 0..ncmds {
+        let cmd = field(data, offset)?;
+        let cmdsize = field(data, offset + 4)? as usize;
+        if cmd == LC_SEGMENT_64 {
+            let name = data
+                .get(offset + 8..offset + 24)
+                .ok_or("truncated segment command")?;
+            let segment = Segment {
+                cmd: offset,
+                fileoff: read_u64(data, offset + 40).ok_or("truncated segment command")?,
+                filesize: read_u64(data, offset + 48).ok_or("truncated segment command")?,
+            };
+            let nsects = field(data, offset + 64)?;
+            for i in 0..nsects as usize {
+                let section_offset = field(data, offset + 72 + i * 80 + 48)? as usize;
+                if section_offset != 0 {
+                    first_section = first_section.min(section_offset);
+                }
+            }
+            if name.starts_with(b"__TEXT\0") {
+                text = Some(segment);
+            } else if name.starts_with(b"__LINKEDIT\0") {
+                linkedit = Some(segment);
+            }
+        } else if cmd == LC_CODE_SIGNATURE {
+            signature = Some((offset, field(data, offset + 8)? as usize));
+        }
+        offset += cmdsize;
+    }
+    let text = text.ok_or("no __TEXT segment")?;
+    let linkedit = linkedit.ok_or("no __LINKEDIT segment")?;
+
+    let mut kept = Vec::new();
+    let code_limit = match signature {
+        Some((_, dataoff)) => {
+            kept = entitlements(&data[dataoff..]);
+            data.truncate(dataoff);
+            dataoff
+        }
+        None => {
+            if linkedit.fileoff + linkedit.filesize != data.len() as u64 {
+                return Err("__LINKEDIT is not at the end of the file".into());
+            }
+            let end_of_cmds = 32 + sizeofcmds;
+            if end_of_cmds + 16 > first_section {
+                return Err("no room for an LC_CODE_SIGNATURE load command".into());
+            }
+            data[end_of_cmds..end_of_cmds + 16].fill(0);
+            write_u32(data, end_of_cmds, LC_CODE_SIGNATURE);
+            write_u32(data, end_of_cmds + 4, 16);
+            write_u32(data, 16, ncmds + 1);
+            write_u32(data, 20, (sizeofcmds + 16) as u32);
+            signature = Some((end_of_cmds, 0));
+            data.len().next_multiple_of(16)
+        }
+    };
+    let (signature_cmd, _) = signature.expect("set above");
+    data.resize(code_limit, 0);
+
+    // An empty requirements set.
+    let requirements = [CSMAGIC_REQUIREMENTS, 12, 0].map(u32::to_be_bytes).concat();
+    let code_slots = code_limit.div_ceil(PAGE_SIZE);
+    let special_slots = kept
+        .iter()
+        .map(|&(slot, _)| slot)
+        .fold(CSSLOT_REQUIREMENTS, u32::max) as usize;
+    let ident_offset = CD_HEADER_SIZE;
+    let hash_offset = ident_offset + identifier.len() + 1 + special_slots * HASH_SIZE;
+    let cd_size = hash_offset + code_slots * HASH_SIZE;
+    let index_size = 12 + (3 + kept.len()) * 8;
+    let kept_size: usize = kept.iter().map(|(_, blob)| blob.len()).sum();
+    let total = index_size + cd_size + requirements.len() + kept_size + 8;
+
+    // The hashed pages include the load commands, so they get their final
+    // values first.
+    write_u32(data, signature_cmd + 8, code_limit as u32);
+    write_u32(data, signature_cmd + 12, total as u32);
+    let linkedit_size = (code_limit + total) as u64 - linkedit.fileoff;
+    let segment_page = if cputype == CPU_TYPE_ARM64 {
+        0x4000
+    } else {
+        0x1000
+    };
+    write_u64(data, linkedit.cmd + 48, linkedit_size);
+    let vmsize = read_u64(data, linkedit.cmd + 32).unwrap_or(0);
+    write_u64(
+        data,
+        linkedit.cmd + 32,
+        vmsize.max(linkedit_size.next_multiple_of(segment_page)),
+    );
+
+    let mut cd = Vec::with_capacity(cd_size);
+    for value in [
+        CSMAGIC_CODEDIRECTORY,
+        cd_size as u32,
+        CD_VERSION,
+        CS_ADHOC,
+        hash_offset as u32,
+        ident_offset as u32,
+        special_slots as u32,
+        code_slots as u32,
+        code_limit as u32,
+    ] {
+        cd.extend(value.to_be_bytes());
+    }
+    cd.extend([HASH_SIZE as u8, CS_HASHTYPE_SHA256, 0, PAGE_SHIFT]);
+    cd.extend([0; 4 * 4]); // spare2, scatter, team, spare3
+    cd.extend(0u64.to_be_bytes()); // codeLimit64
+    cd.extend(text.fileoff.to_be_bytes());
+    cd.extend(text.filesize.to_be_bytes());
+    let exec_flags = if filetype == MH_EXECUTE {
+        CS_EXECSEG_MAIN_BINARY
+    } else {
+        0
+    };
+    cd.extend(exec_flags.to_be_bytes());
+    debug_assert_eq!(cd.len(), CD_HEADER_SIZE);
+    cd.extend(identifier.as_bytes());
+    cd.push(0);
+    // Special slot hashes go in reverse, ending with slot 1 (the unused info
+    // slot) right before the page hashes.
+    for slot in (1..=special_slots as u32).rev() {
+        match kept.iter().find(|&&(kept, _)| kept == slot) {
+            Some((_, blob)) => cd.extend(Sha256::digest(blob)),
+            None if slot == CSSLOT_REQUIREMENTS => cd.extend(Sha256::digest(&requirements)),
+            None => cd.extend([0; HASH_SIZE]),
+        }
+    }
+    for page in data.chunks(PAGE_SIZE) {
+        cd.extend(Sha256::digest(page));
+    }
+
+    let mut blob = Vec::with_capacity(total);
+    for value in [
+        CSMAGIC_EMBEDDED_SIGNATURE,
+        total as u32,
+        3 + kept.len() as u32,
+    ] {
+        blob.extend(value.to_be_bytes());
+    }
+    let mut offset = index_size + cd_size;
+    let mut index = vec![
+        (CSSLOT_CODEDIRECTORY, index_size),
+        (CSSLOT_REQUIREMENTS, offset),
+    ];
+    offset += requirements.len();
+    for (slot, kept) in &kept {
+        index.push((*slot, offset));
+        offset += kept.len();
+    }
+    index.push((CSSLOT_SIGNATURESLOT, offset));
+    for (slot, offset) in index {
+        blob.extend(slot.to_be_bytes());
+        blob.extend((offset as u32).to_be_bytes());
+    }
+    blob.extend(cd);
+    blob.extend(requirements);
+    for (_, kept) in kept {
+        blob.extend(kept);
+    }
+    blob.extend(CSMAGIC_BLOBWRAPPER.to_be_bytes());
+    blob.extend(8u32.to_be_bytes());
+    debug_assert_eq!(blob.len(), total);
+    data.extend(blob);
+    Ok(())
+}
+
+/// The entitlements blobs of the signature `signature`, XML and DER, by slot.
+fn entitlements(signature: &[u8]) -> Vec<(u32, Vec<u8>)> {
+    let be = |offset: usize| {
+        signature
+            .get(offset..offset + 4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+    };
+    if be(0) != Some(CSMAGIC_EMBEDDED_SIGNATURE) {
+        return Vec::new();
+    }
+    let mut blobs = Vec::new();
+    for i in 0..be(8).unwrap_or(0) as usize {
+        let (Some(slot), Some(offset)) = (be(12 + i * 8), be(16 + i * 8)) else {
+            break;
+        };
+        if !matches!(slot, CSSLOT_ENTITLEMENTS | CSSLOT_DER_ENTITLEMENTS) {
+            continue;
+        }
+        let offset = offset as usize;
+        if let Some(blob) =
+            be(offset + 4).and_then(|len| signature.get(offset..offset + len as usize))
+        {
+            blobs.push((slot, blob.to_vec()));
+        }
+    }
+    blobs.sort_by_key(|&(slot, _)| slot);
+    blobs
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u64(data: &mut [u8], offset: usize, value: u64) {
+    data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+}
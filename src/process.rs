@@ -0,0 +1,546 @@
+//! Child processes that don't outlive a cancelled build. When Ctrl-C stops
+//! `cargo build`, Cargo and the build scripts die but a `zig` or bootstrap
+//! child deep in a clang, ninja or LLVM build would keep going, so every
+//! process this crate starts goes through [`spawn()`]:
+//!
+//! - Inside build scripts on Unix each child gets its own process group, and
+//!   SIGINT, SIGTERM and SIGHUP are forwarded to every live group before the
+//!   build script itself dies of the signal. Directories registered with
+//!   [`remove_on_interrupt()`] are deleted on the way out.
+//! - On Windows each child is put in a job object that kills everything in it
+//!   once the child's handle is closed, including when our process is killed.
+//! - A [`Child`] dropped before it exited (an error or panic while forwarding
+//!   its output) is killed together with its group.
+//!
+//! Outside build scripts children stay in the caller's process group, where
+//! the terminal's Ctrl-C reaches them directly, and no signal handlers are
+//! installed.
+//!
+//! A child started for an operation under a
+//! [`CancellationToken`](crate::cancel::CancellationToken) is killed when
+//! the token is cancelled, and waiting for it fails with
+//! [`Cancelled`](crate::cancel::Cancelled).
+//!
+//! Children can also be held to [`Limits`] on memory and CPU time, so a
+//! runaway comptime loop or a huge C++ translation unit fails on its own
+//! instead of taking down a shared build machine: `ZIG_RS_MEMORY_LIMIT`
+//! (e.g. `8G`) and `ZIG_RS_CPU_TIME_LIMIT` (e.g. `30m`) set them for every
+//! child, and builders such as [`Build::limits()`](crate::Build::limits)
+//! for the ones they start.
+
+use std::{
+    cell::Cell,
+    env,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{self, Command, ExitStatus, Output, Stdio},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    cache,
+    cancel::{self, CancellationToken, Cancelled},
+};
+
+/// Environment variable holding the default [`Limits::memory`], e.g. `8G`.
+pub const MEMORY_LIMIT_ENV: &str = "ZIG_RS_MEMORY_LIMIT";
+/// Environment variable holding the default [`Limits::cpu_time`], e.g.
+/// `30m`.
+pub const CPU_TIME_LIMIT_ENV: &str = "ZIG_RS_CPU_TIME_LIMIT";
+
+/// Resource limits for each child process. `None` leaves the system's
+/// limit alone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Bytes of memory: the address space (`RLIMIT_AS`) on Unix, committed
+    /// memory on Windows. A child over the limit fails to allocate.
+    pub memory: Option<u64>,
+    /// CPU time (`RLIMIT_CPU` on Unix, user time on Windows), after which
+    /// the child is killed.
+    pub cpu_time: Option<Duration>,
+}
+
+impl Limits {
+    /// The limits from [`MEMORY_LIMIT_ENV`] and [`CPU_TIME_LIMIT_ENV`],
+    /// parsed like `cargo zig clean --max-size` and `--older-than`.
+    pub fn from_env() -> io::Result<Self> {
+        let invalid = |key: &str, value: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{key}: invalid value: {value}"),
+            )
+        };
+        let memory = match env::var(MEMORY_LIMIT_ENV) {
+            Ok(value) => {
+                Some(cache::parse_size(&value).ok_or_else(|| invalid(MEMORY_LIMIT_ENV, &value))?)
+            }
+            Err(_) => None,
+        };
+        let cpu_time = match env::var(CPU_TIME_LIMIT_ENV) {
+            Ok(value) => {
+                Some(cache::parse_age(&value).ok_or_else(|| invalid(CPU_TIME_LIMIT_ENV, &value))?)
+            }
+            Err(_) => None,
+        };
+        Ok(Self { memory, cpu_time })
+    }
+
+    /// These limits, with unset ones taken from `defaults`.
+    pub fn or(self, defaults: Self) -> Self {
+        Self {
+            memory: self.memory.or(defaults.memory),
+            cpu_time: self.cpu_time.or(defaults.cpu_time),
+        }
+    }
+}
+
+thread_local! {
+    static LIMITS: Cell<Limits> = const { Cell::new(Limits { memory: None, cpu_time: None }) };
+}
+
+/// Runs `f` with `limits` applied to every child [`spawn()`] starts on this
+/// thread, on top of the ones from the environment.
+pub fn with_limits<R>(limits: Limits, f: impl FnOnce() -> R) -> R {
+    let previous = LIMITS.replace(limits);
+    let result = f();
+    LIMITS.set(previous);
+    result
+}
+
+/// Process groups of live children, by the child's pid.
+static GROUPS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Directories to delete when a signal stops the build.
+static TEMP_DIRS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// A child started by [`spawn()`].
+#[derive(Debug)]
+pub struct Child {
+    child: process::Child,
+    grouped: bool,
+    started: Instant,
+    /// Set once [`wait_with_usage()`](Self::wait_with_usage) reaped the
+    /// child behind `child`'s back, after which its pid may be reused.
+    reaped: bool,
+    /// The token of the operation the child was started for.
+    token: Option<CancellationToken>,
+    #[cfg(windows)]
+    job: Option<job::Job>,
+}
+
+/// What a child used, from [`Child::wait_with_usage()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    /// Time from [`spawn()`] until the child exited.
+    pub wall_time: Duration,
+    /// Peak resident set size in bytes on Unix, peak committed memory on
+    /// Windows. `None` when the system doesn't say.
+    pub peak_memory: Option<u64>,
+}
+
+impl Child {
+    pub fn inner(&mut self) -> &mut process::Child {
+        &mut self.child
+    }
+
+    pub fn wait(mut self) -> io::Result<ExitStatus> {
+        let status = self.child.wait()?;
+        self.check_cancelled()?;
+        Ok(status)
+    }
+
+    /// Like [`wait()`](Self::wait) but also returns what the child used.
+    pub fn wait_with_usage(mut self) -> io::Result<(ExitStatus, Usage)> {
+        let result = self.reap_with_usage()?;
+        self.check_cancelled()?;
+        Ok(result)
+    }
+
+    /// Fails when the child's operation was cancelled, which killed it.
+    fn check_cancelled(&self) -> io::Result<()> {
+        match &self.token {
+            Some(token) if token.is_cancelled() => Err(Cancelled::new().into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn reap_with_usage(&mut self) -> io::Result<(ExitStatus, Usage)> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt as _;
+
+            let pid = self.child.id() as libc::pid_t;
+            let mut status = 0;
+            // SAFETY: wait4() only writes the status and the zeroed rusage.
+            let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+            while unsafe { libc::wait4(pid, &mut status, 0, &mut rusage) } != pid {
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+            self.reaped = true;
+            // Linux reports kilobytes, Apple platforms bytes.
+            let scale = if cfg!(target_vendor = "apple") {
+                1
+            } else {
+                1024
+            };
+            let usage = Usage {
+                wall_time: self.started.elapsed(),
+                peak_memory: u64::try_from(rusage.ru_maxrss).ok().map(|rss| rss * scale),
+            };
+            Ok((ExitStatus::from_raw(status), usage))
+        }
+        #[cfg(not(unix))]
+        {
+            let status = self.child.wait()?;
+            let usage = Usage {
+                wall_time: self.started.elapsed(),
+                #[cfg(windows)]
+                peak_memory: self.job.as_ref().and_then(job::Job::peak_memory),
+                #[cfg(not(windows))]
+                peak_memory: None,
+            };
+            Ok((status, usage))
+        }
+    }
+}
+
+impl Drop for Child {
+    fn drop(&mut self) {
+        if !self.reaped
+            && let Ok(None) = self.child.try_wait()
+        {
+            if self.grouped {
+                kill_group(self.child.id(), Signal::Kill);
+            } else {
+                let _ = self.child.kill();
+            }
+            let _ = self.child.wait();
+        }
+        if self.grouped {
+            GROUPS.lock().unwrap().retain(|&pid| pid != self.child.id());
+        }
+        // The pid is free for reuse from the moment the child was reaped;
+        // a cancellation in between would signal whatever took it, which
+        // the few instructions since make unlikely enough.
+        if let Some(token) = &self.token {
+            token.remove_child(self.child.id());
+        }
+    }
+}
+
+/// Starts `cmd` as described in the [module docs](self).
+pub fn spawn(cmd: &mut Command) -> io::Result<Child> {
+    let limits = LIMITS.get().or(Limits::from_env()?);
+    let grouped = cfg!(unix) && in_build_script();
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt as _;
+
+        if grouped {
+            install_signal_forwarding();
+            cmd.process_group(0);
+        }
+        if limits != Limits::default() {
+            // SAFETY: the closure only calls getrlimit() and setrlimit(),
+            // which are async-signal-safe, and allocates nothing.
+            unsafe {
+                cmd.pre_exec(move || set_rlimits(limits));
+            }
+        }
+    }
+    let token = cancel::current();
+    cancel::check()?;
+    let started = Instant::now();
+    let child = cmd.spawn()?;
+    if grouped {
+        GROUPS.lock().unwrap().push(child.id());
+    }
+    if let Some(token) = &token {
+        token.add_child(child.id(), grouped);
+    }
+    Ok(Child {
+        #[cfg(windows)]
+        job: job::Job::assign(&child, limits),
+        child,
+        grouped,
+        started,
+        reaped: false,
+        token,
+    })
+}
+
+/// Lowers the limits of the current process, never raising one above its
+/// hard limit.
+#[cfg(unix)]
+fn set_rlimits(limits: Limits) -> io::Result<()> {
+    let set = |resource, value: u64, slack: u64| {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: `limit` is a valid rlimit for getrlimit() to fill in.
+        if unsafe { libc::getrlimit(resource, &mut limit) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let value = value as libc::rlim_t;
+        let hard = limit
+            .rlim_max
+            .min(value.saturating_add(slack as libc::rlim_t));
+        limit.rlim_cur = value.min(hard);
+        limit.rlim_max = hard;
+        // SAFETY: as above.
+        if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    };
+    if let Some(memory) = limits.memory {
+        set(libc::RLIMIT_AS, memory, 0)?;
+    }
+    if let Some(cpu_time) = limits.cpu_time {
+        // SIGXCPU at the soft limit, SIGKILL a second later.
+        set(libc::RLIMIT_CPU, cpu_time.as_secs().max(1), 1)?;
+    }
+    Ok(())
+}
+
+/// Like [`Command::output()`] but started through [`spawn()`].
+pub fn output(cmd: &mut Command) -> io::Result<Output> {
+    output_with_stdin(cmd.stdin(Stdio::null()), None)
+}
+
+/// Like [`output()`] but with `input` written to the child's stdin, which is
+/// then closed.
+pub fn output_with_input(cmd: &mut Command, input: &[u8]) -> io::Result<Output> {
+    output_with_stdin(cmd.stdin(Stdio::piped()), Some(input))
+}
+
+fn output_with_stdin(cmd: &mut Command, input: Option<&[u8]>) -> io::Result<Output> {
+    let mut child = spawn(cmd.stdout(Stdio::piped()).stderr(Stdio::piped()))?;
+    let inner = child.inner();
+    let stdin = inner.stdin.take();
+    let mut stdout = inner.stdout.take().expect("stdout is piped");
+    let mut stderr = inner.stderr.take().expect("stderr is piped");
+    let (stdout, stderr) = thread::scope(|scope| {
+        if let (Some(mut stdin), Some(input)) = (stdin, input) {
+            // A child that exits without reading everything closes the pipe;
+            // its exit status tells the story.
+            scope.spawn(move || {
+                let _ = stdin.write_all(input);
+            });
+        }
+        let out = scope.spawn(move || {
+            let mut buf = Vec::new();
+            stdout.read_to_end(&mut buf).map(|_| buf)
+        });
+        let mut buf = Vec::new();
+        let err = stderr.read_to_end(&mut buf).map(|_| buf);
+        (out.join().expect("stdout reader panicked"), err)
+    });
+    Ok(Output {
+        stdout: stdout?,
+        stderr: stderr?,
+        status: child.wait()?,
+    })
+}
+
+/// Like [`Command::status()`] but started through [`spawn()`].
+pub fn status(cmd: &mut Command) -> io::Result<ExitStatus> {
+    spawn(cmd)?.wait()
+}
+
+/// Deletes `dir` if a signal stops the build before the returned guard is
+/// dropped. Dropping the guard only unregisters the directory; moving it
+/// into place or removing it is still up to the caller.
+pub fn remove_on_interrupt(dir: &Path) -> TempDirGuard {
+    #[cfg(unix)]
+    if in_build_script() {
+        install_signal_forwarding();
+    }
+    TEMP_DIRS.lock().unwrap().push(dir.to_owned());
+    TempDirGuard(dir.to_owned())
+}
+
+/// Returned by [`remove_on_interrupt()`].
+#[derive(Debug)]
+#[must_use]
+pub struct TempDirGuard(PathBuf);
+
+impl Drop for TempDirGuard {
+    fn drop(&mut self) {
+        let mut dirs = TEMP_DIRS.lock().unwrap();
+        if let Some(i) = dirs.iter().position(|dir| *dir == self.0) {
+            dirs.remove(i);
+        }
+    }
+}
+
+fn in_build_script() -> bool {
+    env::var_os("OUT_DIR").is_some() && env::var_os("TARGET").is_some()
+}
+
+enum Signal {
+    Kill,
+    #[cfg(unix)]
+    Forward(i32),
+}
+
+#[cfg(unix)]
+fn kill_group(pid: u32, signal: Signal) {
+    let signal = match signal {
+        Signal::Kill => libc::SIGKILL,
+        Signal::Forward(signal) => signal,
+    };
+    // SAFETY: kill() has no memory safety requirements; a negative pid
+    // addresses the process group the child leads.
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), signal);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_group(_pid: u32, _signal: Signal) {}
+
+/// Kills the child `pid`, with its process group if it has one.
+pub(crate) fn kill(pid: u32, grouped: bool) {
+    #[cfg(unix)]
+    if grouped {
+        kill_group(pid, Signal::Kill);
+    } else {
+        // SAFETY: kill() has no memory safety requirements.
+        unsafe {
+            libc::kill(pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+
+        use windows_sys::Win32::System::Threading::{
+            OpenProcess, PROCESS_TERMINATE, TerminateProcess,
+        };
+
+        let _ = grouped;
+        // SAFETY: plain Win32 calls on a handle we own; the job object takes
+        // the rest of the tree down with the child's handle.
+        unsafe {
+            let handle = OpenProcess(PROCESS_TERMINATE, 0, pid);
+            if !handle.is_null() {
+                let handle = OwnedHandle::from_raw_handle(handle);
+                TerminateProcess(handle.as_raw_handle(), 1);
+            }
+        }
+    }
+    #[cfg(not(any(unix, windows)))]
+    let _ = (pid, grouped);
+}
+
+/// Starts a thread that waits for a terminating signal, passes it on to the
+/// child process groups, removes the registered directories and then dies of
+/// the signal the way it would have without the handler.
+#[cfg(unix)]
+fn install_signal_forwarding() {
+    use signal_hook::{
+        consts::{SIGHUP, SIGINT, SIGTERM},
+        iterator::Signals,
+    };
+
+    static INSTALLED: std::sync::Once = std::sync::Once::new();
+    INSTALLED.call_once(|| {
+        let Ok(mut signals) = Signals::new([SIGINT, SIGTERM, SIGHUP]) else {
+            return;
+        };
+        thread::spawn(move || {
+            let Some(signal) = signals.forever().next() else {
+                return;
+            };
+            for &pid in GROUPS.lock().unwrap().iter() {
+                kill_group(pid, Signal::Forward(signal));
+            }
+            for dir in TEMP_DIRS.lock().unwrap().iter() {
+                let _ = std::fs::remove_dir_all(dir);
+            }
+            let _ = signal_hook::low_level::emulate_default_handler(signal);
+            process::exit(128 + signal);
+        });
+    });
+}
+
+#[cfg(windows)]
+mod job {
+    use std::{
+        os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle},
+        process,
+    };
+
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+        JOB_OBJECT_LIMIT_PROCESS_MEMORY, JOB_OBJECT_LIMIT_PROCESS_TIME,
+        JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JobObjectExtendedLimitInformation,
+        QueryInformationJobObject, SetInformationJobObject,
+    };
+
+    use super::Limits;
+
+    /// A job object that kills its processes when the handle is closed, and
+    /// holds each of them to the [`Limits`] it was created with.
+    #[derive(Debug)]
+    pub struct Job(OwnedHandle);
+
+    impl Job {
+        /// Puts `child` in a new job. `None` when Windows refuses, e.g. for a
+        /// process already in a job that doesn't allow nesting.
+        pub fn assign(child: &process::Child, limits: Limits) -> Option<Self> {
+            // SAFETY: plain Win32 calls on handles we own; `info` outlives the
+            // call that reads it.
+            unsafe {
+                let handle = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+                if handle.is_null() {
+                    return None;
+                }
+                let job = Self(OwnedHandle::from_raw_handle(handle));
+                let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+                info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+                if let Some(memory) = limits.memory {
+                    info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_MEMORY;
+                    info.ProcessMemoryLimit = memory.try_into().unwrap_or(usize::MAX);
+                }
+                if let Some(cpu_time) = limits.cpu_time {
+                    info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_TIME;
+                    // In 100 ns units.
+                    info.BasicLimitInformation.PerProcessUserTimeLimit =
+                        (cpu_time.as_nanos() / 100).try_into().unwrap_or(i64::MAX);
+                }
+                let ok = SetInformationJobObject(
+                    job.0.as_raw_handle(),
+                    JobObjectExtendedLimitInformation,
+                    (&raw const info).cast(),
+                    size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+                ) != 0
+                    && AssignProcessToJobObject(job.0.as_raw_handle(), child.as_raw_handle()) != 0;
+                ok.then_some(job)
+            }
+        }
+
+        /// The most memory any process in the job committed.
+        pub fn peak_memory(&self) -> Option<u64> {
+            // SAFETY: `info` is large enough for the class queried.
+            unsafe {
+                let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+                let ok = QueryInformationJobObject(
+                    self.0.as_raw_handle(),
+                    JobObjectExtendedLimitInformation,
+                    (&raw mut info).cast(),
+                    size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+                    std::ptr::null_mut(),
+                ) != 0;
+                ok.then_some(info.PeakProcessMemoryUsed as u64)
+            }
+        }
+    }
+}
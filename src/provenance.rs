@@ -0,0 +1,168 @@
+//! A supply-chain attestation of how the toolchain was put together. The
+//! build script writes an [in-toto] Statement with a [SLSA provenance]
+//! predicate next to the `zig` binary, naming:
+//!
+//! - the `zig` binary and the [manifest](crate::manifest), which covers
+//!   every other file, by their SHA-256 digests as subjects,
+//! - what the toolchain came from (the zig-bootstrap sources, the
+//!   ziglang.org release, a packed archive, a cache entry or the system
+//!   `zig`) as resolved dependencies, with digests where there are any,
+//! - and the target, toolchain source and builder environment as
+//!   parameters.
+//!
+//! The statement is unsigned and has no timestamps, so the same inputs give
+//! the same bytes; release pipelines with SLSA requirements sign it
+//! themselves, e.g. as the payload of a DSSE envelope:
+//!
+//! ```no_run
+//! let statement = zig_rs::provenance()?;
+//! std::fs::write("dist/zig.intoto.json", statement)?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! The build scripts of dependent crates find the file in
+//! `DEP_ZIG_PROVENANCE`.
+//!
+//! [in-toto]: https://github.com/in-toto/attestation/blob/main/spec/v1/statement.md
+//! [SLSA provenance]: https://slsa.dev/spec/v1.0/provenance
+
+use std::{io, path::Path};
+
+/// The file next to the `zig` binary.
+pub const FILE_NAME: &str = "zig-rs.provenance.json";
+pub const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+pub const PREDICATE_TYPE: &str = "https://slsa.dev/provenance/v1";
+/// Where what the parameters mean is written down: here.
+pub const BUILD_TYPE: &str = "https://docs.rs/zig/latest/zig_rs/provenance/";
+
+/// An input of the build, an in-toto resource descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    /// A URL, or a `file://` URL for local paths.
+    pub uri: String,
+    /// SHA-256, in lowercase hex, of the file, or of the
+    /// [manifest](crate::manifest) of a toolchain directory.
+    pub sha256: Option<String>,
+}
+
+impl Dependency {
+    /// A local path as a `file://` dependency, made absolute.
+    pub fn file(path: &Path, sha256: Option<String>) -> Self {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.into());
+        let path = path.to_string_lossy().replace('\\', "/");
+        let slash = if path.starts_with('/') { "" } else { "/" };
+        Self {
+            uri: format!("file://{slash}{path}"),
+            sha256,
+        }
+    }
+}
+
+/// The contents of a provenance statement.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    /// The artifacts attested to, by name and SHA-256.
+    pub subjects: Vec<(String, String)>,
+    /// What was asked for: the target, the Zig version and where the
+    /// toolchain came from.
+    pub parameters: Vec<(String, String)>,
+    /// How the builder was set up, e.g. the host and container image.
+    pub environment: Vec<(String, String)>,
+    pub dependencies: Vec<Dependency>,
+    /// The builder, e.g. this crate's page on crates.io.
+    pub builder_id: String,
+}
+
+impl Provenance {
+    /// The statement as JSON.
+    pub fn to_json(&self) -> String {
+        let object = |fields: &[(String, String)], indent: &str| {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(key, value)| format!("{indent}  {}: {}", quote(key), quote(value)))
+                .collect();
+            if fields.is_empty() {
+                "{}".to_owned()
+            } else {
+                format!("{{\n{}\n{indent}}}", fields.join(",\n"))
+            }
+        };
+        let subjects: Vec<String> = self
+            .subjects
+            .iter()
+            .map(|(name, sha256)| {
+                format!(
+                    "    {{ \"name\": {}, \"digest\": {{ \"sha256\": {} }} }}",
+                    quote(name),
+                    quote(sha256)
+                )
+            })
+            .collect();
+        let dependencies: Vec<String> = self
+            .dependencies
+            .iter()
+            .map(|dependency| match &dependency.sha256 {
+                Some(sha256) => format!(
+                    "        {{ \"uri\": {}, \"digest\": {{ \"sha256\": {} }} }}",
+                    quote(&dependency.uri),
+                    quote(sha256)
+                ),
+                None => format!("        {{ \"uri\": {} }}", quote(&dependency.uri)),
+            })
+            .collect();
+        format!(
+            "{{
+  \"_type\": {},
+  \"subject\": [
+{}
+  ],
+  \"predicateType\": {},
+  \"predicate\": {{
+    \"buildDefinition\": {{
+      \"buildType\": {},
+      \"externalParameters\": {},
+      \"internalParameters\": {},
+      \"resolvedDependencies\": [
+{}
+      ]
+    }},
+    \"runDetails\": {{
+      \"builder\": {{ \"id\": {} }}
+    }}
+  }}
+}}
+",
+            quote(STATEMENT_TYPE),
+            subjects.join(",\n"),
+            quote(PREDICATE_TYPE),
+            quote(BUILD_TYPE),
+            object(&self.parameters, "      "),
+            object(&self.environment, "      "),
+            dependencies.join(",\n"),
+            quote(&self.builder_id),
+        )
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs_err::write(path, self.to_json())
+    }
+}
+
+/// `s` as a JSON string.
+pub(crate) fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
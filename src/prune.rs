@@ -0,0 +1,199 @@
+//! Smaller installs. Zig's `lib/` holds the libc headers and sources for
+//! every target it supports, and the build script copies it into the
+//! `OUT_DIR` of every target directory it builds in. Crates that only need
+//! `zig cc` for one target can have the build script remove the rest, by
+//! listing profiles in `ZIG_RS_LIB_PROFILE` (comma-separated) or turning on
+//! the matching `lib-*` Cargo features:
+//!
+//! ```sh
+//! ZIG_RS_LIB_PROFILE=no-docs,native-libc cargo build
+//! ```
+//!
+//! The [manifest](crate::manifest) is written after pruning, so
+//! [`verify()`](crate::verify) checks the smaller tree. Archives from
+//! [`pack_toolchain()`](crate::pack_toolchain) record the profiles in their
+//! [fingerprint](crate::pack::Fingerprint) and are only reused by builds
+//! that prune the same way. A `lib/` linked into the shared cache with
+//! `ZIG_RS_CACHE_LINK` is never pruned.
+
+use std::{fmt, io, path::Path};
+
+use crate::libc_headers;
+
+/// Environment variable listing the profiles the build script prunes with.
+pub const PROFILE_ENV: &str = "ZIG_RS_LIB_PROFILE";
+
+/// The name of the empty list of profiles, which keeps everything.
+pub const FULL: &str = "full";
+
+/// What to remove from `lib/`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Profile {
+    /// `no-docs`: the standard library documentation `zig std` serves.
+    NoDocs,
+    /// `native-libc`: the libc headers and sources of every target but the
+    /// one the toolchain is built for. That includes the wasi-libc headers
+    /// `DEP_ZIG_WASI_SYSROOT` is assembled from.
+    NativeLibc,
+    /// `compiler-rt-only`: every libc, libc++, libunwind and TSan, leaving
+    /// the standard library and compiler-rt, for freestanding code and
+    /// objects that link no libc.
+    CompilerRtOnly,
+}
+
+impl Profile {
+    pub const ALL: [Self; 3] = [Self::NoDocs, Self::NativeLibc, Self::CompilerRtOnly];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::NoDocs => "no-docs",
+            Self::NativeLibc => "native-libc",
+            Self::CompilerRtOnly => "compiler-rt-only",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|profile| profile.name() == name)
+    }
+
+    /// The Cargo feature that turns the profile on, e.g. `lib-no-docs`.
+    pub fn feature(self) -> String {
+        format!("lib-{}", self.name())
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Parses a comma-separated list of profile names, where [`FULL`] or an
+/// empty list means none. The profiles come back sorted and deduplicated.
+pub fn parse_profiles(list: &str) -> Result<Vec<Profile>, String> {
+    let mut profiles = Vec::new();
+    for name in list.split(',').map(str::trim) {
+        if name.is_empty() || name == FULL {
+            continue;
+        }
+        profiles.push(Profile::from_name(name).ok_or_else(|| {
+            let names: Vec<&str> = Profile::ALL.iter().map(|p| p.name()).collect();
+            format!(
+                "unknown lib profile {name:?}; expected {FULL} or {}",
+                names.join(", ")
+            )
+        })?);
+    }
+    profiles.sort();
+    profiles.dedup();
+    Ok(profiles)
+}
+
+/// `profiles` as [`parse_profiles()`] reads them back, [`FULL`] for none.
+pub fn profiles_name(profiles: &[Profile]) -> String {
+    if profiles.is_empty() {
+        return FULL.into();
+    }
+    let names: Vec<&str> = profiles.iter().map(|p| p.name()).collect();
+    names.join(",")
+}
+
+/// The libc source directories under `lib/libc/`, by the libc or OS they
+/// belong to.
+const LIBC_SOURCES: &[&str] = &[
+    "darwin", "freebsd", "glibc", "mingw", "musl", "netbsd", "wasi",
+];
+
+/// Everything under `lib/` other than the standard library and compiler-rt.
+const LIBRARIES: &[&str] = &["libc", "libcxx", "libcxxabi", "libunwind", "tsan"];
+
+/// Removes what `profiles` say from the Zig `lib/` directory `lib_dir` of a
+/// toolchain for `zig_target`; returns how many bytes that freed.
+pub fn prune(lib_dir: &Path, zig_target: &str, profiles: &[Profile]) -> io::Result<u64> {
+    let mut freed = 0;
+    for profile in profiles {
+        match profile {
+            Profile::NoDocs => freed += remove(&lib_dir.join("docs"))?,
+            Profile::NativeLibc => {
+                let keep = libc_headers::include_dirs(lib_dir, zig_target);
+                let include = lib_dir.join("libc").join("include");
+                for dir in read_dir_if_exists(&include)? {
+                    if !keep.contains(&dir) {
+                        freed += remove(&dir)?;
+                    }
+                }
+                let sources = native_libc_sources(zig_target);
+                for name in LIBC_SOURCES {
+                    if !sources.contains(name) {
+                        freed += remove(&lib_dir.join("libc").join(name))?;
+                    }
+                }
+            }
+            Profile::CompilerRtOnly => {
+                for name in LIBRARIES {
+                    freed += remove(&lib_dir.join(name))?;
+                }
+            }
+        }
+    }
+    Ok(freed)
+}
+
+/// The `lib/libc/` source directories `zig cc` builds or links the libc of
+/// `zig_target` from.
+fn native_libc_sources(zig_target: &str) -> Vec<&'static str> {
+    let mut parts = zig_target.split('-');
+    let _arch = parts.next();
+    let os = parts.next().unwrap_or_default();
+    let os = os.split('.').next().unwrap_or(os);
+    let abi = parts.next().unwrap_or_default();
+    match (os, abi) {
+        ("linux", abi) if abi.starts_with("gnu") => vec!["glibc"],
+        (_, abi) if abi.starts_with("musl") && os != "wasi" => vec!["musl"],
+        // wasi-libc is built partly from musl's sources.
+        ("wasi", _) => vec!["wasi", "musl"],
+        ("windows", _) => vec!["mingw"],
+        ("macos" | "ios" | "tvos" | "watchos" | "visionos", _) => vec!["darwin"],
+        ("freebsd", _) => vec!["freebsd"],
+        ("netbsd", _) => vec!["netbsd"],
+        _ => Vec::new(),
+    }
+}
+
+/// Removes `path` if it exists and returns the size of what was there.
+fn remove(path: &Path) -> io::Result<u64> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    if !metadata.is_dir() {
+        fs_err::remove_file(path)?;
+        return Ok(metadata.len());
+    }
+    let size = size(path)?;
+    fs_err::remove_dir_all(path)?;
+    Ok(size)
+}
+
+fn size(dir: &Path) -> io::Result<u64> {
+    let mut total = 0;
+    for entry in fs_err::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        total += if metadata.is_dir() {
+            size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(total)
+}
+
+fn read_dir_if_exists(dir: &Path) -> io::Result<Vec<std::path::PathBuf>> {
+    match std::fs::read_dir(dir) {
+        Ok(read_dir) => read_dir.map(|entry| Ok(entry?.path())).collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,185 @@
+//! Official Zig release archives from ziglang.org. These are static builds
+//! (musl on Linux), so one archive per arch/OS pair runs on every distro.
+//!
+//! Archives are checked against the SHA-256 digests ziglang.org lists in its
+//! [download index](INDEX_URL) and against their minisign signatures (see
+//! [`integrity`](crate::integrity)) before they are extracted.
+
+use std::{error::Error, path::Path};
+
+use crate::{
+    archive::{self, Format},
+    download::{self, Progress},
+    integrity, manifest,
+};
+
+/// The JSON index of releases, with the digest and size of every archive,
+/// plus the latest development snapshot under `"master"`.
+pub const INDEX_URL: &str = "https://ziglang.org/download/index.json";
+
+/// Returns Zig's `(arch, os)` names for the platform this code was compiled
+/// for, if ziglang.org publishes binaries for it.
+pub fn host_platform() -> Option<(&'static str, &'static str)> {
+    let arch = match std::env::consts::ARCH {
+        arch @ ("x86_64" | "aarch64" | "x86" | "riscv64" | "loongarch64" | "s390x") => arch,
+        "arm" => "armv7a",
+        "powerpc64" if cfg!(target_endian = "little") => "powerpc64le",
+        _ => return None,
+    };
+    let os = match std::env::consts::OS {
+        os @ ("linux" | "macos" | "windows" | "freebsd" | "netbsd") => os,
+        _ => return None,
+    };
+    Some((arch, os))
+}
+
+/// Returns Zig's `(arch, os)` names for the platform of the rustc target
+/// `rust_target`, if ziglang.org publishes binaries for it. Linux releases are
+/// static, so they run regardless of the target's libc.
+pub fn target_platform(rust_target: &str) -> Option<(&'static str, &'static str)> {
+    let mut parts = rust_target.split('-');
+    let arch = match parts.next()? {
+        "x86_64" => "x86_64",
+        "aarch64" => "aarch64",
+        "i586" | "i686" => "x86",
+        "riscv64gc" => "riscv64",
+        "loongarch64" => "loongarch64",
+        "s390x" => "s390x",
+        "armv7" => "armv7a",
+        "powerpc64le" => "powerpc64le",
+        _ => return None,
+    };
+    let os = match rust_target {
+        t if t.contains("-linux-") => "linux",
+        t if t.ends_with("-apple-darwin") => "macos",
+        t if t.contains("-windows-") => "windows",
+        t if t.ends_with("-freebsd") => "freebsd",
+        t if t.ends_with("-netbsd") => "netbsd",
+        _ => return None,
+    };
+    Some((arch, os))
+}
+
+fn version_at_least(version: &str, min: (u64, u64, u64)) -> bool {
+    let core = version.split(['-', '+']).next().unwrap_or(version);
+    let mut parts = core.split('.').map(|p| p.parse::<u64>().unwrap_or(0));
+    let v = (
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+    );
+    v >= min
+}
+
+/// File name of the release archive for `version` on `arch`/`os`, e.g.
+/// `zig-linux-x86_64-0.14.0.tar.xz`. Releases after 0.14.0 swapped the order
+/// to `zig-x86_64-linux-0.14.1.tar.xz`.
+pub fn archive_name(version: &str, arch: &str, os: &str) -> String {
+    let format = if os == "windows" {
+        Format::Zip
+    } else {
+        Format::TarXz
+    };
+    let platform = if version_at_least(version, (0, 14, 1)) {
+        format!("{arch}-{os}")
+    } else {
+        format!("{os}-{arch}")
+    };
+    format!("zig-{platform}-{version}.{}", format.extension())
+}
+
+/// Download URL of the release archive. Development snapshots live under
+/// `/builds/` instead of a per-version directory.
+pub fn url(version: &str, arch: &str, os: &str) -> String {
+    let name = archive_name(version, arch, os);
+    if version.contains("-dev") {
+        format!("https://ziglang.org/builds/{name}")
+    } else {
+        format!("https://ziglang.org/download/{version}/{name}")
+    }
+}
+
+/// The SHA-256 digest `index` (the contents of [`INDEX_URL`]) lists for the
+/// archive of `version` on `arch`/`os`. Development snapshots are only listed
+/// while they are the latest one.
+pub fn shasum(index: &str, version: &str, arch: &str, os: &str) -> Option<String> {
+    let name = archive_name(version, arch, os);
+    let key = if version.contains("-dev") {
+        "master"
+    } else {
+        version
+    };
+    let entry = &index[index.find(&format!("\"{key}\""))?..];
+    let entry = &entry[entry.find(&format!("\"{arch}-{os}\""))?..];
+    let entry = &entry[..entry.find('}')?];
+    let tarball = json_string(entry, "tarball")?;
+    tarball
+        .ends_with(&format!("/{name}"))
+        .then(|| json_string(entry, "shasum"))
+        .flatten()
+        .map(str::to_owned)
+}
+
+/// The string value of the first `"key"` in `json`; the index has no escapes
+/// in the fields we read.
+fn json_string<'a>(json: &'a str, key: &str) -> Option<&'a str> {
+    let rest = &json[json.find(&format!("\"{key}\""))? + key.len() + 2..];
+    let rest = &rest[rest.find('"')? + 1..];
+    Some(&rest[..rest.find('"')?])
+}
+
+/// Downloads and extracts the release for `version` into `dest`, which ends
+/// up with the usual `zig` binary and `lib/` layout. Fails if the archive
+/// doesn't match its signature or the digest in the index; development
+/// snapshots the index no longer lists are only checked against their
+/// signature. Returns the SHA-256 of the archive.
+pub fn install(
+    version: &str,
+    arch: &str,
+    os: &str,
+    dest: &Path,
+    on_progress: impl FnMut(&Progress),
+) -> Result<String, Box<dyn Error>> {
+    let name = archive_name(version, arch, os);
+    let format = Format::from_name(&name).expect("archive_name() uses a known format");
+    fs_err::create_dir_all(dest)?;
+    let archive_path = dest.join(&name);
+    let index_path = dest.join("index.json");
+    download::download(INDEX_URL, &index_path, |_| {})?;
+    let expected = shasum(&fs_err::read_to_string(&index_path)?, version, arch, os);
+    fs_err::remove_file(&index_path)?;
+    if expected.is_none() && !version.contains("-dev") {
+        return Err(format!("{INDEX_URL} lists no digest for {name}").into());
+    }
+    download::download(&url(version, arch, os), &archive_path, on_progress)?;
+    let signature_path = dest.join(format!("{name}.minisig"));
+    download::download(
+        &format!("{}.minisig", url(version, arch, os)),
+        &signature_path,
+        |_| {},
+    )?;
+    let signature = fs_err::read_to_string(&signature_path)?;
+    fs_err::remove_file(&signature_path)?;
+    if let Err(e) = integrity::check_minisign(&archive_path, &name, &signature)
+        .and_then(|()| integrity::check_sha256(&archive_path, &name, &url(version, arch, os)))
+    {
+        fs_err::remove_file(&archive_path)?;
+        return Err(e);
+    }
+    let actual = manifest::hash_file(&archive_path)?;
+    if let Some(expected) = expected
+        && !actual.eq_ignore_ascii_case(&expected)
+    {
+        fs_err::remove_file(&archive_path)?;
+        return Err(
+            format!("{name} has SHA-256 {actual}, but {INDEX_URL} lists {expected}").into(),
+        );
+    }
+    archive::extract(&archive_path, format, dest)?;
+    fs_err::remove_file(&archive_path)?;
+    let zig = dest.join(if os == "windows" { "zig.exe" } else { "zig" });
+    if !zig.is_file() || !dest.join("lib").join("std").is_dir() {
+        return Err(format!("{name} has no zig binary and lib/std/ at its root").into());
+    }
+    Ok(actual)
+}
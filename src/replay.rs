@@ -0,0 +1,134 @@
+//! Scripts that rerun a failed `zig` command outside Cargo, for bug reports
+//! against this crate and against Zig. With [`ENV`] set, every
+//! [`CommandError`](crate::error::CommandError) comes with a script holding
+//! the command's exact arguments, environment and working directory, and
+//! its message says where:
+//!
+//! ```sh
+//! ZIG_RS_REPLAY=1 cargo build
+//! # error: `zig cc ...` failed: exit status: 1
+//! # replay with target/debug/build/foo-1234/out/zig-rs-replay/zig-3f2a9c81d0e4.sh
+//! sh target/debug/build/foo-1234/out/zig-rs-replay/zig-3f2a9c81d0e4.sh
+//! ```
+//!
+//! With `ENV` set to `1` the scripts go in `OUT_DIR/zig-rs-replay` in build
+//! scripts and in the temporary directory elsewhere; any other value is the
+//! directory to write them to. Scripts are shell scripts, or `.cmd` files on
+//! Windows. They hold the whole environment, secrets included, so read them
+//! before attaching them anywhere. Input fed to the command on its stdin,
+//! as [`fmt::format_str()`](crate::fmt::format_str) does, isn't replayed.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::manifest;
+
+/// Environment variable turning replay scripts on; see the [module
+/// documentation](self).
+pub const ENV: &str = "ZIG_RS_REPLAY";
+
+/// The directory replay scripts go in, or `None` when [`ENV`] isn't set.
+pub fn dir() -> Option<PathBuf> {
+    let value = env::var_os(ENV).filter(|value| !value.is_empty())?;
+    if value != "1" {
+        return Some(value.into());
+    }
+    let base = env::var_os("OUT_DIR").map_or_else(env::temp_dir, PathBuf::from);
+    Some(base.join("zig-rs-replay"))
+}
+
+/// Writes the replay script of `cmd` into [`dir()`], if [`ENV`] is set, and
+/// returns its path. The script is named after the program and a digest of
+/// the command, so writing it again for the same command replaces it.
+pub fn write_if_enabled(cmd: &Command) -> io::Result<Option<PathBuf>> {
+    match dir() {
+        Some(dir) => write(cmd, &dir).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Writes the replay script of `cmd` into `dir` and returns its path.
+pub fn write(cmd: &Command, dir: &Path) -> io::Result<PathBuf> {
+    let mut envs: BTreeMap<OsString, OsString> = env::vars_os().collect();
+    for (key, value) in cmd.get_envs() {
+        match value {
+            Some(value) => envs.insert(key.to_owned(), value.to_owned()),
+            None => envs.remove(key),
+        };
+    }
+    let cwd = match cmd.get_current_dir() {
+        Some(dir) => dir.to_owned(),
+        None => env::current_dir()?,
+    };
+    let mut argv = vec![cmd.get_program().to_owned()];
+    argv.extend(cmd.get_args().map(ToOwned::to_owned));
+
+    let mut hasher = Sha256::new();
+    for arg in &argv {
+        hasher.update(arg.as_encoded_bytes());
+        hasher.update("\0");
+    }
+    let digest = manifest::hex(&hasher.finalize());
+    let program = Path::new(cmd.get_program())
+        .file_stem()
+        .map_or("command".into(), |stem| stem.to_string_lossy());
+    let extension = if cfg!(windows) { "cmd" } else { "sh" };
+    let path = dir.join(format!("{program}-{}.{extension}", &digest[..12]));
+
+    let script = if cfg!(windows) {
+        cmd_script(&envs, &cwd, &argv)
+    } else {
+        sh_script(&envs, &cwd, &argv)
+    };
+    fs_err::create_dir_all(dir)?;
+    fs_err::write(&path, script)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs_err::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(path)
+}
+
+fn sh_script(envs: &BTreeMap<OsString, OsString>, cwd: &Path, argv: &[OsString]) -> String {
+    let quote = |s: &str| format!("'{}'", s.replace('\'', r"'\''"));
+    let mut script = String::from("#!/bin/sh\n");
+    script += &format!("cd {} || exit\n", quote(&cwd.to_string_lossy()));
+    script += "exec env -i \\\n";
+    for (key, value) in envs {
+        let var = format!("{}={}", key.to_string_lossy(), value.to_string_lossy());
+        script += &format!("    {} \\\n", quote(&var));
+    }
+    let argv: Vec<String> = argv
+        .iter()
+        .map(|arg| quote(&arg.to_string_lossy()))
+        .collect();
+    script += &format!("    {}\n", argv.join(" "));
+    script
+}
+
+fn cmd_script(envs: &BTreeMap<OsString, OsString>, cwd: &Path, argv: &[OsString]) -> String {
+    let mut script = String::from("@echo off\r\nsetlocal\r\n");
+    for (key, value) in envs {
+        script += &format!(
+            "set \"{}={}\"\r\n",
+            key.to_string_lossy(),
+            value.to_string_lossy()
+        );
+    }
+    script += &format!("cd /d \"{}\"\r\n", cwd.display());
+    let argv: Vec<String> = argv
+        .iter()
+        .map(|arg| format!("\"{}\"", arg.to_string_lossy()))
+        .collect();
+    script += &format!("{}\r\n", argv.join(" "));
+    script
+}
@@ -0,0 +1,186 @@
+//! Checks that the static libraries [`Build`](crate::Build) writes are
+//! reproducible, for projects whose reproducible-build checks cover the
+//! native parts too. `Build` archives with `zig ar D`, which records every
+//! member with a zero timestamp, owner and group and mode `644`, in sorted
+//! order. C and C++ are compiled with `-ffile-prefix-map`, recording paths
+//! under `CARGO_MANIFEST_DIR` as relative to `.` and under `OUT_DIR` to
+//! `out`, so the package builds the same from any checkout. Compiles are
+//! deterministic as long as other paths and `SOURCE_DATE_EPOCH` (which
+//! `__DATE__` and `__TIME__` follow) stay the same; clang and Zig take no
+//! random seed.
+//!
+//! [`Build::verify_reproducible()`](crate::Build::verify_reproducible)
+//! builds a library twice and compares the results:
+//!
+//! ```no_run
+//! // build.rs
+//! let problems = zig_rs::Build::new()
+//!     .file("src/foo.c")
+//!     .verify_reproducible("foo")?;
+//! for problem in problems {
+//!     println!("cargo:warning={problem}");
+//! }
+//! # Ok::<(), zig_rs::Error>(())
+//! ```
+
+use std::{io, path::Path};
+
+const AR_MAGIC: &[u8] = b"!<arch>\n";
+const AR_THIN_MAGIC: &[u8] = b"!<thin>\n";
+const AR_HEADER_SIZE: usize = 60;
+
+/// A member of an `ar` archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    pub name: String,
+    pub mtime: u64,
+    pub uid: u32,
+    pub gid: u32,
+    /// The octal mode, e.g. `0o100644`.
+    pub mode: u32,
+    /// Empty for members of thin archives.
+    pub data: Vec<u8>,
+}
+
+/// The members of the `ar` archive at `path`, without the symbol table and
+/// the long name table.
+pub fn members(path: &Path) -> io::Result<Vec<Member>> {
+    let data = fs_err::read(path)?;
+    parse(&data).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {e}", path.display()),
+        )
+    })
+}
+
+/// What keeps the archive at `path` from being reproducible: members with a
+/// timestamp, owner, group or mode, and members out of order. Empty when
+/// there is nothing.
+pub fn check_archive(path: &Path) -> io::Result<Vec<String>> {
+    let members = members(path)?;
+    let mut problems = Vec::new();
+    for member in &members {
+        if member.mtime != 0 {
+            problems.push(format!("{} has timestamp {}", member.name, member.mtime));
+        }
+        if member.uid != 0 || member.gid != 0 {
+            problems.push(format!(
+                "{} has owner {} and group {}",
+                member.name, member.uid, member.gid
+            ));
+        }
+        if member.mode & 0o777 != 0o644 {
+            problems.push(format!("{} has mode {:o}", member.name, member.mode));
+        }
+    }
+    if !members.is_sorted_by(|a, b| a.name <= b.name) {
+        problems.push("members aren't sorted by name".into());
+    }
+    Ok(problems)
+}
+
+/// How the archives at `a` and `b` differ: members only one of them has,
+/// members whose contents differ and a different member order. Empty when
+/// they hold the same files.
+pub fn compare(a: &Path, b: &Path) -> io::Result<Vec<String>> {
+    let (a_members, b_members) = (members(a)?, members(b)?);
+    let mut problems = Vec::new();
+    for member in &a_members {
+        match b_members.iter().find(|other| other.name == member.name) {
+            None => problems.push(format!("only {} has {}", a.display(), member.name)),
+            Some(other) if other.data != member.data => {
+                problems.push(format!("{} differs between builds", member.name));
+            }
+            Some(_) => {}
+        }
+    }
+    for member in &b_members {
+        if !a_members.iter().any(|other| other.name == member.name) {
+            problems.push(format!("only {} has {}", b.display(), member.name));
+        }
+    }
+    let names = |members: &[Member]| members.iter().map(|m| m.name.clone()).collect::<Vec<_>>();
+    if problems.is_empty() && names(&a_members) != names(&b_members) {
+        problems.push("members are in a different order".into());
+    }
+    Ok(problems)
+}
+
+fn parse(data: &[u8]) -> Result<Vec<Member>, String> {
+    let thin = data.starts_with(AR_THIN_MAGIC);
+    if !thin && !data.starts_with(AR_MAGIC) {
+        return Err("not an ar archive".into());
+    }
+    let mut members = Vec::new();
+    let mut long_names: &[u8] = &[];
+    let mut offset = AR_MAGIC.len();
+    while let Some(header) = data.get(offset..offset + AR_HEADER_SIZE) {
+        let field = |range: std::ops::Range<usize>| {
+            std::str::from_utf8(&header[range])
+                .map(str::trim_end)
+                .map_err(|_| "bad archive member header".to_owned())
+        };
+        let size: usize = field(48..58)?
+            .parse()
+            .map_err(|_| "bad archive member size")?;
+        let number = |range, radix| {
+            let value = field(range)?;
+            if value.is_empty() {
+                return Ok(0);
+            }
+            u64::from_str_radix(value, radix).map_err(|_| format!("bad archive field {value:?}"))
+        };
+        let mut start = offset + AR_HEADER_SIZE;
+        let raw_name = field(0..16)?;
+        // Symbol tables and long name tables are always stored in full,
+        // even in thin archives.
+        let special =
+            matches!(raw_name, "/" | "//" | "/SYM64/") || raw_name.starts_with("__.SYMDEF");
+        let stored = if thin && !special { 0 } else { size };
+        let name = if raw_name == "//" {
+            long_names = data.get(start..start + size).ok_or("truncated archive")?;
+            None
+        } else if special {
+            None
+        } else if let Some(len) = raw_name.strip_prefix("#1/") {
+            // BSD archives put long names before the member's data.
+            let len: usize = len.parse().map_err(|_| "bad BSD member name")?;
+            let name = data.get(start..start + len).ok_or("truncated archive")?;
+            start += len;
+            Some(
+                String::from_utf8_lossy(name)
+                    .trim_end_matches('\0')
+                    .to_owned(),
+            )
+        } else if let Some(index) = raw_name.strip_prefix('/') {
+            let index: usize = index.parse().map_err(|_| "bad GNU member name")?;
+            let rest = long_names.get(index..).ok_or("bad GNU member name")?;
+            let end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+            Some(
+                String::from_utf8_lossy(&rest[..end])
+                    .trim_end_matches('/')
+                    .to_owned(),
+            )
+        } else {
+            Some(raw_name.trim_end_matches('/').to_owned())
+        };
+        if let Some(name) = name.filter(|name| !name.starts_with("__.SYMDEF")) {
+            let header_len = start - offset - AR_HEADER_SIZE;
+            let data_len = stored.saturating_sub(header_len);
+            members.push(Member {
+                name,
+                mtime: number(16..28, 10)?,
+                uid: number(28..34, 10)? as u32,
+                gid: number(34..40, 10)? as u32,
+                mode: number(40..48, 8)? as u32,
+                data: data
+                    .get(start..start + data_len)
+                    .ok_or("truncated archive")?
+                    .to_vec(),
+            });
+        }
+        offset = (offset + AR_HEADER_SIZE + stored).next_multiple_of(2);
+    }
+    Ok(members)
+}
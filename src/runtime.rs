@@ -0,0 +1,165 @@
+//! Which of Zig's runtime libraries a [`Build`](crate::Build) links next to
+//! Rust's own. Getting this wrong is the usual source of duplicate
+//! `__udivti3`s, `_Unwind_RaiseException`s resolved to the wrong unwinder and
+//! `std::__1` symbols that no library defines, so [`Runtime::for_target()`]
+//! picks what fits the runtime rustc already links for each target:
+//!
+//! - Rust's `compiler_builtins` covers the compiler-rt routines C code calls,
+//!   so Zig's compiler-rt isn't bundled.
+//! - Unwinding goes through whatever Rust's `std` links (`libgcc_s` on
+//!   glibc, the LLVM libunwind rustc ships for musl, `libSystem` on Apple).
+//!   A second unwinder has its own frame registry, and exceptions thrown
+//!   through the other one's frames terminate.
+//! - `zig c++` compiles against Zig's libc++ headers, so C++ code links
+//!   Zig's libc++ and libc++abi statically, except on Apple targets, whose
+//!   system libc++ has the same ABI, MSVC targets, where the MSVC linker
+//!   resolves the C++ runtime itself, and UEFI targets, which have none.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+use crate::{Toolchain, process};
+
+/// Where the C++ standard library comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CxxStdlib {
+    /// Zig's libc++ and libc++abi, built for the target and linked
+    /// statically.
+    Zig,
+    /// The target system's shared libc++ (`-lc++`). Only sound where the
+    /// system C++ library is libc++, which the headers `zig c++` compiles
+    /// against belong to: Apple targets, FreeBSD, OpenBSD, and Linux systems
+    /// with libc++ rather than libstdc++.
+    System,
+    /// Nothing; the crate links a C++ runtime itself.
+    None,
+}
+
+/// Which unwinder `_Unwind_*` calls from C and C++ code resolve to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unwinder {
+    /// The one Rust's `std` links.
+    Rust,
+    /// Zig's libunwind, linked statically, for `no_std` binaries that still
+    /// throw C++ exceptions. Don't combine with a `std` that brings its own.
+    Zig,
+}
+
+/// The runtime libraries linked with a [`Build`](crate::Build)'s library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Runtime {
+    /// Bundles Zig's compiler-rt, for routines Rust's `compiler_builtins`
+    /// lacks. Its symbols are weak, so it doesn't clash with Rust's.
+    pub compiler_rt: bool,
+    pub unwinder: Unwinder,
+    /// The C++ standard library, linked only when the build has C++ sources.
+    pub cxx_stdlib: CxxStdlib,
+}
+
+impl Runtime {
+    /// The defaults described in the [module docs](self) for the rustc
+    /// target `rust_target`.
+    pub fn for_target(rust_target: &str) -> Self {
+        let cxx_stdlib = if rust_target.contains("-apple-") {
+            CxxStdlib::System
+        } else if rust_target.ends_with("-msvc") || rust_target.contains("-uefi") {
+            CxxStdlib::None
+        } else {
+            CxxStdlib::Zig
+        };
+        Self {
+            compiler_rt: false,
+            unwinder: Unwinder::Rust,
+            cxx_stdlib,
+        }
+    }
+
+    /// The Zig archives this runtime needs, by their `lib<name>.a` names:
+    /// `c++`, `c++abi`, `unwind` and `compiler_rt`. `cpp` is whether the
+    /// build has C++ sources.
+    pub(crate) fn zig_libs(&self, cpp: bool) -> Vec<&'static str> {
+        let mut libs = Vec::new();
+        if cpp && self.cxx_stdlib == CxxStdlib::Zig {
+            libs.extend(["c++", "c++abi"]);
+        }
+        if self.unwinder == Unwinder::Zig {
+            libs.push("unwind");
+        }
+        if self.compiler_rt {
+            libs.push("compiler_rt");
+        }
+        libs
+    }
+}
+
+/// `(zig binary, Zig target)` of a located set of runtime archives.
+type RuntimeKey = (PathBuf, String);
+
+/// Finds the runtime archives Zig builds for `zig_target`, by name (`c++`,
+/// `c++abi`, `unwind`, `compiler_rt`). Zig only builds them on demand for a
+/// link, so this links a throwing C++ probe executable in `dir` with
+/// `ZIG_VERBOSE_LINK` and picks their cache paths out of the printed linker
+/// command line. Results are cached per toolchain and target for the
+/// lifetime of the process.
+pub(crate) fn zig_archives(
+    toolchain: &Toolchain,
+    zig_target: &str,
+    dir: &Path,
+) -> Result<HashMap<String, PathBuf>, Box<dyn Error>> {
+    static ARCHIVES: OnceLock<Mutex<HashMap<RuntimeKey, HashMap<String, PathBuf>>>> =
+        OnceLock::new();
+    let key = (toolchain.zig(), zig_target.to_owned());
+    let archives = ARCHIVES.get_or_init(Default::default);
+    if let Some(found) = archives.lock().unwrap().get(&key) {
+        return Ok(found.clone());
+    }
+
+    fs_err::create_dir_all(dir)?;
+    let source = dir.join("probe.cpp");
+    fs_err::write(
+        &source,
+        "#include <stdexcept>\nint main() { try { throw std::runtime_error(\"\"); } catch (...) {} }\n",
+    )?;
+    let output = process::output(
+        toolchain
+            .command()
+            .env("ZIG_VERBOSE_LINK", "1")
+            .arg("c++")
+            .arg("-target")
+            .arg(zig_target)
+            .arg(&source)
+            .arg("-o")
+            .arg(dir.join("probe")),
+    )?;
+    if !output.status.success() {
+        return Err(format!(
+            "linking a C++ probe for {zig_target} failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+    let found = parse_archives(&String::from_utf8_lossy(&output.stderr));
+    archives.lock().unwrap().insert(key, found.clone());
+    Ok(found)
+}
+
+/// The runtime archives named on a linker command line.
+fn parse_archives(link_line: &str) -> HashMap<String, PathBuf> {
+    link_line
+        .split_whitespace()
+        .filter_map(|arg| {
+            let path = Path::new(arg);
+            let name = path.file_name()?.to_str()?;
+            let name = name
+                .strip_suffix(".a")
+                .or_else(|| name.strip_suffix(".lib"))?;
+            let name = name.strip_prefix("lib").unwrap_or(name);
+            matches!(name, "c++" | "c++abi" | "unwind" | "compiler_rt")
+                .then(|| (name.to_owned(), path.to_owned()))
+        })
+        .collect()
+}
@@ -0,0 +1,216 @@
+//! An end-to-end check that this machine can build with Zig, for CI to run
+//! before a long build rather than failing an hour into it. [`self_test()`]
+//! goes through the whole chain for the host: the toolchain is there and
+//! runs, [`Build`] compiles a Zig library and emits its bindings, and a
+//! Rust program built with `rustc` links it and calls into it:
+//!
+//! ```no_run
+//! // tests/environment.rs
+//! #[test]
+//! fn environment() {
+//!     let report = zig_rs::self_test().unwrap();
+//!     eprintln!("{report}");
+//! }
+//! ```
+//!
+//! The error names the step that failed. The work happens in a temporary
+//! directory, kept when [`KEEP_ENV`](crate::testing::KEEP_ENV) is set.
+//! `rustc` is `RUSTC` when set, as Cargo sets it for build scripts, else the
+//! one on `PATH`.
+
+use std::{
+    env,
+    error::Error,
+    fmt, io,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    BUILD_INFO, Build, Toolchain, error::command_line, process, testing::KEEP_ENV, zig_build,
+};
+
+/// Base name of the sample library, its export and the program calling it.
+const NAME: &str = "zig_rs_self_test";
+
+const SOURCE: &str = "\
+export fn zig_rs_self_test(a: i32, b: i32) i32 {
+    return a *% b +% 1;
+}
+";
+
+/// What [`self_test()`] went through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// The rustc target it built for.
+    pub target: String,
+    /// What `zig version` printed.
+    pub zig_version: String,
+    pub steps: Vec<Step>,
+}
+
+/// One step of a [`Report`], with how long it took.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub name: &'static str,
+    pub duration: Duration,
+}
+
+/// Runs the [self test](self) with the bundled toolchain; see
+/// [`self_test_with()`].
+pub fn self_test() -> Result<Report, Box<dyn Error>> {
+    self_test_with(&Toolchain::bundled())
+}
+
+/// Runs the [self test](self) with `toolchain`, for the target this crate
+/// was built for.
+pub fn self_test_with(toolchain: &Toolchain) -> Result<Report, Box<dyn Error>> {
+    let dir = env::temp_dir().join(format!("{NAME}-{}", std::process::id()));
+    if dir.exists() {
+        fs_err::remove_dir_all(&dir)?;
+    }
+    fs_err::create_dir_all(&dir)?;
+    let result = run(toolchain, &dir);
+    if env::var_os(KEEP_ENV).is_none() {
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+    result
+}
+
+fn run(toolchain: &Toolchain, dir: &Path) -> Result<Report, Box<dyn Error>> {
+    let target = BUILD_INFO.target.to_owned();
+    let mut steps = Vec::new();
+    let mut step = |name: &'static str, f: &mut dyn FnMut() -> Result<(), Box<dyn Error>>| {
+        let start = Instant::now();
+        f().map_err(|e| format!("self test: {name}: {e}"))?;
+        steps.push(Step {
+            name,
+            duration: start.elapsed(),
+        });
+        Ok::<_, Box<dyn Error>>(())
+    };
+
+    let mut zig_version = String::new();
+    step("toolchain", &mut || {
+        // Toolchains installed without a manifest, e.g. a system `zig`, have
+        // nothing to verify against.
+        match toolchain.verify() {
+            Ok(verification) if !verification.is_ok() => {
+                let damaged: Vec<_> = verification
+                    .damaged()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                return Err(format!("damaged files: {}", damaged.join(", ")).into());
+            }
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        zig_version = toolchain.query_version()?.to_string();
+        Ok(())
+    })?;
+
+    let source = dir.join(format!("{NAME}.zig"));
+    let out_dir = dir.join("out");
+    let mut artifacts = None;
+    step("compile", &mut || {
+        fs_err::write(&source, SOURCE)?;
+        artifacts = Some(
+            Build::new()
+                .toolchain(toolchain.clone())
+                .target(&target)
+                .opt_level("0")
+                .out_dir(&out_dir)
+                .cargo_metadata(false)
+                .emit_bindings(true)
+                .file(&source)
+                .try_compile(NAME)?,
+        );
+        Ok(())
+    })?;
+    let artifacts = artifacts.expect("compiled");
+
+    let mut bindings = PathBuf::new();
+    step("bindings", &mut || {
+        let (rust, _) = artifacts
+            .bindings
+            .clone()
+            .ok_or("no bindings were emitted")?;
+        if !fs_err::read_to_string(&rust)?.contains(&format!("{NAME}(")) {
+            return Err(format!("{} doesn't declare {NAME}", rust.display()).into());
+        }
+        bindings = rust;
+        Ok(())
+    })?;
+
+    let exe = dir.join(format!("{NAME}{}", env::consts::EXE_SUFFIX));
+    step("link", &mut || {
+        let main = dir.join("main.rs");
+        fs_err::write(
+            &main,
+            format!(
+                "mod bindings {{\n    include!({:?});\n}}\n\n\
+                 fn main() {{\n    println!(\"{{}}\", unsafe {{ bindings::{NAME}(6, 7) }});\n}}\n",
+                bindings.display().to_string()
+            ),
+        )?;
+        let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+        let mut cmd = Command::new(rustc);
+        cmd.args(["--edition", "2024", "--target", &target])
+            .arg("-L")
+            .arg(format!("native={}", out_dir.display()))
+            .args(["-l", &format!("static={NAME}")]);
+        for lib in &artifacts.runtime_libs {
+            let file_name = lib.file_name().unwrap_or_default().to_string_lossy();
+            if let Some((kind, name)) = zig_build::library_kind_and_name(&file_name) {
+                cmd.arg("-L")
+                    .arg(format!("native={}", lib.parent().unwrap_or(dir).display()))
+                    .args(["-l", &format!("{kind}={name}")]);
+            }
+        }
+        for arg in &artifacts.link_args {
+            cmd.arg(format!("-Clink-arg={arg}"));
+        }
+        cmd.arg("-o").arg(&exe).arg(&main);
+        run_checked(&mut cmd).map(drop)
+    })?;
+
+    step("call", &mut || {
+        let stdout = run_checked(&mut Command::new(&exe))?;
+        match stdout.trim() {
+            "43" => Ok(()),
+            other => Err(format!("{NAME}(6, 7) returned {other:?}, not 43").into()),
+        }
+    })?;
+
+    Ok(Report {
+        target,
+        zig_version,
+        steps,
+    })
+}
+
+/// Runs `cmd` and returns its stdout, or fails with what it printed.
+fn run_checked(cmd: &mut Command) -> Result<String, Box<dyn Error>> {
+    let output = process::output(cmd)?;
+    if !output.status.success() {
+        return Err(format!(
+            "`{}` failed: {}\n{}",
+            command_line(cmd),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "zig {} for {}", self.zig_version, self.target)?;
+        for step in &self.steps {
+            writeln!(f, "  {:<10} ok in {:.2?}", step.name, step.duration)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,425 @@
+//! Wrapper scripts that run `zig cc`, `zig c++` and the bundled
+//! [binutils](crate::tools) for a fixed target, so Zig can be the linker,
+//! C compiler and archiver of a Rust cross-compilation, as with
+//! cargo-zigbuild. Cargo and cc-rs only take a program to run, not one with
+//! arguments, so each wrapper bakes in the `zig` binary, its environment
+//! and `-target`:
+//!
+//! ```no_run
+//! // xtask
+//! use std::process::Command;
+//!
+//! let shims = zig_rs::shims::write("x86_64-unknown-linux-gnu.2.17", "target/zig-shims".as_ref())?;
+//! let mut cargo = Command::new("cargo");
+//! cargo.args(["build", "--release", "--target", &shims.target]);
+//! shims.apply(&mut cargo);
+//! let status = cargo.status()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! A glibc pin on the target (`x86_64-unknown-linux-gnu.2.17`) or in
+//! [`GLIBC_VERSION_ENV`](crate::target::GLIBC_VERSION_ENV) goes into the Zig
+//! target, and [`Shims::target`] is the triple without it, as Cargo
+//! wants it. On Unix the wrappers are shell scripts that translate rustc's
+//! `-lgcc_s`/`-lgcc_eh` into Zig's libunwind and drop `-lgcc` and
+//! `--target=`, the way [`ZigExe`](crate::zig_exe::ZigExe) does; on Windows
+//! they are `.cmd` files that pass arguments through. The `dlltool` and
+//! `lib` wrappers of Windows targets pass the target's machine, so import
+//! libraries for `arm64ec-pc-windows-msvc` get its symbols.
+//!
+//! [`write_with()`] also has the `cc` and `c++` wrappers pass the
+//! [`LinkOptions`] of the binaries they link, with the flags
+//! [`Build`](crate::Build) uses for them: a `-dynamic-linker` override for
+//! NixOS, `--allow-shlib-undefined`, `-z defs`, and the subsystem, stack
+//! size and embedded manifest of Windows binaries. Command lines that only
+//! compile (`-c`, `-E`, `-S`) don't get them.
+//!
+//! With [`cc_cache::PROGRAM_ENV`] naming the `zig-cc-cache` program, the
+//! `cc` and `c++` wrappers run the compiler through it, so the compiles of
+//! `./configure` probes and vendored C code come from the object cache when
+//! they've been done before. With [`cl::PROGRAM_ENV`] naming the `zig-cl`
+//! program, those of `*-msvc` targets also take `cl.exe` command lines
+//! (`/MD`, `/O2`, `/I`, `/D`, `/Fo`, ...) and [translate](cl::translate)
+//! them, for tools that drive the compiler in MSVC mode.
+
+use std::{
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    Error, Toolchain, cc_cache, cl,
+    compile::{self, Subsystem},
+    diagnostic,
+    error::CommandError,
+    process, target, tools,
+};
+
+/// The wrappers [`write()`] made for one target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shims {
+    /// The rustc target without a glibc pin, e.g. `x86_64-unknown-linux-gnu`.
+    pub target: String,
+    /// The Zig target the wrappers pass, e.g. `x86_64-linux-gnu.2.17`.
+    pub zig_target: String,
+    /// Runs `zig cc`; also the linker.
+    pub cc: PathBuf,
+    /// Runs `zig c++`.
+    pub cxx: PathBuf,
+    /// Runs `zig ar`.
+    pub ar: PathBuf,
+    /// Runs `zig ranlib`.
+    pub ranlib: PathBuf,
+    /// Runs `zig dlltool`.
+    pub dlltool: PathBuf,
+    /// Runs `zig objcopy`.
+    pub objcopy: PathBuf,
+    /// Runs `zig lib`.
+    pub lib: PathBuf,
+}
+
+/// Linker settings the `cc` and `c++` wrappers add when they link, as the
+/// [`Build`](crate::Build) options of the same names do for the binaries a
+/// library is linked into. Options that don't apply to the target are
+/// ignored, as by `Build`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkOptions {
+    /// The ELF interpreter of linked executables; see
+    /// [`Build::dynamic_linker()`](crate::Build::dynamic_linker).
+    pub dynamic_linker: Option<PathBuf>,
+    /// `--allow-shlib-undefined` or `--no-allow-shlib-undefined`; see
+    /// [`Build::allow_shlib_undefined()`](crate::Build::allow_shlib_undefined).
+    pub allow_shlib_undefined: Option<bool>,
+    /// `-z defs` (`-undefined error` on Apple) for shared libraries, links
+    /// with `-shared` or `-dynamiclib`; see
+    /// [`Build::no_undefined()`](crate::Build::no_undefined).
+    pub no_undefined: bool,
+    /// The PE subsystem of Windows and UEFI binaries; see
+    /// [`Build::windows_subsystem()`](crate::Build::windows_subsystem).
+    pub subsystem: Option<Subsystem>,
+    /// An application manifest to embed into Windows binaries, compiled
+    /// with `zig rc` when the wrappers are written; see
+    /// [`Build::windows_manifest()`](crate::Build::windows_manifest).
+    pub windows_manifest: Option<PathBuf>,
+    /// The main thread's stack size in bytes on Windows and UEFI; see
+    /// [`Build::stack_size()`](crate::Build::stack_size).
+    pub stack_size: Option<u64>,
+}
+
+/// What the `cc` and `c++` wrappers add to the command lines that link
+/// rather than compile (`-c`), preprocess (`-E`) or emit assembly (`-S`).
+#[derive(Debug, Clone, Default)]
+struct LinkFlags {
+    every: Vec<String>,
+    /// Only added to links of shared libraries.
+    shared: Vec<String>,
+}
+
+/// Writes wrappers for the bundled toolchain; see [`write_for()`].
+pub fn write(rust_target: &str, dir: &Path) -> Result<Shims, Error> {
+    write_for(&Toolchain::bundled(), rust_target, dir)
+}
+
+/// Writes `zig-cc-<target>`, `zig-c++-<target>`, `zig-ar-<target>` and so
+/// on for each of the [`tools`](crate::tools) (`.cmd` on Windows) running
+/// `toolchain` for `rust_target` into `dir`. The `cc` and `c++` wrappers
+/// run `zig-cc-cache` instead of `zig` when [`cc_cache::PROGRAM_ENV`] is set,
+/// and those of `*-msvc` targets `zig-cl` when [`cl::PROGRAM_ENV`] is.
+pub fn write_for(toolchain: &Toolchain, rust_target: &str, dir: &Path) -> Result<Shims, Error> {
+    write_with(toolchain, rust_target, dir, &LinkOptions::default())
+}
+
+/// Like [`write_for()`], with `options` added to the command lines the `cc`
+/// and `c++` wrappers link with:
+///
+/// ```no_run
+/// use zig_rs::shims::{self, LinkOptions};
+///
+/// let options = LinkOptions {
+///     dynamic_linker: Some("/nix/store/…-glibc-2.40/lib/ld-linux-x86-64.so.2".into()),
+///     no_undefined: true,
+///     ..LinkOptions::default()
+/// };
+/// let toolchain = zig_rs::Toolchain::bundled();
+/// shims::write_with(&toolchain, "x86_64-unknown-linux-gnu", "target/zig-shims".as_ref(), &options)?;
+/// # Ok::<(), zig_rs::Error>(())
+/// ```
+pub fn write_with(
+    toolchain: &Toolchain,
+    rust_target: &str,
+    dir: &Path,
+    options: &LinkOptions,
+) -> Result<Shims, Error> {
+    let rust_target = &target::pin_glibc_version(rust_target, None);
+    let zig_target = target::zig_target(rust_target)
+        .ok_or_else(|| Error::Config(format!("no Zig target for {rust_target}")))?;
+    target::check_glibc_version(toolchain, &zig_target)
+        .map_err(|e| Error::Config(e.to_string()))?;
+    let (target, _) = target::split_glibc_version(rust_target);
+    fs_err::create_dir_all(dir)?;
+    let mut link_flags = LinkFlags {
+        every: compile::loader_link_args(
+            target,
+            options.dynamic_linker.as_deref(),
+            options.allow_shlib_undefined,
+        )?,
+        shared: if options.no_undefined {
+            compile::no_undefined_link_arg(target)
+                .into_iter()
+                .map(String::from)
+                .collect()
+        } else {
+            Vec::new()
+        },
+    };
+    if target.contains("-windows") || target.contains("-uefi") {
+        // `zig cc` is the linker even for MSVC targets.
+        link_flags.every.extend(compile::subsystem_link_args(
+            target,
+            options.subsystem,
+            options.stack_size,
+            false,
+        )?);
+    }
+    if let Some(manifest) = &options.windows_manifest
+        && target.contains("-windows")
+    {
+        let manifest = std::path::absolute(manifest)?;
+        let resource_dir = dir.join(format!("zig-manifest-{target}"));
+        fs_err::create_dir_all(&resource_dir)?;
+        let (mut cmd, res) = compile::manifest_resource(toolchain, &manifest, &resource_dir)?;
+        let output = process::output(&mut cmd)?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+            return Err(Error::Command(Box::new(CommandError::new(
+                &cmd,
+                output.status,
+                diagnostic::parse(&stderr),
+                stderr,
+            ))));
+        }
+        link_flags.every.push(res.display().to_string());
+    }
+    let command = toolchain.command();
+    let envs: Vec<(OsString, OsString)> = command
+        .get_envs()
+        .filter_map(|(key, value)| Some((key.to_owned(), value?.to_owned())))
+        .collect();
+    let zig = toolchain.zig();
+    let proxy = env::var_os(cc_cache::PROGRAM_ENV).map(PathBuf::from);
+    let cl = env::var_os(cl::PROGRAM_ENV)
+        .filter(|_| target.ends_with("-msvc"))
+        .map(PathBuf::from);
+    let root = toolchain.root().to_string_lossy();
+    let write = |tool: &str, args: &[&str]| -> Result<PathBuf, Error> {
+        let name = format!("zig-{tool}-{target}");
+        let path = if cfg!(windows) {
+            dir.join(format!("{name}.cmd"))
+        } else {
+            dir.join(name)
+        };
+        let link = matches!(tool, "cc" | "c++").then_some(&link_flags);
+        let script =
+            match (&cl, &proxy) {
+                // zig-cl passes the target on and runs zig-cc-cache's cache
+                // itself.
+                (Some(cl), _) if link.is_some() => {
+                    let mut envs = envs.clone();
+                    envs.extend(proxy.iter().map(|proxy| {
+                        (cc_cache::PROGRAM_ENV.into(), proxy.clone().into_os_string())
+                    }));
+                    let cl_args = [&*root, toolchain.zig_version(), tool, &zig_target];
+                    script(cl, &envs, &cl_args, link)
+                }
+                (None, Some(proxy)) if link.is_some() => {
+                    let mut proxy_args = vec![&*root, toolchain.zig_version()];
+                    proxy_args.extend(args);
+                    script(proxy, &envs, &proxy_args, link)
+                }
+                _ => script(&zig, &envs, args, link),
+            };
+        fs_err::write(&path, script)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs_err::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(path)
+    };
+    // Import libraries made from `.def` files have no objects to take the
+    // machine from, and ARM64EC's can't be told from ARM64's by them.
+    let mut dlltool = vec!["dlltool"];
+    let mut lib = vec!["lib"];
+    let machine = tools::lib_machine(target).map(|machine| format!("/MACHINE:{machine}"));
+    if target.contains("-windows") {
+        dlltool.extend(
+            tools::dlltool_machine(target)
+                .into_iter()
+                .flat_map(|m| ["-m", m]),
+        );
+        lib.extend(machine.as_deref());
+    }
+    Ok(Shims {
+        cc: write("cc", &["cc", "-target", &zig_target])?,
+        cxx: write("c++", &["c++", "-target", &zig_target])?,
+        ar: write("ar", &["ar"])?,
+        ranlib: write("ranlib", &["ranlib"])?,
+        dlltool: write("dlltool", &dlltool)?,
+        objcopy: write("objcopy", &["objcopy"])?,
+        lib: write("lib", &lib)?,
+        target: target.into(),
+        zig_target,
+    })
+}
+
+impl Shims {
+    /// The environment that makes Cargo link with the wrapper and cc-rs
+    /// compile with them: `CARGO_TARGET_<TARGET>_LINKER`, `CC_<target>`,
+    /// `CXX_<target>`, `AR_<target>` and `RANLIB_<target>`. ARM64EC targets
+    /// keep rustc's linker, since Zig can't link ARM64EC images; the
+    /// wrappers compile x64 code, which those images take.
+    pub fn env(&self) -> Vec<(String, PathBuf)> {
+        let target = self.target.replace(['-', '.'], "_");
+        let mut env = Vec::new();
+        if !self.target.starts_with("arm64ec-") {
+            env.push((
+                format!("CARGO_TARGET_{}_LINKER", target.to_uppercase()),
+                self.cc.clone(),
+            ));
+        }
+        env.extend([
+            (format!("CC_{target}"), self.cc.clone()),
+            (format!("CXX_{target}"), self.cxx.clone()),
+            (format!("AR_{target}"), self.ar.clone()),
+            (format!("RANLIB_{target}"), self.ranlib.clone()),
+        ]);
+        env
+    }
+
+    /// Sets [`env()`](Self::env) on a `cargo` command.
+    pub fn apply(&self, cargo: &mut Command) {
+        cargo.envs(self.env());
+    }
+
+    /// The same settings as a `.cargo/config.toml` snippet, for builds that
+    /// aren't run from an xtask.
+    pub fn config_toml(&self) -> String {
+        let quote = |path: &Path| toml::Value::from(path.display().to_string()).to_string();
+        let mut config = String::new();
+        let mut env = String::from("[env]\n");
+        for (key, value) in self.env() {
+            if key.starts_with("CARGO_TARGET_") {
+                config += &format!("[target.{}]\nlinker = {}\n\n", self.target, quote(&value));
+            } else {
+                env += &format!("{key} = {}\n", quote(&value));
+            }
+        }
+        config + &env
+    }
+}
+
+/// A wrapper running `zig` with `envs` and `args` followed by its own
+/// arguments, translated for the linker and with `link`'s flags added to
+/// links when `link` is set.
+fn script(
+    zig: &Path,
+    envs: &[(OsString, OsString)],
+    args: &[&str],
+    link: Option<&LinkFlags>,
+) -> String {
+    let extra = link.is_some_and(|link| !link.every.is_empty() || !link.shared.is_empty());
+    if cfg!(windows) {
+        let mut script = String::from("@echo off\r\n");
+        for (key, value) in envs {
+            script += &format!(
+                "set \"{}={}\"\r\n",
+                key.to_string_lossy(),
+                value.to_string_lossy()
+            );
+        }
+        let mut run = format!("\"{}\" {} %*", zig.display(), args.join(" "));
+        if let Some(link) = link.filter(|_| extra) {
+            let quote = |flags: &[String]| -> String {
+                flags.iter().map(|flag| format!(" \"{flag}\"")).collect()
+            };
+            let every = quote(&link.every);
+            let shared = every.clone() + &quote(&link.shared);
+            script += &format!(
+                "setlocal\r\n\
+                 set \"zig_rs_compile=\"\r\n\
+                 set \"zig_rs_shared=\"\r\n\
+                 for %%a in (%*) do (\r\n\
+                 \x20   if \"%%~a\"==\"-c\" set \"zig_rs_compile=1\"\r\n\
+                 \x20   if \"%%~a\"==\"/c\" set \"zig_rs_compile=1\"\r\n\
+                 \x20   if \"%%~a\"==\"-E\" set \"zig_rs_compile=1\"\r\n\
+                 \x20   if \"%%~a\"==\"-S\" set \"zig_rs_compile=1\"\r\n\
+                 \x20   if \"%%~a\"==\"-shared\" set \"zig_rs_shared=1\"\r\n\
+                 \x20   if \"%%~a\"==\"-dynamiclib\" set \"zig_rs_shared=1\"\r\n\
+                 )\r\n\
+                 set \"zig_rs_flags=\"\r\n\
+                 if not defined zig_rs_compile set \"zig_rs_flags={every}\"\r\n\
+                 if not defined zig_rs_compile if defined zig_rs_shared set \"zig_rs_flags={shared}\"\r\n"
+            );
+            // Empty when compiling, so the flags don't draw warnings.
+            run += "%zig_rs_flags%";
+        }
+        script += &format!("{run}\r\n");
+        return script;
+    }
+    let quote = |s: &str| format!("'{}'", s.replace('\'', r"'\''"));
+    let mut script = String::from("#!/bin/sh\n");
+    for (key, value) in envs {
+        script += &format!(
+            "export {}={}\n",
+            key.to_string_lossy(),
+            quote(&value.to_string_lossy())
+        );
+    }
+    if let Some(link) = link {
+        if extra {
+            script += "link=1\nshared=\n";
+        }
+        // Zig links its own libunwind and compiler-rt, and the target comes
+        // from -target.
+        script += "for arg do\n\
+                   \x20   shift\n\
+                   \x20   case \"$arg\" in\n\
+                   \x20       -lgcc_s | -lgcc_eh) arg=-lunwind ;;\n\
+                   \x20       -lgcc | --target=*) continue ;;\n";
+        if extra {
+            script += "\x20       -c | /c | -E | -S) link= ;;\n\
+                       \x20       -shared | -dynamiclib) shared=1 ;;\n";
+        }
+        script += "\x20   esac\n\
+                   \x20   set -- \"$@\" \"$arg\"\n\
+                   done\n";
+        if extra {
+            let flags = |flags: &[String]| -> String {
+                flags
+                    .iter()
+                    .map(|flag| format!(" {}", quote(flag)))
+                    .collect()
+            };
+            script += "if [ \"$link\" ]; then\n";
+            if !link.every.is_empty() {
+                script += &format!("    set -- \"$@\"{}\n", flags(&link.every));
+            }
+            if !link.shared.is_empty() {
+                script += &format!(
+                    "    if [ \"$shared\" ]; then set -- \"$@\"{}; fi\n",
+                    flags(&link.shared)
+                );
+            }
+            script += "fi\n";
+        }
+    }
+    let args: Vec<String> = args.iter().map(|arg| quote(arg)).collect();
+    script += &format!(
+        "exec {} {} \"$@\"\n",
+        quote(&zig.to_string_lossy()),
+        args.join(" ")
+    );
+    script
+}
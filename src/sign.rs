@@ -0,0 +1,249 @@
+//! Signing build outputs in the build that writes them, for Windows
+//! Authenticode, `codesign` with a Developer ID, or detached GPG and
+//! minisign signatures. A [`Signer`] is a command or a closure;
+//! [`Build::signer()`](crate::Build::signer) and
+//! [`ZigExe::signer()`](crate::zig_exe::ZigExe::signer) run it on what they
+//! write and [`Toolchain::sign()`](crate::Toolchain::sign) on an installed
+//! `zig`:
+//!
+//! ```no_run
+//! use zig_rs::{sign::Signer, zig_exe::ZigExe};
+//!
+//! let exe = ZigExe::new("app", "zig/main.zig")
+//!     .target("x86_64-pc-windows-gnu")
+//!     .signer(Signer::command("osslsigncode", ["sign", "-pkcs12", "cert.p12", "-in"]))
+//!     .build_artifacts()?;
+//! println!("{:?}", exe.signature);
+//! # Ok::<(), zig_rs::Error>(())
+//! ```
+//!
+//! Every signature is recorded in the [`Index`] next to the signed file,
+//! `zig-rs.signatures`, a line per file and one more for a detached
+//! signature:
+//!
+//! ```text
+//! signature <sha256 of the signed file> <file>
+//! detached <detached signature>
+//! ```
+//!
+//! A file still matching its recorded digest, e.g. a library that was up to
+//! date, isn't signed again. With [`ENV`] set, builders with no signer of
+//! their own use that command.
+
+use std::{
+    env,
+    ffi::OsString,
+    fmt, io,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+};
+
+use crate::{error::command_line, manifest, process};
+
+/// File name of the [`Index`] next to signed files.
+pub const INDEX_FILE_NAME: &str = "zig-rs.signatures";
+
+/// Environment variable holding a signer command for builders that have
+/// none, split on whitespace; see [`Signer::command()`].
+pub const ENV: &str = "ZIG_RS_SIGNER";
+
+/// Signs files, in place or with a detached signature.
+#[derive(Clone)]
+pub enum Signer {
+    /// A program run with its arguments and then the file to sign. Programs
+    /// that write a detached signature print its path as the last line of
+    /// their output.
+    Command(OsString, Vec<OsString>),
+    /// A closure signing the file it's given and returning the path of the
+    /// detached signature it wrote, if any.
+    Fn(Arc<SignFn>),
+}
+
+type SignFn = dyn Fn(&Path) -> io::Result<Option<PathBuf>> + Send + Sync;
+
+/// A signed file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub path: PathBuf,
+    /// SHA-256 of the file after signing.
+    pub sha256: String,
+    /// The detached signature, for signers that write one.
+    pub detached: Option<PathBuf>,
+}
+
+/// The signatures of the files in a directory. Paths are relative to it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Index {
+    pub signatures: Vec<Signature>,
+}
+
+impl Signer {
+    /// Runs `program` with `args` and the path of the file to sign.
+    pub fn command<I, S>(program: impl Into<OsString>, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        Self::Command(program.into(), args.into_iter().map(Into::into).collect())
+    }
+
+    pub fn from_fn(
+        f: impl Fn(&Path) -> io::Result<Option<PathBuf>> + Send + Sync + 'static,
+    ) -> Self {
+        Self::Fn(Arc::new(f))
+    }
+
+    /// The command in [`ENV`], if it's set.
+    pub fn from_env() -> Option<Self> {
+        let value = env::var(ENV).ok()?;
+        let mut words = value.split_whitespace();
+        Some(Self::command(words.next()?, words))
+    }
+
+    /// Signs `path` and returns its signature, without recording it.
+    pub fn sign(&self, path: &Path) -> io::Result<Signature> {
+        let detached = match self {
+            Self::Command(program, args) => {
+                let mut cmd = Command::new(program);
+                cmd.args(args).arg(path);
+                let output = process::output(&mut cmd)?;
+                if !output.status.success() {
+                    return Err(io::Error::other(format!(
+                        "`{}` failed: {}\n{}",
+                        command_line(&cmd),
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim_end()
+                    )));
+                }
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .rev()
+                    .map(str::trim)
+                    .find(|line| !line.is_empty())
+                    .map(PathBuf::from)
+                    .filter(|detached| detached != path && detached.is_file())
+            }
+            Self::Fn(f) => f(path)?,
+        };
+        Ok(Signature {
+            path: path.to_owned(),
+            sha256: manifest::hash_file(path)?,
+            detached,
+        })
+    }
+
+    /// Signs `path` unless the [`Index`] next to it records a signature of
+    /// it as it is now, and records the new signature.
+    pub fn sign_and_record(&self, path: &Path) -> io::Result<Signature> {
+        let dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let index_path = dir.join(INDEX_FILE_NAME);
+        let mut index = match Index::read(&index_path) {
+            Ok(index) => index,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Index::default(),
+            Err(e) => return Err(e),
+        };
+        let name = relative(dir, path);
+        if let Some(recorded) = index.signatures.iter().find(|s| s.path == name)
+            && recorded.sha256 == manifest::hash_file(path)?
+            && recorded
+                .detached
+                .as_ref()
+                .is_none_or(|detached| dir.join(detached).is_file())
+        {
+            return Ok(Signature {
+                path: path.to_owned(),
+                sha256: recorded.sha256.clone(),
+                detached: recorded
+                    .detached
+                    .as_ref()
+                    .map(|detached| dir.join(detached)),
+            });
+        }
+        let signature = self.sign(path)?;
+        index.signatures.retain(|s| s.path != name);
+        index.signatures.push(Signature {
+            path: name,
+            sha256: signature.sha256.clone(),
+            detached: signature
+                .detached
+                .as_ref()
+                .map(|detached| relative(dir, detached)),
+        });
+        index.signatures.sort_by(|a, b| a.path.cmp(&b.path));
+        index.write(&index_path)?;
+        Ok(signature)
+    }
+}
+
+impl fmt::Debug for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Command(program, args) => {
+                f.debug_tuple("Command").field(program).field(args).finish()
+            }
+            Self::Fn(_) => f.write_str("Fn(..)"),
+        }
+    }
+}
+
+impl Index {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let invalid = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {message}", path.display()),
+            )
+        };
+        let mut signatures: Vec<Signature> = Vec::new();
+        for line in fs_err::read_to_string(path)?.lines() {
+            let fields: Vec<&str> = line.splitn(3, ' ').collect();
+            match fields[..] {
+                ["signature", sha256, path] => signatures.push(Signature {
+                    path: path.into(),
+                    sha256: sha256.into(),
+                    detached: None,
+                }),
+                ["detached", ..] => {
+                    let signature = signatures
+                        .last_mut()
+                        .ok_or_else(|| invalid(format!("{line:?} before any signature")))?;
+                    signature.detached = Some(line["detached ".len()..].into());
+                }
+                _ => return Err(invalid(format!("bad line {line:?}"))),
+            }
+        }
+        Ok(Self { signatures })
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs_err::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for Index {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for signature in &self.signatures {
+            // Paths go last, since they may have spaces.
+            writeln!(
+                f,
+                "signature {} {}",
+                signature.sha256,
+                signature.path.to_string_lossy()
+            )?;
+            if let Some(detached) = &signature.detached {
+                writeln!(f, "detached {}", detached.to_string_lossy())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `path` relative to `dir` with forward slashes, or as it is outside it.
+fn relative(dir: &Path, path: &Path) -> PathBuf {
+    let relative = path.strip_prefix(dir).unwrap_or(path);
+    relative.to_string_lossy().replace('\\', "/").into()
+}
@@ -0,0 +1,346 @@
+//! What this release of the crate supports, compiled in, for tools that show
+//! it to users and for tests that check a new release doesn't drop
+//! anything. The [`Matrix`] has two halves: for each host, the ways of
+//! getting a toolchain that apply to it, and for each rustc target, whether
+//! it maps to a Zig target and how well that's tested:
+//!
+//! ```
+//! use zig_rs::support::{Acquisition, Level, Matrix};
+//!
+//! let matrix = Matrix::current();
+//! let host = matrix.host("x86_64-unknown-linux-gnu").unwrap();
+//! assert!(host.acquisition.contains(&Acquisition::Prebuilt));
+//! assert_eq!(matrix.target("aarch64-apple-darwin").unwrap().level, Level::Tested);
+//!
+//! // Against a copy saved with an earlier release:
+//! let earlier = Matrix::parse(&matrix.to_string())?;
+//! assert!(matrix.regressions(&earlier).is_empty());
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! The text form is a line per host and per target:
+//!
+//! ```text
+//! host x86_64-unknown-linux-gnu archive,system,cache,prebuilt,source
+//! target x86_64-unknown-linux-gnu tested x86_64-linux-gnu
+//! target x86_64-unknown-uefi mapped x86_64-uefi-msvc
+//! target csky-unknown-linux-gnuabiv2 unmapped -
+//! ```
+//!
+//! The matrix covers the targets listed here, not every triple rustc knows:
+//! [`target::TargetSupport`](crate::target::TargetSupport) asks an installed
+//! toolchain about any of them.
+
+use std::{fmt, io, path::Path};
+
+use crate::{bootstrap_target, release, target_triple};
+
+/// A way of getting a toolchain, named as `ZIG_RS_TOOLCHAIN_ORDER` spells it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Acquisition {
+    /// A packed toolchain named by `ZIG_RS_TOOLCHAIN_ARCHIVE`.
+    Archive,
+    /// A `zig` already installed.
+    System,
+    /// A toolchain installed by an earlier build.
+    Cache,
+    /// An official release from ziglang.org.
+    Prebuilt,
+    /// A build of zig-bootstrap.
+    Source,
+}
+
+/// How well a rustc target is supported as a compilation target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Level {
+    /// No Zig target: [`Build`](crate::Build) fails for it.
+    Unmapped,
+    /// Maps to a Zig target, but isn't built for before releases.
+    Mapped,
+    /// Built for and its outputs run before every release.
+    Tested,
+}
+
+/// The ways of getting a toolchain on a host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Host {
+    /// The host's rustc target.
+    pub target: String,
+    /// In the default order.
+    pub acquisition: Vec<Acquisition>,
+}
+
+/// The support of a rustc target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub rust_target: String,
+    pub level: Level,
+    /// What it maps to, unless it's [`Level::Unmapped`].
+    pub zig_target: Option<String>,
+}
+
+/// See the [module documentation](self).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Matrix {
+    pub hosts: Vec<Host>,
+    pub targets: Vec<Target>,
+}
+
+/// `triple`, `"arch endian os env abi"` as `rustc --print cfg` reports them
+/// (`-` for empty), and whether it's [`Level::Tested`].
+#[rustfmt::skip]
+const TARGETS: &[(&str, &str, bool)] = &[
+    ("aarch64-apple-darwin", "aarch64 little macos - -", true),
+    ("aarch64-apple-ios", "aarch64 little ios - -", false),
+    ("aarch64-linux-android", "aarch64 little android - -", false),
+    ("aarch64-pc-windows-gnullvm", "aarch64 little windows gnu llvm", false),
+    ("aarch64-pc-windows-msvc", "aarch64 little windows msvc -", false),
+    ("aarch64-unknown-freebsd", "aarch64 little freebsd - -", false),
+    ("aarch64-unknown-linux-gnu", "aarch64 little linux gnu -", true),
+    ("aarch64-unknown-linux-musl", "aarch64 little linux musl -", true),
+    ("aarch64-unknown-netbsd", "aarch64 little netbsd - -", false),
+    ("aarch64-unknown-openbsd", "aarch64 little openbsd - -", false),
+    ("arm-unknown-linux-gnueabihf", "arm little linux gnu eabihf", false),
+    ("armv7-linux-androideabi", "arm little android - eabi", false),
+    ("armv7-unknown-linux-gnueabihf", "arm little linux gnu eabihf", false),
+    ("armv7-unknown-linux-musleabihf", "arm little linux musl eabihf", false),
+    ("csky-unknown-linux-gnuabiv2", "csky little linux gnu abiv2", false),
+    ("i686-pc-windows-gnu", "x86 little windows gnu -", false),
+    ("i686-pc-windows-msvc", "x86 little windows msvc -", false),
+    ("i686-unknown-linux-gnu", "x86 little linux gnu -", false),
+    ("i686-unknown-linux-musl", "x86 little linux musl -", false),
+    ("loongarch64-unknown-linux-gnu", "loongarch64 little linux gnu -", false),
+    ("powerpc64le-unknown-linux-gnu", "powerpc64 little linux gnu -", false),
+    ("riscv64gc-unknown-linux-gnu", "riscv64 little linux gnu -", false),
+    ("riscv64gc-unknown-linux-musl", "riscv64 little linux musl -", false),
+    ("s390x-unknown-linux-gnu", "s390x big linux gnu -", false),
+    ("sparcv9-sun-solaris", "sparc64 big solaris - -", false),
+    ("thumbv7em-none-eabihf", "arm little none - eabihf", false),
+    ("wasm32-unknown-emscripten", "wasm32 little emscripten - -", false),
+    ("wasm32-unknown-unknown", "wasm32 little unknown - -", false),
+    ("wasm32-wasip1", "wasm32 little wasi p1 -", true),
+    ("wasm32-wasip1-threads", "wasm32 little wasi p1 -", false),
+    ("x86_64-apple-darwin", "x86_64 little macos - -", true),
+    ("x86_64-linux-android", "x86_64 little android - -", false),
+    ("x86_64-pc-solaris", "x86_64 little solaris - -", false),
+    ("x86_64-pc-windows-gnu", "x86_64 little windows gnu -", true),
+    ("x86_64-pc-windows-gnullvm", "x86_64 little windows gnu llvm", false),
+    ("x86_64-pc-windows-msvc", "x86_64 little windows msvc -", true),
+    ("x86_64-unknown-dragonfly", "x86_64 little dragonfly - -", false),
+    ("x86_64-unknown-freebsd", "x86_64 little freebsd - -", false),
+    ("x86_64-unknown-illumos", "x86_64 little illumos - -", false),
+    ("x86_64-unknown-linux-gnu", "x86_64 little linux gnu -", true),
+    ("x86_64-unknown-linux-gnux32", "x86_64 little linux gnu x32", false),
+    ("x86_64-unknown-linux-musl", "x86_64 little linux musl -", true),
+    ("x86_64-unknown-netbsd", "x86_64 little netbsd - -", false),
+    ("x86_64-unknown-openbsd", "x86_64 little openbsd - -", false),
+    ("x86_64-unknown-uefi", "x86_64 little uefi - -", false),
+];
+
+impl Acquisition {
+    /// Every way, in the default order.
+    pub const ALL: [Self; 5] = [
+        Self::Archive,
+        Self::System,
+        Self::Cache,
+        Self::Prebuilt,
+        Self::Source,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Archive => "archive",
+            Self::System => "system",
+            Self::Cache => "cache",
+            Self::Prebuilt => "prebuilt",
+            Self::Source => "source",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|way| way.name() == name)
+    }
+}
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Unmapped => "unmapped",
+            Self::Mapped => "mapped",
+            Self::Tested => "tested",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [Self::Unmapped, Self::Mapped, Self::Tested]
+            .into_iter()
+            .find(|level| level.name() == name)
+    }
+}
+
+impl Matrix {
+    /// The matrix of this release. Hosts are the targets that have a
+    /// toolchain, prebuilt or built from source; the others can still use
+    /// one made elsewhere, but no `zig` of theirs is known to exist.
+    pub fn current() -> Self {
+        let mut matrix = Self::default();
+        for &(triple, fields, tested) in TARGETS {
+            let fields: Vec<&str> = fields
+                .split(' ')
+                .map(|field| if field == "-" { "" } else { field })
+                .collect();
+            let cfg = bootstrap_target::TargetCfg {
+                triple: triple.into(),
+                arch: fields[0].into(),
+                endian: fields[1].into(),
+                os: fields[2].into(),
+                env: fields[3].into(),
+                abi: fields[4].into(),
+            };
+            let prebuilt = release::target_platform(triple).is_some();
+            let source = bootstrap_target::zig_target_mcpu(&cfg).is_ok();
+            if prebuilt || source {
+                let acquisition = Acquisition::ALL
+                    .into_iter()
+                    .filter(|way| match way {
+                        Acquisition::Prebuilt => prebuilt,
+                        Acquisition::Source => source,
+                        _ => true,
+                    })
+                    .collect();
+                matrix.hosts.push(Host {
+                    target: triple.into(),
+                    acquisition,
+                });
+            }
+            let zig_target = target_triple::builtin_zig_target(triple);
+            let level = match &zig_target {
+                None => Level::Unmapped,
+                Some(_) if tested => Level::Tested,
+                Some(_) => Level::Mapped,
+            };
+            matrix.targets.push(Target {
+                rust_target: triple.into(),
+                level,
+                zig_target,
+            });
+        }
+        matrix
+    }
+
+    /// Reads the text form, as [`Display`](fmt::Display) writes it.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut matrix = Self::default();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[..] {
+                ["host", target, acquisition] => {
+                    let acquisition = acquisition
+                        .split(',')
+                        .map(|name| {
+                            Acquisition::from_name(name)
+                                .ok_or_else(|| invalid(format!("bad acquisition {name:?}")))
+                        })
+                        .collect::<io::Result<_>>()?;
+                    matrix.hosts.push(Host {
+                        target: target.into(),
+                        acquisition,
+                    });
+                }
+                ["target", rust_target, level, zig_target] => matrix.targets.push(Target {
+                    rust_target: rust_target.into(),
+                    level: Level::from_name(level)
+                        .ok_or_else(|| invalid(format!("bad level {level:?}")))?,
+                    zig_target: Some(zig_target).filter(|t| *t != "-").map(Into::into),
+                }),
+                _ => return Err(invalid(format!("bad line {line:?}"))),
+            }
+        }
+        Ok(matrix)
+    }
+
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs_err::read_to_string(path)?).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {e}", path.display()),
+            )
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs_err::write(path, self.to_string())
+    }
+
+    /// The ways of getting a toolchain on the host `rust_target`.
+    pub fn host(&self, rust_target: &str) -> Option<&Host> {
+        self.hosts.iter().find(|host| host.target == rust_target)
+    }
+
+    /// The support of `rust_target`.
+    pub fn target(&self, rust_target: &str) -> Option<&Target> {
+        self.targets
+            .iter()
+            .find(|target| target.rust_target == rust_target)
+    }
+
+    /// What `earlier` supports that this matrix doesn't, one message each: a
+    /// host or target gone, a way of getting a toolchain gone, a lower
+    /// level, or a different Zig target.
+    pub fn regressions(&self, earlier: &Self) -> Vec<String> {
+        let mut regressions = Vec::new();
+        for old in &earlier.hosts {
+            let Some(new) = self.host(&old.target) else {
+                regressions.push(format!("host {} is gone", old.target));
+                continue;
+            };
+            for way in &old.acquisition {
+                if !new.acquisition.contains(way) {
+                    regressions.push(format!("host {} lost {}", old.target, way.name()));
+                }
+            }
+        }
+        for old in &earlier.targets {
+            let Some(new) = self.target(&old.rust_target) else {
+                regressions.push(format!("target {} is gone", old.rust_target));
+                continue;
+            };
+            if new.level < old.level {
+                regressions.push(format!(
+                    "target {} went from {} to {}",
+                    old.rust_target,
+                    old.level.name(),
+                    new.level.name()
+                ));
+            } else if old.zig_target.is_some() && new.zig_target != old.zig_target {
+                regressions.push(format!(
+                    "target {} maps to {} instead of {}",
+                    old.rust_target,
+                    new.zig_target.as_deref().unwrap_or("-"),
+                    old.zig_target.as_deref().unwrap_or("-")
+                ));
+            }
+        }
+        regressions
+    }
+}
+
+impl fmt::Display for Matrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for host in &self.hosts {
+            let acquisition: Vec<&str> = host.acquisition.iter().map(|way| way.name()).collect();
+            writeln!(f, "host {} {}", host.target, acquisition.join(","))?;
+        }
+        for target in &self.targets {
+            writeln!(
+                f,
+                "target {} {} {}",
+                target.rust_target,
+                target.level.name(),
+                target.zig_target.as_deref().unwrap_or("-")
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+//! Readable stack traces for Zig code running inside a Rust process. Zig prints
+//! frames it has no debug info for as
+//!
+//! ```text
+//! ???:?:?: 0x7f3a1c2b4d5e in ??? (libfoo.so)
+//! ```
+//!
+//! which happens whenever the Zig side can't find the debug info at runtime,
+//! e.g. a static library linked into a Rust binary that was split or has its
+//! debug info in the executable rather than where Zig looks. [`symbolicate()`]
+//! resolves such frames against the debug info of the modules loaded in the
+//! current process, so a panic or crash handler can turn the trace it got
+//! into file, line and function names, Rust frames and Zig frames alike.
+//!
+//! Addresses are mapped to modules through `/proc/self/maps`, so resolution
+//! works on Linux and Android; elsewhere frames are left as they were.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use addr2line::Loader;
+use object::{Object as _, ObjectSegment as _};
+
+/// One frame of a Zig stack trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Runtime address of the instruction.
+    pub address: u64,
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    /// File name of the executable or shared library the address is in.
+    pub module: Option<String>,
+}
+
+impl Frame {
+    /// Whether the frame names a source location.
+    pub fn is_resolved(&self) -> bool {
+        self.file.is_some()
+    }
+}
+
+impl fmt::Display for Frame {
+    /// The format Zig prints frames in.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_unknown = |s: &Option<String>| s.clone().unwrap_or_else(|| "???".into());
+        let number = |n: Option<u32>| n.map_or("?".into(), |n| n.to_string());
+        write!(
+            f,
+            "{}:{}:{}: 0x{:x} in {} ({})",
+            or_unknown(&self.file),
+            number(self.line),
+            number(self.column),
+            self.address,
+            or_unknown(&self.function),
+            or_unknown(&self.module),
+        )
+    }
+}
+
+/// Parses the frame lines of a Zig stack trace, skipping the source excerpts
+/// and anything else in between.
+pub fn parse(trace: &str) -> Vec<Frame> {
+    trace.lines().filter_map(parse_line).collect()
+}
+
+/// `<file>:<line>:<column>: 0x<address> in <function> (<module>)`
+fn parse_line(line: &str) -> Option<Frame> {
+    let (location, rest) = line.trim().split_once(": 0x")?;
+    let (address, rest) = rest.split_once(" in ")?;
+    let address = u64::from_str_radix(address, 16).ok()?;
+    let (function, module) = rest.rsplit_once(" (")?;
+    let module = module.strip_suffix(')')?;
+    // The file itself may contain colons (`C:\...`).
+    let mut parts = location.rsplitn(3, ':');
+    let (column, line, file) = (parts.next()?, parts.next()?, parts.next()?);
+    let known = |s: &str| (s != "???").then(|| s.to_owned());
+    Some(Frame {
+        address,
+        function: known(function),
+        file: known(file),
+        line: line.parse().ok(),
+        column: column.parse().ok(),
+        module: known(module),
+    })
+}
+
+/// Rewrites every unresolved frame line of `trace` that can be resolved in
+/// the current process, leaving all other lines untouched. Inlined calls
+/// become a line each, innermost first.
+pub fn symbolicate(trace: &str) -> String {
+    let mut resolver = Resolver::default();
+    let mut out = String::with_capacity(trace.len());
+    for line in trace.lines() {
+        match parse_line(line) {
+            Some(frame) if !frame.is_resolved() => match resolver.resolve(frame.address) {
+                Some(frames) => {
+                    let indent = &line[..line.len() - line.trim_start().len()];
+                    for frame in frames {
+                        out.push_str(indent);
+                        out.push_str(&frame.to_string());
+                        out.push('\n');
+                    }
+                }
+                None => {
+                    out.push_str(line);
+                    out.push('\n');
+                }
+            },
+            _ => {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+/// Resolves `address` in the current process into its frames, innermost
+/// inlined call first. `None` when no loaded module has debug info for it.
+pub fn resolve(address: u64) -> Option<Vec<Frame>> {
+    Resolver::default().resolve(address)
+}
+
+/// Keeps each module's debug info loaded across lookups.
+#[derive(Default)]
+struct Resolver {
+    maps: Option<Vec<Mapping>>,
+    loaders: HashMap<PathBuf, Option<Loader>>,
+}
+
+/// A file-backed line of `/proc/self/maps`.
+struct Mapping {
+    start: u64,
+    end: u64,
+    offset: u64,
+    path: PathBuf,
+}
+
+impl Resolver {
+    fn resolve(&mut self, address: u64) -> Option<Vec<Frame>> {
+        let maps = self.maps.get_or_insert_with(read_maps);
+        let mapping = maps.iter().find(|m| (m.start..m.end).contains(&address))?;
+        let path = mapping.path.clone();
+        let probe = file_address(&path, address - mapping.start + mapping.offset)?;
+        let loader = self
+            .loaders
+            .entry(path.clone())
+            .or_insert_with(|| Loader::new(&path).ok())
+            .as_ref()?;
+
+        let module = path.file_name().map(|n| n.to_string_lossy().into_owned());
+        let mut frames = Vec::new();
+        let mut iter = loader.find_frames(probe).ok()?;
+        while let Ok(Some(frame)) = iter.next() {
+            let location = frame.location;
+            frames.push(Frame {
+                address,
+                function: frame
+                    .function
+                    .and_then(|f| f.demangle().ok().map(|name| name.into_owned())),
+                file: location.as_ref().and_then(|l| l.file.map(str::to_owned)),
+                line: location.as_ref().and_then(|l| l.line),
+                column: location.as_ref().and_then(|l| l.column),
+                module: module.clone(),
+            });
+        }
+        if frames.is_empty() {
+            let function = loader.find_symbol(probe)?;
+            frames.push(Frame {
+                address,
+                function: Some(addr2line::demangle_auto(function.into(), None).into_owned()),
+                file: None,
+                line: None,
+                column: None,
+                module,
+            });
+        }
+        Some(frames)
+    }
+}
+
+fn read_maps() -> Vec<Mapping> {
+    let Ok(maps) = fs_err::read_to_string("/proc/self/maps") else {
+        return Vec::new();
+    };
+    maps.lines()
+        .filter_map(|line| {
+            // start-end perms offset dev inode path
+            let mut fields = line.splitn(6, ' ');
+            let (start, end) = fields.next()?.split_once('-')?;
+            let offset = fields.nth(1)?;
+            let path = fields.nth(2)?.trim_start();
+            if !path.starts_with('/') {
+                return None;
+            }
+            Some(Mapping {
+                start: u64::from_str_radix(start, 16).ok()?,
+                end: u64::from_str_radix(end, 16).ok()?,
+                offset: u64::from_str_radix(offset, 16).ok()?,
+                path: path.into(),
+            })
+        })
+        .collect()
+}
+
+/// Converts an offset into the file at `path` into the address the debug
+/// info uses, through the segment containing it.
+fn file_address(path: &Path, file_offset: u64) -> Option<u64> {
+    let data = fs_err::read(path).ok()?;
+    let file = object::File::parse(&*data).ok()?;
+    file.segments().find_map(|segment| {
+        let (offset, size) = segment.file_range();
+        (offset..offset + size)
+            .contains(&file_offset)
+            .then(|| file_offset - offset + segment.address())
+    })
+}
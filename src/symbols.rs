@@ -0,0 +1,176 @@
+//! The symbols a static library defines for the linker, read from the
+//! archive's symbol table, and checking them against an allowlist, so that
+//! C or Zig code exporting more than it means to fails the build instead of
+//! colliding with another library in a downstream link.
+//! [`Build::allow_symbol()`](crate::Build::allow_symbol) checks the libraries
+//! it writes:
+//!
+//! ```no_run
+//! // build.rs
+//! zig_rs::Build::new()
+//!     .file("vendor/foo/foo.c")
+//!     .allow_symbol("foo_*")
+//!     .compile("foo");
+//! ```
+//!
+//! Every global symbol counts, whatever its visibility: visibility only
+//! takes effect when linking a shared library, and downstream links see the
+//! archive's objects as they are. Symbols are compared by their C names,
+//! without the `_` Apple and 32-bit Windows targets prefix them with.
+//! `cdylib`s only export what rustc and
+//! [`Build::export_symbol()`](crate::Build::export_symbol) export anyway.
+
+use std::{io, path::Path};
+
+const AR_MAGIC: &[u8] = b"!<arch>\n";
+const AR_THIN_MAGIC: &[u8] = b"!<thin>\n";
+const AR_HEADER_SIZE: usize = 60;
+
+/// The symbols the `ar` archive at `path` defines, sorted, from its symbol
+/// table.
+pub fn archive_symbols(path: &Path) -> io::Result<Vec<String>> {
+    let data = fs_err::read(path)?;
+    let mut symbols = symbol_table(&data).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {e}", path.display()),
+        )
+    })?;
+    symbols.sort();
+    symbols.dedup();
+    Ok(symbols)
+}
+
+/// The symbols of the archive at `path`, built for the rustc target
+/// `target`, that none of the `allowed` patterns match. `*` in a pattern
+/// matches any run of characters.
+pub fn unlisted<S: AsRef<str>>(
+    path: &Path,
+    target: &str,
+    allowed: &[S],
+) -> io::Result<Vec<String>> {
+    Ok(archive_symbols(path)?
+        .into_iter()
+        .filter(|symbol| {
+            let name = c_name(symbol, target);
+            !allowed
+                .iter()
+                .any(|pattern| matches(pattern.as_ref(), name))
+        })
+        .collect())
+}
+
+/// `symbol` without the `_` that object files for the rustc target `target`
+/// put in front of C names.
+pub fn c_name<'a>(symbol: &'a str, target: &str) -> &'a str {
+    let prefixed = target.contains("-apple-") || target.starts_with("i686-pc-windows");
+    match symbol.strip_prefix('_') {
+        Some(name) if prefixed => name,
+        _ => symbol,
+    }
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any run of
+/// characters.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let Some((prefix, rest)) = pattern.split_once('*') else {
+        return pattern == name;
+    };
+    let Some(mut name) = name.strip_prefix(prefix) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = rest.split('*').collect();
+    let suffix = parts.pop().unwrap_or_default();
+    for part in parts {
+        match name.find(part) {
+            Some(i) => name = &name[i + part.len()..],
+            None => return false,
+        }
+    }
+    name.ends_with(suffix)
+}
+
+/// The names in the symbol table of an archive, the GNU one (`/` or
+/// `/SYM64/`, also the first linker member of COFF archives) or the BSD one
+/// (`__.SYMDEF`), which comes first.
+fn symbol_table(data: &[u8]) -> Result<Vec<String>, String> {
+    if !data.starts_with(AR_MAGIC) && !data.starts_with(AR_THIN_MAGIC) {
+        return Err("not an ar archive".into());
+    }
+    let no_table = || "no symbol table; run `zig ranlib` on it".to_owned();
+    let header = data
+        .get(AR_MAGIC.len()..AR_MAGIC.len() + AR_HEADER_SIZE)
+        .ok_or_else(no_table)?;
+    let field = |range: std::ops::Range<usize>| {
+        std::str::from_utf8(&header[range])
+            .map(str::trim_end)
+            .map_err(|_| "bad archive member header".to_owned())
+    };
+    let size: usize = field(48..58)?
+        .parse()
+        .map_err(|_| "bad archive member size")?;
+    let start = AR_MAGIC.len() + AR_HEADER_SIZE;
+    let mut body = data.get(start..start + size).ok_or("truncated archive")?;
+    let mut name = field(0..16)?.to_owned();
+    if let Some(len) = name.strip_prefix("#1/") {
+        // BSD archives put long names before the member's data.
+        let len: usize = len.parse().map_err(|_| "bad BSD member name")?;
+        let long_name = body.get(..len).ok_or("truncated archive")?;
+        name = String::from_utf8_lossy(long_name)
+            .trim_end_matches('\0')
+            .to_owned();
+        body = &body[len..];
+    }
+    match name.as_str() {
+        "/" => gnu_symbols(body, 4),
+        "/SYM64/" => gnu_symbols(body, 8),
+        name if name.starts_with("__.SYMDEF") => bsd_symbols(body, name.contains("_64")),
+        _ => Err(no_table()),
+    }
+}
+
+/// A big-endian count of `width` bytes, as many offsets and the names.
+fn gnu_symbols(body: &[u8], width: usize) -> Result<Vec<String>, String> {
+    let count = read_uint(body, 0, width, false).ok_or("truncated symbol table")? as usize;
+    let names = body
+        .get(width * (count + 1)..)
+        .ok_or("truncated symbol table")?;
+    let symbols: Vec<String> = names
+        .split(|&b| b == 0)
+        .take(count)
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect();
+    if symbols.len() < count {
+        return Err("truncated symbol table".into());
+    }
+    Ok(symbols)
+}
+
+/// A little-endian size of the `ranlib` entries, the entries (name offset
+/// and member offset), the size of the string table and the string table.
+fn bsd_symbols(body: &[u8], wide: bool) -> Result<Vec<String>, String> {
+    let width = if wide { 8 } else { 4 };
+    let truncated = || "truncated symbol table".to_owned();
+    let entries_len = read_uint(body, 0, width, true).ok_or_else(truncated)? as usize;
+    let entries = body.get(width..width + entries_len).ok_or_else(truncated)?;
+    let strings_start = width + entries_len + width;
+    let strings = body.get(strings_start..).ok_or_else(truncated)?;
+    let mut symbols = Vec::new();
+    for entry in entries.chunks_exact(2 * width) {
+        let offset = read_uint(entry, 0, width, true).ok_or_else(truncated)? as usize;
+        let name = strings.get(offset..).ok_or_else(truncated)?;
+        let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        symbols.push(String::from_utf8_lossy(&name[..end]).into_owned());
+    }
+    Ok(symbols)
+}
+
+fn read_uint(data: &[u8], offset: usize, width: usize, little_endian: bool) -> Option<u64> {
+    let bytes = data.get(offset..offset + width)?;
+    let fold = |value: u64, &byte: &u8| (value << 8) | u64::from(byte);
+    Some(if little_endian {
+        bytes.iter().rev().fold(0, fold)
+    } else {
+        bytes.iter().fold(0, fold)
+    })
+}
@@ -0,0 +1,373 @@
+//! Mapping from rustc target triples to Zig's `<arch>-<os>-<abi>` targets, and
+//! what a Zig toolchain reports (through `zig targets`) about the targets it
+//! can produce code for.
+
+use std::{
+    env,
+    error::Error,
+    fmt,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use crate::{Toolchain, config::Config};
+
+pub use crate::target_triple::{
+    TARGET_MAP_ENV, TargetMap, builtin_zig_target, split_glibc_version,
+};
+
+/// Maps rustc targets this crate doesn't know, such as a team's custom
+/// targets or ones defined by a JSON target spec, to Zig targets. Mappers
+/// registered with [`register_target_mapper()`] come before the
+/// [`TARGET_MAP_ENV`] table and the built-in mapping in [`zig_target()`],
+/// and so in every builder:
+///
+/// ```
+/// use zig_rs::target;
+///
+/// target::register_target_mapper(|rust_target: &str| {
+///     let arch = rust_target.strip_suffix("-acme-kernel")?;
+///     Some(format!("{arch}-freestanding-none"))
+/// });
+/// assert_eq!(
+///     target::zig_target("aarch64-acme-kernel").as_deref(),
+///     Some("aarch64-freestanding-none")
+/// );
+/// assert_eq!(
+///     target::zig_target("x86_64-unknown-linux-gnu").as_deref(),
+///     Some("x86_64-linux-gnu")
+/// );
+/// ```
+///
+/// Mappers only reach the process that registers them, e.g. one build
+/// script; this crate's own build script and `include_zig!` see only
+/// [`TARGET_MAP_ENV`].
+pub trait TargetMapper: Send + Sync {
+    /// The Zig target for `rust_target`, as Cargo names it (with a glibc
+    /// version pin, if it has one), or `None` to leave it to the next
+    /// mapper.
+    fn zig_target(&self, rust_target: &str) -> Option<String>;
+}
+
+impl<F: Fn(&str) -> Option<String> + Send + Sync> TargetMapper for F {
+    fn zig_target(&self, rust_target: &str) -> Option<String> {
+        self(rust_target)
+    }
+}
+
+/// The mappers [`register_target_mapper()`] registered, first first.
+static MAPPERS: RwLock<Vec<Arc<dyn TargetMapper>>> = RwLock::new(Vec::new());
+
+/// Makes [`zig_target()`] ask `mapper` for the rest of the process, after the
+/// mappers registered before it.
+pub fn register_target_mapper(mapper: impl TargetMapper + 'static) {
+    MAPPERS.write().unwrap().push(Arc::new(mapper));
+}
+
+/// Translates a rustc target triple like `armv7-unknown-linux-gnueabihf` into
+/// the equivalent Zig target (`arm-linux-gnueabihf`): the first answer of the
+/// registered [`TargetMapper`]s, else the [`TARGET_MAP_ENV`] table's, else
+/// the built-in mapping's, [`builtin_zig_target()`]. A glibc version pin
+/// (`x86_64-unknown-linux-gnu.2.17`) carries over. Returns `None` for
+/// triples with no Zig equivalent.
+pub fn zig_target(rust_target: &str) -> Option<String> {
+    let mappers = MAPPERS.read().unwrap().clone();
+    mappers
+        .iter()
+        .find_map(|mapper| mapper.zig_target(rust_target))
+        .or_else(|| crate::target_triple::zig_target(rust_target))
+}
+
+/// Pins `-gnu` Linux targets to a glibc version (`2.17`) when set, for
+/// binaries that run on older distributions than the build machine. A
+/// version on the target or given to a builder takes precedence.
+pub const GLIBC_VERSION_ENV: &str = "ZIG_RS_GLIBC_VERSION";
+
+/// `target` pinned to glibc `version`, or to [`GLIBC_VERSION_ENV`] when
+/// `version` is `None`. Targets that already pin a version and targets
+/// other than `-gnu` Linux come back unchanged.
+pub fn pin_glibc_version(target: &str, version: Option<&str>) -> String {
+    let env_version = env::var(GLIBC_VERSION_ENV).ok();
+    match version.or(env_version.as_deref()).filter(|v| !v.is_empty()) {
+        Some(version)
+            if target.contains("-linux-gnu") && split_glibc_version(target).1.is_none() =>
+        {
+            format!("{target}.{version}")
+        }
+        _ => target.into(),
+    }
+}
+
+/// Fails when `target` pins a glibc version `toolchain` doesn't ship; see
+/// [`ZigTargets::check_glibc()`].
+pub fn check_glibc_version(toolchain: &Toolchain, target: &str) -> Result<(), Box<dyn Error>> {
+    match split_glibc_version(target) {
+        (_, Some(version)) => ZigTargets::query(toolchain)?.check_glibc(version),
+        (_, None) => Ok(()),
+    }
+}
+
+/// Whether `target`, a rustc or Zig target, gets its libc only from the
+/// system's own sysroot because Zig bundles none for it: Haiku, whose libc is
+/// part of `libroot`, and Fuchsia, whose libc comes with the Fuchsia SDK.
+/// Compiling against their headers and linking with `-lc` needs the sysroot
+/// passed as `--sysroot`.
+pub fn needs_sysroot(target: &str) -> bool {
+    target.contains("-haiku") || target.contains("-fuchsia")
+}
+
+/// Whether binaries for `target`, a rustc or Zig target, are linked by the
+/// Solaris link editor, which takes `-z` keywords and mapfiles but none of GNU
+/// ld's long options: illumos and Solaris targets, unless the linker Cargo
+/// was configured with for the target (`RUSTC_LINKER`) is Zig's, whose LLD
+/// takes GNU spellings.
+pub fn uses_solaris_ld(target: &str) -> bool {
+    let solaris = target.contains("-illumos") || target.contains("-solaris");
+    solaris
+        && !env::var_os("RUSTC_LINKER").is_some_and(|linker| {
+            Path::new(&linker)
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().contains("zig"))
+        })
+}
+
+/// The Zig `-mcpu` for `rust_target`: the one configured for it in
+/// [`config`](crate::config), or `baseline`.
+pub fn zig_mcpu(rust_target: &str) -> Result<String, Box<dyn Error>> {
+    let config = Config::load()?;
+    Ok(config.mcpu(rust_target).unwrap_or("baseline").into())
+}
+
+/// Whether code for the wasm target `rust_target` should use threads, i.e.
+/// atomics, bulk memory and shared memory: always for `wasm32-wasip1-threads`,
+/// and for other wasm targets when Rust itself is built with `+atomics`
+/// (read from `CARGO_CFG_TARGET_FEATURE` in build scripts). Mixing the two
+/// models produces modules that fail to instantiate or race silently.
+pub fn wasm_threads_default(rust_target: &str) -> bool {
+    if !rust_target.starts_with("wasm") {
+        return false;
+    }
+    rust_target.ends_with("-threads")
+        || env::var("CARGO_CFG_TARGET_FEATURE")
+            .is_ok_and(|features| features.split(',').any(|f| f == "atomics"))
+}
+
+/// Zig CPU feature set enabling wasm threads.
+pub(crate) const WASM_THREADS_CPU: &str = "generic+atomics+bulk_memory";
+
+/// What a toolchain's `zig targets` reports. Both the JSON output of older
+/// releases and the ZON output of newer ones are understood.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ZigTargets {
+    pub arch: Vec<String>,
+    pub os: Vec<String>,
+    pub abi: Vec<String>,
+    /// Targets Zig can provide a libc for, e.g. `x86_64-linux-musl`.
+    pub libc: Vec<String>,
+    /// glibc versions Zig ships symbol information for, e.g. `2.17.0`.
+    pub glibc: Vec<String>,
+}
+
+impl ZigTargets {
+    /// Runs `zig targets` with `toolchain`.
+    pub fn query(toolchain: &Toolchain) -> Result<Self, Box<dyn Error>> {
+        let output = crate::process::output(toolchain.command().arg("targets"))?;
+        if !output.status.success() {
+            return Err(format!(
+                "zig targets failed: {}\n{}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            )
+            .into());
+        }
+        Ok(Self::parse(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// Checks that Zig ships symbol information for glibc `version` (`2.17`
+    /// or `2.17.0`). The error names the nearest versions it does ship,
+    /// instead of the link failure an unknown version leads to later.
+    pub fn check_glibc(&self, version: &str) -> Result<(), Box<dyn Error>> {
+        let wanted =
+            parse_version(version).ok_or_else(|| format!("invalid glibc version {version:?}"))?;
+        let mut known: Vec<(u32, u32, u32)> =
+            self.glibc.iter().filter_map(|v| parse_version(v)).collect();
+        if known.contains(&wanted) {
+            return Ok(());
+        }
+        known.sort();
+        let below = known.iter().rev().find(|&&v| v < wanted);
+        let above = known.iter().find(|&&v| v > wanted);
+        let nearest: Vec<String> = [below, above]
+            .into_iter()
+            .flatten()
+            .map(|&(major, minor, patch)| match patch {
+                0 => format!("{major}.{minor}"),
+                _ => format!("{major}.{minor}.{patch}"),
+            })
+            .collect();
+        if nearest.is_empty() {
+            return Err(
+                format!("this Zig ships no glibc, so it can't target glibc {version}").into(),
+            );
+        }
+        Err(format!(
+            "this Zig can't target glibc {version} (nearest supported: {})",
+            nearest.join(", ")
+        )
+        .into())
+    }
+
+    /// Parses `zig targets` output. Unknown fields are ignored.
+    pub fn parse(output: &str) -> Self {
+        Self {
+            arch: list_field(output, "arch"),
+            os: list_field(output, "os"),
+            abi: list_field(output, "abi"),
+            libc: list_field(output, "libc"),
+            glibc: list_field(output, "glibc"),
+        }
+    }
+}
+
+/// `2.17` or `2.17.0` as `(2, 17, 0)`.
+pub(crate) fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.split('.').map(str::parse);
+    let major = parts.next()?.ok()?;
+    let minor = parts.next()?.ok()?;
+    let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+    parts.next().is_none().then_some((major, minor, patch))
+}
+
+/// Finds the top-level list `"key": [...]` (JSON) or `.key = .{...}` (ZON) and
+/// returns its string (`"x"`) or enum literal (`.x`, `.@"x"`) elements.
+fn list_field(output: &str, key: &str) -> Vec<String> {
+    let json_key = format!("\"{key}\"");
+    let zon_key = format!(".{key} ");
+    let zon_key_eq = format!(".{key}=");
+    let Some(start) = [json_key, zon_key, zon_key_eq]
+        .iter()
+        .filter_map(|k| output.find(k.as_str()).map(|i| i + k.len()))
+        .min()
+    else {
+        return Vec::new();
+    };
+    let rest = &output[start..];
+    let Some(open) = rest.find(['[', '{']) else {
+        return Vec::new();
+    };
+    let body = &rest[open + 1..];
+    let mut items = Vec::new();
+    let mut depth = 0;
+    let mut chars = body.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '[' | '{' => depth += 1,
+            ']' | '}' if depth == 0 => break,
+            ']' | '}' => depth -= 1,
+            '"' if depth == 0 => {
+                let end = body[i + 1..].find('"').map_or(body.len(), |e| i + 1 + e);
+                items.push(body[i + 1..end].to_owned());
+                while chars.peek().is_some_and(|&(j, _)| j <= end) {
+                    chars.next();
+                }
+            }
+            '.' if depth == 0 => {
+                let ident: String = body[i + 1..]
+                    .chars()
+                    .take_while(|c| c.is_alphanumeric() || *c == '_')
+                    .collect();
+                if !ident.is_empty() {
+                    items.push(ident);
+                }
+            }
+            _ => {}
+        }
+    }
+    items
+}
+
+/// How well a toolchain supports cross-compiling to a rustc target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetSupport {
+    pub rust_target: String,
+    /// The mapped Zig target, if there is one.
+    pub zig_target: Option<String>,
+    /// Set when the toolchain can emit code for the target at all.
+    pub codegen: bool,
+    /// Libcs Zig can provide for the target's arch and OS, e.g.
+    /// `["x86_64-linux-gnu", "x86_64-linux-musl"]`. Empty for freestanding
+    /// targets and for targets whose libc must come from the system.
+    pub libc: Vec<String>,
+    /// Set when Zig can provide the exact libc the rustc target expects.
+    pub exact_libc: bool,
+}
+
+impl TargetSupport {
+    /// Rates `rust_target` against what `targets` reports.
+    pub fn new(rust_target: &str, targets: &ZigTargets) -> Self {
+        let zig_target = zig_target(rust_target);
+        let (codegen, libc, exact_libc) = match &zig_target {
+            Some(zig_target) => {
+                let mut parts = zig_target.splitn(3, '-');
+                let arch = parts.next().unwrap_or_default();
+                let os = parts.next().unwrap_or_default();
+                let codegen =
+                    targets.arch.iter().any(|a| a == arch) && targets.os.iter().any(|o| o == os);
+                let prefix = format!("{arch}-{os}-");
+                let libc: Vec<String> = targets
+                    .libc
+                    .iter()
+                    .filter(|l| l.starts_with(&prefix))
+                    .cloned()
+                    .collect();
+                let exact_libc = libc.iter().any(|l| l == zig_target);
+                (codegen, libc, exact_libc)
+            }
+            None => (false, Vec::new(), false),
+        };
+        Self {
+            rust_target: rust_target.into(),
+            zig_target,
+            codegen,
+            libc,
+            exact_libc,
+        }
+    }
+
+    /// True when cross-compiling C and Zig code for the target should work,
+    /// including linking against libc where the target has one.
+    pub fn is_supported(&self) -> bool {
+        let freestanding = self
+            .zig_target
+            .as_deref()
+            .is_some_and(|t| t.contains("-freestanding-") || t.contains("-uefi-"));
+        self.codegen && (self.exact_libc || freestanding)
+    }
+}
+
+impl fmt::Display for TargetSupport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.zig_target {
+            None => write!(f, "{}: no Zig equivalent", self.rust_target),
+            Some(zig) if !self.codegen => {
+                write!(f, "{} ({zig}): not supported by this Zig", self.rust_target)
+            }
+            Some(zig) if self.is_supported() => {
+                write!(f, "{} ({zig}): supported", self.rust_target)
+            }
+            Some(zig) if self.libc.is_empty() => {
+                write!(
+                    f,
+                    "{} ({zig}): code generation only, no bundled libc",
+                    self.rust_target
+                )
+            }
+            Some(zig) => write!(
+                f,
+                "{} ({zig}): code generation only; bundled libcs: {}",
+                self.rust_target,
+                self.libc.join(", ")
+            ),
+        }
+    }
+}
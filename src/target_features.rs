@@ -0,0 +1,250 @@
+//! Keeping the CPU features of the Zig-compiled half of a crate in line with
+//! the Rust half. Rust code built with `-C target-feature=+avx2` calling C
+//! built for Zig's `baseline` only loses speed, but C built with
+//! `-mcpu=x86_64_v3` for a Rust target without AVX2 crashes with an illegal
+//! instruction on the machines the Rust target promises to run on. On ARM
+//! the float and NEON features also decide the ABI.
+//!
+//! [`compare()`] checks a `-mcpu` against the features Cargo enabled for the
+//! Rust target (`CARGO_CFG_TARGET_FEATURE`), as far as both compilers have a
+//! name for them. [`Build::target_features()`](crate::Build::target_features)
+//! runs it for a build and fails or adjusts the `-mcpu`:
+//!
+//! ```no_run
+//! // build.rs
+//! use zig_rs::target_features::FeaturePolicy;
+//!
+//! zig_rs::Build::new()
+//!     .file("src/simd.c")
+//!     .target_features(FeaturePolicy::Adjust)
+//!     .compile("simd");
+//! ```
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    env,
+    sync::{Mutex, OnceLock},
+};
+
+use crate::{Error, Toolchain, diagnostic, error::CommandError, process};
+
+/// What [`Build`](crate::Build) does when the Rust and Zig features
+/// disagree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum FeaturePolicy {
+    /// Compile for the `-mcpu` as it is.
+    #[default]
+    Ignore,
+    /// Fail the build, naming the features that differ.
+    Deny,
+    /// Add the features only Rust enables to the `-mcpu` and remove the ones
+    /// only Zig does.
+    Adjust,
+}
+
+/// How a `-mcpu` differs from the Rust target's features, in Zig's names.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Comparison {
+    /// Features Rust code may use that the Zig code won't.
+    pub rust_only: Vec<String>,
+    /// Features the Zig code may use that Rust doesn't assume the CPU has,
+    /// the ones that crash.
+    pub zig_only: Vec<String>,
+}
+
+impl Comparison {
+    pub fn is_match(&self) -> bool {
+        self.rust_only.is_empty() && self.zig_only.is_empty()
+    }
+
+    /// `mcpu` with the differences turned into `+feature` and `-feature`,
+    /// e.g. `baseline+avx2-sse4_1`: the CPU Zig should build for to agree.
+    pub fn adjusted_mcpu(&self, mcpu: &str) -> String {
+        let mut adjusted = mcpu.to_owned();
+        for feature in &self.rust_only {
+            adjusted += &format!("+{feature}");
+        }
+        for feature in &self.zig_only {
+            adjusted += &format!("-{feature}");
+        }
+        adjusted
+    }
+}
+
+/// The features Cargo enabled for the target a build script builds for,
+/// in Rust's names.
+pub fn rust_features() -> Vec<String> {
+    env::var("CARGO_CFG_TARGET_FEATURE")
+        .map(|features| {
+            features
+                .split(',')
+                .filter(|f| !f.is_empty())
+                .map(str::to_owned)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Compares `mcpu` for `zig_target` with `rust_features`, the features
+/// enabled for the Rust target `rust_target`. Only features in the table for
+/// the architecture count; Zig has many more, mostly tuning, that Rust has
+/// no name for.
+pub fn compare(
+    toolchain: &Toolchain,
+    rust_target: &str,
+    zig_target: &str,
+    mcpu: &str,
+    rust_features: &[String],
+) -> Result<Comparison, Error> {
+    let table = feature_table(rust_target);
+    if table.is_empty() {
+        return Ok(Comparison::default());
+    }
+    let zig = zig_features(toolchain, zig_target, mcpu)?;
+    let rust: BTreeSet<&str> = rust_features.iter().map(String::as_str).collect();
+    let zig_names: BTreeSet<&str> = table.iter().map(|&(_, zig_name)| zig_name).collect();
+    let mut comparison = Comparison::default();
+    for zig_name in zig_names {
+        // Rust splits some features Zig has one name for.
+        let in_rust = table
+            .iter()
+            .any(|&(rust_name, z)| z == zig_name && rust.contains(rust_name));
+        match (in_rust, zig.contains(zig_name)) {
+            (true, false) => comparison.rust_only.push(zig_name.into()),
+            (false, true) => comparison.zig_only.push(zig_name.into()),
+            _ => {}
+        }
+    }
+    Ok(comparison)
+}
+
+/// The features `toolchain` enables for `mcpu` on `zig_target`, including
+/// the ones they imply, as `zig build-obj --show-builtin` lists them.
+/// Results are cached for the lifetime of the process.
+pub fn zig_features(
+    toolchain: &Toolchain,
+    zig_target: &str,
+    mcpu: &str,
+) -> Result<BTreeSet<String>, Error> {
+    type Key = (std::path::PathBuf, String, String);
+    static FEATURES: OnceLock<Mutex<HashMap<Key, BTreeSet<String>>>> = OnceLock::new();
+    let key = (toolchain.zig(), zig_target.to_owned(), mcpu.to_owned());
+    let features = FEATURES.get_or_init(Default::default);
+    if let Some(cached) = features.lock().unwrap().get(&key) {
+        return Ok(cached.clone());
+    }
+    let mut cmd = toolchain.command();
+    cmd.arg("build-obj")
+        .arg("--show-builtin")
+        .arg("-target")
+        .arg(zig_target)
+        .arg(format!("-mcpu={mcpu}"));
+    let output = process::output(&mut cmd)?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let parsed = parse_feature_set(&stdout);
+    let Some(parsed) = parsed.filter(|_| output.status.success()) else {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        return Err(Error::Command(Box::new(CommandError::new(
+            &cmd,
+            output.status,
+            diagnostic::parse(&stderr),
+            stderr,
+        ))));
+    };
+    features.lock().unwrap().insert(key, parsed.clone());
+    Ok(parsed)
+}
+
+/// The features in the `featureSet(&.{ .sse2, .@"64bit", ... })` of a
+/// `builtin.zig`.
+pub fn parse_feature_set(builtin: &str) -> Option<BTreeSet<String>> {
+    let start = builtin.find("featureSet(&.{")? + "featureSet(&.{".len();
+    let end = start + builtin[start..].find('}')?;
+    Some(
+        builtin[start..end]
+            .split(',')
+            .map(|feature| {
+                let feature = feature.trim().trim_start_matches('.');
+                feature
+                    .strip_prefix("@\"")
+                    .and_then(|f| f.strip_suffix('"'))
+                    .unwrap_or(feature)
+                    .to_owned()
+            })
+            .filter(|feature| !feature.is_empty())
+            .collect(),
+    )
+}
+
+/// `(Rust name, Zig name)` of the features both compilers know for the
+/// architecture of `rust_target`.
+fn feature_table(rust_target: &str) -> &'static [(&'static str, &'static str)] {
+    let arch = rust_target.split('-').next().unwrap_or_default();
+    match arch {
+        "x86_64" | "x86_64h" | "i586" | "i686" => X86,
+        // Rust's ARM64EC features are ARM ones, the Zig half is x64 code.
+        "arm64ec" => &[],
+        "aarch64" | "aarch64_be" | "arm64e" => AARCH64,
+        _ if arch.starts_with("arm") || arch.starts_with("thumb") => ARM,
+        _ if arch.starts_with("riscv") => RISCV,
+        _ if arch.starts_with("wasm") => WASM,
+        _ => &[],
+    }
+}
+
+#[rustfmt::skip]
+const X86: &[(&str, &str)] = &[
+    ("adx", "adx"), ("aes", "aes"), ("avx", "avx"), ("avx2", "avx2"),
+    ("avx512bitalg", "avx512bitalg"), ("avx512bw", "avx512bw"), ("avx512cd", "avx512cd"),
+    ("avx512dq", "avx512dq"), ("avx512f", "avx512f"), ("avx512ifma", "avx512ifma"),
+    ("avx512vbmi", "avx512vbmi"), ("avx512vbmi2", "avx512vbmi2"), ("avx512vl", "avx512vl"),
+    ("avx512vnni", "avx512vnni"), ("avx512vpopcntdq", "avx512vpopcntdq"),
+    ("bmi1", "bmi"), ("bmi2", "bmi2"), ("cmpxchg16b", "cx16"), ("f16c", "f16c"),
+    ("fma", "fma"), ("fxsr", "fxsr"), ("gfni", "gfni"), ("lzcnt", "lzcnt"),
+    ("movbe", "movbe"), ("pclmulqdq", "pclmul"), ("popcnt", "popcnt"),
+    ("rdrand", "rdrnd"), ("rdseed", "rdseed"), ("sha", "sha"), ("sse", "sse"),
+    ("sse2", "sse2"), ("sse3", "sse3"), ("sse4.1", "sse4_1"), ("sse4.2", "sse4_2"),
+    ("sse4a", "sse4a"), ("ssse3", "ssse3"), ("tbm", "tbm"), ("vaes", "vaes"),
+    ("vpclmulqdq", "vpclmulqdq"), ("xsave", "xsave"), ("xsavec", "xsavec"),
+    ("xsaveopt", "xsaveopt"), ("xsaves", "xsaves"),
+];
+
+#[rustfmt::skip]
+const AARCH64: &[(&str, &str)] = &[
+    ("aes", "aes"), ("bf16", "bf16"), ("bti", "bti"), ("crc", "crc"), ("dit", "dit"),
+    ("dotprod", "dotprod"), ("dpb", "ccpp"), ("dpb2", "ccdp"), ("f32mm", "f32mm"),
+    ("f64mm", "f64mm"), ("fcma", "complxnum"), ("fhm", "fp16fml"), ("flagm", "flagm"),
+    ("fp16", "fullfp16"), ("frintts", "fptoint"), ("i8mm", "i8mm"), ("jsconv", "jsconv"),
+    ("lor", "lor"), ("lse", "lse"), ("mte", "mte"), ("neon", "neon"), ("paca", "pauth"),
+    ("pacg", "pauth"), ("pan", "pan"), ("rand", "rand"), ("ras", "ras"), ("rcpc", "rcpc"),
+    ("rcpc2", "rcpc_immo"), ("rdm", "rdm"), ("sb", "sb"), ("sha2", "sha2"), ("sha3", "sha3"),
+    ("sm4", "sm4"), ("ssbs", "ssbs"), ("sve", "sve"), ("sve2", "sve2"),
+    ("sve2-aes", "sve2_aes"), ("sve2-bitperm", "sve2_bitperm"), ("sve2-sha3", "sve2_sha3"),
+    ("sve2-sm4", "sve2_sm4"), ("tme", "tme"), ("vh", "vh"),
+];
+
+#[rustfmt::skip]
+const ARM: &[(&str, &str)] = &[
+    ("aes", "aes"), ("crc", "crc"), ("d32", "d32"), ("dotprod", "dotprod"), ("dsp", "dsp"),
+    ("fp-armv8", "fp_armv8"), ("i8mm", "i8mm"), ("neon", "neon"), ("sha2", "sha2"),
+    ("thumb-mode", "thumb_mode"), ("thumb2", "thumb2"), ("trustzone", "trustzone"),
+    ("vfp2", "vfp2"), ("vfp3", "vfp3"), ("vfp4", "vfp4"),
+];
+
+#[rustfmt::skip]
+const RISCV: &[(&str, &str)] = &[
+    ("a", "a"), ("c", "c"), ("d", "d"), ("f", "f"), ("m", "m"), ("v", "v"),
+    ("zba", "zba"), ("zbb", "zbb"), ("zbc", "zbc"), ("zbkb", "zbkb"), ("zbkc", "zbkc"),
+    ("zbkx", "zbkx"), ("zbs", "zbs"), ("zknd", "zknd"), ("zkne", "zkne"),
+    ("zknh", "zknh"), ("zksed", "zksed"), ("zksh", "zksh"), ("zkt", "zkt"),
+];
+
+#[rustfmt::skip]
+const WASM: &[(&str, &str)] = &[
+    ("atomics", "atomics"), ("bulk-memory", "bulk_memory"),
+    ("exception-handling", "exception_handling"), ("extended-const", "extended_const"),
+    ("multivalue", "multivalue"), ("mutable-globals", "mutable_globals"),
+    ("nontrapping-fptoint", "nontrapping_fptoint"), ("reference-types", "reference_types"),
+    ("relaxed-simd", "relaxed_simd"), ("sign-ext", "sign_ext"), ("simd128", "simd128"),
+    ("tail-call", "tail_call"),
+];
@@ -0,0 +1,187 @@
+//! Translating rustc target triples into Zig's `<arch>-<os>-<abi>` targets.
+//! Re-exported from [`target`](crate::target); this part only needs `std`,
+//! so the build script shares it.
+
+use std::{env, io, path::PathBuf};
+
+/// Environment variable naming a file of Zig targets for rustc targets this
+/// crate doesn't map, such as custom targets from a JSON target spec,
+/// which rustc and Cargo name after the spec's file name. A line per
+/// target, with `#` comments:
+///
+/// ```text
+/// # rustc target      Zig target
+/// x86_64-acme-kernel  x86_64-freestanding-none
+/// ```
+///
+/// Entries win over the built-in mapping. Unlike
+/// [`register_target_mapper()`](crate::target::register_target_mapper),
+/// this also reaches this crate's build script and `include_zig!`.
+pub const TARGET_MAP_ENV: &str = "ZIG_RS_TARGET_MAP";
+
+/// Translates a rustc target triple like `armv7-unknown-linux-gnueabihf` into
+/// the equivalent Zig target (`arm-linux-gnueabihf`), with the
+/// [`TARGET_MAP_ENV`] table first. A glibc version pin
+/// (`x86_64-unknown-linux-gnu.2.17`) carries over. Returns `None` for
+/// triples with no Zig equivalent.
+pub fn zig_target(rust_target: &str) -> Option<String> {
+    let (triple, _) = split_glibc_version(rust_target);
+    // A table that can't be read maps nothing; the build script reports it.
+    let map = TargetMap::from_env().ok().flatten();
+    match map.as_ref().and_then(|map| map.get(triple)) {
+        Some(zig_target) => with_glibc(rust_target, zig_target.into()),
+        None => builtin_zig_target(rust_target),
+    }
+}
+
+/// [`zig_target()`] without the [`TARGET_MAP_ENV`] table.
+pub fn builtin_zig_target(rust_target: &str) -> Option<String> {
+    with_glibc(
+        rust_target,
+        zig_target_without_glibc(split_glibc_version(rust_target).0)?,
+    )
+}
+
+/// The [`TARGET_MAP_ENV`] table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TargetMap {
+    /// The file it was read from.
+    pub path: PathBuf,
+    /// `(rustc target, Zig target)` entries, in file order.
+    pub entries: Vec<(String, String)>,
+}
+
+impl TargetMap {
+    /// The table in [`TARGET_MAP_ENV`], or `None` when it isn't set.
+    pub fn from_env() -> io::Result<Option<Self>> {
+        match env::var_os(TARGET_MAP_ENV).filter(|path| !path.is_empty()) {
+            Some(path) => Self::read(path.into()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn read(path: PathBuf) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        for line in fs_err::read_to_string(&path)?.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [] => {}
+                [rust_target, zig_target] => entries.push((rust_target.into(), zig_target.into())),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}: bad line {line:?}", path.display()),
+                    ));
+                }
+            }
+        }
+        Ok(Self { path, entries })
+    }
+
+    /// The Zig target of `rust_target`, without a glibc version pin.
+    pub fn get(&self, rust_target: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(from, _)| from == rust_target)
+            .map(|(_, to)| to.as_str())
+    }
+}
+
+/// `zig_target` with the glibc version pinned on `rust_target`, if any.
+fn with_glibc(rust_target: &str, zig_target: String) -> Option<String> {
+    let (_, glibc) = split_glibc_version(rust_target);
+    match glibc {
+        Some(version) if zig_target.contains("-linux-gnu") => {
+            Some(format!("{zig_target}.{version}"))
+        }
+        Some(_) => None,
+        None => Some(zig_target),
+    }
+}
+
+/// Splits the glibc version off a target pinned to one the way Zig and
+/// cargo-zigbuild spell it, `x86_64-unknown-linux-gnu.2.17` or
+/// `x86_64-linux-gnu.2.17`.
+pub fn split_glibc_version(target: &str) -> (&str, Option<&str>) {
+    if let Some((head, abi)) = target.rsplit_once('-')
+        && abi.starts_with("gnu")
+        && let Some((abi, version)) = abi.split_once('.')
+    {
+        return (&target[..head.len() + 1 + abi.len()], Some(version));
+    }
+    (target, None)
+}
+
+fn zig_target_without_glibc(rust_target: &str) -> Option<String> {
+    if let Some(arch) = rust_target.strip_suffix("-wasip1-threads") {
+        return Some(format!("{}-wasi-musl", zig_arch(arch)?));
+    }
+    let parts: Vec<&str> = rust_target.split('-').collect();
+    let (arch, os, env) = match parts.as_slice() {
+        // wasm32-wasip1, wasm32-unknown-unknown vs aarch64-apple-darwin...
+        [arch, os] => (*arch, *os, ""),
+        // Android triples have no vendor: aarch64-linux-android.
+        [arch, "linux", env @ ("android" | "androideabi")] => (*arch, "linux", *env),
+        [arch, _vendor, os] => (*arch, *os, ""),
+        [arch, _vendor, os, env] => (*arch, *os, *env),
+        _ => return None,
+    };
+    let zig_arch = zig_arch(arch)?;
+    let (zig_os, default_abi) = match os {
+        "linux" => ("linux", "gnu"),
+        "darwin" | "macos" => ("macos", "none"),
+        "ios" => ("ios", "none"),
+        "windows" => ("windows", "gnu"),
+        "freebsd" | "netbsd" | "openbsd" | "dragonfly" | "haiku" | "fuchsia" | "illumos"
+        | "solaris" => (os, "none"),
+        // rustc links UEFI images with lld-link, so objects follow MSVC
+        // conventions.
+        "uefi" => ("uefi", "msvc"),
+        "wasi" | "wasip1" | "wasip2" => ("wasi", "musl"),
+        "emscripten" => ("emscripten", "musl"),
+        "unknown" | "none" => ("freestanding", "none"),
+        _ => return None,
+    };
+    let abi = match env {
+        "" => default_abi,
+        "gnu" | "musl" | "msvc" | "gnux32" | "gnueabi" | "gnueabihf" | "musleabi"
+        | "musleabihf" | "eabi" | "eabihf" | "android" | "androideabi" => env,
+        "gnullvm" => "gnu",
+        "elf" => "none",
+        _ => return None,
+    };
+    Some(format!("{zig_arch}-{zig_os}-{abi}"))
+}
+
+fn zig_arch(arch: &str) -> Option<&'static str> {
+    Some(match arch {
+        "x86_64" | "x86_64h" => "x86_64",
+        // Zig has no ARM64EC architecture, but ARM64EC images also take x64
+        // code, which Windows runs emulated and calls into without thunks
+        // of our own.
+        "arm64ec" => "x86_64",
+        "i386" | "i586" | "i686" => "x86",
+        "aarch64" | "arm64" | "arm64e" => "aarch64",
+        "aarch64_be" => "aarch64_be",
+        "arm" | "armv5te" | "armv6" | "armv7" | "armv7a" | "armv7r" => "arm",
+        "armebv7r" => "armeb",
+        "thumbv6m" | "thumbv7m" | "thumbv7em" | "thumbv7neon" | "thumbv8m.base"
+        | "thumbv8m.main" => "thumb",
+        "riscv64gc" | "riscv64imac" | "riscv64" => "riscv64",
+        "riscv32i" | "riscv32imc" | "riscv32imac" | "riscv32imafc" | "riscv32gc" => "riscv32",
+        "wasm32" => "wasm32",
+        "wasm64" => "wasm64",
+        "powerpc" => "powerpc",
+        "powerpc64" => "powerpc64",
+        "powerpc64le" => "powerpc64le",
+        "s390x" => "s390x",
+        "sparc64" | "sparcv9" => "sparc64",
+        "mips" => "mips",
+        "mipsel" => "mipsel",
+        "mips64" => "mips64",
+        "mips64el" => "mips64el",
+        "loongarch64" => "loongarch64",
+        "hexagon" => "hexagon",
+        _ => return None,
+    })
+}
@@ -0,0 +1,206 @@
+//! Throwaway projects for integration tests that run the real toolchain:
+//!
+//! ```no_run
+//! use zig_rs::{diagnostic::Severity, testing::Project};
+//!
+//! let project = Project::new();
+//! project
+//!     .file("build.zig", r#"
+//! const std = @import("std");
+//! pub fn build(b: *std.Build) void {
+//!     const exe = b.addExecutable(.{
+//!         .name = "hello",
+//!         .root_source_file = b.path("main.zig"),
+//!         .target = b.standardTargetOptions(.{}),
+//!     });
+//!     b.installArtifact(exe);
+//! }
+//! "#)
+//!     .file("main.zig", "pub fn main() void {}\n");
+//! project.build(["-Doptimize=ReleaseSafe"]).assert_success().assert_clean();
+//! project.assert_exists("zig-out/bin/hello");
+//!
+//! project.file("bad.c", "int f(void) { int unused; return 0; }\n");
+//! project
+//!     .zig(["cc", "-Wall", "-c", "bad.c"])
+//!     .assert_success()
+//!     .assert_diagnostic(Severity::Warning, "unused variable");
+//! ```
+//!
+//! Helpers panic with the command's output instead of returning errors, which
+//! is what a failing test wants. Project directories are deleted when the
+//! [`Project`] is dropped unless [`KEEP_ENV`] is set.
+
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    fmt,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    Toolchain,
+    diagnostic::{self, Diagnostic, Severity},
+    error::command_line,
+    process,
+};
+
+/// Environment variable that keeps project directories around for
+/// inspecting a failed test.
+pub const KEEP_ENV: &str = "ZIG_RS_KEEP_TEST_DIRS";
+
+/// A temporary directory to write sources into and run `zig` in.
+#[derive(Debug)]
+pub struct Project {
+    dir: PathBuf,
+    toolchain: Toolchain,
+}
+
+impl Default for Project {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Project {
+    /// Creates an empty project that runs the bundled toolchain.
+    pub fn new() -> Self {
+        Self::with_toolchain(Toolchain::bundled())
+    }
+
+    pub fn with_toolchain(toolchain: Toolchain) -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = env::temp_dir().join(format!(
+            "zig-rs-test-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        if dir.exists() {
+            fs_err::remove_dir_all(&dir).unwrap();
+        }
+        fs_err::create_dir_all(&dir).unwrap();
+        Self { dir, toolchain }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The absolute path of `relative` inside the project.
+    pub fn path(&self, relative: impl AsRef<Path>) -> PathBuf {
+        self.dir.join(relative)
+    }
+
+    /// Writes `contents` to `relative`, creating parent directories.
+    pub fn file(&self, relative: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> &Self {
+        let path = self.path(relative);
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent).unwrap();
+        }
+        fs_err::write(path, contents).unwrap();
+        self
+    }
+
+    /// Runs `zig <args>` in the project directory. Zig's local cache ends up
+    /// in the project too.
+    pub fn zig<S: AsRef<OsStr>>(&self, args: impl IntoIterator<Item = S>) -> Output {
+        let mut cmd = self.toolchain.command();
+        cmd.args(args)
+            .current_dir(&self.dir)
+            .env("ZIG_LOCAL_CACHE_DIR", self.dir.join(".zig-cache"));
+        Output::run(&mut cmd)
+    }
+
+    /// Runs `zig build <args>`.
+    pub fn build<S: AsRef<OsStr>>(&self, args: impl IntoIterator<Item = S>) -> Output {
+        let mut build_args = vec![OsString::from("build")];
+        build_args.extend(args.into_iter().map(|arg| arg.as_ref().to_owned()));
+        self.zig(build_args)
+    }
+
+    /// Panics unless `relative` exists.
+    pub fn assert_exists(&self, relative: impl AsRef<Path>) -> &Self {
+        let path = self.path(relative);
+        assert!(path.exists(), "{} does not exist", path.display());
+        self
+    }
+}
+
+impl Drop for Project {
+    fn drop(&mut self) {
+        if env::var_os(KEEP_ENV).is_some() {
+            eprintln!("keeping {}", self.dir.display());
+        } else {
+            let _ = fs_err::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+/// What a command run in a [`Project`] did.
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub command: String,
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+    /// Parsed from stderr.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Output {
+    fn run(cmd: &mut Command) -> Self {
+        let command = command_line(cmd);
+        let output =
+            process::output(cmd).unwrap_or_else(|e| panic!("could not run `{command}`: {e}"));
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        Self {
+            diagnostics: diagnostic::parse(&stderr),
+            command,
+            status: output.status,
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr,
+        }
+    }
+
+    pub fn assert_success(&self) -> &Self {
+        assert!(self.status.success(), "{self}");
+        self
+    }
+
+    pub fn assert_failure(&self) -> &Self {
+        assert!(!self.status.success(), "expected failure\n{self}");
+        self
+    }
+
+    /// Panics unless a diagnostic of `severity` has `message` in its message.
+    pub fn assert_diagnostic(&self, severity: Severity, message: &str) -> &Self {
+        assert!(
+            self.diagnostics
+                .iter()
+                .any(|d| d.severity == severity && d.message.contains(message)),
+            "no {severity} containing {message:?}\n{self}"
+        );
+        self
+    }
+
+    /// Panics if anything printed a warning or error.
+    pub fn assert_clean(&self) -> &Self {
+        assert!(
+            self.diagnostics
+                .iter()
+                .all(|d| d.severity == Severity::Note),
+            "expected no warnings or errors\n{self}"
+        );
+        self
+    }
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "`{}`: {}", self.command, self.status)?;
+        writeln!(f, "--- stdout\n{}", self.stdout)?;
+        write!(f, "--- stderr\n{}", self.stderr)
+    }
+}
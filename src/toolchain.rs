@@ -0,0 +1,371 @@
+//! A Zig installation: a `zig` binary with its `lib/` directory next to it.
+//!
+//! The toolchain the build script bootstraps is [`Toolchain::bundled()`]. Other
+//! versions are kept side by side in the shared cache under
+//! `<cache>/toolchains/<version>/` and are downloaded from ziglang.org the first
+//! time [`Toolchain::version()`] asks for them, so a single process can drive
+//! several Zig versions at once (e.g. to match what different upstream
+//! projects require).
+
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Mutex, OnceLock},
+};
+
+use crate::{
+    cache,
+    diagnostic::{self, Diagnostic},
+    download::{self, PercentReporter},
+    libc_headers,
+    licenses::{self, License},
+    log::Tagger,
+    manifest::{self, Manifest, Verification},
+    process, provenance, release,
+    sign::{Signature, Signer},
+    target::{self, TargetSupport, ZigTargets},
+    tools::Tool,
+    zig_env::{Version, ZigEnv},
+};
+
+/// File name of the Zig binary on the platform this crate was built for.
+pub(crate) const ZIG_EXE: &str = if cfg!(windows) { "zig.exe" } else { "zig" };
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toolchain {
+    version: String,
+    root: PathBuf,
+}
+
+impl Toolchain {
+    /// The toolchain installed into `OUT_DIR` by this crate's build script.
+    /// It runs on the host the crate was built on, also when it was
+    /// cross-compiled, which is what build scripts need.
+    pub fn bundled() -> Self {
+        Self {
+            version: crate::BUILD_INFO.zig_version.into(),
+            root: PathBuf::from(env!("OUT_DIR")),
+        }
+    }
+
+    /// The bundled toolchain that runs on the target the crate was built
+    /// for, to ship with an application: [`bundled()`](Self::bundled) unless
+    /// the crate was cross-compiled, in which case only the
+    /// `target-toolchain` feature installs one.
+    pub fn bundled_for_target() -> Option<Self> {
+        crate::BUILD_INFO.target_toolchain.map(|root| Self {
+            version: crate::BUILD_INFO.zig_version.into(),
+            root: PathBuf::from(root),
+        })
+    }
+
+    /// Uses an existing installation at `root` without checking it.
+    pub fn at(version: impl Into<String>, root: impl Into<PathBuf>) -> Self {
+        Self {
+            version: version.into(),
+            root: root.into(),
+        }
+    }
+
+    /// Returns Zig `version` for the host, downloading the official release
+    /// into the shared cache if it isn't there yet. Concurrent callers (other
+    /// threads, processes or build scripts) wait for a single download.
+    ///
+    /// The bundled toolchain is returned when it has the requested version.
+    pub fn version(version: &str) -> Result<Self, Box<dyn Error>> {
+        let bundled = Self::bundled();
+        if bundled.version == version && bundled.zig().exists() {
+            return Ok(bundled);
+        }
+
+        let (arch, os) = release::host_platform()
+            .ok_or("ziglang.org publishes no Zig binaries for this host")?;
+        Self::release(version, arch, os)
+    }
+
+    /// Returns the official release of Zig `version` for Zig's `arch`/`os`,
+    /// e.g. from [`release::target_platform()`], downloading it into the
+    /// shared cache like [`Toolchain::version()`] does. The build script
+    /// installs from there when it builds for that platform, which is how
+    /// `cargo zig prefetch` warms the cache for other hosts; a toolchain for
+    /// another platform doesn't run here.
+    pub fn release(version: &str, arch: &str, os: &str) -> Result<Self, Box<dyn Error>> {
+        let cache_dir = cache::toolchains_dir()
+            .ok_or("no cache directory; set ZIG_RS_CACHE_DIR")?
+            .join(version);
+        let name = format!("{arch}-{os}-release");
+        let root = cache_dir.join(&name);
+        let toolchain = Self::at(version, &root);
+        if root.join(manifest::FILE_NAME).exists() {
+            let _ = cache::touch(&root);
+            return Ok(toolchain);
+        }
+
+        fs_err::create_dir_all(&cache_dir)?;
+        let lock = fs_err::File::create(cache_dir.join(format!("{name}.lock")))?;
+        lock.file().lock()?;
+        // Someone else may have finished the install while we waited.
+        if root.join(manifest::FILE_NAME).exists() {
+            let _ = cache::touch(&root);
+            return Ok(toolchain);
+        }
+
+        let partial = cache_dir.join(format!("{name}.partial"));
+        if partial.exists() {
+            fs_err::remove_dir_all(&partial)?;
+        }
+        let _guard = process::remove_on_interrupt(&partial);
+        download::require_network(
+            &release::url(version, arch, os),
+            "put the toolchain in the cache with zig_rs::unpack_toolchain()",
+        )?;
+        let mut reporter = PercentReporter::new(Tagger::new(&format!("download/{version}")));
+        release::install(version, arch, os, &partial, |p| reporter.report(p))?;
+        licenses::stage(&partial, "zig", &partial.join("LICENSE"))?;
+        licenses::collect(&partial)?;
+        let zig_exe = if os == "windows" { "zig.exe" } else { "zig" };
+        Manifest::scan(&partial, &[zig_exe, "lib", licenses::DIR_NAME])?
+            .write(&partial.join(manifest::FILE_NAME))?;
+        if root.exists() {
+            fs_err::remove_dir_all(&root)?;
+        }
+        fs_err::rename(&partial, &root)?;
+        Ok(toolchain)
+    }
+
+    /// The Zig version this toolchain was installed as.
+    pub fn zig_version(&self) -> &str {
+        &self.version
+    }
+
+    /// Directory containing `zig` and `lib/`.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of the `zig` binary.
+    pub fn zig(&self) -> PathBuf {
+        self.root.join(ZIG_EXE)
+    }
+
+    /// Zig's `lib/` directory, which `ZIG_LIB_DIR` points at in
+    /// [`command()`](Self::command).
+    pub fn lib_dir(&self) -> PathBuf {
+        self.root.join("lib")
+    }
+
+    /// A headers-only wasm32-wasi sysroot built from this toolchain's
+    /// wasi-libc, assembled under the install directory the first time it is
+    /// asked for; see [`libc_headers::wasi_sysroot()`].
+    pub fn wasi_sysroot(&self) -> io::Result<PathBuf> {
+        let sysroot = self.root.join(libc_headers::WASI_SYSROOT_DIR);
+        if !sysroot.join("include").is_dir() {
+            libc_headers::wasi_sysroot(&self.lib_dir(), &sysroot)?;
+        }
+        Ok(sysroot)
+    }
+
+    /// The third-party licenses of this toolchain; see
+    /// [`licenses`](crate::licenses).
+    pub fn licenses(&self) -> io::Result<Vec<License>> {
+        licenses::read(&self.root)
+    }
+
+    /// The [provenance](crate::provenance) statement the build script wrote
+    /// for this toolchain. Toolchains it didn't install, like the releases
+    /// [`Toolchain::version()`] downloads, have none.
+    pub fn provenance(&self) -> io::Result<String> {
+        fs_err::read_to_string(self.root.join(provenance::FILE_NAME))
+    }
+
+    /// The include directories of the libc this toolchain bundles for the
+    /// rustc target `target`; see [`libc_headers`](crate::libc_headers).
+    pub fn libc_include_dirs(&self, target: &str) -> Vec<PathBuf> {
+        target::zig_target(target)
+            .map(|zig_target| libc_headers::include_dirs(&self.lib_dir(), &zig_target))
+            .unwrap_or_default()
+    }
+
+    /// A `zig` command with `ZIG_LIB_DIR` pointing at this toolchain's `lib/`,
+    /// so toolchains of different versions never pick up each other's
+    /// standard library. Inside build scripts the Zig caches default to
+    /// [`cache::zig_build_script_dirs()`]; `ZIG_LOCAL_CACHE_DIR` and
+    /// `ZIG_GLOBAL_CACHE_DIR` still win when set.
+    pub fn command(&self) -> Command {
+        let mut cmd = Command::new(self.zig());
+        cmd.env("ZIG_LIB_DIR", self.lib_dir());
+        if let Some((local, global)) = cache::zig_build_script_dirs() {
+            for (var, dir) in [
+                ("ZIG_LOCAL_CACHE_DIR", local),
+                ("ZIG_GLOBAL_CACHE_DIR", global),
+            ] {
+                if env::var_os(var).is_none() {
+                    cmd.env(var, dir);
+                }
+            }
+        }
+        cmd
+    }
+
+    /// [`command()`](Self::command) running one of the bundled binutils.
+    pub fn tool(&self, tool: Tool) -> Command {
+        let mut cmd = self.command();
+        cmd.arg(tool.subcommand());
+        cmd
+    }
+
+    /// Whether `zig <subcommand> --help` lists `flag`, for options that only
+    /// some Zig versions have. Results are cached per binary for the lifetime
+    /// of the process.
+    pub fn supports_flag(&self, subcommand: &str, flag: &str) -> bool {
+        static HELP: OnceLock<Mutex<HashMap<(PathBuf, String), String>>> = OnceLock::new();
+        let key = (self.zig(), subcommand.to_owned());
+        let mut help = HELP.get_or_init(Default::default).lock().unwrap();
+        let text = help.entry(key).or_insert_with(|| {
+            process::output(self.command().arg(subcommand).arg("--help"))
+                .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+                .unwrap_or_default()
+        });
+        text.split(|c: char| c.is_whitespace() || c == ',' || c == '[' || c == '=')
+            .any(|word| word == flag)
+    }
+
+    /// Checks the Zig source `source` with `zig ast-check` on stdin, which
+    /// parses it and runs the checks that need no semantic analysis (unused
+    /// locals, shadowing, ...), without touching the filesystem. Returns the
+    /// diagnostics, whose `file` is `<stdin>`; an empty list means the source
+    /// is well-formed.
+    pub fn check_syntax(&self, source: &str) -> io::Result<Vec<Diagnostic>> {
+        let output =
+            process::output_with_input(self.command().arg("ast-check"), source.as_bytes())?;
+        let diagnostics = diagnostic::parse(&String::from_utf8_lossy(&output.stderr));
+        if !output.status.success() && diagnostics.is_empty() {
+            return Err(io::Error::other(format!(
+                "zig ast-check failed: {}\n{}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        Ok(diagnostics)
+    }
+
+    /// Runs `zig env` and parses what it prints.
+    pub fn env(&self) -> Result<ZigEnv, Box<dyn Error>> {
+        let stdout = self.query("env")?;
+        ZigEnv::parse(&stdout)
+            .ok_or_else(|| format!("couldn't parse `zig env` output:\n{stdout}").into())
+    }
+
+    /// Runs `zig version` and parses what it prints. Unlike
+    /// [`zig_version()`](Self::zig_version), this is what the binary says it
+    /// is.
+    pub fn query_version(&self) -> Result<Version, Box<dyn Error>> {
+        let stdout = self.query("version")?;
+        Version::parse(&stdout)
+            .ok_or_else(|| format!("`zig version` printed {:?}", stdout.trim()).into())
+    }
+
+    fn query(&self, subcommand: &str) -> Result<String, Box<dyn Error>> {
+        let output = process::output(self.command().arg(subcommand))?;
+        if !output.status.success() {
+            return Err(format!(
+                "zig {subcommand} failed: {}\n{}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            )
+            .into());
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    }
+
+    /// Whether this Zig has `-fincremental`.
+    pub fn supports_incremental(&self) -> bool {
+        self.supports_flag("build-obj", "-fincremental")
+    }
+
+    fn manifest(&self) -> io::Result<Manifest> {
+        Manifest::read(&self.root.join(manifest::FILE_NAME))
+    }
+
+    /// Checks the installation against the hash manifest written when it was
+    /// installed.
+    pub fn verify(&self) -> io::Result<Verification> {
+        self.manifest()?.verify(&self.root)
+    }
+
+    /// Verifies the installation and copies any missing or modified files back
+    /// from `source`, a pristine copy of the same toolchain. Returns the
+    /// verification result from before the repair.
+    pub fn repair(&self, source: &Path) -> io::Result<Verification> {
+        let manifest = self.manifest()?;
+        let verification = manifest.verify(&self.root)?;
+        if !verification.is_ok() {
+            manifest.repair(&self.root, &verification, source)?;
+        }
+        Ok(verification)
+    }
+
+    /// Copies the toolchain into `dest` together with its manifest. The layout
+    /// of `dest` matches an official Zig release, so Zig finds its `lib/`
+    /// directory without any extra configuration.
+    pub fn install(&self, dest: &Path) -> io::Result<Toolchain> {
+        self.manifest()?.install(&self.root, dest)?;
+        // Its subjects are the same files, so it holds for the copy too.
+        let provenance = self.root.join(provenance::FILE_NAME);
+        if provenance.is_file() {
+            fs_err::copy(&provenance, dest.join(provenance::FILE_NAME))?;
+        }
+        Ok(Self::at(self.version.clone(), dest))
+    }
+
+    /// Signs the `zig` binary of this installation, e.g. one made with
+    /// [`install()`](Self::install) for shipping, and updates the manifest
+    /// so it still [verifies](Self::verify). The signature is recorded next
+    /// to the binary; see [`sign`](crate::sign).
+    pub fn sign(&self, signer: &Signer) -> io::Result<Signature> {
+        let signature = signer.sign_and_record(&self.zig())?;
+        let mut manifest = self.manifest()?;
+        manifest.rehash(&self.root, Path::new(ZIG_EXE))?;
+        manifest.write(&self.root.join(manifest::FILE_NAME))?;
+        Ok(signature)
+    }
+
+    /// Reports whether this toolchain can cross-compile for `rust_target`,
+    /// based on the target mapping and what `zig targets` says.
+    pub fn can_target(&self, rust_target: &str) -> Result<TargetSupport, Box<dyn Error>> {
+        Ok(TargetSupport::new(rust_target, &ZigTargets::query(self)?))
+    }
+
+    /// Lists the versions present in the shared cache. Hidden directories are
+    /// in-progress unpacks, not versions.
+    pub fn cached_versions() -> io::Result<Vec<String>> {
+        let Some(dir) = cache::toolchains_dir() else {
+            return Ok(Vec::new());
+        };
+        let mut versions = Vec::new();
+        match fs::read_dir(dir) {
+            Ok(read_dir) => {
+                for entry in read_dir {
+                    let entry = entry?;
+                    if entry.file_type()?.is_dir() {
+                        versions.extend(
+                            entry
+                                .file_name()
+                                .to_str()
+                                .filter(|name| !name.starts_with('.'))
+                                .map(String::from),
+                        );
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        versions.sort();
+        Ok(versions)
+    }
+}
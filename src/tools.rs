@@ -0,0 +1,175 @@
+//! The binutils Zig bundles: `zig ar`, `zig ranlib`, `zig dlltool`,
+//! `zig objcopy` and `zig lib`. They handle every target Zig does, so a
+//! build script can archive, strip or make import libraries when
+//! cross-compiling without a cross binutils installed:
+//!
+//! ```no_run
+//! // build.rs
+//! let status = zig_rs::tools::ar()
+//!     .args(["rcs", "libfoo.a", "foo.o"])
+//!     .status()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! For `-sys` crates whose cc-rs builds need `AR_<target>` and
+//! `RANLIB_<target>` set to programs, [`shims`](crate::shims) writes
+//! wrappers running these. Makefiles and cargo-binutils-style workflows
+//! that want `objcopy` and `strip` on `PATH` can have the `zig-objcopy` and
+//! `zig-strip` programs `cargo install zig` installs; Zig has no `strip`,
+//! so `zig-strip` runs [`strip()`].
+
+use std::{
+    ffi::OsString,
+    io,
+    process::{Command, ExitStatus},
+};
+
+use crate::{Toolchain, process};
+
+/// A binutils subcommand of `zig`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Tool {
+    /// `zig ar`, llvm-ar.
+    Ar,
+    /// `zig ranlib`, llvm-ranlib.
+    Ranlib,
+    /// `zig dlltool`, llvm-dlltool, which makes MinGW import libraries from
+    /// `.def` files.
+    Dlltool,
+    /// `zig objcopy`.
+    Objcopy,
+    /// `zig lib`, llvm-lib, which takes `lib.exe`'s arguments.
+    Lib,
+}
+
+impl Tool {
+    pub const ALL: [Self; 5] = [
+        Self::Ar,
+        Self::Ranlib,
+        Self::Dlltool,
+        Self::Objcopy,
+        Self::Lib,
+    ];
+
+    /// The `zig` subcommand, which is also the tool's usual name.
+    pub fn subcommand(self) -> &'static str {
+        match self {
+            Self::Ar => "ar",
+            Self::Ranlib => "ranlib",
+            Self::Dlltool => "dlltool",
+            Self::Objcopy => "objcopy",
+            Self::Lib => "lib",
+        }
+    }
+}
+
+/// `zig ar` of the bundled toolchain.
+pub fn ar() -> Command {
+    Toolchain::bundled().tool(Tool::Ar)
+}
+
+/// `zig ranlib` of the bundled toolchain.
+pub fn ranlib() -> Command {
+    Toolchain::bundled().tool(Tool::Ranlib)
+}
+
+/// `zig dlltool` of the bundled toolchain; see [`dlltool_machine()`] for
+/// its `-m`.
+pub fn dlltool() -> Command {
+    Toolchain::bundled().tool(Tool::Dlltool)
+}
+
+/// `zig objcopy` of the bundled toolchain.
+pub fn objcopy() -> Command {
+    Toolchain::bundled().tool(Tool::Objcopy)
+}
+
+/// `zig lib` of the bundled toolchain.
+pub fn lib() -> Command {
+    Toolchain::bundled().tool(Tool::Lib)
+}
+
+/// The `-m` machine [`dlltool()`] takes for import libraries of the Windows
+/// rustc target `target`, e.g. `i386:x86-64` for
+/// `x86_64-pc-windows-gnu`.
+pub fn dlltool_machine(target: &str) -> Option<&'static str> {
+    match target.split('-').next()? {
+        "x86_64" => Some("i386:x86-64"),
+        "i586" | "i686" => Some("i386"),
+        "aarch64" => Some("arm64"),
+        // Import libraries of ARM64EC images also carry the `#`-mangled
+        // symbols and thunks of emulation-compatible code.
+        "arm64ec" => Some("arm64ec"),
+        "thumbv7a" => Some("arm"),
+        _ => None,
+    }
+}
+
+/// The `/MACHINE:` [`lib()`] takes for import libraries of the Windows
+/// rustc target `target`, e.g. `X64` for `x86_64-pc-windows-msvc`. A
+/// `.def` file names no machine, so `zig lib /DEF:` needs one.
+pub fn lib_machine(target: &str) -> Option<&'static str> {
+    match target.split('-').next()? {
+        "x86_64" => Some("X64"),
+        "i586" | "i686" => Some("X86"),
+        "aarch64" => Some("ARM64"),
+        "arm64ec" => Some("ARM64EC"),
+        "thumbv7a" => Some("ARM"),
+        _ => None,
+    }
+}
+
+/// Does what `strip <args>` does with `zig objcopy` of `toolchain`: strips
+/// each file in place, or into the file `-o` names when there's one. Takes
+/// `-s`/`--strip-all` (the default), `-g`/`-S`/`-d`/`--strip-debug` and
+/// `--only-keep-debug`; other options are an error.
+pub fn strip(toolchain: &Toolchain, args: &[OsString]) -> io::Result<ExitStatus> {
+    let unsupported = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let mut mode = "--strip-all";
+    let mut output = None;
+    let mut files = Vec::new();
+    let mut args = args.iter();
+    let mut options = true;
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--") if options => options = false,
+            Some("-s" | "--strip-all") if options => mode = "--strip-all",
+            Some("-g" | "-S" | "-d" | "--strip-debug") if options => mode = "--strip-debug",
+            Some("--only-keep-debug") if options => mode = "--only-keep-debug",
+            Some("-o") if options => {
+                output = Some(
+                    args.next()
+                        .ok_or_else(|| unsupported("-o expects a file".into()))?,
+                );
+            }
+            Some(option) if options && option.starts_with('-') => {
+                return Err(unsupported(format!("unsupported option: {option}")));
+            }
+            _ => files.push(arg),
+        }
+    }
+    if files.is_empty() || (output.is_some() && files.len() > 1) {
+        return Err(unsupported(
+            "usage: strip [options] <file>... or strip [options] -o <output> <file>".into(),
+        ));
+    }
+    for file in files {
+        let mut cmd = toolchain.tool(Tool::Objcopy);
+        cmd.arg(mode).arg(file);
+        if let Some(output) = output {
+            cmd.arg(output);
+            return process::status(&mut cmd);
+        }
+        // Write next to the file and rename, so a failure leaves it as it
+        // was.
+        let mut stripped = file.clone();
+        stripped.push(".stripped");
+        let status = process::status(cmd.arg(&stripped))?;
+        if !status.success() {
+            let _ = fs_err::remove_file(&stripped);
+            return Ok(status);
+        }
+        fs_err::rename(&stripped, file)?;
+    }
+    Ok(ExitStatus::default())
+}
@@ -0,0 +1,179 @@
+//! Generating Zig declarations from C headers with `zig translate-c`, so a
+//! build script can bridge a C library into Zig code compiled by
+//! [`Build`](crate::Build) or [`ZigExe`](crate::zig_exe::ZigExe):
+//!
+//! ```no_run
+//! // build.rs
+//! let bindings = zig_rs::TranslateC::new("wrapper.h")
+//!     .include_dir("vendor/foo/include")
+//!     .define("FOO_NO_THREADS", None)
+//!     .write("foo.zig")?;
+//! # Ok::<(), zig_rs::Error>(())
+//! ```
+//!
+//! The header is translated for the target the crate is built for, so
+//! target-dependent types and macros come out right when cross-compiling.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use crate::{Error, Toolchain, diagnostic, error::CommandError, process, target};
+
+/// A `zig translate-c` run over one header.
+#[derive(Debug, Clone)]
+pub struct TranslateC {
+    header: PathBuf,
+    include_dirs: Vec<PathBuf>,
+    defines: Vec<(String, Option<String>)>,
+    flags: Vec<String>,
+    target: Option<String>,
+    glibc_version: Option<String>,
+    libc: bool,
+    out_dir: Option<PathBuf>,
+    cargo_metadata: bool,
+    toolchain: Toolchain,
+}
+
+impl TranslateC {
+    /// Translates `header`, which usually `#include`s the headers of the
+    /// library to bridge.
+    pub fn new(header: impl AsRef<Path>) -> Self {
+        Self {
+            header: header.as_ref().into(),
+            include_dirs: Vec::new(),
+            defines: Vec::new(),
+            flags: Vec::new(),
+            target: None,
+            glibc_version: None,
+            libc: true,
+            out_dir: None,
+            cargo_metadata: true,
+            toolchain: Toolchain::bundled(),
+        }
+    }
+
+    pub fn include_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.include_dirs.push(dir.as_ref().into());
+        self
+    }
+
+    pub fn define<'a>(&mut self, name: &str, value: impl Into<Option<&'a str>>) -> &mut Self {
+        self.defines
+            .push((name.into(), value.into().map(str::to_owned)));
+        self
+    }
+
+    /// Passes a C compiler flag through, e.g. `-std=c11`.
+    pub fn flag(&mut self, flag: &str) -> &mut Self {
+        self.flags.push(flag.into());
+        self
+    }
+
+    /// The rustc target to translate for. Defaults to `TARGET`.
+    pub fn target(&mut self, target: &str) -> &mut Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Translates against the headers of glibc `version` (`2.17`) on `-gnu`
+    /// Linux targets, like a `.2.17` suffix on the target. Defaults to
+    /// [`GLIBC_VERSION_ENV`](target::GLIBC_VERSION_ENV).
+    pub fn glibc_version(&mut self, version: &str) -> &mut Self {
+        self.glibc_version = Some(version.into());
+        self
+    }
+
+    /// Whether the target's libc headers are on the include path. On by
+    /// default; turn it off for freestanding headers.
+    pub fn libc(&mut self, libc: bool) -> &mut Self {
+        self.libc = libc;
+        self
+    }
+
+    /// Where [`write()`](Self::write) puts the file. Defaults to `OUT_DIR`.
+    pub fn out_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.out_dir = Some(dir.as_ref().into());
+        self
+    }
+
+    /// Whether to print `cargo:rerun-if-changed` for the header. On by
+    /// default.
+    pub fn cargo_metadata(&mut self, cargo_metadata: bool) -> &mut Self {
+        self.cargo_metadata = cargo_metadata;
+        self
+    }
+
+    pub fn toolchain(&mut self, toolchain: Toolchain) -> &mut Self {
+        self.toolchain = toolchain;
+        self
+    }
+
+    /// Runs `zig translate-c` and returns the generated Zig source. Failures
+    /// come back as [`Error::Command`] with the compiler's diagnostics.
+    pub fn run(&self) -> Result<String, Error> {
+        let target = match &self.target {
+            Some(target) => target.clone(),
+            None => env::var("TARGET").map_err(|_| Error::Config("TARGET is not set".into()))?,
+        };
+        let target = target::pin_glibc_version(&target, self.glibc_version.as_deref());
+        let zig_target = target::zig_target(&target)
+            .ok_or_else(|| Error::Config(format!("no Zig target for {target}")))?;
+        if self.libc {
+            target::check_glibc_version(&self.toolchain, &zig_target)
+                .map_err(|e| Error::Config(e.to_string()))?;
+        }
+        let mut cmd = self.toolchain.command();
+        cmd.arg("translate-c")
+            .arg(&self.header)
+            .arg("-target")
+            .arg(&zig_target);
+        if self.libc {
+            cmd.arg("-lc");
+        }
+        for dir in &self.include_dirs {
+            cmd.arg(format!("-I{}", dir.display()));
+        }
+        for (name, value) in &self.defines {
+            cmd.arg(match value {
+                Some(value) => format!("-D{name}={value}"),
+                None => format!("-D{name}"),
+            });
+        }
+        cmd.args(&self.flags);
+        if self.cargo_metadata {
+            build::rerun_if_changed(&self.header);
+            build::rerun_if_env_changed(target::GLIBC_VERSION_ENV);
+        }
+        let output = process::output(&mut cmd)?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+            return Err(Error::Command(Box::new(CommandError::new(
+                &cmd,
+                output.status,
+                diagnostic::parse(&stderr),
+                stderr,
+            ))));
+        }
+        String::from_utf8(output.stdout)
+            .map_err(|_| Error::Config("zig translate-c printed invalid UTF-8".into()))
+    }
+
+    /// Like [`run()`](Self::run) but writes the source to `file_name` in the
+    /// [output directory](Self::out_dir) and returns its path, for an
+    /// `@import` from a module compiled later in the build script.
+    pub fn write(&self, file_name: &str) -> Result<PathBuf, Error> {
+        let source = self.run()?;
+        let out_dir = match &self.out_dir {
+            Some(dir) => dir.clone(),
+            None => env::var_os("OUT_DIR")
+                .map(PathBuf::from)
+                .ok_or_else(|| Error::Config("OUT_DIR is not set".into()))?,
+        };
+        fs_err::create_dir_all(&out_dir)?;
+        let path = out_dir.join(file_name);
+        fs_err::write(&path, source)?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,153 @@
+//! Universal macOS binaries: the same executable, dylib or static library
+//! built for `aarch64-apple-darwin` and `x86_64-apple-darwin` and joined
+//! into one fat Mach-O file, the way `lipo -create` does, so a single
+//! artifact runs on both Apple silicon and Intel Macs. The join is done
+//! in-crate, so it works on hosts without Apple's tools:
+//!
+//! ```no_run
+//! use zig_rs::{universal, zig_exe::ZigExe};
+//!
+//! let app = universal::build("target/zig-out/app", |target| {
+//!     ZigExe::new("app", "zig/main.zig")
+//!         .target(target)
+//!         .staticlib(format!("target/{target}/release/libcore.a"))
+//!         .out_dir(format!("target/zig-out/{target}"))
+//!         .build()
+//! })?;
+//! # Ok::<(), zig_rs::Error>(())
+//! ```
+//!
+//! With [`Build`](crate::Build), give each target its own
+//! [`out_dir()`](crate::Build::out_dir) and turn off
+//! [`cargo_metadata()`](crate::Build::cargo_metadata), then return
+//! `artifacts.lib`.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// The targets a universal binary is built for.
+pub const TARGETS: [&str; 2] = ["aarch64-apple-darwin", "x86_64-apple-darwin"];
+
+const FAT_MAGIC: u32 = 0xcafe_babe;
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+const FAT_HEADER_SIZE: usize = 8;
+const FAT_ARCH_SIZE: usize = 20;
+/// Slices start on 16 KiB boundaries, the page size of arm64 macOS, which
+/// is also a multiple of x86_64's.
+const SLICE_ALIGN: u32 = 14;
+const AR_MAGIC: &[u8] = b"!<arch>\n";
+const AR_HEADER_SIZE: usize = 60;
+
+/// Builds the artifact once per target in [`TARGETS`] with `build`, which
+/// returns the path of what it built, and [joins](join()) the results at
+/// `output`. Returns `output`.
+pub fn build<E: From<io::Error>>(
+    output: impl AsRef<Path>,
+    mut build: impl FnMut(&str) -> Result<PathBuf, E>,
+) -> Result<PathBuf, E> {
+    let mut slices = Vec::new();
+    for target in TARGETS {
+        slices.push(build(target)?);
+    }
+    let output = output.as_ref();
+    join(&slices, output)?;
+    Ok(output.into())
+}
+
+/// Writes a fat Mach-O file at `output` holding the thin 64-bit Mach-O
+/// files or static libraries `inputs`, one per architecture.
+pub fn join(inputs: &[PathBuf], output: &Path) -> io::Result<()> {
+    let mut slices: Vec<(u32, u32, Vec<u8>)> = Vec::new();
+    for input in inputs {
+        let data = fs_err::read(input)?;
+        let (cputype, cpusubtype) = cpu(&data).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {e}", input.display()),
+            )
+        })?;
+        if slices.iter().any(|&(other, ..)| other == cputype) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{}: another input has the same architecture",
+                    input.display()
+                ),
+            ));
+        }
+        slices.push((cputype, cpusubtype, data));
+    }
+    // Like lipo, order slices by CPU type, which puts x86_64 before arm64.
+    slices.sort_by_key(|&(cputype, ..)| cputype);
+
+    let align = 1usize << SLICE_ALIGN;
+    let mut fat = Vec::new();
+    fat.extend(FAT_MAGIC.to_be_bytes());
+    fat.extend((slices.len() as u32).to_be_bytes());
+    let mut offset = (FAT_HEADER_SIZE + slices.len() * FAT_ARCH_SIZE).next_multiple_of(align);
+    for (cputype, cpusubtype, data) in &slices {
+        let size = u32::try_from(data.len())
+            .ok()
+            .filter(|_| offset + data.len() <= u32::MAX as usize)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "slices exceed 4 GiB"))?;
+        fat.extend(cputype.to_be_bytes());
+        fat.extend(cpusubtype.to_be_bytes());
+        fat.extend((offset as u32).to_be_bytes());
+        fat.extend(size.to_be_bytes());
+        fat.extend(SLICE_ALIGN.to_be_bytes());
+        offset = (offset + data.len()).next_multiple_of(align);
+    }
+    for (_, _, data) in &slices {
+        fat.resize(fat.len().next_multiple_of(align), 0);
+        fat.extend(data);
+    }
+    fs_err::write(output, fat)
+}
+
+/// The CPU type and subtype of a thin 64-bit Mach-O file, or of the first
+/// Mach-O member of a static library.
+fn cpu(data: &[u8]) -> Result<(u32, u32), String> {
+    if read_u32(data, 0) == Some(MH_MAGIC_64) {
+        let cputype = read_u32(data, 4).ok_or("truncated Mach-O header")?;
+        let cpusubtype = read_u32(data, 8).ok_or("truncated Mach-O header")?;
+        return Ok((cputype, cpusubtype));
+    }
+    if data.get(..4) == Some(&FAT_MAGIC.to_be_bytes()) {
+        return Err("already a fat file".into());
+    }
+    if !data.starts_with(AR_MAGIC) {
+        return Err("not a thin 64-bit Mach-O file or static library".into());
+    }
+    let mut offset = AR_MAGIC.len();
+    while let Some(header) = data.get(offset..offset + AR_HEADER_SIZE) {
+        let field =
+            |range: std::ops::Range<usize>| std::str::from_utf8(&header[range]).map(str::trim_end);
+        let size: usize = field(48..58)
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .ok_or("bad archive member header")?;
+        let mut start = offset + AR_HEADER_SIZE;
+        // BSD archives put long member names before the member's data.
+        let name_len = match field(0..16) {
+            Ok(name) => name
+                .strip_prefix("#1/")
+                .and_then(|len| len.parse().ok())
+                .unwrap_or(0),
+            Err(_) => 0,
+        };
+        start += name_len;
+        if read_u32(data, start) == Some(MH_MAGIC_64) {
+            return cpu(&data[start..]);
+        }
+        offset = (offset + AR_HEADER_SIZE + size).next_multiple_of(2);
+    }
+    Err("static library without Mach-O objects".into())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
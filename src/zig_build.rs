@@ -0,0 +1,472 @@
+//! Building existing `build.zig` packages from a Cargo build script.
+
+use std::{
+    env,
+    error::Error,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    Toolchain, cache,
+    config::Config,
+    download, integrity, jobs,
+    log::{self, Tagger},
+    target,
+};
+
+/// Environment variable that opts `build_zig_package()` into incremental
+/// compilation.
+pub const INCREMENTAL_ENV: &str = "ZIG_RS_INCREMENTAL";
+
+/// Zig's `-Doptimize` mode matching the Cargo profile of the running build
+/// script: `Debug` for `opt-level = 0`, `ReleaseSmall` for `s`/`z`,
+/// `ReleaseSafe` when debug assertions are on and `ReleaseFast` otherwise.
+pub fn optimize_for_profile() -> &'static str {
+    match env::var("OPT_LEVEL").as_deref() {
+        Ok("0") => "Debug",
+        Ok("s" | "z") => "ReleaseSmall",
+        _ if env::var_os("CARGO_CFG_DEBUG_ASSERTIONS").is_some() => "ReleaseSafe",
+        _ => "ReleaseFast",
+    }
+}
+
+/// Builds the Zig package in `dir` (the directory containing `build.zig`) for
+/// the rustc target `target` and links everything it installs into the crate
+/// being built — the `cc::Build::new().compile()` of Zig packages. Shorthand
+/// for a [`ZigBuild`] with a [`define()`](ZigBuild::define) per entry in
+/// `options`; see [`ZigBuild::build()`]. Returns the install prefix, which
+/// [`artifacts()`] lists.
+///
+/// ```no_run
+/// // build.rs
+/// let target = std::env::var("TARGET")?;
+/// zig_rs::build_zig_package("vendor/foo", &target, [("shared", "false")])?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn build_zig_package<K: AsRef<str>, V: AsRef<str>>(
+    dir: impl AsRef<Path>,
+    target: &str,
+    options: impl IntoIterator<Item = (K, V)>,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let mut build = ZigBuild::new(dir);
+    build.target(target);
+    for (key, value) in options {
+        build.define(key.as_ref(), value.as_ref());
+    }
+    build.build()
+}
+
+/// A `zig build` of an existing `build.zig` project, with its installed
+/// libraries linked into the crate being built:
+///
+/// ```no_run
+/// // build.rs
+/// zig_rs::ZigBuild::new("vendor/foo")
+///     .step("lib")
+///     .define("shared", "false")
+///     .build()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ZigBuild {
+    dir: PathBuf,
+    step: String,
+    target: Option<String>,
+    glibc_version: Option<String>,
+    optimize: Option<String>,
+    options: Vec<(String, String)>,
+    toolchain: Toolchain,
+}
+
+impl ZigBuild {
+    /// A build of the package in `dir`, the directory containing
+    /// `build.zig`.
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().into(),
+            step: "install".into(),
+            target: None,
+            glibc_version: None,
+            optimize: None,
+            options: Vec::new(),
+            toolchain: Toolchain::bundled(),
+        }
+    }
+
+    /// The step to run. Defaults to `install`; other steps install under
+    /// the same prefix as far as they depend on install steps.
+    pub fn step(&mut self, step: &str) -> &mut Self {
+        self.step = step.into();
+        self
+    }
+
+    /// Passes `-D<key>=<value>` to `build.zig`.
+    pub fn define(&mut self, key: &str, value: &str) -> &mut Self {
+        self.options.push((key.into(), value.into()));
+        self
+    }
+
+    /// The rustc target to build for, which becomes `-Dtarget`. Defaults to
+    /// `TARGET`.
+    pub fn target(&mut self, target: &str) -> &mut Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Links against glibc `version` (`2.17`) on `-gnu` Linux targets, like
+    /// a `.2.17` suffix on the target. Defaults to
+    /// [`GLIBC_VERSION_ENV`](target::GLIBC_VERSION_ENV).
+    pub fn glibc_version(&mut self, version: &str) -> &mut Self {
+        self.glibc_version = Some(version.into());
+        self
+    }
+
+    /// Zig's `-Doptimize` mode, e.g. `ReleaseSafe`. Defaults to
+    /// [`optimize_for_profile()`].
+    pub fn optimize(&mut self, mode: &str) -> &mut Self {
+        self.optimize = Some(mode.into());
+        self
+    }
+
+    pub fn toolchain(&mut self, toolchain: Toolchain) -> &mut Self {
+        self.toolchain = toolchain;
+        self
+    }
+
+    /// Runs the build and links every library it installs. A glibc pin on the
+    /// target (`x86_64-unknown-linux-gnu.2.17`) is checked against
+    /// `zig targets` first.
+    ///
+    /// This runs `zig build <step>` with `-Dtarget`, `-Doptimize` and the
+    /// [defines](Self::define); wasm targets built with threads also get
+    /// `-Dcpu` with atomics and bulk memory (see
+    /// [`target::wasm_threads_default()`]), unless the project
+    /// [`config`](crate::config) sets an `mcpu` for the target, which becomes
+    /// `-Dcpu`. The Zig caches are those of [`Toolchain::command()`], so they
+    /// live inside `OUT_DIR` unless [`cache::SHARED_ZIG_CACHE_ENV`] is set.
+    /// With [`INCREMENTAL_ENV`] set and a toolchain that supports it the
+    /// build runs with `-fincremental` and a local cache in
+    /// [`cache::incremental_state_dir()`], which outlives `cargo clean`.
+    /// `zig build` runs with `-j` set to the jobs free on Cargo's jobserver
+    /// (see [`jobs::acquire_available()`]) and passes the jobserver on to the
+    /// tools it runs. In strict offline mode (see [`download::OFFLINE_ENV`]) a
+    /// `build.zig.zon` dependency missing from the Zig cache is an error
+    /// instead of a fetch.
+    ///
+    /// The install prefix is `OUT_DIR/zig-out/<dir name>`; every static or
+    /// shared library in its `lib/` directory is linked, and the whole
+    /// package directory is registered with `cargo:rerun-if-changed`.
+    /// Returns the install prefix, which [`artifacts()`] lists.
+    pub fn build(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let dir = self.dir.as_path();
+        let out_dir = PathBuf::from(env::var_os("OUT_DIR").ok_or("OUT_DIR is not set")?);
+        let target = match &self.target {
+            Some(target) => target.clone(),
+            None => env::var("TARGET").map_err(|_| "TARGET is not set")?,
+        };
+        let target = target::pin_glibc_version(&target, self.glibc_version.as_deref());
+        let target = target.as_str();
+        let zig_target =
+            target::zig_target(target).ok_or_else(|| format!("no Zig target for {target}"))?;
+        let name = dir
+            .canonicalize()?
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "package".into());
+        let prefix = out_dir.join("zig-out").join(&name);
+
+        let toolchain = &self.toolchain;
+        target::check_glibc_version(toolchain, &zig_target)?;
+        let mut cmd = toolchain.command();
+        cmd.current_dir(dir)
+            .arg("build")
+            .arg(&self.step)
+            .arg("--prefix")
+            .arg(&prefix)
+            .arg(format!("-Dtarget={zig_target}"))
+            .arg(format!(
+                "-Doptimize={}",
+                self.optimize
+                    .as_deref()
+                    .unwrap_or_else(|| optimize_for_profile())
+            ));
+        let config = Config::load()?;
+        config.rerun_if_changed();
+        let (rust_target, _) = target::split_glibc_version(target);
+        if let Some(mcpu) = config.mcpu(rust_target) {
+            cmd.arg(format!("-Dcpu={mcpu}"));
+        } else if target::wasm_threads_default(target) {
+            cmd.arg(format!("-Dcpu={}", target::WASM_THREADS_CPU));
+        }
+        for (key, value) in &self.options {
+            cmd.arg(format!("-D{key}={value}"));
+        }
+        if env::var_os(INCREMENTAL_ENV).is_some()
+            && toolchain.supports_incremental()
+            && let Some(state) =
+                cache::incremental_state_dir(dir, toolchain.zig_version(), &zig_target)?
+        {
+            cmd.arg("-fincremental").arg("--cache-dir").arg(state);
+        }
+        let global_cache = cache::zig_build_script_dirs()
+            .map(|(_, global)| global)
+            .or_else(cache::zig_global_dir);
+        check_offline_dependencies(dir, global_cache.as_deref())?;
+        check_pinned_dependencies(dir)?;
+        let tokens = jobs::acquire_available(jobs::max_jobs());
+        cmd.arg(format!("-j{}", tokens.jobs()));
+        jobs::configure(&mut cmd);
+        let status = log::run_tagged(&mut cmd, &Tagger::new(&format!("zig-build/{name}")))?;
+        drop(tokens);
+        if !status.success() {
+            return Err(format!("{cmd:?} failed: {status}").into());
+        }
+
+        link_installed_libs(&prefix.join("lib"))?;
+        build::rerun_if_changed(dir);
+        build::rerun_if_env_changed(cache::SHARED_ZIG_CACHE_ENV);
+        build::rerun_if_env_changed(INCREMENTAL_ENV);
+        build::rerun_if_env_changed(target::GLIBC_VERSION_ENV);
+        build::rerun_if_env_changed(download::OFFLINE_ENV);
+        Ok(prefix)
+    }
+}
+
+/// What kind of file a `zig build install` put under its prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArtifactKind {
+    /// In `bin/`.
+    Executable,
+    /// `.a` or `.lib` in `lib/`.
+    StaticLibrary,
+    /// `.so`, `.dylib` or `.dll`, in `lib/` or (Windows) `bin/`.
+    SharedLibrary,
+    /// Anything under `include/`.
+    Header,
+    /// Anything under `docs/` or `share/doc/`.
+    Docs,
+}
+
+/// A file installed by [`ZigBuild::build()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Artifact {
+    pub kind: ArtifactKind,
+    pub path: PathBuf,
+    /// `path` relative to the install prefix, e.g. `include/foo/bar.h`.
+    pub relative: PathBuf,
+    /// The rustc target the package was built for.
+    pub target: String,
+}
+
+impl Artifact {
+    /// The name to link a library as, `foo` for `libfoo.a`.
+    pub fn link_name(&self) -> Option<&str> {
+        if !matches!(
+            self.kind,
+            ArtifactKind::StaticLibrary | ArtifactKind::SharedLibrary
+        ) {
+            return None;
+        }
+        let file_name = self.path.file_name()?.to_str()?;
+        let stem = file_name.split('.').next()?;
+        Some(stem.strip_prefix("lib").unwrap_or(stem))
+    }
+
+    /// Copies the file to `dir`, keeping its path relative to the prefix,
+    /// and returns where it went.
+    pub fn copy_to(&self, dir: &Path) -> io::Result<PathBuf> {
+        let dest = dir.join(&self.relative);
+        if let Some(parent) = dest.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        fs_err::copy(&self.path, &dest)?;
+        Ok(dest)
+    }
+}
+
+/// Lists what `zig build install` put under `prefix` for `target`, sorted by
+/// path. Files that are none of the [`ArtifactKind`]s (`.pdb`, `pkgconfig/`,
+/// ...) are left out.
+///
+/// ```no_run
+/// // build.rs
+/// use zig_rs::zig_build::{self, ArtifactKind};
+///
+/// let target = std::env::var("TARGET")?;
+/// let prefix = zig_rs::build_zig_package("vendor/foo", &target, [("shared", "false")])?;
+/// let artifacts = zig_build::artifacts(&prefix, &target)?;
+/// let headers = artifacts.iter().filter(|a| a.kind == ArtifactKind::Header);
+/// zig_build::copy_to_out_dir(headers)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn artifacts(prefix: &Path, target: &str) -> io::Result<Vec<Artifact>> {
+    let mut files = Vec::new();
+    walk(prefix, &mut files)?;
+    files.sort();
+    Ok(files
+        .into_iter()
+        .filter_map(|path| {
+            let relative = path.strip_prefix(prefix).ok()?.to_owned();
+            let kind = artifact_kind(&relative)?;
+            Some(Artifact {
+                kind,
+                path,
+                relative,
+                target: target.into(),
+            })
+        })
+        .collect())
+}
+
+/// Copies `artifacts` into `OUT_DIR` with [`Artifact::copy_to()`] and returns
+/// the copies.
+pub fn copy_to_out_dir<'a>(
+    artifacts: impl IntoIterator<Item = &'a Artifact>,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").ok_or("OUT_DIR is not set")?);
+    Ok(artifacts
+        .into_iter()
+        .map(|artifact| artifact.copy_to(&out_dir))
+        .collect::<Result<_, _>>()?)
+}
+
+fn artifact_kind(relative: &Path) -> Option<ArtifactKind> {
+    let mut components = relative.components().map(|c| c.as_os_str().to_str());
+    let top = components.next()??;
+    let file_name = relative.file_name()?.to_str()?;
+    let nested = relative.components().count() > 2;
+    Some(match top {
+        "include" => ArtifactKind::Header,
+        "docs" => ArtifactKind::Docs,
+        "share" if components.next()? == Some("doc") => ArtifactKind::Docs,
+        "bin" if nested => return None,
+        "bin" if file_name.ends_with(".dll") => ArtifactKind::SharedLibrary,
+        "bin" if file_name.ends_with(".pdb") => return None,
+        "bin" => ArtifactKind::Executable,
+        "lib" if nested => return None,
+        "lib" if file_name.contains(".so.") => ArtifactKind::SharedLibrary,
+        "lib" => match library_kind_and_name(file_name)?.0 {
+            "static" => ArtifactKind::StaticLibrary,
+            _ => ArtifactKind::SharedLibrary,
+        },
+        _ => return None,
+    })
+}
+
+pub(crate) fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs_err::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            walk(&entry.path(), files)?;
+        } else {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// Fails in strict offline mode when `zig build` in `dir` would fetch a
+/// dependency: one with a `.url` whose hash isn't in `global_cache/p/`.
+fn check_offline_dependencies(
+    dir: &Path,
+    global_cache: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    if !download::strict_offline() {
+        return Ok(());
+    }
+    let Ok(zon) = fs_err::read_to_string(dir.join("build.zig.zon")) else {
+        return Ok(());
+    };
+    for (url, hash) in remote_dependencies(&zon) {
+        let cached = hash
+            .zip(global_cache)
+            .is_some_and(|(hash, cache)| cache.join("p").join(hash).exists());
+        if !cached {
+            download::require_network(
+                &url,
+                &format!(
+                    "`zig fetch` it into a Zig cache shared through {}, or make it a `.path` dependency",
+                    cache::SHARED_ZIG_CACHE_ENV
+                ),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Fails when a [checksum database](integrity::Sums) doesn't pin every
+/// `.url` dependency of the `build.zig.zon` in `dir` to its `.hash`.
+fn check_pinned_dependencies(dir: &Path) -> Result<(), Box<dyn Error>> {
+    let Ok(zon) = fs_err::read_to_string(dir.join("build.zig.zon")) else {
+        return Ok(());
+    };
+    let Some(sums) = integrity::Sums::find()? else {
+        return Ok(());
+    };
+    for (url, hash) in remote_dependencies(&zon) {
+        sums.check_package(&url, hash.as_deref())?;
+    }
+    Ok(())
+}
+
+/// The `.url` and `.hash` of every dependency in a `build.zig.zon`, found by
+/// looking for `.url` fields and the struct literal around each.
+fn remote_dependencies(zon: &str) -> Vec<(String, Option<String>)> {
+    let mut deps = Vec::new();
+    let mut pos = 0;
+    while let Some(i) = zon[pos..].find(".url") {
+        let start = pos + i;
+        let open = zon[..start].rfind(".{").unwrap_or(0);
+        let close = zon[start..].find('}').map_or(zon.len(), |j| start + j);
+        let entry = &zon[open..close];
+        if let Some(url) = zon_string(entry, ".url") {
+            deps.push((url, zon_string(entry, ".hash")));
+        }
+        pos = close;
+    }
+    deps
+}
+
+/// The string literal assigned to `field` in `entry`.
+fn zon_string(entry: &str, field: &str) -> Option<String> {
+    let rest = &entry[entry.find(field)? + field.len()..];
+    let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+    Some(rest[..rest.find('"')?].to_owned())
+}
+
+/// Emits `cargo:rustc-link-search` for `lib_dir` and `cargo:rustc-link-lib`
+/// for every library in it.
+pub(crate) fn link_installed_libs(lib_dir: &Path) -> Result<(), Box<dyn Error>> {
+    if !lib_dir.exists() {
+        return Ok(());
+    }
+    build::rustc_link_search_kind("native", lib_dir);
+    let mut entries: Vec<_> = fs_err::read_dir(lib_dir)?
+        .map(|entry| entry.map(|e| e.file_name()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    for file_name in entries {
+        let Some(file_name) = file_name.to_str() else {
+            continue;
+        };
+        if let Some((kind, name)) = library_kind_and_name(file_name) {
+            build::rustc_link_lib_kind(kind, name);
+        }
+    }
+    Ok(())
+}
+
+/// Splits a library file name into a `rustc-link-lib` kind and name, e.g.
+/// `libfoo.a` → `("static", "foo")`.
+pub(crate) fn library_kind_and_name(file_name: &str) -> Option<(&'static str, &str)> {
+    let (stem, ext) = file_name.rsplit_once('.')?;
+    let kind = match ext {
+        "a" | "lib" => "static",
+        "so" | "dylib" => "dylib",
+        _ => return None,
+    };
+    let name = stem.strip_prefix("lib").unwrap_or(stem);
+    Some((kind, name))
+}
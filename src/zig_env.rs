@@ -0,0 +1,243 @@
+//! What `zig env` and `zig version` say, parsed, for tools that pick cache
+//! paths or features by what the toolchain is:
+//!
+//! ```no_run
+//! use zig_rs::zig_env::Version;
+//!
+//! let env = zig_rs::env()?;
+//! println!("Zig's global cache is in {}", env.global_cache_dir.display());
+//! if zig_rs::version()? >= Version::new(0, 15, 0) {
+//!     // ...
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! `zig env` prints JSON up to Zig 0.14 and ZON after; both are read.
+
+use std::{cmp::Ordering, fmt, path::PathBuf};
+
+/// The output of `zig env`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZigEnv {
+    pub zig_exe: PathBuf,
+    pub lib_dir: PathBuf,
+    pub std_dir: PathBuf,
+    pub global_cache_dir: PathBuf,
+    pub version: Version,
+    /// The native target with its OS and libc versions, e.g.
+    /// `x86_64-linux.6.8...6.8-gnu.2.39`.
+    pub target: String,
+}
+
+impl ZigEnv {
+    /// Parses the output of `zig env`, or gives `None` when a field is
+    /// missing.
+    pub fn parse(output: &str) -> Option<Self> {
+        Some(Self {
+            zig_exe: field(output, "zig_exe")?.into(),
+            lib_dir: field(output, "lib_dir")?.into(),
+            std_dir: field(output, "std_dir")?.into(),
+            global_cache_dir: field(output, "global_cache_dir")?.into(),
+            version: Version::parse(&field(output, "version")?)?,
+            target: field(output, "target")?,
+        })
+    }
+}
+
+/// Reads a string field from `zig env` output, which is JSON in older
+/// releases and ZON in newer ones: `"key": "value"` or `.key = "value"`,
+/// with the first such key winning. `None` when the key is missing, its
+/// value isn't a string or the string isn't closed.
+pub fn field(output: &str, key: &str) -> Option<String> {
+    let is_identifier = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let json = format!("\"{key}\"");
+    let zon = format!(".{key}");
+    let value = |start: usize, separator: char| {
+        let rest = output[start..].trim_start().strip_prefix(separator)?;
+        string(rest.trim_start().strip_prefix('"')?)
+    };
+    let jsons = output
+        .match_indices(&json)
+        .filter_map(|(i, _)| Some((i, value(i + json.len(), ':')?)));
+    let zons = output
+        .match_indices(&zon)
+        .filter(|(i, _)| !output[..*i].ends_with(is_identifier))
+        .filter(|(i, _)| !output[i + zon.len()..].starts_with(is_identifier))
+        .filter_map(|(i, _)| Some((i, value(i + zon.len(), '=')?)));
+    jsons
+        .chain(zons)
+        .min_by_key(|(i, _)| *i)
+        .map(|(_, value)| value)
+}
+
+/// The rest of a string literal after its opening quote, unescaped, or
+/// `None` when it isn't closed.
+fn string(literal: &str) -> Option<String> {
+    let mut value = String::new();
+    let mut chars = literal.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(value),
+            '\\' => value.push(match chars.next()? {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                c => c,
+            }),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// A Zig version, which is a semantic version: `0.14.0`, or
+/// `0.15.0-dev.1+a1b2c3d` for a development build. Versions compare by
+/// semver precedence, so a development build comes before the release it
+/// leads up to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    /// The pre-release part, e.g. `dev.1`.
+    pub pre: Option<String>,
+    /// The build metadata, for Zig the commit, e.g. `a1b2c3d`.
+    pub build: Option<String>,
+}
+
+impl Version {
+    pub const fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            pre: None,
+            build: None,
+        }
+    }
+
+    /// Parses `major.minor.patch[-pre][+build]`, as `zig version` prints it.
+    pub fn parse(version: &str) -> Option<Self> {
+        let version = version.trim();
+        let (version, build) = match version.split_once('+') {
+            Some((version, build)) => (version, Some(build)),
+            None => (version, None),
+        };
+        let (version, pre) = match version.split_once('-') {
+            Some((version, pre)) => (version, Some(pre)),
+            None => (version, None),
+        };
+        let mut numbers = version.split('.').map(|n| n.parse().ok());
+        let (Some(Some(major)), Some(Some(minor)), Some(Some(patch)), None) = (
+            numbers.next(),
+            numbers.next(),
+            numbers.next(),
+            numbers.next(),
+        ) else {
+            return None;
+        };
+        let valid = |part: &str| {
+            !part.is_empty()
+                && part.split('.').all(|identifier| {
+                    !identifier.is_empty()
+                        && identifier
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+                })
+        };
+        if !pre.is_none_or(valid) || !build.is_none_or(valid) {
+            return None;
+        }
+        Some(Self {
+            major,
+            minor,
+            patch,
+            pre: pre.map(str::to_owned),
+            build: build.map(str::to_owned),
+        })
+    }
+
+    /// Whether this is a development build rather than a release.
+    pub fn is_dev(&self) -> bool {
+        self.pre
+            .as_deref()
+            .is_some_and(|pre| pre == "dev" || pre.starts_with("dev."))
+    }
+
+    /// The Zig commit in the build metadata.
+    pub fn commit(&self) -> Option<&str> {
+        self.build.as_deref()
+    }
+
+    /// Whether this is `expected`, or any build of it when `expected` names
+    /// no commit, so `0.15.0-dev.1` asks for `0.15.0-dev.1+a1b2c3d`.
+    pub fn matches(&self, expected: &Version) -> bool {
+        (self.major, self.minor, self.patch, &self.pre)
+            == (
+                expected.major,
+                expected.minor,
+                expected.patch,
+                &expected.pre,
+            )
+            && (expected.build.is_none() || self.build == expected.build)
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (&self.pre, &other.pre) {
+                (None, None) => Ordering::Equal,
+                // A pre-release comes before the release.
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => compare_pre(a, b),
+            })
+            // Semver gives build metadata no precedence; it only keeps the
+            // order consistent with equality.
+            .then_with(|| self.build.cmp(&other.build))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compares pre-releases identifier by identifier: numeric ones as numbers
+/// and before alphanumeric ones, which compare as text.
+fn compare_pre(a: &str, b: &str) -> Ordering {
+    let mut a = a.split('.');
+    let mut b = b.split('.');
+    loop {
+        let ordering = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => match (x.parse::<u64>(), y.parse::<u64>()) {
+                (Ok(x), Ok(y)) => x.cmp(&y),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => x.cmp(y),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(pre) = &self.pre {
+            write!(f, "-{pre}")?;
+        }
+        if let Some(build) = &self.build {
+            write!(f, "+{build}")?;
+        }
+        Ok(())
+    }
+}
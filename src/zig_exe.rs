@@ -0,0 +1,484 @@
+//! Linking Rust `staticlib`s into an executable whose entry point is Zig, for
+//! projects that keep their logic in Rust but their `main` in Zig:
+//!
+//! ```no_run
+//! // xtask, after `cargo build --release -p core --target x86_64-unknown-linux-musl`
+//! let exe = zig_rs::zig_exe::ZigExe::new("app", "zig/main.zig")
+//!     .target("x86_64-unknown-linux-musl")
+//!     .staticlib("target/x86_64-unknown-linux-musl/release/libcore.a")
+//!     .out_dir("target/zig-out")
+//!     .build()?;
+//! # Ok::<(), zig_rs::Error>(())
+//! ```
+//!
+//! A Rust `staticlib` leaves its system libraries (`-lgcc_s -lpthread -lc` on
+//! glibc, `-lSystem` on Apple, `ntdll.lib` on Windows, ...) to whoever links
+//! it; rustc prints them with `--print native-static-libs`. Unless given
+//! [`ZigExe::native_libs()`], the builder assumes the set rustc prints for
+//! `std` on the target, [`default_native_libs()`], and translates it for
+//! `zig build-exe`, which provides libunwind and compiler-rt itself.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    Error, Toolchain,
+    compile::Subsystem,
+    diagnostic,
+    error::CommandError,
+    glibc_audit::{self, GlibcCheck},
+    link_report,
+    log::{self, Tagger},
+    notarize, postprocess,
+    process::{self, Limits},
+    sign::{Signature, Signer},
+    target, zig_build,
+};
+
+/// Builds a Zig executable from a root source file and Rust `staticlib`s with
+/// `zig build-exe`.
+#[derive(Debug, Clone)]
+pub struct ZigExe {
+    name: String,
+    root: PathBuf,
+    sources: Vec<PathBuf>,
+    staticlibs: Vec<PathBuf>,
+    native_libs: Option<Vec<String>>,
+    target: Option<String>,
+    glibc_version: Option<String>,
+    optimize: Option<String>,
+    subsystem: Option<Subsystem>,
+    out_dir: Option<PathBuf>,
+    sysroot: Option<PathBuf>,
+    glibc_check: Option<GlibcCheck>,
+    split_debug_info: bool,
+    entitlements: Option<PathBuf>,
+    notarizable: bool,
+    signer: Option<Signer>,
+    limits: Limits,
+    toolchain: Toolchain,
+}
+
+/// What [`ZigExe::build_artifacts()`] produced.
+#[derive(Debug, Clone)]
+pub struct Artifacts {
+    pub exe: PathBuf,
+    /// The separate debug info, with [`ZigExe::split_debug_info()`]: a
+    /// `.debug` file, `.dSYM` bundle or `.pdb`.
+    pub debug_info: Option<PathBuf>,
+    /// The signature of [`exe`](Self::exe), with [`ZigExe::signer()`].
+    pub signature: Option<Signature>,
+}
+
+impl ZigExe {
+    /// An executable called `name` whose root source file is `root`.
+    pub fn new(name: &str, root: impl AsRef<Path>) -> Self {
+        Self {
+            name: name.into(),
+            root: root.as_ref().into(),
+            sources: Vec::new(),
+            staticlibs: Vec::new(),
+            native_libs: None,
+            target: None,
+            glibc_version: None,
+            optimize: None,
+            subsystem: None,
+            out_dir: None,
+            sysroot: None,
+            glibc_check: None,
+            split_debug_info: false,
+            entitlements: None,
+            notarizable: false,
+            signer: None,
+            limits: Limits::default(),
+            toolchain: Toolchain::bundled(),
+        }
+    }
+
+    /// Adds a C, C++ or assembly source compiled into the executable.
+    pub fn source(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.sources.push(path.as_ref().into());
+        self
+    }
+
+    /// Links a Rust `staticlib` (`lib<name>.a`, `<name>.lib` for MSVC
+    /// targets) built for the same target.
+    pub fn staticlib(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.staticlibs.push(path.as_ref().into());
+        self
+    }
+
+    /// The system libraries the staticlibs need, as rustc prints them for
+    /// `--print native-static-libs` (see [`native_static_libs()`]). Defaults
+    /// to [`default_native_libs()`].
+    pub fn native_libs<S: AsRef<str>>(&mut self, libs: impl IntoIterator<Item = S>) -> &mut Self {
+        self.native_libs = Some(libs.into_iter().map(|s| s.as_ref().into()).collect());
+        self
+    }
+
+    /// The rustc target to build for. Defaults to `TARGET`.
+    pub fn target(&mut self, target: &str) -> &mut Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Links against glibc `version` (`2.17`) on `-gnu` Linux targets, like
+    /// a `.2.17` suffix on the target. Defaults to
+    /// [`GLIBC_VERSION_ENV`](target::GLIBC_VERSION_ENV).
+    pub fn glibc_version(&mut self, version: &str) -> &mut Self {
+        self.glibc_version = Some(version.into());
+        self
+    }
+
+    /// Zig's optimize mode, e.g. `ReleaseFast`. Defaults to
+    /// [`zig_build::optimize_for_profile()`].
+    pub fn optimize(&mut self, mode: &str) -> &mut Self {
+        self.optimize = Some(mode.into());
+        self
+    }
+
+    /// The PE subsystem of Windows and UEFI executables. Zig defaults to
+    /// [`Subsystem::Console`] and [`Subsystem::EfiApplication`]; a UEFI
+    /// executable is written as `<name>.efi`.
+    pub fn subsystem(&mut self, subsystem: Subsystem) -> &mut Self {
+        self.subsystem = Some(subsystem);
+        self
+    }
+
+    /// Where the executable goes. Defaults to `OUT_DIR`.
+    pub fn out_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.out_dir = Some(dir.as_ref().into());
+        self
+    }
+
+    /// The system root to take headers and libraries, libc included, from.
+    /// Required for targets whose libc Zig doesn't bundle, Haiku and Fuchsia
+    /// (see [`target::needs_sysroot()`]).
+    pub fn sysroot(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.sysroot = Some(dir.as_ref().into());
+        self
+    }
+
+    /// Audits the executable after linking for a target pinned to a glibc
+    /// version (`x86_64-unknown-linux-gnu.2.17`), warning about or failing on
+    /// symbols that need a newer glibc; see [`glibc_audit`]. Off by default.
+    pub fn glibc_check(&mut self, check: GlibcCheck) -> &mut Self {
+        self.glibc_check = Some(check);
+        self
+    }
+
+    /// Builds with debug info and moves it out of the executable into a
+    /// separate file that [`build_artifacts()`](Self::build_artifacts)
+    /// returns, so the executable ships stripped while its symbols are
+    /// archived; see [`postprocess::split_debug_info()`].
+    pub fn split_debug_info(&mut self, split: bool) -> &mut Self {
+        self.split_debug_info = split;
+        self
+    }
+
+    /// An entitlements plist to embed in the code signature of an Apple
+    /// executable, which re-signing after
+    /// [`split_debug_info()`](Self::split_debug_info) keeps. The Developer ID
+    /// signature made before notarizing replaces it, so pass the same file to
+    /// `codesign --entitlements` there.
+    pub fn entitlements(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.entitlements = Some(path.as_ref().into());
+        self
+    }
+
+    /// For Apple targets, leaves room in the header for `codesign` and fails
+    /// the build when the executable couldn't be notarized after signing;
+    /// see [`notarize::check()`]. Off by default.
+    pub fn notarizable(&mut self, notarizable: bool) -> &mut Self {
+        self.notarizable = notarizable;
+        self
+    }
+
+    /// Signs the executable once it's written, after everything else that
+    /// rewrites it, and records the signature in the output directory; see
+    /// [`sign`](crate::sign). Defaults to the command in
+    /// [`sign::ENV`](crate::sign::ENV), if set.
+    pub fn signer(&mut self, signer: Signer) -> &mut Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Memory and CPU time limits for `zig build-exe`; see
+    /// [`Build::limits()`](crate::Build::limits).
+    pub fn limits(&mut self, limits: Limits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn toolchain(&mut self, toolchain: Toolchain) -> &mut Self {
+        self.toolchain = toolchain;
+        self
+    }
+
+    /// Links the executable and returns its path.
+    pub fn build(&self) -> Result<PathBuf, Error> {
+        Ok(self.build_artifacts()?.exe)
+    }
+
+    /// Links the executable and returns its path and that of its split debug
+    /// info.
+    pub fn build_artifacts(&self) -> Result<Artifacts, Error> {
+        let target = match &self.target {
+            Some(target) => target.clone(),
+            None => env::var("TARGET").map_err(|_| Error::Config("TARGET is not set".into()))?,
+        };
+        let target = target::pin_glibc_version(&target, self.glibc_version.as_deref());
+        let zig_target = target::zig_target(&target)
+            .ok_or_else(|| Error::Config(format!("no Zig target for {target}")))?;
+        target::check_glibc_version(&self.toolchain, &zig_target)
+            .map_err(|e| Error::Config(e.to_string()))?;
+        let out_dir = match &self.out_dir {
+            Some(dir) => dir.clone(),
+            None => env::var_os("OUT_DIR")
+                .map(PathBuf::from)
+                .ok_or_else(|| Error::Config("OUT_DIR is not set".into()))?,
+        };
+        fs_err::create_dir_all(&out_dir)?;
+        let exe = out_dir.join(format!("{}{}", self.name, exe_suffix(&target)));
+
+        let mut cmd = self.toolchain.command();
+        cmd.arg("build-exe")
+            .arg(&self.root)
+            .arg("-target")
+            .arg(&zig_target)
+            .arg("-O")
+            .arg(
+                self.optimize
+                    .as_deref()
+                    .unwrap_or_else(|| zig_build::optimize_for_profile()),
+            )
+            .arg("--name")
+            .arg(&self.name)
+            .arg(format!("-femit-bin={}", exe.display()))
+            .args(&self.sources)
+            .args(&self.staticlibs);
+        let native_libs = match &self.native_libs {
+            Some(libs) => libs.clone(),
+            None => default_native_libs(&target),
+        };
+        cmd.args(zig_link_args(&native_libs));
+        if let Some(subsystem) = self.subsystem {
+            cmd.arg("--subsystem").arg(subsystem.name());
+        }
+        if self.split_debug_info {
+            cmd.arg("-fno-strip");
+        }
+        let libc = !target.starts_with("wasm32-unknown")
+            && !target.contains("-none")
+            && !target.contains("-uefi");
+        if libc {
+            if self.sysroot.is_none() && target::needs_sysroot(&target) {
+                return Err(Error::Config(format!(
+                    "Zig bundles no libc for {target}; set ZigExe::sysroot() to its SDK's sysroot"
+                )));
+            }
+            cmd.arg("-lc");
+        }
+        if let Some(sysroot) = &self.sysroot {
+            cmd.arg("--sysroot").arg(sysroot);
+        }
+        let apple = target.contains("-apple-");
+        if apple && let Some(entitlements) = &self.entitlements {
+            cmd.arg("--entitlements").arg(entitlements);
+        }
+        if apple && self.notarizable {
+            cmd.arg("-headerpad_max_install_names");
+        }
+        if target.contains("-fuchsia") {
+            // Fuchsia only loads position-independent executables, and
+            // rustc links with 4 KiB pages and eager binding there too.
+            cmd.args(["-fPIE", "-z", "now", "-z", "max-page-size=4096"]);
+        }
+
+        let tagger = self.tagger();
+        let (status, output) = process::with_limits(self.limits, || {
+            let (status, output) = log::run_tagged_captured(&mut cmd, &tagger)?;
+            if status.success() {
+                return Ok((status, output));
+            }
+            link_report::rerun_verbose(&mut cmd, &tagger, status, output)
+        })?;
+        if !status.success() {
+            return Err(Error::Command(Box::new(CommandError::new(
+                &cmd,
+                status,
+                diagnostic::parse(&output),
+                output,
+            ))));
+        }
+        if let Some(check) = self.glibc_check
+            && let (_, Some(glibc)) = target::split_glibc_version(&target)
+            && let Some(report) = glibc_audit::audit(&exe, glibc)?
+        {
+            match check {
+                GlibcCheck::Warn => {
+                    for line in report.to_string().lines() {
+                        build::warning(line);
+                    }
+                }
+                GlibcCheck::Deny => return Err(Error::Glibc(Box::new(report))),
+            }
+        }
+        let debug_info = if self.split_debug_info {
+            postprocess::split_debug_info(&self.toolchain, &target, &exe)
+                .map_err(|e| Error::Config(e.to_string()))?
+        } else {
+            None
+        };
+        if apple && self.notarizable {
+            let problems = notarize::check(&exe)?;
+            if !problems.is_empty() {
+                return Err(Error::Config(format!(
+                    "{} can't be notarized: {}",
+                    exe.display(),
+                    problems.join("; ")
+                )));
+            }
+        }
+        // Last, since stripping and splitting debug info rewrite the file.
+        let signature = match self.signer.clone().or_else(Signer::from_env) {
+            Some(signer) => Some(signer.sign_and_record(&exe)?),
+            None => None,
+        };
+        Ok(Artifacts {
+            exe,
+            debug_info,
+            signature,
+        })
+    }
+
+    fn tagger(&self) -> Tagger {
+        Tagger::new(&format!("zig-exe/{}", self.name))
+    }
+}
+
+/// The libraries in rustc's `note: native-static-libs: ...` line, which
+/// `cargo rustc --crate-type staticlib -- --print native-static-libs`
+/// prints on stderr.
+pub fn native_static_libs(rustc_output: &str) -> Option<Vec<String>> {
+    let line = rustc_output
+        .lines()
+        .find_map(|line| line.split_once("native-static-libs:"))?
+        .1;
+    let mut libs = Vec::new();
+    let mut words = line.split_whitespace();
+    while let Some(word) = words.next() {
+        if word == "-framework" {
+            libs.push(format!("-framework {}", words.next()?));
+        } else {
+            libs.push(word.into());
+        }
+    }
+    Some(libs)
+}
+
+/// What rustc prints for `--print native-static-libs` of a `std` staticlib
+/// for the rustc target `target`.
+pub fn default_native_libs(target: &str) -> Vec<String> {
+    let libs: &[&str] = if target.ends_with("-msvc") {
+        &[
+            "kernel32.lib",
+            "advapi32.lib",
+            "ntdll.lib",
+            "userenv.lib",
+            "ws2_32.lib",
+            "dbghelp.lib",
+            "/defaultlib:msvcrt",
+        ]
+    } else if target.contains("-windows") {
+        &[
+            "-lkernel32",
+            "-ladvapi32",
+            "-lntdll",
+            "-luserenv",
+            "-lws2_32",
+            "-ldbghelp",
+            "-lgcc_eh",
+        ]
+    } else if target.contains("-apple-") {
+        &["-lSystem", "-lc", "-lm"]
+    } else if target.contains("-linux-musl") {
+        &["-lc"]
+    } else if target.contains("-linux") {
+        &[
+            "-lgcc_s",
+            "-lutil",
+            "-lrt",
+            "-lpthread",
+            "-lm",
+            "-ldl",
+            "-lc",
+        ]
+    } else if target.contains("-haiku") {
+        // libroot holds libc, libm and pthreads.
+        &["-lbsd", "-lnetwork", "-lgcc_s", "-lroot"]
+    } else if target.contains("-fuchsia") {
+        &["-lzircon", "-lfdio", "-lunwind", "-lc"]
+    } else if target.contains("-freebsd") {
+        &[
+            "-lexecinfo",
+            "-lpthread",
+            "-lgcc_s",
+            "-lc",
+            "-lm",
+            "-lrt",
+            "-lutil",
+        ]
+    } else {
+        &[]
+    };
+    libs.iter().map(|&lib| lib.into()).collect()
+}
+
+/// Translates rustc's native library flags into `zig build-exe` arguments.
+/// Zig links its own libunwind in place of `libgcc_s`/`libgcc_eh` and its own
+/// compiler-rt in place of `libgcc`; MSVC's default CRT comes with `-lc`.
+fn zig_link_args(native_libs: &[String]) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut unwind = false;
+    for lib in native_libs {
+        if let Some(framework) = lib.strip_prefix("-framework ") {
+            args.extend(["-framework".into(), framework.into()]);
+            continue;
+        }
+        let name = match lib.strip_prefix("-l") {
+            Some(name) => name.strip_prefix(':').map_or(name, |file| {
+                let file = file.strip_prefix("lib").unwrap_or(file);
+                file.strip_suffix(".a").unwrap_or(file)
+            }),
+            None => match lib.strip_suffix(".lib") {
+                Some(name) if !lib.starts_with('/') => name,
+                _ => continue,
+            },
+        };
+        match name {
+            "gcc_s" | "gcc_eh" => unwind = true,
+            "gcc" | "c" => {}
+            _ => args.push(format!("-l{name}")),
+        }
+    }
+    if unwind {
+        args.push("-lunwind".into());
+    }
+    args
+}
+
+/// The file name suffix of executables for the rustc target `target`.
+pub(crate) fn exe_suffix(target: &str) -> &'static str {
+    if target.contains("-windows") {
+        ".exe"
+    } else if target.contains("-uefi") {
+        ".efi"
+    } else if target.starts_with("wasm") {
+        ".wasm"
+    } else {
+        ""
+    }
+}
@@ -0,0 +1,197 @@
+//! The files a Zig module reads, for `cargo:rerun-if-changed`: its root and
+//! every file reached from it through `@import` and `@embedFile` with a
+//! relative path. Zig writes no dependency file for Zig sources, so they are
+//! found by scanning the sources themselves:
+//!
+//! ```no_run
+//! for file in zig_rs::zig_imports::files("src/root.zig".as_ref())? {
+//!     println!("cargo:rerun-if-changed={}", file.display());
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Imports by module name (`std`, `builtin`, modules passed with `-M`) are
+//! the compiler's or the build's business and aren't followed, and neither
+//! are the C headers of `@cImport`.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// A reference from a Zig source to another file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reference {
+    /// `@import("path.zig")` or `@import("path.zon")`, another source.
+    Import(String),
+    /// `@embedFile("path")`, read as bytes.
+    EmbedFile(String),
+}
+
+impl Reference {
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Import(path) | Self::EmbedFile(path) => path,
+        }
+    }
+}
+
+/// The file references in `source`, in order. `@import`s of anything but a
+/// `.zig` or `.zon` file are module names and left out.
+pub fn references(source: &str) -> Vec<Reference> {
+    let bytes = source.as_bytes();
+    let mut references = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            // Comments and `\\` multiline string lines run to the end of the
+            // line.
+            b'/' if bytes.get(i + 1) == Some(&b'/') => i = line_end(bytes, i),
+            b'\\' if bytes.get(i + 1) == Some(&b'\\') => i = line_end(bytes, i),
+            b'"' | b'\'' => i = literal_end(bytes, i),
+            b'@' => {
+                let rest = &source[i + 1..];
+                let builtin = ["import", "embedFile"]
+                    .into_iter()
+                    .find(|name| rest.starts_with(name));
+                let Some(builtin) = builtin else {
+                    i += 1;
+                    continue;
+                };
+                i += 1 + builtin.len();
+                let Some(path) = call_argument(source, &mut i) else {
+                    continue;
+                };
+                if builtin == "embedFile" {
+                    references.push(Reference::EmbedFile(path));
+                } else if path.ends_with(".zig") || path.ends_with(".zon") {
+                    references.push(Reference::Import(path));
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    references
+}
+
+/// `root` and every file it reaches through [`references()`], transitively,
+/// each once. References are resolved against the directory of the file
+/// they're in. Files that don't exist are left out, since the compiler will
+/// have complained about them already; files reached through an `@import`
+/// that can't be read as UTF-8 are an error.
+pub fn files(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![root.to_owned()];
+    let mut i = 0;
+    while i < files.len() {
+        let file = files[i].clone();
+        i += 1;
+        let is_source = matches!(
+            file.extension().and_then(|e| e.to_str()),
+            Some("zig" | "zon")
+        );
+        if !is_source && file != root {
+            continue;
+        }
+        let source = fs_err::read_to_string(&file)?;
+        let dir = file.parent().unwrap_or(Path::new(""));
+        for reference in references(&source) {
+            let path = normalize(&dir.join(reference.path()));
+            if path.is_file() && !files.contains(&path) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// `path` with `.` and `..` components folded away where they can be, so a
+/// file imported as `../a.zig` and `a.zig` counts once.
+fn normalize(path: &Path) -> PathBuf {
+    use std::path::Component;
+
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// The string literal argument of a builtin call starting at `*i`
+/// (`("path")` with any whitespace), moving `*i` past it.
+fn call_argument(source: &str, i: &mut usize) -> Option<String> {
+    let bytes = source.as_bytes();
+    let skip_whitespace = |i: &mut usize| {
+        while bytes.get(*i).is_some_and(u8::is_ascii_whitespace) {
+            *i += 1;
+        }
+    };
+    skip_whitespace(i);
+    if bytes.get(*i) != Some(&b'(') {
+        return None;
+    }
+    *i += 1;
+    skip_whitespace(i);
+    if bytes.get(*i) != Some(&b'"') {
+        return None;
+    }
+    let start = *i;
+    *i = literal_end(bytes, start);
+    if *i < start + 2 || bytes[*i - 1] != b'"' {
+        // Unterminated.
+        return None;
+    }
+    unescape(&source[start + 1..*i - 1])
+}
+
+/// The index just past the string or character literal starting at `start`.
+fn literal_end(bytes: &[u8], start: usize) -> usize {
+    let quote = bytes[start];
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'\n' => return i,
+            b if b == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+fn line_end(bytes: &[u8], start: usize) -> usize {
+    bytes[start..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(bytes.len(), |end| start + end)
+}
+
+/// The value of a string literal's contents, or `None` for escapes a path
+/// has no business using.
+fn unescape(literal: &str) -> Option<String> {
+    let mut value = String::new();
+    let mut chars = literal.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => value.push('\\'),
+            '"' => value.push('"'),
+            '\'' => value.push('\''),
+            _ => return None,
+        }
+    }
+    Some(value)
+}
@@ -0,0 +1,245 @@
+//! Running the `test` blocks of Zig sources under `cargo test`. The build
+//! script compiles a test binary with `zig test --test-no-exec` and tells
+//! rustc where it is, and [`zig_tests!`](crate::zig_tests) turns it into a
+//! `#[test]` that runs it:
+//!
+//! ```no_run
+//! // build.rs
+//! zig_rs::TestBuilder::new("math", "src/math.zig").build()?;
+//! # Ok::<(), zig_rs::Error>(())
+//! ```
+//!
+//! ```ignore
+//! // tests/zig.rs
+//! zig_rs::zig_tests!(math);
+//! ```
+//!
+//! The Rust test fails when any Zig test does, naming the ones that failed.
+//! Test binaries are built for the host, where `cargo test` runs them.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    Error, Toolchain, diagnostic,
+    error::CommandError,
+    log::{self, Tagger},
+    process, target, zig_build, zig_exe, zig_imports,
+};
+
+/// Compiles the tests of a Zig root source file, and those of the files it
+/// imports, into a binary.
+#[derive(Debug, Clone)]
+pub struct TestBuilder {
+    name: String,
+    root: PathBuf,
+    sources: Vec<PathBuf>,
+    filters: Vec<String>,
+    flags: Vec<String>,
+    libc: bool,
+    optimize: Option<String>,
+    out_dir: Option<PathBuf>,
+    cargo_metadata: bool,
+    toolchain: Toolchain,
+}
+
+impl TestBuilder {
+    /// Tests called `name`, which [`zig_tests!`](crate::zig_tests) refers
+    /// to them by, from the root source file `root`.
+    pub fn new(name: &str, root: impl AsRef<Path>) -> Self {
+        Self {
+            name: name.into(),
+            root: root.as_ref().into(),
+            sources: Vec::new(),
+            filters: Vec::new(),
+            flags: Vec::new(),
+            libc: false,
+            optimize: None,
+            out_dir: None,
+            cargo_metadata: true,
+            toolchain: Toolchain::bundled(),
+        }
+    }
+
+    /// Adds a C, C++ or assembly source compiled into the test binary, for
+    /// Zig code that calls into it.
+    pub fn source(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.sources.push(path.as_ref().into());
+        self
+    }
+
+    /// Only runs tests whose names contain `filter`; `--test-filter`. Can be
+    /// given more than once.
+    pub fn filter(&mut self, filter: &str) -> &mut Self {
+        self.filters.push(filter.into());
+        self
+    }
+
+    /// Passes a flag through to `zig test`.
+    pub fn flag(&mut self, flag: &str) -> &mut Self {
+        self.flags.push(flag.into());
+        self
+    }
+
+    /// Whether to link libc. Off by default.
+    pub fn libc(&mut self, libc: bool) -> &mut Self {
+        self.libc = libc;
+        self
+    }
+
+    /// Zig's optimize mode, e.g. `ReleaseSafe`. Defaults to
+    /// [`zig_build::optimize_for_profile()`].
+    pub fn optimize(&mut self, mode: &str) -> &mut Self {
+        self.optimize = Some(mode.into());
+        self
+    }
+
+    /// Where the binary goes. Defaults to `OUT_DIR/zig-tests`.
+    pub fn out_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.out_dir = Some(dir.as_ref().into());
+        self
+    }
+
+    /// Whether to print `cargo:rerun-if-changed` for the Zig sources and
+    /// `cargo:rustc-env` for [`env_var()`]. On by default; without it
+    /// [`zig_tests!`](crate::zig_tests) can't find the binary.
+    pub fn cargo_metadata(&mut self, cargo_metadata: bool) -> &mut Self {
+        self.cargo_metadata = cargo_metadata;
+        self
+    }
+
+    pub fn toolchain(&mut self, toolchain: Toolchain) -> &mut Self {
+        self.toolchain = toolchain;
+        self
+    }
+
+    /// Compiles the test binary without running it and returns its path.
+    pub fn build(&self) -> Result<PathBuf, Error> {
+        let host = env::var("HOST").map_err(|_| Error::Config("HOST is not set".into()))?;
+        let zig_target = target::zig_target(&host)
+            .ok_or_else(|| Error::Config(format!("no Zig target for {host}")))?;
+        let out_dir = match &self.out_dir {
+            Some(dir) => dir.clone(),
+            None => env::var_os("OUT_DIR")
+                .map(|dir| PathBuf::from(dir).join("zig-tests"))
+                .ok_or_else(|| Error::Config("OUT_DIR is not set".into()))?,
+        };
+        fs_err::create_dir_all(&out_dir)?;
+        let binary = out_dir.join(format!("{}{}", self.name, zig_exe::exe_suffix(&host)));
+
+        let mut cmd = self.toolchain.command();
+        cmd.arg("test")
+            .arg(&self.root)
+            .args(&self.sources)
+            .arg("-target")
+            .arg(&zig_target)
+            .arg("-O")
+            .arg(
+                self.optimize
+                    .as_deref()
+                    .unwrap_or_else(|| zig_build::optimize_for_profile()),
+            )
+            .arg("--test-no-exec")
+            .arg(format!("-femit-bin={}", binary.display()));
+        for filter in &self.filters {
+            cmd.arg("--test-filter").arg(filter);
+        }
+        if self.libc {
+            cmd.arg("-lc");
+        }
+        cmd.args(&self.flags);
+        let tagger = Tagger::new(&format!("zig-test/{}", self.name));
+        let (status, output) = log::run_tagged_captured(&mut cmd, &tagger)?;
+        if !status.success() {
+            return Err(Error::Command(Box::new(CommandError::new(
+                &cmd,
+                status,
+                diagnostic::parse(&output),
+                output,
+            ))));
+        }
+        if self.cargo_metadata {
+            // Zig writes no dependency file, so the imports are found like
+            // in Build.
+            for file in zig_imports::files(&self.root)? {
+                build::rerun_if_changed(file);
+            }
+            for source in &self.sources {
+                build::rerun_if_changed(source);
+            }
+            build::rustc_env(&env_var(&self.name), &binary.to_string_lossy());
+        }
+        Ok(binary)
+    }
+}
+
+/// The variable [`TestBuilder::build()`] passes the path of the `name` test
+/// binary to rustc in: `ZIG_TEST_<name>`, with `-` as `_`.
+pub fn env_var(name: &str) -> String {
+    format!("ZIG_TEST_{}", name.replace('-', "_"))
+}
+
+/// Runs the test binary at `binary`, panicking with its output and the names
+/// of the failed tests when any fails. What [`zig_tests!`](crate::zig_tests)
+/// calls.
+#[track_caller]
+pub fn run(binary: &str) {
+    let output = match process::output(&mut std::process::Command::new(binary)) {
+        Ok(output) => output,
+        Err(e) => panic!("couldn't run the Zig tests in {binary}: {e}"),
+    };
+    let mut text = String::from_utf8_lossy(&output.stderr).into_owned();
+    text += &String::from_utf8_lossy(&output.stdout);
+    if output.status.success() {
+        return;
+    }
+    let failed = failed_tests(&text);
+    if failed.is_empty() {
+        panic!(
+            "the Zig tests in {binary} failed: {}\n{text}",
+            output.status
+        );
+    }
+    panic!(
+        "{} Zig test{} failed: {}\n{text}",
+        failed.len(),
+        if failed.len() == 1 { "" } else { "s" },
+        failed.join(", ")
+    );
+}
+
+/// The names of the failed tests in the output of Zig's test runner, whose
+/// lines read `2/5 math.test.add...FAIL (TestExpectedEqual)`.
+pub fn failed_tests(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (head, _) = line.split_once("...FAIL")?;
+            let (counter, name) = head.split_once(' ')?;
+            counter.contains('/').then(|| name.to_owned())
+        })
+        .collect()
+}
+
+/// Defines a `#[test]` for each test binary named, by its
+/// [`TestBuilder`] name, that runs it; see [`zig_test`](crate::zig_test).
+///
+/// ```ignore
+/// zig_rs::zig_tests!(math, strings);
+/// ```
+#[macro_export]
+macro_rules! zig_tests {
+    ($($name:ident),* $(,)?) => {
+        $(
+            #[test]
+            fn $name() {
+                $crate::zig_test::run(::std::env!(::std::concat!(
+                    "ZIG_TEST_",
+                    ::std::stringify!($name)
+                )));
+            }
+        )*
+    };
+}
@@ -0,0 +1,158 @@
+//! The wrappers [`zig_rs::shims`] writes, run against a `zig` that prints
+//! its arguments.
+#![cfg(unix)]
+
+use std::{
+    env,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Once,
+};
+
+use zig_rs::{
+    Toolchain, cl,
+    compile::Subsystem,
+    shims::{self, LinkOptions},
+};
+
+/// The arguments a `cc` wrapper of `x86_64-pc-windows-msvc` is run with and
+/// those `zig` gets after `cc -target x86_64-windows-msvc`.
+#[rustfmt::skip]
+const MSVC_CC: &[(&str, &str)] = &[
+    ("-nologo -MD -O2 -Iinclude -DFOO#1 -c foo.c -Fofoo.o", "-D_MT -D_DLL -O2 -Iinclude -DFOO=1 -c foo.c -ofoo.o"),
+    ("/nologo /W4 /I include /D BAR /c bar.c /Fo:out/", "-Wall -Wextra -Iinclude -DBAR -c bar.c -oout/bar.obj"),
+    ("-nologo -MTd -Zi -GS- -std:c11 -c foo.c -Fofoo.o", "-D_MT -D_DEBUG -g -fno-stack-protector -std=c11 -c foo.c -ofoo.o"),
+    ("-nologo -E foo.c", "-E foo.c"),
+    // Not cl.exe command lines: -MD and -Wall keep their clang meaning.
+    ("-MD -Wall -c baz.c -o baz.o", "-MD -Wall -c baz.c -o baz.o"),
+    ("--target=x86_64-pc-windows-msvc -c baz.c", "-c baz.c"),
+];
+
+/// The arguments the `cc` wrapper of `x86_64-unknown-linux-gnu` is run with
+/// and what `zig` gets after `cc -target x86_64-linux-gnu`, with
+/// [`link_options()`].
+#[rustfmt::skip]
+const LINUX_CC: &[(&str, &str)] = &[
+    ("-c foo.c -o foo.o", "-c foo.c -o foo.o"),
+    ("-E foo.c", "-E foo.c"),
+    ("foo.o -o foo -lgcc_s", "foo.o -o foo -lunwind -Wl,--dynamic-linker=/nix/ld.so -Wl,--no-allow-shlib-undefined"),
+    ("-shared foo.o -o libfoo.so -lgcc", "-shared foo.o -o libfoo.so -Wl,--dynamic-linker=/nix/ld.so -Wl,--no-allow-shlib-undefined -Wl,-z,defs"),
+];
+
+fn link_options() -> LinkOptions {
+    LinkOptions {
+        dynamic_linker: Some("/nix/ld.so".into()),
+        allow_shlib_undefined: Some(false),
+        no_undefined: true,
+        ..LinkOptions::default()
+    }
+}
+
+/// Points [`cl::PROGRAM_ENV`] at `zig-cl` before any test reads the
+/// environment.
+fn set_env() {
+    static ONCE: Once = Once::new();
+    // SAFETY: every test calls this first, so nothing reads the environment
+    // while it's written.
+    ONCE.call_once(|| unsafe { env::set_var(cl::PROGRAM_ENV, env!("CARGO_BIN_EXE_zig-cl")) });
+}
+
+/// A toolchain in a new temporary directory whose `zig` prints its
+/// arguments, one per line.
+fn fake_toolchain(name: &str) -> (Toolchain, PathBuf) {
+    let dir = env::temp_dir().join(format!("zig-rs-shims-{name}-{}", std::process::id()));
+    let root = dir.join("zig");
+    fs_err::create_dir_all(&root).unwrap();
+    let zig = root.join("zig");
+    fs_err::write(&zig, "#!/bin/sh\nfor arg do echo \"$arg\"; done\n").unwrap();
+    fs_err::set_permissions(&zig, std::fs::Permissions::from_mode(0o755)).unwrap();
+    (Toolchain::at("0.14.0", root), dir)
+}
+
+fn run(program: &Path, args: &str) -> String {
+    let output = Command::new(program)
+        .args(args.split_whitespace())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[test]
+fn msvc_cc_translates_cl_command_lines() {
+    set_env();
+    let (toolchain, dir) = fake_toolchain("cl");
+    let shims = shims::write_for(&toolchain, "x86_64-pc-windows-msvc", &dir.join("shims")).unwrap();
+    for (args, expected) in MSVC_CC {
+        let expected = format!("cc -target x86_64-windows-msvc {expected}");
+        assert_eq!(run(&shims.cc, args), expected, "{args}");
+    }
+    assert_eq!(
+        run(&shims.cxx, "-nologo -EHsc -c foo.cpp -Fofoo.o"),
+        "c++ -target x86_64-windows-msvc -fexceptions -c foo.cpp -ofoo.o"
+    );
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn link_options_are_added_to_links() {
+    set_env();
+    let (toolchain, dir) = fake_toolchain("link");
+    let shims_dir = dir.join("shims");
+    let target = "x86_64-unknown-linux-gnu";
+    let shims = shims::write_with(&toolchain, target, &shims_dir, &link_options()).unwrap();
+    for (args, expected) in LINUX_CC {
+        let expected = format!("cc -target x86_64-linux-gnu {expected}");
+        assert_eq!(run(&shims.cc, args), expected, "{args}");
+    }
+    // Without options nothing is added.
+    let shims = shims::write_for(&toolchain, target, &shims_dir).unwrap();
+    assert_eq!(
+        run(&shims.cc, "foo.o -o foo"),
+        "cc -target x86_64-linux-gnu foo.o -o foo"
+    );
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn windows_link_options_are_added_to_links() {
+    set_env();
+    let (toolchain, dir) = fake_toolchain("windows");
+    let manifest = dir.join("app.manifest");
+    fs_err::write(&manifest, "<assembly/>").unwrap();
+    let options = LinkOptions {
+        subsystem: Some(Subsystem::Windows),
+        windows_manifest: Some(manifest.clone()),
+        stack_size: Some(8 << 20),
+        ..LinkOptions::default()
+    };
+    let shims_dir = dir.join("shims");
+    let shims =
+        shims::write_with(&toolchain, "x86_64-pc-windows-gnu", &shims_dir, &options).unwrap();
+    let resources = shims_dir.join("zig-manifest-x86_64-pc-windows-gnu");
+    assert_eq!(
+        fs_err::read_to_string(resources.join("manifest.rc")).unwrap(),
+        format!("1 24 \"{}\"\n", manifest.display())
+    );
+    assert_eq!(
+        run(&shims.cc, "foo.o -o foo.exe"),
+        format!(
+            "cc -target x86_64-windows-gnu foo.o -o foo.exe -Wl,--subsystem,windows -Wl,--stack,8388608 {}",
+            resources.join("manifest.res").display()
+        )
+    );
+    assert_eq!(
+        run(&shims.cc, "-c foo.c -o foo.o"),
+        "cc -target x86_64-windows-gnu -c foo.c -o foo.o"
+    );
+    // UEFI binaries can't be GUI applications.
+    let err =
+        shims::write_with(&toolchain, "x86_64-unknown-uefi", &shims_dir, &options).unwrap_err();
+    assert!(err.to_string().contains("windows subsystem"), "{err}");
+    let _ = std::fs::remove_dir_all(dir);
+}